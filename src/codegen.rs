@@ -0,0 +1,64 @@
+//! Backends which turn IR paths into executable transforms.
+
+use derive_builder::Builder;
+
+use crate::ir::IR;
+
+pub mod jmespath;
+pub mod jq;
+pub mod js;
+pub mod jsonata;
+pub mod rust;
+pub mod sql;
+pub mod swift;
+pub mod ts;
+pub mod wasm;
+
+/// How a function returns what it builds.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReturnStyle {
+    /// Declare the output variable up front, assign to it, and return it.
+    #[default]
+    Variable,
+    /// Declare the output with what it's first assigned, returning that
+    /// straight away when nothing else is written.
+    Literal,
+}
+
+/// Options shaping the functions generators write, as far as their targets
+/// have a use for them.
+#[derive(Builder, Clone, Debug, PartialEq, Eq)]
+#[builder(default)]
+pub struct CodegenOptions {
+    /// Name of the function, where it's named.
+    pub name: String,
+    /// Identifier of its argument.
+    pub input: String,
+    /// Identifier of the variable its result is built in.
+    pub output: String,
+    pub returns: ReturnStyle,
+}
+
+impl Default for CodegenOptions {
+    fn default() -> Self {
+        Self {
+            name: "transform".to_string(),
+            input: "input".to_string(),
+            output: "output".to_string(),
+            returns: ReturnStyle::default(),
+        }
+    }
+}
+
+/// A code generator for some target language.
+pub trait Codegen {
+    /// Generate a function taking its argument as `input` which transforms it
+    /// according to `ir`, building the result in `output`.
+    fn generate(&mut self, ir: &[IR], input: &str, output: &str) -> String;
+
+    /// Generate a function transforming its argument according to `ir`, as
+    /// `options` shape it.
+    fn generate_with(&mut self, ir: &[IR], options: &CodegenOptions) -> String {
+        self.generate(ir, &options.input, &options.output)
+    }
+}
@@ -0,0 +1,365 @@
+//! JMESPath generation: an expression transforming its input the way the
+//! generated JavaScript does, for services which only take JMESPath, like
+//! EventBridge input transformers and Step Functions.
+//!
+//! JMESPath can project, rename and extract, but has no arithmetic, no
+//! string functions past `join` and no way to dispatch on types, so paths
+//! using those can't be written as an expression; generating one reports
+//! the first instruction standing in the way. Where an expression can be
+//! written, it differs from the JavaScript in a few places: absent
+//! properties come out `null` instead of being left out, coalescing skips
+//! every falsy value instead of just `null`, flattening and gathering drop
+//! `null` items, and descending sorts reverse the order of ties.
+
+use std::{fmt::Display, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    ir::{scope_end, text, SortOrder, IR},
+    schema::Ground,
+};
+
+/// Error generating a JMESPath expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JmesPathErr {
+    /// The instruction at this index has no JMESPath equivalent.
+    Unsupported(usize, IR),
+}
+
+impl Display for JmesPathErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(i, op) => write!(
+                f,
+                "instruction {} (`{}`) has no JMESPath equivalent",
+                i,
+                text::print(std::slice::from_ref(op)).trim_end()
+            ),
+        }
+    }
+}
+
+/// The output a scope writes, as far as it's been written.
+enum Output {
+    Unwritten,
+    /// The value of an expression.
+    Expr(String),
+    /// An object with these properties, merged over the value of an
+    /// expression if there is one.
+    Obj(Option<String>, Vec<(Arc<String>, String)>),
+}
+
+impl Output {
+    /// The output as an expression.
+    fn expr(self) -> String {
+        match self {
+            Output::Unwritten => "`null`".to_string(),
+            Output::Expr(expr) => expr,
+            Output::Obj(base, props) => {
+                let hash = if props.is_empty() {
+                    "`{}`".to_string()
+                } else {
+                    let props: Vec<_> = props
+                        .iter()
+                        .map(|(key, value)| format!("{}: {}", identifier(key), value))
+                        .collect();
+                    format!("{{{}}}", props.join(", "))
+                };
+                match base {
+                    Some(base) => format!("merge({}, {})", base, hash),
+                    None => hash,
+                }
+            }
+        }
+    }
+}
+
+/// `key` as an identifier, quoted unless it's unquoted-safe.
+fn identifier(key: &str) -> String {
+    let mut chars = key.chars();
+    let unquoted = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if unquoted {
+        key.to_string()
+    } else {
+        Value::from(key).to_string()
+    }
+}
+
+/// `value` as a JSON literal.
+fn literal(value: &Value) -> String {
+    format!("`{}`", value.to_string().replace('`', "\\`"))
+}
+
+/// `text` as a raw string literal.
+fn raw(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// `expr`, parenthesized if anything following it wouldn't apply to its
+/// whole value: if it has an `||` or a projection outside any brackets.
+fn group(expr: &str) -> String {
+    // the brackets open, with where each started
+    let mut open = vec![];
+    let mut quote = None;
+    let mut escaped = false;
+    let mut loose = false;
+    let mut chars = expr.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '`' | '\'' | '"') => quote = Some(c),
+            (None, '(' | '[' | '{') => open.push((c, i)),
+            (None, ')' | ']' | '}') => {
+                let (opener, start) = open.pop().unwrap_or(('(', 0));
+                // a flatten, wildcard, filter or slice, rather than an index
+                // or a list (which can't follow anything at the top level)
+                let inner = &expr[start + 1..i];
+                loose |= open.is_empty()
+                    && opener == '['
+                    && start > 0
+                    && (inner.is_empty()
+                        || inner == "*"
+                        || inner.starts_with('?')
+                        || inner.contains(':'));
+            }
+            (None, '|') if open.is_empty() && chars.peek().map(|(_, c)| *c) == Some('|') => {
+                loose = true
+            }
+            _ => {}
+        }
+    }
+    if loose {
+        format!("({})", expr)
+    } else {
+        expr.to_string()
+    }
+}
+
+/// The value at `key` of the value of `expr`.
+fn sub(expr: &str, key: &str) -> String {
+    match expr {
+        "@" => identifier(key),
+        expr => format!("{}.{}", group(expr), identifier(key)),
+    }
+}
+
+/// The value at `path` of the value of `expr`.
+fn at(expr: &str, path: &[Arc<String>]) -> String {
+    path.iter()
+        .fold(expr.to_string(), |expr, key| sub(&expr, key))
+}
+
+/// The expression building what `ir` writes from the value of `input`.
+/// `base` is the index of `ir`'s first instruction in the whole path.
+fn scope(ir: &[IR], base: usize, input: String) -> Result<String, JmesPathErr> {
+    let mut output = Output::Unwritten;
+    run(ir, base, input, &mut output)?;
+    Ok(output.expr())
+}
+
+/// Run the instructions of a scope `ir` on the value of `input`, writing
+/// to `output`.
+fn run(ir: &[IR], base: usize, mut input: String, output: &mut Output) -> Result<(), JmesPathErr> {
+    let mut i = 0;
+    while i < ir.len() {
+        let op = &ir[i];
+        let unsupported = || JmesPathErr::Unsupported(base + i, op.clone());
+        if op.closer().is_some() {
+            let end = scope_end(&ir[i..]).map_or(ir.len(), |end| i + end);
+            let body = &ir[i + 1..end.min(ir.len())];
+            let inner = base + i + 1;
+            match op {
+                IR::PushObj => {
+                    *output = Output::Obj(None, vec![]);
+                    run(body, inner, input.clone(), output)?;
+                }
+                IR::Merge => {
+                    let written = std::mem::replace(output, Output::Unwritten);
+                    *output = match written {
+                        Output::Unwritten => Output::Obj(None, vec![]),
+                        written => Output::Obj(Some(written.expr()), vec![]),
+                    };
+                    run(body, inner, input.clone(), output)?;
+                }
+                IR::PushKey(_) | IR::Rename(..) | IR::Nest(_) => {
+                    let (value, key) = match op {
+                        IR::PushKey(key) => (scope(body, inner, sub(&input, key))?, key),
+                        IR::Rename(from, to) => (scope(body, inner, sub(&input, from))?, to),
+                        IR::Nest(key) => (scope(body, inner, input.clone())?, key),
+                        _ => unreachable!("only key scopes write properties"),
+                    };
+                    // properties can only be written into objects
+                    if let Output::Obj(_, props) = output {
+                        props.retain(|(written, _)| written != key);
+                        props.push((key.clone(), value));
+                    }
+                }
+                // absent input comes out `null` either way
+                IR::PushOpt => run(body, inner, input.clone(), output)?,
+                IR::PushArr => {
+                    let item = scope(body, inner, "@".to_string())?;
+                    *output = Output::Expr(match item.as_str() {
+                        "@" => input.clone(),
+                        item => format!("map(&{}, {})", item, input),
+                    });
+                }
+                _ => return Err(unsupported()),
+            }
+            i = end + 1;
+            continue;
+        }
+        match op {
+            IR::Copy => *output = Output::Expr(input.clone()),
+            IR::G2G(_, to) => {
+                *output = Output::Expr(match to {
+                    Ground::String => format!("to_string({})", input),
+                    Ground::Num => format!("to_number({})", input),
+                    Ground::Bool => format!("!!{}", group(&input)),
+                    Ground::Null => "`null`".to_string(),
+                })
+            }
+            IR::Pipe => {
+                input = std::mem::replace(output, Output::Unwritten).expr();
+            }
+            IR::Wrap => {
+                // the rest of the scope writes inside the array
+                let mut wrapped = Output::Unwritten;
+                run(&ir[i + 1..], base + i + 1, input.clone(), &mut wrapped)?;
+                *output = Output::Expr(match wrapped {
+                    Output::Unwritten => "`[]`".to_string(),
+                    wrapped => format!("[{}]", wrapped.expr()),
+                });
+                return Ok(());
+            }
+            IR::Extr(key) => input = sub(&input, key),
+            IR::Coalesce(paths) => {
+                let paths: Vec<_> = paths.iter().map(|path| at(&input, path)).collect();
+                input = paths.join(" || ");
+            }
+            IR::First => input = format!("{}[0]", group(&input)),
+            IR::Flatten(depth) => input = format!("{}{}", group(&input), "[]".repeat(*depth)),
+            IR::Slice(start, end) => {
+                let end = end.map_or(String::new(), |end| end.to_string());
+                input = format!("{}[{}:{}]", group(&input), start, end)
+            }
+            IR::Sort(path, order) => {
+                let sorted = match path.as_slice() {
+                    [] => format!("sort({})", input),
+                    path => format!("sort_by({}, &{})", input, at("@", path)),
+                };
+                input = match order {
+                    SortOrder::Ascending => sorted,
+                    SortOrder::Descending => format!("reverse({})", sorted),
+                }
+            }
+            IR::Gather(paths) => {
+                let paths: Vec<_> = paths.iter().map(|path| at(&input, path)).collect();
+                input = format!("[{}][]", paths.join(", "))
+            }
+            IR::Concat(paths, sep) => {
+                let paths: Vec<_> = paths.iter().map(|path| at(&input, path)).collect();
+                *output = Output::Expr(format!(
+                    "join({}, [{}][?@ != `null`].to_string(@))",
+                    raw(sep),
+                    paths.join(", ")
+                ))
+            }
+            IR::Join(sep) => {
+                *output = Output::Expr(format!("join({}, map(&to_string(@), {}))", raw(sep), input))
+            }
+            IR::Const(value) => *output = Output::Expr(literal(&value.0)),
+            // instructions closing scopes which weren't opened do nothing
+            op if op.is_closer() => {}
+            _ => return Err(unsupported()),
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// A JMESPath expression transforming its input according to `ir`, if it
+/// can be written.
+pub fn expression(ir: &[IR]) -> Result<String, JmesPathErr> {
+    scope(ir, 0, "@".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{ir::Case, schema::Literal, test_util::key};
+
+    #[test]
+    fn test_projection() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("tags")),
+            IR::PushArr,
+            IR::PushObj,
+            IR::PushKey(key("display-name")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopArr,
+            IR::PopKey,
+            IR::Nest(key("meta")),
+            IR::PushObj,
+            IR::PushKey(key("kind")),
+            IR::Const(Literal(json!("user"))),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            expression(&ir),
+            Ok(r#"{id: to_string(user_id), tags: map(&{"display-name": "display-name"}, tags), meta: {kind: `"user"`}}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn test_extractions() {
+        let ir = [
+            IR::Coalesce(vec![vec![key("a")], vec![key("b"), key("c")]]),
+            IR::Sort(vec![key("n")], SortOrder::Descending),
+            IR::First,
+            IR::Extr(key("name")),
+            IR::Copy,
+        ];
+        assert_eq!(
+            expression(&ir),
+            Ok("reverse(sort_by(a || b.c, &n))[0].name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let ir = [
+            IR::PushObj,
+            IR::PushKey(key("name")),
+            IR::CaseTransform(Case::Upper),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let err = expression(&ir).unwrap_err();
+        assert_eq!(
+            err,
+            JmesPathErr::Unsupported(2, IR::CaseTransform(Case::Upper))
+        );
+        assert_eq!(
+            err.to_string(),
+            "instruction 2 (`CaseTransform \"Upper\"`) has no JMESPath equivalent"
+        );
+    }
+}
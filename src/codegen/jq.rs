@@ -0,0 +1,781 @@
+//! jq generation: a filter transforming its input the way the generated
+//! JavaScript does, for running transforms in shell pipelines with
+//! `jq -f`. (jq 1.6 can't read a filter from `-`, but `-f /dev/stdin` does
+//! the same.)
+//!
+//! The filter threads a state object through the path, holding the input
+//! as `i` and the output written so far as `o`, either of which is left out
+//! when it's `undefined`. Helpers making the steps the path takes are
+//! defined ahead of the filter, as far as it uses them.
+//!
+//! jq's builtins stand in for JavaScript's where they're close enough:
+//! sorting compares mixed types the way jq does, case conversions only
+//! change ASCII letters, regular expressions are Oniguruma's, and dates
+//! are read as ISO 8601 date-times in UTC or with an offset.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    ir::{scope_end, Case, Coding, DateFormat, SortOrder, IR},
+    schema::{Ground, Schema},
+};
+
+use super::Codegen;
+
+/// The helpers a filter may use: the name of each, the helpers it uses,
+/// and its definition. Helpers only use those defined before them.
+const HELPERS: &[(&str, &[&str], &str)] = &[
+    (
+        "_str",
+        &[],
+        r#"def _str: if type == "string" then . elif type == "object" then "[object Object]" elif type == "array" then map(if . == null then "" else _str end) | join(",") elif . == 0 then "0" else tojson end;"#,
+    ),
+    (
+        "_num",
+        &["_str"],
+        r#"def _num: if type == "number" then . elif type == "boolean" then (if . then 1 else 0 end) elif . == null then 0 else _str | sub("^\\s+"; "") | sub("\\s+$"; "") | if . == "" then 0 else tonumber? // null end end;"#,
+    ),
+    (
+        "_value",
+        &[],
+        r#"def _value: if has("o") then .o else null end;"#,
+    ),
+    (
+        "_string",
+        &["_str"],
+        r#"def _string: if has("i") then .i | _str else "undefined" end;"#,
+    ),
+    (
+        "_number",
+        &["_num"],
+        r#"def _number: if has("i") then .i | _num else null end;"#,
+    ),
+    (
+        "_truthy",
+        &[],
+        r#"def _truthy: has("i") and (.i | . != null and . != false and . != 0 and . != "");"#,
+    ),
+    (
+        "_copy",
+        &[],
+        r#"def _copy: if has("i") then .o = .i else del(.o) end;"#,
+    ),
+    (
+        "_pipe",
+        &[],
+        r#"def _pipe: if has("o") then .i = .o else del(.i) end;"#,
+    ),
+    (
+        "_out",
+        &[],
+        r#"def _out($k): if (.o | type) == "object" and (.o | has($k)) then {o: .o[$k]} else {} end;"#,
+    ),
+    (
+        "_put",
+        &[],
+        r#"def _put($k; f): if (.o | type) == "object" then (f as $s | if $s | has("o") then .o[$k] = $s.o else del(.o[$k]) end) else . end;"#,
+    ),
+    (
+        "_field",
+        &["_out", "_put"],
+        r#"def _field($from; $to; f): _put($to; (if (.i | type) == "object" and (.i | has($from)) then {i: .i[$from]} else {} end) + _out($to) | f);"#,
+    ),
+    (
+        "_nest",
+        &["_out", "_put"],
+        r#"def _nest($to; f): _put($to; (if has("i") then {i} else {} end) + _out($to) | f);"#,
+    ),
+    (
+        "_merge",
+        &[],
+        r#"def _merge: .o = (if (.o | type) == "object" then .o else {} end);"#,
+    ),
+    (
+        "_opt",
+        &[],
+        r#"def _opt(f): if has("i") then f else . end;"#,
+    ),
+    (
+        "_each",
+        &["_value"],
+        r#"def _each(f): .o = [(if (.i | type) == "array" then .i[] else empty end) as $item | {i: $item} | f | _value];"#,
+    ),
+    (
+        "_rest",
+        &["_out", "_put"],
+        r#"def _rest($named; f): reduce ((if (.i | type) == "object" then .i else {} end) | to_entries[] | select(.key as $k | $named | index([$k]) | not)) as $e (.; _put($e.key; {i: $e.value} + _out($e.key) | f));"#,
+    ),
+    (
+        "_wrap",
+        &[],
+        r#"def _wrap(f): . as $s | (del(.o) | f) as $w | $s | .o = (if $w | has("o") then [$w.o] else [] end);"#,
+    ),
+    (
+        "_extr",
+        &[],
+        r#"def _extr($k): if (.i | type) == "object" and (.i | has($k)) then .i = .i[$k] else del(.i) end;"#,
+    ),
+    (
+        "_call",
+        &[],
+        r#"def _call(f): . as $s | f | if $s | has("i") then .i = $s.i else del(.i) end;"#,
+    ),
+    (
+        "_path",
+        &["_extr"],
+        r#"def _path($p): reduce $p[] as $k (.; _extr($k));"#,
+    ),
+    (
+        "_at",
+        &[],
+        r#"def _at($p): reduce $p[] as $k (.; if type == "object" then .[$k] else null end);"#,
+    ),
+    (
+        "_coalesce",
+        &["_path"],
+        r#"def _coalesce($paths): . as $s | first($paths[] as $p | $s | _path($p) | select(has("i") and .i != null)) // ($s | _path($paths[-1]));"#,
+    ),
+    (
+        "_first",
+        &[],
+        r#"def _first: if (.i | type) == "array" and (.i | length) > 0 then .i = .i[0] else del(.i) end;"#,
+    ),
+    (
+        "_arrays",
+        &[],
+        r#"def _arrays(f): if (.i | type) == "array" then .i |= f else del(.i) end;"#,
+    ),
+    (
+        "_sort",
+        &["_arrays", "_at"],
+        r#"def _sort($p; $desc): _arrays(if $desc then to_entries | sort_by((.value | _at($p)), -.key) | reverse | map(.value) else sort_by(_at($p)) end);"#,
+    ),
+    (
+        "_dedupe",
+        &["_arrays", "_at"],
+        r#"def _dedupe($p): _arrays(reduce .[] as $x ([]; if any(.[]; _at($p // []) == ($x | _at($p // []))) then . else . + [$x] end));"#,
+    ),
+    (
+        "_gather",
+        &["_path"],
+        r#"def _gather($paths): .i = [$paths[] as $p | _path($p) | if (.i | type) == "array" then .i[] else empty end];"#,
+    ),
+    (
+        "_zip",
+        &[],
+        r#"def _zip($pairs): .i as $v | ([$pairs[] as [$f, $t] | $v | if type == "object" and (.[$f] | type) == "array" then .[$f] else [] end]) as $arrays | .i = [range(0; [$arrays[] | length] | max // 0) as $n | [range(0; $pairs | length) as $j | select($n < ($arrays[$j] | length)) | {($pairs[$j][1]): $arrays[$j][$n]}] | add // {}];"#,
+    ),
+    (
+        "_unzip",
+        &[],
+        r#"def _unzip($pairs): (if (.i | type) == "array" then .i else [] end) as $items | .i = ([$pairs[] as [$t, $f] | {($t): [$items[] | if type == "object" then .[$f] else null end]}] | add // {});"#,
+    ),
+    (
+        "_inv",
+        &["_str"],
+        r#"def _inv: if (.i | type) == "array" then .i |= reduce .[] as $e ({}; ($e | if type == "object" and has("key") then .key | _str else "undefined" end) as $k | if $e | type == "object" and has("value") then .[$k] = $e.value else del(.[$k]) end) elif (.i | type) == "object" then .i |= to_entries else del(.i) end;"#,
+    ),
+    (
+        "_group",
+        &["_str"],
+        r#"def _group($k): if (.i | type) == "array" then .i |= reduce .[] as $x ({}; .[$x | if type == "object" and has($k) then .[$k] | _str else "undefined" end] = $x) elif (.i | type) == "object" then .i |= [to_entries[] | (.value | if type == "object" then . else {} end) + {($k): .key}] else del(.i) end;"#,
+    ),
+    (
+        "_scale",
+        &["_number"],
+        r#"def _scale($f; $o): _number as $n | .o = (if $n == null then null else $n * $f + $o end);"#,
+    ),
+    (
+        "_clip",
+        &[],
+        r#"def _clip: if . == null or fabs > 8.64e15 then null elif . < 0 then ceil else floor end;"#,
+    ),
+    (
+        "_from_iso",
+        &[],
+        r#"def _from_iso: (try ((if test("T") then . else . + "T00:00" end) | capture("^(?<s>[0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9]{2}:[0-9]{2}(:[0-9]{2})?)(\\.(?<f>[0-9]+))?(?<z>Z|[+-][0-9]{2}:?[0-9]{2})?$") | ((.s | if length == 16 then . + ":00" else . end) + "Z" | fromdateiso8601) * 1000 + ((.f // "0") + "00" | .[0:3] | tonumber) - ((.z // "Z") | if . == "Z" then 0 else gsub(":"; "") | (if .[0:1] == "-" then -1 else 1 end) * ((.[1:3] | tonumber) * 60 + (.[3:5] | tonumber)) * 60000 end)) catch null) // null;"#,
+    ),
+    (
+        "_date",
+        &["_number", "_clip", "_from_iso"],
+        r#"def _date: (if (.i | type) == "string" then .i | _from_iso else _number end) | _clip;"#,
+    ),
+    (
+        "_seconds",
+        &["_number", "_clip"],
+        r#"def _seconds: _number | if . == null then null else . * 1000 | _clip end;"#,
+    ),
+    (
+        "_to_iso",
+        &[],
+        r#"def _to_iso: (. / 1000 | floor | todate | .[0:19]) + "." + (. - (. / 1000 | floor) * 1000 | tostring | "00" + . | .[-3:]) + "Z";"#,
+    ),
+    (
+        "_iso",
+        &["_to_iso"],
+        r#"def _iso(t): t as $t | if $t == null then del(.o) else .o = ($t | _to_iso) end;"#,
+    ),
+    (
+        "_base64d",
+        &["_string"],
+        r#"def _base64d: (_string | try @base64d catch null) as $d | if $d == null then del(.o) else .o = $d end;"#,
+    ),
+    (
+        "_extract",
+        &["_string"],
+        r#"def _extract($re; $g): ([_string | match($re)][0] | if . == null then null elif $g == 0 then .string else .captures[$g - 1].string end) as $c | if $c == null then del(.o) else .o = $c end;"#,
+    ),
+    (
+        "_words",
+        &[],
+        r#"def _words: gsub("(?<a>[a-z0-9])(?<b>[A-Z])"; "\(.a) \(.b)") | [splits("[-_ ]+") | select(length > 0) | ascii_downcase];"#,
+    ),
+    (
+        "_camel",
+        &["_words"],
+        r#"def _camel: _words | (.[0] // "") + (.[1:] | map((.[0:1] | ascii_upcase) + .[1:]) | join(""));"#,
+    ),
+    ("_snake", &["_words"], r#"def _snake: _words | join("_");"#),
+    (
+        "_lookup",
+        &[],
+        r#"def _lookup($table): . as $s | [$table[] | select(($s | has("i")) and .[0] == $s.i)] as $hits | if $hits == [] then del(.o) else .o = $hits[0][1] end;"#,
+    ),
+    (
+        "_concat",
+        &["_path", "_str"],
+        r#"def _concat($paths; $sep): .o = ([$paths[] as $p | _path($p) | select(has("i") and .i != null) | .i | _str] | join($sep));"#,
+    ),
+    (
+        "_join",
+        &["_str"],
+        r#"def _join($sep): if (.i | type) == "array" then .o = (.i | map(_str) | join($sep)) else . end;"#,
+    ),
+];
+
+/// Generates a jq filter from an IR path.
+#[derive(Default)]
+pub struct JqCodegen {
+    /// The helpers the filter uses.
+    helpers: BTreeSet<&'static str>,
+    /// The label of each recursive conversion open around the stages being
+    /// generated, and the function defined for it, innermost last.
+    recs: Vec<(Arc<String>, String)>,
+}
+
+/// `value` as jq, which reads JSON as it is.
+fn json(value: impl Into<Value>) -> String {
+    value.into().to_string()
+}
+
+/// `path` as a JSON array of keys.
+fn path(path: &[Arc<String>]) -> String {
+    json(path.iter().map(|key| key.as_str()).collect::<Vec<_>>())
+}
+
+/// `paths` as a JSON array of arrays of keys.
+fn paths(paths: &[Vec<Arc<String>>]) -> String {
+    let paths: Vec<_> = paths.iter().map(|p| path(p)).collect();
+    format!("[{}]", paths.join(","))
+}
+
+/// `pairs` of keys as a JSON array of pairs.
+fn pairs(pairs: &[(Arc<String>, Arc<String>)]) -> String {
+    let pairs: Vec<_> = pairs
+        .iter()
+        .map(|(a, b)| json(vec![a.as_str(), b.as_str()]))
+        .collect();
+    format!("[{}]", pairs.join(","))
+}
+
+/// `stages` piped into one another, a stage per line.
+fn pipeline(stages: &[String]) -> String {
+    match stages {
+        [] => ".".to_string(),
+        stages => stages.join("\n| "),
+    }
+}
+
+/// `text` indented a level, past its first line.
+fn indent(text: &str) -> String {
+    text.replace('\n', "\n  ")
+}
+
+/// A call of the helper `name` with `args`, the last of which is `stages`
+/// filtering the state in a scope.
+fn call(name: &str, args: &[String], stages: &[String]) -> String {
+    let mut args: Vec<_> = args.to_vec();
+    let body = pipeline(stages);
+    if body.contains('\n') {
+        args.push(String::new());
+        format!(
+            "{}({}\n  {})",
+            name,
+            args.join("; ").trim_end(),
+            indent(&body)
+        )
+    } else {
+        args.push(body);
+        format!("{}({})", name, args.join("; "))
+    }
+}
+
+impl JqCodegen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `helper`, and the helpers it uses, in the filter.
+    fn helper(&mut self, helper: &'static str) -> &'static str {
+        if self.helpers.insert(helper) {
+            let (_, uses, _) = HELPERS
+                .iter()
+                .find(|(name, _, _)| *name == helper)
+                .expect("helper is defined");
+            for used in *uses {
+                self.helper(used);
+            }
+        }
+        helper
+    }
+
+    /// An expression testing whether the input has the shape of `schema`,
+    /// for dispatching on union branches. Objects are told apart by their
+    /// required properties.
+    fn test(schema: &Schema) -> String {
+        let ty = |ty: &str| format!("(.i | type) == \"{}\"", ty);
+        match schema {
+            Schema::Ground(Ground::Num) => ty("number"),
+            Schema::Ground(Ground::Bool) => ty("boolean"),
+            Schema::Ground(Ground::String) => ty("string"),
+            Schema::Ground(Ground::Null) => "has(\"i\") and .i == null".to_string(),
+            Schema::Arr(_) => ty("array"),
+            Schema::Obj { required, .. } => {
+                let mut test = ty("object");
+                if !required.is_empty() {
+                    let keys: Vec<_> = required
+                        .iter()
+                        .map(|key| format!("has({})", json(key.as_str())))
+                        .collect();
+                    test.push_str(&format!(" and (.i | {})", keys.join(" and ")));
+                }
+                test
+            }
+            Schema::Union(branches) => branches
+                .iter()
+                .map(|branch| format!("({})", Self::test(branch)))
+                .collect::<Vec<_>>()
+                .join(" or "),
+            Schema::Rec(_, body) | Schema::Annotated(body, _) => Self::test(body),
+            Schema::True | Schema::Var(_) => "true".to_string(),
+            Schema::False => "false".to_string(),
+        }
+    }
+
+    /// The stages running `ops`, the instructions of one scope.
+    fn stages(&mut self, ops: &[IR]) -> Vec<String> {
+        let mut stages = vec![];
+        let mut i = 0;
+        while i < ops.len() {
+            let op = &ops[i];
+            if op.closer().is_some() {
+                let end = scope_end(&ops[i..]).unwrap_or(ops.len() - i);
+                let body = &ops[i + 1..(i + end).min(ops.len())];
+                self.scope(op, body, &mut stages);
+                i += end + 1;
+                continue;
+            }
+            i += 1;
+            if let IR::Wrap = op {
+                // the rest of the scope writes inside the array
+                let rest = self.stages(&ops[i..]);
+                stages.push(call(self.helper("_wrap"), &[], &rest));
+                break;
+            }
+            if let Some(stage) = self.step(op) {
+                stages.push(stage);
+            }
+        }
+        stages
+    }
+
+    /// Add the stages running the scope `op` opens, whose instructions are
+    /// `body`, to `stages`.
+    fn scope(&mut self, op: &IR, body: &[IR], stages: &mut Vec<String>) {
+        match op {
+            IR::PushObj => {
+                stages.push(".o = {}".to_string());
+                stages.extend(self.stages(body));
+            }
+            IR::Merge => {
+                stages.push(self.helper("_merge").to_string());
+                stages.extend(self.stages(body));
+            }
+            IR::PushKey(key) | IR::Rename(key, _) => {
+                let to = match op {
+                    IR::Rename(_, to) => to,
+                    _ => key,
+                };
+                let body = self.stages(body);
+                let args = [json(key.as_str()), json(to.as_str())];
+                stages.push(call(self.helper("_field"), &args, &body));
+            }
+            IR::Nest(key) => {
+                let body = self.stages(body);
+                stages.push(call(self.helper("_nest"), &[json(key.as_str())], &body));
+            }
+            IR::PushOpt => {
+                let body = self.stages(body);
+                stages.push(call(self.helper("_opt"), &[], &body));
+            }
+            IR::PushArr => {
+                let body = self.stages(body);
+                stages.push(call(self.helper("_each"), &[], &body));
+            }
+            IR::PushRest(named) => {
+                let body = self.stages(body);
+                stages.push(call(self.helper("_rest"), &[path(named)], &body));
+            }
+            IR::PushUnion => {
+                let mut chain = String::new();
+                let mut i = 0;
+                while i < body.len() {
+                    let end = scope_end(&body[i..]).unwrap_or(body.len() - i);
+                    if let IR::Case(branch) = &body[i] {
+                        let inner = self.stages(&body[i + 1..(i + end).min(body.len())]);
+                        chain.push_str(&format!(
+                            "{} {} then\n  {}\n",
+                            if chain.is_empty() { "if" } else { "elif" },
+                            Self::test(branch),
+                            indent(&pipeline(&inner))
+                        ));
+                    }
+                    i += end + 1;
+                }
+                if !chain.is_empty() {
+                    stages.push(format!("{}else . end", chain));
+                }
+            }
+            // a function calling itself for the parts of the input of the
+            // same schema, which only ever changes the input inside it
+            IR::Rec(label) => {
+                let base: String = label
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect();
+                let mut name = format!("_convert_{}", base);
+                if self.recs.iter().any(|(_, open)| *open == name) {
+                    name = format!("{}_{}", name, self.recs.len());
+                }
+                self.recs.push((label.clone(), name.clone()));
+                let body = self.stages(body);
+                self.recs.pop();
+                let call = format!("{}({})", self.helper("_call"), name);
+                stages.push(format!(
+                    "(def {}:
+  {};
+{})",
+                    name,
+                    indent(&pipeline(&body)),
+                    call
+                ));
+            }
+            IR::Case(_) => unreachable!("cases are only run by their unions"),
+            _ => unreachable!("only openers open scopes"),
+        }
+    }
+
+    /// The stage running an instruction which doesn't open or close a
+    /// scope, if it does anything.
+    fn step(&mut self, op: &IR) -> Option<String> {
+        Some(match op {
+            IR::Copy => self.helper("_copy").to_string(),
+            IR::G2G(_, to) => match to {
+                Ground::String => format!(".o = {}", self.helper("_string")),
+                Ground::Num => format!(".o = {}", self.helper("_number")),
+                Ground::Bool => format!(".o = {}", self.helper("_truthy")),
+                Ground::Null => ".o = null".to_string(),
+            },
+            IR::Pipe => self.helper("_pipe").to_string(),
+            IR::Extr(key) => format!("{}({})", self.helper("_extr"), json(key.as_str())),
+            IR::Coalesce(keys) => format!("{}({})", self.helper("_coalesce"), paths(keys)),
+            IR::First => self.helper("_first").to_string(),
+            IR::Flatten(depth) => format!("{}(flatten({}))", self.helper("_arrays"), depth),
+            IR::Slice(start, end) => format!(
+                "{}(.[{}:{}])",
+                self.helper("_arrays"),
+                start,
+                end.map_or("null".to_string(), |end| end.to_string())
+            ),
+            IR::Sort(key, order) => format!(
+                "{}({}; {})",
+                self.helper("_sort"),
+                path(key),
+                *order == SortOrder::Descending
+            ),
+            IR::Dedupe(key) => format!(
+                "{}({})",
+                self.helper("_dedupe"),
+                key.as_deref().map_or("null".to_string(), path)
+            ),
+            IR::Gather(keys) => format!("{}({})", self.helper("_gather"), paths(keys)),
+            IR::Zip(keys) => format!("{}({})", self.helper("_zip"), pairs(keys)),
+            IR::Unzip(keys) => format!("{}({})", self.helper("_unzip"), pairs(keys)),
+            IR::Inv => self.helper("_inv").to_string(),
+            IR::GroupBy(key) => format!("{}({})", self.helper("_group"), json(key.as_str())),
+            IR::Scale { factor, offset } => {
+                format!("{}({:?}; {:?})", self.helper("_scale"), factor.0, offset.0)
+            }
+            IR::DateConvert {
+                from_format,
+                to_format,
+            } => {
+                let time = match from_format {
+                    DateFormat::EpochSeconds => self.helper("_seconds"),
+                    _ => self.helper("_date"),
+                };
+                match to_format {
+                    DateFormat::Iso => format!("{}({})", self.helper("_iso"), time),
+                    DateFormat::EpochSeconds => format!(
+                        ".o = ({} | if . == null then null else . / 1000 | floor end)",
+                        time
+                    ),
+                    DateFormat::EpochMillis => format!(".o = {}", time),
+                }
+            }
+            IR::Base64(Coding::Encode) => format!(".o = ({} | @base64)", self.helper("_string")),
+            IR::Base64(Coding::Decode) => self.helper("_base64d").to_string(),
+            IR::RegexExtract(pattern, group) => format!(
+                "{}({}; {})",
+                self.helper("_extract"),
+                json(pattern.as_str()),
+                group
+            ),
+            IR::CaseTransform(case) => {
+                let string = self.helper("_string");
+                let convert = match case {
+                    Case::Upper => "ascii_upcase",
+                    Case::Lower => "ascii_downcase",
+                    Case::Camel => self.helper("_camel"),
+                    Case::Snake => self.helper("_snake"),
+                };
+                format!(".o = ({} | {})", string, convert)
+            }
+            IR::MapValues(table) => {
+                let entries: Vec<_> = table
+                    .iter()
+                    .map(|(from, to)| format!("[{},{}]", from.0, to.0))
+                    .collect();
+                format!("{}([{}])", self.helper("_lookup"), entries.join(","))
+            }
+            IR::Concat(keys, sep) => format!(
+                "{}({}; {})",
+                self.helper("_concat"),
+                paths(keys),
+                json(sep.as_str())
+            ),
+            IR::Const(value) => format!(".o = {}", value.0),
+            IR::Join(sep) => format!("{}({})", self.helper("_join"), json(sep.as_str())),
+            IR::Call(label) => {
+                let (_, name) = self.recs.iter().rev().find(|(open, _)| open == label)?;
+                let name = name.clone();
+                format!("{}({})", self.helper("_call"), name)
+            }
+            // instructions closing scopes which weren't opened do nothing
+            _ => return None,
+        })
+    }
+}
+
+impl Codegen for JqCodegen {
+    fn generate(&mut self, ir: &[IR], _input: &str, _output: &str) -> String {
+        self.helpers.clear();
+        let mut stages = vec!["{i: .}".to_string()];
+        stages.extend(self.stages(ir));
+        stages.push(self.helper("_value").to_string());
+
+        let mut out = String::new();
+        for (name, _, definition) in HELPERS {
+            if self.helpers.contains(name) {
+                out.push_str(definition);
+                out.push('\n');
+            }
+        }
+        out.push_str(&pipeline(&stages));
+        out.push('\n');
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::key;
+
+    /// The output of running the filter generated for `ir` on `input` with
+    /// jq, or `None` where jq isn't installed.
+    fn run(ir: &[IR], input: &serde_json::Value) -> Option<serde_json::Value> {
+        use std::io::Write;
+
+        let filter = JqCodegen::new().generate(ir, "input", "output");
+        let mut jq = std::process::Command::new("jq")
+            .args(["-c", &filter])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut stdin = jq.stdin.take().unwrap();
+        stdin.write_all(input.to_string().as_bytes()).unwrap();
+        drop(stdin);
+        let out = jq.wait_with_output().unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        Some(serde_json::from_slice(&out.stdout).unwrap())
+    }
+
+    /// The filter generated for `ir`, without the helpers it defines.
+    fn generate(ir: &[IR]) -> String {
+        let filter = JqCodegen::new().generate(ir, "input", "output");
+        filter
+            .lines()
+            .filter(|line| !line.starts_with("def "))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_object() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::Nest(key("meta")),
+            IR::PushObj,
+            IR::PushKey(key("tags")),
+            IR::PushOpt,
+            IR::Dedupe(None),
+            IR::Copy,
+            IR::PopOpt,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            r#"{i: .}
+| .o = {}
+| _field("user_id"; "id"; .o = _string)
+| _nest("meta";
+  .o = {}
+  | _field("tags"; "tags";
+    _opt(
+      _dedupe(null)
+      | _copy)))
+| _value"#
+        );
+    }
+
+    #[test]
+    fn test_run_object() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::Nest(key("meta")),
+            IR::PushObj,
+            IR::PushKey(key("tags")),
+            IR::PushOpt,
+            IR::Dedupe(None),
+            IR::Copy,
+            IR::PopOpt,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        for input in [
+            serde_json::json!({"user_id": 7, "tags": ["a", "b", "a"]}),
+            serde_json::json!({"user_id": 7}),
+        ] {
+            if let Some(output) = run(&ir, &input) {
+                assert_eq!(output, crate::ir::eval(&ir, &input));
+            }
+        }
+        let input = serde_json::json!({"user_id": 7, "tags": ["a", "b", "a"]});
+        if let Some(output) = run(&ir, &input) {
+            assert_eq!(
+                output,
+                serde_json::json!({"id": "7", "meta": {"tags": ["a", "b"]}})
+            );
+        }
+    }
+
+    #[test]
+    fn test_arrays_and_unions() {
+        let ir = [
+            IR::PushArr,
+            IR::PushUnion,
+            IR::Case(Arc::new(Schema::string())),
+            IR::Wrap,
+            IR::Copy,
+            IR::PopCase,
+            IR::Case(Arc::new(Schema::Arr(Arc::new(Schema::string())))),
+            IR::Copy,
+            IR::PopCase,
+            IR::PopUnion,
+            IR::PopArr,
+        ];
+        let input = serde_json::json!(["a", ["b", "c"], []]);
+        if let Some(output) = run(&ir, &input) {
+            assert_eq!(output, serde_json::json!([["a"], ["b", "c"], []]));
+            assert_eq!(output, crate::ir::eval(&ir, &input));
+        }
+    }
+
+    #[test]
+    fn test_helpers() {
+        // only the helpers used are defined, along with those they use
+        let filter =
+            JqCodegen::new().generate(&[IR::CaseTransform(Case::Camel)], "input", "output");
+        let defined: Vec<_> = filter
+            .lines()
+            .filter_map(|line| line.strip_prefix("def "))
+            .map(|def| &def[..def.find([':', '(']).unwrap()])
+            .collect();
+        assert_eq!(defined, ["_str", "_value", "_string", "_words", "_camel"]);
+    }
+    #[test]
+    fn test_recursion() {
+        let node = Arc::new("node".to_string());
+        let ir = [
+            IR::Rec(node.clone()),
+            IR::PushObj,
+            IR::PushKey(key("children")),
+            IR::PushArr,
+            IR::Call(node),
+            IR::PopArr,
+            IR::PopKey,
+            IR::PushKey(key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopRec,
+        ];
+        let input = serde_json::json!({"id": 1, "children": [{"id": 2, "children": []}]});
+        if let Some(output) = run(&ir, &input) {
+            assert_eq!(
+                output,
+                serde_json::json!({"id": "1", "children": [{"id": "2", "children": []}]})
+            );
+        }
+    }
+}
@@ -0,0 +1,4596 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    ir::{join_path, scope_end, Case, Coding, DateFormat, SortOrder, IR},
+    report::places,
+    schema::{Ground, Schema},
+    searcher::describe,
+};
+
+use super::{
+    ts::{declaration, type_of, SOURCE, TARGET},
+    Codegen, CodegenOptions, ReturnStyle,
+};
+use format::Formatting;
+
+pub mod format;
+pub mod spec;
+
+/// The contents of a template literal naming where `input` reads from,
+/// with the indices and keys of loops filled in.
+fn location(input: &str) -> String {
+    let mut out = String::new();
+    let mut rest = input;
+    while let Some(start) = rest.find('[') {
+        let (before, after) = rest.split_at(start + 1);
+        out.push_str(&template(before));
+        let end = after.find(']').unwrap_or(after.len());
+        let index = &after[..end];
+        if index.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            out.push_str(&format!("${{{}}}", index));
+        } else {
+            out.push_str(&template(index));
+        }
+        rest = &after[end..];
+    }
+    out.push_str(&template(rest));
+    out
+}
+
+/// The condition and the read of an ES5 optional read, which is missing
+/// when the condition holds.
+fn unguarded(input: &str) -> Option<(&str, &str)> {
+    input
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .split_once(" ? undefined : ")
+}
+
+/// `s` escaped for the contents of a template literal.
+fn template(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('`', "\\`")
+        .replace("${", "\\${")
+}
+
+/// The locations the generated code is reading from and writing to.
+#[derive(Clone)]
+struct Frame {
+    input: String,
+    output: String,
+    /// TypeScript type of the output location, when generating TypeScript.
+    ty: String,
+    /// Source schema of the input, where it's known.
+    schema: Option<Arc<Schema>>,
+    /// Target schema of the output, where it's known and objects pass
+    /// unmapped properties through.
+    target: Option<Arc<Schema>>,
+    /// Whether the output is nested in an object built out of the same
+    /// input, whose properties aren't the output's own.
+    nested: bool,
+    /// Whether the input may be `undefined` or `null`.
+    absent: bool,
+    /// Whether the output is an item of an array, where `undefined` is
+    /// written as `null` anyway.
+    item: bool,
+    /// How many `else` branches of checks that the properties it reads are
+    /// there the scope is written in, which are closed along with it.
+    checks: usize,
+}
+
+impl Frame {
+    /// A frame reading and writing in the same places as this one, whose
+    /// input has the shape of `schema`, when that's known.
+    fn with_schema(&self, schema: Option<Arc<Schema>>) -> Self {
+        Self {
+            absent: schema.as_deref().is_none_or(nullable),
+            schema,
+            checks: 0,
+            ..self.clone()
+        }
+    }
+}
+
+/// A JSON Pointer into a schema to where it describes what's found at
+/// `path` (one of the paths [`places`] gives) in its data.
+fn pointer(path: &str) -> String {
+    let mut pointer = String::new();
+    for key in path.split('.').filter(|key| !key.is_empty()) {
+        match key {
+            "[]" | "[0]" => pointer.push_str("/items"),
+            "*" => pointer.push_str("/additionalProperties"),
+            // slices are described by the array's own schema
+            key if key.starts_with('[') => {}
+            key => {
+                let key = key.replace('~', "~0").replace('/', "~1");
+                pointer.push_str(&format!("/properties/{}", key));
+            }
+        }
+    }
+    pointer
+}
+
+/// The schema of property `key` of `schema` objects, where it's known.
+fn member_schema(schema: &Option<Arc<Schema>>, key: &str) -> Option<Arc<Schema>> {
+    match schema.as_deref() {
+        Some(Schema::Obj {
+            props, additional, ..
+        }) => props.get(&key.to_string()).or(additional.as_ref()).cloned(),
+        _ => None,
+    }
+}
+
+/// The schema of the items of `schema` arrays, where it's known.
+fn item_schema(schema: &Option<Arc<Schema>>) -> Option<Arc<Schema>> {
+    match schema.as_deref() {
+        Some(Schema::Arr(item)) => Some(item.clone()),
+        _ => None,
+    }
+}
+
+/// Whether `schema` objects may have any properties besides their own.
+fn open(schema: &Option<Arc<Schema>>) -> bool {
+    matches!(
+        schema.as_deref(),
+        Some(Schema::Obj { additional: Some(additional), .. }) if **additional == Schema::True
+    )
+}
+
+/// Whether values of `schema` may be `null`.
+fn nullable(schema: &Schema) -> bool {
+    match schema {
+        Schema::Ground(Ground::Null) | Schema::True => true,
+        Schema::Union(branches) => branches.iter().any(|branch| nullable(branch)),
+        _ => false,
+    }
+}
+
+/// Whether `key` is an identifier, which can be written as is as a
+/// property name.
+pub(crate) fn identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// What follows an object to read its property `key`: a dot and the key
+/// itself if it's an identifier, or a string in brackets if it isn't.
+fn access(key: &str) -> String {
+    if identifier(key) {
+        format!(".{}", key)
+    } else {
+        format!("[{}]", serde_json::Value::from(key))
+    }
+}
+
+/// A statement making `key` (an expression) an own property of `object`,
+/// so that assigning it sets the property, even if it's `__proto__`, rather
+/// than the object's prototype.
+fn own(object: &str, key: &str) -> String {
+    format!(
+        "Object.defineProperty({}, {}, {{ writable: true, enumerable: true, configurable: true }});",
+        object, key
+    )
+}
+
+/// A short name for the type of values of `schema`.
+fn kind(schema: &Schema) -> String {
+    match schema {
+        Schema::Ground(Ground::Num) => "number".to_string(),
+        Schema::Ground(Ground::Bool) => "boolean".to_string(),
+        Schema::Ground(Ground::String) => "string".to_string(),
+        Schema::Ground(Ground::Null) => "null".to_string(),
+        Schema::Arr(_) => "array".to_string(),
+        Schema::Obj { .. } => "object".to_string(),
+        Schema::Union(branches) => {
+            let mut kinds: Vec<_> = branches.iter().map(|branch| kind(branch)).collect();
+            kinds.dedup();
+            kinds.join("|")
+        }
+        Schema::Rec(_, body) | Schema::Annotated(body, _) => kind(body),
+        Schema::True | Schema::Var(_) => "any".to_string(),
+        Schema::False => "never".to_string(),
+    }
+}
+
+/// The type of the values at `keys` in data of `schema`, along with their
+/// format, unit and encoding, like `string,date-time`.
+fn rule(schema: &Schema, keys: &[&str]) -> Option<String> {
+    let Some((key, rest)) = keys.split_first() else {
+        let annotations = schema.annotations();
+        let rules = [
+            &annotations.format,
+            &annotations.unit,
+            &annotations.encoding,
+        ];
+        let rules = rules.into_iter().flatten().map(|rule| rule.to_string());
+        return Some(
+            [kind(schema)]
+                .into_iter()
+                .chain(rules)
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+    };
+    match schema {
+        Schema::Arr(item) if key.starts_with('[') => rule(item, rest),
+        Schema::Obj {
+            props, additional, ..
+        } => {
+            let key = Arc::new(key.to_string());
+            let prop = props.get(&key).or(additional.as_ref())?;
+            rule(prop, rest)
+        }
+        Schema::Union(branches) => branches.iter().find_map(|branch| rule(branch, keys)),
+        Schema::Annotated(inner, _) => rule(inner, keys),
+        _ => None,
+    }
+}
+
+/// The format of the values at `keys` in data of `schema`, if they have
+/// one.
+fn format_of(schema: &Schema, keys: &[&str]) -> Option<Arc<String>> {
+    let Some((key, rest)) = keys.split_first() else {
+        return schema.annotations().format.clone();
+    };
+    match schema {
+        Schema::Arr(item) if key.starts_with('[') => format_of(item, rest),
+        Schema::Obj {
+            props, additional, ..
+        } => {
+            let key = Arc::new(key.to_string());
+            let prop = props.get(&key).or(additional.as_ref())?;
+            format_of(prop, rest)
+        }
+        Schema::Union(branches) => branches.iter().find_map(|branch| format_of(branch, keys)),
+        Schema::Annotated(inner, _) => format_of(inner, keys),
+        _ => None,
+    }
+}
+
+/// Whether the values at `path` (one of the paths [`places`] gives) in data
+/// of `schema` are 64-bit integers, more than a number holds exactly.
+fn int64(schema: &Schema, path: &str) -> bool {
+    let keys: Vec<_> = path.split('.').filter(|key| !key.is_empty()).collect();
+    format_of(schema, &keys).is_some_and(|format| matches!(format.as_str(), "int64" | "uint64"))
+}
+
+/// `path` of data of `schema`, as written in a provenance comment: the
+/// keys to it followed by the type there, where that's known.
+fn provenance(schema: &Schema, path: &str, root: &str) -> String {
+    let keys: Vec<_> = path.split('.').filter(|key| !key.is_empty()).collect();
+    let place = match path {
+        "" => root.to_string(),
+        _ => path.replace(".[", "["),
+    };
+    match rule(schema, &keys) {
+        Some(rule) => format!("{} ({})", place, rule),
+        None => place,
+    }
+}
+
+/// A function's `body` yielding what it returns instead, where it returns
+/// in its last statement.
+fn yielding(body: &str) -> String {
+    match body.trim_end().rsplit_once('\n') {
+        Some((rest, last)) if last.trim_start().starts_with("return ") => {
+            format!("{}\n{}\n", rest, last.replacen("return ", "yield ", 1))
+        }
+        None if body.trim_start().starts_with("return ") => body.replacen("return ", "yield ", 1),
+        _ => body.to_string(),
+    }
+}
+
+/// The name of the helper converting data of the recursive schema `label`,
+/// like `convertNode`.
+fn converter(label: &str) -> String {
+    let mut name = "convert".to_string();
+    for part in label.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            name.push(first.to_ascii_uppercase());
+            name.extend(chars);
+        }
+    }
+    name
+}
+
+/// The TypeScript type of property (or item) `index` of values of type
+/// `ty`, leaving out `undefined` for properties which are only written once
+/// they're there.
+fn member(ty: &str, index: &str) -> String {
+    format!("NonNullable<{}[{}]>", ty, index)
+}
+
+/// What a helper function writes: a block of instructions, and the source
+/// schema, target schema, absence, itemhood and nesting of its input and
+/// output, which the block is written differently for.
+type Shared = (
+    Vec<IR>,
+    Option<Arc<Schema>>,
+    Option<Arc<Schema>>,
+    bool,
+    bool,
+    bool,
+);
+
+/// Blocks of instructions at least this long are shared by helpers.
+const SHARED_LENGTH: usize = 5;
+
+/// The blocks of `ir` building objects or arrays which it has more than
+/// once, other than those only found inside a larger one.
+fn repeated(ir: &[IR]) -> HashSet<Vec<IR>> {
+    let blocks = |i: usize| {
+        let end = scope_end(&ir[i..]).filter(|end| end + 1 >= SHARED_LENGTH)?;
+        matches!(ir[i], IR::PushObj | IR::PushArr).then(|| &ir[i..=i + end])
+    };
+    let mut counts: HashMap<&[IR], usize> = HashMap::new();
+    for block in (0..ir.len()).filter_map(blocks) {
+        *counts.entry(block).or_default() += 1;
+    }
+    let mut repeated = HashSet::new();
+    let mut i = 0;
+    while i < ir.len() {
+        match blocks(i).filter(|block| counts[block] > 1) {
+            Some(block) => {
+                repeated.insert(block.to_vec());
+                i += block.len();
+            }
+            None => i += 1,
+        }
+    }
+    repeated
+}
+
+/// The names declared in each block open around the code being generated,
+/// outermost first, so that fresh names are never the same as any name
+/// they'd shadow or be shadowed by.
+#[derive(Default)]
+struct Symbols {
+    scopes: Vec<HashSet<String>>,
+    /// What every fresh name starts with.
+    prefix: String,
+    numbering: Numbering,
+    /// Counter for fresh names.
+    uniq: usize,
+    /// Counters for fresh names of each base, when they're numbered apart.
+    counts: HashMap<String, usize>,
+}
+
+impl Symbols {
+    /// No names declared yet, other than `reserved` in the outermost scope,
+    /// like what the function takes and returns.
+    fn new(prefix: &str, numbering: Numbering, reserved: &[&str]) -> Self {
+        Self {
+            scopes: vec![reserved.iter().map(|name| name.to_string()).collect()],
+            prefix: prefix.to_string(),
+            numbering,
+            uniq: 0,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Whether `name` is declared in the scope `depth` blocks deep or any
+    /// around it, closing any blocks deeper than that.
+    fn visible(&mut self, name: &str, depth: usize) -> bool {
+        self.scopes.resize_with(depth + 1, HashSet::new);
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    /// `name` if it's free in the scope `depth` blocks deep, or else a
+    /// fresh name like it, declared there.
+    fn declare(&mut self, name: &str, depth: usize) -> String {
+        if self.visible(name, depth) {
+            return self.fresh(name, depth);
+        }
+        self.scopes[depth].insert(name.to_string());
+        name.to_string()
+    }
+
+    /// `name` (after the prefix) declared in the scope `depth` blocks deep
+    /// from inside a deeper block, like [`Symbols::hoist`] declares a fresh
+    /// name, if no block open sees it already, or else a fresh name like it.
+    fn hoist_as(&mut self, name: &str, depth: usize) -> String {
+        let prefixed = format!("{}{}", self.prefix, name);
+        if self.scopes.iter().any(|scope| scope.contains(&prefixed)) {
+            return self.hoist(name, depth);
+        }
+        self.scopes
+            .resize_with(self.scopes.len().max(depth + 1), HashSet::new);
+        self.scopes[depth].insert(prefixed.clone());
+        prefixed
+    }
+
+    /// A fresh name like [`Symbols::fresh`] gives, declared in the scope
+    /// `depth` blocks deep from inside a deeper block, which stays open.
+    fn hoist(&mut self, base: &str, depth: usize) -> String {
+        let inner = self.scopes.split_off((depth + 1).min(self.scopes.len()));
+        let name = loop {
+            let name = self.fresh(base, depth);
+            if !inner.iter().any(|scope| scope.contains(&name)) {
+                break name;
+            }
+        };
+        self.scopes.extend(inner);
+        name
+    }
+
+    /// A name made of the prefix, `base` and a number, declared in the
+    /// scope `depth` blocks deep.
+    fn fresh(&mut self, base: &str, depth: usize) -> String {
+        loop {
+            let uniq = match self.numbering {
+                Numbering::Sequential => &mut self.uniq,
+                Numbering::PerName => self.counts.entry(base.to_string()).or_default(),
+            };
+            let name = format!("{}{}{}", self.prefix, base, uniq);
+            *uniq += 1;
+            if !self.visible(&name, depth) {
+                self.scopes[depth].insert(name.clone());
+                return name;
+            }
+        }
+    }
+}
+
+/// The JavaScript environment the generated code runs in, for the few
+/// conversions whose builtins differ between them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Runtime {
+    /// Browsers, which base64 encode with `btoa` and decode with `atob`,
+    /// by way of the string's UTF-8 bytes.
+    #[default]
+    Browser,
+    /// Node.js, which does both with `Buffer`.
+    Node,
+}
+
+/// How the generated function is made available to the code around it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ModuleFormat {
+    /// A bare function expression, to be assigned or passed wherever.
+    #[default]
+    Expression,
+    /// The default export of an ES module.
+    ExportDefault,
+    /// An ES module exporting it by its name, as if it were declared.
+    ExportNamed,
+    /// A CommonJS module, as `module.exports`.
+    CommonJs,
+}
+
+/// How the generated function is defined.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FunctionStyle {
+    /// An anonymous function expression.
+    #[default]
+    Expression,
+    /// A function declaration.
+    Declaration,
+    /// An arrow function, bound to a `const` of its name.
+    Arrow,
+}
+
+/// How strings are converted to numbers. Other values are converted by
+/// `Number`, which takes `true` to `1`, and `false` and `null` to `0`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NumberCoercion {
+    /// With `Number`, like the IR's semantics: strings which are wholly a
+    /// number (surrounding whitespace aside) are that number, blank ones
+    /// are `0`, and anything else is `NaN`.
+    #[default]
+    Number,
+    /// With `parseFloat`, reading as much of the start of a string as makes
+    /// a number, so `"12px"` is `12`, and blank strings are `NaN`.
+    ParseFloat,
+    /// Like [`NumberCoercion::Number`], except that blank strings are `NaN`
+    /// rather than `0`.
+    Strict,
+}
+
+/// The version of JavaScript the generated code is written in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// Modern JavaScript, as of ES2020.
+    #[default]
+    Modern,
+    /// ES5, for old engines like Rhino: `var` rather than `let` and
+    /// `const`, function expressions rather than arrow functions, no
+    /// template literals, spreads or optional chains, and only the builtins
+    /// ES5 has. Objects are copied through JSON. Modules and generators are
+    /// still written as asked for.
+    Es5,
+}
+
+/// How the variables the generated code declares are numbered, which is
+/// the same every time the same transform is generated either way.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Numbering {
+    /// One count through the whole function: `idx0`, `sorted1`, `idx2`.
+    #[default]
+    Sequential,
+    /// A count of each name of its own: `idx0`, `sorted0`, `idx1`. A
+    /// variable added or removed only renumbers those named like it after
+    /// it, so regenerating the transform after a change to the schemas
+    /// changes as few lines as it can.
+    PerName,
+}
+
+/// Whether the generated function returns its output or yields it lazily.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Streaming {
+    /// Returning the whole output at once.
+    #[default]
+    Off,
+    /// A generator function, taking any iterable of items and yielding each
+    /// converted item as it's asked for.
+    Generator,
+    /// An async generator function, taking async iterables (like a stream of
+    /// parsed NDJSON lines) as well.
+    AsyncGenerator,
+}
+
+/// How the generated code builds arrays.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ArrayStyle {
+    /// Writing each item in an indexed `for` loop.
+    #[default]
+    Loop,
+    /// Mapping the input array with `map`, and an arrow function returning
+    /// each item.
+    Map,
+}
+
+/// What the generated function does when it can't convert its input: a
+/// number which doesn't parse, or a property the path reads without
+/// checking it's there.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ErrorMode {
+    /// Carry on, writing whatever comes of it, like `NaN` (written as
+    /// `null`) or `"undefined"`.
+    #[default]
+    Ignore,
+    /// Throw a `TypeError` saying where.
+    Throw,
+    /// Carry on, returning `{ result, errors }`, with an `{ path, message }`
+    /// in `errors` for each failure.
+    Collect,
+    /// Write `0` for numbers and `null` for missing properties.
+    Fallback,
+}
+
+/// Generates a JavaScript function from an IR path.
+#[derive(Default)]
+pub struct JSCodegen {
+    runtime: Runtime,
+    module: ModuleFormat,
+    style: FunctionStyle,
+    /// The function's name and how it returns; its argument and output are
+    /// named as [`Codegen::generate`] is told.
+    pub(super) options: CodegenOptions,
+    /// Schema the input is checked against before it's transformed, if
+    /// it's checked at all.
+    validate: Option<Schema>,
+    /// Schema the input is read by, in safe mode.
+    safe: Option<Schema>,
+    /// Source and target schemas to comment each write with where it
+    /// comes from, if it's commented at all.
+    provenance: Option<(Schema, Schema)>,
+    /// Source and target schemas to document the function's types with in
+    /// JSDoc, if it's documented at all.
+    jsdoc: Option<(Schema, Schema)>,
+    /// Source and target schemas whose 64-bit integers are converted as
+    /// `BigInt`s, if any are.
+    bigint: Option<(Schema, Schema)>,
+    errors: ErrorMode,
+    coercion: NumberCoercion,
+    arrays: ArrayStyle,
+    streaming: Streaming,
+    dialect: Dialect,
+    /// Whether the function opts into strict mode.
+    strict: bool,
+    /// Whether the output is deeply frozen before it's returned.
+    freeze: bool,
+    /// Target schema whose open objects unmapped properties are passed
+    /// through to.
+    passthrough: Option<Schema>,
+    /// Whether blocks the path has more than once are written once, as
+    /// helper functions.
+    helpers: bool,
+    /// The blocks shared by helpers in the path being generated.
+    repeated: HashSet<Vec<IR>>,
+    /// The helpers written so far, by what they write.
+    shared: HashMap<Shared, String>,
+    /// The helpers' definitions, hoisted to the top of the body, and the
+    /// instruction each line was written for.
+    definitions: String,
+    defined: Vec<Option<usize>>,
+    /// What fresh variable names start with.
+    prefix: String,
+    /// TypeScript type of the whole output, to annotate the generated code
+    /// with types for [`TSCodegen`](super::ts::TSCodegen).
+    pub(super) output_type: Option<String>,
+    numbering: Numbering,
+    formatting: Formatting,
+    symbols: Symbols,
+    /// The array errors are collected in, in collect mode.
+    collected: String,
+    /// The label of each recursive conversion open around the code being
+    /// generated, and the helper writing it, innermost last.
+    recs: Vec<(Arc<String>, String)>,
+    /// The parameters of the `map` callbacks open around the code being
+    /// generated, each with where the items it's passed are found in the
+    /// input (as the contents of a template literal), innermost last.
+    aliases: Vec<(String, String)>,
+    indent: usize,
+    body: String,
+    /// The instruction each line of the body was written for, if any.
+    owners: Vec<Option<usize>>,
+    /// The instruction being written.
+    op: Option<usize>,
+    /// The instruction each line of the code last generated was written
+    /// for, if any.
+    lines: Vec<Option<usize>>,
+}
+
+impl JSCodegen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_runtime(runtime: Runtime) -> Self {
+        Self {
+            runtime,
+            ..Self::default()
+        }
+    }
+
+    /// The same generator, making the function available as `module`.
+    pub fn module(self, module: ModuleFormat) -> Self {
+        Self { module, ..self }
+    }
+
+    /// The same generator, checking the input has the shape of `source`
+    /// before transforming it, and throwing a `TypeError` saying where it
+    /// doesn't if it hasn't. Members of unions are told apart the way
+    /// union dispatches tell them apart, without checking them further.
+    pub fn validate(self, source: &Schema) -> Self {
+        Self {
+            validate: Some(source.stripped()),
+            ..self
+        }
+    }
+
+    /// The same generator, in safe mode: reading the input through optional
+    /// chains wherever `source` says what's read may be missing or `null`,
+    /// so that input which doesn't have its shape is read as `undefined`
+    /// rather than crashing the function, and writing array items read
+    /// that way as `null`.
+    pub fn safe(self, source: &Schema) -> Self {
+        Self {
+            safe: Some(source.stripped()),
+            ..self
+        }
+    }
+
+    /// The same generator, commenting each value written with the path it's
+    /// read from in `source` data and the path it's written to in `target`
+    /// data, along with their types, like
+    /// `// user.created_at (string,date-time) -> createdAt (number)`.
+    pub fn provenance(self, source: &Schema, target: &Schema) -> Self {
+        Self {
+            provenance: Some((source.clone(), target.clone())),
+            ..self
+        }
+    }
+
+    /// The same generator, documenting the function with JSDoc typedefs of
+    /// `source` and `target` data, as what it takes and returns, so editors
+    /// and `// @ts-check` can check code calling it.
+    pub fn jsdoc(self, source: &Schema, target: &Schema) -> Self {
+        Self {
+            jsdoc: Some((source.clone(), target.clone())),
+            ..self
+        }
+    }
+
+    /// The same generator, handling failures to convert the input as
+    /// `errors` says.
+    pub fn errors(self, errors: ErrorMode) -> Self {
+        Self { errors, ..self }
+    }
+
+    /// The same generator, converting strings to numbers as `coercion`
+    /// says.
+    pub fn coercion(self, coercion: NumberCoercion) -> Self {
+        Self { coercion, ..self }
+    }
+
+    /// The same generator, streaming the output as `streaming` says. Paths
+    /// converting arrays item by item convert each item only as it's asked
+    /// for, without the whole array ever being in memory, and any other
+    /// path yields its whole output once. Arrow functions can't be
+    /// generators, so they're written as function expressions instead.
+    pub fn streaming(self, streaming: Streaming) -> Self {
+        Self { streaming, ..self }
+    }
+
+    /// The same generator, writing code in `dialect`.
+    pub fn dialect(self, dialect: Dialect) -> Self {
+        Self { dialect, ..self }
+    }
+
+    /// The same generator, building arrays in `arrays` style.
+    pub fn arrays(self, arrays: ArrayStyle) -> Self {
+        Self { arrays, ..self }
+    }
+
+    /// The same generator, opting the function into strict mode with a
+    /// `"use strict"` directive, for scripts which aren't already strict.
+    pub fn strict(self) -> Self {
+        Self {
+            strict: true,
+            ..self
+        }
+    }
+
+    /// The same generator, deeply freezing the output with `Object.freeze`
+    /// before returning it, for callers treating it as an immutable
+    /// snapshot. The output is copied as it's frozen, so that nothing it
+    /// shares with the input, like objects copied from it, is frozen too.
+    pub fn freeze(self) -> Self {
+        Self {
+            freeze: true,
+            ..self
+        }
+    }
+
+    /// The same generator, passing the properties of input objects the
+    /// transform doesn't map through to the objects they're converted to,
+    /// where `target` allows objects any other properties (with
+    /// `"additionalProperties": true`). Those objects start out as a copy of
+    /// the input object, without the properties renamed away, and have the
+    /// mapped properties written over it.
+    pub fn passthrough(self, target: &Schema) -> Self {
+        Self {
+            passthrough: Some(target.stripped()),
+            ..self
+        }
+    }
+
+    /// The same generator, laying out the code it generates (and its
+    /// declaration files) as `formatting` says.
+    pub fn formatting(self, formatting: Formatting) -> Self {
+        Self { formatting, ..self }
+    }
+
+    /// The same generator, numbering the variables it declares as
+    /// `numbering` says. Numbers start over in each function.
+    pub fn numbering(self, numbering: Numbering) -> Self {
+        Self { numbering, ..self }
+    }
+
+    /// The same generator, converting the 64-bit integers of `source` and
+    /// `target` data (those with format `int64` or `uint64`) as `BigInt`s,
+    /// rather than as numbers, which only hold integers up to 2^53 exactly.
+    /// Strings are parsed into `BigInt`s where the target has them, throwing
+    /// a `SyntaxError` if they aren't integers, and scaled by whole factors
+    /// without converting them to numbers in between. `JSON.stringify` throws
+    /// on `BigInt`s, so callers writing the output out as JSON have to
+    /// serialize them themselves, say with a replacer like
+    /// `(k, v) => typeof v === "bigint" ? v.toString() : v`. Not for
+    /// TypeScript, whose types have them as numbers.
+    pub fn bigint(self, source: &Schema, target: &Schema) -> Self {
+        Self {
+            bigint: Some((source.clone(), target.clone())),
+            ..self
+        }
+    }
+
+    /// The same generator, writing each block of instructions building an
+    /// object or array which the path has more than once (as it does for
+    /// a subschema `$ref`erenced from several places) as a helper function,
+    /// which the function defines at the top and calls wherever it's used.
+    /// Not for TypeScript, whose helpers would be untyped.
+    pub fn helpers(self) -> Self {
+        Self {
+            helpers: true,
+            ..self
+        }
+    }
+
+    /// The same generator, starting the names of the variables it declares
+    /// with `prefix`.
+    pub fn prefix(self, prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            ..self
+        }
+    }
+
+    /// The same generator, defining the function in `style`.
+    pub fn style(self, style: FunctionStyle) -> Self {
+        Self { style, ..self }
+    }
+
+    fn fresh(&mut self, base: &str) -> String {
+        self.symbols.fresh(base, self.indent)
+    }
+
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.body.push_str("  ");
+        }
+        self.body.push_str(line);
+        self.body.push('\n');
+        self.owners.push(self.op);
+    }
+
+    fn generate_ground_to_ground(&self, from: &Ground, to: &Ground, input: &str) -> String {
+        match (from, to) {
+            (_, Ground::String) => format!("String({})", input),
+            (Ground::String, Ground::Num) => match self.coercion {
+                NumberCoercion::Number => format!("Number({})", input),
+                NumberCoercion::ParseFloat => format!("parseFloat({})", input),
+                NumberCoercion::Strict => {
+                    format!("(String({input}).trim() === \"\" ? NaN : Number({input}))")
+                }
+            },
+            (_, Ground::Num) => format!("Number({})", input),
+            (_, Ground::Bool) => format!("Boolean({})", input),
+            (_, Ground::Null) => "null".to_string(),
+        }
+    }
+
+    /// An expression testing whether `input` has the shape of `schema`, for
+    /// dispatching on union branches. Objects are told apart by their
+    /// required properties.
+    fn generate_test(schema: &Schema, input: &str) -> String {
+        match schema {
+            Schema::Ground(Ground::Num) => format!("typeof {} === \"number\"", input),
+            Schema::Ground(Ground::Bool) => format!("typeof {} === \"boolean\"", input),
+            Schema::Ground(Ground::String) => format!("typeof {} === \"string\"", input),
+            Schema::Ground(Ground::Null) => format!("{} === null", input),
+            Schema::Arr(_) => format!("Array.isArray({})", input),
+            Schema::Obj { required, .. } => {
+                let mut test = format!(
+                    "typeof {input} === \"object\" && {input} !== null && !Array.isArray({input})"
+                );
+                for key in required {
+                    test.push_str(&format!(
+                        " && {} in {}",
+                        serde_json::Value::from(key.as_str()),
+                        input
+                    ));
+                }
+                test
+            }
+            Schema::Union(branches) => branches
+                .iter()
+                .map(|branch| format!("({})", Self::generate_test(branch, input)))
+                .collect::<Vec<_>>()
+                .join(" || "),
+            Schema::Rec(_, body) | Schema::Annotated(body, _) => Self::generate_test(body, input),
+            Schema::True | Schema::Var(_) => "true".to_string(),
+            Schema::False => "false".to_string(),
+        }
+    }
+}
+
+impl Codegen for JSCodegen {
+    fn generate(&mut self, ir: &[IR], input: &str, output: &str) -> String {
+        let (keyword, body) = match self.streaming {
+            Streaming::Off => ("function", self.generate_body(ir, input, output)),
+            Streaming::Generator => ("function*", self.generate_stream(ir, input, output)),
+            Streaming::AsyncGenerator => {
+                ("async function*", self.generate_stream(ir, input, output))
+            }
+        };
+        let name = &self.options.name;
+        let function = format!("{}({}) {{\n{}}}", keyword, input, body);
+        let named = format!("{} {}({}) {{\n{}}}", keyword, name, input, body);
+        let arrow = match (self.streaming, self.dialect) {
+            (Streaming::Off, Dialect::Modern) => {
+                format!("const {} = ({}) => {{\n{}}};", name, input, body)
+            }
+            (Streaming::Off, Dialect::Es5) => format!("var {} = {};", name, function),
+            _ => format!("const {} = {};", name, function),
+        };
+        let exported = |definition: &str, export: &str| format!("{}\n{}\n", definition, export);
+        let doc = self.generate_jsdoc(input);
+        let body_start = |code: &str| code[..code.find(&body).unwrap_or(0)].lines().count();
+        let code = match (self.style, self.module) {
+            (FunctionStyle::Expression, ModuleFormat::Expression) => format!("{}\n", function),
+            (FunctionStyle::Declaration, ModuleFormat::Expression) => format!("{}\n", named),
+            (FunctionStyle::Arrow, ModuleFormat::Expression) => format!("{}\n", arrow),
+            (FunctionStyle::Expression, ModuleFormat::ExportDefault) => {
+                format!("export default {}\n", function)
+            }
+            (FunctionStyle::Declaration, ModuleFormat::ExportDefault) => {
+                format!("export default {}\n", named)
+            }
+            (FunctionStyle::Arrow, ModuleFormat::ExportDefault) => {
+                exported(&arrow, &format!("export default {};", name))
+            }
+            (FunctionStyle::Expression | FunctionStyle::Declaration, ModuleFormat::ExportNamed) => {
+                format!("export {}\n", named)
+            }
+            (FunctionStyle::Arrow, ModuleFormat::ExportNamed) => format!("export {}\n", arrow),
+            (FunctionStyle::Expression, ModuleFormat::CommonJs) => {
+                format!("module.exports = {};\n", function)
+            }
+            (FunctionStyle::Declaration, ModuleFormat::CommonJs) => {
+                exported(&named, &format!("module.exports = {};", name))
+            }
+            (FunctionStyle::Arrow, ModuleFormat::CommonJs) => {
+                exported(&arrow, &format!("module.exports = {};", name))
+            }
+        };
+        let code = format!("{}{}", doc, code);
+        self.lines = vec![None; body_start(&code)];
+        self.lines.extend(&self.owners);
+        self.formatting.apply(&code)
+    }
+
+    fn generate_with(&mut self, ir: &[IR], options: &CodegenOptions) -> String {
+        self.options = options.clone();
+        self.generate(ir, &options.input, &options.output)
+    }
+}
+
+impl JSCodegen {
+    /// The statements of a function transforming `input` according to `ir`
+    /// and returning the result, built in `output`. `output` is declared
+    /// up front, and only ever written into through objects and arrays the
+    /// function creates itself, so the input is never mutated: an object
+    /// copied from it is copied again before anything's added to it, and
+    /// arrays are copied before they're sorted.
+    pub(super) fn generate_body(&mut self, ir: &[IR], input: &str, output: &str) -> String {
+        self.body.clear();
+        self.owners.clear();
+        self.indent = 1;
+        self.symbols = Symbols::new(&self.prefix, self.numbering, &[input, output]);
+        self.aliases.clear();
+        self.recs.clear();
+        self.repeated = match self.helpers && self.output_type.is_none() {
+            true => repeated(ir),
+            false => HashSet::new(),
+        };
+        self.shared.clear();
+        self.definitions.clear();
+        self.defined.clear();
+        if self.strict {
+            self.line("\"use strict\";");
+        }
+        if let Some(source) = self.validate.clone() {
+            self.generate_checks(&source, input, &template(input));
+        }
+        if self.errors == ErrorMode::Collect {
+            self.collected = self.symbols.declare("errors", self.indent);
+            let line = format!("{} {} = [];", self.keyword("const"), self.collected);
+            self.line(&line);
+        }
+        let hoisted = (self.body.len(), self.owners.len());
+        let ty = self.output_type.clone().unwrap_or_default();
+        match &self.output_type {
+            // it's up to the path to write it
+            Some(ty) => self.line(&format!("let {}!: {};", output, ty)),
+            None => self.line(&format!("{} {};", self.keyword("let"), output)),
+        }
+
+        let frame = Frame {
+            input: input.to_string(),
+            output: output.to_string(),
+            ty,
+            schema: None,
+            target: self.passthrough.clone().map(Arc::new),
+            nested: false,
+            absent: true,
+            item: false,
+            checks: 0,
+        };
+        let frame = frame.with_schema(self.safe.clone().map(Arc::new));
+        let places = match self.provenance.as_ref().or(self.bigint.as_ref()) {
+            Some((source, _)) => places(ir, source),
+            None => vec![],
+        };
+        self.generate_ops(ir, 0, frame, &places, (input, output));
+        // where every call can see them
+        self.body.insert_str(hoisted.0, &self.definitions);
+        let defined = std::mem::take(&mut self.defined);
+        self.owners.splice(hoisted.1..hoisted.1, defined);
+        self.op = None;
+
+        let result = match self.freeze {
+            true => format!("{}({})", self.generate_freeze(), output),
+            false => output.to_string(),
+        };
+        match self.errors {
+            ErrorMode::Collect => {
+                // shorthand properties are ES2015
+                let errors = match (self.collected.as_str(), self.dialect) {
+                    ("errors", Dialect::Modern) => "errors".to_string(),
+                    (collected, _) => format!("errors: {}", collected),
+                };
+                self.line(&format!("return {{ result: {}, {} }};", result, errors));
+            }
+            _ => self.line(&format!("return {};", result)),
+        }
+        if self.options.returns == ReturnStyle::Literal && self.output_type.is_none() {
+            self.return_literal(output);
+        }
+        self.body.clone()
+    }
+
+    /// Statements writing what `ir` makes of `frame`'s input to its output,
+    /// where `ir` starts `offset` instructions into the whole path, which
+    /// works at `places` and transforms `input` into `output`.
+    fn generate_ops(
+        &mut self,
+        ir: &[IR],
+        offset: usize,
+        frame: Frame,
+        places: &[(String, String)],
+        (input, output): (&str, &str),
+    ) {
+        let mut frames = vec![frame];
+        // whether each open union dispatch has had a case yet
+        let mut unions = vec![];
+        // how to finish each open `map` callback: where it started in the
+        // body, what it starts with, the item it returns, and what follows
+        let mut maps = vec![];
+        // instructions left to skip, written by a helper already
+        let mut skip = 0;
+        for (i, op) in ir.iter().enumerate() {
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            self.op = Some(offset + i);
+            let top = frames.last_mut().expect("unbalanced IR");
+            // properties which may be missing are only read once they're there
+            let required = !matches!(ir.get(i + 1), Some(IR::PushOpt));
+            let place = places.get(offset + i);
+            if let Some(comment) = self.provenance_comment(op, place, input, output) {
+                self.line(&comment);
+            }
+            if let Some(end) = self.shared_block(&ir[i..]) {
+                let block = &ir[i..=i + end];
+                let helper = self.helper(block, offset + i, top, places, (input, output));
+                self.op = Some(offset + i);
+                let line = format!("{} = {};", top.output, helper);
+                self.line(&line);
+                skip = end;
+                continue;
+            }
+            if let IR::Rec(label) = op {
+                let end = scope_end(&ir[i..]).unwrap_or(ir.len() - i);
+                let body = &ir[i + 1..(i + end).min(ir.len())];
+                let line =
+                    self.recursive(label, body, offset + i + 1, top, places, (input, output));
+                self.op = Some(offset + i);
+                self.line(&line);
+                skip = end;
+                continue;
+            }
+            if let IR::PushKey(key) | IR::Rename(_, key) | IR::Nest(key) = op {
+                if key.as_str() == "__proto__" {
+                    let line = own(&top.output, "\"__proto__\"");
+                    self.line(&line);
+                }
+            }
+            match op {
+                IR::Copy => {
+                    let line = if self.safe.is_some() && top.absent && top.item {
+                        format!("{} = {};", top.output, self.or(&top.input, "null"))
+                    } else {
+                        format!("{} = {};", top.output, top.input)
+                    };
+                    self.line(&line);
+                }
+                IR::G2G(from, to) => {
+                    let (_, wide) = self.wide(place);
+                    let value = match (from, to) {
+                        (Ground::Null, Ground::Num) if wide => self.bigint_literal(0.0),
+                        (_, Ground::Num) if wide => format!("BigInt({})", top.input),
+                        _ => self.generate_ground_to_ground(from, to, &top.input),
+                    };
+                    let line = format!("{} = {};", top.output, value);
+                    self.line(&line);
+                    // where parsing a `BigInt` throws rather than giving NaN
+                    if *to == Ground::Num && *from != Ground::Num && !wide {
+                        let test = match self.dialect {
+                            Dialect::Modern => format!("Number.isNaN({})", top.output),
+                            Dialect::Es5 => format!("{} !== {}", top.output, top.output),
+                        };
+                        let fallback = format!("{} = 0;", top.output);
+                        let failure = self.failure(&test, &top.input, "not a number", &fallback);
+                        if let Some(line) = failure {
+                            self.line(&line);
+                        }
+                    }
+                }
+                IR::PushObj if open(&top.target) && !top.nested => {
+                    let object = match self.safe.is_some() && top.absent {
+                        true => self.or(&top.input, "{}"),
+                        false => top.input.clone(),
+                    };
+                    let lines = match (self.dialect, &self.output_type) {
+                        (Dialect::Modern, Some(_)) => {
+                            vec![format!(
+                                "{} = {{ ...{} }} as {};",
+                                top.output, object, top.ty
+                            )]
+                        }
+                        (Dialect::Modern, None) => {
+                            vec![format!("{} = {{ ...{} }};", top.output, object)]
+                        }
+                        (Dialect::Es5, _) => vec![
+                            format!("{} = {{}};", top.output),
+                            format!(
+                                "Object.keys({}).forEach(function (key) {{ {} {}[key] = {}[key]; }});",
+                                object,
+                                own(&top.output, "key"),
+                                top.output,
+                                object
+                            ),
+                        ],
+                    };
+                    for line in lines {
+                        self.line(&line);
+                    }
+                    // what's renamed isn't passed through under its old name
+                    let end = scope_end(&ir[i..]).map_or(ir.len(), |end| i + end);
+                    let mut depth = 0;
+                    for op in &ir[i + 1..end] {
+                        match op {
+                            IR::Rename(from, to) if depth == 0 && from != to => {
+                                self.line(&format!("delete {}{};", top.output, access(from)));
+                            }
+                            _ => {}
+                        }
+                        if op.closer().is_some() {
+                            depth += 1;
+                        } else if op.is_closer() {
+                            depth -= 1;
+                        }
+                    }
+                }
+                IR::PushObj => {
+                    // the properties are checked as they're written
+                    let line = match self.output_type {
+                        Some(_) => format!("{} = {{}} as {};", top.output, top.ty),
+                        None => format!("{} = {{}};", top.output),
+                    };
+                    self.line(&line);
+                }
+                IR::Merge => {
+                    // copy rather than add to the existing object, which may
+                    // be a copied part of the input
+                    let line = match self.dialect {
+                        Dialect::Modern => format!("{} = {{ ...{} }};", top.output, top.output),
+                        Dialect::Es5 => format!(
+                            "{} = JSON.parse(JSON.stringify({} || {{}}));",
+                            top.output, top.output
+                        ),
+                    };
+                    self.line(&line);
+                }
+                IR::PopObj => {}
+                IR::PushKey(key) => {
+                    let mut frame = Frame {
+                        output: format!("{}{}", top.output, access(key)),
+                        ty: member(&top.ty, &serde_json::Value::from(key.as_str()).to_string()),
+                        target: member_schema(&top.target, key),
+                        nested: false,
+                        ..self.property(top, key)
+                    };
+                    if required {
+                        self.require(&mut frame);
+                    }
+                    frames.push(frame);
+                }
+                IR::Rename(from, to) => {
+                    let mut frame = Frame {
+                        output: format!("{}{}", top.output, access(to)),
+                        ty: member(&top.ty, &serde_json::Value::from(to.as_str()).to_string()),
+                        target: member_schema(&top.target, to),
+                        nested: false,
+                        ..self.property(top, from)
+                    };
+                    if required {
+                        self.require(&mut frame);
+                    }
+                    frames.push(frame);
+                }
+                IR::Nest(key) => {
+                    let frame = Frame {
+                        output: format!("{}{}", top.output, access(key)),
+                        ty: member(&top.ty, &serde_json::Value::from(key.as_str()).to_string()),
+                        target: member_schema(&top.target, key),
+                        nested: true,
+                        item: false,
+                        checks: 0,
+                        ..top.clone()
+                    };
+                    frames.push(frame);
+                }
+                IR::PopKey => {
+                    let frame = frames.pop().expect("unbalanced IR");
+                    self.close(&frame);
+                }
+                IR::PushOpt => {
+                    let line = format!("if ({} !== undefined) {{", top.input);
+                    // only `null` now, if anything
+                    let frame = top.with_schema(top.schema.clone());
+                    self.line(&line);
+                    self.indent += 1;
+                    frames.push(frame);
+                }
+                IR::PopOpt => {
+                    let frame = frames.pop().expect("unbalanced IR");
+                    self.close(&frame);
+                    self.indent -= 1;
+                    self.line("}");
+                }
+                IR::PushUnion => unions.push(false),
+                IR::Case(branch) => {
+                    let test = Self::generate_test(branch, &top.input);
+                    let frame = top.with_schema(Some(Arc::new(branch.stripped())));
+                    let started = unions.last_mut().expect("case outside union");
+                    if *started {
+                        self.line(&format!("}} else if ({}) {{", test));
+                    } else {
+                        self.line(&format!("if ({}) {{", test));
+                        *started = true;
+                    }
+                    self.indent += 1;
+                    frames.push(frame);
+                }
+                IR::PopCase => {
+                    let frame = frames.pop().expect("unbalanced IR");
+                    self.close(&frame);
+                    self.indent -= 1;
+                }
+                IR::PopUnion => {
+                    if unions.pop() == Some(true) {
+                        self.line("}");
+                    }
+                }
+                IR::Call(label) => {
+                    let call = match self.recs.iter().rev().find(|(open, _)| open == label) {
+                        Some((_, name)) => self.call(name, top),
+                        None => "undefined".to_string(),
+                    };
+                    let line = format!("{} = {};", top.output, call);
+                    self.line(&line);
+                }
+                // written by a helper of their own
+                IR::Rec(_) | IR::PopRec => {}
+                IR::PushArr if self.arrays == ArrayStyle::Map => {
+                    let ty = member(&top.ty, "number");
+                    let item = self.fresh("item");
+                    let mut params = item.clone();
+                    if self.errors != ErrorMode::Ignore {
+                        // so that failures can say which item failed
+                        let idx = self.fresh("idx");
+                        let path = format!("{}[${{{}}}]", self.whence(&top.input), idx);
+                        self.aliases.push((item.clone(), path));
+                        params = format!("{}, {}", item, idx);
+                    }
+                    let mapped = self.fresh("mapped");
+                    // a missing array is mapped to an empty one, like a loop
+                    // over it writes
+                    let missing = self.safe.is_some() && top.absent;
+                    let (head, tail) = match self.dialect {
+                        Dialect::Modern => (
+                            format!(
+                                "{} = {}map(({}) => ",
+                                top.output,
+                                self.read(top, "."),
+                                params
+                            ),
+                            if missing { " ?? []" } else { "" },
+                        ),
+                        Dialect::Es5 => {
+                            let array = match missing {
+                                true => format!("({} || [])", top.input),
+                                false => top.input.clone(),
+                            };
+                            let head =
+                                format!("{} = {}.map(function ({}) ", top.output, array, params);
+                            (head, "")
+                        }
+                    };
+                    let frame = Frame {
+                        input: item,
+                        output: mapped.clone(),
+                        ty: ty.clone(),
+                        target: item_schema(&top.target),
+                        nested: false,
+                        item: true,
+                        ..top.with_schema(Self::items(top))
+                    };
+                    maps.push((self.body.len(), head.clone(), mapped.clone(), tail));
+                    self.line(&format!("{}{{", head));
+                    self.indent += 1;
+                    match self.output_type {
+                        Some(_) => self.line(&format!("let {}!: {};", mapped, ty)),
+                        None => self.line(&format!("{} {};", self.keyword("let"), mapped)),
+                    }
+                    frames.push(frame);
+                }
+                IR::PopArr if self.arrays == ArrayStyle::Map => {
+                    let frame = frames.pop().expect("unbalanced IR");
+                    self.close(&frame);
+                    let (start, head, mapped, tail) = maps.pop().expect("unbalanced IR");
+                    if self.errors != ErrorMode::Ignore {
+                        self.aliases.pop();
+                    }
+                    // an item written in one statement is returned as is
+                    let indent = "  ".repeat(self.indent);
+                    let keyword = self.keyword("let");
+                    let declared = format!("{indent}{keyword} {mapped};\n{indent}{mapped} = ");
+                    let opened = self.body[start..].split_once('\n').map_or("", |(_, b)| b);
+                    let single = opened
+                        .strip_prefix(&declared)
+                        .and_then(|value| value.strip_suffix(";\n"))
+                        .filter(|value| !value.contains('\n'))
+                        .map(str::to_string);
+                    self.indent -= 1;
+                    match single {
+                        Some(value) => {
+                            self.body.truncate(start);
+                            self.owners.truncate(self.body.lines().count());
+                            let line = match self.dialect {
+                                Dialect::Modern => format!("{}{}){};", head, value, tail),
+                                Dialect::Es5 => format!("{}{{ return {}; }});", head, value),
+                            };
+                            self.line(&line);
+                        }
+                        None => {
+                            self.indent += 1;
+                            self.line(&format!("return {};", mapped));
+                            self.indent -= 1;
+                            self.line(&format!("}}){};", tail));
+                        }
+                    }
+                }
+                IR::PushArr => {
+                    let (input, output) = (top.input.clone(), top.output.clone());
+                    let ty = member(&top.ty, "number");
+                    let idx = self.fresh("idx");
+                    let length = self.read(top, ".length");
+                    let item = top.with_schema(Self::items(top));
+                    let target = item_schema(&top.target);
+                    self.line(&format!("{} = [];", output));
+                    self.line(&format!(
+                        "for ({} {idx} = 0; {idx} < {}; {idx}++) {{",
+                        self.keyword("let"),
+                        length
+                    ));
+                    self.indent += 1;
+                    frames.push(Frame {
+                        input: format!("{}[{}]", input, idx),
+                        output: format!("{}[{}]", output, idx),
+                        ty,
+                        target,
+                        nested: false,
+                        item: true,
+                        ..item
+                    });
+                }
+                IR::PopArr | IR::PopRest => {
+                    let frame = frames.pop().expect("unbalanced IR");
+                    self.close(&frame);
+                    self.indent -= 1;
+                    self.line("}");
+                }
+                IR::PushRest(named) => {
+                    let (input, output) = (top.input.clone(), top.output.clone());
+                    let ty = member(&top.ty, "string");
+                    let target = match top.target.as_deref() {
+                        Some(Schema::Obj { additional, .. }) => additional.clone(),
+                        _ => None,
+                    };
+                    let key = self.fresh("key");
+                    let object = if self.safe.is_some() && top.absent {
+                        self.or(&input, "{}")
+                    } else {
+                        input.clone()
+                    };
+                    let value = match top.schema.as_deref() {
+                        Some(Schema::Obj { additional, .. }) => top.with_schema(additional.clone()),
+                        _ => top.with_schema(None),
+                    };
+                    match self.dialect {
+                        Dialect::Modern => {
+                            self.line(&format!(
+                                "for (const {} of Object.keys({})) {{",
+                                key, object
+                            ));
+                            self.indent += 1;
+                        }
+                        Dialect::Es5 => {
+                            let (keys, idx) = (self.fresh("keys"), self.fresh("idx"));
+                            self.line(&format!("var {} = Object.keys({});", keys, object));
+                            self.line(&format!(
+                                "for (var {idx} = 0; {idx} < {keys}.length; {idx}++) {{"
+                            ));
+                            self.indent += 1;
+                            self.line(&format!("var {} = {}[{}];", key, keys, idx));
+                        }
+                    }
+                    if !named.is_empty() {
+                        let named: Vec<_> = named.iter().map(|k| k.as_str()).collect();
+                        let named = serde_json::Value::from(named);
+                        let test = match self.dialect {
+                            Dialect::Modern => format!("{}.includes({})", named, key),
+                            Dialect::Es5 => format!("{}.indexOf({}) !== -1", named, key),
+                        };
+                        self.line(&format!("if ({}) continue;", test));
+                    }
+                    self.line(&format!(
+                        "if ({} === \"__proto__\") {}",
+                        key,
+                        own(&output, &key)
+                    ));
+                    frames.push(Frame {
+                        input: format!("{}[{}]", input, key),
+                        output: format!("{}[{}]", output, key),
+                        ty,
+                        target,
+                        nested: false,
+                        item: false,
+                        ..value
+                    });
+                }
+                IR::Extr(key) => {
+                    // still in the branches checking the levels above
+                    let mut frame = Frame {
+                        output: top.output.clone(),
+                        ty: top.ty.clone(),
+                        item: top.item,
+                        checks: top.checks,
+                        ..self.property(top, key)
+                    };
+                    if required {
+                        self.require(&mut frame);
+                    }
+                    *top = frame;
+                }
+                IR::Coalesce(paths) => {
+                    let values: Vec<_> = paths
+                        .iter()
+                        .map(|path| {
+                            path.iter()
+                                .fold(top.clone(), |frame, k| self.property(&frame, k))
+                                .input
+                        })
+                        .collect();
+                    let coalesced = self.fresh("coalesced");
+                    let (last, values) = values.split_last().expect("nothing to coalesce");
+                    let value = values
+                        .iter()
+                        .rev()
+                        .fold(last.clone(), |value, first| self.or(first, &value));
+                    let line = format!("{} {} = {};", self.keyword("const"), coalesced, value);
+                    top.input = coalesced;
+                    *top = top.with_schema(None);
+                    self.line(&line);
+                }
+                IR::Wrap => {
+                    let line = format!("{} = [];", top.output);
+                    top.output = format!("{}[0]", top.output);
+                    top.ty = member(&top.ty, "number");
+                    top.target = item_schema(&top.target);
+                    top.item = true;
+                    self.line(&line);
+                }
+                IR::First => {
+                    let input = self.read(top, "[0]");
+                    // which may be missing from an empty array
+                    *top = Frame {
+                        input,
+                        absent: true,
+                        ..top.with_schema(Self::items(top))
+                    };
+                }
+                IR::Flatten(depth) => {
+                    let flat = self.fresh("flat");
+                    let line = match self.dialect {
+                        Dialect::Modern => {
+                            format!(
+                                "const {} = {};",
+                                flat,
+                                self.read(top, &format!(".flat({})", depth))
+                            )
+                        }
+                        // concatenating arrays' items flattens them a level
+                        Dialect::Es5 => {
+                            let flattened = (0..*depth).fold(top.input.clone(), |array, _| {
+                                format!("[].concat.apply([], {})", array)
+                            });
+                            format!("var {} = {}{};", flat, self.guard(top), flattened)
+                        }
+                    };
+                    top.input = flat;
+                    top.schema = None;
+                    self.line(&line);
+                }
+                IR::Slice(start, end) => {
+                    let sliced = self.fresh("sliced");
+                    let bounds = match end {
+                        Some(end) => format!("{}, {}", start, end),
+                        None => start.to_string(),
+                    };
+                    let line = format!(
+                        "{} {} = {};",
+                        self.keyword("const"),
+                        sliced,
+                        self.read(top, &format!(".slice({})", bounds))
+                    );
+                    top.input = sliced;
+                    top.schema = None;
+                    self.line(&line);
+                }
+                IR::Sort(path, order) => {
+                    let sorted = self.fresh("sorted");
+                    let value =
+                        |item: &str| path.iter().fold(item.to_string(), |s, k| s + &access(k));
+                    let (a, b) = match order {
+                        SortOrder::Ascending => (value("a"), value("b")),
+                        SortOrder::Descending => (value("b"), value("a")),
+                    };
+                    // copied first, since sorting is in place (and stable)
+                    let compare =
+                        self.lambda("a, b", &format!("({a} < {b} ? -1 : {a} > {b} ? 1 : 0)"));
+                    let copy = match self.dialect {
+                        Dialect::Modern => format!("[...{}]", top.input),
+                        Dialect::Es5 => format!("{}.slice()", top.input),
+                    };
+                    let line = format!(
+                        "{} {} = {}{}.sort({});",
+                        self.keyword("const"),
+                        sorted,
+                        self.guard(top),
+                        copy,
+                        compare
+                    );
+                    top.input = sorted;
+                    top.schema = None;
+                    self.line(&line);
+                }
+                IR::Dedupe(path) => {
+                    let seen = self.fresh("seen");
+                    let unique = self.fresh("unique");
+                    let key = match path {
+                        Some(path) => path.iter().fold("item".to_string(), |s, k| s + &access(k)),
+                        None => "JSON.stringify(item)".to_string(),
+                    };
+                    let (set, test) = match self.dialect {
+                        Dialect::Modern => (
+                            "new Set()",
+                            format!("!{seen}.has({key}) && {seen}.add({key})"),
+                        ),
+                        Dialect::Es5 => (
+                            "[]",
+                            format!("{seen}.indexOf({key}) === -1 && {seen}.push({key})"),
+                        ),
+                    };
+                    let filter = format!(".filter({})", self.lambda("item", &test));
+                    let line = format!(
+                        "{} {} = {};",
+                        self.keyword("const"),
+                        unique,
+                        self.read(top, &filter)
+                    );
+                    self.line(&format!("{} {} = {};", self.keyword("const"), seen, set));
+                    self.line(&line);
+                    top.input = unique;
+                    top.schema = None;
+                }
+                IR::Gather(paths) => {
+                    let spreads: Vec<_> = paths
+                        .iter()
+                        .map(|path| {
+                            let array = path
+                                .iter()
+                                .fold(top.clone(), |frame, k| self.property(&frame, k))
+                                .input;
+                            match self.dialect {
+                                Dialect::Modern => format!("...({} ?? [])", array),
+                                Dialect::Es5 => self.or(&array, "[]"),
+                            }
+                        })
+                        .collect();
+                    let gathered = self.fresh("gathered");
+                    let line = match self.dialect {
+                        Dialect::Modern => {
+                            format!("const {} = [{}];", gathered, spreads.join(", "))
+                        }
+                        Dialect::Es5 => {
+                            format!("var {} = [].concat({});", gathered, spreads.join(", "))
+                        }
+                    };
+                    top.input = gathered;
+                    *top = top.with_schema(None);
+                    top.absent = false;
+                    self.line(&line);
+                }
+                IR::Zip(pairs) => {
+                    let zipped = self.fresh("zipped");
+                    let arrays: Vec<_> = pairs
+                        .iter()
+                        .map(|(from, _)| self.property(top, from).input)
+                        .collect();
+                    let lengths: Vec<_> = arrays
+                        .iter()
+                        .map(|array| match self.dialect {
+                            Dialect::Modern => format!("({}).length", self.or(array, "[]")),
+                            Dialect::Es5 => format!("{}.length", self.or(array, "[]")),
+                        })
+                        .collect();
+                    // fresh, so the callback's parameters don't shadow the
+                    // arrays it reads
+                    let blank = match self.dialect {
+                        Dialect::Modern => self.fresh("_"),
+                        Dialect::Es5 => String::new(),
+                    };
+                    let idx = self.fresh("idx");
+                    let fields: Vec<_> = pairs
+                        .iter()
+                        .zip(&arrays)
+                        .map(|((_, to), array)| {
+                            format!(
+                                "{}: {}",
+                                serde_json::Value::from(to.as_str()),
+                                self.optional(array, &format!("[{}]", idx))
+                            )
+                        })
+                        .collect();
+                    match self.dialect {
+                        Dialect::Modern => self.line(&format!(
+                            "const {} = Array.from({{ length: Math.max({}) }}, ({}, {}) => ({{ {} }}));",
+                            zipped,
+                            lengths.join(", "),
+                            blank,
+                            idx,
+                            fields.join(", ")
+                        )),
+                        Dialect::Es5 => {
+                            self.line(&format!("var {} = [];", zipped));
+                            self.line(&format!(
+                                "for (var {idx} = 0; {idx} < Math.max({}); {idx}++) {{",
+                                lengths.join(", ")
+                            ));
+                            self.line(&format!("  {}.push({{ {} }});", zipped, fields.join(", ")));
+                            self.line("}");
+                        }
+                    }
+                    let top = frames.last_mut().expect("unbalanced IR");
+                    top.input = zipped;
+                    *top = top.with_schema(None);
+                    top.absent = false;
+                }
+                IR::Unzip(pairs) => {
+                    let unzipped = self.fresh("unzipped");
+                    let fields: Vec<_> = pairs
+                        .iter()
+                        .map(|(to, from)| {
+                            let item = self.lambda("item", &format!("item{}", access(from)));
+                            format!(
+                                "{}: {}",
+                                serde_json::Value::from(to.as_str()),
+                                self.read(top, &format!(".map({})", item))
+                            )
+                        })
+                        .collect();
+                    let line = format!(
+                        "{} {} = {{ {} }};",
+                        self.keyword("const"),
+                        unzipped,
+                        fields.join(", ")
+                    );
+                    top.input = unzipped;
+                    *top = top.with_schema(None);
+                    top.absent = false;
+                    self.line(&line);
+                }
+                IR::Inv => {
+                    let inv = self.fresh("inv");
+                    match self.dialect {
+                        Dialect::Modern => self.line(&format!(
+                            "const {inv} = {guard}Array.isArray({input}) \
+                             ? Object.fromEntries({input}.map((e) => [e.key, e.value])) \
+                             : Object.entries({input}).map(([key, value]) => ({{ key, value }}));",
+                            input = top.input,
+                            guard = self.guard(top)
+                        )),
+                        Dialect::Es5 => {
+                            let input = top.input.clone();
+                            let entries = format!(
+                                "Object.keys({input}).map(function (key) {{ return {{ key: key, value: {input}[key] }}; }})"
+                            );
+                            self.generate_es5_entries(
+                                top,
+                                &inv,
+                                ("item.key", "item.value"),
+                                &entries,
+                            );
+                        }
+                    }
+                    let top = frames.last_mut().expect("unbalanced IR");
+                    top.input = inv;
+                    top.schema = None;
+                }
+                IR::GroupBy(key) => {
+                    let grouped = self.fresh("grouped");
+                    let key = serde_json::Value::from(key.as_str());
+                    match self.dialect {
+                        Dialect::Modern => self.line(&format!(
+                            "const {grouped} = {guard}Array.isArray({input}) \
+                             ? Object.fromEntries({input}.map((item) => [item[{key}], item])) \
+                             : Object.entries({input}).map(([key, item]) => ({{ ...item, [{key}]: key }}));",
+                            input = top.input,
+                            guard = self.guard(top)
+                        )),
+                        Dialect::Es5 => {
+                            let input = top.input.clone();
+                            let entries = format!(
+                                "Object.keys({input}).map(function (key) {{ \
+                                 var item = JSON.parse(JSON.stringify({input}[key])); \
+                                 item[{key}] = key; return item; }})"
+                            );
+                            let by = format!("item[{}]", key);
+                            self.generate_es5_entries(top, &grouped, (&by, "item"), &entries);
+                        }
+                    }
+                    let top = frames.last_mut().expect("unbalanced IR");
+                    top.input = grouped;
+                    top.schema = None;
+                }
+                IR::Pipe => {
+                    let piped = self.fresh("piped");
+                    let line = format!("{} {} = {};", self.keyword("const"), piped, top.output);
+                    top.input = piped;
+                    *top = top.with_schema(None);
+                    self.line(&line);
+                }
+                IR::Scale { factor, offset } => {
+                    let (source, target) = self.wide(place);
+                    // scaled as `BigInt`s by whole factors, and as numbers
+                    // by any others
+                    let whole = factor.0.fract() == 0.0 && offset.0.fract() == 0.0;
+                    let big = (source || target) && whole;
+                    let mut value = match (big, source) {
+                        (true, _) => {
+                            format!("BigInt({}) * {}", top.input, self.bigint_literal(factor.0))
+                        }
+                        (false, true) => format!("Number({}) * {}", top.input, factor.0),
+                        (false, false) => format!("{} * {}", top.input, factor.0),
+                    };
+                    if offset.0 != 0.0 {
+                        let offset = match big {
+                            true => self.bigint_literal(offset.0),
+                            false => offset.0.to_string(),
+                        };
+                        value = format!("{} + {}", value, offset);
+                    }
+                    if big && !target {
+                        value = format!("Number({})", value);
+                    }
+                    let line = format!("{} = {};", top.output, value);
+                    self.line(&line);
+                }
+                IR::DateConvert {
+                    from_format,
+                    to_format,
+                } => {
+                    let date = match from_format {
+                        DateFormat::Iso | DateFormat::EpochMillis => {
+                            format!("new Date({})", top.input)
+                        }
+                        DateFormat::EpochSeconds => format!("new Date({} * 1000)", top.input),
+                    };
+                    let value = match to_format {
+                        DateFormat::Iso => format!("{}.toISOString()", date),
+                        DateFormat::EpochSeconds => {
+                            format!("Math.floor({}.getTime() / 1000)", date)
+                        }
+                        DateFormat::EpochMillis => format!("{}.getTime()", date),
+                    };
+                    let line = format!("{} = {};", top.output, value);
+                    self.line(&line);
+                }
+                IR::Base64(coding) => {
+                    // `btoa` and `atob` only take Latin-1, so strings are
+                    // encoded as UTF-8 bytes, as everywhere else
+                    let value = match (self.runtime, self.dialect, coding) {
+                        (Runtime::Browser, Dialect::Modern, Coding::Encode) => format!(
+                            "btoa(Array.from(new TextEncoder().encode({}), {}).join(\"\"))",
+                            top.input,
+                            self.lambda("b", "String.fromCharCode(b)")
+                        ),
+                        (Runtime::Browser, Dialect::Modern, Coding::Decode) => format!(
+                            "new TextDecoder().decode(Uint8Array.from(atob({}), {}))",
+                            top.input,
+                            self.lambda("c", "c.charCodeAt(0)")
+                        ),
+                        (Runtime::Browser, Dialect::Es5, Coding::Encode) => {
+                            format!("btoa(unescape(encodeURIComponent({})))", top.input)
+                        }
+                        (Runtime::Browser, Dialect::Es5, Coding::Decode) => {
+                            format!("decodeURIComponent(escape(atob({})))", top.input)
+                        }
+                        (Runtime::Node, _, Coding::Encode) => {
+                            format!("Buffer.from({}, \"utf8\").toString(\"base64\")", top.input)
+                        }
+                        (Runtime::Node, _, Coding::Decode) => {
+                            format!("Buffer.from({}, \"base64\").toString(\"utf8\")", top.input)
+                        }
+                    };
+                    let line = format!("{} = {};", top.output, value);
+                    self.line(&line);
+                }
+                IR::RegexExtract(pattern, group) => {
+                    let matched = format!(
+                        "String({}).match(new RegExp({}))",
+                        top.input,
+                        serde_json::Value::from(pattern.as_str())
+                    );
+                    let value = match self.dialect {
+                        Dialect::Modern => format!("{}?.[{}]", matched, group),
+                        Dialect::Es5 => format!("({} || [])[{}]", matched, group),
+                    };
+                    let line = format!("{} = {};", top.output, value);
+                    self.line(&line);
+                }
+                IR::CaseTransform(case) => {
+                    // camel and snake case split into words like
+                    // `Case::apply` does
+                    let words = format!(
+                        "String({}).replace(/([a-z0-9])([A-Z])/g, \"$1 $2\").split(/[-_ ]+/).filter(Boolean)",
+                        top.input
+                    );
+                    let value = match case {
+                        Case::Upper => format!("String({}).toUpperCase()", top.input),
+                        Case::Lower => format!("String({}).toLowerCase()", top.input),
+                        Case::Camel => format!(
+                            "{}.map({}).join(\"\")",
+                            words,
+                            self.lambda(
+                                "w, i",
+                                "i ? w[0].toUpperCase() + w.slice(1).toLowerCase() : w.toLowerCase()"
+                            )
+                        ),
+                        Case::Snake => format!(
+                            "{}.map({}).join(\"_\")",
+                            words,
+                            self.lambda("w", "w.toLowerCase()")
+                        ),
+                    };
+                    let line = format!("{} = {};", top.output, value);
+                    self.line(&line);
+                }
+                IR::MapValues(table) => {
+                    let line = match self.dialect {
+                        Dialect::Modern => {
+                            let entries: Vec<_> = table
+                                .iter()
+                                .map(|(from, to)| format!("[{}, {}]", from.0, to.0))
+                                .collect();
+                            format!(
+                                "{} = new Map([{}]).get({});",
+                                top.output,
+                                entries.join(", "),
+                                top.input
+                            )
+                        }
+                        // a switch compares like a `Map` looks keys up
+                        Dialect::Es5 => {
+                            let cases: Vec<_> = table
+                                .iter()
+                                .map(|(from, to)| format!("case {}: return {};", from.0, to.0))
+                                .collect();
+                            format!(
+                                "{} = (function (value) {{ switch (value) {{ {} }} }})({});",
+                                top.output,
+                                cases.join(" "),
+                                top.input
+                            )
+                        }
+                    };
+                    self.line(&line);
+                }
+                IR::Concat(paths, sep) => {
+                    let values: Vec<_> = paths
+                        .iter()
+                        .map(|path| {
+                            path.iter()
+                                .fold(top.clone(), |frame, k| self.property(&frame, k))
+                                .input
+                        })
+                        .collect();
+                    let line = format!(
+                        "{} = [{}].filter({}).map(String).join({});",
+                        top.output,
+                        values.join(", "),
+                        self.lambda("v", "v != null"),
+                        serde_json::Value::from(sep.as_str())
+                    );
+                    self.line(&line);
+                }
+                IR::Const(value) => {
+                    let line = format!("{} = {};", top.output, value.0);
+                    self.line(&line);
+                }
+                IR::Join(sep) => {
+                    let join = format!(
+                        ".map(String).join({})",
+                        serde_json::Value::from(sep.as_str())
+                    );
+                    let line = format!("{} = {};", top.output, self.read(top, &join));
+                    self.line(&line);
+                }
+            }
+        }
+        // the whole input may have been extracted from, too
+        for frame in frames.iter().rev() {
+            self.close(frame);
+        }
+    }
+
+    /// How many instructions after the first there are in the block `ir`
+    /// starts with, if a helper shares it.
+    fn shared_block(&self, ir: &[IR]) -> Option<usize> {
+        let end = scope_end(ir)?;
+        self.repeated.contains(&ir[..=end]).then_some(end)
+    }
+
+    /// A call of the helper writing `block`, which starts `offset`
+    /// instructions into the path, with `site`'s input. The helper is
+    /// written the first time it's called with input like it.
+    fn helper(
+        &mut self,
+        block: &[IR],
+        offset: usize,
+        site: &Frame,
+        places: &[(String, String)],
+        names: (&str, &str),
+    ) -> String {
+        // what the block's written differently for in safe mode, and when
+        // passing properties through
+        let safe = self.safe.is_some();
+        let passthrough = self.passthrough.is_some();
+        let shared = (
+            block.to_vec(),
+            site.schema.clone().filter(|_| safe),
+            site.target.clone().filter(|_| passthrough),
+            site.absent && safe,
+            site.item && safe,
+            site.nested && passthrough,
+        );
+        let name = match self.shared.get(&shared) {
+            Some(name) => name.clone(),
+            None => {
+                let name = self.symbols.hoist("convert", 1);
+                self.shared.insert(shared, name.clone());
+                self.generate_helper(&name, block, offset, site, places, names);
+                name
+            }
+        };
+        self.call(&name, site)
+    }
+
+    /// A call of helper `name` with `site`'s input.
+    fn call(&self, name: &str, site: &Frame) -> String {
+        match self.errors {
+            ErrorMode::Ignore => format!("{}({})", name, site.input),
+            // so failures in it can say where they are
+            _ => format!(
+                "{}({}, {})",
+                name,
+                site.input,
+                self.text(&self.whence(&site.input))
+            ),
+        }
+    }
+
+    /// A statement writing what the recursive conversion `body` named
+    /// `label`, which starts `offset` instructions into the path, makes of
+    /// `site`'s input. It's written as a helper named after the label, like
+    /// `convertNode`, which the [`IR::Call`]s of the label in it call again.
+    fn recursive(
+        &mut self,
+        label: &Arc<String>,
+        body: &[IR],
+        offset: usize,
+        site: &Frame,
+        places: &[(String, String)],
+        names: (&str, &str),
+    ) -> String {
+        let name = self.symbols.hoist_as(&converter(label), 1);
+        // called on parts of the input which may be anywhere
+        let frame = Frame {
+            schema: site
+                .schema
+                .as_deref()
+                .map(|schema| Arc::new(schema.unfold())),
+            absent: true,
+            item: false,
+            ..site.clone()
+        };
+        self.recs.push((label.clone(), name.clone()));
+        self.generate_helper(&name, body, offset, &frame, places, names);
+        self.recs.pop();
+        format!("{} = {};", site.output, self.call(&name, site))
+    }
+
+    /// Define helper `name`, writing `block` with input like `site`'s, to
+    /// be hoisted to the top of the body.
+    fn generate_helper(
+        &mut self,
+        name: &str,
+        block: &[IR],
+        offset: usize,
+        site: &Frame,
+        places: &[(String, String)],
+        names: (&str, &str),
+    ) {
+        let body = std::mem::take(&mut self.body);
+        let owners = std::mem::take(&mut self.owners);
+        let aliases = std::mem::take(&mut self.aliases);
+        let indent = std::mem::replace(&mut self.indent, 1);
+        let scopes = self.symbols.scopes.clone();
+        self.symbols.scopes.truncate(2);
+
+        let value = self.symbols.declare("value", 2);
+        let result = self.symbols.declare("result", 2);
+        let params = match self.errors {
+            ErrorMode::Ignore => value.clone(),
+            _ => {
+                let path = self.symbols.declare("path", 2);
+                self.aliases.push((value.clone(), format!("${{{}}}", path)));
+                format!("{}, {}", value, path)
+            }
+        };
+        self.line(&format!("function {}({}) {{", name, params));
+        self.indent = 2;
+        let line = format!("{} {};", self.keyword("let"), result);
+        self.line(&line);
+        let frame = Frame {
+            input: value,
+            output: result.clone(),
+            checks: 0,
+            ..site.clone()
+        };
+        // the block itself, rather than a call of the helper
+        let shared = self.repeated.remove(block);
+        self.generate_ops(block, offset, frame, places, names);
+        if shared {
+            self.repeated.insert(block.to_vec());
+        }
+        self.line(&format!("return {};", result));
+        self.indent = 1;
+        self.line("}");
+
+        self.definitions
+            .push_str(&std::mem::replace(&mut self.body, body));
+        self.defined
+            .extend(std::mem::replace(&mut self.owners, owners));
+        self.aliases = aliases;
+        self.indent = indent;
+        self.symbols.scopes = scopes;
+    }
+
+    /// The statements of a generator function yielding the output of `ir`
+    /// for `input`: each item in turn, if `ir` converts an array item by
+    /// item, or else the whole output.
+    fn generate_stream(&mut self, ir: &[IR], input: &str, output: &str) -> String {
+        let whole = match ir {
+            [IR::PushArr, items @ .., IR::PopArr] if scope_end(ir) == Some(ir.len() - 1) => {
+                Some(items)
+            }
+            _ => None,
+        };
+        let Some(items) = whole else {
+            let body = self.generate_body(ir, input, output);
+            return yielding(&body);
+        };
+
+        // each item is converted like a whole input of the item schemas
+        let item = |schema: &Option<Schema>| match schema.as_ref().map(Schema::bare) {
+            Some(Schema::Arr(item)) => Some(item.as_ref().clone()),
+            _ => None,
+        };
+        let (validate, safe, passthrough, provenance, bigint) = (
+            self.validate.clone(),
+            self.safe.clone(),
+            self.passthrough.clone(),
+            self.provenance.clone(),
+            self.bigint.clone(),
+        );
+        self.validate = item(&validate);
+        self.safe = item(&safe);
+        self.passthrough = item(&passthrough);
+        self.provenance = provenance
+            .clone()
+            .and_then(|(source, target)| Some((item(&Some(source))?, item(&Some(target))?)));
+        self.bigint = bigint
+            .clone()
+            .and_then(|(source, target)| Some((item(&Some(source))?, item(&Some(target))?)));
+        let name = match format!("{}Item", input) {
+            name if name == output => format!("{}0", name),
+            name => name,
+        };
+        let body = self.generate_body(items, &name, output);
+        (
+            self.validate,
+            self.safe,
+            self.passthrough,
+            self.provenance,
+            self.bigint,
+        ) = (validate, safe, passthrough, provenance, bigint);
+
+        let loop_ = match self.streaming {
+            Streaming::AsyncGenerator => "for await",
+            _ => "for",
+        };
+        let mut out = format!(
+            "  {} ({} {} of {}) {{\n",
+            loop_,
+            self.keyword("const"),
+            name,
+            input
+        );
+        for line in yielding(&body).lines() {
+            out.push_str(&format!("  {}\n", line));
+        }
+        out.push_str("  }\n");
+        // the items' instructions come after the array's
+        for owner in self.owners.iter_mut().flatten() {
+            *owner += 1;
+        }
+        self.owners.insert(0, None);
+        self.owners.push(None);
+        out
+    }
+
+    /// JSDoc typedefs of the source and target types, and the function's
+    /// own documentation, taking them as `input`, if it's documented.
+    fn generate_jsdoc(&self, input: &str) -> String {
+        let Some((source, target)) = &self.jsdoc else {
+            return String::new();
+        };
+        let mut out = String::new();
+        for (name, schema) in [(SOURCE, source), (TARGET, target)] {
+            let ty = type_of(schema, 0).replace('\n', "\n * ");
+            out.push_str(&format!("/**\n * @typedef {{{}}} {}\n */\n", ty, name));
+        }
+        out.push_str(&format!(
+            "/**\n * @param {{{}}} {}\n * @returns {{{}}}\n */\n",
+            SOURCE,
+            input,
+            self.returns(TARGET)
+        ));
+        out
+    }
+
+    /// The type of what the function returns, given the type of its output.
+    fn returns(&self, target: &str) -> String {
+        match self.errors {
+            ErrorMode::Collect => format!(
+                "{{ result: {}, errors: {{ path: string, message: string }}[] }}",
+                target
+            ),
+            _ => target.to_string(),
+        }
+    }
+
+    /// A TypeScript declaration file for the function this generates with
+    /// the module format and options it has, declaring the types of the
+    /// `source` data it takes and the `target` data it returns, for
+    /// TypeScript code using it.
+    pub fn declarations(&self, source: &Schema, target: &Schema) -> String {
+        let signature = |source: &str, target: &str| {
+            format!(
+                "declare function {}({}: {}): {};\n",
+                self.options.name,
+                self.options.input,
+                source,
+                self.returns(target)
+            )
+        };
+        let types = [declaration(SOURCE, source), declaration(TARGET, target)];
+        let exported: Vec<_> = types.iter().map(|ty| format!("export {}", ty)).collect();
+        let declarations = match self.module {
+            ModuleFormat::Expression => {
+                format!("{}\n{}", types.join("\n"), signature(SOURCE, TARGET))
+            }
+            ModuleFormat::ExportDefault => format!(
+                "{}\n{}export default {};\n",
+                exported.join("\n"),
+                signature(SOURCE, TARGET),
+                self.options.name
+            ),
+            ModuleFormat::ExportNamed => format!(
+                "{}\nexport {}",
+                exported.join("\n"),
+                signature(SOURCE, TARGET)
+            ),
+            // the types go in a namespace merged with the function, since
+            // nothing else can be exported alongside it
+            ModuleFormat::CommonJs => {
+                let name = &self.options.name;
+                let types: Vec<_> = types
+                    .join("\n")
+                    .lines()
+                    .map(|line| match line {
+                        "" => String::new(),
+                        line => format!("  {}", line),
+                    })
+                    .collect();
+                format!(
+                    "{}declare namespace {} {{\n{}\n}}\nexport = {};\n",
+                    signature(
+                        &format!("{}.{}", name, SOURCE),
+                        &format!("{}.{}", name, TARGET)
+                    ),
+                    name,
+                    types.join("\n"),
+                    name
+                )
+            }
+        };
+        self.formatting.apply(&declarations)
+    }
+
+    /// A manifest of the code last generated for `ir`, which transforms
+    /// `source` data, for tools linking the code to the schemas: each run of
+    /// lines an instruction wrote (counting from 1), with the instruction as
+    /// a program writes it and JSON Pointers into the source and target
+    /// schemas to where it reads and writes. Lines written for no
+    /// instruction in particular, like validation, aren't in it.
+    pub fn manifest(&self, ir: &[IR], source: &Schema) -> serde_json::Value {
+        let places = places(ir, source);
+        let mut runs: Vec<(usize, usize, usize)> = vec![];
+        for (line, owner) in self.lines.iter().enumerate() {
+            let Some(op) = *owner else { continue };
+            match runs.last_mut() {
+                Some((run, _, last)) if *run == op && *last == line => *last = line + 1,
+                _ => runs.push((op, line + 1, line + 1)),
+            }
+        }
+        let statements: Vec<_> = runs
+            .into_iter()
+            .map(|(op, first, last)| {
+                let (from, to) = places.get(op).cloned().unwrap_or_default();
+                serde_json::json!({
+                    "lines": [first, last],
+                    "index": op,
+                    "op": serde_json::Value::from(&ir[op]),
+                    "source": pointer(&from),
+                    "target": pointer(&to),
+                })
+            })
+            .collect();
+        serde_json::json!({ "statements": statements })
+    }
+
+    /// Whether the input and the output at `place` are 64-bit integers,
+    /// converted as `BigInt`s.
+    fn wide(&self, place: Option<&(String, String)>) -> (bool, bool) {
+        match (&self.bigint, place) {
+            (Some((source, target)), Some((from, to))) if self.output_type.is_none() => {
+                (int64(source, from), int64(target, to))
+            }
+            _ => (false, false),
+        }
+    }
+
+    /// `n`, a whole number, as a `BigInt`.
+    fn bigint_literal(&self, n: f64) -> String {
+        match self.dialect {
+            Dialect::Modern => format!("{}n", n),
+            Dialect::Es5 => format!("BigInt({})", n),
+        }
+    }
+
+    /// The provenance comment for `op`, working at `place`, if it writes a
+    /// value.
+    fn provenance_comment(
+        &self,
+        op: &IR,
+        place: Option<&(String, String)>,
+        input: &str,
+        output: &str,
+    ) -> Option<String> {
+        let (source, target) = self.provenance.as_ref()?;
+        let (from, to) = place?;
+        let from = match op {
+            IR::Const(_) => "constant".to_string(),
+            IR::Concat(paths, _) => {
+                let paths: Vec<_> = paths
+                    .iter()
+                    .map(|path| {
+                        let path = path.iter().fold(from.clone(), |s, k| join_path(&s, k));
+                        provenance(source, &path, input)
+                    })
+                    .collect();
+                paths.join(", ")
+            }
+            IR::Copy
+            | IR::G2G(..)
+            | IR::Join(_)
+            | IR::Scale { .. }
+            | IR::DateConvert { .. }
+            | IR::Base64(_)
+            | IR::RegexExtract(..)
+            | IR::CaseTransform(_)
+            | IR::MapValues(_) => provenance(source, from, input),
+            _ => return None,
+        };
+        Some(format!("// {} -> {}", from, provenance(target, to, output)))
+    }
+
+    /// A frame reading property `key` of `top`'s input, through an optional
+    /// chain in safe mode if the input may be missing.
+    fn property(&self, top: &Frame, key: &Arc<String>) -> Frame {
+        let (schema, required) = match top.schema.as_deref() {
+            Some(Schema::Obj {
+                props,
+                required,
+                additional,
+                ..
+            }) => (
+                props.get(key).or(additional.as_ref()).cloned(),
+                required.contains(key),
+            ),
+            _ => (None, false),
+        };
+        let frame = top.with_schema(schema);
+        Frame {
+            input: self.read(top, &access(key)),
+            absent: frame.absent || !required,
+            item: false,
+            checks: 0,
+            ..frame
+        }
+    }
+
+    /// Check the property `frame` reads is there, as the path expects, or
+    /// fall back to `null`. Unless that throws, the rest of the scope goes
+    /// in an `else` branch, so it doesn't read into the missing property.
+    fn require(&mut self, frame: &mut Frame) {
+        let test = format!("{} === undefined", frame.input);
+        let fallback = format!("{} = null;", frame.output);
+        match self.handler(&frame.input, "required but missing", &fallback) {
+            Some(handle) if self.errors == ErrorMode::Throw => {
+                self.line(&format!("if ({}) {}", test, handle))
+            }
+            Some(handle) => {
+                self.line(&format!("if ({}) {{", test));
+                self.line(&format!("  {}", handle));
+                self.line("} else {");
+                self.indent += 1;
+                frame.checks += 1;
+            }
+            None => {}
+        }
+    }
+
+    /// Close the `else` branches `frame` is written in, if any.
+    fn close(&mut self, frame: &Frame) {
+        for _ in 0..frame.checks {
+            self.indent -= 1;
+            self.line("}");
+        }
+    }
+
+    /// A statement handling a failure to convert the value read by `input`,
+    /// when `test` says it failed, as the error mode says to.
+    fn failure(&self, test: &str, input: &str, message: &str, fallback: &str) -> Option<String> {
+        let handle = self.handler(input, message, fallback)?;
+        Some(format!("if ({}) {}", test, handle))
+    }
+
+    /// The statement handling a failure to convert the value read by
+    /// `input` as the error mode says to, if it says to at all.
+    fn handler(&self, input: &str, message: &str, fallback: &str) -> Option<String> {
+        let path = self.whence(input);
+        let handle = match self.errors {
+            ErrorMode::Ignore => return None,
+            ErrorMode::Throw => format!(
+                "throw new TypeError({});",
+                self.text(&format!("{}: {}", path, template(message)))
+            ),
+            ErrorMode::Collect => format!(
+                "{}.push({{ path: {}, message: {} }});",
+                self.collected,
+                self.text(&path),
+                serde_json::Value::from(message)
+            ),
+            ErrorMode::Fallback => fallback.to_string(),
+        };
+        Some(handle)
+    }
+
+    /// Where in the input `input` reads from, as the contents of a template
+    /// literal, with the items `map` callbacks are passed found where they
+    /// come from.
+    fn whence(&self, input: &str) -> String {
+        let input = unguarded(input).map_or(input, |(_, read)| read);
+        for (alias, path) in self.aliases.iter().rev() {
+            if let Some(rest) = input.strip_prefix(alias.as_str()) {
+                if rest.is_empty() || rest.starts_with(['.', '[', '?']) {
+                    return format!("{}{}", path, location(rest));
+                }
+            }
+        }
+        location(input)
+    }
+
+    /// The schema of the items of `top`'s input, where it's known.
+    fn items(top: &Frame) -> Option<Arc<Schema>> {
+        match top.schema.as_deref() {
+            Some(Schema::Arr(item)) => Some(item.clone()),
+            _ => None,
+        }
+    }
+
+    /// `suffix` read from `top`'s input, through an optional chain in safe
+    /// mode if the input may be missing.
+    fn read(&self, top: &Frame, suffix: &str) -> String {
+        if self.safe.is_some() && top.absent {
+            self.optional(&top.input, suffix)
+        } else {
+            format!("{}{}", top.input, suffix)
+        }
+    }
+
+    /// `suffix` read from `object` unless it's missing or `null`. Before
+    /// ES2015 that's a conditional, which reading on from adds to the
+    /// condition of rather than nesting.
+    fn optional(&self, object: &str, suffix: &str) -> String {
+        match (self.dialect, unguarded(object)) {
+            (Dialect::Modern, _) => format!("{}?.{}", object, suffix.trim_start_matches('.')),
+            (Dialect::Es5, Some((guard, read))) => {
+                format!(
+                    "({} || {r} == null ? undefined : {r}{})",
+                    guard,
+                    suffix,
+                    r = read
+                )
+            }
+            (Dialect::Es5, None) => {
+                format!("({o} == null ? undefined : {o}{})", suffix, o = object)
+            }
+        }
+    }
+
+    /// `value`, or `fallback` if it's missing or `null`.
+    fn or(&self, value: &str, fallback: &str) -> String {
+        match self.dialect {
+            Dialect::Modern => format!("{} ?? {}", value, fallback),
+            Dialect::Es5 => format!("({v} != null ? {v} : {})", fallback, v = value),
+        }
+    }
+
+    /// A function of `params` returning `value`.
+    fn lambda(&self, params: &str, value: &str) -> String {
+        match self.dialect {
+            Dialect::Modern => format!("({}) => {}", params, value),
+            Dialect::Es5 => format!("function ({}) {{ return {}; }}", params, value),
+        }
+    }
+
+    /// The keyword declaring a variable `modern` declares, which is `var`
+    /// before ES2015.
+    fn keyword<'a>(&self, modern: &'a str) -> &'a str {
+        match self.dialect {
+            Dialect::Modern => modern,
+            Dialect::Es5 => "var",
+        }
+    }
+
+    /// `template`, the contents of a template literal, as an expression
+    /// the dialect can evaluate: a template literal, or before ES2015 the
+    /// strings and substitutions in it added together.
+    fn text(&self, template: &str) -> String {
+        if self.dialect == Dialect::Modern {
+            return format!("`{}`", template);
+        }
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => literal.extend(chars.next()),
+                '$' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    let mut depth = 0;
+                    let mut expr = String::new();
+                    for c in chars.by_ref() {
+                        match c {
+                            '{' => depth += 1,
+                            '}' if depth == 0 => break,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                        expr.push(c);
+                    }
+                    if !literal.is_empty() {
+                        parts.push(
+                            serde_json::Value::from(std::mem::take(&mut literal)).to_string(),
+                        );
+                    }
+                    parts.push(expr);
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() || parts.is_empty() {
+            parts.push(serde_json::Value::from(literal).to_string());
+        }
+        // a string first or second, so the rest are added to it
+        let string = |part: Option<&String>| part.is_some_and(|part| part.starts_with('"'));
+        if !string(parts.first()) && !string(parts.get(1)) {
+            parts.insert(0, "\"\"".to_string());
+        }
+        parts.join(" + ")
+    }
+
+    /// Before ES2015, declare `name` as `top`'s input inverted: an object of
+    /// the `(key, value)` each `item` of it makes if it's an array, and
+    /// `entries` otherwise.
+    fn generate_es5_entries(
+        &mut self,
+        top: &Frame,
+        name: &str,
+        (key, value): (&str, &str),
+        entries: &str,
+    ) {
+        let input = &top.input;
+        self.line(&format!("var {} = {};", name, input));
+        self.line(&format!("if (Array.isArray({})) {{", input));
+        self.line(&format!("  {} = {{}};", name));
+        self.line(&format!(
+            "  {}.forEach(function (item) {{ {}[{}] = {}; }});",
+            input, name, key, value
+        ));
+        if self.safe.is_some() && top.absent {
+            self.line(&format!("}} else if ({}) {{", input));
+        } else {
+            self.line("} else {");
+        }
+        self.line(&format!("  {} = {};", name, entries));
+        self.line("}");
+    }
+
+    /// What comes ahead of an expression working on `top`'s input, so that
+    /// in safe mode it's missing or `null` if the input is.
+    fn guard(&self, top: &Frame) -> String {
+        if self.safe.is_some() && top.absent {
+            format!("{} && ", top.input)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Declare a function returning a frozen copy of a value along with
+    /// everything in it, and return its name.
+    fn generate_freeze(&mut self) -> String {
+        let freeze = self.fresh("freeze");
+        self.line(&format!("function {}(value) {{", freeze));
+        match self.dialect {
+            Dialect::Modern => {
+                self.line(&format!(
+                    "  if (Array.isArray(value)) return Object.freeze(value.map({}));",
+                    freeze
+                ));
+                self.line("  if (typeof value === \"object\" && value !== null) {");
+                self.line(&format!(
+                    "    const entries = Object.entries(value).map(([key, item]) => [key, {}(item)]);",
+                    freeze
+                ));
+                self.line("    return Object.freeze(Object.fromEntries(entries));");
+                self.line("  }");
+                self.line("  return value;");
+            }
+            // defining rather than assigning the properties, which could set
+            // the copy's prototype
+            Dialect::Es5 => {
+                self.line("  if (typeof value !== \"object\" || value === null) return value;");
+                self.line("  var copy = Array.isArray(value) ? [] : {};");
+                self.line("  Object.keys(value).forEach(function (key) {");
+                self.line(&format!(
+                    "    Object.defineProperty(copy, key, {{ value: {}(value[key]), enumerable: true }});",
+                    freeze
+                ));
+                self.line("  });");
+                self.line("  return Object.freeze(copy);");
+            }
+        }
+        self.line("}");
+        freeze
+    }
+
+    /// Declare `output` with what it's first assigned, if that's the first
+    /// statement, and return that instead if it's all there is.
+    fn return_literal(&mut self, output: &str) {
+        let keyword = self.keyword("let");
+        let declared = format!("  {} {};\n  {} = ", keyword, output, output);
+        let Some((checks, rest)) = self.body.split_once(&declared) else {
+            return;
+        };
+        let checks = checks.to_string();
+        let (first, after) = rest.split_once('\n').unwrap_or((rest, ""));
+        // only whole statements, which the next line doesn't carry on
+        if !first.ends_with(';') || after.starts_with("   ") {
+            return;
+        }
+        // the declaration's line is gone, and so is the return's if the
+        // assignment's returned
+        let declaration = checks.lines().count();
+        self.owners.remove(declaration);
+        self.body = if after == format!("  return {};\n", output) {
+            self.owners.truncate(declaration + 1);
+            format!("{}  return {}\n", checks, first)
+        } else {
+            format!("{}  {} {} = {}\n{}", checks, keyword, output, first, after)
+        };
+    }
+
+    /// Statements throwing if `value`, found at `path` (the contents of a
+    /// template literal), hasn't the shape of `schema`.
+    fn generate_checks(&mut self, schema: &Schema, value: &str, path: &str) {
+        let expected = format!("expected {}", describe(schema));
+        match schema {
+            Schema::True => {}
+            Schema::Arr(item) => {
+                self.check(&Self::generate_test(schema, value), path, &expected);
+                if **item != Schema::True {
+                    let idx = self.fresh("idx");
+                    self.line(&format!(
+                        "for ({} {idx} = 0; {idx} < {value}.length; {idx}++) {{",
+                        self.keyword("let")
+                    ));
+                    self.indent += 1;
+                    let item_value = format!("{}[{}]", value, idx);
+                    let item_path = format!("{}[${{{}}}]", path, idx);
+                    self.generate_checks(item, &item_value, &item_path);
+                    self.indent -= 1;
+                    self.line("}");
+                }
+            }
+            Schema::Obj {
+                props, required, ..
+            } => {
+                let test = format!(
+                    "typeof {value} === \"object\" && {value} !== null && !Array.isArray({value})"
+                );
+                self.check(&test, path, &expected);
+                for (key, prop) in props {
+                    let prop_value = format!("{}{}", value, access(key));
+                    let prop_path = format!("{}{}", path, template(&access(key)));
+                    if required.contains(key) {
+                        let test =
+                            format!("{} in {}", serde_json::Value::from(key.as_str()), value);
+                        self.check(&test, &prop_path, "required but missing");
+                        self.generate_checks(prop, &prop_value, &prop_path);
+                    } else if **prop != Schema::True {
+                        self.line(&format!("if ({} !== undefined) {{", prop_value));
+                        self.indent += 1;
+                        self.generate_checks(prop, &prop_value, &prop_path);
+                        self.indent -= 1;
+                        self.line("}");
+                    }
+                }
+            }
+            _ => self.check(&Self::generate_test(schema, value), path, &expected),
+        }
+    }
+
+    /// A statement throwing a `TypeError` saying what's wrong with the value
+    /// at `path` unless `test` passes.
+    fn check(&mut self, test: &str, path: &str, problem: &str) {
+        let message = self.text(&format!("{}: {}", path, template(problem)));
+        self.line(&format!(
+            "if (!({})) throw new TypeError({});",
+            test, message
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        codegen::CodegenOptionsBuilder,
+        schema::{Literal, Real},
+        test_util::key as name,
+    };
+
+    fn key(k: &str) -> IR {
+        IR::PushKey(Arc::new(k.to_string()))
+    }
+
+    /// The result of running the generated function `code` on `input` with
+    /// node, or `None` where node isn't installed.
+    fn run(code: &str, input: &serde_json::Value) -> Option<serde_json::Value> {
+        // parsed rather than written as a literal, which would treat a
+        // `__proto__` key as the prototype
+        let input = serde_json::Value::from(input.to_string());
+        let script = format!(
+            "console.log(JSON.stringify(({})(JSON.parse({}))));",
+            code, input
+        );
+        let out = std::process::Command::new("node")
+            .args(["-e", &script])
+            .output()
+            .ok()?;
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        Some(serde_json::from_slice(&out.stdout).unwrap())
+    }
+
+    fn generate(ir: &[IR]) -> String {
+        JSCodegen::new().generate(ir, "input", "output")
+    }
+
+    #[test]
+    fn test_copy() {
+        assert_eq!(
+            generate(&[IR::Copy]),
+            "function(input) {\n  let output;\n  output = input;\n  return output;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_object() {
+        let ir = [
+            IR::PushObj,
+            key("foo"),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            key("bar"),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  output.foo = String(input.foo);
+  output.bar = input.bar;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_rename() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(
+                Arc::new("user_id".to_string()),
+                Arc::new("userId".to_string()),
+            ),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  output.userId = input.user_id;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_optional_guard() {
+        let ir = [
+            IR::PushObj,
+            key("age"),
+            IR::PushOpt,
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopOpt,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  if (input.age !== undefined) {
+    output.age = String(input.age);
+  }
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_nested_extraction() {
+        let ir = [
+            IR::Extr(Arc::new("meta".to_string())),
+            IR::Extr(Arc::new("id".to_string())),
+            IR::Copy,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {\n  let output;\n  output = input.meta.id;\n  return output;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_extraction_in_context() {
+        let ir = [
+            IR::PushObj,
+            key("owner"),
+            IR::Extr(Arc::new("id".to_string())),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            key("tags"),
+            IR::PushArr,
+            IR::Extr(Arc::new("name".to_string())),
+            IR::Copy,
+            IR::PopArr,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  output.owner = String(input.owner.id);
+  output.tags = [];
+  for (let idx0 = 0; idx0 < input.tags.length; idx0++) {
+    output.tags[idx0] = input.tags[idx0].name;
+  }
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_union_dispatch() {
+        let ir = [
+            IR::PushUnion,
+            IR::Case(Arc::new(Schema::string())),
+            IR::Wrap,
+            IR::Copy,
+            IR::PopCase,
+            IR::Case(Arc::new(Schema::Arr(Arc::new(Schema::string())))),
+            IR::Copy,
+            IR::PopCase,
+            IR::PopUnion,
+        ];
+        assert_eq!(
+            generate(&ir),
+            r#"function(input) {
+  let output;
+  if (typeof input === "string") {
+    output = [];
+    output[0] = input;
+  } else if (Array.isArray(input)) {
+    output = input;
+  }
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let user = Arc::new("user".to_string());
+        let ir = [
+            IR::PushObj,
+            IR::Rename(Arc::new("profile".to_string()), user.clone()),
+            IR::Copy,
+            IR::PopKey,
+            IR::Nest(user),
+            IR::Merge,
+            key("id"),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  output.user = input.profile;
+  output.user = { ...output.user };
+  output.user.id = input.id;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_nest() {
+        let ir = [
+            IR::PushObj,
+            IR::Nest(Arc::new("address".to_string())),
+            IR::PushObj,
+            IR::Rename(
+                Arc::new("address_city".to_string()),
+                Arc::new("city".to_string()),
+            ),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  output.address = {};
+  output.address.city = input.address_city;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_nested_arrays() {
+        let ir = [
+            IR::PushArr,
+            IR::PushArr,
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopArr,
+            IR::PopArr,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = [];
+  for (let idx0 = 0; idx0 < input.length; idx0++) {
+    output[idx0] = [];
+    for (let idx1 = 0; idx1 < input[idx0].length; idx1++) {
+      output[idx0][idx1] = Number(input[idx0][idx1]);
+    }
+  }
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let ir = [
+            IR::PushObj,
+            key("tags"),
+            IR::Wrap,
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  output.tags = [];
+  output.tags[0] = String(input.tags);
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_first_and_join() {
+        let ir = [
+            IR::PushObj,
+            key("primary"),
+            IR::First,
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            key("tags"),
+            IR::Join(", ".to_string()),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            r#"function(input) {
+  let output;
+  output = {};
+  output.primary = String(input.primary[0]);
+  output.tags = input.tags.map(String).join(", ");
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_flatten() {
+        let ir = [
+            IR::Flatten(2),
+            IR::PushArr,
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopArr,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  const flat0 = input.flat(2);
+  output = [];
+  for (let idx1 = 0; idx1 < flat0.length; idx1++) {
+    output[idx1] = String(flat0[idx1]);
+  }
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_slice() {
+        assert_eq!(
+            generate(&[IR::Slice(0, Some(3)), IR::Copy]),
+            "function(input) {
+  let output;
+  const sliced0 = input.slice(0, 3);
+  output = sliced0;
+  return output;
+}
+"
+        );
+        assert!(generate(&[IR::Slice(2, None), IR::Copy]).contains("input.slice(2);"));
+    }
+
+    #[test]
+    fn test_sort() {
+        let ir = [
+            IR::Sort(
+                vec![Arc::new("timestamp".to_string())],
+                SortOrder::Descending,
+            ),
+            IR::Copy,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  const sorted0 = [...input].sort((a, b) => (b.timestamp < a.timestamp ? -1 : b.timestamp > a.timestamp ? 1 : 0));
+  output = sorted0;
+  return output;
+}
+"
+        );
+        assert!(
+            generate(&[IR::Sort(vec![], SortOrder::Ascending), IR::Copy])
+                .contains("(a < b ? -1 : a > b ? 1 : 0)")
+        );
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let ir = [
+            IR::Gather(vec![
+                vec![Arc::new("tags".to_string())],
+                vec![Arc::new("labels".to_string())],
+            ]),
+            IR::Dedupe(None),
+            IR::Copy,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  const gathered0 = [...(input.tags ?? []), ...(input.labels ?? [])];
+  const seen1 = new Set();
+  const unique2 = gathered0.filter((item) => !seen1.has(JSON.stringify(item)) && seen1.add(JSON.stringify(item)));
+  output = unique2;
+  return output;
+}
+"
+        );
+        let ir = [IR::Dedupe(Some(vec![Arc::new("id".to_string())])), IR::Copy];
+        assert!(generate(&ir).contains("!seen0.has(item.id) && seen0.add(item.id)"));
+    }
+
+    #[test]
+    fn test_zip_param_names() {
+        let pairs = vec![(name("names"), name("name")), (name("ages"), name("age"))];
+        let ir = [IR::Zip(pairs), IR::Copy];
+        let input = serde_json::json!({"names": ["ada", "bob"], "ages": [36, 41]});
+        let expected = serde_json::json!([{"name": "ada", "age": 36}, {"name": "bob", "age": 41}]);
+        // the callback's parameters don't shadow the input, whatever it's
+        // called
+        for param in ["i", "_", "idx0", "_0"] {
+            for dialect in [Dialect::Modern, Dialect::Es5] {
+                let code = JSCodegen::new()
+                    .dialect(dialect)
+                    .generate(&ir, param, "output");
+                if let Some(output) = run(&code, &input) {
+                    assert_eq!(output, expected, "{}", code);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_recursion() {
+        let node = name("node");
+        let ir = [
+            IR::Rec(node.clone()),
+            IR::PushObj,
+            IR::PushKey(name("children")),
+            IR::PushArr,
+            IR::Call(node),
+            IR::PopArr,
+            IR::PopKey,
+            IR::PushKey(name("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopRec,
+        ];
+        let input = serde_json::json!({"id": 1, "children": [
+            {"id": 2, "children": [{"id": 3, "children": []}]}
+        ]});
+        let expected = serde_json::json!({"id": "1", "children": [
+            {"id": "2", "children": [{"id": "3", "children": []}]}
+        ]});
+        for dialect in [Dialect::Modern, Dialect::Es5] {
+            let code = JSCodegen::new()
+                .dialect(dialect)
+                .generate(&ir, "input", "output");
+            assert!(code.contains("function convertNode("), "{}", code);
+            if let Some(output) = run(&code, &input) {
+                assert_eq!(output, expected, "{}", code);
+            }
+        }
+    }
+
+    #[test]
+    fn test_zip() {
+        let pairs = vec![
+            (Arc::new("names".to_string()), Arc::new("name".to_string())),
+            (Arc::new("ages".to_string()), Arc::new("age".to_string())),
+        ];
+        assert_eq!(
+            generate(&[IR::Zip(pairs.clone()), IR::Copy]),
+            "function(input) {
+  let output;
+  const zipped0 = Array.from({ length: Math.max((input.names ?? []).length, (input.ages ?? []).length) }, (_1, idx2) => ({ \"name\": input.names?.[idx2], \"age\": input.ages?.[idx2] }));
+  output = zipped0;
+  return output;
+}
+"
+        );
+        assert_eq!(
+            generate(&[IR::Unzip(pairs), IR::Copy]),
+            "function(input) {
+  let output;
+  const unzipped0 = { \"names\": input.map((item) => item.name), \"ages\": input.map((item) => item.age) };
+  output = unzipped0;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_pipe() {
+        let ir = [
+            IR::PushObj,
+            IR::PushKey(Arc::new("tags".to_string())),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::Pipe,
+            IR::Inv,
+            IR::Copy,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  output.tags = input.tags;
+  const piped0 = output;
+  const inv1 = Array.isArray(piped0) ? Object.fromEntries(piped0.map((e) => [e.key, e.value])) : Object.entries(piped0).map(([key, value]) => ({ key, value }));
+  output = inv1;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_group_by() {
+        let ir = [IR::GroupBy(Arc::new("id".to_string())), IR::Copy];
+        assert_eq!(
+            generate(&ir),
+            r#"function(input) {
+  let output;
+  const grouped0 = Array.isArray(input) ? Object.fromEntries(input.map((item) => [item["id"], item])) : Object.entries(input).map(([key, item]) => ({ ...item, ["id"]: key }));
+  output = grouped0;
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_inv() {
+        let ir = [
+            IR::Inv,
+            IR::PushArr,
+            IR::PushObj,
+            key("key"),
+            IR::Copy,
+            IR::PopKey,
+            key("value"),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopArr,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  const inv0 = Array.isArray(input) ? Object.fromEntries(input.map((e) => [e.key, e.value])) \
+: Object.entries(input).map(([key, value]) => ({ key, value }));
+  output = [];
+  for (let idx1 = 0; idx1 < inv0.length; idx1++) {
+    output[idx1] = {};
+    output[idx1].key = inv0[idx1].key;
+    output[idx1].value = String(inv0[idx1].value);
+  }
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_inv_entries() {
+        let ir = [
+            IR::Inv,
+            IR::PushObj,
+            key("a"),
+            IR::PushOpt,
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopOpt,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  const inv0 = Array.isArray(input) ? Object.fromEntries(input.map((e) => [e.key, e.value])) \
+: Object.entries(input).map(([key, value]) => ({ key, value }));
+  output = {};
+  if (inv0.a !== undefined) {
+    output.a = Number(inv0.a);
+  }
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_inv_maps() {
+        let to_entries = [
+            IR::Inv,
+            IR::PushArr,
+            IR::PushObj,
+            key("key"),
+            IR::Copy,
+            IR::PopKey,
+            key("value"),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopArr,
+        ];
+        let from_entries = [
+            IR::Inv,
+            IR::PushObj,
+            IR::PushRest(vec![]),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopRest,
+            IR::PopObj,
+        ];
+        let map = serde_json::json!({"a": 1, "b": 2});
+        let entries = serde_json::json!([{"key": "a", "value": "1"}, {"key": "b", "value": "2"}]);
+        if let Some(output) = run(&generate(&to_entries), &map) {
+            assert_eq!(output, entries);
+        }
+        if let Some(output) = run(&generate(&from_entries), &entries) {
+            assert_eq!(output, map);
+        }
+    }
+
+    #[test]
+    fn test_scale() {
+        let ir = [
+            IR::PushObj,
+            key("temp"),
+            IR::Scale {
+                factor: Real(1.8),
+                offset: Real(32.0),
+            },
+            IR::PopKey,
+            key("price"),
+            IR::Scale {
+                factor: Real(0.01),
+                offset: Real(0.0),
+            },
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  output.temp = input.temp * 1.8 + 32;
+  output.price = input.price * 0.01;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_date_convert() {
+        let convert = |from_format, to_format| {
+            generate(&[IR::DateConvert {
+                from_format,
+                to_format,
+            }])
+        };
+        assert_eq!(
+            convert(DateFormat::EpochSeconds, DateFormat::Iso),
+            "function(input) {
+  let output;
+  output = new Date(input * 1000).toISOString();
+  return output;
+}
+"
+        );
+        assert_eq!(
+            convert(DateFormat::Iso, DateFormat::EpochSeconds),
+            "function(input) {
+  let output;
+  output = Math.floor(new Date(input).getTime() / 1000);
+  return output;
+}
+"
+        );
+        assert_eq!(
+            convert(DateFormat::Iso, DateFormat::EpochMillis),
+            "function(input) {
+  let output;
+  output = new Date(input).getTime();
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_base64() {
+        let ir = [IR::Base64(Coding::Encode)];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = btoa(Array.from(new TextEncoder().encode(input), (b) => String.fromCharCode(b)).join(\"\"));
+  return output;
+}
+"
+        );
+        assert_eq!(
+            JSCodegen::with_runtime(Runtime::Node).generate(
+                &[IR::Base64(Coding::Decode)],
+                "input",
+                "output"
+            ),
+            r#"function(input) {
+  let output;
+  output = Buffer.from(input, "base64").toString("utf8");
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_base64_unicode() {
+        let text = serde_json::json!("héllo €");
+        let encoded = serde_json::json!("aMOpbGxvIOKCrA==");
+        for runtime in [Runtime::Browser, Runtime::Node] {
+            for dialect in [Dialect::Modern, Dialect::Es5] {
+                let generate = |coding| {
+                    JSCodegen::with_runtime(runtime).dialect(dialect).generate(
+                        &[IR::Base64(coding)],
+                        "input",
+                        "output",
+                    )
+                };
+                let Some(output) = run(&generate(Coding::Encode), &text) else {
+                    return;
+                };
+                assert_eq!(output, encoded);
+                assert_eq!(run(&generate(Coding::Decode), &encoded), Some(text.clone()));
+            }
+        }
+        assert_eq!(
+            crate::ir::eval(&[IR::Base64(Coding::Encode)], &text),
+            encoded
+        );
+    }
+
+    #[test]
+    fn test_regex_extract() {
+        assert_eq!(
+            generate(&[IR::RegexExtract("@(.+)$".to_string(), 1)]),
+            r#"function(input) {
+  let output;
+  output = String(input).match(new RegExp("@(.+)$"))?.[1];
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_case_transform() {
+        assert_eq!(
+            generate(&[IR::CaseTransform(Case::Upper)]),
+            "function(input) {
+  let output;
+  output = String(input).toUpperCase();
+  return output;
+}
+"
+        );
+        assert_eq!(
+            generate(&[IR::CaseTransform(Case::Snake)]),
+            r#"function(input) {
+  let output;
+  output = String(input).replace(/([a-z0-9])([A-Z])/g, "$1 $2").split(/[-_ ]+/).filter(Boolean).map((w) => w.toLowerCase()).join("_");
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_map_values() {
+        let table = [("ACTIVE", 1), ("INACTIVE", 0)]
+            .into_iter()
+            .map(|(from, to)| (Literal(from.into()), Literal(to.into())))
+            .collect();
+        let ir = [IR::MapValues(table)];
+        assert_eq!(
+            generate(&ir),
+            r#"function(input) {
+  let output;
+  output = new Map([["ACTIVE", 1], ["INACTIVE", 0]]).get(input);
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let paths = ["email", "contact_email"]
+            .into_iter()
+            .map(|k| vec![Arc::new(k.to_string())])
+            .collect();
+        let ir = [
+            IR::PushObj,
+            IR::Nest(Arc::new("email".to_string())),
+            IR::Coalesce(paths),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            "function(input) {
+  let output;
+  output = {};
+  const coalesced0 = input.email ?? input.contact_email;
+  output.email = coalesced0;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_concat() {
+        let path = |k: &str| vec![Arc::new(k.to_string())];
+        let ir = [
+            IR::PushObj,
+            IR::Nest(Arc::new("name".to_string())),
+            IR::Concat(vec![path("first"), path("last")], " ".to_string()),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            r#"function(input) {
+  let output;
+  output = {};
+  output.name = [input.first, input.last].filter((v) => v != null).map(String).join(" ");
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_const() {
+        let ir = [
+            IR::PushObj,
+            IR::Nest(Arc::new("meta".to_string())),
+            IR::Const(Literal(serde_json::json!({"version": 2}))),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            r#"function(input) {
+  let output;
+  output = {};
+  output.meta = {"version":2};
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_rest() {
+        let ir = [
+            IR::PushObj,
+            key("id"),
+            IR::Copy,
+            IR::PopKey,
+            IR::PushRest(vec![Arc::new("id".to_string())]),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopRest,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            r#"function(input) {
+  let output;
+  output = {};
+  output.id = input.id;
+  for (const key0 of Object.keys(input)) {
+    if (["id"].includes(key0)) continue;
+    if (key0 === "__proto__") Object.defineProperty(output, key0, { writable: true, enumerable: true, configurable: true });
+    output[key0] = String(input[key0]);
+  }
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_module_formats() {
+        let generate = |module| {
+            JSCodegen::new()
+                .module(module)
+                .generate(&[IR::Copy], "input", "output")
+        };
+        let body = "(input) {\n  let output;\n  output = input;\n  return output;\n}";
+        assert_eq!(
+            generate(ModuleFormat::ExportDefault),
+            format!("export default function{}\n", body)
+        );
+        assert_eq!(
+            generate(ModuleFormat::ExportNamed),
+            format!("export function transform{}\n", body)
+        );
+        assert_eq!(
+            generate(ModuleFormat::CommonJs),
+            format!("module.exports = function{};\n", body)
+        );
+    }
+
+    #[test]
+    fn test_function_styles() {
+        let generate = |style, module| {
+            JSCodegen::new()
+                .style(style)
+                .module(module)
+                .generate(&[IR::Copy], "input", "output")
+        };
+        let body = "{\n  let output;\n  output = input;\n  return output;\n}";
+        assert_eq!(
+            generate(FunctionStyle::Declaration, ModuleFormat::Expression),
+            format!("function transform(input) {}\n", body)
+        );
+        assert_eq!(
+            generate(FunctionStyle::Arrow, ModuleFormat::Expression),
+            format!("const transform = (input) => {};\n", body)
+        );
+        assert_eq!(
+            generate(FunctionStyle::Arrow, ModuleFormat::ExportNamed),
+            format!("export const transform = (input) => {};\n", body)
+        );
+        assert_eq!(
+            generate(FunctionStyle::Declaration, ModuleFormat::CommonJs),
+            format!(
+                "function transform(input) {}\nmodule.exports = transform;\n",
+                body
+            )
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let options = CodegenOptionsBuilder::default()
+            .name("convert".to_string())
+            .input("data".to_string())
+            .returns(ReturnStyle::Literal)
+            .build()
+            .unwrap();
+        let generate = |ir: &[IR]| {
+            JSCodegen::new()
+                .style(FunctionStyle::Declaration)
+                .generate_with(ir, &options)
+        };
+        assert_eq!(
+            generate(&[IR::G2G(Ground::Num, Ground::String)]),
+            "function convert(data) {\n  return String(data);\n}\n"
+        );
+        let ir = [IR::PushObj, key("foo"), IR::Copy, IR::PopKey, IR::PopObj];
+        assert_eq!(
+            generate(&ir),
+            "function convert(data) {
+  let output = {};
+  output.foo = data.foo;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["id"]
+        });
+        let code = JSCodegen::new()
+            .validate(&source)
+            .generate(&[IR::Copy], "input", "output");
+        assert_eq!(
+            code,
+            r#"function(input) {
+  if (!(typeof input === "object" && input !== null && !Array.isArray(input))) throw new TypeError(`input: expected object {id, tags}`);
+  if (!("id" in input)) throw new TypeError(`input.id: required but missing`);
+  if (!(typeof input.id === "number")) throw new TypeError(`input.id: expected number`);
+  if (input.tags !== undefined) {
+    if (!(Array.isArray(input.tags))) throw new TypeError(`input.tags: expected array of string`);
+    for (let idx0 = 0; idx0 < input.tags.length; idx0++) {
+      if (!(typeof input.tags[idx0] === "string")) throw new TypeError(`input.tags[${idx0}]: expected string`);
+    }
+  }
+  let output;
+  output = input;
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_safe() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {
+                "a": {
+                    "type": "object",
+                    "properties": {
+                        "b": {"type": "array", "items": {"type": "object", "properties": {"c": {"type": "number"}}}},
+                        "d": {"type": "array", "items": {"anyOf": [{"type": "number"}, {"type": "null"}]}}
+                    },
+                    "required": ["b", "d"]
+                }
+            },
+            "required": ["a"]
+        });
+        let extr = |k: &str| IR::Extr(Arc::new(k.to_string()));
+        let generate = |ir: &[IR]| {
+            JSCodegen::new()
+                .safe(&source)
+                .generate(ir, "input", "output")
+        };
+        // only what may be missing is read through optional chains
+        let ir = [extr("a"), extr("b"), IR::First, extr("c"), IR::Copy];
+        assert!(generate(&ir).contains("  output = input.a.b[0]?.c;\n"));
+        let ir = [extr("a"), extr("d"), IR::PushArr, IR::Copy, IR::PopArr];
+        assert!(generate(&ir).contains("    output[idx0] = input.a.d[idx0] ?? null;\n"));
+    }
+
+    #[test]
+    fn test_missing_array() {
+        let ir = [
+            IR::PushObj,
+            key("list"),
+            IR::PushArr,
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopArr,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let generate = |errors| {
+            JSCodegen::new()
+                .errors(errors)
+                .generate(&ir, "input", "output")
+        };
+        let input = serde_json::json!({});
+        let Some(collected) = run(&generate(ErrorMode::Collect), &input) else {
+            return;
+        };
+        assert_eq!(
+            collected,
+            serde_json::json!({
+                "result": {},
+                "errors": [{"path": "input.list", "message": "required but missing"}]
+            })
+        );
+        assert_eq!(
+            run(&generate(ErrorMode::Fallback), &input),
+            Some(serde_json::json!({"list": null}))
+        );
+        assert_eq!(
+            run(
+                &generate(ErrorMode::Fallback),
+                &serde_json::json!({"list": [1]})
+            ),
+            Some(serde_json::json!({"list": ["1"]}))
+        );
+    }
+
+    #[test]
+    fn test_flatten_error_modes() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(name("address"), name("address_city")),
+            IR::Extr(name("city")),
+            IR::Copy,
+            IR::PopKey,
+            IR::Rename(name("address"), name("address_zip")),
+            IR::Extr(name("zip")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let generate = |errors| {
+            JSCodegen::new()
+                .errors(errors)
+                .generate(&ir, "input", "output")
+        };
+        let whole = serde_json::json!({"address": {"city": "Oslo", "zip": "0150"}});
+        let flat = serde_json::json!({"address_city": "Oslo", "address_zip": "0150"});
+        let Some(collected) = run(&generate(ErrorMode::Collect), &whole) else {
+            return;
+        };
+        assert_eq!(collected, serde_json::json!({"result": flat, "errors": []}));
+        // each level extracted from is checked
+        assert_eq!(
+            run(
+                &generate(ErrorMode::Collect),
+                &serde_json::json!({"address": {"zip": "0150"}})
+            ),
+            Some(serde_json::json!({
+                "result": {"address_zip": "0150"},
+                "errors": [{"path": "input.address.city", "message": "required but missing"}]
+            }))
+        );
+        assert_eq!(run(&generate(ErrorMode::Fallback), &whole), Some(flat));
+        assert_eq!(
+            run(&generate(ErrorMode::Fallback), &serde_json::json!({})),
+            Some(serde_json::json!({"address_city": null, "address_zip": null}))
+        );
+        assert_eq!(
+            run(&generate(ErrorMode::Throw), &whole),
+            Some(serde_json::json!({"address_city": "Oslo", "address_zip": "0150"}))
+        );
+        assert_eq!(
+            run(&generate(ErrorMode::Ignore), &whole),
+            Some(serde_json::json!({"address_city": "Oslo", "address_zip": "0150"}))
+        );
+    }
+
+    #[test]
+    fn test_error_modes() {
+        let ir = [
+            IR::PushObj,
+            key("n"),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let generate = |errors| {
+            JSCodegen::new()
+                .errors(errors)
+                .generate(&ir, "input", "output")
+        };
+        assert_eq!(
+            generate(ErrorMode::Throw),
+            "function(input) {
+  let output;
+  output = {};
+  if (input.n === undefined) throw new TypeError(`input.n: required but missing`);
+  output.n = Number(input.n);
+  if (Number.isNaN(output.n)) throw new TypeError(`input.n: not a number`);
+  return output;
+}
+"
+        );
+        let code = generate(ErrorMode::Collect);
+        assert!(code.starts_with("function(input) {\n  const errors = [];\n"));
+        assert!(code.contains(
+            "  if (Number.isNaN(output.n)) errors.push({ path: `input.n`, message: \"not a number\" });\n"
+        ));
+        assert!(code.ends_with("  return { result: output, errors };\n}\n"));
+        // missing properties fall back instead of being converted
+        assert!(generate(ErrorMode::Fallback).contains(
+            "  if (input.n === undefined) {\n    output.n = null;\n  } else {\n    output.n = Number(input.n);\n    if (Number.isNaN(output.n)) output.n = 0;\n  }\n"
+        ));
+        assert_eq!(
+            location("input.a[idx0][\"b`\"]"),
+            "input.a[${idx0}][\"b\\`\"]"
+        );
+    }
+
+    #[test]
+    fn test_non_identifier_keys() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {
+                "first name": {"type": "string"},
+                "meta": {
+                    "anyOf": [
+                        {"type": "object", "properties": {"2x": {"type": "number"}}},
+                        {"type": "null"}
+                    ]
+                }
+            },
+            "required": ["first name"]
+        });
+        let ir = [
+            IR::PushObj,
+            IR::Rename(
+                Arc::new("first name".to_string()),
+                Arc::new("first-name".to_string()),
+            ),
+            IR::Copy,
+            IR::PopKey,
+            IR::Nest(Arc::new("$x".to_string())),
+            IR::Extr(Arc::new("meta".to_string())),
+            IR::Extr(Arc::new("2x".to_string())),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let code = JSCodegen::new()
+            .validate(&source)
+            .safe(&source)
+            .generate(&ir, "input", "output");
+        assert_eq!(
+            code,
+            r#"function(input) {
+  if (!(typeof input === "object" && input !== null && !Array.isArray(input))) throw new TypeError(`input: expected object {first name, meta}`);
+  if (!("first name" in input)) throw new TypeError(`input["first name"]: required but missing`);
+  if (!(typeof input["first name"] === "string")) throw new TypeError(`input["first name"]: expected string`);
+  if (input.meta !== undefined) {
+    if (!((typeof input.meta === "object" && input.meta !== null && !Array.isArray(input.meta)) || (input.meta === null))) throw new TypeError(`input.meta: expected one of (object {2x} | null)`);
+  }
+  let output;
+  output = {};
+  output["first-name"] = input["first name"];
+  output.$x = input.meta?.["2x"];
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_proto_keys() {
+        let proto = || Arc::new("__proto__".to_string());
+        let ir = [
+            IR::PushObj,
+            IR::PushKey(proto()),
+            IR::Copy,
+            IR::PopKey,
+            IR::PushRest(vec![proto()]),
+            IR::Copy,
+            IR::PopRest,
+            IR::PopObj,
+        ];
+        let input: serde_json::Value =
+            serde_json::from_str(r#"{"__proto__": {"polluted": true}, "a": 1}"#).unwrap();
+        let check = |code: String| {
+            format!(
+                "(input) => {{ const output = ({})(input); return [Object.getPrototypeOf(output) === Object.prototype, output.polluted === undefined, output]; }}",
+                code
+            )
+        };
+        for dialect in [Dialect::Modern, Dialect::Es5] {
+            let code = JSCodegen::new()
+                .dialect(dialect)
+                .generate(&ir, "input", "output");
+            let Some(result) = run(&check(code), &input) else {
+                return;
+            };
+            assert_eq!(result, serde_json::json!([true, true, input]));
+        }
+    }
+
+    #[test]
+    fn test_fresh_names() {
+        let ir = [
+            IR::PushArr,
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopArr,
+        ];
+        let code = JSCodegen::new()
+            .errors(ErrorMode::Collect)
+            .generate(&ir, "idx0", "errors");
+        assert_eq!(
+            code,
+            "function(idx0) {
+  const errors0 = [];
+  let errors;
+  errors = [];
+  for (let idx1 = 0; idx1 < idx0.length; idx1++) {
+    errors[idx1] = Number(idx0[idx1]);
+    if (Number.isNaN(errors[idx1])) errors0.push({ path: `idx0[${idx1}]`, message: \"not a number\" });
+  }
+  return { result: errors, errors: errors0 };
+}
+"
+        );
+        let code = JSCodegen::new()
+            .prefix("$")
+            .generate(&ir, "input", "output");
+        assert!(code.contains("for (let $idx0 = 0; $idx0 < input.length; $idx0++) {"));
+
+        // numbered apart, an array sorted ahead of the loop doesn't renumber it
+        let ir = [
+            IR::PushObj,
+            key("a"),
+            IR::Sort(vec![], SortOrder::Ascending),
+            IR::Copy,
+            IR::PopKey,
+            key("b"),
+            IR::PushArr,
+            IR::Copy,
+            IR::PopArr,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let code = JSCodegen::new()
+            .numbering(Numbering::PerName)
+            .generate(&ir, "input", "output");
+        assert!(code.contains("const sorted0 = "));
+        assert!(code.contains("for (let idx0 = 0; idx0 < input.b.length; idx0++) {"));
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let ir = [
+            IR::Copy,
+            IR::Merge,
+            IR::Nest(Arc::new("n".to_string())),
+            IR::Const(Literal(serde_json::json!(1))),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            JSCodegen::new().strict().generate(&ir, "input", "output"),
+            r#"function(input) {
+  "use strict";
+  let output;
+  output = input;
+  output = { ...output };
+  output.n = 1;
+  return output;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_jsdoc() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {"n": {"type": "string"}},
+            "required": ["n"]
+        });
+        let target = crate::schema!({"type": "array", "items": {"type": "number"}});
+        let code = JSCodegen::new()
+            .jsdoc(&source, &target)
+            .style(FunctionStyle::Declaration)
+            .module(ModuleFormat::ExportDefault)
+            .generate(&[IR::Copy], "input", "output");
+        assert_eq!(
+            code,
+            "/**
+ * @typedef {{
+ *   n: string;
+ * }} Source
+ */
+/**
+ * @typedef {number[]} Target
+ */
+/**
+ * @param {Source} input
+ * @returns {Target}
+ */
+export default function transform(input) {
+  let output;
+  output = input;
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_declarations() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {"n": {"type": "string"}},
+            "required": ["n"]
+        });
+        let target = crate::schema!({"type": "array", "items": {"type": "number"}});
+        assert_eq!(
+            JSCodegen::new()
+                .module(ModuleFormat::ExportDefault)
+                .declarations(&source, &target),
+            "export interface Source {
+  n: string;
+}
+
+export type Target = number[];
+
+declare function transform(input: Source): Target;
+export default transform;
+"
+        );
+        assert_eq!(
+            JSCodegen::new()
+                .module(ModuleFormat::CommonJs)
+                .errors(ErrorMode::Collect)
+                .declarations(&source, &target),
+            "declare function transform(input: transform.Source): { result: transform.Target, errors: { path: string, message: string }[] };
+declare namespace transform {
+  interface Source {
+    n: string;
+  }
+
+  type Target = number[];
+}
+export = transform;
+"
+        );
+    }
+
+    #[test]
+    fn test_map_arrays() {
+        let ir = [
+            IR::PushObj,
+            key("a"),
+            IR::PushArr,
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopArr,
+            IR::PopKey,
+            key("b"),
+            IR::PushArr,
+            IR::PushObj,
+            key("c"),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopArr,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let generate = |errors| {
+            JSCodegen::new()
+                .arrays(ArrayStyle::Map)
+                .errors(errors)
+                .generate(&ir, "input", "output")
+        };
+        assert_eq!(
+            generate(ErrorMode::Ignore),
+            "function(input) {
+  let output;
+  output = {};
+  output.a = input.a.map((item0) => Number(item0));
+  output.b = input.b.map((item2) => {
+    let mapped3;
+    mapped3 = {};
+    mapped3.c = item2.c;
+    return mapped3;
+  });
+  return output;
+}
+"
+        );
+        // failures say where the item came from
+        let code = generate(ErrorMode::Throw);
+        assert!(code.contains("  output.a = input.a.map((item0, idx1) => {\n"));
+        assert!(code.contains("throw new TypeError(`input.b[${idx4}].c: required but missing`);"));
+    }
+
+    #[test]
+    fn test_number_coercion() {
+        let generate = |from, coercion| {
+            JSCodegen::new().coercion(coercion).generate(
+                &[IR::G2G(from, Ground::Num)],
+                "input",
+                "output",
+            )
+        };
+        let number = |code: &str| code.lines().nth(2).unwrap().trim().to_string();
+        assert_eq!(
+            number(&generate(Ground::String, NumberCoercion::Number)),
+            "output = Number(input);"
+        );
+        assert_eq!(
+            number(&generate(Ground::String, NumberCoercion::ParseFloat)),
+            "output = parseFloat(input);"
+        );
+        assert_eq!(
+            number(&generate(Ground::String, NumberCoercion::Strict)),
+            "output = (String(input).trim() === \"\" ? NaN : Number(input));"
+        );
+        // booleans are always 1 or 0
+        assert_eq!(
+            number(&generate(Ground::Bool, NumberCoercion::ParseFloat)),
+            "output = Number(input);"
+        );
+    }
+
+    #[test]
+    fn test_streaming() {
+        let ir = [
+            IR::PushArr,
+            IR::PushObj,
+            key("n"),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopArr,
+        ];
+        assert_eq!(
+            JSCodegen::new()
+                .streaming(Streaming::AsyncGenerator)
+                .style(FunctionStyle::Declaration)
+                .generate(&ir, "input", "output"),
+            "async function* transform(input) {
+  for await (const inputItem of input) {
+    let output;
+    output = {};
+    output.n = Number(inputItem.n);
+    yield output;
+  }
+}
+"
+        );
+        // anything else is yielded whole
+        let options = CodegenOptionsBuilder::default()
+            .returns(ReturnStyle::Literal)
+            .build()
+            .unwrap();
+        assert_eq!(
+            JSCodegen::new()
+                .streaming(Streaming::Generator)
+                .style(FunctionStyle::Arrow)
+                .generate_with(&[IR::G2G(Ground::Num, Ground::String)], &options),
+            "const transform = function*(input) {
+  yield String(input);
+};
+"
+        );
+    }
+
+    #[test]
+    fn test_es5() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {
+                "user": {
+                    "type": "object",
+                    "properties": {"age": {"type": "string"}}
+                },
+                "tags": {"type": "array", "items": {"type": "string"}}
+            }
+        });
+        let ir = [
+            IR::PushObj,
+            key("tags"),
+            IR::Sort(vec![], SortOrder::Descending),
+            IR::Copy,
+            IR::PopKey,
+            IR::Nest(name("age")),
+            IR::Coalesce(vec![vec![name("user"), name("age")], vec![name("age")]]),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            JSCodegen::new()
+                .dialect(Dialect::Es5)
+                .safe(&source)
+                .errors(ErrorMode::Throw)
+                .style(FunctionStyle::Arrow)
+                .generate(&ir, "input", "output"),
+            r#"var transform = function(input) {
+  var output;
+  output = {};
+  if (input.tags === undefined) throw new TypeError("input.tags: required but missing");
+  var sorted0 = input.tags && input.tags.slice().sort(function (a, b) { return (b < a ? -1 : b > a ? 1 : 0); });
+  output.tags = sorted0;
+  var coalesced1 = ((input.user == null ? undefined : input.user.age) != null ? (input.user == null ? undefined : input.user.age) : input.age);
+  output.age = Number(coalesced1);
+  if (output.age !== output.age) throw new TypeError("coalesced1: not a number");
+  return output;
+};
+"#
+        );
+    }
+
+    #[test]
+    fn test_freeze() {
+        let ir = [IR::PushObj, key("tags"), IR::Copy, IR::PopKey, IR::PopObj];
+        assert_eq!(
+            JSCodegen::new().freeze().generate(&ir, "input", "output"),
+            r#"function(input) {
+  let output;
+  output = {};
+  output.tags = input.tags;
+  function freeze0(value) {
+    if (Array.isArray(value)) return Object.freeze(value.map(freeze0));
+    if (typeof value === "object" && value !== null) {
+      const entries = Object.entries(value).map(([key, item]) => [key, freeze0(item)]);
+      return Object.freeze(Object.fromEntries(entries));
+    }
+    return value;
+  }
+  return freeze0(output);
+}
+"#
+        );
+        // leaving the input as it was
+        let ir = [IR::PushObj, key("meta"), IR::Copy, IR::PopKey, IR::PopObj];
+        for dialect in [Dialect::Modern, Dialect::Es5] {
+            let code = JSCodegen::new()
+                .freeze()
+                .dialect(dialect)
+                .generate(&ir, "input", "output");
+            let check = format!(
+                "(input) => {{ const output = ({})(input); return [Object.isFrozen(input.meta), Object.isFrozen(output.meta), output]; }}",
+                code
+            );
+            let input = serde_json::json!({"meta": {"a": [1]}});
+            let Some(result) = run(&check, &input) else {
+                return;
+            };
+            assert_eq!(
+                result,
+                serde_json::json!([false, true, {"meta": {"a": [1]}}])
+            );
+        }
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let target = crate::schema!({
+            "type": "object",
+            "properties": {
+                "userId": {"type": "number"},
+                "meta": {"type": "object", "properties": {"n": {"type": "number"}}}
+            },
+            "additionalProperties": true
+        });
+        let ir = [
+            IR::PushObj,
+            IR::Rename(name("user_id"), name("userId")),
+            IR::Copy,
+            IR::PopKey,
+            key("meta"),
+            IR::PushObj,
+            key("n"),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        // only objects the target leaves open pass properties through
+        assert_eq!(
+            JSCodegen::new()
+                .passthrough(&target)
+                .generate(&ir, "input", "output"),
+            "function(input) {
+  let output;
+  output = { ...input };
+  delete output.user_id;
+  output.userId = input.user_id;
+  output.meta = {};
+  output.meta.n = Number(input.meta.n);
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {"tags": {"type": "array", "items": {"type": "string"}}}
+        });
+        let ir = [
+            IR::PushObj,
+            key("tags"),
+            IR::PushArr,
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopArr,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let mut js = JSCodegen::new();
+        js.generate(&ir, "input", "output");
+        assert_eq!(
+            js.manifest(&ir, &source),
+            serde_json::json!({"statements": [
+                {"lines": [3, 3], "index": 0, "op": "PushObj", "source": "", "target": ""},
+                {
+                    "lines": [4, 5],
+                    "index": 2,
+                    "op": "PushArr",
+                    "source": "/properties/tags/items",
+                    "target": "/properties/tags/items"
+                },
+                {
+                    "lines": [6, 6],
+                    "index": 3,
+                    "op": {"G2G": ["String", "Num"]},
+                    "source": "/properties/tags/items",
+                    "target": "/properties/tags/items"
+                },
+                {
+                    "lines": [7, 7],
+                    "index": 4,
+                    "op": "PopArr",
+                    "source": "/properties/tags/items",
+                    "target": "/properties/tags/items"
+                }
+            ]})
+        );
+    }
+
+    #[test]
+    fn test_helpers() {
+        let address = [
+            IR::PushObj,
+            key("street"),
+            IR::Copy,
+            IR::PopKey,
+            key("zip"),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let mut ir = vec![IR::PushObj, key("home")];
+        ir.extend(address.iter().cloned());
+        ir.extend([IR::PopKey, key("work")]);
+        ir.extend(address.iter().cloned());
+        ir.extend([IR::PopKey, IR::PopObj]);
+        assert_eq!(
+            JSCodegen::new().helpers().generate(&ir, "input", "output"),
+            "function(input) {
+  function convert0(value) {
+    let result;
+    result = {};
+    result.street = value.street;
+    result.zip = Number(value.zip);
+    return result;
+  }
+  let output;
+  output = {};
+  output.home = convert0(input.home);
+  output.work = convert0(input.work);
+  return output;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_bigint() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "string"},
+                "micros": {"type": "integer", "format": "int64"},
+                "count": {"type": "string"}
+            }
+        });
+        let target = crate::schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "integer", "format": "int64"},
+                "nanos": {"type": "integer", "format": "int64"},
+                "count": {"type": "number"}
+            }
+        });
+        let ir = [
+            IR::PushObj,
+            key("count"),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopKey,
+            key("id"),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopKey,
+            IR::Rename(name("micros"), name("nanos")),
+            IR::Scale {
+                factor: Real(1000.0),
+                offset: Real(0.0),
+            },
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        // only 64-bit integers are `BigInt`s
+        assert_eq!(
+            JSCodegen::new()
+                .bigint(&source, &target)
+                .generate(&ir, "input", "output"),
+            "function(input) {
+  let output;
+  output = {};
+  output.count = Number(input.count);
+  output.id = BigInt(input.id);
+  output.nanos = BigInt(input.micros) * 1000n;
+  return output;
+}
+"
+        );
+        // which plain `JSON.stringify` can't write, but the replacer the
+        // setter suggests can
+        let code = JSCodegen::new()
+            .bigint(&source, &target)
+            .generate(&ir, "input", "output");
+        let script = format!(
+            "const output = ({})({{count: '1.5', id: '9007199254740993', micros: 2}});
+            try {{ JSON.stringify(output); process.exit(1); }} catch (e) {{}}
+            console.log(JSON.stringify(output, (k, v) => typeof v === 'bigint' ? v.toString() : v));",
+            code
+        );
+        if let Ok(out) = std::process::Command::new("node")
+            .args(["-e", &script])
+            .output()
+        {
+            assert!(out.status.success());
+            assert_eq!(
+                serde_json::from_slice::<serde_json::Value>(&out.stdout).unwrap(),
+                serde_json::json!({"count": 1.5, "id": "9007199254740993", "nanos": "2000"})
+            );
+        }
+    }
+
+    #[test]
+    fn test_provenance() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {
+                "user": {
+                    "type": "object",
+                    "properties": {"created_at": {"type": "string", "format": "date-time"}}
+                },
+                "tags": {"type": "array", "items": {"type": "string"}}
+            }
+        });
+        let target = crate::schema!({
+            "type": "object",
+            "properties": {
+                "createdAt": {"type": "number", "unit": "s"},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            }
+        });
+        let ir = [
+            IR::PushObj,
+            IR::Nest(Arc::new("createdAt".to_string())),
+            IR::Extr(Arc::new("user".to_string())),
+            IR::Extr(Arc::new("created_at".to_string())),
+            IR::DateConvert {
+                from_format: DateFormat::Iso,
+                to_format: DateFormat::EpochSeconds,
+            },
+            IR::PopKey,
+            key("tags"),
+            IR::PushArr,
+            IR::Copy,
+            IR::PopArr,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let code = JSCodegen::new()
+            .provenance(&source, &target)
+            .generate(&ir, "input", "output");
+        assert_eq!(
+            code,
+            "function(input) {
+  let output;
+  output = {};
+  // user.created_at (string,date-time) -> createdAt (number,s)
+  output.createdAt = Math.floor(new Date(input.user.created_at).getTime() / 1000);
+  output.tags = [];
+  for (let idx0 = 0; idx0 < input.tags.length; idx0++) {
+    // tags[] (string) -> tags[] (string)
+    output.tags[idx0] = input.tags[idx0];
+  }
+  return output;
+}
+"
+        );
+    }
+}
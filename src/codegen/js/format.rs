@@ -0,0 +1,180 @@
+//! Layout of the generated code, so that it's written the way the formatter
+//! of the project it's generated into would write it, and isn't rewritten
+//! (and diffed again) when that formatter next runs.
+
+use derive_builder::Builder;
+
+/// What a level of indentation is made of.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Indent {
+    /// The given number of spaces.
+    Spaces(usize),
+    /// A tab.
+    Tab,
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Indent::Spaces(2)
+    }
+}
+
+/// The quotes string literals are written in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Quotes {
+    #[default]
+    Double,
+    Single,
+}
+
+/// How the generated code is laid out. The default is how the generator
+/// writes it in the first place: two spaces a level, semicolons, and double
+/// quotes.
+#[derive(Builder, Clone, Debug, PartialEq, Eq)]
+#[builder(default)]
+pub struct Formatting {
+    pub indent: Indent,
+    /// Whether statements end with semicolons.
+    pub semicolons: bool,
+    pub quotes: Quotes,
+}
+
+impl Default for Formatting {
+    fn default() -> Self {
+        Self {
+            indent: Indent::default(),
+            semicolons: true,
+            quotes: Quotes::default(),
+        }
+    }
+}
+
+impl Formatting {
+    /// `code`, as the generator writes it, laid out this way instead.
+    /// Comments are only indented.
+    pub fn apply(&self, code: &str) -> String {
+        if *self == Self::default() {
+            return code.to_string();
+        }
+        let unit = match self.indent {
+            Indent::Spaces(width) => " ".repeat(width),
+            Indent::Tab => "\t".to_string(),
+        };
+        let mut out = String::new();
+        let mut comment = false;
+        for line in code.split_inclusive('\n') {
+            let (line, newline) = match line.strip_suffix('\n') {
+                Some(line) => (line, "\n"),
+                None => (line, ""),
+            };
+            let content = line.trim_start_matches(' ');
+            let spaces = line.len() - content.len();
+            // what's left over is the space before the `*` of a doc comment
+            out.push_str(&unit.repeat(spaces / 2));
+            out.push_str(&" ".repeat(spaces % 2));
+
+            if comment || content.starts_with("/*") || content.starts_with("//") {
+                if content.starts_with("/*") {
+                    comment = true;
+                }
+                if comment && content.contains("*/") {
+                    comment = false;
+                }
+                out.push_str(content);
+            } else {
+                let mut content = match self.quotes {
+                    Quotes::Double => content.to_string(),
+                    Quotes::Single => single_quoted(content),
+                };
+                if !self.semicolons {
+                    if let Some(statement) = content.strip_suffix(';') {
+                        content = statement.to_string();
+                    }
+                    // which would otherwise carry on the statement before
+                    if content.starts_with(['(', '[', '`']) {
+                        content.insert(0, ';');
+                    }
+                }
+                out.push_str(&content);
+            }
+            out.push_str(newline);
+        }
+        out
+    }
+}
+
+/// `line` with its double-quoted string literals single-quoted instead,
+/// leaving template literals as they are.
+fn single_quoted(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push('\'');
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('"') => out.push('"'),
+                            Some(escaped) => {
+                                out.push('\\');
+                                out.push(escaped);
+                            }
+                            None => out.push('\\'),
+                        },
+                        '\'' => out.push_str("\\'"),
+                        c => out.push(c),
+                    }
+                }
+                out.push('\'');
+            }
+            '`' => {
+                out.push('`');
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '`' => break,
+                        '\\' => out.extend(chars.next()),
+                        _ => {}
+                    }
+                }
+            }
+            '\'' => {
+                // already single-quoted
+                out.push('\'');
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\'' => break,
+                        '\\' => out.extend(chars.next()),
+                        _ => {}
+                    }
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let code = "/**\n * @param {Source} input\n */\nfunction(input) {\n  \"use strict\";\n  if (!(\"n\" in input)) throw new TypeError(`input.n: it's \"missing\"`);\n  return { n: input[\"it's\"] };\n}\n";
+        let formatting = FormattingBuilder::default()
+            .indent(Indent::Tab)
+            .semicolons(false)
+            .quotes(Quotes::Single)
+            .build()
+            .unwrap();
+        assert_eq!(
+            formatting.apply(code),
+            "/**\n * @param {Source} input\n */\nfunction(input) {\n\t'use strict'\n\tif (!('n' in input)) throw new TypeError(`input.n: it's \"missing\"`)\n\treturn { n: input['it\\'s'] }\n}\n"
+        );
+        assert_eq!(Formatting::default().apply(code), code);
+    }
+}
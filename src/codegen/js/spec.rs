@@ -0,0 +1,222 @@
+//! Test files for generated transforms, running each on example inputs and
+//! checking what comes out has the shape of the target schema, so that a
+//! transform arrives with tests of its own.
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    codegen::{Codegen, CodegenOptions, ReturnStyle},
+    ir::IR,
+    schema::{Ground, Schema},
+};
+
+use super::{FunctionStyle, JSCodegen, ModuleFormat};
+
+/// The test runner the tests are written for.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Framework {
+    /// Jest, which provides `describe`, `test` and `expect` as globals.
+    #[default]
+    Jest,
+    /// Vitest, which has them imported.
+    Vitest,
+}
+
+/// An example value of `schema`, with every optional property of its
+/// objects or just the required ones. Strings of a `format` or encoding
+/// look like one.
+pub fn example(schema: &Schema, optional: bool) -> Value {
+    value(schema, None, optional)
+}
+
+fn value(schema: &Schema, format: Option<&str>, optional: bool) -> Value {
+    match schema {
+        Schema::Ground(Ground::Num) => json!(1),
+        Schema::Ground(Ground::Bool) => json!(true),
+        Schema::Ground(Ground::String) => json!(match format {
+            Some("date-time") => "2024-01-01T00:00:00.000Z",
+            Some("date") => "2024-01-01",
+            Some("time") => "00:00:00",
+            Some("email") => "user@example.com",
+            Some("uri" | "url") => "https://example.com/",
+            Some("uuid") => "00000000-0000-0000-0000-000000000000",
+            Some("base64") => "ZXhhbXBsZQ==",
+            _ => "example",
+        }),
+        // a schema's references to itself end the example where they can
+        Schema::Arr(item) if matches!(**item, Schema::Var(_)) => json!([]),
+        Schema::Arr(item) => json!([value(item, None, optional)]),
+        Schema::Obj {
+            props, required, ..
+        } => {
+            let mut object = Map::new();
+            for (key, prop) in props {
+                let recursive = matches!(**prop, Schema::Var(_));
+                if (!optional || recursive) && !required.contains(key) {
+                    continue;
+                }
+                object.insert(key.to_string(), value(prop, None, optional));
+            }
+            Value::Object(object)
+        }
+        Schema::Annotated(inner, annotations) => {
+            let values = annotations.values.as_deref().unwrap_or_default();
+            match annotations.default.as_ref().or(values.first()) {
+                Some(literal) => literal.0.clone(),
+                None => {
+                    let format = annotations
+                        .format
+                        .as_ref()
+                        .or(annotations.encoding.as_ref());
+                    value(inner, format.map(|f| f.as_str()), optional)
+                }
+            }
+        }
+        Schema::Union(branches) => branches
+            .first()
+            .map_or(Value::Null, |branch| value(branch, format, optional)),
+        Schema::Rec(_, body) => value(body, format, optional),
+        Schema::Ground(Ground::Null) | Schema::Var(_) | Schema::True | Schema::False => Value::Null,
+    }
+}
+
+/// A test file for the transform generated with `options`, imported as
+/// `module` from `path`, running it on `examples`
+/// and on examples made up from `source` (with every optional property and
+/// with none), and checking each output has the shape of `target`. A bare
+/// function expression is required as if it were a CommonJS module, so
+/// should be written to one by whatever wraps it.
+pub fn generate(
+    source: &Schema,
+    target: &Schema,
+    examples: &[Value],
+    options: &CodegenOptions,
+    module: ModuleFormat,
+    path: &str,
+    framework: Framework,
+) -> String {
+    let name = &options.name;
+    let path = Value::from(path);
+    let mut out = String::new();
+    if framework == Framework::Vitest {
+        out.push_str("import { describe, expect, test } from \"vitest\";\n");
+    }
+    out.push_str(&match module {
+        ModuleFormat::ExportDefault => format!("import {} from {};\n", name, path),
+        ModuleFormat::ExportNamed => format!("import {{ {} }} from {};\n", name, path),
+        ModuleFormat::Expression | ModuleFormat::CommonJs => {
+            format!("const {} = require({});\n", name, path)
+        }
+    });
+
+    // the checks validation makes of the input, made of the output instead
+    let conforms = CodegenOptions {
+        name: "conforms".to_string(),
+        input: "output".to_string(),
+        output: "checked".to_string(),
+        returns: ReturnStyle::Literal,
+    };
+    let conforms = JSCodegen::new()
+        .validate(target)
+        .style(FunctionStyle::Declaration)
+        .generate_with(&[IR::Copy], &conforms);
+    out.push('\n');
+    out.push_str(&conforms);
+
+    let mut cases = vec![];
+    for (i, example) in examples.iter().enumerate() {
+        cases.push((format!("example {}", i + 1), example.clone()));
+    }
+    cases.push(("every property".to_string(), example(source, true)));
+    cases.push(("required properties".to_string(), example(source, false)));
+    cases.dedup_by(|a, b| a.1 == b.1);
+
+    out.push('\n');
+    out.push_str(&format!(
+        "describe({}, () => {{\n",
+        Value::from(name.as_str())
+    ));
+    for (case, input) in cases {
+        out.push_str(&format!(
+            "  test({}, () => {{\n    expect(() => conforms({}({}))).not.toThrow();\n  }});\n",
+            Value::from(format!("converts {} to the target schema", case)),
+            name,
+            input
+        ));
+    }
+    out.push_str("});\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example() {
+        let schema = crate::schema!({
+            "type": "object",
+            "properties": {
+                "at": {"type": "string", "format": "date-time"},
+                "kind": {"type": "string", "enum": ["a", "b"]},
+                "tags": {"type": "array", "items": {"type": "number"}}
+            },
+            "required": ["at"]
+        });
+        assert_eq!(
+            example(&schema, true),
+            json!({"at": "2024-01-01T00:00:00.000Z", "kind": "a", "tags": [1]})
+        );
+        assert_eq!(
+            example(&schema, false),
+            json!({"at": "2024-01-01T00:00:00.000Z"})
+        );
+    }
+
+    #[test]
+    fn test_generate() {
+        let source = crate::schema!({
+            "type": "object",
+            "properties": {"n": {"type": "string"}}
+        });
+        let target = crate::schema!({
+            "type": "object",
+            "properties": {"n": {"type": "number"}},
+            "required": ["n"]
+        });
+        let spec = generate(
+            &source,
+            &target,
+            &[json!({"n": "2"})],
+            &CodegenOptions::default(),
+            ModuleFormat::ExportDefault,
+            "./transform.js",
+            Framework::Vitest,
+        );
+        assert_eq!(
+            spec,
+            r#"import { describe, expect, test } from "vitest";
+import transform from "./transform.js";
+
+function conforms(output) {
+  if (!(typeof output === "object" && output !== null && !Array.isArray(output))) throw new TypeError(`output: expected object {n}`);
+  if (!("n" in output)) throw new TypeError(`output.n: required but missing`);
+  if (!(typeof output.n === "number")) throw new TypeError(`output.n: expected number`);
+  return output;
+}
+
+describe("transform", () => {
+  test("converts example 1 to the target schema", () => {
+    expect(() => conforms(transform({"n":"2"}))).not.toThrow();
+  });
+  test("converts every property to the target schema", () => {
+    expect(() => conforms(transform({"n":"example"}))).not.toThrow();
+  });
+  test("converts required properties to the target schema", () => {
+    expect(() => conforms(transform({}))).not.toThrow();
+  });
+});
+"#
+        );
+    }
+}
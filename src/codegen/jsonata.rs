@@ -0,0 +1,536 @@
+//! JSONata generation: an expression transforming its input the way the
+//! generated JavaScript does, for platforms which run JSONata natively.
+//!
+//! Objects are built with object constructors, which leave out properties
+//! whose values are undefined just as the JavaScript does, arrays are
+//! mapped with `$map`, and ground values are coerced with JSONata's own
+//! functions. Those differ from JavaScript's at the edges: `$string` writes
+//! objects and arrays as JSON and leaves undefined undefined, `$number`
+//! fails on strings which aren't numbers instead of making `NaN`, and
+//! `$boolean` takes empty arrays and objects to be false. Wrapping an
+//! array spreads it, since array constructors flatten the arrays in them.
+//!
+//! Paths with no JSONata equivalent, like those converting the case of
+//! names or grouping and zipping arrays, report the first instruction
+//! standing in the way.
+
+use std::{fmt::Display, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    ir::{scope_end, text, Case, Coding, DateFormat, SortOrder, IR},
+    schema::{Ground, Schema},
+};
+
+/// Error generating a JSONata expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JsonataErr {
+    /// The instruction at this index has no JSONata equivalent.
+    Unsupported(usize, IR),
+}
+
+impl Display for JsonataErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(i, op) => write!(
+                f,
+                "instruction {} (`{}`) has no JSONata equivalent",
+                i,
+                text::print(std::slice::from_ref(op)).trim_end()
+            ),
+        }
+    }
+}
+
+/// The output a scope writes, as far as it's been written.
+enum Output {
+    Unwritten,
+    /// The value of an expression.
+    Expr(String),
+    /// An object with these properties, merged over the value of an
+    /// expression if there is one.
+    Obj(Option<String>, Vec<(Arc<String>, String)>),
+}
+
+impl Output {
+    /// The output as an expression, if it's written.
+    fn expr(self) -> Option<String> {
+        match self {
+            Output::Unwritten => None,
+            Output::Expr(expr) => Some(expr),
+            Output::Obj(base, props) => {
+                let props: Vec<_> = props
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", json(key.as_str()), value))
+                    .collect();
+                let object = format!("{{{}}}", props.join(", "));
+                Some(match base {
+                    Some(base) => format!("$merge([{}, {}])", base, object),
+                    None => object,
+                })
+            }
+        }
+    }
+}
+
+/// `value` as JSON, which JSONata reads as it is.
+fn json(value: impl Into<Value>) -> String {
+    value.into().to_string()
+}
+
+/// `key` as a name in a path, quoted with backticks unless it's an
+/// identifier.
+fn name(key: &str) -> String {
+    let mut chars = key.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if identifier {
+        key.to_string()
+    } else {
+        format!("`{}`", key)
+    }
+}
+
+/// `expr`, parenthesized unless it's a single term: if it has operators
+/// outside any brackets and literals.
+fn group(expr: &str) -> String {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut loose = false;
+    for c in expr.chars() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '`' | '\'' | '"') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, ' ') if depth == 0 => loose = true,
+            _ => {}
+        }
+    }
+    if loose {
+        format!("({})", expr)
+    } else {
+        expr.to_string()
+    }
+}
+
+/// The value at `key` of the value of `expr`.
+fn sub(expr: &str, key: &str) -> String {
+    format!("{}.{}", group(expr), name(key))
+}
+
+/// The value at `path` of the value of `expr`.
+fn at(expr: &str, path: &[Arc<String>]) -> String {
+    path.iter()
+        .fold(expr.to_string(), |expr, key| sub(&expr, key))
+}
+
+/// `expr` if it's defined and not null, and undefined otherwise.
+fn present(expr: &str) -> String {
+    format!("{} != null ? {}", group(expr), group(expr))
+}
+
+/// A condition testing whether the value of `input` has the shape of
+/// `schema`, for dispatching on union branches. Objects are told apart by
+/// their required properties.
+fn test(input: &str, schema: &Schema) -> String {
+    let ty = |ty: &str| format!("$type({}) = \"{}\"", input, ty);
+    match schema {
+        Schema::Ground(Ground::Num) => ty("number"),
+        Schema::Ground(Ground::Bool) => ty("boolean"),
+        Schema::Ground(Ground::String) => ty("string"),
+        Schema::Ground(Ground::Null) => ty("null"),
+        Schema::Arr(_) => ty("array"),
+        Schema::Obj { required, .. } => {
+            let mut tests = vec![ty("object")];
+            for key in required {
+                tests.push(format!("$exists({})", sub(input, key)));
+            }
+            tests.join(" and ")
+        }
+        Schema::Union(branches) => branches
+            .iter()
+            .map(|branch| format!("({})", test(input, branch)))
+            .collect::<Vec<_>>()
+            .join(" or "),
+        Schema::Rec(_, body) | Schema::Annotated(body, _) => test(input, body),
+        Schema::True | Schema::Var(_) => "true".to_string(),
+        Schema::False => "false".to_string(),
+    }
+}
+
+/// The point in time the value of `input`, written in `format`, is, as
+/// milliseconds since the epoch.
+fn millis(input: &str, format: DateFormat) -> String {
+    match format {
+        DateFormat::Iso => format!("$toMillis({})", input),
+        DateFormat::EpochSeconds => format!("$number({}) * 1000", group(input)),
+        DateFormat::EpochMillis => format!("$number({})", input),
+    }
+}
+
+/// Generates JSONata expressions from IR paths.
+struct Generator {
+    /// How many arrays the instructions generated are inside, naming the
+    /// variables their items are bound to.
+    depth: usize,
+}
+
+impl Generator {
+    /// The expression building what `ir` writes from the value of `input`,
+    /// if it writes anything. `base` is the index of `ir`'s first
+    /// instruction in the whole path.
+    fn scope(
+        &mut self,
+        ir: &[IR],
+        base: usize,
+        input: String,
+    ) -> Result<Option<String>, JsonataErr> {
+        let mut output = Output::Unwritten;
+        self.run(ir, base, input, &mut output)?;
+        Ok(output.expr())
+    }
+
+    /// Run the instructions of a scope `ir` on the value of `input`,
+    /// writing to `output`.
+    fn run(
+        &mut self,
+        ir: &[IR],
+        base: usize,
+        mut input: String,
+        output: &mut Output,
+    ) -> Result<(), JsonataErr> {
+        let mut i = 0;
+        while i < ir.len() {
+            let op = &ir[i];
+            let unsupported = || JsonataErr::Unsupported(base + i, op.clone());
+            if op.closer().is_some() {
+                let end = scope_end(&ir[i..]).map_or(ir.len(), |end| i + end);
+                let body = &ir[i + 1..end.min(ir.len())];
+                let inner = base + i + 1;
+                match op {
+                    IR::PushObj => {
+                        *output = Output::Obj(None, vec![]);
+                        self.run(body, inner, input.clone(), output)?;
+                    }
+                    IR::Merge => {
+                        let written = std::mem::replace(output, Output::Unwritten);
+                        *output = Output::Obj(written.expr(), vec![]);
+                        self.run(body, inner, input.clone(), output)?;
+                    }
+                    IR::PushKey(_) | IR::Rename(..) | IR::Nest(_) => {
+                        let (value, key) = match op {
+                            IR::PushKey(key) => (self.scope(body, inner, sub(&input, key))?, key),
+                            IR::Rename(from, to) => {
+                                (self.scope(body, inner, sub(&input, from))?, to)
+                            }
+                            IR::Nest(key) => (self.scope(body, inner, input.clone())?, key),
+                            _ => unreachable!("only key scopes write properties"),
+                        };
+                        // properties can only be written into objects
+                        if let (Output::Obj(_, props), Some(value)) = (&mut *output, value) {
+                            props.retain(|(written, _)| written != key);
+                            props.push((key.clone(), value));
+                        }
+                    }
+                    // functions of undefined values are undefined, leaving
+                    // the properties they'd write out
+                    IR::PushOpt => self.run(body, inner, input.clone(), output)?,
+                    IR::PushArr => {
+                        let item = format!("$item{}", self.depth);
+                        self.depth += 1;
+                        let mapped = self.scope(body, inner, item.clone());
+                        self.depth -= 1;
+                        let mapped = mapped?.unwrap_or_else(|| "null".to_string());
+                        *output = Output::Expr(if mapped == item {
+                            format!("[{}]", input)
+                        } else {
+                            format!("[$map({}, function({}) {{ {} }})]", input, item, mapped)
+                        });
+                    }
+                    IR::PushUnion => {
+                        let mut cases = vec![];
+                        let mut j = 0;
+                        while j < body.len() {
+                            let end = scope_end(&body[j..]).map_or(body.len(), |end| j + end);
+                            if let IR::Case(branch) = &body[j] {
+                                let case = &body[j + 1..end.min(body.len())];
+                                let written = self.scope(case, inner + j + 1, input.clone())?;
+                                cases.push((test(&input, branch), written));
+                            }
+                            j = end + 1;
+                        }
+                        let before = std::mem::replace(output, Output::Unwritten).expr();
+                        let dispatch =
+                            cases
+                                .into_iter()
+                                .rev()
+                                .fold(before, |rest, (test, written)| {
+                                    let written = written.unwrap_or_else(|| "null".to_string());
+                                    Some(match rest {
+                                        Some(rest) => {
+                                            format!("{} ? {} : ({})", test, group(&written), rest)
+                                        }
+                                        None => format!("{} ? {}", test, group(&written)),
+                                    })
+                                });
+                        *output = dispatch.map_or(Output::Unwritten, Output::Expr);
+                    }
+                    _ => return Err(unsupported()),
+                }
+                i = end + 1;
+                continue;
+            }
+            match op {
+                IR::Copy => *output = Output::Expr(input.clone()),
+                IR::G2G(_, to) => {
+                    *output = Output::Expr(match to {
+                        Ground::String => format!("$string({})", input),
+                        Ground::Num => format!("$number({})", input),
+                        Ground::Bool => format!("$boolean({})", input),
+                        Ground::Null => "null".to_string(),
+                    })
+                }
+                IR::Pipe => {
+                    input = std::mem::replace(output, Output::Unwritten)
+                        .expr()
+                        .map_or("undefined".to_string(), |expr| group(&expr));
+                }
+                IR::Wrap => {
+                    // the rest of the scope writes inside the array
+                    let wrapped = self.scope(&ir[i + 1..], base + i + 1, input.clone())?;
+                    *output = Output::Expr(format!("[{}]", wrapped.unwrap_or_default()));
+                    return Ok(());
+                }
+                IR::Extr(key) => input = sub(&input, key),
+                IR::Coalesce(paths) => {
+                    let mut paths = paths.iter().map(|path| at(&input, path)).rev();
+                    let last = paths.next().unwrap_or_else(|| "undefined".to_string());
+                    input = group(&paths.fold(last, |rest, path| {
+                        format!("{} : ({})", present(&path), rest)
+                    }));
+                }
+                IR::First => input = format!("{}[0]", group(&input)),
+                IR::Flatten(depth) => {
+                    for _ in 0..*depth {
+                        input = format!("$reduce({}, $append, [])", input);
+                    }
+                }
+                IR::Slice(start, end) => {
+                    let end = match end {
+                        Some(end) => format!(" and $i < {}", end),
+                        None => String::new(),
+                    };
+                    input = format!(
+                        "[$filter({}, function($v, $i) {{ $i >= {}{} }})]",
+                        input, start, end
+                    );
+                }
+                IR::Sort(path, order) => {
+                    let (a, b) = (at("$a", path), at("$b", path));
+                    let compare = match order {
+                        SortOrder::Ascending => format!("{} > {}", a, b),
+                        SortOrder::Descending => format!("{} < {}", a, b),
+                    };
+                    input = format!("$sort({}, function($a, $b) {{ {} }})", input, compare);
+                }
+                IR::Dedupe(None) => input = format!("$distinct({})", input),
+                IR::Gather(paths) => {
+                    let paths: Vec<_> = paths.iter().map(|path| at(&input, path)).collect();
+                    input = format!("[{}]", paths.join(", "));
+                }
+                IR::Scale { factor, offset } => {
+                    *output = Output::Expr(format!(
+                        "$number({}) * {} + {}",
+                        input,
+                        json(factor.0),
+                        json(offset.0)
+                    ))
+                }
+                IR::DateConvert {
+                    from_format,
+                    to_format,
+                } => {
+                    let time = millis(&input, *from_format);
+                    *output = Output::Expr(match to_format {
+                        DateFormat::Iso => format!("$fromMillis({})", time),
+                        DateFormat::EpochSeconds => format!("$floor({} / 1000)", group(&time)),
+                        DateFormat::EpochMillis => time,
+                    })
+                }
+                IR::Base64(coding) => {
+                    let function = match coding {
+                        Coding::Encode => "$base64encode",
+                        Coding::Decode => "$base64decode",
+                    };
+                    *output = Output::Expr(format!("{}($string({}))", function, input))
+                }
+                IR::RegexExtract(pattern, group) => {
+                    let matched = format!(
+                        "$match($string({}), /{}/)[0]",
+                        input,
+                        pattern.replace('/', "\\/")
+                    );
+                    *output = Output::Expr(match group {
+                        0 => format!("{}.match", matched),
+                        group => format!("{}.groups[{}]", matched, group - 1),
+                    })
+                }
+                IR::CaseTransform(case @ (Case::Upper | Case::Lower)) => {
+                    let function = match case {
+                        Case::Upper => "$uppercase",
+                        _ => "$lowercase",
+                    };
+                    *output = Output::Expr(format!("{}($string({}))", function, input))
+                }
+                IR::MapValues(table) => {
+                    // scalars compare by value, unlike arrays and objects
+                    if table
+                        .keys()
+                        .any(|from| from.0.is_array() || from.0.is_object())
+                    {
+                        return Err(unsupported());
+                    }
+                    let lookup = table.iter().rev().fold(None, |rest, (from, to)| {
+                        let test = format!("{} = {}", group(&input), from.0);
+                        Some(match rest {
+                            Some(rest) => format!("{} ? {} : ({})", test, to.0, rest),
+                            None => format!("{} ? {}", test, to.0),
+                        })
+                    });
+                    *output = lookup.map_or(Output::Unwritten, Output::Expr);
+                }
+                IR::Concat(paths, sep) => {
+                    let paths: Vec<_> = paths.iter().map(|path| at(&input, path)).collect();
+                    *output = Output::Expr(format!(
+                        "$join($map($filter([{}], function($v) {{ $v != null }}), $string), {})",
+                        paths.join(", "),
+                        json(sep.as_str())
+                    ))
+                }
+                IR::Join(sep) => {
+                    *output = Output::Expr(format!(
+                        "$type({}) = \"array\" ? $join($map({}, $string), {})",
+                        input,
+                        input,
+                        json(sep.as_str())
+                    ))
+                }
+                IR::Const(value) => *output = Output::Expr(value.0.to_string()),
+                // instructions closing scopes which weren't opened do
+                // nothing
+                op if op.is_closer() => {}
+                _ => return Err(unsupported()),
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+/// A JSONata expression transforming its input according to `ir`, if it
+/// can be written.
+pub fn expression(ir: &[IR]) -> Result<String, JsonataErr> {
+    let output = Generator { depth: 0 }.scope(ir, 0, "$".to_string())?;
+    Ok(output.unwrap_or_else(|| "null".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        schema::{Literal, Real},
+        test_util::key,
+    };
+
+    #[test]
+    fn test_objects() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("age")),
+            IR::PushOpt,
+            IR::Scale {
+                factor: Real(12.0),
+                offset: Real(0.0),
+            },
+            IR::PopOpt,
+            IR::PopKey,
+            IR::Nest(key("meta")),
+            IR::PushObj,
+            IR::PushKey(key("display-name")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PushKey(key("kind")),
+            IR::Const(Literal(json!("user"))),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            expression(&ir),
+            Ok(concat!(
+                r#"{"id": $string($.user_id), "age": $number($.age) * 12.0 + 0.0, "#,
+                r#""meta": {"display-name": $.`display-name`, "kind": "user"}}"#
+            )
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn test_arrays_and_unions() {
+        let ir = [
+            IR::PushArr,
+            IR::PushUnion,
+            IR::Case(Arc::new(Schema::string())),
+            IR::Wrap,
+            IR::Copy,
+            IR::PopCase,
+            IR::Case(Arc::new(Schema::Arr(Arc::new(Schema::string())))),
+            IR::PushArr,
+            IR::CaseTransform(Case::Upper),
+            IR::PopArr,
+            IR::PopCase,
+            IR::PopUnion,
+            IR::PopArr,
+        ];
+        assert_eq!(
+            expression(&ir),
+            Ok(concat!(
+                r#"[$map($, function($item0) { $type($item0) = "string" ? [$item0] : "#,
+                r#"($type($item0) = "array" ? [$map($item0, function($item1) { "#,
+                r#"$uppercase($string($item1)) })]) })]"#
+            )
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let ir = [
+            IR::PushObj,
+            IR::PushRest(vec![]),
+            IR::Copy,
+            IR::PopRest,
+            IR::PopObj,
+        ];
+        let err = expression(&ir).unwrap_err();
+        assert_eq!(err, JsonataErr::Unsupported(1, IR::PushRest(vec![])));
+        assert_eq!(
+            err.to_string(),
+            "instruction 1 (`PushRest []`) has no JSONata equivalent"
+        );
+    }
+}
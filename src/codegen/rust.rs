@@ -0,0 +1,688 @@
+//! Rust generation: a function transforming a `serde_json::Value`, for
+//! compiling transforms straight into Rust services. The generated code
+//! runs the way the generated JavaScript does, on a copy of the
+//! [`crate::runtime`] module which comes with it.
+//!
+//! Values which may be `undefined` are `Option`s, and what the function
+//! writes is built up in `Option<Value>`s, so that `undefined` properties
+//! are left out and `undefined` items are written as `null`.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::{
+    ir::{Case, Coding, DateFormat, SortOrder, IR},
+    schema::{Ground, Schema},
+};
+
+use super::Codegen;
+
+pub mod typed;
+
+/// Source of the runtime, bundled into the generated code.
+const RUNTIME: &str = include_str!("../runtime.rs");
+/// Source of the runtime's regular expressions.
+const REGEX: &str = include_str!("../regex.rs");
+
+/// The locations the generated code is reading from and writing to.
+#[derive(Clone)]
+struct Frame {
+    /// An expression for the input, of type `Option<&Value>`.
+    input: String,
+    /// The variable holding the output, of type `Option<Value>`.
+    output: String,
+    /// Lines finishing the scope once its instructions have run.
+    close: Vec<String>,
+}
+
+/// Generates a Rust function from an IR path.
+#[derive(Default)]
+pub struct RustCodegen {
+    /// Counter for fresh variable names.
+    uniq: usize,
+    indent: usize,
+    body: String,
+}
+
+/// `s` as a string literal.
+fn string(s: &str) -> String {
+    format!("{:?}", s)
+}
+
+/// `value` as a `serde_json::json!` expression.
+fn literal(value: &Value) -> String {
+    fn tokens(value: &Value) -> String {
+        match value {
+            Value::String(s) => string(s),
+            Value::Array(items) => {
+                let items: Vec<_> = items.iter().map(tokens).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Value::Object(map) => {
+                let props: Vec<_> = map
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", string(key), tokens(value)))
+                    .collect();
+                format!("{{{}}}", props.join(", "))
+            }
+            value => value.to_string(),
+        }
+    }
+    format!("serde_json::json!({})", tokens(value))
+}
+
+/// `path` as a slice of keys.
+fn path(path: &[Arc<String>]) -> String {
+    let keys: Vec<_> = path.iter().map(|key| string(key)).collect();
+    format!("&[{}]", keys.join(", "))
+}
+
+/// `paths` as a slice of slices of keys.
+fn paths(paths: &[Vec<Arc<String>>]) -> String {
+    let paths: Vec<_> = paths.iter().map(|p| path(p)).collect();
+    format!("&[{}]", paths.join(", "))
+}
+
+/// `pairs` of keys as a slice of tuples.
+fn pairs(pairs: &[(Arc<String>, Arc<String>)]) -> String {
+    let pairs: Vec<_> = pairs
+        .iter()
+        .map(|(a, b)| format!("({}, {})", string(a), string(b)))
+        .collect();
+    format!("&[{}]", pairs.join(", "))
+}
+
+/// `module` without its tests, in a `mod name { ... }` block.
+fn inline(name: &str, module: &str) -> String {
+    let module = module.split("\n#[cfg(test)]\n").next().unwrap_or(module);
+    let mut out = format!("mod {} {{\n", name);
+    for line in module.trim_end().lines() {
+        if !line.is_empty() {
+            out.push_str("    ");
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// The runtime module, as the generated code carries it.
+fn runtime_module() -> String {
+    let regex = format!("pub {}", inline("regex", REGEX));
+    let runtime = RUNTIME.replace("use crate::regex;", regex.trim_end());
+    format!("#[allow(dead_code)]\n{}", inline("runtime", &runtime))
+}
+
+impl RustCodegen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        let name = format!("{}{}", prefix, self.uniq);
+        self.uniq += 1;
+        name
+    }
+
+    fn line(&mut self, line: &str) {
+        // lines closing a block are written a level out
+        if line.starts_with('}') {
+            self.indent -= 1;
+        }
+        for _ in 0..self.indent {
+            self.body.push_str("    ");
+        }
+        self.body.push_str(line);
+        self.body.push('\n');
+        if line.ends_with('{') {
+            self.indent += 1;
+        }
+    }
+
+    /// Finish the scope of `frame`.
+    fn close(&mut self, frame: Frame) {
+        for line in frame.close {
+            self.line(&line);
+        }
+    }
+
+    fn generate_ground_to_ground(to: &Ground, input: &str) -> String {
+        match to {
+            Ground::String => format!("Some(Value::from(runtime::to_string({})))", input),
+            Ground::Num => format!("Some(runtime::number(runtime::to_number({})))", input),
+            Ground::Bool => format!("Some(Value::from(runtime::truthy({})))", input),
+            Ground::Null => "Some(Value::Null)".to_string(),
+        }
+    }
+
+    /// An expression testing whether `input` has the shape of `schema`, for
+    /// dispatching on union branches. Objects are told apart by their
+    /// required properties.
+    fn generate_test(schema: &Schema, input: &str) -> String {
+        match schema {
+            Schema::Ground(Ground::Num) => format!("{}.is_some_and(Value::is_number)", input),
+            Schema::Ground(Ground::Bool) => format!("{}.is_some_and(Value::is_boolean)", input),
+            Schema::Ground(Ground::String) => format!("{}.is_some_and(Value::is_string)", input),
+            Schema::Ground(Ground::Null) => format!("{}.is_some_and(Value::is_null)", input),
+            Schema::Arr(_) => format!("{}.is_some_and(Value::is_array)", input),
+            Schema::Obj { required, .. } if required.is_empty() => {
+                format!("matches!({}, Some(Value::Object(_)))", input)
+            }
+            Schema::Obj { required, .. } => {
+                let keys: Vec<_> = required
+                    .iter()
+                    .map(|key| format!("map.contains_key({})", string(key)))
+                    .collect();
+                format!(
+                    "matches!({}, Some(Value::Object(map)) if {})",
+                    input,
+                    keys.join(" && ")
+                )
+            }
+            Schema::Union(branches) => branches
+                .iter()
+                .map(|branch| format!("({})", Self::generate_test(branch, input)))
+                .collect::<Vec<_>>()
+                .join(" || "),
+            Schema::Rec(_, body) | Schema::Annotated(body, _) => Self::generate_test(body, input),
+            Schema::True | Schema::Var(_) => "true".to_string(),
+            Schema::False => "false".to_string(),
+        }
+    }
+}
+
+impl Codegen for RustCodegen {
+    fn generate(&mut self, ir: &[IR], input: &str, output: &str) -> String {
+        format!(
+            "use serde_json::Value;\n\n{}\n{}",
+            self.generate_function(ir, input, output),
+            runtime_module()
+        )
+    }
+}
+
+impl RustCodegen {
+    /// A function `transform` converting `input` according to `ir` and
+    /// returning the result, built in `output`. It runs on the
+    /// [`runtime_module`].
+    fn generate_function(&mut self, ir: &[IR], input: &str, output: &str) -> String {
+        self.body.clear();
+        self.indent = 1;
+        self.line(&format!("let {} = Some({});", input, input));
+        self.line(&format!("let mut {}: Option<Value> = None;", output));
+
+        let mut frames = vec![Frame {
+            input: input.to_string(),
+            output: output.to_string(),
+            close: vec![],
+        }];
+        // whether each open union dispatch has had a case yet
+        let mut unions = vec![];
+        // the label of each open recursive conversion, and its function
+        let mut recs: Vec<(&Arc<String>, String)> = vec![];
+        for op in ir {
+            let top = frames.last_mut().expect("unbalanced IR");
+            let (input, output) = (top.input.clone(), top.output.clone());
+            // an expression for what `op` writes, if it writes something
+            let value = match op {
+                IR::Copy => Some(format!("{}.cloned()", input)),
+                IR::G2G(_, to) => Some(Self::generate_ground_to_ground(to, &input)),
+                IR::PushObj => Some("Some(Value::Object(serde_json::Map::new()))".to_string()),
+                IR::Merge => {
+                    // copied rather than added to, like the generated
+                    // JavaScript does
+                    Some(format!(
+                        "Some(Value::Object(runtime::spread({}.as_ref())))",
+                        output
+                    ))
+                }
+                IR::PopObj => None,
+                IR::PushKey(_) | IR::Rename(..) | IR::Nest(_) => {
+                    let (from, to) = match op {
+                        IR::PushKey(key) => (Some(key), key),
+                        IR::Rename(from, to) => (Some(from), to),
+                        IR::Nest(key) => (None, key),
+                        _ => unreachable!(),
+                    };
+                    let field = self.fresh("field");
+                    self.line(&format!(
+                        "let mut {} = runtime::field(&{}, {});",
+                        field,
+                        output,
+                        string(to)
+                    ));
+                    frames.push(Frame {
+                        input: match from {
+                            Some(from) => format!("runtime::get({}, {})", input, string(from)),
+                            None => input,
+                        },
+                        output: field.clone(),
+                        close: vec![format!(
+                            "runtime::put(&mut {}, {}, {});",
+                            output,
+                            string(to),
+                            field
+                        )],
+                    });
+                    None
+                }
+                IR::PushOpt => {
+                    self.line(&format!("if {}.is_some() {{", input));
+                    frames.push(Frame {
+                        input,
+                        output: output.clone(),
+                        close: vec!["}".to_string()],
+                    });
+                    None
+                }
+                IR::PushUnion => {
+                    unions.push(false);
+                    None
+                }
+                IR::Case(branch) => {
+                    let test = Self::generate_test(branch, &input);
+                    let started = unions.last_mut().expect("case outside union");
+                    if *started {
+                        self.line(&format!("}} else if {} {{", test));
+                    } else {
+                        self.line(&format!("if {} {{", test));
+                        *started = true;
+                    }
+                    frames.push(Frame {
+                        input,
+                        output: output.clone(),
+                        close: vec![],
+                    });
+                    None
+                }
+                IR::PopUnion => {
+                    if unions.pop() == Some(true) {
+                        self.line("}");
+                    }
+                    None
+                }
+                IR::PushArr => {
+                    let (items, item, written) = (
+                        self.fresh("items"),
+                        self.fresh("item"),
+                        self.fresh("written"),
+                    );
+                    self.line(&format!("let mut {} = vec![];", items));
+                    self.line(&format!("for {} in runtime::items({}) {{", item, input));
+                    self.line(&format!("let mut {}: Option<Value> = None;", written));
+                    frames.push(Frame {
+                        input: format!("Some({})", item),
+                        output: written.clone(),
+                        close: vec![
+                            format!("{}.push({}.unwrap_or(Value::Null));", items, written),
+                            "}".to_string(),
+                            format!("{} = Some(Value::Array({}));", output, items),
+                        ],
+                    });
+                    None
+                }
+                IR::PushRest(named) => {
+                    let (rest, key, value) =
+                        (self.fresh("rest"), self.fresh("key"), self.fresh("value"));
+                    let field = self.fresh("field");
+                    self.line(&format!(
+                        "if let Some(Value::Object({})) = {} {{",
+                        rest, input
+                    ));
+                    self.line(&format!("for ({}, {}) in {} {{", key, value, rest));
+                    if !named.is_empty() {
+                        let named: Vec<_> = named.iter().map(|key| string(key)).collect();
+                        self.line(&format!(
+                            "if [{}].contains(&{}.as_str()) {{",
+                            named.join(", "),
+                            key
+                        ));
+                        self.line("continue;");
+                        self.line("}");
+                    }
+                    self.line(&format!(
+                        "let mut {} = runtime::field(&{}, {});",
+                        field, output, key
+                    ));
+                    frames.push(Frame {
+                        input: format!("Some({})", value),
+                        output: field.clone(),
+                        close: vec![
+                            format!("runtime::put(&mut {}, {}, {});", output, key, field),
+                            "}".to_string(),
+                            "}".to_string(),
+                        ],
+                    });
+                    None
+                }
+                // a function of its own, which calls itself on the parts of
+                // the input of the same schema
+                IR::Rec(label) => {
+                    let (function, value, converted) = (
+                        self.fresh("convert"),
+                        self.fresh("value"),
+                        self.fresh("converted"),
+                    );
+                    self.line(&format!(
+                        "fn {}({}: Option<&Value>) -> Option<Value> {{",
+                        function, value
+                    ));
+                    self.line(&format!("let mut {}: Option<Value> = None;", converted));
+                    frames.push(Frame {
+                        input: value,
+                        output: converted.clone(),
+                        close: vec![
+                            converted,
+                            "}".to_string(),
+                            format!("{} = {}({});", output, function, input),
+                        ],
+                    });
+                    recs.push((label, function));
+                    None
+                }
+                IR::Call(label) => Some(match recs.iter().rev().find(|(open, _)| *open == label) {
+                    Some((_, function)) => format!("{}({})", function, input),
+                    None => "None".to_string(),
+                }),
+                IR::PopKey | IR::PopOpt | IR::PopArr | IR::PopRest | IR::PopCase | IR::PopRec => {
+                    if *op == IR::PopRec {
+                        recs.pop();
+                    }
+                    let frame = frames.pop().expect("unbalanced IR");
+                    self.close(frame);
+                    None
+                }
+                IR::Wrap => {
+                    let wrapped = self.fresh("wrapped");
+                    self.line(&format!("let mut {}: Option<Value> = None;", wrapped));
+                    top.close.insert(
+                        0,
+                        format!(
+                            "{} = Some(Value::Array({}.into_iter().collect()));",
+                            output, wrapped
+                        ),
+                    );
+                    top.output = wrapped;
+                    None
+                }
+                IR::Pipe => {
+                    let piped = self.fresh("piped");
+                    self.line(&format!("let {} = {}.clone();", piped, output));
+                    top.input = format!("{}.as_ref()", piped);
+                    None
+                }
+                IR::Extr(key) => {
+                    top.input = format!("runtime::get({}, {})", input, string(key));
+                    None
+                }
+                IR::First => {
+                    top.input = format!("runtime::items({}).first()", input);
+                    None
+                }
+                IR::Coalesce(keys) => {
+                    let coalesced = self.fresh("coalesced");
+                    self.line(&format!(
+                        "let {} = runtime::coalesce({}, {});",
+                        coalesced,
+                        input,
+                        paths(keys)
+                    ));
+                    top.input = coalesced;
+                    None
+                }
+                IR::Flatten(_)
+                | IR::Slice(..)
+                | IR::Sort(..)
+                | IR::Dedupe(_)
+                | IR::Gather(_)
+                | IR::Zip(_)
+                | IR::Unzip(_)
+                | IR::Inv
+                | IR::GroupBy(_) => {
+                    let (name, call) = match op {
+                        IR::Flatten(depth) => ("flat", format!("flatten({}, {})", input, depth)),
+                        IR::Slice(start, end) => {
+                            ("sliced", format!("slice({}, {}, {:?})", input, start, end))
+                        }
+                        IR::Sort(key, order) => (
+                            "sorted",
+                            format!(
+                                "sort({}, {}, {})",
+                                input,
+                                path(key),
+                                *order == SortOrder::Descending
+                            ),
+                        ),
+                        IR::Dedupe(key) => (
+                            "unique",
+                            match key {
+                                Some(key) => format!("dedupe({}, Some({}[..]))", input, path(key)),
+                                None => format!("dedupe({}, None)", input),
+                            },
+                        ),
+                        IR::Gather(keys) => {
+                            ("gathered", format!("gather({}, {})", input, paths(keys)))
+                        }
+                        IR::Zip(keys) => ("zipped", format!("zip({}, {})", input, pairs(keys))),
+                        IR::Unzip(keys) => {
+                            ("unzipped", format!("unzip({}, {})", input, pairs(keys)))
+                        }
+                        IR::Inv => ("inverted", format!("invert({})", input)),
+                        IR::GroupBy(key) => {
+                            ("grouped", format!("group_by({}, {})", input, string(key)))
+                        }
+                        _ => unreachable!(),
+                    };
+                    let name = self.fresh(name);
+                    self.line(&format!("let {} = runtime::{};", name, call));
+                    top.input = format!("{}.as_ref()", name);
+                    None
+                }
+                IR::Scale { factor, offset } => Some(format!(
+                    "Some(runtime::number(runtime::to_number({}) * {:?} + {:?}))",
+                    input, factor.0, offset.0
+                )),
+                IR::DateConvert {
+                    from_format,
+                    to_format,
+                } => {
+                    let time = match from_format {
+                        DateFormat::EpochSeconds => {
+                            format!("runtime::time(runtime::to_number({}) * 1000.0)", input)
+                        }
+                        _ => format!("runtime::date({})", input),
+                    };
+                    Some(match to_format {
+                        DateFormat::Iso => format!("runtime::to_iso({}).map(Value::from)", time),
+                        DateFormat::EpochSeconds => {
+                            format!("Some(runtime::number(({} / 1000.0).floor()))", time)
+                        }
+                        DateFormat::EpochMillis => format!("Some(runtime::number({}))", time),
+                    })
+                }
+                IR::Base64(Coding::Encode) => Some(format!(
+                    "Some(Value::from(runtime::base64_encode(&runtime::to_string({}))))",
+                    input
+                )),
+                IR::Base64(Coding::Decode) => Some(format!(
+                    "runtime::base64_decode(&runtime::to_string({})).map(Value::from)",
+                    input
+                )),
+                IR::RegexExtract(pattern, group) => Some(format!(
+                    "runtime::extract({}, {}, {})",
+                    input,
+                    string(pattern),
+                    group
+                )),
+                IR::CaseTransform(case) => {
+                    let s = format!("runtime::to_string({})", input);
+                    Some(format!(
+                        "Some(Value::from({}))",
+                        match case {
+                            Case::Upper => format!("{}.to_uppercase()", s),
+                            Case::Lower => format!("{}.to_lowercase()", s),
+                            Case::Camel => format!("runtime::camel_case(&{})", s),
+                            Case::Snake => format!("runtime::snake_case(&{})", s),
+                        }
+                    ))
+                }
+                IR::MapValues(table) => {
+                    let entries: Vec<_> = table
+                        .iter()
+                        .map(|(from, to)| format!("({}, {})", literal(&from.0), literal(&to.0)))
+                        .collect();
+                    Some(format!(
+                        "runtime::map_values({}, &[{}])",
+                        input,
+                        entries.join(", ")
+                    ))
+                }
+                IR::Concat(keys, sep) => Some(format!(
+                    "Some(runtime::concat({}, {}, {}))",
+                    input,
+                    paths(keys),
+                    string(sep)
+                )),
+                IR::Const(value) => Some(format!("Some({})", literal(&value.0))),
+                IR::Join(sep) => {
+                    // only arrays are joined
+                    let joined = self.fresh("joined");
+                    self.line(&format!(
+                        "if let Some({}) = runtime::join({}, {}) {{",
+                        joined,
+                        input,
+                        string(sep)
+                    ));
+                    self.line(&format!("{} = Some({});", output, joined));
+                    self.line("}");
+                    None
+                }
+            };
+            if let Some(value) = value {
+                self.line(&format!("{} = {};", output, value));
+            }
+        }
+        while let Some(frame) = frames.pop() {
+            self.close(frame);
+        }
+        self.line(&format!("{}.unwrap_or(Value::Null)", output));
+
+        format!(
+            "#[allow(unused_assignments, unused_mut, unused_variables)]\npub fn transform({}: &Value) -> Value {{\n{}}}\n",
+            input, self.body
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        schema::{Literal, Real},
+        test_util::key,
+    };
+
+    use super::*;
+
+    /// The generated function, without the runtime it carries.
+    fn generate(ir: &[IR]) -> String {
+        let code = RustCodegen::new().generate(ir, "input", "output");
+        let end = code.find("\n\n#[allow(dead_code)]").unwrap();
+        code[..end].to_string()
+    }
+
+    #[test]
+    fn test_object() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("age")),
+            IR::PushOpt,
+            IR::Scale {
+                factor: Real(12.0),
+                offset: Real(0.0),
+            },
+            IR::PopOpt,
+            IR::PopKey,
+            IR::PushKey(key("kind")),
+            IR::Const(Literal(serde_json::json!({"name": "user"}))),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            generate(&ir),
+            r#"use serde_json::Value;
+
+#[allow(unused_assignments, unused_mut, unused_variables)]
+pub fn transform(input: &Value) -> Value {
+    let input = Some(input);
+    let mut output: Option<Value> = None;
+    output = Some(Value::Object(serde_json::Map::new()));
+    let mut field0 = runtime::field(&output, "id");
+    field0 = Some(Value::from(runtime::to_string(runtime::get(input, "user_id"))));
+    runtime::put(&mut output, "id", field0);
+    let mut field1 = runtime::field(&output, "age");
+    if runtime::get(input, "age").is_some() {
+        field1 = Some(runtime::number(runtime::to_number(runtime::get(input, "age")) * 12.0 + 0.0));
+    }
+    runtime::put(&mut output, "age", field1);
+    let mut field2 = runtime::field(&output, "kind");
+    field2 = Some(serde_json::json!({"name": "user"}));
+    runtime::put(&mut output, "kind", field2);
+    output.unwrap_or(Value::Null)
+}"#
+        );
+    }
+
+    #[test]
+    fn test_arrays_and_unions() {
+        let ir = [
+            IR::PushArr,
+            IR::PushUnion,
+            IR::Case(Arc::new(Schema::string())),
+            IR::Wrap,
+            IR::Copy,
+            IR::PopCase,
+            IR::Case(Arc::new(Schema::Arr(Arc::new(Schema::string())))),
+            IR::Copy,
+            IR::PopCase,
+            IR::PopUnion,
+            IR::PopArr,
+        ];
+        assert_eq!(
+            generate(&ir),
+            r#"use serde_json::Value;
+
+#[allow(unused_assignments, unused_mut, unused_variables)]
+pub fn transform(input: &Value) -> Value {
+    let input = Some(input);
+    let mut output: Option<Value> = None;
+    let mut items0 = vec![];
+    for item1 in runtime::items(input) {
+        let mut written2: Option<Value> = None;
+        if Some(item1).is_some_and(Value::is_string) {
+            let mut wrapped3: Option<Value> = None;
+            wrapped3 = Some(item1).cloned();
+            written2 = Some(Value::Array(wrapped3.into_iter().collect()));
+        } else if Some(item1).is_some_and(Value::is_array) {
+            written2 = Some(item1).cloned();
+        }
+        items0.push(written2.unwrap_or(Value::Null));
+    }
+    output = Some(Value::Array(items0));
+    output.unwrap_or(Value::Null)
+}"#
+        );
+    }
+
+    #[test]
+    fn test_runtime_module() {
+        let runtime = runtime_module();
+        assert!(runtime.contains("    pub mod regex {\n        //! A backtracking matcher"));
+        assert!(runtime.contains("\n    pub fn to_string(value: Option<&Value>) -> String {\n"));
+        assert!(!runtime.contains("#[cfg(test)]"));
+    }
+}
@@ -0,0 +1,786 @@
+//! Typed Rust generation: structs for the source and target schemas, and a
+//! `From` implementation converting one into the other field by field, for
+//! embedding transforms in services without going through `Value`.
+//!
+//! Only paths whose steps the types can express are converted that way.
+//! Those dispatching on unions, say, or rearranging arrays are converted
+//! through `Value` instead, by the function [`RustCodegen`] generates.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    ir::{scope_end, Case, IR},
+    matcher::words,
+    schema::{Ground, Schema},
+};
+
+use super::{literal, runtime_module, Codegen, RustCodegen};
+
+/// Name of the type declared for the input.
+const SOURCE: &str = "Source";
+/// Name of the type declared for the output.
+const TARGET: &str = "Target";
+
+/// Keywords which can't name a field as they are.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// Generates Rust types for a source and target schema, and the conversion
+/// between them an IR path makes.
+pub struct TypedRustCodegen {
+    source: Schema,
+    target: Schema,
+    /// The struct declared for each object, by its path from the root
+    /// type.
+    names: HashMap<Vec<String>, String>,
+    declarations: Vec<String>,
+    /// Whether the generated code uses `Value`, `BTreeMap` and the runtime.
+    value: bool,
+    map: bool,
+    runtime: bool,
+}
+
+/// The fields of an object's struct, by the property each holds, along
+/// with the field holding its additional properties, if it allows them.
+fn layout(schema: &Schema) -> (Vec<(Arc<String>, String)>, Option<String>) {
+    let Schema::Obj {
+        props, additional, ..
+    } = schema
+    else {
+        return (vec![], None);
+    };
+    let mut taken = HashSet::new();
+    let mut unique = |name: String| {
+        let mut unique = name.clone();
+        let mut n = 1;
+        while !taken.insert(unique.clone()) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+        unique
+    };
+    let fields = props
+        .keys()
+        .map(|key| (key.clone(), unique(identifier(key))))
+        .collect();
+    let rest = additional.as_ref().map(|_| unique("rest".to_string()));
+    (fields, rest)
+}
+
+/// A snake case field name for property `key`.
+fn identifier(key: &str) -> String {
+    let name: String = words(key)
+        .join("_")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+    match name.as_str() {
+        "" => "field".to_string(),
+        "self" | "super" | "crate" => format!("{}_", name),
+        name if KEYWORDS.contains(&name) => format!("r#{}", name),
+        name if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{}", name),
+        name => name.to_string(),
+    }
+}
+
+/// A pascal case type name for `path`.
+pub(crate) fn type_name(path: &[String]) -> String {
+    path.iter()
+        .flat_map(|segment| words(segment))
+        .map(|word| {
+            let word: String = word.chars().filter(char::is_ascii_alphanumeric).collect();
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// `path` extended by `segment`.
+pub(crate) fn child(path: &[String], segment: &str) -> Vec<String> {
+    let mut path = path.to_vec();
+    path.push(segment.to_string());
+    path
+}
+
+/// Whether values of `schema` are `Copy` in Rust.
+fn copied(schema: &Schema) -> bool {
+    matches!(
+        schema,
+        Schema::Ground(Ground::Num | Ground::Bool | Ground::Null)
+    )
+}
+
+/// Whether `schema` is typed as a `Value`.
+pub(crate) fn dynamic(schema: &Schema) -> bool {
+    matches!(
+        schema,
+        Schema::Union(_) | Schema::Rec(..) | Schema::Var(_) | Schema::True | Schema::False
+    )
+}
+
+/// Property `key` of the object `schema`, along with whether it's
+/// required.
+pub(crate) fn property<'a>(schema: &'a Schema, key: &str) -> Option<(&'a Schema, bool)> {
+    let Schema::Obj {
+        props, required, ..
+    } = schema
+    else {
+        return None;
+    };
+    let (key, prop) = props.iter().find(|(k, _)| k.as_str() == key)?;
+    Some((prop, required.contains(key)))
+}
+
+/// The field holding property `key` of the object `schema`.
+fn field_name(schema: &Schema, key: &str) -> Option<String> {
+    layout(schema)
+        .0
+        .into_iter()
+        .find(|(k, _)| k.as_str() == key)
+        .map(|(_, field)| field)
+}
+
+/// `expr` indented one level further, past its first line.
+pub(crate) fn nested(expr: &str) -> String {
+    expr.replace('\n', "\n    ")
+}
+
+impl TypedRustCodegen {
+    /// A generator for conversions from `source` data into `target` data.
+    pub fn new(source: &Schema, target: &Schema) -> Self {
+        // the types declared only follow the shape of the data
+        Self {
+            source: source.stripped(),
+            target: target.stripped(),
+            names: HashMap::new(),
+            declarations: vec![],
+            value: false,
+            map: false,
+            runtime: false,
+        }
+    }
+
+    /// Declare the struct for the root type `name`, wrapping the type of
+    /// `schema` unless it's an object.
+    fn declare_root(&mut self, name: &str, schema: &Schema) {
+        let path = vec![name.to_string()];
+        if let Schema::Obj { .. } = schema {
+            self.declare(schema, &path);
+            return;
+        }
+        let at = self.declarations.len();
+        self.declarations.push(String::new());
+        let ty = self.declare(schema, &path);
+        self.declarations[at] = format!(
+            "#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]\n#[serde(transparent)]\npub struct {}(pub {});\n",
+            name, ty
+        );
+    }
+
+    /// The Rust type of `schema`, declaring structs for the objects in it,
+    /// named after their `path` from the root type.
+    fn declare(&mut self, schema: &Schema, path: &[String]) -> String {
+        match schema {
+            Schema::Ground(Ground::Num) => "f64".to_string(),
+            Schema::Ground(Ground::Bool) => "bool".to_string(),
+            Schema::Ground(Ground::String) => "String".to_string(),
+            Schema::Ground(Ground::Null) => "()".to_string(),
+            Schema::Arr(item) => format!("Vec<{}>", self.declare(item, &child(path, "item"))),
+            Schema::Obj {
+                props,
+                required,
+                additional,
+                ..
+            } => {
+                let mut name = type_name(path);
+                let taken: HashSet<_> = self.names.values().cloned().collect();
+                let mut n = 1;
+                while taken.contains(&name) {
+                    n += 1;
+                    name = format!("{}{}", type_name(path), n);
+                }
+                self.names.insert(path.to_vec(), name.clone());
+
+                // declared ahead of the structs of its properties
+                let at = self.declarations.len();
+                self.declarations.push(String::new());
+                let (fields, rest) = layout(schema);
+                let mut out = format!(
+                    "#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]\npub struct {} {{\n",
+                    name
+                );
+                for (key, field) in fields {
+                    let ty = self.declare(&props[&key], &child(path, &key));
+                    let mut attributes = vec![];
+                    if field.trim_start_matches("r#") != key.as_str() {
+                        attributes.push(format!("rename = {:?}", key.as_str()));
+                    }
+                    let ty = if required.contains(&key) {
+                        ty
+                    } else {
+                        attributes.push("default".to_string());
+                        attributes.push("skip_serializing_if = \"Option::is_none\"".to_string());
+                        format!("Option<{}>", ty)
+                    };
+                    if !attributes.is_empty() {
+                        out.push_str(&format!("    #[serde({})]\n", attributes.join(", ")));
+                    }
+                    out.push_str(&format!("    pub {}: {},\n", field, ty));
+                }
+                if let (Some(rest), Some(additional)) = (rest, additional) {
+                    self.map = true;
+                    let ty = self.declare(additional, &child(path, "additional"));
+                    out.push_str(&format!(
+                        "    #[serde(flatten)]\n    pub {}: BTreeMap<String, {}>,\n",
+                        rest, ty
+                    ));
+                }
+                out.push_str("}\n");
+                self.declarations[at] = out;
+                name
+            }
+            Schema::Annotated(inner, _) => self.declare(inner, path),
+            Schema::Union(_) | Schema::Rec(..) | Schema::Var(_) | Schema::True | Schema::False => {
+                self.value = true;
+                "Value".to_string()
+            }
+        }
+    }
+
+    /// An expression for what `body` writes, converting `input`, a place
+    /// (or reference) holding `source` data, into `target` data, whose type
+    /// is named after `path`. `None` if the types can't express it.
+    fn convert(
+        &mut self,
+        body: &[IR],
+        input: &str,
+        source: &Schema,
+        target: &Schema,
+        path: &[String],
+    ) -> Option<String> {
+        let (mut input, mut source) = (input.to_string(), source);
+        for (i, op) in body.iter().enumerate() {
+            let last = i + 1 == body.len();
+            match op {
+                IR::Extr(key) => {
+                    let (prop, true) = property(source, key)? else {
+                        return None;
+                    };
+                    input = format!("{}.{}", input, field_name(source, key)?);
+                    source = prop;
+                }
+                IR::Wrap => {
+                    let Schema::Arr(item) = target else {
+                        return None;
+                    };
+                    let rest = &body[i + 1..];
+                    let item = self.convert(rest, &input, source, item, &child(path, "item"))?;
+                    return Some(format!("vec![{}]", item));
+                }
+                op if op.closer().is_some() => {
+                    // the rest of the body is one scope
+                    let inner = &body[i + 1..body.len() - 1];
+                    if scope_end(&body[i..])? != body.len() - i - 1 {
+                        return None;
+                    }
+                    return self.scope(op, inner, &input, source, target, path);
+                }
+                IR::Copy if last => return self.copy(&input, source, target, path),
+                _ if last => return self.ground(op, &input, source, target),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// An expression for a value converted by a single instruction `op`
+    /// from `input`, a place holding `source` data, into `target` data.
+    fn ground(&mut self, op: &IR, input: &str, source: &Schema, target: &Schema) -> Option<String> {
+        if let IR::Const(value) = op {
+            return match (target, &value.0) {
+                (Schema::Ground(Ground::Num), serde_json::Value::Number(n)) => {
+                    Some(format!("{:?}", n.as_f64()?))
+                }
+                (Schema::Ground(Ground::String), serde_json::Value::String(s)) => {
+                    Some(format!("{:?}.to_string()", s))
+                }
+                (Schema::Ground(Ground::Bool), serde_json::Value::Bool(b)) => Some(b.to_string()),
+                (Schema::Ground(Ground::Null), serde_json::Value::Null) => Some("()".to_string()),
+                (target, value) if dynamic(target) => {
+                    self.value = true;
+                    Some(literal(value))
+                }
+                _ => None,
+            };
+        }
+        let (Schema::Ground(from), Schema::Ground(to)) = (source, target) else {
+            return None;
+        };
+        match op {
+            IR::G2G(_, written) if written == to => Some(match (from, to) {
+                (Ground::Num, Ground::String) => {
+                    self.runtime = true;
+                    format!("runtime::number_to_string({})", input)
+                }
+                (Ground::Bool, Ground::String) => format!("{}.to_string()", input),
+                (Ground::Null, Ground::String) => "\"null\".to_string()".to_string(),
+                (Ground::String, Ground::Num) => {
+                    self.runtime = true;
+                    format!("runtime::string_to_number(&{})", input)
+                }
+                (Ground::Bool, Ground::Num) => format!("f64::from(u8::from({}))", input),
+                (Ground::Null, Ground::Num) => "0.0".to_string(),
+                (Ground::Num, Ground::Bool) => format!("{} != 0.0 && !{}.is_nan()", input, input),
+                (Ground::String, Ground::Bool) => format!("!{}.is_empty()", input),
+                (Ground::Null, Ground::Bool) => "false".to_string(),
+                (_, Ground::Null) => "()".to_string(),
+                (Ground::String, Ground::String) => format!("{}.clone()", input),
+                _ => input.to_string(),
+            }),
+            IR::Scale { factor, offset } if (from, to) == (&Ground::Num, &Ground::Num) => {
+                Some(format!("{} * {:?} + {:?}", input, factor.0, offset.0))
+            }
+            IR::CaseTransform(case) if (from, to) == (&Ground::String, &Ground::String) => {
+                Some(match case {
+                    Case::Upper => format!("{}.to_uppercase()", input),
+                    Case::Lower => format!("{}.to_lowercase()", input),
+                    Case::Camel | Case::Snake => {
+                        self.runtime = true;
+                        let case = if *case == Case::Camel {
+                            "camel_case"
+                        } else {
+                            "snake_case"
+                        };
+                        format!("runtime::{}(&{})", case, input)
+                    }
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// An expression for `input`, a place holding `source` data, copied as
+    /// `target` data.
+    fn copy(
+        &mut self,
+        input: &str,
+        source: &Schema,
+        target: &Schema,
+        path: &[String],
+    ) -> Option<String> {
+        match (source, target) {
+            (Schema::Ground(a), Schema::Ground(b)) if a == b => Some(match copied(source) {
+                true => input.to_string(),
+                false => format!("{}.clone()", input),
+            }),
+            (source, target) if dynamic(source) && dynamic(target) => {
+                Some(format!("{}.clone()", input))
+            }
+            (Schema::Ground(ground), target) if dynamic(target) => Some(match ground {
+                Ground::Null => "Value::Null".to_string(),
+                Ground::String => format!("Value::from({}.clone())", input),
+                _ => format!("Value::from({})", input),
+            }),
+            (Schema::Arr(a), Schema::Arr(b)) => {
+                let item = self.copy("item", a, b, &child(path, "item"))?;
+                match item.as_str() {
+                    "item" | "item.clone()" => Some(format!("{}.clone()", input)),
+                    _ => Some(format!(
+                        "{}.iter().map(|{}| {}).collect()",
+                        input,
+                        if copied(a) { "&item" } else { "item" },
+                        item
+                    )),
+                }
+            }
+            (
+                Schema::Obj {
+                    additional: from, ..
+                },
+                Schema::Obj {
+                    props,
+                    required,
+                    additional: to,
+                    ..
+                },
+            ) => {
+                let name = self.names.get(path)?.clone();
+                let (fields, rest) = layout(target);
+                let mut out = format!("{} {{\n", name);
+                for (key, field) in fields {
+                    let path = child(path, &key);
+                    let optional = !required.contains(&key);
+                    let value = match property(source, &key) {
+                        None if optional => "None".to_string(),
+                        None => return None,
+                        Some((from, true)) => {
+                            let value = self.copy(
+                                &format!("{}.{}", input, field_name(source, &key)?),
+                                from,
+                                &props[&key],
+                                &path,
+                            )?;
+                            match optional {
+                                true => format!("Some({})", value),
+                                false => value,
+                            }
+                        }
+                        Some((_, false)) if !optional => return None,
+                        Some((from, false)) => {
+                            let value = self.copy(&field, from, &props[&key], &path)?;
+                            self.optional(input, source, &key, &field, from, &value)?
+                        }
+                    };
+                    out.push_str(&format!("    {}: {},\n", field, nested(&value)));
+                }
+                if let (Some(rest), Some(to)) = (rest, to) {
+                    let value = match (from, layout(source).1) {
+                        (Some(from), Some(from_rest)) => {
+                            let value = self.copy("value", from, to, &child(path, "additional"))?;
+                            match value.as_str() {
+                                "value" | "value.clone()" => {
+                                    format!("{}.{}.clone()", input, from_rest)
+                                }
+                                _ => format!(
+                                    "{}.{}.iter().map(|(key, {})| (key.clone(), {})).collect()",
+                                    input,
+                                    from_rest,
+                                    if copied(from) { "&value" } else { "value" },
+                                    value
+                                ),
+                            }
+                        }
+                        _ => "BTreeMap::new()".to_string(),
+                    };
+                    out.push_str(&format!("    {}: {},\n", rest, nested(&value)));
+                }
+                out.push('}');
+                Some(out)
+            }
+            _ => None,
+        }
+    }
+
+    /// An expression for optional property `key` of `input`, a place
+    /// holding `source` data, as `value`, written in terms of the property's
+    /// value bound to `binding`.
+    fn optional(
+        &mut self,
+        input: &str,
+        source: &Schema,
+        key: &str,
+        binding: &str,
+        prop: &Schema,
+        value: &str,
+    ) -> Option<String> {
+        let place = format!("{}.{}", input, field_name(source, key)?);
+        let trivial = [binding.to_string(), format!("{}.clone()", binding)];
+        Some(if trivial.contains(&value.to_string()) {
+            match copied(prop) {
+                true => place,
+                false => format!("{}.clone()", place),
+            }
+        } else if copied(prop) {
+            format!("{}.map(|{}| {})", place, binding, value)
+        } else {
+            format!("{}.as_ref().map(|{}| {})", place, binding, value)
+        })
+    }
+
+    /// An expression for what the scope `op` opens writes, whose
+    /// instructions are `body`, converting `input` (a place holding `source`
+    /// data) into `target` data.
+    fn scope(
+        &mut self,
+        op: &IR,
+        body: &[IR],
+        input: &str,
+        source: &Schema,
+        target: &Schema,
+        path: &[String],
+    ) -> Option<String> {
+        match (op, source, target) {
+            (IR::PushArr, Schema::Arr(from), Schema::Arr(to)) => {
+                let item = self.convert(body, "item", from, to, &child(path, "item"))?;
+                match item.as_str() {
+                    "item" | "item.clone()" => Some(format!("{}.clone()", input)),
+                    _ => Some(format!(
+                        "{}.iter().map(|{}| {}).collect()",
+                        input,
+                        if copied(from) { "&item" } else { "item" },
+                        nested(&item)
+                    )),
+                }
+            }
+            (
+                IR::PushObj,
+                _,
+                Schema::Obj {
+                    props,
+                    required,
+                    additional,
+                    ..
+                },
+            ) => {
+                let name = self.names.get(path)?.clone();
+                let mut written: HashMap<String, String> = HashMap::new();
+                let mut i = 0;
+                while i < body.len() {
+                    let end = scope_end(&body[i..])?;
+                    let (op, inner) = (&body[i], &body[i + 1..i + end]);
+                    i += end + 1;
+                    let (from, to) = match op {
+                        IR::PushKey(key) => (Some(key), key),
+                        IR::Rename(from, to) => (Some(from), to),
+                        IR::Nest(key) => (None, key),
+                        _ => return None,
+                    };
+                    let prop = props.get(to)?;
+                    let optional = !required.contains(to);
+                    let path = child(path, to);
+                    let value = match from.map(|from| (from, property(source, from))) {
+                        None => self.convert(inner, input, source, prop, &path)?,
+                        Some((from, Some((from_prop, true)))) => {
+                            let place = format!("{}.{}", input, field_name(source, from)?);
+                            self.convert(inner, &place, from_prop, prop, &path)?
+                        }
+                        Some((from, Some((from_prop, false)))) if optional => {
+                            let binding = field_name(source, from)?;
+                            let inner = match inner {
+                                [IR::PushOpt, inner @ .., IR::PopOpt] => inner,
+                                [IR::Copy] => inner,
+                                _ => return None,
+                            };
+                            let value = self.convert(inner, &binding, from_prop, prop, &path)?;
+                            let value =
+                                self.optional(input, source, from, &binding, from_prop, &value)?;
+                            written.insert(to.to_string(), value);
+                            continue;
+                        }
+                        _ => return None,
+                    };
+                    let value = match optional {
+                        true => format!("Some({})", value),
+                        false => value,
+                    };
+                    if written.insert(to.to_string(), value).is_some() {
+                        return None;
+                    }
+                }
+                let (fields, rest) = layout(target);
+                let mut out = format!("{} {{\n", name);
+                for (key, field) in fields {
+                    let value = match written.remove(key.as_str()) {
+                        Some(value) => value,
+                        None if !required.contains(&key) => "None".to_string(),
+                        None => return None,
+                    };
+                    out.push_str(&format!("    {}: {},\n", field, nested(&value)));
+                }
+                if let (Some(rest), Some(_)) = (rest, additional) {
+                    out.push_str(&format!("    {}: BTreeMap::new(),\n", rest));
+                }
+                out.push('}');
+                Some(out)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Codegen for TypedRustCodegen {
+    fn generate(&mut self, ir: &[IR], input: &str, output: &str) -> String {
+        self.names.clear();
+        self.declarations.clear();
+        (self.value, self.map, self.runtime) = (false, false, false);
+        let (source, target) = (self.source.clone(), self.target.clone());
+        self.declare_root(SOURCE, &source);
+        self.declare_root(TARGET, &target);
+
+        let from = match source {
+            Schema::Obj { .. } => input.to_string(),
+            _ => format!("{}.0", input),
+        };
+        let converted = self
+            .convert(ir, &from, &source, &target, &[TARGET.to_string()])
+            .map(|value| match target {
+                Schema::Obj { .. } => value,
+                _ => format!("{}({})", TARGET, nested(&value)),
+            });
+        let (body, function) = match converted {
+            Some(value) => (format!("let {} = &{};\n{}", input, input, value), None),
+            None => {
+                // converted through `Value` by the untyped transform
+                self.runtime = true;
+                let function = RustCodegen::new().generate_function(ir, input, output);
+                (
+                    format!(
+                        "let {} = serde_json::to_value({}).expect(\"the source is JSON\");\nserde_json::from_value(transform(&{})).expect(\"the transform writes the target\")",
+                        input, input, input
+                    ),
+                    Some(function),
+                )
+            }
+        };
+
+        let mut out = "use serde::{Deserialize, Serialize};\n".to_string();
+        if self.value || function.is_some() {
+            out.push_str("use serde_json::Value;\n");
+        }
+        if self.map {
+            out.push_str("use std::collections::BTreeMap;\n");
+        }
+        for declaration in &self.declarations {
+            out.push('\n');
+            out.push_str(declaration);
+        }
+        let body = body.replace('\n', "\n        ");
+        out.push_str(&format!(
+            "\nimpl From<{}> for {} {{\n    fn from({}: {}) -> Self {{\n        {}\n    }}\n}}\n",
+            SOURCE, TARGET, input, SOURCE, body
+        ));
+        if let Some(function) = function {
+            out.push('\n');
+            out.push_str(&function);
+        }
+        if self.runtime {
+            out.push('\n');
+            out.push_str(&runtime_module());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{schema, test_util::key};
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(identifier("userId"), "user_id");
+        assert_eq!(identifier("type"), "r#type");
+        assert_eq!(identifier("2fa"), "_2fa");
+        assert_eq!(identifier("self"), "self_");
+        assert_eq!(
+            type_name(&["Target".to_string(), "user-info".to_string()]),
+            "TargetUserInfo"
+        );
+        let (fields, rest) = layout(&schema!({
+            "type": "object",
+            "properties": {"a_b": {"type": "number"}, "aB": {"type": "number"}},
+            "additionalProperties": {"type": "string"}
+        }));
+        let fields: Vec<_> = fields.into_iter().map(|(_, field)| field).collect();
+        assert_eq!(fields, ["a_b", "a_b_2"]);
+        assert_eq!(rest.as_deref(), Some("rest"));
+    }
+
+    #[test]
+    fn test_typed_conversion() {
+        let source = schema!({
+            "type": "object",
+            "properties": {
+                "user_id": {"type": "number"},
+                "tags": {"type": "array", "items": {"type": "boolean"}},
+                "nick": {"type": "string"}
+            },
+            "required": ["user_id", "tags"]
+        });
+        let target = schema!({
+            "type": "object",
+            "properties": {
+                "userId": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "nick": {"type": "string"}
+            },
+            "required": ["userId", "tags"]
+        });
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("userId")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("tags")),
+            IR::PushArr,
+            IR::G2G(Ground::Bool, Ground::String),
+            IR::PopArr,
+            IR::PopKey,
+            IR::PushKey(key("nick")),
+            IR::PushOpt,
+            IR::CaseTransform(Case::Upper),
+            IR::PopOpt,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let code = TypedRustCodegen::new(&source, &target).generate(&ir, "input", "output");
+        let end = code.find("\n#[allow(dead_code)]").unwrap();
+        assert_eq!(
+            &code[..end],
+            r#"use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nick: Option<String>,
+    pub tags: Vec<bool>,
+    pub user_id: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Target {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nick: Option<String>,
+    pub tags: Vec<String>,
+    #[serde(rename = "userId")]
+    pub user_id: String,
+}
+
+impl From<Source> for Target {
+    fn from(input: Source) -> Self {
+        let input = &input;
+        Target {
+            nick: input.nick.as_ref().map(|nick| nick.to_uppercase()),
+            tags: input.tags.iter().map(|&item| item.to_string()).collect(),
+            user_id: runtime::number_to_string(input.user_id),
+        }
+    }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_converted_through_values() {
+        let source = schema!({"anyOf": [{"type": "number"}, {"type": "string"}]});
+        let target = schema!({"type": "string"});
+        let ir = [
+            IR::PushUnion,
+            IR::Case(Arc::new(Schema::Ground(Ground::Num))),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopCase,
+            IR::Case(Arc::new(Schema::string())),
+            IR::Copy,
+            IR::PopCase,
+            IR::PopUnion,
+        ];
+        let code = TypedRustCodegen::new(&source, &target).generate(&ir, "input", "output");
+        assert!(code.contains("#[serde(transparent)]\npub struct Source(pub Value);\n"));
+        assert!(code.contains("#[serde(transparent)]\npub struct Target(pub String);\n"));
+        assert!(code.contains(
+            "serde_json::from_value(transform(&input)).expect(\"the transform writes the target\")"
+        ));
+        assert!(code.contains("\npub fn transform(input: &Value) -> Value {\n"));
+    }
+}
@@ -0,0 +1,835 @@
+//! PostgreSQL generation: a SQL expression transforming a `jsonb` value
+//! the way the generated JavaScript does, or a function wrapping one, for
+//! running migrations in the database.
+//!
+//! Undefined values are SQL `NULL`s, and JSON nulls are `'null'::jsonb`.
+//! Objects are built with `jsonb_object_agg`, leaving out properties whose
+//! values are `NULL`, and arrays with `jsonb_agg` over
+//! `jsonb_array_elements`. Values used more than once are bound in a
+//! subquery, so each is only computed once.
+//!
+//! PostgreSQL differs from JavaScript in a few places: numbers converted to
+//! strings are written the way `jsonb` keeps them (so `1.50` stays `1.50`),
+//! `jsonb` keeps the properties of objects in its own order, sorting
+//! compares mixed types the way `jsonb` does, regular expressions are
+//! PostgreSQL's, and date-times without an offset are read as UTC.
+//! Instructions with no reasonable SQL equivalent, like those grouping or
+//! zipping arrays, are reported.
+
+use std::{fmt::Display, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    ir::{scope_end, text, Case, Coding, DateFormat, SortOrder, IR},
+    schema::{Ground, Schema},
+};
+
+/// Error generating SQL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SqlErr {
+    /// The instruction at this index has no SQL equivalent.
+    Unsupported(usize, IR),
+}
+
+impl Display for SqlErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(i, op) => write!(
+                f,
+                "instruction {} (`{}`) has no SQL equivalent",
+                i,
+                text::print(std::slice::from_ref(op)).trim_end()
+            ),
+        }
+    }
+}
+
+/// `'{}'`, the empty object.
+const EMPTY: &str = "'{}'::jsonb";
+
+/// A part of an object being built.
+enum Part {
+    /// A property, left out if its value is `NULL`.
+    Prop(Arc<String>, String),
+    /// The properties of an object.
+    Spread(String),
+}
+
+/// The output a scope writes, as far as it's been written.
+enum Output {
+    Unwritten,
+    /// The value of an expression.
+    Expr(String),
+    /// An object made of these parts, on top of the value of an expression
+    /// if there is one (and it's an object).
+    Obj(Option<String>, Vec<Part>),
+}
+
+/// `text` as a string literal.
+fn string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// `value` as a `jsonb` literal.
+fn jsonb(value: &Value) -> String {
+    format!("{}::jsonb", string(&value.to_string()))
+}
+
+/// `keys` as a `text[]` literal.
+fn keys<'a>(keys: impl IntoIterator<Item = &'a Arc<String>>) -> String {
+    let keys: Vec<_> = keys.into_iter().map(|key| string(key)).collect();
+    format!("ARRAY[{}]::text[]", keys.join(", "))
+}
+
+/// Whether `expr` is a name, which can be repeated without computing
+/// anything again.
+fn simple(expr: &str) -> bool {
+    expr.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// The terms of `expr` separated by spaces outside any brackets and
+/// literals.
+fn terms(expr: &str) -> Vec<&str> {
+    let mut terms = vec![];
+    let (mut depth, mut quoted, mut start) = (0, false, 0);
+    for (i, c) in expr.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            '(' | '[' if !quoted => depth += 1,
+            ')' | ']' if !quoted => depth -= 1,
+            ' ' if !quoted && depth == 0 => {
+                terms.push(&expr[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    terms.push(&expr[start..]);
+    terms
+}
+
+/// `expr`, parenthesized unless it's a single term or a chain of `->`s,
+/// which bind the way the operators following them expect.
+fn group(expr: &str) -> String {
+    let terms = terms(expr);
+    let chain = terms.iter().skip(1).step_by(2).all(|term| *term == "->");
+    if terms.len() == 1 || (chain && terms.len() % 2 == 1) {
+        expr.to_string()
+    } else {
+        format!("({})", expr)
+    }
+}
+
+/// The value at `key` of the value of `expr`.
+fn get(expr: &str, key: &str) -> String {
+    format!("{} -> {}", group(expr), string(key))
+}
+
+/// The value at `path` of the value of `expr`.
+fn at(expr: &str, path: &[Arc<String>]) -> String {
+    path.iter()
+        .fold(expr.to_string(), |expr, key| get(&expr, key))
+}
+
+/// The items of the value of `v`, if it's an array, and no rows otherwise,
+/// as `alias (value, n)`.
+fn elements(v: &str, alias: &str) -> String {
+    format!(
+        "jsonb_array_elements(CASE WHEN jsonb_typeof({v}) = 'array' THEN {v} END) WITH ORDINALITY AS {alias} (value, n)",
+        v = v,
+        alias = alias
+    )
+}
+
+/// The item `i` of an array as a string, the way JavaScript's `String`
+/// writes it in the array.
+const ITEM: &str = "CASE jsonb_typeof(i.value) WHEN 'null' THEN '' WHEN 'string' THEN i.value #>> '{}' WHEN 'object' THEN '[object Object]' ELSE i.value::text END";
+
+/// The value of `v`, a `jsonb` value, as a string the way JavaScript's
+/// `String` writes it.
+fn text(v: &str) -> String {
+    format!(
+        "CASE WHEN {v} IS NULL THEN 'undefined' WHEN jsonb_typeof({v}) = 'string' THEN {v} #>> '{{}}' WHEN jsonb_typeof({v}) = 'object' THEN '[object Object]' WHEN jsonb_typeof({v}) = 'array' THEN (SELECT COALESCE(string_agg({item}, ',' ORDER BY i.n), '') FROM jsonb_array_elements({v}) WITH ORDINALITY AS i (value, n)) ELSE {v}::text END",
+        v = v,
+        item = ITEM
+    )
+}
+
+/// The value of `v`, a `jsonb` value, as a `float8` the way JavaScript's
+/// `Number` makes one, or `NULL` for `NaN`.
+fn number(v: &str) -> String {
+    let trimmed = format!("btrim({} #>> '{{}}')", v);
+    format!(
+        "CASE jsonb_typeof({v}) WHEN 'number' THEN ({v} #>> '{{}}')::float8 WHEN 'boolean' THEN CASE WHEN {v} = 'true' THEN 1 ELSE 0 END WHEN 'null' THEN 0 WHEN 'string' THEN CASE WHEN {t} = '' THEN 0 WHEN {t} ~ '^[+-]?([0-9]+\\.?[0-9]*|\\.[0-9]+)([eE][+-]?[0-9]+)?$' THEN {t}::float8 END END",
+        v = v,
+        t = trimmed
+    )
+}
+
+/// Milliseconds since the epoch, as a `float8`, as a JSON number, or `null`
+/// if it's out of range.
+fn time(millis: &str) -> String {
+    format!(
+        "CASE WHEN abs({m}) <= 8.64e15 THEN trunc({m}) END",
+        m = millis
+    )
+}
+
+/// A condition testing whether `v` has the shape of `schema`, for
+/// dispatching on union branches. Objects are told apart by their required
+/// properties.
+fn test(v: &str, schema: &Schema) -> String {
+    let ty = |ty: &str| format!("jsonb_typeof({}) = '{}'", v, ty);
+    match schema {
+        Schema::Ground(Ground::Num) => ty("number"),
+        Schema::Ground(Ground::Bool) => ty("boolean"),
+        Schema::Ground(Ground::String) => ty("string"),
+        Schema::Ground(Ground::Null) => ty("null"),
+        Schema::Arr(_) => ty("array"),
+        Schema::Obj { required, .. } if required.is_empty() => ty("object"),
+        Schema::Obj { required, .. } => {
+            format!("{} AND {} ?& {}", ty("object"), v, keys(required))
+        }
+        Schema::Union(branches) => branches
+            .iter()
+            .map(|branch| format!("({})", test(v, branch)))
+            .collect::<Vec<_>>()
+            .join(" OR "),
+        Schema::Rec(_, body) | Schema::Annotated(body, _) => test(v, body),
+        Schema::True | Schema::Var(_) => "TRUE".to_string(),
+        Schema::False => "FALSE".to_string(),
+    }
+}
+
+/// Generates SQL from IR paths.
+#[derive(Default)]
+struct Generator {
+    /// Count of the subqueries made, naming them.
+    uniq: usize,
+}
+
+impl Generator {
+    /// A fresh name for a subquery, starting with `prefix`.
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.uniq += 1;
+        format!("{}{}", prefix, self.uniq)
+    }
+
+    /// `body`, given a name for the value of `expr`, which it may use more
+    /// than once.
+    fn bind(
+        &mut self,
+        expr: &str,
+        body: impl FnOnce(&mut Self, &str) -> Result<String, SqlErr>,
+    ) -> Result<String, SqlErr> {
+        if simple(expr) {
+            return body(self, expr);
+        }
+        let alias = self.fresh("b");
+        let body = body(self, &format!("{}.v", alias))?;
+        Ok(format!(
+            "(SELECT {} FROM (SELECT {} AS v) AS {})",
+            body, expr, alias
+        ))
+    }
+
+    /// The items of the value of `expr` in the order `f` sorts them by,
+    /// among those it keeps, or `NULL` if it's not an array. `f` is given
+    /// the name of the value and of the table of its items, and returns
+    /// the order and the condition items are kept on, if any.
+    fn arrays(
+        &mut self,
+        expr: &str,
+        f: impl FnOnce(&mut Self, &str, &str) -> Result<(String, Option<String>), SqlErr>,
+    ) -> Result<String, SqlErr> {
+        self.bind(expr, |g, v| {
+            let alias = g.fresh("a");
+            let (order, kept) = f(g, v, &alias)?;
+            let kept = kept.map_or(String::new(), |kept| format!(" FILTER (WHERE {})", kept));
+            Ok(format!(
+                "CASE WHEN jsonb_typeof({v}) = 'array' THEN (SELECT COALESCE(jsonb_agg({a}.value ORDER BY {order}){kept}, '[]'::jsonb) FROM {elements}) END",
+                v = v,
+                a = alias,
+                order = order,
+                kept = kept,
+                elements = elements(v, &alias)
+            ))
+        })
+    }
+
+    /// The output as an expression, if it's written.
+    fn expr(&mut self, output: Output) -> Result<Option<String>, SqlErr> {
+        let (base, parts) = match output {
+            Output::Unwritten => return Ok(None),
+            Output::Expr(expr) => return Ok(Some(expr)),
+            Output::Obj(base, parts) => (base, parts),
+        };
+        let mut object = match base {
+            Some(base) => self.bind(&base, |_, v| {
+                Ok(format!(
+                    "CASE WHEN jsonb_typeof({v}) = 'object' THEN {v} ELSE {} END",
+                    EMPTY,
+                    v = v
+                ))
+            })?,
+            None => EMPTY.to_string(),
+        };
+        let mut parts = parts.into_iter().peekable();
+        while let Some(part) = parts.next() {
+            let written = match part {
+                Part::Spread(spread) => spread,
+                Part::Prop(key, value) => {
+                    // the properties written next to this one
+                    let mut props = vec![(key, value)];
+                    while let Some(Part::Prop(..)) = parts.peek() {
+                        if let Some(Part::Prop(key, value)) = parts.next() {
+                            props.push((key, value));
+                        }
+                    }
+                    let rows: Vec<_> = props
+                        .iter()
+                        .map(|(key, value)| format!("({}, {})", string(key), value))
+                        .collect();
+                    let built = format!(
+                        "(SELECT COALESCE(jsonb_object_agg(p.k, p.v), {}) FROM (VALUES {}) AS p (k, v) WHERE p.v IS NOT NULL)",
+                        EMPTY,
+                        rows.join(", ")
+                    );
+                    if object == EMPTY {
+                        object = built;
+                        continue;
+                    }
+                    // properties left undefined are removed
+                    object = format!(
+                        "({} - {})",
+                        group(&object),
+                        keys(props.iter().map(|(key, _)| key))
+                    );
+                    built
+                }
+            };
+            object = if object == EMPTY {
+                written
+            } else {
+                format!("{} || {}", group(&object), group(&written))
+            };
+        }
+        Ok(Some(object))
+    }
+
+    /// The expression building what `ir` writes from the value of `input`,
+    /// if it writes anything. `base` is the index of `ir`'s first
+    /// instruction in the whole path.
+    fn scope(&mut self, ir: &[IR], base: usize, input: String) -> Result<Option<String>, SqlErr> {
+        let mut output = Output::Unwritten;
+        self.run(ir, base, input, &mut output)?;
+        self.expr(output)
+    }
+
+    /// Run the instructions of a scope `ir` on the value of `input`,
+    /// writing to `output`.
+    fn run(
+        &mut self,
+        ir: &[IR],
+        base: usize,
+        mut input: String,
+        output: &mut Output,
+    ) -> Result<(), SqlErr> {
+        let mut i = 0;
+        while i < ir.len() {
+            let op = &ir[i];
+            let unsupported = || SqlErr::Unsupported(base + i, op.clone());
+            if op.closer().is_some() {
+                let end = scope_end(&ir[i..]).map_or(ir.len(), |end| i + end);
+                let body = &ir[i + 1..end.min(ir.len())];
+                let inner = base + i + 1;
+                match op {
+                    IR::PushObj => {
+                        *output = Output::Obj(None, vec![]);
+                        self.run(body, inner, input.clone(), output)?;
+                    }
+                    IR::Merge => {
+                        let written = std::mem::replace(output, Output::Unwritten);
+                        *output = Output::Obj(self.expr(written)?, vec![]);
+                        self.run(body, inner, input.clone(), output)?;
+                    }
+                    IR::PushKey(_) | IR::Rename(..) | IR::Nest(_) => {
+                        let (value, key) = match op {
+                            IR::PushKey(key) => (self.scope(body, inner, get(&input, key))?, key),
+                            IR::Rename(from, to) => {
+                                (self.scope(body, inner, get(&input, from))?, to)
+                            }
+                            IR::Nest(key) => (self.scope(body, inner, input.clone())?, key),
+                            _ => unreachable!("only key scopes write properties"),
+                        };
+                        // properties can only be written into objects
+                        if let Output::Obj(_, parts) = output {
+                            let value = value.unwrap_or_else(|| "NULL::jsonb".to_string());
+                            parts.push(Part::Prop(key.clone(), value));
+                        }
+                    }
+                    IR::PushRest(named) => {
+                        let rest = self.bind(&input, |g, v| {
+                            let alias = g.fresh("e");
+                            let value = g
+                                .scope(body, inner, format!("{}.value", alias))?
+                                .unwrap_or_else(|| "NULL::jsonb".to_string());
+                            Ok(format!(
+                                "(SELECT COALESCE(jsonb_object_agg(r.key, r.v), {empty}) FROM (SELECT {e}.key, {value} AS v FROM jsonb_each(CASE WHEN jsonb_typeof({v}) = 'object' THEN {v} END) AS {e} WHERE {e}.key <> ALL ({named})) AS r WHERE r.v IS NOT NULL)",
+                                empty = EMPTY,
+                                e = alias,
+                                value = value,
+                                v = v,
+                                named = keys(named)
+                            ))
+                        })?;
+                        if let Output::Obj(_, parts) = output {
+                            parts.push(Part::Spread(rest));
+                        }
+                    }
+                    IR::PushOpt => {
+                        let before = std::mem::replace(output, Output::Unwritten);
+                        let before = self.expr(before)?;
+                        let mut written = before.clone().map_or(Output::Unwritten, Output::Expr);
+                        self.run(body, inner, input.clone(), &mut written)?;
+                        let written = self.expr(written)?;
+                        *output = match (written, before) {
+                            (Some(written), before) if Some(&written) != before.as_ref() => {
+                                // the output is left as it was when the input
+                                // is undefined
+                                let before = before
+                                    .map_or(String::new(), |before| format!(" ELSE {}", before));
+                                Output::Expr(format!(
+                                    "CASE WHEN {} IS NOT NULL THEN {}{} END",
+                                    group(&input),
+                                    written,
+                                    before
+                                ))
+                            }
+                            (written, _) => written.map_or(Output::Unwritten, Output::Expr),
+                        };
+                    }
+                    IR::PushArr => {
+                        let written = self.bind(&input, |g, v| {
+                            let alias = g.fresh("a");
+                            let item = format!("{}.value", alias);
+                            let mapped = g
+                                .scope(body, inner, item.clone())?
+                                .unwrap_or_else(|| "NULL::jsonb".to_string());
+                            if mapped == item {
+                                return Ok(format!(
+                                    "CASE WHEN jsonb_typeof({v}) = 'array' THEN {v} ELSE '[]'::jsonb END",
+                                    v = v
+                                ));
+                            }
+                            Ok(format!(
+                                "(SELECT COALESCE(jsonb_agg(COALESCE({}, 'null'::jsonb) ORDER BY {}.n), '[]'::jsonb) FROM {})",
+                                mapped,
+                                alias,
+                                elements(v, &alias)
+                            ))
+                        })?;
+                        *output = Output::Expr(written);
+                    }
+                    IR::PushUnion => {
+                        let before = std::mem::replace(output, Output::Unwritten);
+                        let before = self.expr(before)?;
+                        let written = self.bind(&input, |g, v| {
+                            let mut cases = String::new();
+                            let mut j = 0;
+                            while j < body.len() {
+                                let end = scope_end(&body[j..]).map_or(body.len(), |end| j + end);
+                                if let IR::Case(branch) = &body[j] {
+                                    let case = &body[j + 1..end.min(body.len())];
+                                    let written = g
+                                        .scope(case, inner + j + 1, v.to_string())?
+                                        .or_else(|| before.clone())
+                                        .unwrap_or_else(|| "NULL::jsonb".to_string());
+                                    cases.push_str(&format!(
+                                        " WHEN {} THEN {}",
+                                        test(v, branch),
+                                        written
+                                    ));
+                                }
+                                j = end + 1;
+                            }
+                            let before = before
+                                .clone()
+                                .map_or(String::new(), |before| format!(" ELSE {}", before));
+                            Ok(format!("CASE{}{} END", cases, before))
+                        })?;
+                        *output = Output::Expr(written);
+                    }
+                    _ => return Err(unsupported()),
+                }
+                i = end + 1;
+                continue;
+            }
+            match op {
+                IR::Copy => *output = Output::Expr(input.clone()),
+                IR::G2G(_, to) => {
+                    let converted = match to {
+                        Ground::String => self.bind(&input, |_, v| {
+                            Ok(format!("to_jsonb({})", text(v)))
+                        })?,
+                        Ground::Num => self.bind(&input, |_, v| {
+                            Ok(format!("COALESCE(to_jsonb({}), 'null'::jsonb)", number(v)))
+                        })?,
+                        Ground::Bool => format!(
+                            "to_jsonb(COALESCE({} NOT IN ('null'::jsonb, 'false'::jsonb, '0'::jsonb, '\"\"'::jsonb), FALSE))",
+                            group(&input)
+                        ),
+                        Ground::Null => "'null'::jsonb".to_string(),
+                    };
+                    *output = Output::Expr(converted);
+                }
+                IR::Pipe => {
+                    let written = std::mem::replace(output, Output::Unwritten);
+                    input = self
+                        .expr(written)?
+                        .unwrap_or_else(|| "NULL::jsonb".to_string());
+                }
+                IR::Wrap => {
+                    // the rest of the scope writes inside the array
+                    let wrapped = self.scope(&ir[i + 1..], base + i + 1, input.clone())?;
+                    let wrapped = match wrapped {
+                        Some(wrapped) => self.bind(&wrapped, |_, w| {
+                            Ok(format!(
+                                "CASE WHEN {w} IS NULL THEN '[]'::jsonb ELSE jsonb_build_array({w}) END",
+                                w = w
+                            ))
+                        })?,
+                        None => "'[]'::jsonb".to_string(),
+                    };
+                    *output = Output::Expr(wrapped);
+                    return Ok(());
+                }
+                IR::Extr(key) => input = get(&input, key),
+                IR::Coalesce(paths) => {
+                    input = self.bind(&input, |_, v| {
+                        let mut paths: Vec<_> = paths.iter().map(|path| at(v, path)).collect();
+                        let last = paths.pop().unwrap_or_else(|| "NULL::jsonb".to_string());
+                        let mut present: Vec<_> = paths
+                            .iter()
+                            .map(|path| format!("NULLIF({}, 'null'::jsonb)", path))
+                            .collect();
+                        present.push(last);
+                        Ok(format!("COALESCE({})", present.join(", ")))
+                    })?;
+                }
+                IR::First => {
+                    input = self.bind(&input, |_, v| {
+                        Ok(format!(
+                            "CASE WHEN jsonb_typeof({v}) = 'array' THEN {v} -> 0 END",
+                            v = v
+                        ))
+                    })?
+                }
+                IR::Flatten(depth) => {
+                    for _ in 0..*depth {
+                        input = self.bind(&input, |g, v| {
+                            let (outer, inner) = (g.fresh("a"), g.fresh("a"));
+                            Ok(format!(
+                                "CASE WHEN jsonb_typeof({v}) = 'array' THEN (SELECT COALESCE(jsonb_agg({i}.value ORDER BY {o}.n, {i}.n), '[]'::jsonb) FROM {elements}, jsonb_array_elements(CASE WHEN jsonb_typeof({o}.value) = 'array' THEN {o}.value ELSE jsonb_build_array({o}.value) END) WITH ORDINALITY AS {i} (value, n)) END",
+                                v = v,
+                                i = inner,
+                                o = outer,
+                                elements = elements(v, &outer)
+                            ))
+                        })?;
+                    }
+                }
+                IR::Slice(start, end) => {
+                    input = self.arrays(&input, |_, _, a| {
+                        let end = end.map_or(String::new(), |end| format!(" AND {}.n <= {}", a, end));
+                        Ok((format!("{}.n", a), Some(format!("{}.n > {}{}", a, start, end))))
+                    })?
+                }
+                IR::Sort(path, order) => {
+                    input = self.arrays(&input, |_, _, a| {
+                        let direction = match order {
+                            SortOrder::Ascending => "",
+                            SortOrder::Descending => " DESC",
+                        };
+                        let key = at(&format!("{}.value", a), path);
+                        Ok((format!("{}{} NULLS LAST, {}.n", key, direction, a), None))
+                    })?
+                }
+                IR::Dedupe(path) => {
+                    input = self.arrays(&input, |g, v, a| {
+                        let earlier = g.fresh("a");
+                        let path = path.as_deref().unwrap_or_default();
+                        let (key, earlier_key) = (
+                            at(&format!("{}.value", a), path),
+                            at(&format!("{}.value", earlier), path),
+                        );
+                        // the first of the items with each key is kept
+                        Ok((
+                            format!("{}.n", a),
+                            Some(format!(
+                                "NOT EXISTS (SELECT FROM {} WHERE {e}.n < {a}.n AND {} IS NOT DISTINCT FROM {})",
+                                elements(v, &earlier),
+                                earlier_key,
+                                key,
+                                e = earlier,
+                                a = a
+                            )),
+                        ))
+                    })?
+                }
+                IR::Gather(paths) => {
+                    input = self.bind(&input, |g, v| {
+                        let (gathered, alias) = (g.fresh("g"), g.fresh("a"));
+                        let rows: Vec<_> = paths
+                            .iter()
+                            .enumerate()
+                            .map(|(n, path)| format!("({}, {})", n, at(v, path)))
+                            .collect();
+                        Ok(format!(
+                            "(SELECT COALESCE(jsonb_agg({a}.value ORDER BY {g}.n, {a}.n), '[]'::jsonb) FROM (VALUES {rows}) AS {g} (n, value), {elements})",
+                            a = alias,
+                            g = gathered,
+                            rows = rows.join(", "),
+                            elements = elements(&format!("{}.value", gathered), &alias)
+                        ))
+                    })?
+                }
+                IR::Scale { factor, offset } => {
+                    let scaled = self.bind(&input, |_, v| {
+                        Ok(format!(
+                            "COALESCE(to_jsonb(({}) * {:?} + {:?}), 'null'::jsonb)",
+                            number(v),
+                            factor.0,
+                            offset.0
+                        ))
+                    })?;
+                    *output = Output::Expr(scaled);
+                }
+                IR::DateConvert {
+                    from_format,
+                    to_format,
+                } => {
+                    let converted = self.bind(&input, |g, v| {
+                        let millis = match from_format {
+                            DateFormat::Iso => {
+                                let s = format!("({} #>> '{{}}')", v);
+                                format!(
+                                    "CASE WHEN jsonb_typeof({v}) <> 'string' THEN {number} WHEN {s} ~ '^\\d{{4}}-\\d{{2}}-\\d{{2}}(T\\d{{2}}:\\d{{2}}(:\\d{{2}}(\\.\\d+)?)?)?(Z|[+-]\\d{{2}}:?\\d{{2}})$' THEN extract(epoch FROM {s}::timestamptz)::float8 * 1000 WHEN {s} ~ '^\\d{{4}}-\\d{{2}}-\\d{{2}}(T\\d{{2}}:\\d{{2}}(:\\d{{2}}(\\.\\d+)?)?)?$' THEN extract(epoch FROM {s}::timestamp AT TIME ZONE 'UTC')::float8 * 1000 END",
+                                    v = v,
+                                    s = s,
+                                    number = number(v)
+                                )
+                            }
+                            DateFormat::EpochSeconds => format!("({}) * 1000", number(v)),
+                            DateFormat::EpochMillis => number(v),
+                        };
+                        g.bind(&time(&millis), |_, t| {
+                            Ok(match to_format {
+                                DateFormat::Iso => format!(
+                                    "to_jsonb(to_char(to_timestamp({t} / 1000) AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS.MS\"Z\"'))",
+                                    t = t
+                                ),
+                                DateFormat::EpochSeconds => format!(
+                                    "COALESCE(to_jsonb(floor({} / 1000)), 'null'::jsonb)",
+                                    t
+                                ),
+                                DateFormat::EpochMillis => {
+                                    format!("COALESCE(to_jsonb({}), 'null'::jsonb)", t)
+                                }
+                            })
+                        })
+                    })?;
+                    *output = Output::Expr(converted);
+                }
+                IR::Base64(coding) => {
+                    let coded = self.bind(&input, |g, v| {
+                        let string = text(v);
+                        Ok(match coding {
+                            Coding::Encode => format!(
+                                "to_jsonb(translate(encode(convert_to({}, 'UTF8'), 'base64'), E'\\n', ''))",
+                                string
+                            ),
+                            Coding::Decode => g.bind(&format!("to_jsonb({})", string), |_, s| {
+                                let s = format!("({} #>> '{{}}')", s);
+                                Ok(format!(
+                                    "CASE WHEN length({s}) % 4 = 0 AND {s} ~ '^[A-Za-z0-9+/]*={{0,2}}$' THEN to_jsonb(convert_from(decode({s}, 'base64'), 'UTF8')) END",
+                                    s = s
+                                ))
+                            })?,
+                        })
+                    })?;
+                    *output = Output::Expr(coded);
+                }
+                IR::RegexExtract(pattern, group) => {
+                    let extracted = self.bind(&input, |_, v| {
+                        let (pattern, group) = match group {
+                            0 => (format!("({})", pattern), 1),
+                            group => (pattern.clone(), *group),
+                        };
+                        Ok(format!(
+                            "to_jsonb((regexp_match({}, {}))[{}])",
+                            text(v),
+                            string(&pattern),
+                            group
+                        ))
+                    })?;
+                    *output = Output::Expr(extracted);
+                }
+                IR::CaseTransform(case @ (Case::Upper | Case::Lower)) => {
+                    let function = match case {
+                        Case::Upper => "upper",
+                        _ => "lower",
+                    };
+                    let converted = self.bind(&input, |_, v| {
+                        Ok(format!("to_jsonb({}({}))", function, text(v)))
+                    })?;
+                    *output = Output::Expr(converted);
+                }
+                IR::MapValues(table) => {
+                    let cases: Vec<_> = table
+                        .iter()
+                        .map(|(from, to)| format!(" WHEN {} THEN {}", jsonb(&from.0), jsonb(&to.0)))
+                        .collect();
+                    *output = Output::Expr(format!("CASE {}{} END", group(&input), cases.concat()));
+                }
+                IR::Concat(paths, sep) => {
+                    let concatenated = self.bind(&input, |g, v| {
+                        let mut strings = vec![];
+                        for path in paths {
+                            strings.push(g.bind(&at(v, path), |_, p| {
+                                Ok(format!(
+                                    "CASE WHEN jsonb_typeof({}) <> 'null' THEN {} END",
+                                    p,
+                                    text(p)
+                                ))
+                            })?);
+                        }
+                        Ok(format!(
+                            "to_jsonb(concat_ws({}, {}))",
+                            string(sep),
+                            strings.join(", ")
+                        ))
+                    })?;
+                    *output = Output::Expr(concatenated);
+                }
+                IR::Join(sep) => {
+                    let joined = self.bind(&input, |g, v| {
+                        let alias = g.fresh("a");
+                        let item = format!("{}.value", alias);
+                        Ok(format!(
+                            "CASE WHEN jsonb_typeof({v}) = 'array' THEN (SELECT to_jsonb(COALESCE(string_agg({text}, {sep} ORDER BY {a}.n), '')) FROM {elements}) END",
+                            v = v,
+                            text = text(&item),
+                            sep = string(sep),
+                            a = alias,
+                            elements = elements(v, &alias)
+                        ))
+                    })?;
+                    // the output is left as it was for other input
+                    let before = std::mem::replace(output, Output::Unwritten);
+                    *output = Output::Expr(match self.expr(before)? {
+                        Some(before) => format!("COALESCE({}, {})", joined, before),
+                        None => joined,
+                    });
+                }
+                IR::Const(value) => *output = Output::Expr(jsonb(&value.0)),
+                // instructions closing scopes which weren't opened do
+                // nothing
+                op if op.is_closer() => {}
+                _ => return Err(unsupported()),
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+/// A SQL expression transforming the `jsonb` value of `input`, such as a
+/// column, according to `ir`, if it can be written.
+pub fn expression(ir: &[IR], input: &str) -> Result<String, SqlErr> {
+    let output = Generator::default().scope(ir, 0, input.to_string())?;
+    Ok(output.unwrap_or_else(|| "'null'::jsonb".to_string()))
+}
+
+/// A `CREATE FUNCTION` statement making a function `name` which transforms
+/// its `jsonb` argument according to `ir`, if it can be written.
+pub fn function(ir: &[IR], name: &str) -> Result<String, SqlErr> {
+    let body = expression(ir, "input")?;
+    // dollar quotes can't appear in the body
+    let quote = if body.contains("$$") { "$body$" } else { "$$" };
+    Ok(format!(
+        "CREATE OR REPLACE FUNCTION {}(input jsonb) RETURNS jsonb\nLANGUAGE sql IMMUTABLE\nAS {}\nSELECT {}\n{};\n",
+        name, quote, body, quote
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{schema::Literal, test_util::key};
+
+    #[test]
+    fn test_object() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::Copy,
+            IR::PopKey,
+            IR::Nest(key("meta")),
+            IR::PushObj,
+            IR::PushKey(key("kind")),
+            IR::Const(Literal(json!("it's"))),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+            IR::Pipe,
+            IR::Extr(key("meta")),
+            IR::Extr(key("kind")),
+            IR::Copy,
+        ];
+        assert_eq!(
+            expression(&ir, "payload"),
+            Ok(concat!(
+                "(SELECT COALESCE(jsonb_object_agg(p.k, p.v), '{}'::jsonb) FROM (VALUES ",
+                "('id', payload -> 'user_id'), ",
+                "('meta', (SELECT COALESCE(jsonb_object_agg(p.k, p.v), '{}'::jsonb) FROM (VALUES ",
+                r#"('kind', '"it''s"'::jsonb)) AS p (k, v) WHERE p.v IS NOT NULL))) "#,
+                "AS p (k, v) WHERE p.v IS NOT NULL) -> 'meta' -> 'kind'"
+            )
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn test_function() {
+        let ir = [
+            IR::PushArr,
+            IR::G2G(Ground::String, Ground::Null),
+            IR::PopArr,
+        ];
+        assert_eq!(
+            function(&ir, "migrate").unwrap(),
+            "CREATE OR REPLACE FUNCTION migrate(input jsonb) RETURNS jsonb
+LANGUAGE sql IMMUTABLE
+AS $$
+SELECT (SELECT COALESCE(jsonb_agg(COALESCE('null'::jsonb, 'null'::jsonb) ORDER BY a1.n), '[]'::jsonb) FROM jsonb_array_elements(CASE WHEN jsonb_typeof(input) = 'array' THEN input END) WITH ORDINALITY AS a1 (value, n))
+$$;
+"
+        );
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let err = expression(&[IR::Inv, IR::Copy], "input").unwrap_err();
+        assert_eq!(err, SqlErr::Unsupported(0, IR::Inv));
+        assert_eq!(
+            err.to_string(),
+            "instruction 0 (`Inv`) has no SQL equivalent"
+        );
+    }
+}
@@ -0,0 +1,961 @@
+//! Swift generation: `Codable` structs for the source and target schemas,
+//! and a function converting one into the other field by field, for iOS
+//! clients moving between versions of an API's payloads.
+//!
+//! As with [`TypedRustCodegen`](super::rust::typed::TypedRustCodegen), only
+//! paths whose steps the types can express are converted that way. The rest
+//! are converted through JSON by the function [`JSCodegen`] generates, run
+//! in JavaScriptCore.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use serde_json::Value;
+
+use crate::{
+    ir::{scope_end, Case, IR},
+    schema::{Ground, Schema},
+};
+
+use super::{
+    js::JSCodegen,
+    rust::typed::{child, dynamic, nested, property, type_name},
+    Codegen,
+};
+
+/// Name of the type declared for the input.
+const SOURCE: &str = "Source";
+/// Name of the type declared for the output.
+const TARGET: &str = "Target";
+
+/// Keywords which can't name a property unless they're escaped.
+const KEYWORDS: &[&str] = &[
+    "Any",
+    "Self",
+    "as",
+    "associatedtype",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "continue",
+    "default",
+    "defer",
+    "deinit",
+    "do",
+    "else",
+    "enum",
+    "extension",
+    "fallthrough",
+    "false",
+    "fileprivate",
+    "for",
+    "func",
+    "guard",
+    "if",
+    "import",
+    "in",
+    "init",
+    "inout",
+    "internal",
+    "is",
+    "let",
+    "nil",
+    "open",
+    "operator",
+    "private",
+    "precedencegroup",
+    "protocol",
+    "public",
+    "repeat",
+    "rethrows",
+    "return",
+    "self",
+    "static",
+    "struct",
+    "subscript",
+    "super",
+    "switch",
+    "throw",
+    "throws",
+    "true",
+    "try",
+    "typealias",
+    "var",
+    "where",
+    "while",
+];
+
+/// Any JSON value, for data of unions and unconstrained schemas.
+const JSON_VALUE: &str = r#"enum JSONValue: Codable, Equatable {
+    case null
+    case bool(Bool)
+    case number(Double)
+    case string(String)
+    case array([JSONValue])
+    case object([String: JSONValue])
+
+    init(from decoder: Decoder) throws {
+        let container = try decoder.singleValueContainer()
+        if container.decodeNil() {
+            self = .null
+        } else if let value = try? container.decode(Bool.self) {
+            self = .bool(value)
+        } else if let value = try? container.decode(Double.self) {
+            self = .number(value)
+        } else if let value = try? container.decode(String.self) {
+            self = .string(value)
+        } else if let value = try? container.decode([JSONValue].self) {
+            self = .array(value)
+        } else {
+            self = .object(try container.decode([String: JSONValue].self))
+        }
+    }
+
+    func encode(to encoder: Encoder) throws {
+        var container = encoder.singleValueContainer()
+        switch self {
+        case .null: try container.encodeNil()
+        case .bool(let value): try container.encode(value)
+        case .number(let value): try container.encode(value)
+        case .string(let value): try container.encode(value)
+        case .array(let value): try container.encode(value)
+        case .object(let value): try container.encode(value)
+        }
+    }
+}
+"#;
+
+/// `null`, which Swift has no `Codable` type for.
+const JSON_NULL: &str = r#"struct JSONNull: Codable, Equatable {
+    init() {}
+
+    init(from decoder: Decoder) throws {
+        let container = try decoder.singleValueContainer()
+        guard container.decodeNil() else {
+            throw DecodingError.typeMismatch(
+                JSONNull.self,
+                DecodingError.Context(codingPath: decoder.codingPath, debugDescription: "expected null")
+            )
+        }
+    }
+
+    func encode(to encoder: Encoder) throws {
+        var container = encoder.singleValueContainer()
+        try container.encodeNil()
+    }
+}
+"#;
+
+/// Keys of objects with additional properties, which `CodingKeys` can't
+/// list.
+const ANY_KEY: &str = r#"struct AnyKey: CodingKey {
+    var stringValue: String
+    var intValue: Int? { return nil }
+
+    init(_ stringValue: String) {
+        self.stringValue = stringValue
+    }
+
+    init?(stringValue: String) {
+        self.stringValue = stringValue
+    }
+
+    init?(intValue: Int) {
+        return nil
+    }
+}
+"#;
+
+/// JavaScript's conversions between numbers and strings, which Swift makes
+/// differently.
+const RUNTIME: &str = r#"enum Runtime {
+    /// `String(n)` in JavaScript.
+    static func numberToString(_ n: Double) -> String {
+        if n.isNaN { return "NaN" }
+        if n.isInfinite { return n > 0 ? "Infinity" : "-Infinity" }
+        if n == 0 { return "0" }
+        // the shortest digits which read back as `n`, and where the point goes
+        let parts = "\(abs(n))".split(separator: "e")
+        let mantissa = parts[0]
+        var point = mantissa.firstIndex(of: ".").map { mantissa.distance(from: mantissa.startIndex, to: $0) } ?? mantissa.count
+        point += parts.count > 1 ? Int(parts[1])! : 0
+        var digits = Array(mantissa.filter { $0 != "." })
+        while digits.first == "0" {
+            digits.removeFirst()
+            point -= 1
+        }
+        while digits.last == "0" {
+            digits.removeLast()
+        }
+        let text: String
+        if digits.count <= point && point <= 21 {
+            text = String(digits) + String(repeating: "0", count: point - digits.count)
+        } else if 0 < point && point <= 21 {
+            text = String(digits[..<point]) + "." + String(digits[point...])
+        } else if -6 < point && point <= 0 {
+            text = "0." + String(repeating: "0", count: -point) + String(digits)
+        } else {
+            let exponent = point - 1
+            let fraction = digits.count > 1 ? "." + String(digits[1...]) : ""
+            text = String(digits[0]) + fraction + "e" + (exponent < 0 ? "-" : "+") + String(abs(exponent))
+        }
+        return n < 0 ? "-" + text : text
+    }
+
+    /// `Number(s)` in JavaScript.
+    static func stringToNumber(_ s: String) -> Double {
+        let s = s.trimmingCharacters(in: .whitespacesAndNewlines)
+        if s.isEmpty { return 0 }
+        for (prefix, radix) in [("0x", 16), ("0X", 16), ("0o", 8), ("0O", 8), ("0b", 2), ("0B", 2)] where s.hasPrefix(prefix) {
+            return UInt64(s.dropFirst(2), radix: radix).map { Double($0) } ?? .nan
+        }
+        switch s {
+        case "Infinity", "+Infinity": return .infinity
+        case "-Infinity": return -.infinity
+        default: break
+        }
+        // Swift reads `inf`, `nan` and hexadecimal fractions, which JavaScript doesn't
+        guard s.allSatisfy({ $0.isASCII && ($0.isNumber || ".eE+-".contains($0)) }) else { return .nan }
+        return Double(s) ?? .nan
+    }
+}
+"#;
+
+/// Generates Swift types for a source and target schema, and the conversion
+/// between them an IR path makes.
+pub struct SwiftCodegen {
+    source: Schema,
+    target: Schema,
+    /// The struct declared for each object, by its path from the root
+    /// type.
+    names: HashMap<Vec<String>, String>,
+    declarations: Vec<String>,
+    /// Whether the generated code uses `JSONValue`, `JSONNull`, `AnyKey`
+    /// and the runtime.
+    value: bool,
+    null: bool,
+    keys: bool,
+    runtime: bool,
+}
+
+/// The properties of an object's struct, by the property each holds, along
+/// with the property holding its additional properties, if it allows them.
+fn layout(schema: &Schema) -> (Vec<(Arc<String>, String)>, Option<String>) {
+    let Schema::Obj {
+        props, additional, ..
+    } = schema
+    else {
+        return (vec![], None);
+    };
+    let mut taken = HashSet::new();
+    let mut unique = |name: String| {
+        let mut unique = name.clone();
+        let mut n = 1;
+        while !taken.insert(unique.clone()) {
+            n += 1;
+            unique = format!("{}{}", name, n);
+        }
+        unique
+    };
+    let fields = props
+        .keys()
+        .map(|key| (key.clone(), unique(identifier(key))))
+        .collect();
+    let rest = additional.as_ref().map(|_| unique("rest".to_string()));
+    (fields, rest)
+}
+
+/// A camel case property name for property `key`.
+fn identifier(key: &str) -> String {
+    let name = type_name(&[key.to_string()]);
+    let mut chars = name.chars();
+    let name: String = match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    };
+    match name.as_str() {
+        "" => "field".to_string(),
+        name if KEYWORDS.contains(&name) => format!("`{}`", name),
+        name if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{}", name),
+        name => name.to_string(),
+    }
+}
+
+/// The property holding property `key` of the object `schema`.
+fn field_name(schema: &Schema, key: &str) -> Option<String> {
+    layout(schema)
+        .0
+        .into_iter()
+        .find(|(k, _)| k.as_str() == key)
+        .map(|(_, field)| field)
+}
+
+/// `s` as a string literal.
+fn string(s: &str) -> String {
+    let mut out = "\"".to_string();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `n` as a `Double` literal, if it can be written as one.
+fn number(n: f64) -> Option<String> {
+    n.is_finite().then(|| format!("{:?}", n))
+}
+
+/// `value` as a `JSONValue`.
+fn literal(value: &Value) -> String {
+    match value {
+        Value::Null => ".null".to_string(),
+        Value::Bool(b) => format!(".bool({})", b),
+        Value::Number(n) => format!(".number({:?})", n.as_f64().unwrap_or(0.0)),
+        Value::String(s) => format!(".string({})", string(s)),
+        Value::Array(items) => {
+            let items: Vec<_> = items.iter().map(literal).collect();
+            format!(".array([{}])", items.join(", "))
+        }
+        Value::Object(map) if map.is_empty() => ".object([:])".to_string(),
+        Value::Object(map) => {
+            let props: Vec<_> = map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", string(key), literal(value)))
+                .collect();
+            format!(".object([{}])", props.join(", "))
+        }
+    }
+}
+
+/// A call of the initializer of `name` with `args`, a line each.
+fn initializer(name: &str, args: &[String]) -> String {
+    if args.is_empty() {
+        return format!("{}()", name);
+    }
+    let args: Vec<_> = args
+        .iter()
+        .map(|arg| format!("    {}", nested(arg)))
+        .collect();
+    format!("{}(\n{}\n)", name, args.join(",\n"))
+}
+
+/// `value` computed from each `binding` of the collection `input` by the
+/// method `method`, or just `input` if it's unchanged.
+fn map(input: &str, method: &str, binding: &str, value: &str) -> String {
+    if value == binding {
+        input.to_string()
+    } else if value.contains('\n') {
+        let value = value.replace('\n', "\n    ");
+        format!("{}.{} {{ {} in\n    {}\n}}", input, method, binding, value)
+    } else {
+        format!("{}.{} {{ {} in {} }}", input, method, binding, value)
+    }
+}
+
+impl SwiftCodegen {
+    /// A generator for conversions from `source` data into `target` data.
+    pub fn new(source: &Schema, target: &Schema) -> Self {
+        // the types declared only follow the shape of the data
+        Self {
+            source: source.stripped(),
+            target: target.stripped(),
+            names: HashMap::new(),
+            declarations: vec![],
+            value: false,
+            null: false,
+            keys: false,
+            runtime: false,
+        }
+    }
+
+    /// Declare the root type `name`, aliasing the type of `schema` unless
+    /// it's an object.
+    fn declare_root(&mut self, name: &str, schema: &Schema) {
+        let path = vec![name.to_string()];
+        if let Schema::Obj { .. } = schema {
+            self.declare(schema, &path);
+            return;
+        }
+        let at = self.declarations.len();
+        self.declarations.push(String::new());
+        let ty = self.declare(schema, &path);
+        self.declarations[at] = format!("typealias {} = {}\n", name, ty);
+    }
+
+    /// The Swift type of `schema`, declaring structs for the objects in it,
+    /// named after their `path` from the root type.
+    fn declare(&mut self, schema: &Schema, path: &[String]) -> String {
+        match schema {
+            Schema::Ground(Ground::Num) => "Double".to_string(),
+            Schema::Ground(Ground::Bool) => "Bool".to_string(),
+            Schema::Ground(Ground::String) => "String".to_string(),
+            Schema::Ground(Ground::Null) => {
+                self.null = true;
+                "JSONNull".to_string()
+            }
+            Schema::Arr(item) => format!("[{}]", self.declare(item, &child(path, "item"))),
+            Schema::Obj {
+                props,
+                required,
+                additional,
+                ..
+            } => {
+                let mut name = type_name(path);
+                let taken: HashSet<_> = self.names.values().cloned().collect();
+                let mut n = 1;
+                while taken.contains(&name) {
+                    n += 1;
+                    name = format!("{}{}", type_name(path), n);
+                }
+                self.names.insert(path.to_vec(), name.clone());
+
+                // declared ahead of the structs of its properties
+                let at = self.declarations.len();
+                self.declarations.push(String::new());
+                let (fields, rest) = layout(schema);
+                let mut properties = String::new();
+                let mut keys = vec![];
+                let (mut decode, mut encode) = (vec![], vec![]);
+                for (key, field) in &fields {
+                    let ty = self.declare(&props[key], &child(path, key));
+                    let (decoding, encoding) = match required.contains(key) {
+                        true => ("decode", "encode"),
+                        false => ("decodeIfPresent", "encodeIfPresent"),
+                    };
+                    decode.push(format!(
+                        "        {} = try container.{}({}.self, forKey: AnyKey({}))\n",
+                        field,
+                        decoding,
+                        ty,
+                        string(key)
+                    ));
+                    encode.push(format!(
+                        "        try container.{}({}, forKey: AnyKey({}))\n",
+                        encoding,
+                        field,
+                        string(key)
+                    ));
+                    let optional = if required.contains(key) { "" } else { "?" };
+                    properties.push_str(&format!("    var {}: {}{}\n", field, ty, optional));
+                    keys.push(match field.trim_matches('`') == key.as_str() {
+                        true => format!("        case {}\n", field),
+                        false => format!("        case {} = {}\n", field, string(key)),
+                    });
+                }
+                let out = match (rest, additional) {
+                    (Some(rest), Some(additional)) => {
+                        // decoded by hand, to gather the additional properties
+                        self.keys = true;
+                        let ty = self.declare(additional, &child(path, "additional"));
+                        properties.push_str(&format!("    var {}: [String: {}]\n", rest, ty));
+                        let declared: Vec<_> = fields.iter().map(|(key, _)| string(key)).collect();
+                        let filter = match declared.is_empty() {
+                            true => String::new(),
+                            false => format!(
+                                " where ![{}].contains(key.stringValue)",
+                                declared.join(", ")
+                            ),
+                        };
+                        format!(
+                            "struct {name}: Equatable {{\n{properties}}}\n\nextension {name}: Codable {{\n    init(from decoder: Decoder) throws {{\n        let container = try decoder.container(keyedBy: AnyKey.self)\n{decode}        {rest} = [:]\n        for key in container.allKeys{filter} {{\n            {rest}[key.stringValue] = try container.decode({ty}.self, forKey: key)\n        }}\n    }}\n\n    func encode(to encoder: Encoder) throws {{\n        var container = encoder.container(keyedBy: AnyKey.self)\n{encode}        for (key, value) in {rest} {{\n            try container.encode(value, forKey: AnyKey(key))\n        }}\n    }}\n}}\n",
+                            decode = decode.concat(),
+                            encode = encode.concat(),
+                        )
+                    }
+                    _ if fields.iter().all(|(key, field)| field.trim_matches('`') == key.as_str()) => {
+                        format!("struct {}: Codable, Equatable {{\n{}}}\n", name, properties)
+                    }
+                    _ => format!(
+                        "struct {}: Codable, Equatable {{\n{}\n    enum CodingKeys: String, CodingKey {{\n{}    }}\n}}\n",
+                        name,
+                        properties,
+                        keys.concat()
+                    ),
+                };
+                self.declarations[at] = out;
+                name
+            }
+            Schema::Annotated(inner, _) => self.declare(inner, path),
+            Schema::Union(_) | Schema::Rec(..) | Schema::Var(_) | Schema::True | Schema::False => {
+                self.value = true;
+                "JSONValue".to_string()
+            }
+        }
+    }
+
+    /// An expression for what `body` writes, converting `input`, holding
+    /// `source` data, into `target` data, whose type is named after `path`.
+    /// `None` if the types can't express it.
+    fn convert(
+        &mut self,
+        body: &[IR],
+        input: &str,
+        source: &Schema,
+        target: &Schema,
+        path: &[String],
+    ) -> Option<String> {
+        let (mut input, mut source) = (input.to_string(), source);
+        for (i, op) in body.iter().enumerate() {
+            let last = i + 1 == body.len();
+            match op {
+                IR::Extr(key) => {
+                    let (prop, true) = property(source, key)? else {
+                        return None;
+                    };
+                    input = format!("{}.{}", input, field_name(source, key)?);
+                    source = prop;
+                }
+                IR::Wrap => {
+                    let Schema::Arr(item) = target else {
+                        return None;
+                    };
+                    let rest = &body[i + 1..];
+                    let item = self.convert(rest, &input, source, item, &child(path, "item"))?;
+                    return Some(format!("[{}]", item));
+                }
+                op if op.closer().is_some() => {
+                    // the rest of the body is one scope
+                    let inner = &body[i + 1..body.len() - 1];
+                    if scope_end(&body[i..])? != body.len() - i - 1 {
+                        return None;
+                    }
+                    return self.scope(op, inner, &input, source, target, path);
+                }
+                IR::Copy if last => return self.copy(&input, source, target, path),
+                _ if last => return self.ground(op, &input, source, target),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// An expression for a value converted by a single instruction `op`
+    /// from `input`, holding `source` data, into `target` data.
+    fn ground(&mut self, op: &IR, input: &str, source: &Schema, target: &Schema) -> Option<String> {
+        if let IR::Const(value) = op {
+            return match (target, &value.0) {
+                (Schema::Ground(Ground::Num), Value::Number(n)) => number(n.as_f64()?),
+                (Schema::Ground(Ground::String), Value::String(s)) => Some(string(s)),
+                (Schema::Ground(Ground::Bool), Value::Bool(b)) => Some(b.to_string()),
+                (Schema::Ground(Ground::Null), Value::Null) => Some("JSONNull()".to_string()),
+                (target, value) if dynamic(target) => Some(literal(value)),
+                _ => None,
+            };
+        }
+        let (Schema::Ground(from), Schema::Ground(to)) = (source, target) else {
+            return None;
+        };
+        match op {
+            IR::G2G(_, written) if written == to => Some(match (from, to) {
+                (Ground::Num, Ground::String) => {
+                    self.runtime = true;
+                    format!("Runtime.numberToString({})", input)
+                }
+                (Ground::Bool, Ground::String) => format!("String({})", input),
+                (Ground::Null, Ground::String) => "\"null\"".to_string(),
+                (Ground::String, Ground::Num) => {
+                    self.runtime = true;
+                    format!("Runtime.stringToNumber({})", input)
+                }
+                (Ground::Bool, Ground::Num) => format!("({} ? 1.0 : 0.0)", input),
+                (Ground::Null, Ground::Num) => "0.0".to_string(),
+                (Ground::Num, Ground::Bool) => format!("({} != 0 && !{}.isNaN)", input, input),
+                (Ground::String, Ground::Bool) => format!("!{}.isEmpty", input),
+                (Ground::Null, Ground::Bool) => "false".to_string(),
+                (_, Ground::Null) => "JSONNull()".to_string(),
+                _ => input.to_string(),
+            }),
+            IR::Scale { factor, offset } if (from, to) == (&Ground::Num, &Ground::Num) => Some(
+                format!("{} * {} + {}", input, number(factor.0)?, number(offset.0)?),
+            ),
+            IR::CaseTransform(case) if (from, to) == (&Ground::String, &Ground::String) => {
+                match case {
+                    Case::Upper => Some(format!("{}.uppercased()", input)),
+                    Case::Lower => Some(format!("{}.lowercased()", input)),
+                    // left to the JavaScript, which splits words for them
+                    Case::Camel | Case::Snake => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// An expression for `input`, holding `source` data, copied as `target`
+    /// data.
+    fn copy(
+        &mut self,
+        input: &str,
+        source: &Schema,
+        target: &Schema,
+        path: &[String],
+    ) -> Option<String> {
+        match (source, target) {
+            (Schema::Ground(a), Schema::Ground(b)) if a == b => Some(input.to_string()),
+            (source, target) if dynamic(source) && dynamic(target) => Some(input.to_string()),
+            (Schema::Ground(ground), target) if dynamic(target) => Some(match ground {
+                Ground::Null => ".null".to_string(),
+                Ground::Bool => format!(".bool({})", input),
+                Ground::Num => format!(".number({})", input),
+                Ground::String => format!(".string({})", input),
+            }),
+            (Schema::Arr(a), Schema::Arr(b)) => {
+                let item = self.copy("item", a, b, &child(path, "item"))?;
+                Some(map(input, "map", "item", &item))
+            }
+            (
+                Schema::Obj {
+                    additional: from, ..
+                },
+                Schema::Obj {
+                    props,
+                    required,
+                    additional: to,
+                    ..
+                },
+            ) => {
+                let name = self.names.get(path)?.clone();
+                let (fields, rest) = layout(target);
+                let mut args = vec![];
+                for (key, field) in fields {
+                    let path = child(path, &key);
+                    let optional = !required.contains(&key);
+                    let value = match property(source, &key) {
+                        None if optional => "nil".to_string(),
+                        None => return None,
+                        Some((from, true)) => self.copy(
+                            &format!("{}.{}", input, field_name(source, &key)?),
+                            from,
+                            &props[&key],
+                            &path,
+                        )?,
+                        Some((_, false)) if !optional => return None,
+                        Some((from, false)) => {
+                            let value = self.copy(&field, from, &props[&key], &path)?;
+                            self.optional(input, source, &key, &field, &value)?
+                        }
+                    };
+                    args.push(format!("{}: {}", field, value));
+                }
+                if let (Some(rest), Some(to)) = (rest, to) {
+                    let value = match (from, layout(source).1) {
+                        (Some(from), Some(from_rest)) => {
+                            let value = self.copy("value", from, to, &child(path, "additional"))?;
+                            let place = format!("{}.{}", input, from_rest);
+                            map(&place, "mapValues", "value", &value)
+                        }
+                        _ => "[:]".to_string(),
+                    };
+                    args.push(format!("{}: {}", rest, value));
+                }
+                Some(initializer(&name, &args))
+            }
+            _ => None,
+        }
+    }
+
+    /// An expression for optional property `key` of `input`, holding
+    /// `source` data, as `value`, written in terms of the property's value
+    /// bound to `binding`.
+    fn optional(
+        &mut self,
+        input: &str,
+        source: &Schema,
+        key: &str,
+        binding: &str,
+        value: &str,
+    ) -> Option<String> {
+        let place = format!("{}.{}", input, field_name(source, key)?);
+        Some(map(&place, "map", binding, value))
+    }
+
+    /// An expression for what the scope `op` opens writes, whose
+    /// instructions are `body`, converting `input` (holding `source` data)
+    /// into `target` data.
+    fn scope(
+        &mut self,
+        op: &IR,
+        body: &[IR],
+        input: &str,
+        source: &Schema,
+        target: &Schema,
+        path: &[String],
+    ) -> Option<String> {
+        match (op, source, target) {
+            (IR::PushArr, Schema::Arr(from), Schema::Arr(to)) => {
+                let item = self.convert(body, "item", from, to, &child(path, "item"))?;
+                Some(map(input, "map", "item", &item))
+            }
+            (
+                IR::PushObj,
+                _,
+                Schema::Obj {
+                    props,
+                    required,
+                    additional,
+                    ..
+                },
+            ) => {
+                let name = self.names.get(path)?.clone();
+                let mut written: HashMap<String, String> = HashMap::new();
+                let mut i = 0;
+                while i < body.len() {
+                    let end = scope_end(&body[i..])?;
+                    let (op, inner) = (&body[i], &body[i + 1..i + end]);
+                    i += end + 1;
+                    let (from, to) = match op {
+                        IR::PushKey(key) => (Some(key), key),
+                        IR::Rename(from, to) => (Some(from), to),
+                        IR::Nest(key) => (None, key),
+                        _ => return None,
+                    };
+                    let prop = props.get(to)?;
+                    let optional = !required.contains(to);
+                    let path = child(path, to);
+                    let value = match from.map(|from| (from, property(source, from))) {
+                        None => self.convert(inner, input, source, prop, &path)?,
+                        Some((from, Some((from_prop, true)))) => {
+                            let place = format!("{}.{}", input, field_name(source, from)?);
+                            self.convert(inner, &place, from_prop, prop, &path)?
+                        }
+                        Some((from, Some((from_prop, false)))) if optional => {
+                            let binding = field_name(source, from)?;
+                            let inner = match inner {
+                                [IR::PushOpt, inner @ .., IR::PopOpt] => inner,
+                                [IR::Copy] => inner,
+                                _ => return None,
+                            };
+                            let value = self.convert(inner, &binding, from_prop, prop, &path)?;
+                            self.optional(input, source, from, &binding, &value)?
+                        }
+                        _ => return None,
+                    };
+                    if written.insert(to.to_string(), value).is_some() {
+                        return None;
+                    }
+                }
+                let (fields, rest) = layout(target);
+                let mut args = vec![];
+                for (key, field) in fields {
+                    let value = match written.remove(key.as_str()) {
+                        Some(value) => value,
+                        None if !required.contains(&key) => "nil".to_string(),
+                        None => return None,
+                    };
+                    args.push(format!("{}: {}", field, value));
+                }
+                if let (Some(rest), Some(_)) = (rest, additional) {
+                    args.push(format!("{}: [:]", rest));
+                }
+                Some(initializer(&name, &args))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Codegen for SwiftCodegen {
+    fn generate(&mut self, ir: &[IR], input: &str, output: &str) -> String {
+        self.names.clear();
+        self.declarations.clear();
+        (self.value, self.null, self.keys, self.runtime) = (false, false, false, false);
+        let (source, target) = (self.source.clone(), self.target.clone());
+        self.declare_root(SOURCE, &source);
+        self.declare_root(TARGET, &target);
+
+        let converted = self.convert(ir, input, &source, &target, &[TARGET.to_string()]);
+        let (body, script) = match converted {
+            Some(value) => (format!("return {}", value), None),
+            None => {
+                // converted through JSON by the JavaScript transform
+                let function = JSCodegen::new().generate(ir, input, output);
+                let script = format!(
+                    "(function(json) {{\n  const transform = {};\n  return JSON.stringify(transform(JSON.parse(json)));\n}})",
+                    function.trim_end().replace('\n', "\n  ")
+                );
+                let body = format!(
+                    "let json = String(decoding: try! JSONEncoder().encode({}), as: UTF8.self)\nlet transformed = JSContext()!.evaluateScript(script)!.call(withArguments: [json])!.toString()!\nreturn try! JSONDecoder().decode({}.self, from: Data(transformed.utf8))",
+                    input, TARGET
+                );
+                (body, Some(script))
+            }
+        };
+
+        let mut out = "import Foundation\n".to_string();
+        if script.is_some() {
+            out.push_str("import JavaScriptCore\n");
+        }
+        for declaration in &self.declarations {
+            out.push('\n');
+            out.push_str(declaration);
+        }
+        out.push_str(&format!(
+            "\nfunc transform(_ {}: {}) -> {} {{\n    {}\n}}\n",
+            input,
+            SOURCE,
+            TARGET,
+            body.replace('\n', "\n    ")
+        ));
+        if let Some(script) = script {
+            // a raw string, delimited by enough `#`s to take it as it is
+            let mut hashes = "#".to_string();
+            while script.contains(&format!("\"{}", hashes))
+                || script.contains(&format!("\\{}", hashes))
+            {
+                hashes.push('#');
+            }
+            out.push_str(&format!(
+                "\n/// The JavaScript transform, taking and returning JSON.\nlet script = {}\"\"\"\n{}\n\"\"\"{}\n",
+                hashes, script, hashes
+            ));
+        }
+        let support = [
+            (self.value, JSON_VALUE),
+            (self.null, JSON_NULL),
+            (self.keys, ANY_KEY),
+            (self.runtime, RUNTIME),
+        ];
+        for (used, declaration) in support {
+            if used {
+                out.push('\n');
+                out.push_str(declaration);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{schema, test_util::key};
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(identifier("user_id"), "userId");
+        assert_eq!(identifier("default"), "`default`");
+        assert_eq!(identifier("2fa"), "_2fa");
+        assert_eq!(string("a \"b\"\n\u{1}"), r#""a \"b\"\n\u{1}""#);
+        assert_eq!(
+            literal(&serde_json::json!({"a": [1, null, "x"]})),
+            r#".object(["a": .array([.number(1.0), .null, .string("x")])])"#
+        );
+        let (fields, rest) = layout(&schema!({
+            "type": "object",
+            "properties": {"a_b": {"type": "number"}, "aB": {"type": "number"}},
+            "additionalProperties": {"type": "string"}
+        }));
+        let fields: Vec<_> = fields.into_iter().map(|(_, field)| field).collect();
+        assert_eq!(fields, ["aB", "aB2"]);
+        assert_eq!(rest.as_deref(), Some("rest"));
+    }
+
+    #[test]
+    fn test_typed_conversion() {
+        let source = schema!({
+            "type": "object",
+            "properties": {
+                "user_id": {"type": "number"},
+                "tags": {"type": "array", "items": {"type": "boolean"}},
+                "nick": {"type": "string"}
+            },
+            "required": ["user_id", "tags"]
+        });
+        let target = schema!({
+            "type": "object",
+            "properties": {
+                "userId": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "nick": {"type": "string"}
+            },
+            "required": ["userId", "tags"]
+        });
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("userId")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("tags")),
+            IR::PushArr,
+            IR::G2G(Ground::Bool, Ground::String),
+            IR::PopArr,
+            IR::PopKey,
+            IR::PushKey(key("nick")),
+            IR::PushOpt,
+            IR::CaseTransform(Case::Upper),
+            IR::PopOpt,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let code = SwiftCodegen::new(&source, &target).generate(&ir, "input", "output");
+        let end = code.find("\nenum Runtime {").unwrap();
+        assert_eq!(
+            &code[..end],
+            r#"import Foundation
+
+struct Source: Codable, Equatable {
+    var nick: String?
+    var tags: [Bool]
+    var userId: Double
+
+    enum CodingKeys: String, CodingKey {
+        case nick
+        case tags
+        case userId = "user_id"
+    }
+}
+
+struct Target: Codable, Equatable {
+    var nick: String?
+    var tags: [String]
+    var userId: String
+}
+
+func transform(_ input: Source) -> Target {
+    return Target(
+        nick: input.nick.map { nick in nick.uppercased() },
+        tags: input.tags.map { item in String(item) },
+        userId: Runtime.numberToString(input.userId)
+    )
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_converted_through_javascript() {
+        let source = schema!({"anyOf": [{"type": "number"}, {"type": "string"}]});
+        let target = schema!({"type": "string"});
+        let ir = [
+            IR::PushUnion,
+            IR::Case(Arc::new(Schema::Ground(Ground::Num))),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopCase,
+            IR::Case(Arc::new(Schema::string())),
+            IR::Copy,
+            IR::PopCase,
+            IR::PopUnion,
+        ];
+        let code = SwiftCodegen::new(&source, &target).generate(&ir, "input", "output");
+        assert!(code.contains("import JavaScriptCore\n"));
+        assert!(code.contains("\ntypealias Source = JSONValue\n\ntypealias Target = String\n"));
+        assert!(code.contains(
+            "return try! JSONDecoder().decode(Target.self, from: Data(transformed.utf8))\n}\n"
+        ));
+        assert!(code.contains(
+            "let script = #\"\"\"\n(function(json) {\n  const transform = function(input) {\n"
+        ));
+        assert!(code.contains("\nenum JSONValue: Codable, Equatable {\n"));
+    }
+}
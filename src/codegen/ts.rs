@@ -0,0 +1,263 @@
+//! TypeScript generation: the same transform as [`JSCodegen`] generates,
+//! typed by declarations derived from the source and target schemas, so
+//! the compiler checks that it reads its input and builds its output the
+//! way the schemas shape them.
+
+use crate::{
+    ir::IR,
+    schema::{Ground, Schema},
+};
+
+use super::{
+    js::{identifier, JSCodegen, Runtime},
+    Codegen, CodegenOptions,
+};
+
+/// Name of the type declared for the input.
+pub(super) const SOURCE: &str = "Source";
+/// Name of the type declared for the output.
+pub(super) const TARGET: &str = "Target";
+
+/// Generates a typed TypeScript function from an IR path, along with the
+/// types of its input and output.
+pub struct TSCodegen {
+    js: JSCodegen,
+    source: Schema,
+    target: Schema,
+}
+
+impl TSCodegen {
+    /// A generator for transforms converting `source` data into `target`
+    /// data.
+    pub fn new(source: &Schema, target: &Schema) -> Self {
+        Self::with_runtime(source, target, Runtime::default())
+    }
+
+    pub fn with_runtime(source: &Schema, target: &Schema, runtime: Runtime) -> Self {
+        let mut js = JSCodegen::with_runtime(runtime);
+        js.output_type = Some(TARGET.to_string());
+        Self {
+            js,
+            source: source.clone(),
+            target: target.clone(),
+        }
+    }
+}
+
+/// `key` as a property name, quoted unless it's an identifier.
+fn property(key: &str) -> String {
+    if identifier(key) {
+        key.to_string()
+    } else {
+        serde_json::Value::from(key).to_string()
+    }
+}
+
+/// The union of `types`, leaving out repeats.
+fn union(types: Vec<String>) -> String {
+    let mut branches: Vec<String> = vec![];
+    for ty in types {
+        if !branches.contains(&ty) {
+            branches.push(ty);
+        }
+    }
+    branches.join(" | ")
+}
+
+/// `schema` as a TypeScript type, where the type is written `depth` levels
+/// deep in the declarations.
+pub fn type_of(schema: &Schema, depth: usize) -> String {
+    match schema {
+        Schema::Ground(Ground::Num) => "number".to_string(),
+        Schema::Ground(Ground::Bool) => "boolean".to_string(),
+        Schema::Ground(Ground::String) => "string".to_string(),
+        Schema::Ground(Ground::Null) => "null".to_string(),
+        Schema::Arr(item) => match item.as_ref() {
+            Schema::Union(_) => format!("({})[]", type_of(item, depth)),
+            item => format!("{}[]", type_of(item, depth)),
+        },
+        Schema::Obj {
+            props,
+            required,
+            additional,
+            ..
+        } => {
+            if props.is_empty() && additional.is_none() {
+                return "{}".to_string();
+            }
+            let indent = "  ".repeat(depth + 1);
+            let mut out = "{\n".to_string();
+            let mut all = vec![];
+            for (key, prop) in props {
+                let ty = type_of(prop, depth + 1);
+                let optional = if required.contains(key) {
+                    ""
+                } else {
+                    all.push("undefined".to_string());
+                    "?"
+                };
+                out.push_str(&format!(
+                    "{}{}{}: {};\n",
+                    indent,
+                    property(key),
+                    optional,
+                    ty
+                ));
+                all.push(ty);
+            }
+            if let Some(additional) = additional {
+                // the declared properties have to fit the index signature too
+                all.insert(0, type_of(additional, depth + 1));
+                out.push_str(&format!("{}[key: string]: {};\n", indent, union(all)));
+            }
+            out.push_str(&format!("{}}}", "  ".repeat(depth)));
+            out
+        }
+        Schema::Union(branches) => union(
+            branches
+                .iter()
+                .map(|branch| type_of(branch, depth))
+                .collect(),
+        ),
+        Schema::Annotated(inner, annotations) => {
+            // the values an enumeration allows, if they can be written as
+            // literal types
+            let literals = annotations.values.as_ref().filter(|values| {
+                values
+                    .iter()
+                    .all(|value| !value.0.is_array() && !value.0.is_object())
+            });
+            match literals {
+                Some(values) => union(values.iter().map(|value| value.0.to_string()).collect()),
+                None => type_of(inner, depth),
+            }
+        }
+        Schema::Rec(_, body) => type_of(body, depth),
+        Schema::True | Schema::Var(_) => "unknown".to_string(),
+        Schema::False => "never".to_string(),
+    }
+}
+
+/// A declaration of type `name` for `schema`: an interface if it's an
+/// object, and an alias otherwise.
+pub(super) fn declaration(name: &str, schema: &Schema) -> String {
+    match schema {
+        Schema::Obj { .. } => format!("interface {} {}\n", name, type_of(schema, 0)),
+        _ => format!("type {} = {};\n", name, type_of(schema, 0)),
+    }
+}
+
+impl Codegen for TSCodegen {
+    fn generate(&mut self, ir: &[IR], input: &str, output: &str) -> String {
+        let body = self.js.generate_body(ir, input, output);
+        format!(
+            "{}\n{}\nfunction {}({}: {}): {} {{\n{}}}\n",
+            declaration(SOURCE, &self.source),
+            declaration(TARGET, &self.target),
+            self.js.options.name,
+            input,
+            SOURCE,
+            TARGET,
+            body
+        )
+    }
+
+    fn generate_with(&mut self, ir: &[IR], options: &CodegenOptions) -> String {
+        self.js.options = options.clone();
+        self.generate(ir, &options.input, &options.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{schema, test_util::key};
+
+    #[test]
+    fn test_types() {
+        let schema = schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "kind": {"type": "string", "enum": ["a", "b"]},
+                "tags": {
+                    "type": "array",
+                    "items": {"anyOf": [{"type": "string"}, {"type": "null"}]}
+                },
+                "user-name": {"type": "string"}
+            },
+            "required": ["id", "tags"],
+            "additionalProperties": {"type": "boolean"}
+        });
+        assert_eq!(
+            declaration("Source", &schema),
+            r#"interface Source {
+  id: number;
+  kind?: "a" | "b";
+  tags: (string | null)[];
+  "user-name"?: string;
+  [key: string]: boolean | number | undefined | "a" | "b" | (string | null)[] | string;
+}
+"#
+        );
+        assert_eq!(
+            declaration(
+                "Target",
+                &schema!({"type": "array", "items": {"type": "number"}})
+            ),
+            "type Target = number[];\n"
+        );
+    }
+
+    #[test]
+    fn test_typed_function() {
+        let source = schema!({
+            "type": "object",
+            "properties": {"n": {"type": "number"}},
+            "required": ["n"]
+        });
+        let target = schema!({
+            "type": "object",
+            "properties": {
+                "user": {
+                    "type": "object",
+                    "properties": {"count": {"type": "string"}},
+                    "required": ["count"]
+                }
+            },
+            "required": ["user"]
+        });
+        let ir = [
+            IR::PushObj,
+            IR::Nest(key("user")),
+            IR::PushObj,
+            IR::Rename(key("n"), key("count")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            TSCodegen::new(&source, &target).generate(&ir, "input", "output"),
+            r#"interface Source {
+  n: number;
+}
+
+interface Target {
+  user: {
+    count: string;
+  };
+}
+
+function transform(input: Source): Target {
+  let output!: Target;
+  output = {} as Target;
+  output.user = {} as NonNullable<Target["user"]>;
+  output.user.count = String(input.n);
+  return output;
+}
+"#
+        );
+    }
+}
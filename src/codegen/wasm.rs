@@ -0,0 +1,1039 @@
+//! WebAssembly generation: a module transforming JSON text, for running
+//! transforms sandboxed in any host with a WebAssembly engine, without
+//! generating source in its language.
+//!
+//! The module exports its `memory`, `alloc(n)`, which makes room for `n`
+//! bytes of input and returns where they go, `transform(p, n)`, which
+//! transforms the input written there and returns where its output is, and
+//! `length()`, the length of that output. From JavaScript, say:
+//!
+//! ```js
+//! const { memory, alloc, transform, length } = instance.exports;
+//! const input = new TextEncoder().encode(json);
+//! const p = alloc(input.length);
+//! new Uint8Array(memory.buffer, p, input.length).set(input);
+//! const out = transform(p, input.length);
+//! const output = new TextDecoder().decode(new Uint8Array(memory.buffer, out, length()));
+//! ```
+//!
+//! Each call to `alloc` frees what the last transform took. Transforms work
+//! on the text of the input rather than parsing it, copying what they copy
+//! as it's written, so they take well-formed JSON and only ever write JSON
+//! equal to what the JavaScript writes. Except that properties looked up by
+//! key are found by comparing it with their keys as they're written, and
+//! numbers are converted to strings by their digits without rounding them
+//! to a double's precision, they convert values like the JavaScript does,
+//! but only upper and lower case ASCII letters. Paths sorting, hashing,
+//! doing arithmetic or merging objects, among others, can't be compiled, and
+//! compiling one reports the first instruction standing in the way.
+
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    ir::{scope_end, text, Case, IR},
+    runtime::number_to_string,
+    schema::{Ground, Schema},
+};
+
+use assembler::{Function, Module};
+
+pub mod assembler;
+pub mod runtime;
+
+/// Where the constants the code writes are laid out from.
+const DATA: usize = 16;
+
+/// Error compiling a WebAssembly module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WasmErr {
+    /// The instruction at this index has no WebAssembly equivalent.
+    Unsupported(usize, IR),
+}
+
+impl Display for WasmErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(i, op) => write!(
+                f,
+                "instruction {} (`{}`) has no WebAssembly equivalent",
+                i,
+                text::print(std::slice::from_ref(op)).trim_end()
+            ),
+        }
+    }
+}
+
+/// A value, held in a pair of locals.
+#[derive(Clone)]
+struct Slot {
+    ptr: String,
+    len: String,
+}
+
+/// Where a scope writes: from `start` on, with its output so far from
+/// `begin` up to `stop`, past anything it wrote along the way.
+struct Frame {
+    start: String,
+    begin: String,
+    stop: String,
+}
+
+/// `key`'s bytes as they're written in JSON, without quotes.
+fn escaped(key: &str) -> Vec<u8> {
+    let quoted = Value::from(key).to_string();
+    quoted.as_bytes()[1..quoted.len() - 1].to_vec()
+}
+
+#[derive(Default)]
+struct Compiler {
+    /// The constants the code writes, laid out from `DATA`.
+    data: Vec<u8>,
+    locals: Vec<String>,
+    /// The flags of the open union dispatches, set once a case has run.
+    unions: Vec<String>,
+    code: String,
+    indent: usize,
+}
+
+impl Compiler {
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.code.push_str("  ");
+        }
+        self.code.push_str(line);
+        self.code.push('\n');
+    }
+
+    /// A fresh local.
+    fn local(&mut self, prefix: &str) -> String {
+        let name = format!("{}{}", prefix, self.locals.len());
+        self.locals.push(name.clone());
+        name
+    }
+
+    /// A fresh pair of locals holding `undefined`.
+    fn slot(&mut self) -> Slot {
+        let slot = Slot {
+            ptr: self.local("p"),
+            len: self.local("n"),
+        };
+        self.line(&format!("i32.const 0 local.set {}", slot.len));
+        slot
+    }
+
+    /// The address and length of `bytes` among the constants.
+    fn constant(&mut self, bytes: &[u8]) -> (usize, usize) {
+        let found = (!bytes.is_empty())
+            .then(|| self.data.windows(bytes.len()).position(|w| w == bytes))
+            .flatten();
+        let at = found.unwrap_or_else(|| {
+            self.data.extend_from_slice(bytes);
+            self.data.len() - bytes.len()
+        });
+        (DATA + at, bytes.len())
+    }
+
+    /// Write `bytes`.
+    fn emit(&mut self, bytes: &[u8]) {
+        let (at, len) = self.constant(bytes);
+        self.line(&format!("i32.const {} i32.const {} call $emit", at, len));
+    }
+
+    /// Write the byte `c`.
+    fn byte(&mut self, c: char) {
+        self.line(&format!("i32.const {} call $byte", c as u32));
+    }
+
+    /// Property `key` of `input`.
+    fn get(&mut self, input: &Slot, key: &str) -> Slot {
+        let (at, len) = self.constant(&escaped(key));
+        let slot = Slot {
+            ptr: self.local("p"),
+            len: self.local("n"),
+        };
+        self.line(&format!(
+            "local.get {} local.get {} i32.const {} i32.const {} call $get local.set {} local.set {}",
+            input.ptr, input.len, at, len, slot.len, slot.ptr
+        ));
+        slot
+    }
+
+    /// The value at `path` into `input`.
+    fn at(&mut self, input: &Slot, path: &[Arc<String>]) -> Slot {
+        path.iter()
+            .fold(input.clone(), |slot, key| self.get(&slot, key))
+    }
+
+    /// An expression for `kind` of `input` (see the runtime).
+    fn kind(input: &Slot) -> String {
+        format!("local.get {} local.get {} call $kind", input.ptr, input.len)
+    }
+
+    /// A frame writing from where the output is now.
+    fn enter(&mut self) -> Frame {
+        let frame = Frame {
+            start: self.local("start"),
+            begin: self.local("begin"),
+            stop: self.local("stop"),
+        };
+        self.line(&format!(
+            "global.get $out local.tee {} local.tee {} local.set {}",
+            frame.start, frame.begin, frame.stop
+        ));
+        frame
+    }
+
+    /// Move the output of `frame` to its start, leaving the rest behind.
+    fn leave(&mut self, frame: &Frame) {
+        let Frame { start, begin, stop } = frame;
+        self.line(&format!(
+            "local.get {start} local.get {begin} local.get {stop} local.get {begin} i32.sub memory.copy"
+        ));
+        self.line(&format!(
+            "local.get {start} local.get {stop} local.get {begin} i32.sub i32.add global.set $out"
+        ));
+    }
+
+    /// Start writing the output of `frame` afresh.
+    fn writing(&mut self, frame: &Frame) {
+        self.line(&format!("global.get $out local.set {}", frame.begin));
+    }
+
+    /// Finish writing the output of `frame`.
+    fn written(&mut self, frame: &Frame) {
+        self.line(&format!("global.get $out local.set {}", frame.stop));
+    }
+
+    /// Open a block only run for each item of `input`, if it's an array,
+    /// with the item held in the slot returned, closed by `close_items`.
+    fn items(&mut self, input: &Slot) -> (Slot, String, String) {
+        let (limit, p) = (self.local("limit"), self.local("i"));
+        let item = Slot {
+            ptr: self.local("p"),
+            len: self.local("n"),
+        };
+        self.line(&format!("{} i32.const 5 i32.eq", Self::kind(input)));
+        self.line("if");
+        self.indent += 1;
+        self.line(&format!(
+            "local.get {ptr} local.get {len} i32.add i32.const 1 i32.sub local.set {limit}",
+            ptr = input.ptr,
+            len = input.len
+        ));
+        self.line(&format!(
+            "local.get {} i32.const 1 i32.add local.set {}",
+            input.ptr, p
+        ));
+        self.line("block loop");
+        self.indent += 1;
+        self.line(&format!(
+            "local.get {p} local.get {limit} call $ws local.tee {p} local.get {limit} i32.ge_u br_if 1"
+        ));
+        self.line(&format!(
+            "local.get {p} local.tee {ptr} local.get {limit} call $skip local.get {p} i32.sub local.tee {len}",
+            ptr = item.ptr,
+            len = item.len
+        ));
+        self.line("i32.eqz br_if 1");
+        (item, p, limit)
+    }
+
+    /// Close the block `items` opened.
+    fn close_items(&mut self, item: &Slot, p: &str, limit: &str) {
+        self.line(&format!(
+            "local.get {} local.get {} i32.add local.get {} call $next local.set {}",
+            item.ptr, item.len, limit, p
+        ));
+        self.line("br 0");
+        self.indent -= 1;
+        self.line("end end");
+        self.indent -= 1;
+        self.line("end");
+    }
+
+    /// Write a property of the object being written, whose key is written
+    /// by `key`, and whose value `body` writes from `input`, leaving it out
+    /// if `body` writes nothing.
+    fn property(
+        &mut self,
+        key: impl FnOnce(&mut Self),
+        body: &[IR],
+        base: usize,
+        input: &Slot,
+    ) -> Result<(), WasmErr> {
+        let mark = self.local("mark");
+        self.line(&format!(
+            "global.get $out local.tee {} i32.const 1 i32.sub i32.load8_u i32.const 123 i32.ne",
+            mark
+        ));
+        self.line("if i32.const 44 call $byte end");
+        key(self);
+        let frame = self.enter();
+        self.run(body, base, &frame, input)?;
+        self.leave(&frame);
+        self.line(&format!(
+            "global.get $out local.get {} i32.eq if local.get {} global.set $out end",
+            frame.start, mark
+        ));
+        Ok(())
+    }
+
+    /// Write the properties the scopes of `body`, inside an object scope,
+    /// write from `input`.
+    fn properties(&mut self, body: &[IR], base: usize, input: &Slot) -> Result<(), WasmErr> {
+        let mut i = 0;
+        while i < body.len() {
+            let op = &body[i];
+            let end = scope_end(&body[i..]).map_or(body.len(), |end| i + end);
+            let inner = &body[i + 1..end.min(body.len())];
+            let (value, key) = match op {
+                IR::PushKey(key) => (self.get(input, key), key),
+                IR::Rename(from, to) => (self.get(input, from), to),
+                IR::Nest(key) => (input.clone(), key),
+                IR::PushRest(named) => {
+                    self.rest(named, inner, base + i + 1, input)?;
+                    i = end + 1;
+                    continue;
+                }
+                op if op.is_closer() => {
+                    i += 1;
+                    continue;
+                }
+                op => return Err(WasmErr::Unsupported(base + i, op.clone())),
+            };
+            let key = [b"\"".as_slice(), &escaped(key), b"\":"].concat();
+            self.property(|c| c.emit(&key), inner, base + i + 1, &value)?;
+            i = end + 1;
+        }
+        Ok(())
+    }
+
+    /// Write the properties of `input` other than `named`, each as `body`
+    /// writes it.
+    fn rest(
+        &mut self,
+        named: &[Arc<String>],
+        body: &[IR],
+        base: usize,
+        input: &Slot,
+    ) -> Result<(), WasmErr> {
+        let (limit, p, q) = (self.local("limit"), self.local("i"), self.local("q"));
+        let value = Slot {
+            ptr: self.local("p"),
+            len: self.local("n"),
+        };
+        self.line(&format!("{} i32.const 6 i32.eq", Self::kind(input)));
+        self.line("if");
+        self.indent += 1;
+        self.line(&format!(
+            "local.get {} local.get {} i32.add i32.const 1 i32.sub local.set {}",
+            input.ptr, input.len, limit
+        ));
+        self.line(&format!(
+            "local.get {} i32.const 1 i32.add local.set {}",
+            input.ptr, p
+        ));
+        self.line("block loop");
+        self.indent += 1;
+        self.line(&format!(
+            "local.get {p} local.get {limit} call $ws local.tee {p} local.get {limit} i32.ge_u br_if 1"
+        ));
+        self.line(&format!(
+            "local.get {p} local.get {limit} call $skip local.tee {q} local.get {p} i32.eq br_if 1"
+        ));
+        self.line(&format!(
+            "local.get {q} local.get {limit} call $ws i32.const 1 i32.add local.get {limit} call $ws local.tee {ptr}",
+            ptr = value.ptr
+        ));
+        self.line(&format!(
+            "local.get {limit} call $skip local.get {ptr} i32.sub local.set {len}",
+            ptr = value.ptr,
+            len = value.len
+        ));
+        // the key, at `p` up to `q`, which is kept for the next property
+        let key = self.local("key");
+        self.line(&format!("local.get {} local.set {}", p, key));
+        self.line(&format!(
+            "local.get {} local.get {} i32.add local.get {} call $next local.set {}",
+            value.ptr, value.len, limit, p
+        ));
+        for name in named {
+            let (at, len) = self.constant(&escaped(name));
+            self.line(&format!(
+                "local.get {key} i32.const 1 i32.add local.get {q} local.get {key} i32.sub i32.const 2 i32.sub i32.const {at} i32.const {len} call $eq br_if 0"
+            ));
+        }
+        self.property(
+            |c| {
+                c.line(&format!(
+                    "local.get {key} local.get {q} local.get {key} i32.sub call $emit i32.const 58 call $byte"
+                ))
+            },
+            body,
+            base,
+            &value,
+        )?;
+        self.line("br 0");
+        self.indent -= 1;
+        self.line("end end");
+        self.indent -= 1;
+        self.line("end");
+        Ok(())
+    }
+
+    /// An expression for whether `input` has the shape of `schema`,
+    /// telling objects apart by their required properties.
+    fn test(&mut self, schema: &Schema, input: &Slot) -> String {
+        let kind = |code: u8| format!("{} i32.const {} i32.eq", Self::kind(input), code);
+        match schema {
+            Schema::True | Schema::Var(_) => "i32.const 1".to_string(),
+            Schema::False => "i32.const 0".to_string(),
+            Schema::Ground(Ground::Null) => kind(1),
+            Schema::Ground(Ground::Bool) => kind(2),
+            Schema::Ground(Ground::Num) => kind(3),
+            Schema::Ground(Ground::String) => kind(4),
+            Schema::Arr(_) => kind(5),
+            Schema::Obj { required, .. } => {
+                let mut test = kind(6);
+                for key in required {
+                    let (at, len) = self.constant(&escaped(key));
+                    let found = self.local("n");
+                    test.push_str(&format!(
+                        " local.get {} local.get {} i32.const {} i32.const {} call $get local.set {} drop local.get {} i32.const 0 i32.ne i32.and",
+                        input.ptr, input.len, at, len, found, found
+                    ));
+                }
+                test
+            }
+            Schema::Union(branches) => {
+                let mut tests = branches.iter().map(|branch| self.test(branch, input));
+                let first = tests.next().unwrap_or_else(|| "i32.const 0".to_string());
+                tests.fold(first, |test, branch| format!("{} {} i32.or", test, branch))
+            }
+            Schema::Rec(_, body) | Schema::Annotated(body, _) => self.test(body, input),
+        }
+    }
+
+    /// Run the instructions of a scope `ir` on `input`, writing to `frame`.
+    /// `base` is the index of `ir`'s first instruction in the whole path.
+    /// Returns whether it might write anything.
+    fn run(
+        &mut self,
+        ir: &[IR],
+        base: usize,
+        frame: &Frame,
+        input: &Slot,
+    ) -> Result<bool, WasmErr> {
+        let mut input = input.clone();
+        let mut writes = false;
+        let mut i = 0;
+        while i < ir.len() {
+            let op = &ir[i];
+            let unsupported = || WasmErr::Unsupported(base + i, op.clone());
+            if op.closer().is_some() {
+                let end = scope_end(&ir[i..]).map_or(ir.len(), |end| i + end);
+                let body = &ir[i + 1..end.min(ir.len())];
+                let inner = base + i + 1;
+                match op {
+                    IR::PushObj => {
+                        self.writing(frame);
+                        self.byte('{');
+                        self.properties(body, inner, &input)?;
+                        self.byte('}');
+                        self.written(frame);
+                        writes = true;
+                    }
+                    IR::PushOpt => {
+                        self.line(&format!("local.get {} if", input.len));
+                        self.indent += 1;
+                        writes |= self.run(body, inner, frame, &input)?;
+                        self.indent -= 1;
+                        self.line("end");
+                    }
+                    IR::PushUnion => {
+                        let started = self.local("started");
+                        self.line(&format!("i32.const 0 local.set {}", started));
+                        self.unions.push(started);
+                        writes |= self.run(body, inner, frame, &input)?;
+                        self.unions.pop();
+                    }
+                    IR::Case(branch) => {
+                        let test = self.test(branch, &input);
+                        match self.unions.last().cloned() {
+                            Some(started) => {
+                                self.line(&format!(
+                                    "local.get {} i32.eqz {} i32.and",
+                                    started, test
+                                ));
+                                self.line("if");
+                                self.line(&format!("  i32.const 1 local.set {}", started));
+                            }
+                            None => {
+                                self.line(&test);
+                                self.line("if");
+                            }
+                        }
+                        self.indent += 1;
+                        writes |= self.run(body, inner, frame, &input)?;
+                        self.indent -= 1;
+                        self.line("end");
+                    }
+                    IR::PushArr => {
+                        self.writing(frame);
+                        self.byte('[');
+                        let (item, p, limit) = self.items(&input);
+                        self.line(
+                            "global.get $out i32.const 1 i32.sub i32.load8_u i32.const 91 i32.ne",
+                        );
+                        self.line("if i32.const 44 call $byte end");
+                        let written = self.enter();
+                        self.run(body, inner, &written, &item)?;
+                        self.leave(&written);
+                        // `undefined` items are written as `null`
+                        self.line(&format!(
+                            "global.get $out local.get {} i32.eq",
+                            written.start
+                        ));
+                        self.line("if");
+                        self.indent += 1;
+                        self.emit(b"null");
+                        self.indent -= 1;
+                        self.line("end");
+                        self.close_items(&item, &p, &limit);
+                        self.byte(']');
+                        self.written(frame);
+                        writes = true;
+                    }
+                    // properties are only written into objects
+                    _ => return Err(unsupported()),
+                }
+                i = end + 1;
+                continue;
+            }
+            match op {
+                IR::Copy => {
+                    self.writing(frame);
+                    self.line(&format!(
+                        "local.get {} local.get {} call $emit",
+                        input.ptr, input.len
+                    ));
+                    self.written(frame);
+                    writes = true;
+                }
+                IR::G2G(_, to) => {
+                    self.writing(frame);
+                    let call = |f: &str| {
+                        format!(
+                            "local.get {} local.get {} call ${}",
+                            input.ptr, input.len, f
+                        )
+                    };
+                    match to {
+                        Ground::String => {
+                            self.byte('"');
+                            self.line(&call("str"));
+                            self.byte('"');
+                        }
+                        Ground::Num => self.line(&call("num")),
+                        Ground::Bool => {
+                            self.line(&call("truthy"));
+                            self.line("if");
+                            self.indent += 1;
+                            self.emit(b"true");
+                            self.indent -= 1;
+                            self.line("else");
+                            self.indent += 1;
+                            self.emit(b"false");
+                            self.indent -= 1;
+                            self.line("end");
+                        }
+                        Ground::Null => self.emit(b"null"),
+                    }
+                    self.written(frame);
+                    writes = true;
+                }
+                IR::Const(value) => {
+                    self.writing(frame);
+                    self.emit(value.0.to_string().as_bytes());
+                    self.written(frame);
+                    writes = true;
+                }
+                IR::Wrap => {
+                    // the rest of the scope writes inside the array
+                    self.writing(frame);
+                    self.byte('[');
+                    let inside = self.enter();
+                    let wraps = self.run(&ir[i + 1..], base + i + 1, &inside, &input)?;
+                    self.leave(&inside);
+                    if wraps {
+                        // as `undefined`, written as `null`
+                        self.line(&format!(
+                            "global.get $out local.get {} i32.eq",
+                            inside.start
+                        ));
+                        self.line("if");
+                        self.indent += 1;
+                        self.emit(b"null");
+                        self.indent -= 1;
+                        self.line("end");
+                    }
+                    self.byte(']');
+                    self.written(frame);
+                    return Ok(true);
+                }
+                IR::Pipe => {
+                    let piped = self.slot();
+                    self.line(&format!(
+                        "local.get {begin} local.set {ptr} local.get {stop} local.get {begin} i32.sub local.set {len}",
+                        begin = frame.begin,
+                        stop = frame.stop,
+                        ptr = piped.ptr,
+                        len = piped.len
+                    ));
+                    input = piped;
+                }
+                IR::Extr(key) => input = self.get(&input, key),
+                IR::Coalesce(paths) => {
+                    let found = self.slot();
+                    self.line("block");
+                    self.indent += 1;
+                    for path in paths {
+                        let value = self.at(&input, path);
+                        self.line(&format!(
+                            "local.get {} local.set {} local.get {} local.set {}",
+                            value.ptr, found.ptr, value.len, found.len
+                        ));
+                        self.line(&format!(
+                            "{} i32.const 1 i32.gt_u br_if 0",
+                            Self::kind(&value)
+                        ));
+                    }
+                    self.indent -= 1;
+                    self.line("end");
+                    input = found;
+                }
+                IR::First => {
+                    let first = self.slot();
+                    let limit = self.local("limit");
+                    self.line(&format!("{} i32.const 5 i32.eq", Self::kind(&input)));
+                    self.line("if");
+                    self.line(&format!(
+                        "  local.get {p} i32.const 1 i32.add local.get {p} local.get {n} i32.add i32.const 1 i32.sub local.tee {limit}",
+                        p = input.ptr,
+                        n = input.len
+                    ));
+                    self.line(&format!(
+                        "  call $ws local.tee {ptr} local.get {limit} call $skip local.get {ptr} i32.sub local.set {len}",
+                        ptr = first.ptr,
+                        len = first.len
+                    ));
+                    self.line("end");
+                    input = first;
+                }
+                IR::Flatten(depth) => {
+                    let flat = self.slot();
+                    self.line(&format!("{} i32.const 5 i32.eq", Self::kind(&input)));
+                    self.line("if");
+                    self.indent += 1;
+                    self.line(&format!("global.get $out local.set {}", flat.ptr));
+                    self.byte('[');
+                    self.line(&format!(
+                        "local.get {} local.get {} i32.const {} call $flat",
+                        input.ptr, input.len, depth
+                    ));
+                    // over the comma after the last item, if there is one
+                    self.line(
+                        "global.get $out i32.const 1 i32.sub i32.load8_u i32.const 44 i32.eq",
+                    );
+                    self.line("if global.get $out i32.const 1 i32.sub global.set $out end");
+                    self.byte(']');
+                    self.line(&format!(
+                        "global.get $out local.get {} i32.sub local.set {}",
+                        flat.ptr, flat.len
+                    ));
+                    self.indent -= 1;
+                    self.line("end");
+                    input = flat;
+                }
+                IR::Slice(start, end) => {
+                    let sliced = self.slot();
+                    let index = self.local("index");
+                    self.line(&format!("global.get $out local.set {}", sliced.ptr));
+                    self.line(&format!("i32.const 0 local.set {}", index));
+                    self.byte('[');
+                    let (item, p, limit) = self.items(&input);
+                    let before_end = match end {
+                        Some(end) => {
+                            format!(" local.get {} i32.const {} i32.lt_u i32.and", index, end)
+                        }
+                        None => String::new(),
+                    };
+                    self.line(&format!(
+                        "local.get {} i32.const {} i32.ge_u{}",
+                        index, start, before_end
+                    ));
+                    self.line("if");
+                    self.line(
+                        "  global.get $out i32.const 1 i32.sub i32.load8_u i32.const 91 i32.ne",
+                    );
+                    self.line("  if i32.const 44 call $byte end");
+                    self.line(&format!(
+                        "  local.get {} local.get {} call $emit",
+                        item.ptr, item.len
+                    ));
+                    self.line("end");
+                    self.line(&format!(
+                        "local.get {} i32.const 1 i32.add local.set {}",
+                        index, index
+                    ));
+                    self.close_items(&item, &p, &limit);
+                    self.byte(']');
+                    // only an array if the input was one
+                    self.line(&format!(
+                        "{} i32.const 5 i32.eq global.get $out local.get {} i32.sub i32.mul local.set {}",
+                        Self::kind(&input),
+                        sliced.ptr,
+                        sliced.len
+                    ));
+                    input = sliced;
+                }
+                IR::CaseTransform(case @ (Case::Upper | Case::Lower)) => {
+                    self.writing(frame);
+                    self.byte('"');
+                    self.line(&format!(
+                        "local.get {} local.get {} call $str",
+                        input.ptr, input.len
+                    ));
+                    self.line(&format!(
+                        "local.get {} i32.const 1 i32.add i32.const {} call $case",
+                        frame.begin,
+                        u8::from(*case == Case::Upper)
+                    ));
+                    self.byte('"');
+                    self.written(frame);
+                    writes = true;
+                }
+                IR::MapValues(table) => {
+                    self.writing(frame);
+                    let (canonical, found) = (self.local("canonical"), self.local("found"));
+                    self.line("block");
+                    self.indent += 1;
+                    for (from, to) in table {
+                        let test = match &from.0 {
+                            // numbers as `String` writes them, written past the output
+                            Value::Number(n) => {
+                                let n = number_to_string(n.as_f64().unwrap_or(f64::NAN));
+                                let (at, len) = self.constant(n.as_bytes());
+                                self.line(&format!(
+                                    "global.get $out local.set {} i32.const 0 local.set {}",
+                                    canonical, found
+                                ));
+                                self.line(&format!("{} i32.const 3 i32.eq", Self::kind(&input)));
+                                self.line("if");
+                                self.line(&format!(
+                                    "  local.get {} local.get {} call $canon drop",
+                                    input.ptr, input.len
+                                ));
+                                self.line(&format!(
+                                    "  local.get {c} global.get $out local.get {c} i32.sub i32.const {at} i32.const {len} call $eq local.set {found}",
+                                    c = canonical
+                                ));
+                                self.line(&format!("  local.get {} global.set $out", canonical));
+                                self.line("end");
+                                format!("local.get {}", found)
+                            }
+                            // arrays and objects are only ever the same as themselves
+                            Value::Array(_) | Value::Object(_) => continue,
+                            from => {
+                                let (at, len) = self.constant(from.to_string().as_bytes());
+                                format!(
+                                    "local.get {} local.get {} i32.const {} i32.const {} call $eq",
+                                    input.ptr, input.len, at, len
+                                )
+                            }
+                        };
+                        self.line(&test);
+                        self.line("if");
+                        self.indent += 1;
+                        self.emit(to.0.to_string().as_bytes());
+                        self.line("br 1");
+                        self.indent -= 1;
+                        self.line("end");
+                    }
+                    self.indent -= 1;
+                    self.line("end");
+                    self.written(frame);
+                    writes = true;
+                }
+                IR::Concat(paths, sep) => {
+                    self.writing(frame);
+                    let first = self.local("first");
+                    self.line(&format!("i32.const 1 local.set {}", first));
+                    self.byte('"');
+                    for path in paths {
+                        let value = self.at(&input, path);
+                        self.line(&format!("{} i32.const 1 i32.gt_u", Self::kind(&value)));
+                        self.line("if");
+                        self.indent += 1;
+                        self.line(&format!("local.get {} i32.eqz", first));
+                        self.line("if");
+                        self.indent += 1;
+                        self.emit(&escaped(sep));
+                        self.indent -= 1;
+                        self.line("end");
+                        self.line(&format!("i32.const 0 local.set {}", first));
+                        self.line(&format!(
+                            "local.get {} local.get {} call $str",
+                            value.ptr, value.len
+                        ));
+                        self.indent -= 1;
+                        self.line("end");
+                    }
+                    self.byte('"');
+                    self.written(frame);
+                    writes = true;
+                }
+                IR::Join(sep) => {
+                    // only written if the input is an array
+                    let first = self.local("first");
+                    self.line(&format!("{} i32.const 5 i32.eq", Self::kind(&input)));
+                    self.line("if");
+                    self.indent += 1;
+                    self.writing(frame);
+                    self.line(&format!("i32.const 1 local.set {}", first));
+                    self.byte('"');
+                    let (item, p, limit) = self.items(&input);
+                    self.line(&format!("local.get {} i32.eqz", first));
+                    self.line("if");
+                    self.indent += 1;
+                    self.emit(&escaped(sep));
+                    self.indent -= 1;
+                    self.line("end");
+                    self.line(&format!("i32.const 0 local.set {}", first));
+                    self.line(&format!(
+                        "local.get {} local.get {} call $str",
+                        item.ptr, item.len
+                    ));
+                    self.close_items(&item, &p, &limit);
+                    self.byte('"');
+                    self.written(frame);
+                    self.indent -= 1;
+                    self.line("end");
+                    writes = true;
+                }
+                // instructions closing scopes which weren't opened do nothing
+                op if op.is_closer() => {}
+                _ => return Err(unsupported()),
+            }
+            i += 1;
+        }
+        Ok(writes)
+    }
+
+    /// The body of `transform`, running `ir` on its input.
+    fn transform(&mut self, ir: &[IR]) -> Result<String, WasmErr> {
+        let input = Slot {
+            ptr: "input".to_string(),
+            len: "size".to_string(),
+        };
+        // the value, without the whitespace around it
+        let limit = self.local("limit");
+        self.line(&format!(
+            "local.get input local.get input local.get size i32.add local.tee {} call $ws local.tee input",
+            limit
+        ));
+        self.line(&format!(
+            "local.get {} call $skip local.get input i32.sub local.set size",
+            limit
+        ));
+        let frame = self.enter();
+        self.run(ir, 0, &frame, &input)?;
+        self.leave(&frame);
+        self.line(&format!("global.get $out local.get {} i32.eq", frame.start));
+        self.line("if");
+        self.indent += 1;
+        self.emit(b"null");
+        self.indent -= 1;
+        self.line("end");
+        self.line(&format!(
+            "global.get $out local.get {} i32.sub global.set $len",
+            frame.start
+        ));
+        self.line(&format!("local.get {}", frame.start));
+        Ok(std::mem::take(&mut self.code))
+    }
+}
+
+/// The text of the function `transform` in a module compiled from `ir`,
+/// along with the constants it writes.
+fn compile(ir: &[IR]) -> Result<(Function, Vec<u8>), WasmErr> {
+    let mut compiler = Compiler::default();
+    for (_, word) in runtime::WORDS {
+        compiler.constant(word.as_bytes());
+    }
+    let body = compiler.transform(ir)?;
+    let locals: Vec<&str> = compiler.locals.iter().map(String::as_str).collect();
+    let function = Function::new("transform", &["input", "size"], 1, &locals, &body);
+    Ok((function, compiler.data))
+}
+
+/// A WebAssembly module transforming JSON according to `ir`, if it can be
+/// compiled.
+pub fn module(ir: &[IR]) -> Result<Vec<u8>, WasmErr> {
+    let (transform, data) = compile(ir)?;
+    let mut constants = HashMap::new();
+    let mut at = DATA;
+    for (name, word) in runtime::WORDS {
+        constants.insert(*name, at as i32);
+        at += word.len();
+    }
+    // free memory from past the constants, aligned for good measure
+    let base = (DATA + data.len() + 7) & !7;
+    constants.insert("base", base as i32);
+
+    let mut functions = runtime::functions();
+    functions.push(transform);
+    let module = Module {
+        functions,
+        globals: vec!["out", "len"],
+        constants,
+        data,
+        offset: DATA,
+        pages: base / 65536 + 1,
+        exports: vec!["alloc", "transform", "length"],
+    };
+    Ok(module.encode())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir::SortOrder, schema::Real, test_util::key};
+
+    #[test]
+    fn test_property_lookup() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let (transform, data) = compile(&ir).expect("compiles");
+        // the key looked up and the one written are among the constants
+        let at = DATA + data.windows(7).position(|w| w == b"user_id").expect("key");
+        assert!(transform
+            .body
+            .contains(&format!("i32.const {} i32.const 7 call $get", at)));
+        assert!(data.windows(5).any(|w| w == b"\"id\":"));
+        assert!(transform.body.contains("call $str"));
+    }
+
+    #[test]
+    fn test_module() {
+        let bytes = module(&[IR::Copy]).expect("compiles");
+        assert_eq!(&bytes[..8], b"\0asm\x01\0\0\0");
+        for export in ["memory", "alloc", "transform", "length"] {
+            let name = [&[export.len() as u8], export.as_bytes()].concat();
+            assert!(bytes.windows(name.len()).any(|w| w == name));
+        }
+        // the constants come first, from where they're laid out
+        let words = b"undefined[object Object]nulltruefalse";
+        assert!(bytes.windows(words.len()).any(|w| w == words));
+    }
+
+    /// The output of running `module` on `input` with node, or `None` where
+    /// node isn't installed.
+    fn run(module: &[u8], input: &Value) -> Option<Value> {
+        use std::io::Write;
+
+        let script = format!(
+            "const bytes = require('fs').readFileSync(0);
+            if (!WebAssembly.validate(bytes)) throw new Error('invalid module');
+            WebAssembly.instantiate(bytes).then(({{ instance }}) => {{
+              const {{ memory, alloc, transform, length }} = instance.exports;
+              const input = new TextEncoder().encode({});
+              const p = alloc(input.length);
+              new Uint8Array(memory.buffer, p, input.length).set(input);
+              const out = transform(p, input.length);
+              console.log(new TextDecoder().decode(new Uint8Array(memory.buffer, out, length())));
+            }});",
+            Value::from(input.to_string())
+        );
+        let mut node = std::process::Command::new("node")
+            .args(["-e", &script])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .ok()?;
+        node.stdin.take().unwrap().write_all(module).unwrap();
+        let out = node.wait_with_output().unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        Some(serde_json::from_slice(&out.stdout).unwrap())
+    }
+
+    #[test]
+    fn test_run() {
+        let ir = [
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("tags")),
+            IR::PushArr,
+            IR::Copy,
+            IR::PopArr,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let input = serde_json::json!({
+            "user_id": 42,
+            "tags": ["a", "b\"c"],
+            "ignored": {"x": [1, 2]}
+        });
+        let bytes = module(&ir).expect("compiles");
+        if let Some(output) = run(&bytes, &input) {
+            assert_eq!(output, crate::ir::eval(&ir, &input));
+            assert_eq!(
+                output,
+                serde_json::json!({"id": "42", "tags": ["a", "b\"c"]})
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let ir = [
+            IR::PushObj,
+            IR::PushKey(key("tags")),
+            IR::Sort(vec![], SortOrder::Ascending),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let err = module(&ir).unwrap_err();
+        assert_eq!(
+            err,
+            WasmErr::Unsupported(2, IR::Sort(vec![], SortOrder::Ascending))
+        );
+        assert_eq!(
+            err.to_string(),
+            "instruction 2 (`Sort [[],\"Ascending\"]`) has no WebAssembly equivalent"
+        );
+        let scale = IR::Scale {
+            factor: Real(2.0),
+            offset: Real(0.0),
+        };
+        assert_eq!(
+            module(std::slice::from_ref(&scale)),
+            Err(WasmErr::Unsupported(0, scale))
+        );
+    }
+}
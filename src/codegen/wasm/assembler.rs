@@ -0,0 +1,312 @@
+//! Assembly of WebAssembly modules from functions written as flat text:
+//! instructions one after another, each followed by its immediates, with
+//! locals, globals, functions and constants referred to by name. Every value
+//! is an `i32`, and blocks take and leave nothing on the stack.
+
+use std::collections::HashMap;
+
+/// A function of a module.
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    /// How many values it returns.
+    pub results: usize,
+    pub locals: Vec<String>,
+    /// The instructions, with `;;` starting comments.
+    pub body: String,
+}
+
+impl Function {
+    pub fn new(name: &str, params: &[&str], results: usize, locals: &[&str], body: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+            results,
+            locals: locals.iter().map(|local| local.to_string()).collect(),
+            body: body.to_string(),
+        }
+    }
+}
+
+/// A module: its functions, its mutable globals (all starting at 0), a
+/// memory holding `data` at `offset`, and its exports.
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub globals: Vec<&'static str>,
+    /// Constants instructions can take as immediates.
+    pub constants: HashMap<&'static str, i32>,
+    pub data: Vec<u8>,
+    pub offset: usize,
+    /// Pages the memory starts with.
+    pub pages: usize,
+    /// The functions exported, along with the memory as `memory`.
+    pub exports: Vec<&'static str>,
+}
+
+/// Instructions without immediates.
+const PLAIN: &[(&str, u8)] = &[
+    ("unreachable", 0x00),
+    ("else", 0x05),
+    ("end", 0x0b),
+    ("return", 0x0f),
+    ("drop", 0x1a),
+    ("select", 0x1b),
+    ("i32.eqz", 0x45),
+    ("i32.eq", 0x46),
+    ("i32.ne", 0x47),
+    ("i32.lt_s", 0x48),
+    ("i32.lt_u", 0x49),
+    ("i32.gt_s", 0x4a),
+    ("i32.gt_u", 0x4b),
+    ("i32.le_s", 0x4c),
+    ("i32.le_u", 0x4d),
+    ("i32.ge_s", 0x4e),
+    ("i32.ge_u", 0x4f),
+    ("i32.add", 0x6a),
+    ("i32.sub", 0x6b),
+    ("i32.mul", 0x6c),
+    ("i32.div_u", 0x6e),
+    ("i32.rem_u", 0x70),
+    ("i32.and", 0x71),
+    ("i32.or", 0x72),
+    ("i32.shl", 0x74),
+    ("i32.shr_u", 0x76),
+];
+
+/// `n` as an unsigned LEB128 integer.
+pub fn unsigned(mut n: u64) -> Vec<u8> {
+    let mut out = vec![];
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return out;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// `n` as a signed LEB128 integer.
+pub fn signed(mut n: i64) -> Vec<u8> {
+    let mut out = vec![];
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        let done = (n == 0 && byte & 0x40 == 0) || (n == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return out;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// `bytes` prefixed with their length.
+fn sized(bytes: &[u8]) -> Vec<u8> {
+    let mut out = unsigned(bytes.len() as u64);
+    out.extend_from_slice(bytes);
+    out
+}
+
+/// A vector of `items`.
+fn vector(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = unsigned(items.len() as u64);
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    out
+}
+
+/// The section `id` holding `contents`.
+fn section(id: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![id];
+    out.extend(sized(contents));
+    out
+}
+
+/// The type of functions taking `params` values and returning `results`.
+fn signature(params: usize, results: usize) -> Vec<u8> {
+    let mut out = vec![0x60];
+    out.extend(sized(&vec![0x7f; params]));
+    out.extend(sized(&vec![0x7f; results]));
+    out
+}
+
+impl Module {
+    /// The code of `function`, locals and all.
+    fn code(&self, function: &Function) -> Vec<u8> {
+        let index = |names: &[&str], name: &str, what: &str| {
+            let found = names.iter().position(|n| *n == name);
+            found.unwrap_or_else(|| panic!("no {} `{}` in `{}`", what, name, function.name)) as u64
+        };
+        let locals: Vec<&str> = function
+            .params
+            .iter()
+            .chain(&function.locals)
+            .map(String::as_str)
+            .collect();
+        let functions: Vec<&str> = self.functions.iter().map(|f| f.name.as_str()).collect();
+
+        let mut out = vector(&[[unsigned(function.locals.len() as u64), vec![0x7f]].concat()]);
+        let text: Vec<&str> = function
+            .body
+            .lines()
+            .map(|line| line.split(";;").next().unwrap_or_default())
+            .collect();
+        let mut tokens = text.iter().flat_map(|line| line.split_whitespace());
+        while let Some(token) = tokens.next() {
+            let mut immediate = || {
+                tokens
+                    .next()
+                    .unwrap_or_else(|| panic!("`{}` needs an immediate", token))
+            };
+            if let Some((_, op)) = PLAIN.iter().find(|(name, _)| *name == token) {
+                out.push(*op);
+                continue;
+            }
+            match token {
+                "block" => out.extend([0x02, 0x40]),
+                "loop" => out.extend([0x03, 0x40]),
+                "if" => out.extend([0x04, 0x40]),
+                "br" | "br_if" => {
+                    out.push(if token == "br" { 0x0c } else { 0x0d });
+                    out.extend(unsigned(immediate().parse().expect("a depth")));
+                }
+                "call" => {
+                    out.push(0x10);
+                    let name = immediate().trim_start_matches('$');
+                    out.extend(unsigned(index(&functions, name, "function")));
+                }
+                "local.get" | "local.set" | "local.tee" => {
+                    out.push(match token {
+                        "local.get" => 0x20,
+                        "local.set" => 0x21,
+                        _ => 0x22,
+                    });
+                    out.extend(unsigned(index(&locals, immediate(), "local")));
+                }
+                "global.get" | "global.set" => {
+                    out.push(if token == "global.get" { 0x23 } else { 0x24 });
+                    let name = immediate().trim_start_matches('$');
+                    out.extend(unsigned(index(&self.globals, name, "global")));
+                }
+                "i32.const" => {
+                    out.push(0x41);
+                    let value = match immediate() {
+                        name if name.starts_with('$') => self.constants[&name[1..]] as i64,
+                        value => value.parse().expect("a constant"),
+                    };
+                    out.extend(signed(value));
+                }
+                // aligned to bytes, at no offset
+                "i32.load8_u" => out.extend([0x2d, 0x00, 0x00]),
+                "i32.store8" => out.extend([0x3a, 0x00, 0x00]),
+                "memory.size" => out.extend([0x3f, 0x00]),
+                "memory.grow" => out.extend([0x40, 0x00]),
+                "memory.copy" => out.extend([0xfc, 0x0a, 0x00, 0x00]),
+                _ => panic!("unknown instruction `{}` in `{}`", token, function.name),
+            }
+        }
+        out.push(0x0b);
+        sized(&out)
+    }
+
+    /// The module's binary encoding.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut types: Vec<Vec<u8>> = vec![];
+        let mut indices = vec![];
+        for function in &self.functions {
+            let ty = signature(function.params.len(), function.results);
+            let index = match types.iter().position(|t| *t == ty) {
+                Some(index) => index,
+                None => {
+                    types.push(ty);
+                    types.len() - 1
+                }
+            };
+            indices.push(unsigned(index as u64));
+        }
+        let memory = [vec![0x00], unsigned(self.pages as u64)].concat();
+        let globals: Vec<_> = self
+            .globals
+            .iter()
+            .map(|_| vec![0x7f, 0x01, 0x41, 0x00, 0x0b])
+            .collect();
+        let mut exports = vec![[sized(b"memory"), vec![0x02, 0x00]].concat()];
+        for name in &self.exports {
+            let index = self.functions.iter().position(|f| f.name == *name);
+            let index = index.unwrap_or_else(|| panic!("no function `{}` to export", name));
+            exports.push([sized(name.as_bytes()), vec![0x00], unsigned(index as u64)].concat());
+        }
+        let code: Vec<_> = self.functions.iter().map(|f| self.code(f)).collect();
+        let offset = [vec![0x41], signed(self.offset as i64), vec![0x0b]].concat();
+        let data = [vec![0x00], offset, sized(&self.data)].concat();
+
+        let mut out = b"\0asm".to_vec();
+        out.extend([0x01, 0x00, 0x00, 0x00]);
+        out.extend(section(1, &vector(&types)));
+        out.extend(section(3, &vector(&indices)));
+        out.extend(section(5, &vector(&[memory])));
+        out.extend(section(6, &vector(&globals)));
+        out.extend(section(7, &vector(&exports)));
+        out.extend(section(10, &vector(&code)));
+        out.extend(section(11, &vector(&[data])));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leb128() {
+        assert_eq!(unsigned(0), [0x00]);
+        assert_eq!(unsigned(624485), [0xe5, 0x8e, 0x26]);
+        assert_eq!(signed(-1), [0x7f]);
+        assert_eq!(signed(64), [0xc0, 0x00]);
+        assert_eq!(signed(-123456), [0xc0, 0xbb, 0x78]);
+    }
+
+    #[test]
+    fn test_code() {
+        let module = Module {
+            functions: vec![Function::new(
+                "add",
+                &["a", "b"],
+                1,
+                &["sum"],
+                "local.get a local.get b i32.add ;; a + b\nlocal.tee sum i32.const $one i32.add",
+            )],
+            globals: vec![],
+            constants: HashMap::from([("one", 1)]),
+            data: vec![],
+            offset: 0,
+            pages: 1,
+            exports: vec!["add"],
+        };
+        assert_eq!(
+            module.code(&module.functions[0]),
+            [14, 1, 1, 0x7f, 0x20, 0, 0x20, 1, 0x6a, 0x22, 2, 0x41, 1, 0x6a, 0x0b]
+        );
+    }
+
+    #[test]
+    fn test_encode() {
+        let module = Module {
+            functions: vec![Function::new("answer", &[], 1, &[], "global.get $n")],
+            globals: vec!["n"],
+            constants: HashMap::new(),
+            data: b"hi".to_vec(),
+            offset: 8,
+            pages: 1,
+            exports: vec!["answer"],
+        };
+        let bytes = module.encode();
+        assert_eq!(&bytes[..8], b"\0asm\x01\0\0\0");
+        // the type section, then the function section
+        assert_eq!(&bytes[8..19], [1, 5, 1, 0x60, 0, 1, 0x7f, 3, 2, 1, 0]);
+        assert!(bytes.ends_with(&[11, 8, 1, 0, 0x41, 8, 0x0b, 2, b'h', b'i']));
+    }
+}
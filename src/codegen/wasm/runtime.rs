@@ -0,0 +1,582 @@
+//! The functions generated modules run on, working on JSON text in memory.
+//! A value is the bytes of its text, as a pointer and a length, with an
+//! empty one for `undefined`; what's written goes at the end of what's been
+//! written so far, from `$out` on.
+//!
+//! Their text takes the constants `$base`, where memory is free from,
+//! `$undefined`, `$object`, `$null`, `$true` and `$false`, the addresses of
+//! those words (`$object` being `[object Object]`).
+
+use super::assembler::Function;
+
+/// Bytes of the words at `$undefined`, `$object`, `$null`, `$true` and
+/// `$false`, in the order the module lays them out.
+pub const WORDS: &[(&str, &str)] = &[
+    ("undefined", "undefined"),
+    ("object", "[object Object]"),
+    ("null", "null"),
+    ("true", "true"),
+    ("false", "false"),
+];
+
+/// The functions, in the order the module declares them.
+pub fn functions() -> Vec<Function> {
+    vec![
+        // alloc(n): room for `n` bytes of input, freeing what the last
+        // transform took
+        Function::new(
+            "alloc",
+            &["n"],
+            1,
+            &[],
+            "i32.const $base global.set $out
+            local.get n call $reserve
+            global.get $out local.get n i32.add global.set $out
+            i32.const $base",
+        ),
+        // reserve(n): grow memory to fit `n` more bytes past `$out`
+        Function::new(
+            "reserve",
+            &["n"],
+            0,
+            &["size"],
+            "memory.size i32.const 16 i32.shl local.set size
+            global.get $out local.get n i32.add local.get size i32.gt_u
+            if
+              global.get $out local.get n i32.add local.get size i32.sub
+              i32.const 16 i32.shr_u i32.const 1 i32.add
+              memory.grow i32.const -1 i32.eq
+              if unreachable end
+            end",
+        ),
+        // emit(p, n): write the `n` bytes at `p`
+        Function::new(
+            "emit",
+            &["p", "n"],
+            0,
+            &[],
+            "local.get n call $reserve
+            global.get $out local.get p local.get n memory.copy
+            global.get $out local.get n i32.add global.set $out",
+        ),
+        // byte(c): write `c`
+        Function::new(
+            "byte",
+            &["c"],
+            0,
+            &[],
+            "i32.const 1 call $reserve
+            global.get $out local.get c i32.store8
+            global.get $out i32.const 1 i32.add global.set $out",
+        ),
+        // space(c): whether `c` is whitespace
+        Function::new(
+            "space",
+            &["c"],
+            1,
+            &[],
+            "local.get c i32.const 32 i32.eq
+            local.get c i32.const 9 i32.eq i32.or
+            local.get c i32.const 10 i32.eq i32.or
+            local.get c i32.const 13 i32.eq i32.or",
+        ),
+        // ws(p, limit): `p` past whitespace
+        Function::new(
+            "ws",
+            &["p", "limit"],
+            1,
+            &[],
+            "block loop
+              local.get p local.get limit i32.ge_u br_if 1
+              local.get p i32.load8_u call $space i32.eqz br_if 1
+              local.get p i32.const 1 i32.add local.set p
+              br 0
+            end end
+            local.get p",
+        ),
+        // next(p, limit): `p` past whitespace and a comma
+        Function::new(
+            "next",
+            &["p", "limit"],
+            1,
+            &[],
+            "local.get p local.get limit call $ws local.set p
+            local.get p local.get limit i32.lt_u
+            if
+              local.get p i32.load8_u i32.const 44 i32.eq
+              if local.get p i32.const 1 i32.add local.set p end
+            end
+            local.get p",
+        ),
+        // skip(p, limit): `p` past the value starting there
+        Function::new(
+            "skip",
+            &["p", "limit"],
+            1,
+            &["c", "depth"],
+            "local.get p local.get limit i32.ge_u if local.get p return end
+            local.get p i32.load8_u local.tee c i32.const 34 i32.eq
+            if
+              ;; a string, up to the quote ending it
+              local.get p i32.const 1 i32.add local.set p
+              block loop
+                local.get p local.get limit i32.ge_u br_if 1
+                local.get p i32.load8_u local.set c
+                local.get p i32.const 1 i32.add local.set p
+                local.get c i32.const 34 i32.eq br_if 1
+                local.get c i32.const 92 i32.eq
+                if local.get p i32.const 1 i32.add local.set p end
+                br 0
+              end end
+              local.get p return
+            end
+            local.get c i32.const 123 i32.eq local.get c i32.const 91 i32.eq i32.or
+            if
+              ;; an object or array, up to the bracket closing it
+              block loop
+                local.get p local.get limit i32.ge_u br_if 1
+                local.get p i32.load8_u local.tee c i32.const 34 i32.eq
+                if
+                  local.get p local.get limit call $skip local.set p
+                  br 1
+                end
+                local.get p i32.const 1 i32.add local.set p
+                local.get c i32.const 123 i32.eq local.get c i32.const 91 i32.eq i32.or
+                if local.get depth i32.const 1 i32.add local.set depth end
+                local.get c i32.const 125 i32.eq local.get c i32.const 93 i32.eq i32.or
+                if
+                  local.get depth i32.const 1 i32.sub local.tee depth i32.eqz br_if 2
+                end
+                br 0
+              end end
+              local.get p return
+            end
+            ;; a number or a word, up to whatever delimits it
+            block loop
+              local.get p local.get limit i32.ge_u br_if 1
+              local.get p i32.load8_u local.tee c i32.const 33 i32.lt_u
+              local.get c i32.const 44 i32.eq i32.or
+              local.get c i32.const 58 i32.eq i32.or
+              local.get c i32.const 93 i32.eq i32.or
+              local.get c i32.const 125 i32.eq i32.or
+              br_if 1
+              local.get p i32.const 1 i32.add local.set p
+              br 0
+            end end
+            local.get p",
+        ),
+        // eq(a, m, b, n): whether the `m` bytes at `a` are the `n` at `b`
+        Function::new(
+            "eq",
+            &["a", "m", "b", "n"],
+            1,
+            &["i"],
+            "local.get m local.get n i32.ne if i32.const 0 return end
+            block loop
+              local.get i local.get m i32.ge_u br_if 1
+              local.get a local.get i i32.add i32.load8_u
+              local.get b local.get i i32.add i32.load8_u
+              i32.ne if i32.const 0 return end
+              local.get i i32.const 1 i32.add local.set i
+              br 0
+            end end
+            i32.const 1",
+        ),
+        // kind(p, n): 0 for `undefined`, then 1 to 6 for null, booleans,
+        // numbers, strings, arrays and objects
+        Function::new(
+            "kind",
+            &["p", "n"],
+            1,
+            &["c"],
+            "local.get n i32.eqz if i32.const 0 return end
+            local.get p i32.load8_u local.set c
+            local.get c i32.const 110 i32.eq if i32.const 1 return end
+            local.get c i32.const 116 i32.eq local.get c i32.const 102 i32.eq i32.or
+            if i32.const 2 return end
+            local.get c i32.const 34 i32.eq if i32.const 4 return end
+            local.get c i32.const 91 i32.eq if i32.const 5 return end
+            local.get c i32.const 123 i32.eq if i32.const 6 return end
+            i32.const 3",
+        ),
+        // get(p, n, key, size): property `key` (escaped, without quotes) of
+        // the value, or the last of them if it's there more than once
+        Function::new(
+            "get",
+            &["p", "n", "key", "size"],
+            2,
+            &["limit", "q", "found", "at", "len"],
+            "local.get p local.get n call $kind i32.const 6 i32.ne
+            if i32.const 0 i32.const 0 return end
+            local.get p local.get n i32.add i32.const 1 i32.sub local.set limit
+            local.get p i32.const 1 i32.add local.set p
+            block loop
+              local.get p local.get limit call $ws local.tee p
+              local.get limit i32.ge_u br_if 1
+              local.get p local.get limit call $skip local.tee q
+              local.get p i32.eq br_if 1
+              local.get p i32.const 1 i32.add
+              local.get q local.get p i32.sub i32.const 2 i32.sub
+              local.get key local.get size call $eq local.set found
+              ;; the value, past the colon
+              local.get q local.get limit call $ws i32.const 1 i32.add
+              local.get limit call $ws local.tee p
+              local.get limit call $skip local.set q
+              local.get found
+              if
+                local.get p local.set at
+                local.get q local.get p i32.sub local.set len
+              end
+              local.get q local.get limit call $next local.set p
+              br 0
+            end end
+            local.get at local.get len",
+        ),
+        // str(p, n): write `String(value)`, escaped for a string's contents
+        Function::new(
+            "str",
+            &["p", "n"],
+            0,
+            &["kind", "limit", "q", "first"],
+            "local.get p local.get n call $kind local.set kind
+            local.get kind i32.eqz
+            if i32.const $undefined i32.const 9 call $emit return end
+            local.get kind i32.const 3 i32.eq
+            if local.get p local.get n call $canon drop return end
+            local.get kind i32.const 4 i32.eq
+            if
+              local.get p i32.const 1 i32.add local.get n i32.const 2 i32.sub call $emit
+              return
+            end
+            local.get kind i32.const 6 i32.eq
+            if i32.const $object i32.const 15 call $emit return end
+            local.get kind i32.const 5 i32.eq
+            if
+              ;; the items, joined by commas, with null ones left empty
+              local.get p local.get n i32.add i32.const 1 i32.sub local.set limit
+              local.get p i32.const 1 i32.add local.set p
+              i32.const 1 local.set first
+              block loop
+                local.get p local.get limit call $ws local.tee p
+                local.get limit i32.ge_u br_if 1
+                local.get p local.get limit call $skip local.tee q
+                local.get p i32.eq br_if 1
+                local.get first i32.eqz if i32.const 44 call $byte end
+                i32.const 0 local.set first
+                local.get p local.get q local.get p i32.sub call $kind i32.const 1 i32.ne
+                if local.get p local.get q local.get p i32.sub call $str end
+                local.get q local.get limit call $next local.set p
+                br 0
+              end end
+              return
+            end
+            ;; null and booleans
+            local.get p local.get n call $emit",
+        ),
+        // num(p, n): write `Number(value)` as JSON
+        Function::new(
+            "num",
+            &["p", "n"],
+            0,
+            &["kind", "mark", "end"],
+            "local.get p local.get n call $kind local.set kind
+            local.get kind i32.eqz if i32.const $null i32.const 4 call $emit return end
+            local.get kind i32.const 1 i32.eq if i32.const 48 call $byte return end
+            local.get kind i32.const 2 i32.eq
+            if
+              i32.const 49 i32.const 48 local.get p i32.load8_u i32.const 116 i32.eq select
+              call $byte
+              return
+            end
+            local.get kind i32.const 3 i32.eq if local.get p local.get n call $canon drop return end
+            local.get kind i32.const 4 i32.eq
+            if local.get p i32.const 1 i32.add local.get n i32.const 2 i32.sub call $parse return end
+            ;; arrays and objects through their string form, moved over it
+            global.get $out local.set mark
+            local.get p local.get n call $str
+            global.get $out local.set end
+            local.get mark local.get end local.get mark i32.sub call $parse
+            local.get mark local.get end global.get $out local.get end i32.sub memory.copy
+            local.get mark global.get $out local.get end i32.sub i32.add global.set $out",
+        ),
+        // parse(p, n): write `Number(s)` as JSON, for the contents `s` of a
+        // string
+        Function::new(
+            "parse",
+            &["p", "n"],
+            0,
+            &["limit"],
+            "local.get p local.get n i32.add local.set limit
+            block loop
+              local.get p local.get limit i32.ge_u br_if 1
+              local.get p i32.load8_u call $space i32.eqz br_if 1
+              local.get p i32.const 1 i32.add local.set p
+              br 0
+            end end
+            block loop
+              local.get limit local.get p i32.le_u br_if 1
+              local.get limit i32.const 1 i32.sub i32.load8_u call $space i32.eqz br_if 1
+              local.get limit i32.const 1 i32.sub local.set limit
+              br 0
+            end end
+            local.get p local.get limit i32.eq if i32.const 48 call $byte return end
+            local.get p local.get limit local.get p i32.sub call $canon i32.eqz
+            if i32.const $null i32.const 4 call $emit end",
+        ),
+        // truthy(p, n): `Boolean(value)`
+        Function::new(
+            "truthy",
+            &["p", "n"],
+            1,
+            &["kind", "c"],
+            "local.get p local.get n call $kind local.tee kind i32.const 2 i32.lt_u
+            if i32.const 0 return end
+            local.get kind i32.const 2 i32.eq
+            if local.get p i32.load8_u i32.const 116 i32.eq return end
+            local.get kind i32.const 4 i32.eq if local.get n i32.const 2 i32.gt_u return end
+            local.get kind i32.const 3 i32.ne if i32.const 1 return end
+            ;; numbers, unless every digit ahead of the exponent is zero
+            local.get p local.get n i32.add local.set n
+            block loop
+              local.get p local.get n i32.ge_u br_if 1
+              local.get p i32.load8_u local.tee c i32.const 32 i32.or i32.const 101 i32.eq br_if 1
+              local.get c i32.const 49 i32.sub i32.const 9 i32.lt_u if i32.const 1 return end
+              local.get p i32.const 1 i32.add local.set p
+              br 0
+            end end
+            i32.const 0",
+        ),
+        // canon(p, n): write the decimal number written at `p` the way
+        // `String` writes it, if it's one
+        Function::new(
+            "canon",
+            &["p", "n"],
+            1,
+            &[
+                "limit", "c", "neg", "seen", "lead", "frac", "first", "k", "zeros", "point", "e",
+                "sign",
+            ],
+            "local.get p local.get n i32.add local.set limit
+            i32.const 1 local.set lead
+            i32.const 1 local.set sign
+            local.get p local.get limit i32.lt_u
+            if
+              local.get p i32.load8_u local.tee c i32.const 45 i32.eq local.set neg
+              local.get c i32.const 45 i32.eq local.get c i32.const 43 i32.eq i32.or
+              if local.get p i32.const 1 i32.add local.set p end
+            end
+            ;; the significant digits, and how many come ahead of the point
+            block loop
+              local.get p local.get limit i32.ge_u br_if 1
+              local.get p i32.load8_u local.tee c i32.const 46 i32.eq
+              if
+                local.get frac if i32.const 0 return end
+                i32.const 1 local.set frac
+              else
+                local.get c i32.const 48 i32.sub i32.const 10 i32.ge_u br_if 2
+                i32.const 1 local.set seen
+                local.get lead local.get c i32.const 48 i32.eq i32.and
+                if
+                  local.get frac if local.get point i32.const 1 i32.sub local.set point end
+                else
+                  local.get lead if local.get p local.set first end
+                  i32.const 0 local.set lead
+                  local.get k i32.const 1 i32.add local.set k
+                  local.get frac i32.eqz if local.get point i32.const 1 i32.add local.set point end
+                  local.get zeros i32.const 1 i32.add i32.const 0 local.get c i32.const 48 i32.eq select
+                  local.set zeros
+                end
+              end
+              local.get p i32.const 1 i32.add local.set p
+              br 0
+            end end
+            local.get seen i32.eqz if i32.const 0 return end
+            ;; the exponent, which saturates far past where numbers overflow
+            local.get p local.get limit i32.lt_u
+            if
+              local.get p i32.load8_u i32.const 32 i32.or i32.const 101 i32.ne if i32.const 0 return end
+              local.get p i32.const 1 i32.add local.set p
+              local.get p local.get limit i32.lt_u
+              if
+                local.get p i32.load8_u local.tee c i32.const 45 i32.eq
+                if i32.const -1 local.set sign end
+                local.get c i32.const 45 i32.eq local.get c i32.const 43 i32.eq i32.or
+                if local.get p i32.const 1 i32.add local.set p end
+              end
+              local.get p local.get limit i32.ge_u if i32.const 0 return end
+              block loop
+                local.get p local.get limit i32.ge_u br_if 1
+                local.get p i32.load8_u i32.const 48 i32.sub local.tee c i32.const 10 i32.ge_u
+                if i32.const 0 return end
+                local.get e i32.const 100000 i32.lt_u
+                if local.get e i32.const 10 i32.mul local.get c i32.add local.set e end
+                local.get p i32.const 1 i32.add local.set p
+                br 0
+              end end
+            end
+            ;; zero, without its sign
+            local.get k local.get zeros i32.sub local.tee k i32.eqz
+            if i32.const 48 call $byte i32.const 1 return end
+            local.get point local.get e local.get sign i32.mul i32.add local.set point
+            local.get neg if i32.const 45 call $byte end
+            local.get k local.get point i32.le_s local.get point i32.const 21 i32.le_s i32.and
+            if
+              local.get first i32.const 0 local.get k call $digits
+              local.get point local.get k i32.sub call $zeros
+              i32.const 1 return
+            end
+            local.get point i32.const 0 i32.gt_s local.get point i32.const 21 i32.le_s i32.and
+            if
+              local.get first i32.const 0 local.get point call $digits
+              i32.const 46 call $byte
+              local.get first local.get point local.get k call $digits
+              i32.const 1 return
+            end
+            local.get point i32.const -6 i32.gt_s local.get point i32.const 0 i32.le_s i32.and
+            if
+              i32.const 48 call $byte i32.const 46 call $byte
+              i32.const 0 local.get point i32.sub call $zeros
+              local.get first i32.const 0 local.get k call $digits
+              i32.const 1 return
+            end
+            local.get first i32.const 0 i32.const 1 call $digits
+            local.get k i32.const 1 i32.gt_s
+            if
+              i32.const 46 call $byte
+              local.get first i32.const 1 local.get k call $digits
+            end
+            i32.const 101 call $byte
+            local.get point i32.const 1 i32.sub local.tee e i32.const 0 i32.lt_s
+            if
+              i32.const 45 call $byte
+              i32.const 0 local.get e i32.sub local.set e
+            else
+              i32.const 43 call $byte
+            end
+            local.get e call $int
+            i32.const 1",
+        ),
+        // digits(p, from, to): write digits `from` to `to` of those from `p`
+        // on, past any point
+        Function::new(
+            "digits",
+            &["p", "from", "to"],
+            0,
+            &["i", "c"],
+            "block loop
+              local.get i local.get to i32.ge_s br_if 1
+              local.get p i32.load8_u local.tee c i32.const 46 i32.ne
+              if
+                local.get i local.get from i32.ge_s if local.get c call $byte end
+                local.get i i32.const 1 i32.add local.set i
+              end
+              local.get p i32.const 1 i32.add local.set p
+              br 0
+            end end",
+        ),
+        // zeros(n): write `n` zeros
+        Function::new(
+            "zeros",
+            &["n"],
+            0,
+            &[],
+            "block loop
+              local.get n i32.const 0 i32.le_s br_if 1
+              i32.const 48 call $byte
+              local.get n i32.const 1 i32.sub local.set n
+              br 0
+            end end",
+        ),
+        // int(n): write `n` in decimal
+        Function::new(
+            "int",
+            &["n"],
+            0,
+            &[],
+            "local.get n i32.const 10 i32.ge_u if local.get n i32.const 10 i32.div_u call $int end
+            local.get n i32.const 10 i32.rem_u i32.const 48 i32.add call $byte",
+        ),
+        // case(p, upper): upper or lower case the ASCII letters written from
+        // `p` on, past escapes
+        Function::new(
+            "case",
+            &["p", "upper"],
+            0,
+            &["c"],
+            "block loop
+              local.get p global.get $out i32.ge_u br_if 1
+              local.get p i32.load8_u local.tee c i32.const 92 i32.eq
+              if
+                local.get p i32.const 2 i32.add local.set p
+                br 1
+              end
+              local.get upper
+              if
+                local.get c i32.const 97 i32.sub i32.const 26 i32.lt_u
+                if local.get p local.get c i32.const 32 i32.sub i32.store8 end
+              else
+                local.get c i32.const 65 i32.sub i32.const 26 i32.lt_u
+                if local.get p local.get c i32.const 32 i32.add i32.store8 end
+              end
+              local.get p i32.const 1 i32.add local.set p
+              br 0
+            end end",
+        ),
+        // flat(p, n, depth): write the items of the array, flattened `depth`
+        // deep, each followed by a comma
+        Function::new(
+            "flat",
+            &["p", "n", "depth"],
+            0,
+            &["limit", "q"],
+            "local.get p local.get n i32.add i32.const 1 i32.sub local.set limit
+            local.get p i32.const 1 i32.add local.set p
+            block loop
+              local.get p local.get limit call $ws local.tee p
+              local.get limit i32.ge_u br_if 1
+              local.get p local.get limit call $skip local.tee q
+              local.get p i32.eq br_if 1
+              local.get depth i32.const 0 i32.ne
+              local.get p local.get q local.get p i32.sub call $kind i32.const 5 i32.eq i32.and
+              if
+                local.get p local.get q local.get p i32.sub local.get depth i32.const 1 i32.sub
+                call $flat
+              else
+                local.get p local.get q local.get p i32.sub call $emit
+                i32.const 44 call $byte
+              end
+              local.get q local.get limit call $next local.set p
+              br 0
+            end end",
+        ),
+        // length(): the length of what the last transform wrote
+        Function::new("length", &[], 1, &[], "global.get $len"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::super::assembler::Module;
+    use super::*;
+
+    #[test]
+    fn test_functions_assemble() {
+        let mut constants = HashMap::from([("base", 64)]);
+        for (i, (name, _)) in WORDS.iter().enumerate() {
+            constants.insert(name, 16 * i as i32);
+        }
+        let module = Module {
+            functions: functions(),
+            globals: vec!["out", "len"],
+            constants,
+            data: vec![],
+            offset: 0,
+            pages: 1,
+            exports: vec!["alloc", "length"],
+        };
+        let bytes = module.encode();
+        assert_eq!(&bytes[..4], b"\0asm");
+    }
+}
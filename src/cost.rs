@@ -0,0 +1,744 @@
+//! Cost models, which decide how expensive each kind of conversion is and so
+//! which of several possible transforms the searcher prefers.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+use crate::schema::{ExtNat, Ground};
+
+/// Whether converting a `from` value to `to` can lose information, i.e.
+/// whether two different inputs can produce the same output.
+pub fn is_lossy(from: &Ground, to: &Ground) -> bool {
+    use Ground::*;
+
+    match (from, to) {
+        (f, t) if f == t => false,
+        // there's only one null, so nothing to lose
+        (Null, _) => false,
+        (Num | Bool, String) | (Bool, Num) => false,
+        _ => true,
+    }
+}
+
+/// How safe a conversion is, from safest to least safe.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tier {
+    /// Nothing changes.
+    #[default]
+    Identity,
+    /// Every input converts to a distinct output, as with numbers to strings.
+    Widening,
+    /// Different inputs can convert to the same output (see [`is_lossy`]).
+    Narrowing,
+    /// The output is made up rather than converted, as with null to a number
+    /// or filling in a value the input doesn't have.
+    Fabricated,
+}
+
+impl Display for Tier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Identity => write!(f, "identity"),
+            Self::Widening => write!(f, "widening"),
+            Self::Narrowing => write!(f, "narrowing"),
+            Self::Fabricated => write!(f, "fabricated"),
+        }
+    }
+}
+
+/// The safety tier of converting a `from` value to `to`.
+pub fn tier(from: &Ground, to: &Ground) -> Tier {
+    match (from, to) {
+        (f, t) if f == t => Tier::Identity,
+        (Ground::Null, _) => Tier::Fabricated,
+        (f, t) if is_lossy(f, t) => Tier::Narrowing,
+        _ => Tier::Widening,
+    }
+}
+
+/// The cost of each kind of conversion step. Every method has a default, so
+/// implementations only need to override the costs they care about; costs of
+/// [`ExtNat::Inf`] forbid a conversion outright.
+pub trait CostModel: Debug + Send + Sync {
+    /// Cost of converting between two different ground types.
+    fn ground_to_ground(&self, from: &Ground, to: &Ground) -> ExtNat {
+        ExtNat::Nat(1) + self.lossy(from, to)
+    }
+
+    /// Extra cost on top of [`CostModel::ground_to_ground`] for conversions
+    /// which lose information (see [`is_lossy`]).
+    fn lossy(&self, from: &Ground, to: &Ground) -> ExtNat {
+        let _ = (from, to);
+        ExtNat::Nat(0)
+    }
+
+    /// Cost of dropping source property `key` because nothing in the target
+    /// corresponds to it.
+    fn drop_field(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of leaving optional target property `key` out because nothing in
+    /// the source corresponds to it.
+    fn skip_field(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of building target object `key` out of several properties of the
+    /// source object.
+    fn nest(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of filling target property `key` with a fixed value because
+    /// nothing in the source corresponds to it.
+    fn fill(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of building target string `key` by concatenating several source
+    /// properties.
+    fn concat(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of feeding target property `key` from whichever of several
+    /// source properties is present.
+    fn coalesce(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of converting the number in target property `key` to another
+    /// unit.
+    fn scale(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of base64 encoding or decoding the string in target property
+    /// `key`.
+    fn transcode(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of extracting part of the string in target property `key` with
+    /// a pattern.
+    fn extract_pattern(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of keeping only some of the items of the array in target
+    /// property `key`.
+    fn slice(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of sorting the items of the array in target property `key`.
+    fn sort(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of leaving out the duplicate items of the array in target
+    /// property `key`.
+    fn dedupe(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of concatenating several source arrays into target array `key`.
+    fn gather(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of converting the string in target property `key` to another
+    /// case.
+    fn change_case(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of writing the date in target property `key` in another
+    /// format.
+    fn convert_date(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of translating the enumerated value in target property `key`
+    /// from the source's values to the target's.
+    fn map_values(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of renaming `from` to `to`, given the key matcher's score for the
+    /// pair.
+    fn rename(&self, from: &str, to: &str, score: u64) -> ExtNat {
+        let _ = (from, to);
+        ExtNat::Nat(score)
+    }
+
+    /// Cost of pulling property `key` out of an object.
+    fn extract(&self, key: &str) -> ExtNat {
+        let _ = key;
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of wrapping a value into a single-item array.
+    fn wrap(&self) -> ExtNat {
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of taking the first item of an array.
+    fn first(&self) -> ExtNat {
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of flattening nested arrays into one (however many levels deep).
+    fn flatten(&self) -> ExtNat {
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of joining an array into a string (not counting conversions of
+    /// the items).
+    fn join(&self) -> ExtNat {
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of inverting an object into entries or back.
+    fn invert(&self) -> ExtNat {
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of grouping an array of objects into an object keyed by one of
+    /// their properties, or back.
+    fn group_by(&self) -> ExtNat {
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of converting between an object of parallel arrays and an array
+    /// of objects, either way.
+    fn zip(&self) -> ExtNat {
+        ExtNat::Nat(1)
+    }
+
+    /// Cost of dispatching on which branch of a union the input matches (not
+    /// counting the conversions of the branches).
+    fn dispatch(&self) -> ExtNat {
+        ExtNat::Nat(1)
+    }
+}
+
+/// The costs the searcher uses unless told otherwise: every step costs 1, and
+/// lossy conversions cost no more than lossless ones.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultCostModel;
+
+impl CostModel for DefaultCostModel {}
+
+/// A cost model which penalizes lossy ground conversions, so that they're
+/// only used when nothing else works.
+#[derive(Clone, Copy, Debug)]
+pub struct LossAverse {
+    pub penalty: u64,
+}
+
+impl CostModel for LossAverse {
+    fn lossy(&self, from: &Ground, to: &Ground) -> ExtNat {
+        ExtNat::Nat(if is_lossy(from, to) { self.penalty } else { 0 })
+    }
+}
+
+/// Wraps another cost model, penalizing every step which can't be undone: lossy
+/// conversions, dropping properties, and collapsing arrays into one value.
+/// Used when a transform has to be paired with its inverse.
+#[derive(Clone, Debug)]
+pub struct PreferInvertible {
+    pub inner: Arc<dyn CostModel>,
+    pub penalty: u64,
+}
+
+impl CostModel for PreferInvertible {
+    fn ground_to_ground(&self, from: &Ground, to: &Ground) -> ExtNat {
+        self.inner.ground_to_ground(from, to) + self.lossy(from, to)
+    }
+
+    fn lossy(&self, from: &Ground, to: &Ground) -> ExtNat {
+        ExtNat::Nat(if is_lossy(from, to) { self.penalty } else { 0 })
+    }
+
+    fn drop_field(&self, key: &str) -> ExtNat {
+        self.inner.drop_field(key) + ExtNat::Nat(self.penalty)
+    }
+
+    fn skip_field(&self, key: &str) -> ExtNat {
+        self.inner.skip_field(key)
+    }
+
+    fn nest(&self, key: &str) -> ExtNat {
+        self.inner.nest(key)
+    }
+
+    fn fill(&self, key: &str) -> ExtNat {
+        self.inner.fill(key)
+    }
+
+    fn concat(&self, key: &str) -> ExtNat {
+        self.inner.concat(key) + ExtNat::Nat(self.penalty)
+    }
+
+    fn coalesce(&self, key: &str) -> ExtNat {
+        self.inner.coalesce(key) + ExtNat::Nat(self.penalty)
+    }
+
+    fn scale(&self, key: &str) -> ExtNat {
+        self.inner.scale(key)
+    }
+
+    fn map_values(&self, key: &str) -> ExtNat {
+        self.inner.map_values(key)
+    }
+
+    fn convert_date(&self, key: &str) -> ExtNat {
+        self.inner.convert_date(key)
+    }
+
+    fn transcode(&self, key: &str) -> ExtNat {
+        self.inner.transcode(key)
+    }
+
+    fn extract_pattern(&self, key: &str) -> ExtNat {
+        self.inner.extract_pattern(key)
+    }
+
+    fn slice(&self, key: &str) -> ExtNat {
+        self.inner.slice(key) + ExtNat::Nat(self.penalty)
+    }
+
+    fn sort(&self, key: &str) -> ExtNat {
+        self.inner.sort(key) + ExtNat::Nat(self.penalty)
+    }
+
+    fn dedupe(&self, key: &str) -> ExtNat {
+        self.inner.dedupe(key) + ExtNat::Nat(self.penalty)
+    }
+
+    fn gather(&self, key: &str) -> ExtNat {
+        self.inner.gather(key) + ExtNat::Nat(self.penalty)
+    }
+
+    fn change_case(&self, key: &str) -> ExtNat {
+        self.inner.change_case(key)
+    }
+
+    fn rename(&self, from: &str, to: &str, score: u64) -> ExtNat {
+        self.inner.rename(from, to, score)
+    }
+
+    fn extract(&self, key: &str) -> ExtNat {
+        self.inner.extract(key) + ExtNat::Nat(self.penalty)
+    }
+
+    fn wrap(&self) -> ExtNat {
+        self.inner.wrap()
+    }
+
+    fn first(&self) -> ExtNat {
+        self.inner.first() + ExtNat::Nat(self.penalty)
+    }
+
+    fn flatten(&self) -> ExtNat {
+        self.inner.flatten() + ExtNat::Nat(self.penalty)
+    }
+
+    fn join(&self) -> ExtNat {
+        self.inner.join() + ExtNat::Nat(self.penalty)
+    }
+
+    fn invert(&self) -> ExtNat {
+        self.inner.invert()
+    }
+
+    fn group_by(&self) -> ExtNat {
+        self.inner.group_by()
+    }
+
+    fn zip(&self) -> ExtNat {
+        self.inner.zip()
+    }
+
+    fn dispatch(&self) -> ExtNat {
+        self.inner.dispatch()
+    }
+}
+
+/// Wraps another cost model, so that paths using only safer conversion tiers
+/// always win over paths using any less safe ones (see [`Tier`]). Each tier
+/// costs so much more than the last that no number of safer steps adds up to
+/// a single less safe one, so narrowing conversions are only used when no
+/// path makes do without them, and fabricated values only as a last resort.
+///
+/// Steps which can lose information are narrowing: lossy ground conversions,
+/// concatenating or coalescing properties, extracting part of a string,
+/// changing its case, and taking the first, sorted, deduplicated, sliced,
+/// flattened, joined or grouped items of an array. Filling in a value is
+/// fabricated, and every other step is widening at worst. (Converting dates
+/// counts as widening, although writing them as epoch seconds drops what's
+/// finer than a second.)
+#[derive(Clone, Debug)]
+pub struct SafetyFirst {
+    pub inner: Arc<dyn CostModel>,
+}
+
+impl SafetyFirst {
+    /// Extra cost of a step in the given tier. Each tier is `1 << 20` times
+    /// the last, so the ordering holds as long as a path's safer steps cost
+    /// less than that in all: fewer than about a million narrowing steps,
+    /// and inner costs adding up to less than a million, which any search
+    /// that finishes is well within.
+    pub fn penalty(tier: Tier) -> ExtNat {
+        ExtNat::Nat(match tier {
+            Tier::Identity | Tier::Widening => 0,
+            Tier::Narrowing => 1 << 20,
+            Tier::Fabricated => 1 << 40,
+        })
+    }
+}
+
+impl CostModel for SafetyFirst {
+    fn ground_to_ground(&self, from: &Ground, to: &Ground) -> ExtNat {
+        self.inner.ground_to_ground(from, to) + Self::penalty(tier(from, to))
+    }
+
+    fn lossy(&self, from: &Ground, to: &Ground) -> ExtNat {
+        self.inner.lossy(from, to)
+    }
+
+    fn drop_field(&self, key: &str) -> ExtNat {
+        self.inner.drop_field(key)
+    }
+
+    fn skip_field(&self, key: &str) -> ExtNat {
+        self.inner.skip_field(key)
+    }
+
+    fn nest(&self, key: &str) -> ExtNat {
+        self.inner.nest(key)
+    }
+
+    fn fill(&self, key: &str) -> ExtNat {
+        self.inner.fill(key) + Self::penalty(Tier::Fabricated)
+    }
+
+    fn concat(&self, key: &str) -> ExtNat {
+        self.inner.concat(key) + Self::penalty(Tier::Narrowing)
+    }
+
+    fn coalesce(&self, key: &str) -> ExtNat {
+        self.inner.coalesce(key) + Self::penalty(Tier::Narrowing)
+    }
+
+    fn scale(&self, key: &str) -> ExtNat {
+        self.inner.scale(key)
+    }
+
+    fn map_values(&self, key: &str) -> ExtNat {
+        self.inner.map_values(key)
+    }
+
+    fn convert_date(&self, key: &str) -> ExtNat {
+        self.inner.convert_date(key)
+    }
+
+    fn transcode(&self, key: &str) -> ExtNat {
+        self.inner.transcode(key)
+    }
+
+    fn extract_pattern(&self, key: &str) -> ExtNat {
+        self.inner.extract_pattern(key) + Self::penalty(Tier::Narrowing)
+    }
+
+    fn slice(&self, key: &str) -> ExtNat {
+        self.inner.slice(key) + Self::penalty(Tier::Narrowing)
+    }
+
+    fn sort(&self, key: &str) -> ExtNat {
+        self.inner.sort(key) + Self::penalty(Tier::Narrowing)
+    }
+
+    fn dedupe(&self, key: &str) -> ExtNat {
+        self.inner.dedupe(key) + Self::penalty(Tier::Narrowing)
+    }
+
+    fn gather(&self, key: &str) -> ExtNat {
+        self.inner.gather(key)
+    }
+
+    fn change_case(&self, key: &str) -> ExtNat {
+        self.inner.change_case(key) + Self::penalty(Tier::Narrowing)
+    }
+
+    fn rename(&self, from: &str, to: &str, score: u64) -> ExtNat {
+        self.inner.rename(from, to, score)
+    }
+
+    fn extract(&self, key: &str) -> ExtNat {
+        self.inner.extract(key)
+    }
+
+    fn wrap(&self) -> ExtNat {
+        self.inner.wrap()
+    }
+
+    fn first(&self) -> ExtNat {
+        self.inner.first() + Self::penalty(Tier::Narrowing)
+    }
+
+    fn flatten(&self) -> ExtNat {
+        self.inner.flatten() + Self::penalty(Tier::Narrowing)
+    }
+
+    fn join(&self) -> ExtNat {
+        self.inner.join() + Self::penalty(Tier::Narrowing)
+    }
+
+    fn invert(&self) -> ExtNat {
+        self.inner.invert()
+    }
+
+    fn group_by(&self) -> ExtNat {
+        self.inner.group_by() + Self::penalty(Tier::Narrowing)
+    }
+
+    fn zip(&self) -> ExtNat {
+        self.inner.zip()
+    }
+
+    fn dispatch(&self) -> ExtNat {
+        self.inner.dispatch()
+    }
+}
+
+/// Wraps another cost model, making renaming or extracting a source
+/// property cost more the less often it turned up in the corpus the source
+/// schema was inferred from, so that a target property is fed from one
+/// which is usually there when there's a choice. Properties with no
+/// recorded presence (see [`crate::frontend::infer::Stats::presence`])
+/// cost no more.
+#[derive(Clone, Debug)]
+pub struct Rarity {
+    pub inner: Arc<dyn CostModel>,
+    /// How often each property turned up, in percent.
+    pub presence: BTreeMap<String, u64>,
+    /// Extra cost of a property which never turned up at all.
+    pub penalty: u64,
+}
+
+impl Rarity {
+    /// Extra cost of reading source property `key`, in proportion to how
+    /// often it was missing.
+    fn rarity(&self, key: &str) -> ExtNat {
+        let missing = self
+            .presence
+            .get(key)
+            .map_or(0, |rate| 100 - (*rate).min(100));
+        ExtNat::Nat(self.penalty * missing / 100)
+    }
+}
+
+impl CostModel for Rarity {
+    fn ground_to_ground(&self, from: &Ground, to: &Ground) -> ExtNat {
+        self.inner.ground_to_ground(from, to)
+    }
+
+    fn lossy(&self, from: &Ground, to: &Ground) -> ExtNat {
+        self.inner.lossy(from, to)
+    }
+
+    fn drop_field(&self, key: &str) -> ExtNat {
+        self.inner.drop_field(key)
+    }
+
+    fn skip_field(&self, key: &str) -> ExtNat {
+        self.inner.skip_field(key)
+    }
+
+    fn nest(&self, key: &str) -> ExtNat {
+        self.inner.nest(key)
+    }
+
+    fn fill(&self, key: &str) -> ExtNat {
+        self.inner.fill(key)
+    }
+
+    fn concat(&self, key: &str) -> ExtNat {
+        self.inner.concat(key)
+    }
+
+    fn coalesce(&self, key: &str) -> ExtNat {
+        self.inner.coalesce(key)
+    }
+
+    fn scale(&self, key: &str) -> ExtNat {
+        self.inner.scale(key)
+    }
+
+    fn map_values(&self, key: &str) -> ExtNat {
+        self.inner.map_values(key)
+    }
+
+    fn convert_date(&self, key: &str) -> ExtNat {
+        self.inner.convert_date(key)
+    }
+
+    fn transcode(&self, key: &str) -> ExtNat {
+        self.inner.transcode(key)
+    }
+
+    fn extract_pattern(&self, key: &str) -> ExtNat {
+        self.inner.extract_pattern(key)
+    }
+
+    fn slice(&self, key: &str) -> ExtNat {
+        self.inner.slice(key)
+    }
+
+    fn sort(&self, key: &str) -> ExtNat {
+        self.inner.sort(key)
+    }
+
+    fn dedupe(&self, key: &str) -> ExtNat {
+        self.inner.dedupe(key)
+    }
+
+    fn gather(&self, key: &str) -> ExtNat {
+        self.inner.gather(key)
+    }
+
+    fn change_case(&self, key: &str) -> ExtNat {
+        self.inner.change_case(key)
+    }
+
+    fn rename(&self, from: &str, to: &str, score: u64) -> ExtNat {
+        self.inner.rename(from, to, score) + self.rarity(from)
+    }
+
+    fn extract(&self, key: &str) -> ExtNat {
+        self.inner.extract(key) + self.rarity(key)
+    }
+
+    fn wrap(&self) -> ExtNat {
+        self.inner.wrap()
+    }
+
+    fn first(&self) -> ExtNat {
+        self.inner.first()
+    }
+
+    fn flatten(&self) -> ExtNat {
+        self.inner.flatten()
+    }
+
+    fn join(&self) -> ExtNat {
+        self.inner.join()
+    }
+
+    fn invert(&self) -> ExtNat {
+        self.inner.invert()
+    }
+
+    fn group_by(&self) -> ExtNat {
+        self.inner.group_by()
+    }
+
+    fn zip(&self) -> ExtNat {
+        self.inner.zip()
+    }
+
+    fn dispatch(&self) -> ExtNat {
+        self.inner.dispatch()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossiness() {
+        assert!(!is_lossy(&Ground::Num, &Ground::String));
+        assert!(!is_lossy(&Ground::Null, &Ground::Bool));
+        assert!(is_lossy(&Ground::String, &Ground::Num));
+        assert!(is_lossy(&Ground::Num, &Ground::Bool));
+        assert!(is_lossy(&Ground::Bool, &Ground::Null));
+    }
+
+    #[test]
+    fn test_tiers() {
+        assert_eq!(tier(&Ground::Num, &Ground::Num), Tier::Identity);
+        assert_eq!(tier(&Ground::Bool, &Ground::Num), Tier::Widening);
+        assert_eq!(tier(&Ground::String, &Ground::Bool), Tier::Narrowing);
+        assert_eq!(tier(&Ground::Null, &Ground::String), Tier::Fabricated);
+    }
+
+    #[test]
+    fn test_safety_first() {
+        let model = SafetyFirst {
+            inner: Arc::new(DefaultCostModel),
+        };
+        let narrowing = SafetyFirst::penalty(Tier::Narrowing) + ExtNat::Nat(1);
+        assert_eq!(model.first(), narrowing);
+        assert_eq!(model.extract_pattern("code"), narrowing);
+        assert_eq!(model.slice("tags"), narrowing);
+        assert_eq!(
+            model.ground_to_ground(&Ground::String, &Ground::Num),
+            narrowing
+        );
+        assert_eq!(model.wrap(), ExtNat::Nat(1));
+        assert_eq!(model.scale("height"), ExtNat::Nat(1));
+        assert!(model.fill("id") > model.concat("name"));
+    }
+
+    #[test]
+    fn test_rarity() {
+        let model = Rarity {
+            inner: Arc::new(DefaultCostModel),
+            presence: [("always", 100), ("half", 50), ("never", 0)]
+                .map(|(key, rate)| (key.to_string(), rate))
+                .into(),
+            penalty: 4,
+        };
+        assert_eq!(model.rename("always", "a", 1), ExtNat::Nat(1));
+        assert_eq!(model.rename("half", "h", 1), ExtNat::Nat(3));
+        assert_eq!(model.extract("never"), ExtNat::Nat(5));
+        assert_eq!(model.extract("unobserved"), ExtNat::Nat(1));
+        assert_eq!(model.drop_field("never"), ExtNat::Nat(1));
+    }
+
+    #[test]
+    fn test_loss_averse() {
+        let model = LossAverse { penalty: 10 };
+        assert_eq!(
+            model.ground_to_ground(&Ground::Num, &Ground::String),
+            ExtNat::Nat(1)
+        );
+        assert_eq!(
+            model.ground_to_ground(&Ground::String, &Ground::Num),
+            ExtNat::Nat(11)
+        );
+    }
+}
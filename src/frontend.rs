@@ -0,0 +1,241 @@
+//! Frontends for reading schemas which aren't written as JSON Schema. Each
+//! frontend lowers its input format into our [`Schema`] representation so the
+//! rest of the pipeline doesn't need to know where a schema came from.
+
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use serde_json::Value;
+
+use crate::schema::{PropOrder, Schema, SchemaErr};
+
+pub mod bigquery;
+pub mod csv;
+pub mod infer;
+pub mod json5;
+
+/// Input formats we know how to turn into a [`Schema`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    JsonSchema,
+    BigQuery,
+    /// An example instance rather than a schema; the schema is inferred.
+    Example,
+    /// Newline-delimited example instances; the schema is inferred from all
+    /// of them together.
+    Corpus,
+    /// A CSV file with a header row; the schema describes a single row.
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonschema" => Ok(Self::JsonSchema),
+            "bigquery" => Ok(Self::BigQuery),
+            "infer" => Ok(Self::Example),
+            "ndjson" => Ok(Self::Corpus),
+            "csv" => Ok(Self::Csv),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Error while loading a schema from disk through one of the frontends.
+#[derive(Debug)]
+pub enum LoadErr {
+    Io(std::io::Error),
+    Json(json5::Json5Err),
+    Ndjson(serde_json::Error),
+    Schema(SchemaErr),
+    BigQuery(bigquery::BigQueryErr),
+    Csv(csv::CsvErr),
+}
+
+impl Display for LoadErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "couldn't read schema: {}", e),
+            Self::Json(e) => write!(f, "schema isn't valid JSON: {}", e),
+            Self::Ndjson(e) => write!(f, "corpus isn't valid NDJSON: {}", e),
+            Self::Schema(e) => write!(f, "invalid JSON schema: {}", e),
+            Self::BigQuery(e) => write!(f, "invalid BigQuery schema: {}", e),
+            Self::Csv(e) => write!(f, "invalid CSV: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadErr {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<json5::Json5Err> for LoadErr {
+    fn from(value: json5::Json5Err) -> Self {
+        Self::Json(value)
+    }
+}
+
+impl From<serde_json::Error> for LoadErr {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Ndjson(value)
+    }
+}
+
+impl From<SchemaErr> for LoadErr {
+    fn from(value: SchemaErr) -> Self {
+        Self::Schema(value)
+    }
+}
+
+impl From<bigquery::BigQueryErr> for LoadErr {
+    fn from(value: bigquery::BigQueryErr) -> Self {
+        Self::BigQuery(value)
+    }
+}
+
+impl From<csv::CsvErr> for LoadErr {
+    fn from(value: csv::CsvErr) -> Self {
+        Self::Csv(value)
+    }
+}
+
+/// Parse a schema out of a JSON document. A JSON Schema is always an object or
+/// a boolean, so a top-level array is taken to be a BigQuery table schema.
+pub fn from_json(json: &Value) -> Result<Schema, LoadErr> {
+    match json {
+        Value::Array(_) => Ok(bigquery::from_json(json)?),
+        _ => Ok(Schema::try_from(json)?),
+    }
+}
+
+/// Parse a JSON document. Strict JSON is tried first, falling back to JSON5 so
+/// that hand-maintained files with comments and trailing commas still load.
+pub fn parse_json(text: &str) -> Result<Value, LoadErr> {
+    match serde_json::from_str(text) {
+        Ok(json) => Ok(json),
+        Err(_) => Ok(json5::from_str(text)?),
+    }
+}
+
+/// The format of the file at `path`, where its extension says.
+fn guess(path: &Path) -> Option<Format> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ndjson" | "jsonl") => Some(Format::Corpus),
+        Some("csv") => Some(Format::Csv),
+        _ => None,
+    }
+}
+
+/// Parse a schema out of `contents`, in `format`, or if that isn't known, as
+/// a JSON document. Documents are parsed as [`parse_json`] does, whatever
+/// their format.
+pub fn parse_schema(contents: &str, format: Option<Format>) -> Result<Schema, LoadErr> {
+    match format {
+        None => from_json(&parse_json(contents)?),
+        Some(Format::JsonSchema) => Ok(Schema::try_from(&parse_json(contents)?)?),
+        Some(Format::BigQuery) => Ok(bigquery::from_json(&parse_json(contents)?)?),
+        Some(Format::Example) => Ok(infer::from_instance(&parse_json(contents)?)),
+        Some(Format::Corpus) => Ok(infer::from_ndjson(contents)?.schema()),
+        Some(Format::Csv) => Ok(csv::from_csv(contents, csv::DEFAULT_SAMPLE_ROWS)?),
+    }
+}
+
+/// Read and parse the schema stored at `path`, guessing its format.
+pub fn load(path: impl AsRef<Path>) -> Result<Schema, LoadErr> {
+    let path = path.as_ref();
+    parse_schema(&std::fs::read_to_string(path)?, guess(path))
+}
+
+/// Read and parse the schema stored at `path` in the given format.
+pub fn load_as(path: impl AsRef<Path>, format: Format) -> Result<Schema, LoadErr> {
+    parse_schema(&std::fs::read_to_string(path)?, Some(format))
+}
+
+/// The order the JSON Schema `contents` declares its properties in, which
+/// parsing it into a [`Schema`] loses.
+pub fn parse_order(contents: &str) -> Result<PropOrder, LoadErr> {
+    let (json, orders) = json5::from_str_ordered(contents)?;
+    Ok(PropOrder::from_json(&json, &orders))
+}
+
+/// Read the order the JSON Schema stored at `path` declares its properties
+/// in, which parsing it into a [`Schema`] loses.
+pub fn load_order(path: impl AsRef<Path>) -> Result<PropOrder, LoadErr> {
+    parse_order(&std::fs::read_to_string(path)?)
+}
+
+/// The example instances of the schema in `contents`, in `format`: those
+/// a JSON Schema lists in `examples`, or the instances a schema is inferred
+/// from. Other formats have none.
+pub fn parse_examples(contents: &str, format: Option<Format>) -> Result<Vec<Value>, LoadErr> {
+    match format.unwrap_or(Format::JsonSchema) {
+        Format::Example => Ok(vec![parse_json(contents)?]),
+        Format::Corpus => Ok(contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?),
+        Format::JsonSchema => match parse_json(contents)?.get("examples") {
+            Some(Value::Array(examples)) => Ok(examples.clone()),
+            _ => Ok(vec![]),
+        },
+        Format::BigQuery | Format::Csv => Ok(vec![]),
+    }
+}
+
+/// The example instances of the schema loaded from command-line argument
+/// `arg`, as [`parse_examples`] finds them.
+pub fn load_examples(arg: &str) -> Result<Vec<Value>, LoadErr> {
+    let (format, path) = split_arg(arg);
+    parse_examples(&std::fs::read_to_string(path)?, format)
+}
+
+/// A command-line argument naming a schema, split into the format it's
+/// in and where it's found. Arguments may be prefixed with a format name,
+/// e.g. `infer:payload.json`; otherwise the format is guessed from the
+/// extension, if there is one.
+pub fn split_arg(arg: &str) -> (Option<Format>, &str) {
+    if let Some((prefix, path)) = arg.split_once(':') {
+        if let Ok(format) = prefix.parse() {
+            return (Some(format), path);
+        }
+    }
+    (guess(Path::new(arg)), arg)
+}
+
+/// Load a schema from a command-line argument, in the format
+/// [`split_arg`] finds.
+pub fn load_arg(arg: &str) -> Result<Schema, LoadErr> {
+    let (format, path) = split_arg(arg);
+    parse_schema(&std::fs::read_to_string(path)?, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lenient_formats() {
+        let schema = r#"{
+            // hand-maintained
+            "type": "object",
+            "properties": {"id": {"type": "number"},},
+        }"#;
+        let parsed = parse_schema(schema, None).unwrap();
+        assert_eq!(
+            parse_schema(schema, Some(Format::JsonSchema)).unwrap(),
+            parsed
+        );
+        let table = "[{name: 'id', type: 'INTEGER', mode: 'REQUIRED'},]";
+        assert!(parse_schema(table, Some(Format::BigQuery)).is_ok());
+        let example = "{id: 1, /* sample */}";
+        assert!(parse_schema(example, Some(Format::Example)).is_ok());
+        assert_eq!(
+            parse_examples(example, Some(Format::Example)).unwrap(),
+            vec![serde_json::json!({"id": 1})]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! BigQuery table schemas, as exported by `bq show --schema` or the
+//! `tables.get` API. These are arrays of field descriptors of the form
+//! `{"name": ..., "type": ..., "mode": ..., "fields": [...]}`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    sync::Arc,
+};
+
+use serde_json::{Map, Value};
+
+use crate::schema::Schema;
+
+/// Error while parsing a BigQuery table schema.
+#[derive(Debug)]
+pub enum BigQueryErr {
+    /// The table schema (or a record's `fields`) wasn't an array of fields.
+    FieldsNotArray,
+    FieldNotObject,
+    FieldNeedsName,
+    FieldNeedsType,
+    RecordNeedsFields(String),
+    UnknownType(String),
+    UnknownMode(String),
+}
+
+impl Display for BigQueryErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use BigQueryErr::*;
+        match self {
+            FieldsNotArray => write!(f, "expected an array of fields"),
+            FieldNotObject => write!(f, "expected field to be an object"),
+            FieldNeedsName => write!(f, "field is missing a name"),
+            FieldNeedsType => write!(f, "field is missing a type"),
+            RecordNeedsFields(name) => write!(f, "record field `{}` has no fields", name),
+            UnknownType(ty) => write!(f, "unknown field type `{}`", ty),
+            UnknownMode(mode) => write!(f, "unknown field mode `{}`", mode),
+        }
+    }
+}
+
+/// Convert a BigQuery table schema into a [`Schema`]. The table itself becomes
+/// an object whose properties are the top-level fields. NULLABLE fields (the
+/// default) become optional properties which may also be null.
+pub fn from_json(json: &Value) -> Result<Schema, BigQueryErr> {
+    fields_to_obj(json)
+}
+
+fn fields_to_obj(fields: &Value) -> Result<Schema, BigQueryErr> {
+    use BigQueryErr::*;
+
+    let fields = fields.as_array().ok_or(FieldsNotArray)?;
+    let mut props = BTreeMap::new();
+    let mut required = BTreeSet::new();
+    for field in fields {
+        let field = field.as_object().ok_or(FieldNotObject)?;
+        let name = field
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or(FieldNeedsName)?;
+        let key = Arc::new(name.to_string());
+        let mode = field
+            .get("mode")
+            .and_then(Value::as_str)
+            .unwrap_or("NULLABLE");
+        if !mode.eq_ignore_ascii_case("NULLABLE") {
+            required.insert(key.clone());
+        }
+        props.insert(key, Arc::new(field_to_schema(name, field)?));
+    }
+    Ok(Schema::Obj {
+        props,
+        required,
+        additional: None,
+        closed: false,
+    })
+}
+
+fn field_to_schema(name: &str, field: &Map<String, Value>) -> Result<Schema, BigQueryErr> {
+    use BigQueryErr::*;
+
+    let ty = field
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or(FieldNeedsType)?;
+    let schema = match ty.to_ascii_uppercase().as_str() {
+        "INTEGER" | "INT64" | "FLOAT" | "FLOAT64" | "NUMERIC" | "BIGNUMERIC" => Schema::num(),
+        "BOOLEAN" | "BOOL" => Schema::bool(),
+        "STRING" | "BYTES" | "TIMESTAMP" | "DATE" | "TIME" | "DATETIME" | "GEOGRAPHY"
+        | "INTERVAL" => Schema::string(),
+        "JSON" => Schema::True,
+        "RECORD" | "STRUCT" => {
+            let fields = field
+                .get("fields")
+                .ok_or_else(|| RecordNeedsFields(name.to_string()))?;
+            fields_to_obj(fields)?
+        }
+        _ => return Err(UnknownType(ty.to_string())),
+    };
+
+    // NULLABLE fields are optional in their parent object, and exported
+    // as null where they're left out of a row; JSON ones are already
+    let nullable = |schema| match schema {
+        Schema::True => Schema::True,
+        schema => Schema::Union(vec![Arc::new(schema), Arc::new(Schema::null())]),
+    };
+    let mode = field
+        .get("mode")
+        .and_then(Value::as_str)
+        .unwrap_or("NULLABLE");
+    match mode.to_ascii_uppercase().as_str() {
+        "NULLABLE" => Ok(nullable(schema)),
+        "REQUIRED" => Ok(schema),
+        "REPEATED" => Ok(Schema::Arr(Arc::new(schema))),
+        _ => Err(UnknownMode(mode.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        sync::Arc,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_flat_table() {
+        let table = json!([
+            {"name": "id", "type": "INTEGER", "mode": "REQUIRED"},
+            {"name": "name", "type": "STRING"},
+            {"name": "active", "type": "BOOL", "mode": "NULLABLE"}
+        ]);
+        let expected = crate::schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "name": {"anyOf": [{"type": "string"}, {"type": "null"}]},
+                "active": {"anyOf": [{"type": "boolean"}, {"type": "null"}]}
+            },
+            "required": ["id"]
+        });
+        assert_eq!(from_json(&table).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_repeated_record() {
+        let table = json!([
+            {
+                "name": "addresses",
+                "type": "RECORD",
+                "mode": "REPEATED",
+                "fields": [
+                    {"name": "city", "type": "STRING", "mode": "REQUIRED"},
+                    {"name": "zip", "type": "STRING"}
+                ]
+            },
+            {"name": "tags", "type": "STRING", "mode": "REPEATED"}
+        ]);
+        let expected = crate::schema!({
+            "type": "object",
+            "properties": {
+                "addresses": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "city": {"type": "string"},
+                            "zip": {"anyOf": [{"type": "string"}, {"type": "null"}]}
+                        },
+                        "required": ["city"]
+                    }
+                },
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["addresses", "tags"]
+        });
+        assert_eq!(from_json(&table).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_nullable_record() {
+        let table = json!([{
+            "name": "owner",
+            "type": "RECORD",
+            "mode": "NULLABLE",
+            "fields": [{"name": "id", "type": "INT64", "mode": "REQUIRED"}]
+        }]);
+        let expected = crate::schema!({
+            "type": "object",
+            "properties": {
+                "owner": {"anyOf": [
+                    {"type": "object", "properties": {"id": {"type": "number"}}, "required": ["id"]},
+                    {"type": "null"}
+                ]}
+            }
+        });
+        assert_eq!(from_json(&table).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_json_column_is_true() {
+        let table = json!([{"name": "payload", "type": "JSON"}]);
+        let mut props = BTreeMap::new();
+        props.insert(Arc::new("payload".to_string()), Arc::new(Schema::True));
+        assert_eq!(
+            from_json(&table).unwrap(),
+            Schema::Obj {
+                props,
+                required: BTreeSet::new(),
+                additional: None,
+                closed: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_unknown_type() {
+        let table = json!([{"name": "x", "type": "WIDGET"}]);
+        assert!(matches!(
+            from_json(&table),
+            Err(BigQueryErr::UnknownType(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_needs_fields() {
+        let table = json!([{"name": "x", "type": "RECORD"}]);
+        assert!(matches!(
+            from_json(&table),
+            Err(BigQueryErr::RecordNeedsFields(_))
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+//! CSV files. The header row names the properties of a flat object schema
+//! describing one row, and each column's type is sniffed from a sample of the
+//! rows beneath it. Columns with an empty cell among those rows are taken to
+//! be optional, and the rest to be required.
+
+use std::{collections::BTreeMap, fmt::Display, sync::Arc};
+
+use crate::schema::{Ground, Schema};
+
+/// Number of rows looked at when sniffing column types.
+pub const DEFAULT_SAMPLE_ROWS: usize = 100;
+
+/// Error while parsing a CSV file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CsvErr {
+    NoHeader,
+    UnterminatedQuote,
+    /// A data row (numbered from 1, after the header) had the wrong number of
+    /// fields.
+    RowLength {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl Display for CsvErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoHeader => write!(f, "missing header row"),
+            Self::UnterminatedQuote => write!(f, "unterminated quoted field"),
+            Self::RowLength {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {} has {} fields, but the header has {}",
+                row, found, expected
+            ),
+        }
+    }
+}
+
+/// Split CSV text into records of fields, following RFC 4180: fields may be
+/// quoted with `"`, quotes are escaped by doubling, and quoted fields may
+/// contain separators and newlines.
+pub fn parse_records(text: &str) -> Result<Vec<Vec<String>>, CsvErr> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(CsvErr::UnterminatedQuote);
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Whether `value` is a number as JSON writes them, unlike `NaN`, `inf` or
+/// `007`, which Rust would parse as one.
+fn numeral(value: &str) -> bool {
+    value.trim() == value && serde_json::from_str::<serde_json::Number>(value).is_ok()
+}
+
+/// Guess the ground type of a column from its (non-empty) sampled values.
+fn sniff<'a>(values: impl Iterator<Item = &'a str>) -> Ground {
+    let mut ty = None;
+    for value in values.filter(|v| !v.is_empty()) {
+        let this = if numeral(value) {
+            Ground::Num
+        } else if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+            Ground::Bool
+        } else {
+            return Ground::String;
+        };
+        match ty {
+            None => ty = Some(this),
+            Some(ref t) if *t != this => return Ground::String,
+            _ => {}
+        }
+    }
+    ty.unwrap_or(Ground::String)
+}
+
+/// Produce the schema of a single row of the CSV file `text`, sniffing column
+/// types from (at most) the first `sample` rows.
+pub fn from_csv(text: &str, sample: usize) -> Result<Schema, CsvErr> {
+    let records = parse_records(text)?;
+    let (header, rows) = records.split_first().ok_or(CsvErr::NoHeader)?;
+
+    for (i, row) in rows.iter().enumerate() {
+        if row.len() != header.len() {
+            return Err(CsvErr::RowLength {
+                row: i + 1,
+                expected: header.len(),
+                found: row.len(),
+            });
+        }
+    }
+
+    let sampled = &rows[..rows.len().min(sample)];
+    let props: BTreeMap<_, _> = header
+        .iter()
+        .enumerate()
+        .map(|(col, name)| {
+            let ty = sniff(sampled.iter().map(|row| row[col].as_str()));
+            (Arc::new(name.clone()), Arc::new(Schema::Ground(ty)))
+        })
+        .collect();
+    let mut schema = Schema::obj(props);
+    if let Schema::Obj { required, .. } = &mut schema {
+        for (col, name) in header.iter().enumerate() {
+            if sampled.iter().any(|row| row[col].is_empty()) {
+                required.remove(name);
+            }
+        }
+    }
+    Ok(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quoted_records() {
+        let text = "a,b\r\n\"x, y\",\"say \"\"hi\"\"\"\n\"multi\nline\",z";
+        assert_eq!(
+            parse_records(text).unwrap(),
+            vec![
+                vec!["a".to_string(), "b".to_string()],
+                vec!["x, y".to_string(), "say \"hi\"".to_string()],
+                vec!["multi\nline".to_string(), "z".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sniff_column_types() {
+        let text = "id,name,active,score\n1,ada,true,\n2,bob,FALSE,3.5\n";
+        let expected = crate::schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "name": {"type": "string"},
+                "active": {"type": "boolean"},
+                "score": {"type": "number"}
+            },
+            "required": ["id", "name", "active"]
+        });
+        assert_eq!(from_csv(text, DEFAULT_SAMPLE_ROWS).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sniff_json_numerals() {
+        let text = "a,b,c,d,e
+NaN,inf,infinity,007,-1.5e3
+";
+        let expected = crate::schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "string"},
+                "b": {"type": "string"},
+                "c": {"type": "string"},
+                "d": {"type": "string"},
+                "e": {"type": "number"}
+            },
+            "required": ["a", "b", "c", "d", "e"]
+        });
+        assert_eq!(from_csv(text, DEFAULT_SAMPLE_ROWS).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sniff_respects_sample_size() {
+        let text = "code\n1\n2\nA3\n";
+        assert_eq!(
+            from_csv(text, 2).unwrap(),
+            crate::schema!({
+                "type": "object",
+                "properties": {"code": {"type": "number"}},
+                "required": ["code"]
+            })
+        );
+        assert_eq!(
+            from_csv(text, 3).unwrap(),
+            crate::schema!({
+                "type": "object",
+                "properties": {"code": {"type": "string"}},
+                "required": ["code"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_ragged_row() {
+        assert_eq!(
+            from_csv("a,b\n1\n", DEFAULT_SAMPLE_ROWS),
+            Err(CsvErr::RowLength {
+                row: 1,
+                expected: 2,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_unterminated_quote() {
+        assert_eq!(parse_records("a\n\"oops"), Err(CsvErr::UnterminatedQuote));
+    }
+}
@@ -0,0 +1,418 @@
+//! Schema inference from example documents. When all we have for one side of
+//! a migration is a sample payload (or a dump of many of them), we synthesize
+//! the narrowest [`Schema`] that the samples validate against.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde_json::Value;
+
+use crate::schema::Schema;
+
+/// Infer a schema from a single example instance.
+pub fn from_instance(instance: &Value) -> Schema {
+    match instance {
+        Value::Null => Schema::null(),
+        Value::Bool(_) => Schema::bool(),
+        Value::Number(_) => Schema::num(),
+        Value::String(_) => Schema::string(),
+        Value::Array(items) => {
+            let item = items
+                .iter()
+                .map(from_instance)
+                .reduce(|acc, s| unify(&acc, &s))
+                .unwrap_or(Schema::True);
+            Schema::Arr(Arc::new(item))
+        }
+        Value::Object(obj) => Schema::obj(
+            obj.iter()
+                .map(|(k, v)| (Arc::new(k.clone()), Arc::new(from_instance(v))))
+                .collect(),
+        ),
+    }
+}
+
+/// Find a schema which admits everything both `s1` and `s2` admit. Objects are
+/// unified property-wise (properties only required if both sides require
+/// them) and arrays item-wise; anything else which disagrees becomes a
+/// [`Schema::Union`] with one branch per kind of value.
+pub fn unify(s1: &Schema, s2: &Schema) -> Schema {
+    use Schema::*;
+
+    if s1 == s2 {
+        return s1.clone();
+    }
+
+    match (s1, s2) {
+        (False, s) | (s, False) => s.clone(),
+        (True, _) | (_, True) => True,
+        // only the annotations both sides agree on are kept
+        (Annotated(i1, a1), Annotated(i2, a2)) => unify(i1, i2).annotated(a1.common(a2)),
+        (Annotated(inner, _), s) | (s, Annotated(inner, _)) => unify(inner, s),
+        (Union(_), _) | (_, Union(_)) => {
+            let mut branches: Vec<Arc<Schema>> = vec![];
+            for branch in [s1, s2].into_iter().flat_map(branches_of) {
+                match branches.iter_mut().find(|b| same_kind(b, &branch)) {
+                    Some(b) => *b = Arc::new(unify(b, &branch)),
+                    None => branches.push(branch),
+                }
+            }
+            union(branches)
+        }
+        (Arr(i1), Arr(i2)) => Arr(Arc::new(unify(i1, i2))),
+        (
+            Obj {
+                props: o1,
+                required: r1,
+                additional: a1,
+                closed: k1,
+            },
+            Obj {
+                props: o2,
+                required: r2,
+                additional: a2,
+                closed: k2,
+            },
+        ) => {
+            let mut props = BTreeMap::new();
+            for (k, v1) in o1.iter() {
+                let v = match o2.get(k) {
+                    Some(v2) => Arc::new(unify(v1, v2)),
+                    None => v1.clone(),
+                };
+                props.insert(k.clone(), v);
+            }
+            for (k, v2) in o2.iter() {
+                props.entry(k.clone()).or_insert_with(|| v2.clone());
+            }
+            let required = r1.intersection(r2).cloned().collect();
+            let additional = match (a1, a2) {
+                (Some(a1), Some(a2)) => Some(Arc::new(unify(a1, a2))),
+                _ => None,
+            };
+            Obj {
+                props,
+                required,
+                additional,
+                closed: *k1 && *k2,
+            }
+        }
+        (_, _) => Union(vec![Arc::new(s1.clone()), Arc::new(s2.clone())]),
+    }
+}
+
+fn branches_of(schema: &Schema) -> Vec<Arc<Schema>> {
+    match schema {
+        Schema::Union(branches) => branches.clone(),
+        _ => vec![Arc::new(schema.clone())],
+    }
+}
+
+/// Whether two schemas describe the same kind of value, and so unify into a
+/// single branch of a union.
+fn same_kind(s1: &Schema, s2: &Schema) -> bool {
+    use Schema::*;
+
+    match (s1.bare(), s2.bare()) {
+        (Ground(g1), Ground(g2)) => g1 == g2,
+        (Arr(_), Arr(_)) | (Obj { .. }, Obj { .. }) => true,
+        _ => false,
+    }
+}
+
+/// A union of `branches`, or the only branch if there's just one.
+fn union(mut branches: Vec<Arc<Schema>>) -> Schema {
+    match branches.len() {
+        0 => Schema::False,
+        1 => branches.remove(0).as_ref().clone(),
+        _ => Schema::Union(branches),
+    }
+}
+
+/// Occurrence statistics for one position (the root, a property, or the items
+/// of an array) across a corpus of documents, from which the schema tells
+/// which fields are optional or nullable in practice.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of values observed at this position.
+    pub seen: u64,
+    pub nulls: u64,
+    pub bools: u64,
+    pub nums: u64,
+    pub strings: u64,
+    pub arrays: u64,
+    pub objects: u64,
+    /// Statistics over the items of every array observed here.
+    pub items: Option<Box<Stats>>,
+    /// Statistics for each property of every object observed here.
+    pub props: BTreeMap<String, Stats>,
+}
+
+impl Stats {
+    /// Record one more value at this position.
+    pub fn observe(&mut self, value: &Value) {
+        self.seen += 1;
+        match value {
+            Value::Null => self.nulls += 1,
+            Value::Bool(_) => self.bools += 1,
+            Value::Number(_) => self.nums += 1,
+            Value::String(_) => self.strings += 1,
+            Value::Array(items) => {
+                self.arrays += 1;
+                let item_stats = self.items.get_or_insert_with(Default::default);
+                for item in items {
+                    item_stats.observe(item);
+                }
+            }
+            Value::Object(obj) => {
+                self.objects += 1;
+                for (k, v) in obj.iter() {
+                    self.props.entry(k.clone()).or_default().observe(v);
+                }
+            }
+        }
+    }
+
+    /// Whether some object observed here was missing property `key`.
+    pub fn is_optional(&self, key: &str) -> bool {
+        self.props
+            .get(key)
+            .is_none_or(|prop| prop.seen < self.objects)
+    }
+
+    /// How often each property turned up, as a percentage of the objects
+    /// it could have turned up in, here or anywhere beneath. A property
+    /// found in several places gets the lowest of its rates.
+    pub fn presence(&self) -> BTreeMap<String, u64> {
+        let mut presence = BTreeMap::new();
+        self.gather_presence(&mut presence);
+        presence
+    }
+
+    fn gather_presence(&self, presence: &mut BTreeMap<String, u64>) {
+        for (key, prop) in &self.props {
+            let rate = prop.seen * 100 / self.objects.max(1);
+            presence
+                .entry(key.clone())
+                .and_modify(|lowest: &mut u64| *lowest = (*lowest).min(rate))
+                .or_insert(rate);
+            prop.gather_presence(presence);
+        }
+        if let Some(items) = &self.items {
+            items.gather_presence(presence);
+        }
+    }
+
+    /// Whether both null and non-null values were observed here.
+    pub fn is_nullable(&self) -> bool {
+        self.nulls > 0 && self.nulls < self.seen
+    }
+
+    /// Whether values of more than one (non-null) type were observed here.
+    pub fn is_heterogeneous(&self) -> bool {
+        self.kinds().filter(|n| *n > 0).count() > 1
+    }
+
+    fn kinds(&self) -> impl Iterator<Item = u64> {
+        [
+            self.bools,
+            self.nums,
+            self.strings,
+            self.arrays,
+            self.objects,
+        ]
+        .into_iter()
+    }
+
+    /// The schema of the values observed here. Properties missing from some
+    /// objects are optional, and heterogeneous and nullable positions
+    /// become a [`Schema::Union`] of each kind of value observed, `null`
+    /// included.
+    pub fn schema(&self) -> Schema {
+        let mut branches = vec![];
+        if self.bools > 0 {
+            branches.push(Arc::new(Schema::bool()));
+        }
+        if self.nums > 0 {
+            branches.push(Arc::new(Schema::num()));
+        }
+        if self.strings > 0 {
+            branches.push(Arc::new(Schema::string()));
+        }
+        if self.arrays > 0 {
+            let items = self.items.as_ref().map_or(Schema::True, |i| i.schema());
+            branches.push(Arc::new(Schema::Arr(Arc::new(items))));
+        }
+        if self.objects > 0 {
+            branches.push(Arc::new(self.object_schema()));
+        }
+        if self.is_nullable() {
+            branches.push(Arc::new(Schema::null()));
+        }
+        match branches.len() {
+            0 if self.nulls > 0 => Schema::null(),
+            0 => Schema::True,
+            _ => union(branches),
+        }
+    }
+
+    fn object_schema(&self) -> Schema {
+        Schema::Obj {
+            props: self
+                .props
+                .iter()
+                .map(|(k, v)| (Arc::new(k.clone()), Arc::new(v.schema())))
+                .collect(),
+            required: self
+                .props
+                .keys()
+                .filter(|k| !self.is_optional(k))
+                .map(|k| Arc::new(k.clone()))
+                .collect(),
+            additional: None,
+            closed: false,
+        }
+    }
+}
+
+/// Gather statistics over a newline-delimited stream of JSON documents.
+pub fn from_ndjson(corpus: &str) -> Result<Stats, serde_json::Error> {
+    let mut stats = Stats::default();
+    for record in serde_json::Deserializer::from_str(corpus).into_iter::<Value>() {
+        stats.observe(&record?);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_infer_grounds() {
+        assert_eq!(from_instance(&json!(1.5)), Schema::num());
+        assert_eq!(from_instance(&json!("hi")), Schema::string());
+        assert_eq!(from_instance(&json!(false)), Schema::bool());
+        assert_eq!(from_instance(&json!(null)), Schema::null());
+    }
+
+    #[test]
+    fn test_infer_nested_object() {
+        let instance = json!({
+            "id": 7,
+            "user": {"name": "ada", "admin": true},
+            "tags": ["a", "b"]
+        });
+        let expected = crate::schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "user": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "admin": {"type": "boolean"}
+                    },
+                    "required": ["name", "admin"]
+                },
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["id", "user", "tags"]
+        });
+        assert_eq!(from_instance(&instance), expected);
+    }
+
+    #[test]
+    fn test_infer_unifies_array_items() {
+        let instance = json!([{"a": 1}, {"b": "x"}, {"a": 2, "b": "y"}]);
+        let expected = crate::schema!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "a": {"type": "number"},
+                    "b": {"type": "string"}
+                }
+            }
+        });
+        assert_eq!(from_instance(&instance), expected);
+    }
+
+    #[test]
+    fn test_infer_mixed_array_is_union() {
+        let instance = json!([1, "one", 2, {"a": 1}, {"b": true}]);
+        let expected = crate::schema!({
+            "type": "array",
+            "items": {
+                "oneOf": [
+                    {"type": "number"},
+                    {"type": "string"},
+                    {
+                        "type": "object",
+                        "properties": {
+                            "a": {"type": "number"},
+                            "b": {"type": "boolean"}
+                        }
+                    }
+                ]
+            }
+        });
+        assert_eq!(from_instance(&instance), expected);
+    }
+
+    #[test]
+    fn test_infer_empty_array_is_true() {
+        assert_eq!(
+            from_instance(&json!([])),
+            Schema::Arr(Arc::new(Schema::True))
+        );
+    }
+
+    #[test]
+    fn test_ndjson_optional_and_nullable() {
+        let corpus = r#"
+            {"id": 1, "name": "a", "email": null}
+            {"id": 2, "name": "b", "email": "b@example.com"}
+            {"id": 3, "email": "c@example.com"}
+        "#;
+        let stats = from_ndjson(corpus).unwrap();
+        assert_eq!(stats.seen, 3);
+        assert!(!stats.is_optional("id"));
+        assert!(stats.is_optional("name"));
+        assert!(stats.props["email"].is_nullable());
+        assert_eq!(stats.presence()["id"], 100);
+        assert_eq!(stats.presence()["name"], 66);
+        let expected = crate::schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "name": {"type": "string"},
+                "email": {"anyOf": [{"type": "string"}, {"type": "null"}]}
+            },
+            "required": ["id", "email"]
+        });
+        assert_eq!(stats.schema(), expected);
+    }
+
+    #[test]
+    fn test_ndjson_heterogeneous_array() {
+        let corpus = r#"{"xs": [1, 2]}
+{"xs": ["three"]}"#;
+        let stats = from_ndjson(corpus).unwrap();
+        let xs = &stats.props["xs"];
+        assert!(xs.items.as_ref().unwrap().is_heterogeneous());
+        assert_eq!(
+            xs.schema(),
+            crate::schema!({
+                "type": "array",
+                "items": {"oneOf": [{"type": "number"}, {"type": "string"}]}
+            })
+        );
+    }
+
+    #[test]
+    fn test_ndjson_bad_record() {
+        assert!(from_ndjson("{\"a\": 1}\n{nope}").is_err());
+    }
+}
@@ -0,0 +1,432 @@
+//! A parser for JSON5 (and so also JSONC, its comments-and-trailing-commas
+//! subset), producing ordinary [`Value`]s. Hand-maintained schemas tend to
+//! pick up comments and trailing commas which strict JSON rejects.
+//!
+//! Everything in JSON5 is supported except `Infinity` and `NaN`, which have no
+//! JSON representation.
+
+use std::{collections::HashMap, fmt::Display, iter::Peekable, str::Chars};
+
+use serde_json::{Map, Number, Value};
+
+/// Error while parsing JSON5, with the (1-based) position it occurred at.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Json5Err {
+    pub line: usize,
+    pub col: usize,
+    pub msg: String,
+}
+
+impl Display for Json5Err {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at line {} column {}", self.msg, self.line, self.col)
+    }
+}
+
+/// The keys of each object in a document in the order they were written,
+/// by the path of keys (and array indices) leading to the object. [`Value`]
+/// objects keep their keys sorted, so this is the only record of it.
+pub type KeyOrders = HashMap<Vec<String>, Vec<String>>;
+
+/// Parse a JSON5 document.
+pub fn from_str(text: &str) -> Result<Value, Json5Err> {
+    from_str_ordered(text).map(|(value, _)| value)
+}
+
+/// Parse a JSON5 document, also recording the order its keys were written in.
+pub fn from_str_ordered(text: &str) -> Result<(Value, KeyOrders), Json5Err> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+        line: 1,
+        col: 1,
+        path: vec![],
+        orders: KeyOrders::new(),
+    };
+    let value = parser.value()?;
+    parser.skip_trivia()?;
+    match parser.peek() {
+        None => Ok((value, parser.orders)),
+        Some(c) => Err(parser.err(format!("unexpected trailing `{}`", c))),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    col: usize,
+    /// Path to the value being parsed.
+    path: Vec<String>,
+    orders: KeyOrders,
+}
+
+impl Parser<'_> {
+    fn err(&self, msg: impl Into<String>) -> Json5Err {
+        Json5Err {
+            line: self.line,
+            col: self.col,
+            msg: msg.into(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Json5Err> {
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.err(format!("expected `{}`, found `{}`", expected, c))),
+            None => Err(self.err(format!("expected `{}`, found end of input", expected))),
+        }
+    }
+
+    /// Skip whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), Json5Err> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('/') => {
+                    self.bump();
+                    match self.bump() {
+                        Some('/') => {
+                            while !matches!(self.peek(), None | Some('\n')) {
+                                self.bump();
+                            }
+                        }
+                        Some('*') => loop {
+                            match self.bump() {
+                                Some('*') if self.peek() == Some('/') => {
+                                    self.bump();
+                                    break;
+                                }
+                                Some(_) => {}
+                                None => return Err(self.err("unterminated block comment")),
+                            }
+                        },
+                        _ => return Err(self.err("expected comment after `/`")),
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Json5Err> {
+        self.skip_trivia()?;
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some(q @ ('"' | '\'')) => self.string(q).map(Value::String),
+            Some(c) if c.is_ascii_digit() || matches!(c, '+' | '-' | '.') => self.number(),
+            Some(c) if is_ident_start(c) => match self.ident().as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                "null" => Ok(Value::Null),
+                "Infinity" | "NaN" => Err(self.err("Infinity and NaN can't be represented")),
+                word => Err(self.err(format!("unexpected identifier `{}`", word))),
+            },
+            Some(c) => Err(self.err(format!("unexpected `{}`", c))),
+            None => Err(self.err("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, Json5Err> {
+        self.expect('{')?;
+        let mut obj = Map::new();
+        let mut keys = vec![];
+        loop {
+            self.skip_trivia()?;
+            let key = match self.peek() {
+                Some('}') => break,
+                Some(q @ ('"' | '\'')) => self.string(q)?,
+                Some(c) if is_ident_start(c) => self.ident(),
+                _ => return Err(self.err("expected property name")),
+            };
+            self.skip_trivia()?;
+            self.expect(':')?;
+            self.path.push(key.clone());
+            let value = self.value()?;
+            self.path.pop();
+            // a repeated key replaces the earlier one, so it moves to the end
+            keys.retain(|k| *k != key);
+            keys.push(key.clone());
+            obj.insert(key, value);
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+        self.skip_trivia()?;
+        self.expect('}')?;
+        self.orders.insert(self.path.clone(), keys);
+        Ok(Value::Object(obj))
+    }
+
+    fn array(&mut self) -> Result<Value, Json5Err> {
+        self.expect('[')?;
+        let mut items = vec![];
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some(']') {
+                break;
+            }
+            self.path.push(items.len().to_string());
+            let item = self.value();
+            self.path.pop();
+            items.push(item?);
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+        self.skip_trivia()?;
+        self.expect(']')?;
+        Ok(Value::Array(items))
+    }
+
+    fn ident(&mut self) -> String {
+        let mut ident = String::new();
+        while let Some(c) = self.peek() {
+            if !(is_ident_start(c) || c.is_ascii_digit()) {
+                break;
+            }
+            ident.push(c);
+            self.bump();
+        }
+        ident
+    }
+
+    fn string(&mut self, quote: char) -> Result<String, Json5Err> {
+        self.expect(quote)?;
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.err("unterminated string")),
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') => match self.bump() {
+                    // escaped line terminators continue the string on the
+                    // next line
+                    Some('\n' | '\u{2028}' | '\u{2029}') => {}
+                    Some('\r') => {
+                        if self.peek() == Some('\n') {
+                            self.bump();
+                        }
+                    }
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('v') => s.push('\u{b}'),
+                    Some('0') if self.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                        return Err(self.err("octal escapes aren't allowed"))
+                    }
+                    Some('0') => s.push('\0'),
+                    Some('1'..='9') => return Err(self.err("octal escapes aren't allowed")),
+                    Some('x') => {
+                        let code = self.hex(2, "invalid hex escape")?;
+                        s.push(char::from_u32(code).expect("two hex digits are a char"));
+                    }
+                    Some('u') => s.push(self.unicode_escape()?),
+                    Some(c) => s.push(c),
+                    None => return Err(self.err("unterminated string")),
+                },
+                Some('\n') => return Err(self.err("unescaped newline in string")),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// The number written in the next `digits` hex digits, or `invalid`.
+    fn hex(&mut self, digits: usize, invalid: &str) -> Result<u32, Json5Err> {
+        let mut code = 0;
+        for _ in 0..digits {
+            let digit = self
+                .bump()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.err(invalid))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, Json5Err> {
+        let hi = self.hex(4, "invalid unicode escape")?;
+        let code = if (0xD800..0xDC00).contains(&hi) {
+            // surrogate pair
+            self.expect('\\')?;
+            self.expect('u')?;
+            let lo = self.hex(4, "invalid unicode escape")?;
+            0x10000 + ((hi - 0xD800) << 10) + (lo.wrapping_sub(0xDC00) & 0x3FF)
+        } else {
+            hi
+        };
+        char::from_u32(code).ok_or_else(|| self.err("invalid unicode escape"))
+    }
+
+    fn number(&mut self) -> Result<Value, Json5Err> {
+        let mut text = String::new();
+        let negative = match self.peek() {
+            Some('-') => {
+                self.bump();
+                true
+            }
+            Some('+') => {
+                self.bump();
+                false
+            }
+            _ => false,
+        };
+
+        let mut hex = false;
+        while let Some(c) = self.peek() {
+            if c == 'x' || c == 'X' {
+                hex = true;
+            } else if !(c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-')) {
+                break;
+            }
+            text.push(c);
+            self.bump();
+        }
+
+        if text == "Infinity" || text == "NaN" {
+            return Err(self.err("Infinity and NaN can't be represented"));
+        }
+
+        if hex {
+            let digits = text
+                .strip_prefix("0x")
+                .or_else(|| text.strip_prefix("0X"))
+                .ok_or_else(|| self.err("invalid hex number"))?;
+            let n = i64::from_str_radix(digits, 16)
+                .map_err(|_| self.err(format!("invalid hex number `{}`", text)))?;
+            return Ok(Value::Number(Number::from(if negative { -n } else { n })));
+        }
+
+        // JSON5 allows `.5` and `5.`; normalize them into something JSON-ish
+        if text.starts_with('.') {
+            text.insert(0, '0');
+        }
+        if text.ends_with('.') {
+            text.push('0');
+        }
+        text = text.replace(".e", ".0e").replace(".E", ".0E");
+        if negative {
+            text.insert(0, '-');
+        }
+
+        serde_json::from_str::<Number>(&text)
+            .map(Value::Number)
+            .map_err(|_| self.err(format!("invalid number `{}`", text)))
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_plain_json() {
+        let text = r#"{"type": "array", "items": {"type": "number"}, "x": [1, -2.5e3, null]}"#;
+        assert_eq!(
+            from_str(text).unwrap(),
+            serde_json::from_str::<Value>(text).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_jsonc_comments_and_trailing_commas() {
+        let text = r#"
+            // the user record
+            {
+                "type": "object", /* inline */
+                "properties": {
+                    "id": {"type": "number"},
+                },
+                "required": ["id",],
+            }
+        "#;
+        assert_eq!(
+            from_str(text).unwrap(),
+            json!({
+                "type": "object",
+                "properties": {"id": {"type": "number"}},
+                "required": ["id"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_json5_extensions() {
+        let text = "{unquoted: 'single \"quoted\"', hex: 0xFF, lead: .5, trail: 5., plus: +1, \
+                    cont: 'a\\\nb'}";
+        assert_eq!(
+            from_str(text).unwrap(),
+            json!({
+                "unquoted": "single \"quoted\"",
+                "hex": 255,
+                "lead": 0.5,
+                "trail": 5.0,
+                "plus": 1,
+                "cont": "ab"
+            })
+        );
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(from_str(r#"'\x41\x7e'"#).unwrap(), json!("A~"));
+        assert!(from_str(r#"'\x4'"#).is_err());
+        assert_eq!(from_str(r#"'a\0b'"#).unwrap(), json!("a\u{0}b"));
+        assert!(from_str(r#"'\01'"#).is_err());
+        assert!(from_str(r#"'\7'"#).is_err());
+        assert_eq!(from_str("'a\\\u{2028}b\\\u{2029}c'").unwrap(), json!("abc"));
+    }
+
+    #[test]
+    fn test_key_order() {
+        let (_, orders) = from_str_ordered(r#"{"z": 1, "a": [{"y": 1, "b": 2}], "m": 3}"#).unwrap();
+        assert_eq!(orders[&vec![]], vec!["z", "a", "m"]);
+        assert_eq!(
+            orders[&vec!["a".to_string(), "0".to_string()]],
+            vec!["y", "b"]
+        );
+    }
+
+    #[test]
+    fn test_error_position() {
+        let err = from_str("{\n  a: tru\n}").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_rejects_infinity() {
+        assert!(from_str("[Infinity]").is_err());
+        assert!(from_str("[-Infinity]").is_err());
+    }
+}
@@ -1,2 +1,446 @@
-/// IR for schema transformers
-enum IR { }
\ No newline at end of file
+//! The intermediate representation of transforms: paths of instructions
+//! moving focus around the input and output and converting what's in
+//! focus, which the searcher finds and the backends generate code from.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    matcher::words,
+    schema::{Ground, Literal, Real, Schema},
+};
+
+pub mod compose;
+pub mod diff;
+pub mod eval;
+pub mod optimize;
+pub mod program;
+pub mod text;
+pub mod verify;
+
+pub use compose::compose;
+pub use eval::eval;
+pub use verify::verify;
+
+/// Keys leading from the input down to a value nested inside it.
+pub type SourcePath = Vec<Arc<String>>;
+
+/// Source values and the target values they translate to.
+pub type ValueTable = BTreeMap<Literal, Literal>;
+
+/// A naming convention to convert strings to.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Case {
+    /// `USER ID`: every letter uppercased, and nothing else changed.
+    Upper,
+    /// `user id`: every letter lowercased, and nothing else changed.
+    Lower,
+    /// `userId`
+    Camel,
+    /// `user_id`
+    Snake,
+}
+
+impl Case {
+    pub const ALL: [Case; 4] = [Case::Upper, Case::Lower, Case::Camel, Case::Snake];
+
+    /// The case called `name` (`upper`, `lower`, `camel` or `snake`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|case| case.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Case::Upper => "upper",
+            Case::Lower => "lower",
+            Case::Camel => "camel",
+            Case::Snake => "snake",
+        }
+    }
+
+    /// `s` in this case. Camel and snake case are made of the words of `s`,
+    /// split at separators and camelCase humps.
+    pub fn apply(&self, s: &str) -> String {
+        match self {
+            Case::Upper => s.to_uppercase(),
+            Case::Lower => s.to_lowercase(),
+            Case::Camel => words(s)
+                .iter()
+                .enumerate()
+                .map(|(i, word)| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) if i > 0 => first.to_uppercase().chain(chars).collect(),
+                        _ => word.clone(),
+                    }
+                })
+                .collect(),
+            Case::Snake => words(s).join("_"),
+        }
+    }
+}
+
+/// Which way to convert between a string and its base64 encoding.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Coding {
+    Encode,
+    Decode,
+}
+
+/// Which way to sort the items of an array.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// How a point in time is written down.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum DateFormat {
+    /// An ISO 8601 date-time string, like `2024-01-31T12:00:00.000Z`.
+    Iso,
+    /// Whole seconds since the Unix epoch.
+    EpochSeconds,
+    /// Milliseconds since the Unix epoch.
+    EpochMillis,
+}
+
+/// IR for schema transformers. A program is a flat stream of instructions run
+/// against a stack of (input, output) locations: the top of the stack is the
+/// part of the input being read and the part of the output being written.
+/// Push/Pop pairs nest, so the instruction stream mirrors the shape of the
+/// schemas being converted.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum IR {
+    /// Write the input to the output unchanged.
+    Copy,
+    /// Convert the input from one ground type to another and write it.
+    G2G(Ground, Ground),
+    /// Start building an object at the output.
+    PushObj,
+    /// Start building another part of the object at the output, keeping the
+    /// properties already written there. Merges several separately built
+    /// parts (or a copied object and more properties) into one object.
+    /// Closed by [`IR::PopObj`] like [`IR::PushObj`].
+    Merge,
+    /// Finish the object started by the matching [`IR::PushObj`] or
+    /// [`IR::Merge`].
+    PopObj,
+    /// Run the instructions up to the matching [`IR::PopRest`] once per
+    /// property of the input object which isn't one of the given named ones,
+    /// with focus on that property of both the input and the output.
+    PushRest(Vec<Arc<String>>),
+    /// Close the loop opened by the matching [`IR::PushRest`].
+    PopRest,
+    /// Focus both input and output on property `key`.
+    PushKey(Arc<String>),
+    /// Focus the input on property `.0` and the output on property `.1`,
+    /// renaming the key. Closed by [`IR::PopKey`] like [`IR::PushKey`].
+    Rename(Arc<String>, Arc<String>),
+    /// Focus the output on property `key`, leaving the input where it is, to
+    /// build a nested object out of the input's own properties. Closed by
+    /// [`IR::PopKey`] like [`IR::PushKey`].
+    Nest(Arc<String>),
+    /// Return focus to the location before the matching [`IR::PushKey`],
+    /// [`IR::Rename`] or [`IR::Nest`].
+    PopKey,
+    /// Run the instructions up to the matching [`IR::PopOpt`] only if the
+    /// input is present, leaving the output untouched otherwise. Used for
+    /// optional source properties.
+    PushOpt,
+    /// Close the guard opened by the matching [`IR::PushOpt`].
+    PopOpt,
+    /// Start dispatching on the runtime shape of the input, which matches one
+    /// of the branches of a union. Contains one [`IR::Case`] per branch.
+    PushUnion,
+    /// Run the instructions up to the matching [`IR::PopCase`] if the input
+    /// matches the given branch and no earlier case did.
+    Case(Arc<Schema>),
+    /// Close the case opened by the matching [`IR::Case`].
+    PopCase,
+    /// Finish the dispatch started by the matching [`IR::PushUnion`].
+    PopUnion,
+    /// Start building an array at the output. The instructions up to the
+    /// matching [`IR::PopArr`] run once per item of the input array, with
+    /// focus on that item of the input and the same index of the output.
+    PushArr,
+    /// Finish the array started by the matching [`IR::PushArr`].
+    PopArr,
+    /// Replace the input with its property `key`, leaving the output where it
+    /// is. Lasts until the enclosing scope is popped.
+    Extr(Arc<String>),
+    /// Replace the input with the first of the values at the given paths
+    /// into it which is neither missing nor null, leaving the output where
+    /// it is. Lasts until the enclosing scope is popped.
+    Coalesce(Vec<SourcePath>),
+    /// Write a single-item array to the output and move the output focus to
+    /// that item, leaving the input where it is. Lasts until the enclosing
+    /// scope is popped.
+    Wrap,
+    /// Replace the input array with its first item, leaving the output where
+    /// it is. Lasts until the enclosing scope is popped.
+    First,
+    /// Replace the input array with its items flattened the given number of
+    /// levels, leaving the output where it is: the items of each nested
+    /// array take its place, in order. Lasts until the enclosing scope is
+    /// popped.
+    Flatten(usize),
+    /// Replace the input array with its items from the start index up to
+    /// (but not including) the end one, or to the end of the array if there
+    /// is none, leaving the output where it is. Lasts until the enclosing
+    /// scope is popped.
+    Slice(usize, Option<usize>),
+    /// Replace the input array with its items sorted by the value at the
+    /// given path in each (or by the items themselves, if the path is
+    /// empty), leaving the output where it is. Items with equal values keep
+    /// their order. Lasts until the enclosing scope is popped.
+    Sort(SourcePath, SortOrder),
+    /// Replace the input array with its items, leaving out any equal to an
+    /// earlier one, or if a path is given, any with the same value there as
+    /// an earlier one. Leaves the output where it is, and lasts until the
+    /// enclosing scope is popped.
+    Dedupe(Option<SourcePath>),
+    /// Replace the input with one array of the items of the arrays at each
+    /// of the given paths in it, in order, leaving the output where it is.
+    /// Missing and null arrays are skipped. Lasts until the enclosing scope
+    /// is popped.
+    Gather(Vec<SourcePath>),
+    /// Write the items of the input array, each converted to a string, joined
+    /// with the given separator.
+    Join(String),
+    /// Write the input number multiplied by `factor`, plus `offset`, for
+    /// converting between units.
+    Scale { factor: Real, offset: Real },
+    /// Write the input string base64 encoded, or decoded.
+    Base64(Coding),
+    /// Write the given capture group of the first match of the regular
+    /// expression (in JavaScript syntax) in the input string, where group 0
+    /// is the whole match. Writes nothing if there's no match.
+    RegexExtract(String, usize),
+    /// Write the input string converted to the given case. Keys are
+    /// converted ahead of time instead, with [`IR::Rename`].
+    CaseTransform(Case),
+    /// Write the date the input represents in another format.
+    DateConvert {
+        from_format: DateFormat,
+        to_format: DateFormat,
+    },
+    /// Write the target value the input translates to in the table, for
+    /// converting between enumerations. Inputs missing from the table
+    /// translate to nothing.
+    MapValues(ValueTable),
+    /// Write the values at each of the given paths into the input, converted
+    /// to strings and joined with the separator. Missing and null values are
+    /// left out.
+    Concat(Vec<SourcePath>, String),
+    /// Write a fixed value to the output, regardless of the input. Fills in
+    /// target properties from their `const` or `default` (including the
+    /// discriminators telling the branches of a target union apart) and
+    /// from the mapping's fills.
+    Const(Literal),
+    /// Replace the input with its inversion, leaving the output where it is:
+    /// an object becomes an array of `{"key": ..., "value": ...}` entries, in
+    /// the order of its properties, and an array of such entries becomes an
+    /// object, where later entries win over earlier ones with the same key.
+    /// Lasts until the enclosing scope is popped.
+    Inv,
+    /// Replace the input with its grouping by property `key`, leaving the
+    /// output where it is: an array of objects becomes an object of them
+    /// keyed by their `key` (where later items win over earlier ones with
+    /// the same key), and an object of objects becomes an array of them,
+    /// each with its key written back to its `key` property. Lasts until the
+    /// enclosing scope is popped.
+    GroupBy(Arc<String>),
+    /// Replace the input object of parallel arrays with an array of objects,
+    /// leaving the output where it is: for each pair of an array property
+    /// and an item property, item `i` of the array goes into the item
+    /// property of object `i`. There are as many objects as items in the
+    /// longest array. Lasts until the enclosing scope is popped.
+    Zip(Vec<(Arc<String>, Arc<String>)>),
+    /// The other way around from [`IR::Zip`]: replace the input array of
+    /// objects with an object of parallel arrays, leaving the output where
+    /// it is. For each pair of an array property and an item property, the
+    /// array holds the item property of each object, in order. Lasts until
+    /// the enclosing scope is popped.
+    Unzip(Vec<(Arc<String>, Arc<String>)>),
+    /// Replace the input with the output written so far, for the
+    /// instructions after it to convert further, leaving the output where
+    /// it is. Chains one path onto another (see [`compose()`]). Lasts until
+    /// the enclosing scope is popped.
+    Pipe,
+    /// Run the instructions up to the matching [`IR::PopRec`], which convert
+    /// data of a recursive schema, naming them `label` for the
+    /// [`IR::Call`]s among them to run again on the parts of the input of
+    /// the same schema. Changes to the input made inside don't last past
+    /// its end.
+    Rec(Arc<String>),
+    /// Close the conversion opened by the matching [`IR::Rec`].
+    PopRec,
+    /// Run the instructions of the enclosing [`IR::Rec`] named `label` again
+    /// at the current location, converting a part of the input of the same
+    /// schema as the whole.
+    Call(Arc<String>),
+}
+
+impl IR {
+    /// The instruction closing the scope this one opens, if it opens one.
+    pub fn closer(&self) -> Option<IR> {
+        match self {
+            IR::PushObj | IR::Merge => Some(IR::PopObj),
+            IR::PushRest(_) => Some(IR::PopRest),
+            IR::PushKey(_) | IR::Rename(_, _) | IR::Nest(_) => Some(IR::PopKey),
+            IR::PushOpt => Some(IR::PopOpt),
+            IR::PushUnion => Some(IR::PopUnion),
+            IR::Case(_) => Some(IR::PopCase),
+            IR::PushArr => Some(IR::PopArr),
+            IR::Rec(_) => Some(IR::PopRec),
+            _ => None,
+        }
+    }
+
+    /// Whether this instruction closes a scope opened by an earlier one.
+    pub fn is_closer(&self) -> bool {
+        matches!(
+            self,
+            IR::PopObj
+                | IR::PopRest
+                | IR::PopKey
+                | IR::PopOpt
+                | IR::PopUnion
+                | IR::PopCase
+                | IR::PopArr
+                | IR::PopRec
+        )
+    }
+}
+
+/// Index of the instruction closing the scope opened at `ops[0]`, if it's
+/// closed.
+pub fn scope_end(ops: &[IR]) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, op) in ops.iter().enumerate() {
+        if op.closer().is_some() {
+            depth += 1;
+        } else if op.is_closer() {
+            depth = depth.checked_sub(1)?;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Extend a dot-separated path with `key`.
+pub fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Every rename in `ir`, as (source path, target path) pairs. Paths are
+/// dot-separated keys, with `[]` standing for the items of an array.
+/// Renames which go on to extract from the renamed value are flattens, and
+/// aren't included.
+pub fn renames(ir: &[IR]) -> Vec<(String, String)> {
+    moves(ir)
+        .into_iter()
+        .filter(|(_, _, flat)| !flat)
+        .map(|(from, to, _)| (from, to))
+        .collect()
+}
+
+/// Every flatten in `ir`, i.e. a rename followed by extractions, as pairs
+/// of the nested source path and the target path it's moved to.
+pub fn flattens(ir: &[IR]) -> Vec<(String, String)> {
+    moves(ir)
+        .into_iter()
+        .filter(|(_, _, flat)| *flat)
+        .map(|(from, to, _)| (from, to))
+        .collect()
+}
+
+/// The renames in `ir`, each flagged with whether it's a flatten.
+fn moves(ir: &[IR]) -> Vec<(String, String, bool)> {
+    let join = join_path;
+    let mut frames = vec![(String::new(), String::new())];
+    let mut renames: Vec<(String, String, bool)> = vec![];
+    // The rename the last op was, or extracted for.
+    let mut last = None;
+    for op in ir {
+        let (src, dst) = frames.last().cloned().expect("unbalanced IR");
+        let renamed = last.take();
+        match op {
+            IR::PushKey(key) => frames.push((join(&src, key), join(&dst, key))),
+            IR::Rename(from, to) => {
+                let frame = (join(&src, from), join(&dst, to));
+                last = Some(renames.len());
+                renames.push((frame.0.clone(), frame.1.clone(), false));
+                frames.push(frame);
+            }
+            IR::Nest(key) => frames.push((src, join(&dst, key))),
+            IR::PushArr => frames.push((join(&src, "[]"), join(&dst, "[]"))),
+            IR::PushRest(_) => frames.push((join(&src, "*"), join(&dst, "*"))),
+            IR::Case(_) => frames.push((src, dst)),
+            IR::PopKey | IR::PopArr | IR::PopCase | IR::PopRest => {
+                frames.pop();
+            }
+            IR::Extr(key) => {
+                if let Some(top) = frames.last_mut() {
+                    top.0 = join(&src, key);
+                    if let Some(i) = renamed {
+                        renames[i].0 = top.0.clone();
+                        renames[i].2 = true;
+                        last = Some(i);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    renames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::key;
+
+    #[test]
+    fn test_cases() {
+        assert_eq!(Case::Upper.apply("userId"), "USERID");
+        assert_eq!(Case::Lower.apply("In Progress"), "in progress");
+        assert_eq!(Case::Camel.apply("user_id"), "userId");
+        assert_eq!(Case::Camel.apply("IN-PROGRESS"), "inProgress");
+        assert_eq!(Case::Snake.apply("userId"), "user_id");
+        assert_eq!(Case::Snake.apply("In Progress"), "in_progress");
+        assert_eq!(Case::from_name("Snake"), Some(Case::Snake));
+        assert_eq!(Case::from_name("kebab"), None);
+    }
+
+    #[test]
+    fn test_flattens() {
+        let ir = vec![
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("userId")),
+            IR::Copy,
+            IR::PopKey,
+            IR::Rename(key("address"), key("address_city")),
+            IR::Extr(key("city")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            renames(&ir),
+            vec![("user_id".to_string(), "userId".to_string())]
+        );
+        assert_eq!(
+            flattens(&ir),
+            vec![("address.city".to_string(), "address_city".to_string())]
+        );
+    }
+}
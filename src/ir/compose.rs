@@ -0,0 +1,219 @@
+//! Chaining IR paths: the path converting data the way one path does and
+//! then another, such as a v1 to v2 transform and a v2 to v3 one making a
+//! v1 to v3 transform.
+//!
+//! Where both paths build the same kind of scope, the scopes are fused, so
+//! the chained path reads straight from the first path's input: objects
+//! whose properties the second path reads from the properties the first
+//! writes, and arrays whose items the second path converts further.
+//! Anywhere else, the second path reads the first one's output through an
+//! [`IR::Pipe`].
+
+use std::sync::Arc;
+
+use super::{scope_end, IR};
+
+/// The path doing what `first` does, then what `second` does to its output.
+pub fn compose(first: &[IR], second: &[IR]) -> Vec<IR> {
+    if first == [IR::Copy] {
+        return second.to_vec();
+    }
+    if second == [IR::Copy] {
+        return first.to_vec();
+    }
+    if let Some(fused) = fuse(first, second) {
+        return fused;
+    }
+    let mut ir = first.to_vec();
+    ir.push(IR::Pipe);
+    ir.extend_from_slice(second);
+    ir
+}
+
+/// The instruction opening `ir` and the instructions inside its scope, if
+/// `ir` is a single scope.
+fn scope(ir: &[IR]) -> Option<(&IR, &[IR])> {
+    let op = ir.first()?;
+    op.closer()?;
+    (scope_end(ir)? == ir.len() - 1).then(|| (op, &ir[1..ir.len() - 1]))
+}
+
+/// The scopes making up `ir`, if it's made up of nothing else.
+fn scopes(mut ir: &[IR]) -> Option<Vec<(&IR, &[IR])>> {
+    let mut scopes = vec![];
+    while !ir.is_empty() {
+        let end = scope_end(ir)?;
+        scopes.push(scope(&ir[..=end])?);
+        ir = &ir[end + 1..];
+    }
+    Some(scopes)
+}
+
+/// `first` and `second` fused into one scope, if they're both objects or
+/// both arrays and the second reads each property it reads from a single
+/// property written by the first.
+fn fuse(first: &[IR], second: &[IR]) -> Option<Vec<IR>> {
+    let (first, second) = (scope(first)?, scope(second)?);
+    match (first, second) {
+        ((IR::PushArr, a), (IR::PushArr, b)) => {
+            let mut ir = vec![IR::PushArr];
+            ir.extend(compose(a, b));
+            ir.push(IR::PopArr);
+            Some(ir)
+        }
+        ((IR::PushObj, a), (IR::PushObj, b)) => {
+            let written = scopes(a)?;
+            if written.iter().any(|(op, _)| writes(op).is_none()) {
+                return None;
+            }
+            let mut ir = vec![IR::PushObj];
+            for (op, b) in scopes(b)? {
+                let (from, to) = match op {
+                    IR::PushKey(key) => (key, key),
+                    IR::Rename(from, to) => (from, to),
+                    _ => return None,
+                };
+                let mut writers = written.iter().filter(|(op, _)| writes(op) == Some(from));
+                let (op, a) = writers.next()?;
+                if writers.next().is_some() {
+                    return None;
+                }
+                ir.push(match op {
+                    IR::Nest(_) => IR::Nest(to.clone()),
+                    IR::PushKey(key) | IR::Rename(key, _) if key == to => IR::PushKey(key.clone()),
+                    IR::PushKey(key) | IR::Rename(key, _) => IR::Rename(key.clone(), to.clone()),
+                    _ => unreachable!("only key scopes write properties"),
+                });
+                ir.extend(compose(a, b));
+                ir.push(IR::PopKey);
+            }
+            ir.push(IR::PopObj);
+            Some(ir)
+        }
+        _ => None,
+    }
+}
+
+/// The property the scope `op` opens writes to, if it writes to one.
+fn writes(op: &IR) -> Option<&Arc<String>> {
+    match op {
+        IR::PushKey(key) | IR::Rename(_, key) | IR::Nest(key) => Some(key),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::{ir::eval, schema::Ground, test_util::key};
+
+    use super::*;
+
+    /// Check that `compose(first, second)` converts `input` the way the two
+    /// paths do in turn, returning it.
+    fn chained(first: &[IR], second: &[IR], input: Value) -> Vec<IR> {
+        let ir = compose(first, second);
+        assert_eq!(eval(&ir, &input), eval(second, &eval(first, &input)));
+        ir
+    }
+
+    #[test]
+    fn test_identity() {
+        let ir = vec![IR::G2G(Ground::Num, Ground::String)];
+        assert_eq!(compose(&[IR::Copy], &ir), ir);
+        assert_eq!(compose(&ir, &[IR::Copy]), ir);
+    }
+
+    #[test]
+    fn test_fused_objects() {
+        let first = vec![
+            IR::PushObj,
+            IR::Rename(key("n"), key("count")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("tags")),
+            IR::PushArr,
+            IR::Copy,
+            IR::PopArr,
+            IR::PopKey,
+            IR::Nest(key("meta")),
+            IR::PushObj,
+            IR::PushKey(key("id")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let second = vec![
+            IR::PushObj,
+            IR::Rename(key("count"), key("n")),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopKey,
+            IR::PushKey(key("tags")),
+            IR::PushArr,
+            IR::CaseTransform(crate::ir::Case::Upper),
+            IR::PopArr,
+            IR::PopKey,
+            IR::Rename(key("meta"), key("info")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let ir = chained(
+            &first,
+            &second,
+            json!({"n": 2, "tags": ["a", "b"], "id": 7}),
+        );
+        assert_eq!(
+            ir,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("n")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::Pipe,
+                IR::G2G(Ground::String, Ground::Num),
+                IR::PopKey,
+                IR::PushKey(key("tags")),
+                IR::PushArr,
+                IR::CaseTransform(crate::ir::Case::Upper),
+                IR::PopArr,
+                IR::PopKey,
+                IR::Nest(key("info")),
+                IR::PushObj,
+                IR::PushKey(key("id")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj,
+                IR::PopKey,
+                IR::PopObj,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_piped() {
+        let first = vec![
+            IR::PushObj,
+            IR::PushKey(key("a")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let second = vec![IR::Inv, IR::Copy];
+        let ir = chained(&first, &second, json!({"a": 1, "b": 2}));
+        assert_eq!(ir[first.len()], IR::Pipe);
+
+        // the second path reads a property the first doesn't write
+        let second = vec![
+            IR::PushObj,
+            IR::PushKey(key("b")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let ir = chained(&first, &second, json!({"a": 1, "b": 2}));
+        assert!(ir.contains(&IR::Pipe));
+    }
+}
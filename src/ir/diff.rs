@@ -0,0 +1,291 @@
+//! Differences between two IR paths, such as a transform and the one found
+//! again after its schemas changed, for reviewing at the level of the IR
+//! rather than the code generated from it.
+//!
+//! Paths are compared as trees of scopes, so an instruction changing deep
+//! inside an object shows up under the scopes leading to it:
+//!
+//! ```text
+//!   PushObj
+//!     PushKey "id"
+//!       ...
+//! -   PushKey "n"
+//! +   Rename ["n","count"]
+//!       G2G ["Num","String"]
+//! +   PushKey "tags"
+//! +     Copy
+//! ```
+
+use std::mem::discriminant;
+
+use super::{
+    scope_end,
+    text::{line, INDENT},
+    IR,
+};
+
+/// An instruction, along with the ones inside the scope it opens.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub op: IR,
+    pub body: Vec<Node>,
+}
+
+/// `ir` as a tree of scopes, leaving out the instructions closing them.
+/// Instructions opening scopes that aren't closed stand on their own.
+pub fn tree(ir: &[IR]) -> Vec<Node> {
+    let mut nodes = vec![];
+    let mut i = 0;
+    while i < ir.len() {
+        let op = &ir[i];
+        let end = op.closer().and(scope_end(&ir[i..]));
+        nodes.push(Node {
+            op: op.clone(),
+            body: end.map_or(vec![], |end| tree(&ir[i + 1..i + end])),
+        });
+        i += end.unwrap_or(0) + 1;
+    }
+    nodes
+}
+
+/// One step turning the old path into the new one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    /// An instruction and its scope left as they were.
+    Kept(Node),
+    Added(Node),
+    Removed(Node),
+    /// An instruction replaced by one of the same kind, or kept with its
+    /// scope changed, along with the changes inside its scope.
+    Changed {
+        old: IR,
+        new: IR,
+        body: Vec<Edit>,
+    },
+}
+
+/// The edits turning the `old` path into the `new` one.
+pub fn diff(old: &[IR], new: &[IR]) -> Vec<Edit> {
+    nodes(&tree(old), &tree(new))
+}
+
+/// Whether `edits` change nothing.
+pub fn unchanged(edits: &[Edit]) -> bool {
+    edits.iter().all(|edit| matches!(edit, Edit::Kept(_)))
+}
+
+/// The edits turning the `old` instructions of a scope into the `new` ones.
+fn nodes(old: &[Node], new: &[Node]) -> Vec<Edit> {
+    // longest common subsequence of whole subtrees
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut edits = vec![];
+    let (mut i, mut j) = (0, 0);
+    let (mut removed, mut added) = (vec![], vec![]);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            pair(&removed, &added, &mut edits);
+            removed.clear();
+            added.clear();
+            edits.push(Edit::Kept(old[i].clone()));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            added.push(&new[j]);
+            j += 1;
+        } else {
+            removed.push(&old[i]);
+            i += 1;
+        }
+    }
+    pair(&removed, &added, &mut edits);
+    edits
+}
+
+/// Edits for a run of `removed` instructions replaced by `added` ones,
+/// pairing each removed instruction with an added one after the last pair
+/// of the same kind, or opening the same kind of scope (like a `PushKey`
+/// turned into a `Rename`). Those that are the same come first, then those
+/// with the same scope.
+fn pair(removed: &[&Node], added: &[&Node], edits: &mut Vec<Edit>) {
+    let mut next = 0;
+    for old in removed {
+        let rest = &added[next..];
+        let likeness = |new: &Node| {
+            let kind = discriminant(&new.op) == discriminant(&old.op);
+            let scope = new.op.closer().is_some() && new.op.closer() == old.op.closer();
+            (kind || scope).then_some((new.op == old.op, new.body == old.body, kind))
+        };
+        let mut found = None;
+        for (k, new) in rest.iter().enumerate() {
+            let like = likeness(new);
+            if like.is_some() && found.is_none_or(|(_, best)| like > best) {
+                found = Some((k, like));
+            }
+        }
+        let Some((k, _)) = found else {
+            edits.push(Edit::Removed((*old).clone()));
+            continue;
+        };
+        edits.extend(rest[..k].iter().map(|new| Edit::Added((*new).clone())));
+        let new = rest[k];
+        edits.push(Edit::Changed {
+            old: old.op.clone(),
+            new: new.op.clone(),
+            body: nodes(&old.body, &new.body),
+        });
+        next += k + 1;
+    }
+    edits.extend(added[next..].iter().map(|new| Edit::Added((*new).clone())));
+}
+
+/// `edits` as text, one instruction per line in the [text
+/// format](super::text), marked `+` if it was added and `-` if it was
+/// removed. The scopes of kept instructions are elided to `...`.
+pub fn render(edits: &[Edit]) -> String {
+    let mut out = String::new();
+    render_at(edits, 0, &mut out);
+    out
+}
+
+fn render_at(edits: &[Edit], depth: usize, out: &mut String) {
+    for edit in edits {
+        match edit {
+            Edit::Kept(node) => {
+                write(out, ' ', depth, &line(&node.op));
+                if !node.body.is_empty() {
+                    write(out, ' ', depth + 1, "...");
+                }
+            }
+            Edit::Added(node) => whole(node, '+', depth, out),
+            Edit::Removed(node) => whole(node, '-', depth, out),
+            Edit::Changed { old, new, body } => {
+                if old == new {
+                    write(out, ' ', depth, &line(old));
+                } else {
+                    write(out, '-', depth, &line(old));
+                    write(out, '+', depth, &line(new));
+                }
+                render_at(body, depth + 1, out);
+            }
+        }
+    }
+}
+
+/// Every line of `node` and its scope, marked `mark`.
+fn whole(node: &Node, mark: char, depth: usize, out: &mut String) {
+    write(out, mark, depth, &line(&node.op));
+    for inner in &node.body {
+        whole(inner, mark, depth + 1, out);
+    }
+}
+
+fn write(out: &mut String, mark: char, depth: usize, text: &str) {
+    out.push_str(&format!(
+        "{} {}{}\n",
+        mark,
+        " ".repeat(depth * INDENT),
+        text
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{schema::Ground, test_util::key};
+
+    use super::*;
+
+    #[test]
+    fn test_unchanged() {
+        let ir = vec![
+            IR::PushObj,
+            IR::PushKey(key("a")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let edits = diff(&ir, &ir);
+        assert!(unchanged(&edits));
+        assert_eq!(render(&edits), "  PushObj\n    ...\n");
+    }
+
+    #[test]
+    fn test_nested_changes() {
+        let old = vec![
+            IR::PushObj,
+            IR::PushKey(key("id")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PushKey(key("n")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let new = vec![
+            IR::PushObj,
+            IR::PushKey(key("id")),
+            IR::Copy,
+            IR::PopKey,
+            IR::Rename(key("n"), key("count")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("tags")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let edits = diff(&old, &new);
+        assert!(!unchanged(&edits));
+        assert_eq!(
+            render(&edits),
+            [
+                "  PushObj",
+                "    PushKey \"id\"",
+                "      ...",
+                "-   PushKey \"n\"",
+                "+   Rename [\"n\",\"count\"]",
+                "      G2G [\"Num\",\"String\"]",
+                "+   PushKey \"tags\"",
+                "+     Copy",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_pairing() {
+        let old = vec![IR::G2G(Ground::Num, Ground::String), IR::First, IR::Copy];
+        let new = vec![IR::G2G(Ground::Num, Ground::Bool), IR::Copy, IR::Inv];
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Edit::Changed {
+                    old: IR::G2G(Ground::Num, Ground::String),
+                    new: IR::G2G(Ground::Num, Ground::Bool),
+                    body: vec![],
+                },
+                Edit::Removed(Node {
+                    op: IR::First,
+                    body: vec![]
+                }),
+                Edit::Kept(Node {
+                    op: IR::Copy,
+                    body: vec![]
+                }),
+                Edit::Added(Node {
+                    op: IR::Inv,
+                    body: vec![]
+                }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,599 @@
+//! An interpreter for IR paths, transforming JSON data in process the way
+//! the generated JavaScript would.
+//!
+//! Where the generated code would throw (reading a property of `null`, say,
+//! or looping over something which isn't an array), the interpreter carries
+//! on instead, treating what it couldn't read as `undefined` and leaving out
+//! what it couldn't write. `undefined` properties are left out of the
+//! result, and `undefined` items are written as `null`, the way
+//! `JSON.stringify` writes them.
+
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+
+use crate::{
+    runtime::{self, get, items, number, spread, to_iso, to_number, to_string, truthy},
+    schema::{Ground, Schema},
+};
+
+use super::{scope_end, Case, Coding, DateFormat, SortOrder, SourcePath, IR};
+
+/// A step from a value into one of its parts.
+#[derive(Clone, Debug)]
+enum Seg {
+    Key(String),
+    Index(usize),
+}
+
+/// The locations the instructions are reading from and writing to.
+#[derive(Clone, Debug)]
+struct Frame {
+    /// The input, or `None` for `undefined`.
+    input: Option<Value>,
+    /// Where in the output to write.
+    output: Vec<Seg>,
+}
+
+/// Run `ir` over `input`, returning what it writes.
+pub fn eval(ir: &[IR], input: &Value) -> Value {
+    let mut machine = Machine::default();
+    let frame = Frame {
+        input: Some(input.clone()),
+        output: vec![],
+    };
+    machine.run(ir, frame);
+    machine.output.unwrap_or(Value::Null)
+}
+
+#[derive(Default)]
+struct Machine {
+    output: Option<Value>,
+    /// Whether each open union dispatch has run a case yet.
+    unions: Vec<bool>,
+    /// The label and instructions of each recursive conversion open,
+    /// innermost last.
+    recs: Vec<(Arc<String>, Vec<IR>)>,
+}
+
+/// The keys of `path`, the way the runtime takes them.
+fn keys(path: &[Arc<String>]) -> Vec<&str> {
+    path.iter().map(|key| key.as_str()).collect()
+}
+
+/// The keys of each of `paths`.
+fn all_keys(paths: &[SourcePath]) -> Vec<Vec<&str>> {
+    paths.iter().map(|path| keys(path)).collect()
+}
+
+/// `(from, to)` pairs of keys, the way the runtime takes them.
+fn pairs(pairs: &[(Arc<String>, Arc<String>)]) -> Vec<(&str, &str)> {
+    pairs
+        .iter()
+        .map(|(from, to)| (from.as_str(), to.as_str()))
+        .collect()
+}
+
+/// Whether `value` has the shape of `schema`, telling objects apart by
+/// their required properties like the generated code does.
+fn test(schema: &Schema, value: Option<&Value>) -> bool {
+    match (schema, value) {
+        (Schema::True | Schema::Var(_), _) => true,
+        (Schema::Rec(_, body) | Schema::Annotated(body, _), value) => test(body, value),
+        (Schema::Union(branches), value) => branches.iter().any(|branch| test(branch, value)),
+        (Schema::Ground(ground), Some(value)) => match ground {
+            Ground::Num => value.is_number(),
+            Ground::Bool => value.is_boolean(),
+            Ground::String => value.is_string(),
+            Ground::Null => value.is_null(),
+        },
+        (Schema::Arr(_), Some(value)) => value.is_array(),
+        (Schema::Obj { required, .. }, Some(Value::Object(map))) => {
+            required.iter().all(|key| map.contains_key(key.as_str()))
+        }
+        _ => false,
+    }
+}
+
+impl Machine {
+    /// Run the instructions of one scope, returning the frame they leave.
+    fn run(&mut self, ops: &[IR], mut frame: Frame) -> Frame {
+        let mut i = 0;
+        while i < ops.len() {
+            let op = &ops[i];
+            if op.closer().is_some() {
+                let end = scope_end(&ops[i..]).unwrap_or(ops.len() - i);
+                let body = &ops[i + 1..(i + end).min(ops.len())];
+                frame = self.scope(op, body, frame);
+                i += end + 1;
+            } else {
+                // instructions closing scopes which weren't opened do nothing
+                if !op.is_closer() {
+                    self.step(op, &mut frame);
+                }
+                i += 1;
+            }
+        }
+        frame
+    }
+
+    /// Write `value` at `path` into the output, if there's somewhere to
+    /// write it.
+    fn write(&mut self, path: &[Seg], value: Option<Value>) {
+        let Some((last, parents)) = path.split_last() else {
+            self.output = value;
+            return;
+        };
+        let mut slot = self.output.as_mut();
+        for seg in parents {
+            slot = match (slot, seg) {
+                (Some(Value::Object(map)), Seg::Key(key)) => map.get_mut(key),
+                (Some(Value::Object(map)), Seg::Index(i)) => map.get_mut(&i.to_string()),
+                (Some(Value::Array(items)), Seg::Index(i)) => items.get_mut(*i),
+                _ => return,
+            };
+        }
+        match (slot, last) {
+            (Some(Value::Object(map)), Seg::Key(key)) => match value {
+                Some(value) => {
+                    map.insert(key.clone(), value);
+                }
+                None => {
+                    map.remove(key);
+                }
+            },
+            (Some(Value::Array(items)), Seg::Index(i)) => {
+                let value = value.unwrap_or(Value::Null);
+                match items.get_mut(*i) {
+                    Some(item) => *item = value,
+                    None => {
+                        items.resize(*i, Value::Null);
+                        items.push(value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The output written at `path` so far.
+    fn written(&self, path: &[Seg]) -> Option<&Value> {
+        path.iter()
+            .try_fold(self.output.as_ref()?, |value, seg| match (value, seg) {
+                (Value::Object(map), Seg::Key(key)) => map.get(key),
+                (Value::Array(items), Seg::Index(i)) => items.get(*i),
+                _ => None,
+            })
+    }
+
+    /// Run the scope `op` opens, whose instructions are `body`, returning
+    /// the frame left after it.
+    fn scope(&mut self, op: &IR, body: &[IR], frame: Frame) -> Frame {
+        let enter = |from: Option<&str>, to: &str| {
+            let mut output = frame.output.clone();
+            output.push(Seg::Key(to.to_string()));
+            Frame {
+                input: match from {
+                    Some(from) => get(frame.input.as_ref(), from).cloned(),
+                    None => frame.input.clone(),
+                },
+                output,
+            }
+        };
+        match op {
+            IR::PushObj => {
+                self.write(&frame.output, Some(Value::Object(Map::new())));
+                self.run(body, frame)
+            }
+            IR::Merge => {
+                // copied rather than added to, like the generated code does
+                let merged = spread(self.written(&frame.output));
+                self.write(&frame.output, Some(Value::Object(merged)));
+                self.run(body, frame)
+            }
+            IR::PushKey(key) => {
+                self.run(body, enter(Some(key), key));
+                frame
+            }
+            IR::Rename(from, to) => {
+                self.run(body, enter(Some(from), to));
+                frame
+            }
+            IR::Nest(key) => {
+                self.run(body, enter(None, key));
+                frame
+            }
+            IR::Rec(label) => {
+                self.recs.push((label.clone(), body.to_vec()));
+                self.run(body, frame.clone());
+                self.recs.pop();
+                frame
+            }
+            IR::PushOpt if frame.input.is_some() => self.run(body, frame),
+            IR::PushOpt => frame,
+            IR::PushUnion => {
+                self.unions.push(false);
+                let frame = self.run(body, frame);
+                self.unions.pop();
+                frame
+            }
+            IR::Case(branch) => {
+                let started = self.unions.last().copied().unwrap_or(false);
+                if !started && test(branch, frame.input.as_ref()) {
+                    if let Some(started) = self.unions.last_mut() {
+                        *started = true;
+                    }
+                    self.run(body, frame.clone());
+                }
+                frame
+            }
+            IR::PushArr => {
+                self.write(&frame.output, Some(Value::Array(vec![])));
+                for (i, item) in items(frame.input.as_ref()).iter().enumerate() {
+                    let mut output = frame.output.clone();
+                    output.push(Seg::Index(i));
+                    let item = Frame {
+                        input: Some(item.clone()),
+                        output,
+                    };
+                    self.run(body, item);
+                }
+                frame
+            }
+            IR::PushRest(named) => {
+                if let Some(Value::Object(map)) = &frame.input {
+                    for (key, value) in map {
+                        if named.iter().any(|named| named.as_str() == key) {
+                            continue;
+                        }
+                        let mut output = frame.output.clone();
+                        output.push(Seg::Key(key.clone()));
+                        let rest = Frame {
+                            input: Some(value.clone()),
+                            output,
+                        };
+                        self.run(body, rest);
+                    }
+                }
+                frame
+            }
+            _ => unreachable!("only openers open scopes"),
+        }
+    }
+
+    /// Run an instruction which doesn't open or close a scope.
+    fn step(&mut self, op: &IR, frame: &mut Frame) {
+        let input = frame.input.as_ref();
+        let output = match op {
+            IR::Copy => input.cloned(),
+            IR::G2G(_, to) => Some(match to {
+                Ground::String => Value::from(to_string(input)),
+                Ground::Num => number(to_number(input)),
+                Ground::Bool => Value::from(truthy(input)),
+                Ground::Null => Value::Null,
+            }),
+            IR::Wrap => {
+                self.write(&frame.output, Some(Value::Array(vec![])));
+                frame.output.push(Seg::Index(0));
+                return;
+            }
+            IR::Pipe => {
+                frame.input = self.written(&frame.output).cloned();
+                return;
+            }
+            IR::Call(label) => {
+                let rec = self.recs.iter().rev().find(|(open, _)| open == label);
+                if let Some((_, body)) = rec {
+                    let body = body.clone();
+                    self.run(&body, frame.clone());
+                }
+                return;
+            }
+            IR::Scale { factor, offset } => Some(number(to_number(input) * factor.0 + offset.0)),
+            IR::DateConvert {
+                from_format,
+                to_format,
+            } => {
+                let time = match from_format {
+                    DateFormat::EpochSeconds => runtime::time(to_number(input) * 1000.0),
+                    _ => runtime::date(input),
+                };
+                match to_format {
+                    DateFormat::Iso => to_iso(time).map(Value::from),
+                    DateFormat::EpochSeconds => Some(number((time / 1000.0).floor())),
+                    DateFormat::EpochMillis => Some(number(time)),
+                }
+            }
+            IR::Base64(Coding::Encode) => {
+                Some(Value::from(runtime::base64_encode(&to_string(input))))
+            }
+            IR::Base64(Coding::Decode) => {
+                runtime::base64_decode(&to_string(input)).map(Value::from)
+            }
+            IR::RegexExtract(pattern, group) => runtime::extract(input, pattern, *group),
+            IR::CaseTransform(case) => {
+                let s = to_string(input);
+                Some(Value::from(match case {
+                    Case::Upper => s.to_uppercase(),
+                    Case::Lower => s.to_lowercase(),
+                    Case::Camel => runtime::camel_case(&s),
+                    Case::Snake => runtime::snake_case(&s),
+                }))
+            }
+            IR::MapValues(table) => {
+                let table: Vec<_> = table
+                    .iter()
+                    .map(|(from, to)| (from.0.clone(), to.0.clone()))
+                    .collect();
+                runtime::map_values(input, &table)
+            }
+            IR::Concat(paths, sep) => {
+                let paths = all_keys(paths);
+                let paths: Vec<_> = paths.iter().map(Vec::as_slice).collect();
+                Some(runtime::concat(input, &paths, sep))
+            }
+            IR::Const(value) => Some(value.0.clone()),
+            IR::Join(sep) => match runtime::join(input, sep) {
+                Some(joined) => Some(joined),
+                None => return,
+            },
+            _ => {
+                frame.input = refocus(op, input);
+                return;
+            }
+        };
+        self.write(&frame.output, output);
+    }
+}
+
+/// The input after `op`, which only replaces it.
+fn refocus(op: &IR, input: Option<&Value>) -> Option<Value> {
+    match op {
+        IR::Extr(key) => get(input, key).cloned(),
+        IR::Coalesce(paths) => {
+            let paths = all_keys(paths);
+            let paths: Vec<_> = paths.iter().map(Vec::as_slice).collect();
+            runtime::coalesce(input, &paths).cloned()
+        }
+        IR::First => items(input).first().cloned(),
+        IR::Flatten(depth) => runtime::flatten(input, *depth),
+        IR::Slice(start, end) => runtime::slice(input, *start, *end),
+        IR::Sort(path, order) => runtime::sort(input, &keys(path), *order == SortOrder::Descending),
+        IR::Dedupe(path) => runtime::dedupe(input, path.as_deref().map(keys).as_deref()),
+        IR::Gather(paths) => {
+            let paths = all_keys(paths);
+            let paths: Vec<_> = paths.iter().map(Vec::as_slice).collect();
+            runtime::gather(input, &paths)
+        }
+        IR::Zip(zipped) => runtime::zip(input, &pairs(zipped)),
+        IR::Unzip(unzipped) => runtime::unzip(input, &pairs(unzipped)),
+        IR::Inv => runtime::invert(input),
+        IR::GroupBy(key) => runtime::group_by(input, key),
+        _ => unreachable!("only instructions which replace the input are left"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use crate::{
+        schema,
+        schema::{Literal, Real},
+        searcher::SchemaSearcher,
+        test_util::key,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_objects() {
+        let ir = vec![
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("age")),
+            IR::PushOpt,
+            IR::Scale {
+                factor: Real(12.0),
+                offset: Real(0.0),
+            },
+            IR::PopOpt,
+            IR::PopKey,
+            IR::Nest(key("meta")),
+            IR::PushObj,
+            IR::PushKey(key("kind")),
+            IR::Const(Literal(json!("user"))),
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            eval(&ir, &json!({"user_id": 7, "age": 2})),
+            json!({"id": "7", "age": 24, "meta": {"kind": "user"}})
+        );
+        // the missing age is left out rather than converted
+        assert_eq!(
+            eval(&ir, &json!({"user_id": 7})),
+            json!({"id": "7", "meta": {"kind": "user"}})
+        );
+    }
+
+    #[test]
+    fn test_arrays_and_unions() {
+        let ir = vec![
+            IR::PushArr,
+            IR::PushUnion,
+            IR::Case(Arc::new(Schema::string())),
+            IR::Wrap,
+            IR::Copy,
+            IR::PopCase,
+            IR::Case(Arc::new(Schema::Arr(Arc::new(Schema::string())))),
+            IR::Copy,
+            IR::PopCase,
+            IR::PopUnion,
+            IR::PopArr,
+        ];
+        // items matching no case are holes, written as null
+        assert_eq!(
+            eval(&ir, &json!([3, "a", ["b", "c"]])),
+            json!([null, ["a"], ["b", "c"]])
+        );
+        let ir = vec![
+            IR::PushObj,
+            IR::PushRest(vec![key("id")]),
+            IR::G2G(Ground::String, Ground::Num),
+            IR::PopRest,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            eval(&ir, &json!({"id": "x", "a": "1", "b": "2.5"})),
+            json!({"a": 1, "b": 2.5})
+        );
+    }
+
+    #[test]
+    fn test_arranging_arrays() {
+        let items = json!({
+            "a": [{"n": 3, "k": "x"}, {"n": 1, "k": "y"}, {"n": 2, "k": "x"}],
+            "b": [{"n": 0, "k": "z"}]
+        });
+        let run = |ops: Vec<IR>| eval(&[ops, vec![IR::Copy]].concat(), &items);
+        let gather = IR::Gather(vec![vec![key("a")], vec![key("b")]]);
+        assert_eq!(
+            run(vec![
+                gather.clone(),
+                IR::Sort(vec![key("n")], SortOrder::Descending),
+                IR::Slice(1, Some(3)),
+            ]),
+            json!([{"n": 2, "k": "x"}, {"n": 1, "k": "y"}])
+        );
+        assert_eq!(
+            run(vec![
+                gather,
+                IR::Dedupe(Some(vec![key("k")])),
+                IR::Unzip(vec![(key("ns"), key("n"))])
+            ]),
+            json!({"ns": [3, 1, 0]})
+        );
+        let zip = IR::Zip(vec![(key("xs"), key("x")), (key("ys"), key("y"))]);
+        assert_eq!(
+            eval(&[zip, IR::Copy], &json!({"xs": [1, 2], "ys": ["a"]})),
+            json!([{"x": 1, "y": "a"}, {"x": 2}])
+        );
+        assert_eq!(
+            eval(&[IR::Inv, IR::Copy], &json!({"a": 1})),
+            json!([{"key": "a", "value": 1}])
+        );
+        assert_eq!(
+            eval(
+                &[IR::GroupBy(key("id")), IR::Copy],
+                &json!([{"id": 1}, {"id": 2}])
+            ),
+            json!({"1": {"id": 1}, "2": {"id": 2}})
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        let run = |op: IR, input: Value| eval(&[op], &input);
+        assert_eq!(
+            run(
+                IR::RegexExtract("@(.+)$".to_string(), 1),
+                json!("me@example.com")
+            ),
+            json!("example.com")
+        );
+        assert_eq!(
+            run(
+                IR::DateConvert {
+                    from_format: DateFormat::EpochSeconds,
+                    to_format: DateFormat::Iso
+                },
+                json!(86400)
+            ),
+            json!("1970-01-02T00:00:00.000Z")
+        );
+        assert_eq!(run(IR::Base64(Coding::Encode), json!("hi!")), json!("aGkh"));
+        assert_eq!(
+            run(IR::CaseTransform(Case::Snake), json!("userId")),
+            json!("user_id")
+        );
+        assert_eq!(
+            run(IR::Join(", ".to_string()), json!([1, null])),
+            json!("1, null")
+        );
+        assert_eq!(
+            run(
+                IR::Concat(
+                    vec![vec![key("a")], vec![key("b")], vec![key("c")]],
+                    " ".to_string()
+                ),
+                json!({"a": "x", "b": null, "c": 2})
+            ),
+            json!("x 2")
+        );
+        let table = [(json!(1), json!("one")), (json!(2), json!("two"))]
+            .into_iter()
+            .map(|(from, to)| (Literal(from), Literal(to)))
+            .collect();
+        assert_eq!(run(IR::MapValues(table), json!(2.0)), json!("two"));
+    }
+
+    #[test]
+    fn test_found_paths() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "user_id": {"type": "number"},
+                "tags": {"type": "array", "items": {"type": "boolean"}}
+            },
+            "required": ["user_id", "tags"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "userId": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["userId", "tags"]
+        });
+        let ir = SchemaSearcher::new().find_path(&s1, &s2).unwrap();
+        assert_eq!(
+            eval(&ir, &json!({"user_id": 42, "tags": [true, false]})),
+            json!({"userId": "42", "tags": ["true", "false"]})
+        );
+    }
+
+    #[test]
+    fn test_recursion() {
+        let tree = |id: &str| {
+            schema!({
+                "type": "object",
+                "properties": {
+                    "id": {"type": id},
+                    "children": {"type": "array", "items": {"$ref": "#"}}
+                },
+                "required": ["id", "children"]
+            })
+        };
+        let ir = SchemaSearcher::new()
+            .find_path(&tree("number"), &tree("string"))
+            .unwrap();
+        let input = json!({"id": 1, "children": [
+            {"id": 2, "children": [{"id": 3, "children": []}]},
+            {"id": 4, "children": []}
+        ]});
+        assert_eq!(
+            eval(&ir, &input),
+            json!({"id": "1", "children": [
+                {"id": "2", "children": [{"id": "3", "children": []}]},
+                {"id": "4", "children": []}
+            ]})
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! A peephole pass cleaning up the scaffolding the searcher leaves in IR
+//! paths, without changing what they do.
+//!
+//! - Objects built by copying the input whole are copied instead:
+//!   `PushObj; Copy; PopObj` becomes `Copy`, and so does an object copying
+//!   each property of the input under the same key, if the input rules
+//!   out any others with `additionalProperties: false` (or the rest are
+//!   copied too, by a [`IR::PushRest`] loop). Leaving `additionalProperties`
+//!   out allows undeclared properties, which copying the whole object would
+//!   carry along.
+//! - Scopes which write nothing are removed, such as a `PushKey` whose
+//!   instructions were all removed, along with instructions changing the
+//!   input at the end of a scope, which nothing reads.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use crate::schema::Schema;
+
+use super::{scope_end, IR};
+
+/// `ir`, which converts data of the `source` schema, with its redundant
+/// instructions removed.
+pub fn optimize(ir: &[IR], source: &Schema) -> Vec<IR> {
+    block(ir, Some(source))
+}
+
+/// Whether `op` only replaces the input, leaving the output where it is
+/// and writing nothing.
+fn refocuses(op: &IR) -> bool {
+    matches!(
+        op,
+        IR::Extr(_)
+            | IR::Coalesce(_)
+            | IR::First
+            | IR::Flatten(_)
+            | IR::Slice(_, _)
+            | IR::Sort(_, _)
+            | IR::Dedupe(_)
+            | IR::Gather(_)
+            | IR::Inv
+            | IR::GroupBy(_)
+            | IR::Zip(_)
+            | IR::Unzip(_)
+            | IR::Pipe
+    )
+}
+
+/// Schema of property `key` of `input`, if it's known.
+fn prop<'a>(input: Option<&'a Schema>, key: &Arc<String>) -> Option<&'a Schema> {
+    match input? {
+        Schema::Obj { props, .. } => props.get(key).map(|schema| schema.as_ref()),
+        _ => None,
+    }
+}
+
+/// The instructions of one scope, optimized, where `input` is the schema
+/// of the input at its start, if it's known.
+fn block(ops: &[IR], mut input: Option<&Schema>) -> Vec<IR> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < ops.len() {
+        // annotations don't change the shape of the input
+        input = input.map(Schema::bare);
+        let op = &ops[i];
+        let Some(end) = op.closer().and(scope_end(&ops[i..])) else {
+            input = match op {
+                IR::Extr(key) => prop(input, key),
+                op if refocuses(op) => None,
+                _ => input,
+            };
+            out.push(op.clone());
+            i += 1;
+            continue;
+        };
+        let unfolded = match op {
+            IR::Rec(_) => input.map(Schema::unfold),
+            _ => None,
+        };
+        let inner = match op {
+            IR::Rec(_) => unfolded.as_ref(),
+            IR::PushKey(key) | IR::Rename(key, _) => prop(input, key),
+            IR::Case(branch) => Some(branch.as_ref()),
+            IR::PushArr => match input {
+                Some(Schema::Arr(item)) => Some(item.as_ref()),
+                _ => None,
+            },
+            IR::PushRest(_) => match input {
+                Some(Schema::Obj { additional, .. }) => additional.as_deref(),
+                _ => None,
+            },
+            _ => input,
+        };
+        let body = block(&ops[i + 1..i + end], inner);
+        i += end + 1;
+        match op {
+            IR::PushKey(_)
+            | IR::Rename(_, _)
+            | IR::Nest(_)
+            | IR::PushOpt
+            | IR::PushRest(_)
+            | IR::PushUnion
+            | IR::Rec(_)
+                if body.is_empty() => {}
+            IR::PushObj if body == [IR::Copy] || copies_whole(&body, input) => out.push(IR::Copy),
+            _ => {
+                out.push(op.clone());
+                out.extend(body);
+                out.extend(op.closer());
+            }
+        }
+    }
+    // nothing after them reads the input they replace
+    while out.last().is_some_and(refocuses) {
+        out.pop();
+    }
+    out
+}
+
+/// Whether the instructions building an object copy every property `input`
+/// can have under the same key, and nothing else. Inputs which don't rule
+/// out undeclared properties may have more.
+fn copies_whole(body: &[IR], input: Option<&Schema>) -> bool {
+    let mut copied = BTreeSet::new();
+    let mut rest = None;
+    for group in body.chunks(3) {
+        match group {
+            [IR::PushKey(key), IR::Copy, IR::PopKey] => {
+                copied.insert(key.clone());
+            }
+            [IR::PushRest(named), IR::Copy, IR::PopRest] if rest.is_none() => {
+                rest = Some(named.iter().cloned().collect::<BTreeSet<_>>());
+            }
+            _ => return false,
+        }
+    }
+    match (rest, input) {
+        (Some(named), _) => named == copied,
+        (
+            None,
+            Some(Schema::Obj {
+                props,
+                additional,
+                closed,
+                ..
+            }),
+        ) => *closed && additional.is_none() && props.keys().eq(copied.iter()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{schema, schema::Ground, test_util::key};
+
+    use super::*;
+
+    #[test]
+    fn test_copied_objects() {
+        let open = schema!({
+            "type": "object",
+            "properties": {"a": {"type": "number"}, "b": {"type": "string"}},
+            "additionalProperties": {"type": "number"}
+        });
+        assert_eq!(
+            optimize(&[IR::PushObj, IR::Copy, IR::PopObj], &open),
+            vec![IR::Copy]
+        );
+        let by_key = vec![
+            IR::PushObj,
+            IR::PushKey(key("a")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PushKey(key("b")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        // the additional properties would be copied along
+        assert_eq!(optimize(&by_key, &open), by_key);
+        // and so would undeclared ones, which aren't ruled out
+        let undeclared = schema!({
+            "type": "object",
+            "properties": {"a": {"type": "number"}, "b": {"type": "string"}}
+        });
+        assert_eq!(optimize(&by_key, &undeclared), by_key);
+        let input = serde_json::json!({"a": 1, "b": "x", "secret": "leak"});
+        assert_eq!(
+            crate::ir::eval(&optimize(&by_key, &undeclared), &input),
+            serde_json::json!({"a": 1, "b": "x"})
+        );
+        let closed = schema!({
+            "type": "object",
+            "properties": {"a": {"type": "number"}, "b": {"type": "string"}},
+            "additionalProperties": false
+        });
+        assert_eq!(optimize(&by_key, &closed), vec![IR::Copy]);
+
+        let mut with_rest = by_key.clone();
+        with_rest.splice(
+            7..7,
+            [
+                IR::PushRest(vec![key("a"), key("b")]),
+                IR::Copy,
+                IR::PopRest,
+            ],
+        );
+        assert_eq!(optimize(&with_rest, &open), vec![IR::Copy]);
+    }
+
+    #[test]
+    fn test_nested_copies() {
+        let source = schema!({
+            "type": "object",
+            "properties": {
+                "user": {
+                    "type": "object",
+                    "properties": {"id": {"type": "number"}},
+                    "additionalProperties": false
+                },
+                "n": {"type": "number"}
+            }
+        });
+        let ir = vec![
+            IR::PushObj,
+            IR::Rename(key("user"), key("account")),
+            IR::PushObj,
+            IR::PushKey(key("id")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PushKey(key("n")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            optimize(&ir, &source),
+            vec![
+                IR::PushObj,
+                IR::Rename(key("user"), key("account")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PushKey(key("n")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PopObj,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dead_scopes() {
+        let ir = vec![
+            IR::PushObj,
+            IR::PushKey(key("a")),
+            IR::PushOpt,
+            IR::Extr(key("b")),
+            IR::PopOpt,
+            IR::PopKey,
+            IR::PushArr,
+            IR::PopArr,
+            IR::PopObj,
+            IR::Inv,
+        ];
+        // the empty object and array are still written
+        assert_eq!(
+            optimize(&ir, &Schema::True),
+            vec![IR::PushObj, IR::PushArr, IR::PopArr, IR::PopObj]
+        );
+    }
+}
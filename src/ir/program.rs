@@ -0,0 +1,527 @@
+//! Programs: IR paths along with the schemas they were found for, written
+//! out as JSON so that transforms can be stored, diffed, audited and
+//! compiled again later without searching for them again.
+//!
+//! Instructions are written the way serde writes enums: those without
+//! operands as just their name (`"Copy"`), and the rest as an object from
+//! their name to their operand, the array of their operands, or the object
+//! of their named operands (`{"PushKey": "id"}`, `{"Rename": ["id", "key"]}`,
+//! `{"Scale": {"factor": 0.01, "offset": 0.0}}`). Source paths are arrays of
+//! keys, and schemas are written as JSON Schema.
+
+use std::{fmt::Debug, fmt::Display, sync::Arc};
+
+use serde_json::{json, Map, Value};
+
+use crate::schema::{Ground, Literal, Real, Schema};
+
+use super::{compose, optimize::optimize, Case, Coding, DateFormat, SortOrder, SourcePath, IR};
+
+/// Error while reading a [`Program`] or an [`IR`] instruction from JSON,
+/// or chaining programs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgramErr {
+    /// A program which isn't an object of its version, the fingerprints of
+    /// its schemas and its instructions.
+    NotProgram,
+    /// An instruction this doesn't know of.
+    UnknownOp(String),
+    /// An instruction whose operands aren't what it takes.
+    InvalidOperands(String),
+    /// Programs chained where the first doesn't convert into the schema
+    /// the second converts from.
+    Unchained,
+    /// A program whose instruction at this index closes a scope other than
+    /// the innermost one open, or opens a scope which is never closed.
+    Unbalanced(usize),
+}
+
+impl Display for ProgramErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotProgram => write!(
+                f,
+                "program must be an object with a version, source, target and ir"
+            ),
+            Self::UnknownOp(op) => write!(f, "unknown instruction `{}`", op),
+            Self::InvalidOperands(op) => write!(f, "invalid operands to `{}`", op),
+            Self::Unchained => write!(
+                f,
+                "the first program's target isn't the second program's source"
+            ),
+            Self::Unbalanced(i) => write!(f, "instruction {} leaves its scopes unbalanced", i),
+        }
+    }
+}
+
+/// A transform found by the searcher, with enough about where it came from
+/// to tell whether it still applies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    /// Version of the tool which found the program.
+    pub version: String,
+    /// Fingerprint of the schema the program converts from (see
+    /// [`Schema::fingerprint`]).
+    pub source: u64,
+    /// Fingerprint of the schema the program converts into.
+    pub target: u64,
+    pub ir: Vec<IR>,
+}
+
+impl Program {
+    /// A program running `ir` to convert `source` data into `target` data.
+    pub fn new(ir: Vec<IR>, source: &Schema, target: &Schema) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            source: source.fingerprint(),
+            target: target.fingerprint(),
+            ir,
+        }
+    }
+
+    /// Whether this program was found for converting `source` into `target`.
+    pub fn converts(&self, source: &Schema, target: &Schema) -> bool {
+        self.source == source.fingerprint() && self.target == target.fingerprint()
+    }
+
+    /// A program converting data the way `a` does, then the way `b` does,
+    /// from the schema `a` converts from into the one `b` converts into.
+    /// Fails unless `b` converts from the schema `a` converts into.
+    pub fn compose(a: &Program, b: &Program) -> Result<Program, ProgramErr> {
+        if a.target != b.source {
+            return Err(ProgramErr::Unchained);
+        }
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            source: a.source,
+            target: b.target,
+            ir: optimize(&compose(&a.ir, &b.ir), &Schema::True),
+        })
+    }
+}
+
+impl From<&Program> for Value {
+    fn from(program: &Program) -> Self {
+        // as hex strings, since not every JSON reader can hold 64 bits
+        json!({
+            "version": program.version,
+            "source": format!("{:016x}", program.source),
+            "target": format!("{:016x}", program.target),
+            "ir": program.ir.iter().map(Value::from).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl TryFrom<&Value> for Program {
+    type Error = ProgramErr;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let fingerprint = |key| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                .ok_or(ProgramErr::NotProgram)
+        };
+        let version = value
+            .get("version")
+            .and_then(Value::as_str)
+            .ok_or(ProgramErr::NotProgram)?;
+        let ir = value
+            .get("ir")
+            .and_then(Value::as_array)
+            .ok_or(ProgramErr::NotProgram)?;
+        let ir = ir.iter().map(IR::try_from).collect::<Result<Vec<_>, _>>()?;
+        balanced(&ir)?;
+        Ok(Self {
+            version: version.to_string(),
+            source: fingerprint("source")?,
+            target: fingerprint("target")?,
+            ir,
+        })
+    }
+}
+
+/// Check each scope `ir` opens is closed, innermost first, so that
+/// composing or diffing it can rely on them nesting.
+fn balanced(ir: &[IR]) -> Result<(), ProgramErr> {
+    let mut open = vec![];
+    for (i, op) in ir.iter().enumerate() {
+        if let Some(closer) = op.closer() {
+            open.push((i, closer));
+        } else if op.is_closer() {
+            match open.pop() {
+                Some((_, closer)) if closer == *op => {}
+                _ => return Err(ProgramErr::Unbalanced(i)),
+            }
+        }
+    }
+    match open.first() {
+        Some((i, _)) => Err(ProgramErr::Unbalanced(*i)),
+        None => Ok(()),
+    }
+}
+
+/// The name of a variant of one of the IR's operand enums, like `Num` or
+/// `Ascending`.
+fn name(variant: impl Debug) -> Value {
+    json!(format!("{:?}", variant))
+}
+
+/// The variant out of `all` with the name in `value`.
+fn named<T: Clone + Debug>(all: &[T], value: &Value) -> Option<T> {
+    let value = value.as_str()?;
+    all.iter()
+        .find(|variant| format!("{:?}", variant) == value)
+        .cloned()
+}
+
+const GROUNDS: [Ground; 4] = [Ground::Num, Ground::Bool, Ground::String, Ground::Null];
+const CODINGS: [Coding; 2] = [Coding::Encode, Coding::Decode];
+const ORDERS: [SortOrder; 2] = [SortOrder::Ascending, SortOrder::Descending];
+const DATE_FORMATS: [DateFormat; 3] = [
+    DateFormat::Iso,
+    DateFormat::EpochSeconds,
+    DateFormat::EpochMillis,
+];
+
+fn key(key: &Arc<String>) -> Value {
+    json!(key.as_str())
+}
+
+fn path(path: &SourcePath) -> Value {
+    path.iter().map(key).collect()
+}
+
+fn pairs(pairs: &[(Arc<String>, Arc<String>)]) -> Value {
+    pairs.iter().map(|(a, b)| json!([key(a), key(b)])).collect()
+}
+
+impl From<&IR> for Value {
+    fn from(op: &IR) -> Self {
+        let (op, operands) = match op {
+            IR::Copy => ("Copy", None),
+            IR::G2G(from, to) => ("G2G", Some(json!([name(from), name(to)]))),
+            IR::PushObj => ("PushObj", None),
+            IR::Merge => ("Merge", None),
+            IR::PopObj => ("PopObj", None),
+            IR::PushRest(named) => ("PushRest", Some(named.iter().map(key).collect())),
+            IR::PopRest => ("PopRest", None),
+            IR::PushKey(k) => ("PushKey", Some(key(k))),
+            IR::Rename(from, to) => ("Rename", Some(json!([key(from), key(to)]))),
+            IR::Nest(k) => ("Nest", Some(key(k))),
+            IR::PopKey => ("PopKey", None),
+            IR::PushOpt => ("PushOpt", None),
+            IR::PopOpt => ("PopOpt", None),
+            IR::PushUnion => ("PushUnion", None),
+            IR::Case(branch) => ("Case", Some(Value::from(branch.as_ref()))),
+            IR::PopCase => ("PopCase", None),
+            IR::PopUnion => ("PopUnion", None),
+            IR::PushArr => ("PushArr", None),
+            IR::PopArr => ("PopArr", None),
+            IR::Extr(k) => ("Extr", Some(key(k))),
+            IR::Coalesce(paths) => ("Coalesce", Some(paths.iter().map(path).collect())),
+            IR::Wrap => ("Wrap", None),
+            IR::First => ("First", None),
+            IR::Flatten(depth) => ("Flatten", Some(json!(depth))),
+            IR::Slice(start, end) => ("Slice", Some(json!([start, end]))),
+            IR::Sort(by, order) => ("Sort", Some(json!([path(by), name(order)]))),
+            IR::Dedupe(by) => ("Dedupe", Some(by.as_ref().map_or(Value::Null, path))),
+            IR::Gather(paths) => ("Gather", Some(paths.iter().map(path).collect())),
+            IR::Join(sep) => ("Join", Some(json!(sep))),
+            IR::Scale {
+                factor: Real(factor),
+                offset: Real(offset),
+            } => ("Scale", Some(json!({"factor": factor, "offset": offset}))),
+            IR::Base64(coding) => ("Base64", Some(name(coding))),
+            IR::RegexExtract(pattern, group) => ("RegexExtract", Some(json!([pattern, group]))),
+            IR::CaseTransform(case) => ("CaseTransform", Some(name(case))),
+            IR::DateConvert {
+                from_format,
+                to_format,
+            } => (
+                "DateConvert",
+                Some(json!({"from_format": name(from_format), "to_format": name(to_format)})),
+            ),
+            // as pairs, since the values needn't be strings
+            IR::MapValues(table) => (
+                "MapValues",
+                Some(table.iter().map(|(k, v)| json!([k.0, v.0])).collect()),
+            ),
+            IR::Concat(paths, sep) => (
+                "Concat",
+                Some(json!([paths.iter().map(path).collect::<Vec<_>>(), sep])),
+            ),
+            IR::Const(value) => ("Const", Some(value.0.clone())),
+            IR::Inv => ("Inv", None),
+            IR::GroupBy(k) => ("GroupBy", Some(key(k))),
+            IR::Zip(zipped) => ("Zip", Some(pairs(zipped))),
+            IR::Unzip(unzipped) => ("Unzip", Some(pairs(unzipped))),
+            IR::Pipe => ("Pipe", None),
+            IR::Rec(label) => ("Rec", Some(key(label))),
+            IR::PopRec => ("PopRec", None),
+            IR::Call(label) => ("Call", Some(key(label))),
+        };
+        match operands {
+            None => json!(op),
+            Some(operands) => Value::Object(Map::from_iter([(op.to_string(), operands)])),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for IR {
+    type Error = ProgramErr;
+
+    fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+        let (op, operands) = match value {
+            Value::String(op) => (op.as_str(), &Value::Null),
+            Value::Object(op) if op.len() == 1 => {
+                let (op, operands) = op.iter().next().expect("one entry");
+                (op.as_str(), operands)
+            }
+            _ => return Err(ProgramErr::UnknownOp(value.to_string())),
+        };
+        let invalid = || ProgramErr::InvalidOperands(op.to_string());
+        let key = |v: &Value| {
+            v.as_str()
+                .map(|k| Arc::new(k.to_string()))
+                .ok_or_else(invalid)
+        };
+        let index = |v: &Value| v.as_u64().map(|i| i as usize).ok_or_else(invalid);
+        let list = |v: &'a Value| v.as_array().map(Vec::as_slice).ok_or_else(invalid);
+        let path = |v: &'a Value| list(v)?.iter().map(key).collect::<Result<SourcePath, _>>();
+        let paths = |v: &'a Value| list(v)?.iter().map(path).collect::<Result<Vec<_>, _>>();
+        let pair = |v| match list(v)? {
+            [a, b] => Ok((a, b)),
+            _ => Err(invalid()),
+        };
+        let pairs = |v: &'a Value| {
+            list(v)?
+                .iter()
+                .map(|p| {
+                    let (a, b) = pair(p)?;
+                    Ok((key(a)?, key(b)?))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let field = |name| operands.get(name).ok_or_else(invalid);
+
+        Ok(match op {
+            "Copy" => IR::Copy,
+            "G2G" => {
+                let (from, to) = pair(operands)?;
+                IR::G2G(
+                    named(&GROUNDS, from).ok_or_else(invalid)?,
+                    named(&GROUNDS, to).ok_or_else(invalid)?,
+                )
+            }
+            "PushObj" => IR::PushObj,
+            "Merge" => IR::Merge,
+            "PopObj" => IR::PopObj,
+            "PushRest" => IR::PushRest(path(operands)?),
+            "PopRest" => IR::PopRest,
+            "PushKey" => IR::PushKey(key(operands)?),
+            "Rename" => {
+                let (from, to) = pair(operands)?;
+                IR::Rename(key(from)?, key(to)?)
+            }
+            "Nest" => IR::Nest(key(operands)?),
+            "PopKey" => IR::PopKey,
+            "PushOpt" => IR::PushOpt,
+            "PopOpt" => IR::PopOpt,
+            "PushUnion" => IR::PushUnion,
+            "Case" => IR::Case(Arc::new(Schema::try_from(operands).map_err(|_| invalid())?)),
+            "PopCase" => IR::PopCase,
+            "PopUnion" => IR::PopUnion,
+            "PushArr" => IR::PushArr,
+            "PopArr" => IR::PopArr,
+            "Extr" => IR::Extr(key(operands)?),
+            "Coalesce" => IR::Coalesce(paths(operands)?),
+            "Wrap" => IR::Wrap,
+            "First" => IR::First,
+            "Flatten" => IR::Flatten(index(operands)?),
+            "Slice" => {
+                let (start, end) = pair(operands)?;
+                let end = match end {
+                    Value::Null => None,
+                    end => Some(index(end)?),
+                };
+                IR::Slice(index(start)?, end)
+            }
+            "Sort" => {
+                let (by, order) = pair(operands)?;
+                IR::Sort(path(by)?, named(&ORDERS, order).ok_or_else(invalid)?)
+            }
+            "Dedupe" => IR::Dedupe(match operands {
+                Value::Null => None,
+                by => Some(path(by)?),
+            }),
+            "Gather" => IR::Gather(paths(operands)?),
+            "Join" => IR::Join(operands.as_str().ok_or_else(invalid)?.to_string()),
+            "Scale" => {
+                let number = |name| field(name)?.as_f64().map(Real).ok_or_else(invalid);
+                IR::Scale {
+                    factor: number("factor")?,
+                    offset: number("offset")?,
+                }
+            }
+            "Base64" => IR::Base64(named(&CODINGS, operands).ok_or_else(invalid)?),
+            "RegexExtract" => {
+                let (pattern, group) = pair(operands)?;
+                let pattern = pattern.as_str().ok_or_else(invalid)?;
+                IR::RegexExtract(pattern.to_string(), index(group)?)
+            }
+            "CaseTransform" => IR::CaseTransform(named(&Case::ALL, operands).ok_or_else(invalid)?),
+            "DateConvert" => IR::DateConvert {
+                from_format: named(&DATE_FORMATS, field("from_format")?).ok_or_else(invalid)?,
+                to_format: named(&DATE_FORMATS, field("to_format")?).ok_or_else(invalid)?,
+            },
+            "MapValues" => IR::MapValues(
+                list(operands)?
+                    .iter()
+                    .map(|p| {
+                        let (k, v) = pair(p)?;
+                        Ok((Literal(k.clone()), Literal(v.clone())))
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            "Concat" => {
+                let (sources, sep) = pair(operands)?;
+                let sep = sep.as_str().ok_or_else(invalid)?;
+                IR::Concat(paths(sources)?, sep.to_string())
+            }
+            "Const" => IR::Const(Literal(operands.clone())),
+            "Inv" => IR::Inv,
+            "GroupBy" => IR::GroupBy(key(operands)?),
+            "Zip" => IR::Zip(pairs(operands)?),
+            "Unzip" => IR::Unzip(pairs(operands)?),
+            "Pipe" => IR::Pipe,
+            "Rec" => IR::Rec(key(operands)?),
+            "PopRec" => IR::PopRec,
+            "Call" => IR::Call(key(operands)?),
+            _ => return Err(ProgramErr::UnknownOp(op.to_string())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::test_util::key;
+
+    #[test]
+    fn test_round_trip() {
+        let ir = vec![
+            IR::Copy,
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PushObj,
+            IR::Merge,
+            IR::PopObj,
+            IR::PopObj,
+            IR::PushRest(vec![key("id")]),
+            IR::PopRest,
+            IR::PushKey(key("id")),
+            IR::PopKey,
+            IR::Rename(key("a"), key("b")),
+            IR::PopKey,
+            IR::Nest(key("n")),
+            IR::PopKey,
+            IR::PushOpt,
+            IR::PopOpt,
+            IR::PushUnion,
+            IR::Case(Arc::new(Schema::Arr(Arc::new(Schema::num())))),
+            IR::PopCase,
+            IR::PopUnion,
+            IR::PushArr,
+            IR::PopArr,
+            IR::Extr(key("x")),
+            IR::Coalesce(vec![vec![key("a")], vec![key("b"), key("c")]]),
+            IR::Wrap,
+            IR::First,
+            IR::Flatten(2),
+            IR::Slice(1, None),
+            IR::Slice(0, Some(3)),
+            IR::Sort(vec![key("ts")], SortOrder::Descending),
+            IR::Dedupe(None),
+            IR::Dedupe(Some(vec![key("id")])),
+            IR::Gather(vec![vec![key("a")], vec![key("b")]]),
+            IR::Join(", ".to_string()),
+            IR::Scale {
+                factor: Real(0.01),
+                offset: Real(-3.5),
+            },
+            IR::Base64(Coding::Decode),
+            IR::RegexExtract("@(.+)$".to_string(), 1),
+            IR::CaseTransform(Case::Snake),
+            IR::DateConvert {
+                from_format: DateFormat::Iso,
+                to_format: DateFormat::EpochMillis,
+            },
+            IR::MapValues(BTreeMap::from([
+                (Literal(json!("ACTIVE")), Literal(json!(1))),
+                (Literal(json!(false)), Literal(json!(null))),
+            ])),
+            IR::Concat(vec![vec![key("first")], vec![key("last")]], " ".to_string()),
+            IR::Const(Literal(json!({"a": [1, 2]}))),
+            IR::Inv,
+            IR::GroupBy(key("id")),
+            IR::Zip(vec![(key("names"), key("name"))]),
+            IR::Unzip(vec![(key("ages"), key("age"))]),
+            IR::Pipe,
+        ];
+        let program = Program::new(ir, &Schema::num(), &Schema::string());
+        let json = Value::from(&program);
+        assert_eq!(json["ir"][0], json!("Copy"));
+        assert_eq!(json["ir"][10], json!({"Rename": ["a", "b"]}));
+        assert_eq!(Program::try_from(&json), Ok(program.clone()));
+        assert!(program.converts(&Schema::num(), &Schema::string()));
+        assert!(!program.converts(&Schema::string(), &Schema::num()));
+    }
+
+    #[test]
+    fn test_invalid_programs() {
+        assert_eq!(
+            IR::try_from(&json!("Shuffle")),
+            Err(ProgramErr::UnknownOp("Shuffle".to_string()))
+        );
+        assert_eq!(
+            IR::try_from(&json!({"Rename": ["a"]})),
+            Err(ProgramErr::InvalidOperands("Rename".to_string()))
+        );
+        assert_eq!(
+            IR::try_from(&json!({"G2G": ["Num", "Text"]})),
+            Err(ProgramErr::InvalidOperands("G2G".to_string()))
+        );
+        assert_eq!(
+            Program::try_from(&json!({"version": "0.1.0", "ir": []})),
+            Err(ProgramErr::NotProgram)
+        );
+        let program = |ir| json!({"version": "0.1.0", "source": "0", "target": "0", "ir": ir});
+        assert_eq!(
+            Program::try_from(&program(json!(["PushObj", {"PushKey": "a"}, "PopObj"]))),
+            Err(ProgramErr::Unbalanced(2))
+        );
+        assert_eq!(
+            Program::try_from(&program(json!(["PushArr", "Copy"]))),
+            Err(ProgramErr::Unbalanced(0))
+        );
+        assert_eq!(
+            Program::try_from(&program(json!(["PopKey"]))),
+            Err(ProgramErr::Unbalanced(0))
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let to_string = vec![IR::G2G(Ground::Num, Ground::String)];
+        let to_bool = vec![IR::G2G(Ground::String, Ground::Bool)];
+        let a = Program::new(to_string.clone(), &Schema::num(), &Schema::string());
+        let b = Program::new(to_bool.clone(), &Schema::string(), &Schema::bool());
+        let chained = Program::compose(&a, &b).unwrap();
+        assert!(chained.converts(&Schema::num(), &Schema::bool()));
+        assert_eq!(chained.ir, [to_string, vec![IR::Pipe], to_bool].concat());
+        assert_eq!(Program::compose(&b, &a), Err(ProgramErr::Unchained));
+    }
+}
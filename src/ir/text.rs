@@ -0,0 +1,265 @@
+//! A textual form of IR paths, for reviewing and tweaking transforms by hand
+//! before generating code from them.
+//!
+//! Each instruction goes on its own line: its name, then its operands
+//! written as JSON the same way as in [programs](super::program), if it has
+//! any:
+//!
+//! ```text
+//! PushObj
+//!   Rename ["user_id","id"]
+//!     G2G ["Num","String"]
+//!   PushKey "tags"
+//!     Dedupe null
+//!     PushArr
+//!       Copy
+//! ```
+//!
+//! The instructions inside a scope are indented under the one opening it,
+//! and the instruction closing it is left out: the scope ends at the first
+//! line indented no further than its opener. Blank lines and lines starting
+//! with `#` are ignored.
+
+use std::fmt::Display;
+
+use serde_json::{Map, Value};
+
+use crate::frontend;
+
+use super::{program::ProgramErr, IR};
+
+/// Spaces each scope is indented by.
+pub(super) const INDENT: usize = 2;
+
+/// Error while reading IR from text, with the line (counted from 1) it's
+/// on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextErr {
+    /// Operands which aren't JSON.
+    Operands(usize),
+    /// An instruction which isn't one, or whose operands it doesn't take.
+    Op(usize, ProgramErr),
+    /// A line indented further than its scope's others, or under one which
+    /// doesn't open a scope.
+    Indent(usize),
+    /// An instruction closing a scope, which is left to the indentation.
+    Closer(usize),
+}
+
+impl Display for TextErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Operands(line) => write!(f, "line {}: operands must be JSON", line),
+            Self::Op(line, err) => write!(f, "line {}: {}", line, err),
+            Self::Indent(line) => write!(f, "line {}: unexpected indentation", line),
+            Self::Closer(line) => write!(
+                f,
+                "line {}: scopes are closed by indentation, not instructions",
+                line
+            ),
+        }
+    }
+}
+
+/// `ir` as text, one instruction per line. `ir` should close every scope
+/// it opens, in order; instructions closing scopes it didn't open are left
+/// out.
+pub fn print(ir: &[IR]) -> String {
+    let mut out = String::new();
+    let mut depth = 0;
+    for op in ir {
+        if op.is_closer() {
+            depth = usize::saturating_sub(depth, 1);
+            continue;
+        }
+        out.push_str(&" ".repeat(depth * INDENT));
+        out.push_str(&line(op));
+        out.push('\n');
+        if op.closer().is_some() {
+            depth += 1;
+        }
+    }
+    out
+}
+
+/// `op` as a line of text, without indentation.
+pub fn line(op: &IR) -> String {
+    match Value::from(op) {
+        Value::Object(map) => map
+            .into_iter()
+            .map(|(name, operands)| format!("{} {}", name, operands))
+            .collect(),
+        name => name.as_str().unwrap_or_default().to_string(),
+    }
+}
+
+/// A scope opened on one line and not yet closed.
+struct Scope {
+    /// Indentation of the line opening the scope.
+    opener: usize,
+    /// Indentation of the lines inside the scope, once there are any.
+    body: Option<usize>,
+    closer: Option<IR>,
+}
+
+/// The IR written out as `text` by [`print()`], or by hand.
+pub fn parse(text: &str) -> Result<Vec<IR>, TextErr> {
+    let mut ir = vec![];
+    // the outermost scope, which is never closed, then the ones inside it
+    let mut scopes = vec![Scope {
+        opener: 0,
+        body: Some(0),
+        closer: None,
+    }];
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let op = line.trim();
+        if op.is_empty() || op.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        loop {
+            let scope = scopes.last_mut().expect("outermost scope");
+            match scope.body {
+                None if indent > scope.opener => scope.body = Some(indent),
+                Some(body) if indent == body => {}
+                Some(body) if indent > body => return Err(TextErr::Indent(number)),
+                _ => {
+                    ir.extend(scopes.pop().and_then(|scope| scope.closer));
+                    continue;
+                }
+            }
+            break;
+        }
+        let op = parse_op(op).map_err(|err| match err {
+            Some(err) => TextErr::Op(number, err),
+            None => TextErr::Operands(number),
+        })?;
+        if op.is_closer() {
+            return Err(TextErr::Closer(number));
+        }
+        if let Some(closer) = op.closer() {
+            scopes.push(Scope {
+                opener: indent,
+                body: None,
+                closer: Some(closer),
+            });
+        }
+        ir.push(op);
+    }
+    ir.extend(scopes.into_iter().rev().filter_map(|scope| scope.closer));
+    Ok(ir)
+}
+
+/// The instruction on a line, failing with nothing if its operands aren't
+/// JSON.
+fn parse_op(line: &str) -> Result<IR, Option<ProgramErr>> {
+    let json = match line.split_once(char::is_whitespace) {
+        None => Value::String(line.to_string()),
+        Some((name, operands)) => {
+            let operands = frontend::parse_json(operands).map_err(|_| None)?;
+            Value::Object(Map::from_iter([(name.to_string(), operands)]))
+        }
+    };
+    IR::try_from(&json).map_err(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        ir::SortOrder,
+        schema::{Ground, Real, Schema},
+        test_util::key,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let ir = vec![
+            IR::PushObj,
+            IR::Rename(key("user_id"), key("id")),
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopKey,
+            IR::PushKey(key("tags")),
+            IR::Sort(vec![], SortOrder::Ascending),
+            IR::PushArr,
+            IR::PushUnion,
+            IR::Case(Arc::new(Schema::num())),
+            IR::Copy,
+            IR::PopCase,
+            IR::Case(Arc::new(Schema::string())),
+            IR::PopCase,
+            IR::PopUnion,
+            IR::PopArr,
+            IR::PopKey,
+            IR::PopObj,
+            IR::Copy,
+        ];
+        let text = print(&ir);
+        assert_eq!(
+            text.lines().take(4).collect::<Vec<_>>(),
+            vec![
+                "PushObj",
+                "  Rename [\"user_id\",\"id\"]",
+                "    G2G [\"Num\",\"String\"]",
+                "  PushKey \"tags\"",
+            ]
+        );
+        assert_eq!(parse(&text), Ok(ir));
+    }
+
+    #[test]
+    fn test_hand_written() {
+        let text = "
+# unwrap the payload
+PushObj
+    Rename ['n', 'count']
+        Scale {factor: 0.001, offset: 0}
+
+    PushKey \"tags\"
+      PushArr
+        Copy
+Copy
+";
+        let ir = parse(text).expect("valid text");
+        assert_eq!(ir.len(), 11);
+        assert_eq!(
+            ir[1..3],
+            [
+                IR::Rename(key("n"), key("count")),
+                IR::Scale {
+                    factor: Real(0.001),
+                    offset: Real(0.0)
+                }
+            ][..]
+        );
+        assert_eq!(
+            ir[3..],
+            [
+                IR::PopKey,
+                IR::PushKey(key("tags")),
+                IR::PushArr,
+                IR::Copy,
+                IR::PopArr,
+                IR::PopKey,
+                IR::PopObj,
+                IR::Copy
+            ][..]
+        );
+    }
+
+    #[test]
+    fn test_invalid_text() {
+        assert_eq!(parse("Copy\n  Copy"), Err(TextErr::Indent(2)));
+        assert_eq!(parse("PushObj\n    Copy\n  Copy"), Err(TextErr::Indent(3)));
+        assert_eq!(parse("PushObj\nPopObj"), Err(TextErr::Closer(2)));
+        assert_eq!(parse("PushKey id"), Err(TextErr::Operands(1)));
+        assert_eq!(
+            parse("Copy\nSwap"),
+            Err(TextErr::Op(2, ProgramErr::UnknownOp("Swap".to_string())))
+        );
+    }
+}
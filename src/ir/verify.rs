@@ -0,0 +1,624 @@
+//! A typechecker for IR paths, which runs them over the schemas of their
+//! input instead of data to make sure they read the input the way it's
+//! shaped and build output of the shape the target schema wants. A path
+//! failing to check is a bug in whatever produced it, caught before it
+//! becomes code which fails at runtime.
+//!
+//! The checks are conservative: wherever a step's input or output can't be
+//! worked out (after an [`IR::Inv`] or [`IR::Coalesce`], say), it's taken to
+//! be anything, and anything is taken to fit. Output copied from input the
+//! source schema doesn't require, or written behind an [`IR::PushOpt`]
+//! guard, may be missing, so it doesn't count towards the properties the
+//! target schema requires.
+
+use std::{collections::BTreeMap, fmt::Display, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    schema::{Literal, Schema},
+    searcher::{flat, grouped, unzipped, zipped},
+};
+
+use super::{join_path, DateFormat, IR};
+
+/// Error found checking an IR path against its schemas.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyErr {
+    /// The instruction at this index closes a scope it didn't open.
+    Unbalanced(usize),
+    /// The path ends with scopes still open.
+    Unclosed,
+    /// The instruction at this index reads input which can't have the shape
+    /// it expects, at this source path.
+    BadInput(usize, String),
+    /// The instruction at this index writes a property where no object is
+    /// being built.
+    NotObject(usize),
+    /// The output at this target path can't have the shape the target
+    /// schema wants.
+    Mismatch(String),
+    /// The output at this target path is required by the target schema,
+    /// but isn't written.
+    Missing(String),
+}
+
+impl Display for VerifyErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unbalanced(i) => write!(f, "instruction {} closes a scope it didn't open", i),
+            Self::Unclosed => write!(f, "scopes left open at the end"),
+            Self::BadInput(i, path) => {
+                write!(f, "instruction {} can't read the input at `{}`", i, path)
+            }
+            Self::NotObject(i) => {
+                write!(f, "instruction {} writes a property outside an object", i)
+            }
+            Self::Mismatch(path) => {
+                write!(f, "output at `{}` doesn't match the target schema", path)
+            }
+            Self::Missing(path) => write!(f, "required output at `{}` is missing", path),
+        }
+    }
+}
+
+/// A scope opened by an instruction, and what's known about the input and
+/// output inside it.
+struct Frame {
+    /// The instruction opening the scope, or `None` for the whole path.
+    opener: Option<IR>,
+    /// Schema of the input, if known.
+    input: Option<Arc<Schema>>,
+    source: String,
+    target: String,
+    /// Shape of the output written so far, where [`Schema::True`] is
+    /// output of an unknown shape.
+    output: Option<Schema>,
+    /// How many single-item arrays the output is wrapped in.
+    wraps: usize,
+    /// The output of each case of a union dispatch which writes any.
+    cases: Vec<Schema>,
+    /// Whether the input may be missing.
+    absent: bool,
+    /// Whether the output written may be missing after all.
+    partial: bool,
+}
+
+impl Frame {
+    /// A frame inside this one, for the scope `opener` opens.
+    fn enter(&self, opener: &IR) -> Frame {
+        Frame {
+            opener: Some(opener.clone()),
+            input: self.input.clone(),
+            source: self.source.clone(),
+            target: self.target.clone(),
+            output: None,
+            wraps: 0,
+            cases: vec![],
+            absent: self.absent,
+            partial: false,
+        }
+    }
+
+    fn write(&mut self, schema: Schema) {
+        // annotations don't change the shape of what's written
+        self.output = Some(schema.bare().clone());
+        self.partial = false;
+    }
+
+    /// The output written in this scope, inside its wrapping arrays.
+    fn written(&self) -> Option<Schema> {
+        let mut output = self.output.clone()?;
+        for _ in 0..self.wraps {
+            output = Schema::Arr(Arc::new(output));
+        }
+        Some(output)
+    }
+}
+
+/// Schema of property `key` of `input`, if it's known. Fails if `input`
+/// can't have the property.
+fn prop(input: Option<&Schema>, key: &Arc<String>) -> Result<Option<Arc<Schema>>, ()> {
+    match input {
+        Some(Schema::Obj {
+            props, additional, ..
+        }) => match props.get(key).or(additional.as_ref()) {
+            Some(schema) => Ok(Some(schema.clone())),
+            None => Err(()),
+        },
+        Some(Schema::Ground(_) | Schema::Arr(_) | Schema::False) => Err(()),
+        _ => Ok(None),
+    }
+}
+
+/// Whether `input` is known to have property `key` whenever it's there.
+fn has(input: Option<&Schema>, key: &Arc<String>) -> bool {
+    match input {
+        Some(Schema::Obj { required, .. }) => required.contains(key),
+        _ => true,
+    }
+}
+
+/// Schema of a fixed value written to the output.
+fn literal(value: &Literal) -> Schema {
+    match &value.0 {
+        Value::Null => Schema::null(),
+        Value::Bool(_) => Schema::bool(),
+        Value::Number(_) => Schema::num(),
+        Value::String(_) => Schema::string(),
+        Value::Array(_) | Value::Object(_) => Schema::True,
+    }
+}
+
+/// The union of `schemas`, leaving out repeats.
+fn union(schemas: Vec<Schema>) -> Schema {
+    let mut branches: Vec<Arc<Schema>> = vec![];
+    for schema in schemas {
+        if !branches.iter().any(|branch| **branch == schema) {
+            branches.push(Arc::new(schema));
+        }
+    }
+    match branches.len() {
+        1 => branches[0].as_ref().clone(),
+        _ => Schema::Union(branches),
+    }
+}
+
+/// Check that `ir` converts data matching `source` into data matching
+/// `target`.
+pub fn verify(ir: &[IR], source: &Schema, target: &Schema) -> Result<(), VerifyErr> {
+    let mut frames = vec![Frame {
+        opener: None,
+        input: Some(Arc::new(source.clone())),
+        source: String::new(),
+        target: String::new(),
+        output: None,
+        wraps: 0,
+        cases: vec![],
+        absent: false,
+        partial: false,
+    }];
+
+    for (i, op) in ir.iter().enumerate() {
+        if op.is_closer() {
+            let frame = match frames.pop() {
+                Some(frame) if frame.opener.as_ref().and_then(IR::closer).as_ref() == Some(op) => {
+                    frame
+                }
+                _ => return Err(VerifyErr::Unbalanced(i)),
+            };
+            let top = frames.last_mut().expect("the whole path's frame");
+            close(top, frame, i)?;
+            continue;
+        }
+
+        let top = frames.last_mut().expect("the whole path's frame");
+        // a recursive schema reads as what it stands for, and an annotated
+        // one as the schema it annotates
+        if let Some(Schema::Annotated(inner, _)) = top.input.as_deref() {
+            top.input = Some(inner.clone());
+        }
+        if let Some(rec @ Schema::Rec(..)) = top.input.as_deref() {
+            top.input = Some(Arc::new(rec.unfold().bare().clone()));
+        }
+        let input = top.input.as_deref();
+        let bad_input = |path: &str| VerifyErr::BadInput(i, path.to_string());
+        match op {
+            IR::Copy => {
+                let output = input.cloned().unwrap_or(Schema::True);
+                top.write(output);
+                top.partial = top.absent;
+            }
+            IR::G2G(from, to) => {
+                if matches!(input, Some(Schema::Ground(ground)) if ground != from) {
+                    return Err(bad_input(&top.source));
+                }
+                top.write(Schema::Ground(to.clone()));
+            }
+            IR::PushObj | IR::Merge => {
+                let mut frame = top.enter(op);
+                frame.output = match (op, &top.output) {
+                    (IR::Merge, Some(output @ (Schema::Obj { .. } | Schema::True))) => {
+                        Some(output.clone())
+                    }
+                    _ => Some(Schema::obj(BTreeMap::new())),
+                };
+                frames.push(frame);
+            }
+            IR::PushKey(from) | IR::Rename(from, _) => {
+                let to = match op {
+                    IR::Rename(_, to) => to,
+                    _ => from,
+                };
+                let schema =
+                    prop(input, from).map_err(|_| bad_input(&join_path(&top.source, from)))?;
+                let mut frame = top.enter(op);
+                frame.input = schema;
+                frame.absent = top.absent || !has(input, from);
+                frame.source = join_path(&top.source, from);
+                frame.target = join_path(&top.target, to);
+                frames.push(frame);
+            }
+            IR::Nest(key) => {
+                let mut frame = top.enter(op);
+                frame.target = join_path(&top.target, key);
+                frames.push(frame);
+            }
+            IR::PushOpt => {
+                let mut frame = top.enter(op);
+                frame.output = top.output.clone();
+                frame.absent = false;
+                frames.push(frame);
+            }
+            IR::PushUnion => {
+                let mut frame = top.enter(op);
+                frame.output = top.output.clone();
+                frames.push(frame);
+            }
+            IR::Case(branch) => {
+                if top.opener != Some(IR::PushUnion) {
+                    return Err(VerifyErr::Unbalanced(i));
+                }
+                let mut frame = top.enter(op);
+                frame.input = Some(branch.clone());
+                frame.output = top.output.clone();
+                frame.absent = false;
+                frames.push(frame);
+            }
+            IR::PushArr => {
+                let item = match input {
+                    Some(Schema::Arr(item)) => Some(item.clone()),
+                    Some(Schema::Ground(_) | Schema::Obj { .. } | Schema::False) => {
+                        return Err(bad_input(&top.source))
+                    }
+                    _ => None,
+                };
+                let mut frame = top.enter(op);
+                frame.input = item;
+                frame.absent = false;
+                frame.source = join_path(&top.source, "[]");
+                frame.target = join_path(&top.target, "[]");
+                frames.push(frame);
+            }
+            IR::Rec(_) => {
+                let frame = top.enter(op);
+                frames.push(frame);
+            }
+            // converting a part of the input like the whole, into output
+            // which has yet to be worked out
+            IR::Call(_) => top.write(Schema::True),
+            IR::PushRest(_) => {
+                let additional = match input {
+                    Some(Schema::Obj { additional, .. }) => additional.clone(),
+                    _ => None,
+                };
+                let mut frame = top.enter(op);
+                frame.input = additional;
+                frame.absent = false;
+                frame.source = join_path(&top.source, "*");
+                frame.target = join_path(&top.target, "*");
+                frames.push(frame);
+            }
+            IR::Extr(key) => {
+                top.absent |= !has(input, key);
+                top.input =
+                    prop(input, key).map_err(|_| bad_input(&join_path(&top.source, key)))?;
+                top.source = join_path(&top.source, key);
+            }
+            IR::Wrap => top.wraps += 1,
+            IR::First => {
+                top.input = match input {
+                    Some(Schema::Arr(item)) => Some(item.clone()),
+                    _ => None,
+                };
+            }
+            IR::Flatten(depth) => {
+                top.input = input.and_then(|schema| flat(schema, *depth)).map(Arc::new);
+            }
+            IR::GroupBy(key) => {
+                top.input = input.and_then(|schema| grouped(schema, key)).map(Arc::new);
+            }
+            IR::Zip(pairs) => {
+                top.input = input.and_then(|schema| zipped(schema, pairs)).map(Arc::new);
+            }
+            IR::Unzip(pairs) => {
+                top.input = input
+                    .and_then(|schema| unzipped(schema, pairs))
+                    .map(Arc::new);
+            }
+            IR::Slice(_, _) | IR::Sort(_, _) | IR::Dedupe(_) => {}
+            IR::Coalesce(_) | IR::Gather(_) | IR::Inv => {
+                top.input = None;
+                top.absent = false;
+            }
+            IR::Pipe => top.input = top.output.clone().map(Arc::new),
+            IR::Scale { .. } => top.write(Schema::num()),
+            IR::Join(_)
+            | IR::Base64(_)
+            | IR::RegexExtract(_, _)
+            | IR::CaseTransform(_)
+            | IR::Concat(_, _) => top.write(Schema::string()),
+            IR::DateConvert { to_format, .. } => top.write(match to_format {
+                DateFormat::Iso => Schema::string(),
+                DateFormat::EpochSeconds | DateFormat::EpochMillis => Schema::num(),
+            }),
+            IR::MapValues(table) => top.write(union(table.values().map(literal).collect())),
+            IR::Const(value) => top.write(literal(value)),
+            IR::PopObj
+            | IR::PopRest
+            | IR::PopKey
+            | IR::PopOpt
+            | IR::PopCase
+            | IR::PopUnion
+            | IR::PopArr
+            | IR::PopRec => unreachable!("closers are handled above"),
+        }
+    }
+
+    let [root] = &frames[..] else {
+        return Err(VerifyErr::Unclosed);
+    };
+    match root.written() {
+        Some(output) => fits(&output, target, ""),
+        None => Err(VerifyErr::Missing(String::new())),
+    }
+}
+
+/// Fold the output of the scope `frame`, closed by the instruction at index
+/// `i`, into the scope `top` around it.
+fn close(top: &mut Frame, frame: Frame, i: usize) -> Result<(), VerifyErr> {
+    let written = frame.written();
+    match frame.opener.as_ref().expect("only scopes are closed") {
+        IR::PushObj | IR::Merge => top.write(written.expect("objects are written")),
+        IR::PushKey(key) | IR::Rename(_, key) | IR::Nest(key) => {
+            let Some(written) = written else {
+                return Ok(());
+            };
+            match &mut top.output {
+                Some(Schema::Obj {
+                    props, required, ..
+                }) => {
+                    props.insert(key.clone(), Arc::new(written));
+                    if !frame.partial {
+                        required.insert(key.clone());
+                    }
+                }
+                Some(Schema::True) => {}
+                _ => return Err(VerifyErr::NotObject(i)),
+            }
+        }
+        // output written only behind the guard may be missing, unless
+        // something was written before it
+        IR::PushOpt => {
+            if written.is_some() {
+                top.partial |= top.output.is_none();
+                top.output = written;
+            }
+        }
+        IR::Case(_) => top.cases.extend(written),
+        IR::PushUnion => {
+            if !frame.cases.is_empty() {
+                top.write(union(frame.cases));
+            }
+        }
+        IR::PushArr => top.write(Schema::Arr(Arc::new(written.unwrap_or(Schema::True)))),
+        IR::Rec(_) => {
+            if let Some(written) = written {
+                top.write(written);
+                top.partial = frame.partial;
+            }
+        }
+        IR::PushRest(_) => match &mut top.output {
+            Some(Schema::Obj { additional, .. }) => {
+                *additional = Some(Arc::new(written.unwrap_or(Schema::True)))
+            }
+            Some(Schema::True) => {}
+            _ => return Err(VerifyErr::NotObject(i)),
+        },
+        _ => unreachable!("only openers open scopes"),
+    }
+    Ok(())
+}
+
+/// Check that output of the shape `output` matches `target`, where both
+/// are at `path` in the output.
+fn fits(output: &Schema, target: &Schema, path: &str) -> Result<(), VerifyErr> {
+    let mismatch = || Err(VerifyErr::Mismatch(path.to_string()));
+    match (output, target) {
+        _ if output == target => Ok(()),
+        (_, Schema::True) | (Schema::True, _) => Ok(()),
+        (Schema::Annotated(output, _), _) => fits(output, target, path),
+        (_, Schema::Annotated(target, _)) => fits(output, target, path),
+        // output copied from a recursive schema is only known to fit that
+        // same schema, while output built step by step is finite, so fits
+        // a recursive schema if it fits it unrolled
+        (Schema::Rec(..) | Schema::Var(_), _) | (_, Schema::Var(_)) => Ok(()),
+        (_, Schema::Rec(..)) => fits(output, &target.unfold(), path),
+        (Schema::Union(outputs), _) => outputs
+            .iter()
+            .try_for_each(|output| fits(output, target, path)),
+        (_, Schema::Union(branches)) => {
+            match branches
+                .iter()
+                .any(|branch| fits(output, branch, path).is_ok())
+            {
+                true => Ok(()),
+                false => mismatch(),
+            }
+        }
+        (Schema::Ground(a), Schema::Ground(b)) if a == b => Ok(()),
+        (Schema::Arr(output), Schema::Arr(target)) => fits(output, target, &join_path(path, "[]")),
+        (
+            Schema::Obj {
+                props,
+                required,
+                additional,
+                ..
+            },
+            Schema::Obj {
+                props: wanted,
+                required: needed,
+                additional: allowed,
+                ..
+            },
+        ) => {
+            for key in needed {
+                if !required.contains(key) {
+                    return Err(VerifyErr::Missing(join_path(path, key)));
+                }
+            }
+            for (key, output) in props {
+                let path = join_path(path, key);
+                match wanted.get(key).or(allowed.as_ref()) {
+                    Some(target) => fits(output, target, &path)?,
+                    None => return Err(VerifyErr::Mismatch(path)),
+                }
+            }
+            match (additional, allowed) {
+                (None, _) => Ok(()),
+                (Some(output), Some(target)) => fits(output, target, &join_path(path, "*")),
+                (Some(_), None) => Err(VerifyErr::Mismatch(join_path(path, "*"))),
+            }
+        }
+        _ => mismatch(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{schema, schema::Ground, searcher::SchemaSearcher, test_util::key};
+
+    use super::*;
+
+    #[test]
+    fn test_found_paths() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "user_id": {"type": "number"},
+                "tags": {"type": "array", "items": {"type": "boolean"}},
+                "note": {"type": "string"}
+            },
+            "required": ["user_id", "tags"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "userId": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "note": {"type": "string"}
+            },
+            "required": ["userId", "tags"]
+        });
+        let ir = SchemaSearcher::new().find_path(&s1, &s2).unwrap();
+        assert_eq!(verify(&ir, &s1, &s2), Ok(()));
+        let back = SchemaSearcher::new().find_path(&s2, &s1).unwrap();
+        assert_eq!(verify(&back, &s2, &s1), Ok(()));
+    }
+
+    #[test]
+    fn test_optional_input() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {"n": {"type": "number"}}
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {"n": {"type": "string"}},
+            "required": ["n"]
+        });
+        let copy = vec![
+            IR::PushObj,
+            IR::PushKey(key("n")),
+            IR::PushOpt,
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopOpt,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        assert_eq!(
+            verify(&copy, &s1, &s2),
+            Err(VerifyErr::Missing("n".to_string()))
+        );
+        // unless something's written first to fall back on
+        let mut filled = copy.clone();
+        filled.splice(
+            1..1,
+            [
+                IR::Nest(key("n")),
+                IR::Const(Literal(serde_json::json!(""))),
+                IR::PopKey,
+            ],
+        );
+        assert_eq!(verify(&filled, &s1, &s2), Ok(()));
+    }
+
+    #[test]
+    fn test_balance() {
+        let s = Schema::Arr(Arc::new(Schema::num()));
+        assert_eq!(
+            verify(&[IR::PushArr, IR::Copy, IR::PopKey], &s, &s),
+            Err(VerifyErr::Unbalanced(2))
+        );
+        assert_eq!(verify(&[IR::PopObj], &s, &s), Err(VerifyErr::Unbalanced(0)));
+        assert_eq!(
+            verify(&[IR::PushObj, IR::PushOpt], &s, &s),
+            Err(VerifyErr::Unclosed)
+        );
+    }
+
+    #[test]
+    fn test_bad_paths() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {"n": {"type": "number"}},
+            "required": ["n"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {"n": {"type": "string"}, "m": {"type": "number"}},
+            "required": ["n", "m"]
+        });
+        let obj = |body: Vec<IR>| [vec![IR::PushObj], body, vec![IR::PopObj]].concat();
+        let convert = |from: &str, ground: Ground| {
+            vec![
+                IR::PushKey(key(from)),
+                IR::G2G(ground, Ground::String),
+                IR::PopKey,
+            ]
+        };
+        assert_eq!(
+            verify(&obj(convert("x", Ground::Num)), &s1, &s2),
+            Err(VerifyErr::BadInput(1, "x".to_string()))
+        );
+        assert_eq!(
+            verify(&obj(convert("n", Ground::Bool)), &s1, &s2),
+            Err(VerifyErr::BadInput(2, "n".to_string()))
+        );
+        assert_eq!(
+            verify(&obj(convert("n", Ground::Num)), &s1, &s2),
+            Err(VerifyErr::Missing("m".to_string()))
+        );
+        let mut path = obj(convert("n", Ground::Num));
+        path.splice(
+            4..4,
+            [IR::PushKey(key("m")), IR::PushArr, IR::PopArr, IR::PopKey],
+        );
+        assert_eq!(
+            verify(&path, &s1, &s2),
+            Err(VerifyErr::BadInput(4, "m".to_string()))
+        );
+        path[4] = IR::Rename(key("n"), key("m"));
+        path.splice(5..7, [IR::Copy]);
+        assert_eq!(verify(&path, &s1, &s2), Ok(()));
+        path[2] = IR::Copy;
+        assert_eq!(
+            verify(&path, &s1, &s2),
+            Err(VerifyErr::Mismatch("n".to_string()))
+        );
+        assert_eq!(
+            verify(&[IR::PushKey(key("n")), IR::Copy, IR::PopKey], &s1, &s2),
+            Err(VerifyErr::NotObject(2))
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Transforms between JSON Schemas. Given a source and a target schema,
+//! [`searcher`] finds a path of [`ir`] instructions converting data of one
+//! into data of the other, and [`codegen`] turns that path into a transform
+//! in one of several languages.
+//!
+//! ```
+//! use jsonschema_transformer::{
+//!     codegen::{js::JSCodegen, Codegen},
+//!     schema,
+//!     searcher::SchemaSearcher,
+//! };
+//!
+//! let source = schema!({
+//!     "type": "object",
+//!     "properties": {"user_id": {"type": "string"}}
+//! });
+//! let target = schema!({
+//!     "type": "object",
+//!     "properties": {"userId": {"type": "string"}}
+//! });
+//! let path = SchemaSearcher::new().find_path(&source, &target).unwrap();
+//! let js = JSCodegen::new().generate(&path, "input", "output");
+//! assert!(js.contains("output.userId = input.user_id;"));
+//! ```
+//!
+//! Schemas are read with [`frontend`] (or [`schema::Schema`]'s conversion
+//! from JSON), and the search can be steered with [`mapping`]s, [`matcher`]
+//! synonyms and [`cost`] models through [`searcher::SearchConfig`].
+
+pub mod codegen;
+pub mod cost;
+pub mod frontend;
+pub mod ir;
+pub mod mapping;
+pub mod matcher;
+pub mod regex;
+pub mod report;
+pub mod runtime;
+pub mod schema;
+pub mod searcher;
+#[cfg(test)]
+mod test_util;
+mod units;
+
+// for [`schema!`] to build its JSON with wherever it's used
+#[doc(hidden)]
+pub use serde_json;
@@ -1,51 +1,900 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    process::ExitCode,
+};
 
-use egg::{rewrite as rw, *};
+use jsonschema_transformer::{
+    codegen::{
+        jmespath,
+        jq::JqCodegen,
+        js::{
+            format::{Formatting, Indent, Quotes},
+            spec::{self, Framework},
+            ArrayStyle, Dialect, ErrorMode, FunctionStyle, JSCodegen, ModuleFormat, NumberCoercion,
+            Numbering, Runtime, Streaming,
+        },
+        jsonata,
+        rust::{typed::TypedRustCodegen, RustCodegen},
+        sql,
+        swift::SwiftCodegen,
+        ts::TSCodegen,
+        wasm, Codegen, CodegenOptions, ReturnStyle,
+    },
+    cost,
+    frontend::{self, Format},
+    ir, mapping, matcher, report, schema, searcher,
+};
 
-mod schema;
+/// What each command does, and how it's run.
+const COMMANDS: [(&str, &str, &str); 5] = [
+    (
+        "transform",
+        "[transform] [OPTIONS] SOURCE TARGET",
+        "Generate a transform from SOURCE data to TARGET data (the default)",
+    ),
+    (
+        "explain",
+        "explain [SEARCH OPTIONS] SOURCE TARGET",
+        "Describe the transform found, step by step, without generating it",
+    ),
+    (
+        "apply",
+        "apply [SEARCH OPTIONS] SOURCE TARGET --data FILE [--validate]",
+        "Transform the data in FILE (or standard input, for `-`) and print it,\n\
+         checking it against TARGET first, with `--validate`",
+    ),
+    (
+        "compose",
+        "compose FIRST SECOND",
+        "Chain two programs (as written by `--target ir`) into one",
+    ),
+    (
+        "diff",
+        "diff OLD NEW",
+        "Show how the transforms in two IR files differ",
+    ),
+];
 
-define_language! {
-    enum Schema {
-        "bool"  = Bool,
-        "num"   = Num,
-        "null"  = Null,
-        "str"   = Str,
-        "arr"   = Arr(Id),
-        "obj"   = Obj([Id; 2]),
-        "pair"  = Pair([Id; 2]),
-        "empty" = Empty,
-        Key(Symbol),
+/// How schemas are named on the command line.
+const SCHEMAS: &str = "\
+Schemas may be prefixed with their format, like `infer:payload.json`; the
+formats are jsonschema, bigquery, infer, ndjson and csv. A schema given as
+`-` is read from standard input; if both are, the source schema comes first,
+then a line of `---`, then the target schema. A source ndjson corpus also
+says how often each property turns up, and rarely present ones are the last
+choice to feed a target property from.
+";
+
+/// How much more renaming a source property costs if it never turned up in
+/// the corpus the source schema was inferred from.
+const RARITY_PENALTY: u64 = 4;
+
+const SEARCH_OPTIONS: &str = "\
+Search options:
+  --mapping FILE        Pin down which source properties become which target ones
+  --fill FILE           Values to fill target properties with
+  --synonyms FILE       Words to treat as the same when matching property names
+  --key-order ORDER     sorted or declared (in TARGET)
+  --beam WIDTH          Search only the WIDTH cheapest alternatives at a time
+  --strict              Refuse to drop source properties the mapping doesn't waive
+  --safe                Rank conversions by how safe they are before what they cost
+";
+
+const APPLY_OPTIONS: &str = "\
+Apply options:
+  --data FILE           The data to transform, or `-` for standard input
+  --validate            Check the output against TARGET
+";
+
+const OUTPUT_OPTIONS: &str = "\
+Output options:
+  --target TARGET       js, ts, rust, rust-typed, jq, jmespath, jsonata, sql,
+                        swift, wasm, ir or text
+  --name NAME           Name of the generated function
+  --param NAME          Name of its parameter
+  --return STYLE        variable or literal
+";
+
+const JS_OPTIONS: &str = "\
+JavaScript options (for `--target js`, and `--runtime` for ts too):
+  --runtime RUNTIME     browser or node
+  --module FORMAT       default, named or commonjs
+  --style STYLE         declaration or arrow
+  --dialect DIALECT     modern or es5
+  --errors MODE         ignore, throw, collect or fallback
+  --arrays STYLE        loop or map
+  --coercion MODE       number, parse-float or strict
+  --stream MODE         generator or async
+  --numbering MODE      sequential or per-name
+  --prefix PREFIX       What generated variable names start with
+  --validate            Check the input against SOURCE first
+  --optional-chaining   Read input which may be missing safely
+  --provenance          Comment each write with where it comes from
+  --use-strict          Opt into strict mode
+  --jsdoc               Document the types taken and returned
+  --freeze              Deeply freeze a copy of the output
+  --passthrough         Pass unmapped properties through to open objects
+  --helpers             Share repeated blocks as helper functions
+  --bigint              Convert 64-bit integers as BigInts, which callers have
+                        to serialize themselves, since JSON.stringify can't
+  --tab-width N         Indent with N spaces
+  --use-tabs            Indent with tabs
+  --no-semi             Leave out semicolons
+  --single-quote        Quote strings with single quotes
+  --dts FILE            Write TypeScript declarations to FILE
+  --manifest FILE       Write which lines come from which instructions to FILE
+  --tests FILE          Write tests of SOURCE's examples to FILE
+  --framework NAME      jest or vitest, for --tests
+";
+
+/// The options only JavaScript is generated with, leaving out `--runtime`,
+/// which TypeScript is too.
+const JS_ONLY: [&str; 26] = [
+    "--module",
+    "--style",
+    "--dialect",
+    "--errors",
+    "--arrays",
+    "--coercion",
+    "--stream",
+    "--numbering",
+    "--prefix",
+    "--tab-width",
+    "--dts",
+    "--manifest",
+    "--tests",
+    "--framework",
+    "--validate",
+    "--optional-chaining",
+    "--provenance",
+    "--use-strict",
+    "--jsdoc",
+    "--freeze",
+    "--passthrough",
+    "--helpers",
+    "--bigint",
+    "--use-tabs",
+    "--no-semi",
+    "--single-quote",
+];
+
+/// How to use the binary, or with a `command`, how to use that, for
+/// `--help`.
+fn help(command: Option<&str>) -> String {
+    let usage =
+        |(_, usage, _): &(&str, &str, &str)| format!("  jsonschema-transformer {}\n", usage);
+    let (mut help, sections): (String, &[&str]) =
+        match COMMANDS.iter().find(|(name, _, _)| Some(*name) == command) {
+            None => {
+                let mut help = "\
+                    Finds transforms between JSON Schemas and generates code for them.\n\n\
+                    Usage:\n"
+                    .to_string();
+                COMMANDS
+                    .iter()
+                    .for_each(|command| help.push_str(&usage(command)));
+                help.push_str("\nCommands:\n");
+                for (name, _, about) in COMMANDS {
+                    let about = about.replace('\n', "\n             ");
+                    help.push_str(&format!("  {:<9}  {}\n", name, about));
+                }
+                (help, &[SCHEMAS, SEARCH_OPTIONS, OUTPUT_OPTIONS, JS_OPTIONS])
+            }
+            Some(command @ (name, _, about)) => {
+                let about = about.replace('\n', " ");
+                let help = format!("{}.\n\nUsage:\n{}", about, usage(command));
+                let sections: &[&str] = match *name {
+                    "transform" => &[SCHEMAS, SEARCH_OPTIONS, OUTPUT_OPTIONS, JS_OPTIONS],
+                    "explain" => &[SCHEMAS, SEARCH_OPTIONS],
+                    "apply" => &[SCHEMAS, SEARCH_OPTIONS, APPLY_OPTIONS],
+                    _ => &[],
+                };
+                (help, sections)
+            }
+        };
+    for section in sections {
+        help.push('\n');
+        help.push_str(section);
     }
+    help
 }
 
+/// Error while running a command, reported instead of its output.
 #[derive(Debug)]
-enum Transformer {
-    // Num2Bool,
-    // RemoveKey,
-    // InvertList,
-    ReorderKeys,
+enum CliErr {
+    /// The arguments don't make up a command.
+    Usage(String),
+    /// A file couldn't be read or written, or isn't what it should be.
+    File(String, String),
+    /// The command couldn't do what it was asked.
+    Failed(String),
 }
 
-impl Display for Transformer {
+impl Display for CliErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("{:?}", self))
+        match self {
+            Self::Usage(e) => write!(f, "{}", e),
+            Self::File(path, e) => write!(f, "`{}`: {}", path, e),
+            Self::Failed(e) => write!(f, "{}", e),
+        }
     }
 }
 
-fn main() -> Result<(), std::io::Error> {
-    let s1_path = std::env::args().nth(1).expect("need first argument");
-    let s2_path = std::env::args().nth(2).expect("need second argument");
+/// `value` of `option`, which isn't one of those it can be.
+fn unknown(option: &str, value: &str) -> CliErr {
+    CliErr::Usage(format!("unknown {} `{}`", option, value))
+}
+
+/// The arguments of a command, which are taken out as they're parsed.
+struct Args(Vec<String>);
 
-    let s1_json: serde_json::Value =
-        serde_json::from_str(std::fs::read_to_string(s1_path)?.as_str())
-            .expect("first schema has valid JSON");
-    let s2_json: serde_json::Value =
-        serde_json::from_str(std::fs::read_to_string(s2_path)?.as_str())
-            .expect("second schema has valid JSON");
+impl Args {
+    /// The value given to option `name`, either as the next argument or
+    /// after an `=`, if it's given.
+    fn option(&mut self, name: &str) -> Result<Option<String>, CliErr> {
+        let joined = |arg: &String| {
+            arg.strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('='))
+                .map(str::to_string)
+        };
+        let Some(i) = self
+            .0
+            .iter()
+            .position(|arg| arg == name || joined(arg).is_some())
+        else {
+            return Ok(None);
+        };
+        let arg = self.0.remove(i);
+        if let Some(value) = joined(&arg) {
+            return Ok(Some(value));
+        }
+        match i < self.0.len() {
+            true => Ok(Some(self.0.remove(i))),
+            false => Err(CliErr::Usage(format!("`{}` needs a value", name))),
+        }
+    }
 
-    let s1 = schema::Schema::try_from(&s1_json).expect("first schema valid");
-    let s2 = schema::Schema::try_from(&s2_json).expect("first schema valid");
+    /// Whether option or flag `name` is given, without taking it out.
+    fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|arg| {
+            arg == name
+                || arg
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with('='))
+        })
+    }
 
-    println!("edit distance between schemas: {:?}", s1.edit_distance(&s2));
+    /// Whether flag `name` is given.
+    fn flag(&mut self, name: &str) -> bool {
+        match self.0.iter().position(|arg| arg == name) {
+            Some(i) => {
+                self.0.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The arguments left once the options are taken out, which should be
+    /// the `names` given.
+    fn positional(self, names: &[&str]) -> Result<Vec<String>, CliErr> {
+        if let Some(arg) = self.0.iter().find(|arg| arg.starts_with("--")) {
+            return Err(CliErr::Usage(format!("unknown option `{}`", arg)));
+        }
+        match self.0.len() == names.len() {
+            true => Ok(self.0),
+            false => Err(CliErr::Usage(format!("expected {}", names.join(" and ")))),
+        }
+    }
+}
+
+/// The contents of the file at `path`.
+fn read(path: &str) -> Result<String, CliErr> {
+    std::fs::read_to_string(path).map_err(|e| CliErr::File(path.to_string(), e.to_string()))
+}
+
+/// Write `contents` to the file at `path`.
+fn write(path: &str, contents: impl AsRef<[u8]>) -> Result<(), CliErr> {
+    std::fs::write(path, contents).map_err(|e| CliErr::File(path.to_string(), e.to_string()))
+}
+
+/// The JSON in the file at `path`.
+fn read_json(path: &str) -> Result<serde_json::Value, CliErr> {
+    frontend::parse_json(&read(path)?).map_err(|e| CliErr::File(path.to_string(), e.to_string()))
+}
+
+/// The IR path in the file at `path`, either a program or in the text
+/// format.
+fn load_ir(path: &str) -> Result<Vec<ir::IR>, CliErr> {
+    let text = read(path)?;
+    let invalid = |e: &dyn Display| CliErr::File(path.to_string(), e.to_string());
+    match frontend::parse_json(&text) {
+        Ok(json) => Ok(ir::program::Program::try_from(&json)
+            .map_err(|e| invalid(&e))?
+            .ir),
+        Err(_) => ir::text::parse(&text).map_err(|e| invalid(&e)),
+    }
+}
+
+/// The program in the file at `path`.
+fn load_program(path: &str) -> Result<ir::program::Program, CliErr> {
+    ir::program::Program::try_from(&read_json(path)?)
+        .map_err(|e| CliErr::File(path.to_string(), e.to_string()))
+}
+
+/// A schema named on the command line, read but not parsed yet: what it's
+/// called in errors, the format it's in, if that's known, and its contents.
+struct Text {
+    name: String,
+    format: Option<Format>,
+    contents: String,
+}
+
+impl Text {
+    /// The schema in the text.
+    fn schema(&self) -> Result<schema::Schema, CliErr> {
+        frontend::parse_schema(&self.contents, self.format)
+            .map_err(|e| CliErr::File(self.name.clone(), e.to_string()))
+    }
+}
+
+/// The schemas `args` name, read from standard input where they're `-`
+/// (or prefixed with a format, like `infer:-`). If both are, standard input
+/// has the source schema, then a line of `---`, then the target schema.
+fn read_schemas(args: [&str; 2]) -> Result<[Text; 2], CliErr> {
+    let stdin = "standard input";
+    let piped = args
+        .iter()
+        .filter(|arg| frontend::split_arg(arg).1 == "-")
+        .count();
+    let mut documents = match piped {
+        0 => vec![],
+        _ => {
+            let mut contents = String::new();
+            std::io::stdin()
+                .read_to_string(&mut contents)
+                .map_err(|e| CliErr::File(stdin.to_string(), e.to_string()))?;
+            match piped {
+                1 => vec![contents],
+                _ => match split_documents(&contents) {
+                    Some((source, target)) => vec![source, target],
+                    None => {
+                        let e = "expected the schemas separated by a line of `---`";
+                        return Err(CliErr::File(stdin.to_string(), e.to_string()));
+                    }
+                },
+            }
+        }
+    }
+    .into_iter();
+    let mut text = |arg: &str| {
+        let (format, path) = frontend::split_arg(arg);
+        Ok(match path {
+            "-" => Text {
+                name: stdin.to_string(),
+                format,
+                contents: documents.next().unwrap_or_default(),
+            },
+            _ => Text {
+                name: path.to_string(),
+                format,
+                contents: read(path)?,
+            },
+        })
+    };
+    Ok([text(args[0])?, text(args[1])?])
+}
+
+/// The two documents in `text`, separated by a line of `---`.
+fn split_documents(text: &str) -> Option<(String, String)> {
+    let mut lines = text.split_inclusive('\n');
+    let first: String = lines
+        .by_ref()
+        .take_while(|line| line.trim() != "---")
+        .collect();
+    let second: String = lines.collect();
+    match first.len() < text.len() {
+        true => Some((first, second)),
+        false => None,
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(Args(args)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            match e {
+                CliErr::Usage(_) => {
+                    eprintln!("run with `--help` for usage");
+                    ExitCode::from(2)
+                }
+                _ => ExitCode::FAILURE,
+            }
+        }
+    }
+}
+
+/// Run the command `args` make up.
+fn run(mut args: Args) -> Result<(), CliErr> {
+    if args.0.first().is_some_and(|arg| arg == "help") {
+        print!("{}", help(args.0.get(1).map(String::as_str)));
+        return Ok(());
+    }
+    let named = args
+        .0
+        .first()
+        .filter(|arg| COMMANDS.iter().any(|(name, _, _)| name == arg))
+        .cloned();
+    if named.is_some() {
+        args.0.remove(0);
+    }
+    if args.flag("--help") || args.flag("-h") {
+        print!("{}", help(named.as_deref()));
+        return Ok(());
+    }
+    let command = named.unwrap_or_else(|| "transform".to_string());
+    match command.as_str() {
+        "compose" => compose(args),
+        "diff" => diff(args),
+        "explain" => explain(args),
+        "apply" => apply(args),
+        _ => transform(args),
+    }
+}
+
+/// Print one program doing what two do in turn.
+fn compose(args: Args) -> Result<(), CliErr> {
+    let paths = args.positional(&["FIRST", "SECOND"])?;
+    let first = load_program(&paths[0])?;
+    let second = load_program(&paths[1])?;
+    let program = ir::program::Program::compose(&first, &second)
+        .map_err(|e| CliErr::Failed(format!("can't compose: {}", e)))?;
+    let json = serde_json::Value::from(&program);
+    println!("{:#}", json);
+    Ok(())
+}
+
+/// Print how the transforms in two files differ.
+fn diff(args: Args) -> Result<(), CliErr> {
+    let paths = args.positional(&["OLD", "NEW"])?;
+    let old = load_ir(&paths[0])?;
+    let new = load_ir(&paths[1])?;
+    print!("{}", ir::diff::render(&ir::diff::diff(&old, &new)));
+    Ok(())
+}
+
+/// A transform found between two schemas, as the search options say to
+/// find it.
+struct Found {
+    s1: schema::Schema,
+    s2: schema::Schema,
+    source: Text,
+    path: Vec<ir::IR>,
+    report: report::PathReport,
+    key_matcher: matcher::KeyMatcher,
+}
+
+/// Print what the transform found does, and what it may lose.
+fn explain(args: Args) -> Result<(), CliErr> {
+    let found = search(args)?;
+    for (kind, note) in notes(&found) {
+        println!("{}: {}", kind, note);
+    }
+    print!("{}", ir::text::print(&found.path));
+    Ok(())
+}
+
+/// Print the data in the file `args` name transformed by the transform
+/// found, checking it has the shape of the target schema first if asked.
+fn apply(mut args: Args) -> Result<(), CliErr> {
+    let data_path = args
+        .option("--data")?
+        .ok_or_else(|| CliErr::Usage("`apply` needs `--data FILE`".to_string()))?;
+    let validate = args.flag("--validate");
+    if data_path == "-" && args.0.iter().any(|arg| frontend::split_arg(arg).1 == "-") {
+        let e = "only the data or the schemas can be read from standard input";
+        return Err(CliErr::Usage(e.to_string()));
+    }
+    let found = search(args)?;
+    for (kind, note) in notes(&found) {
+        eprintln!("{}: {}", kind, note);
+    }
+    let data = match data_path.as_str() {
+        "-" => {
+            let mut contents = String::new();
+            std::io::stdin()
+                .read_to_string(&mut contents)
+                .map_err(|e| CliErr::File("standard input".to_string(), e.to_string()))?;
+            frontend::parse_json(&contents)
+                .map_err(|e| CliErr::File("standard input".to_string(), e.to_string()))?
+        }
+        path => read_json(path)?,
+    };
+    let output = ir::eval(&found.path, &data);
+    if validate {
+        schema::check::check(&found.s2, &output, "output")
+            .map_err(|e| CliErr::Failed(format!("output doesn't match the target: {}", e)))?;
+    }
+    println!("{:#}", output);
+    Ok(())
+}
+
+/// What's worth knowing about `found`: the properties it renames, how
+/// safe its conversions are and what it loses, each of which is a note or
+/// a warning.
+fn notes(found: &Found) -> Vec<(&'static str, String)> {
+    let mut notes = vec![];
+    for (from, to) in ir::renames(&found.path) {
+        let last = |path: &str| path.rsplit('.').next().unwrap_or_default().to_string();
+        let confidence = found.key_matcher.confidence(&last(&from), &last(&to));
+        notes.push((
+            "note",
+            format!(
+                "renamed `{}` to `{}` (confidence {:.2})",
+                from, to, confidence
+            ),
+        ));
+    }
+    for (from, to) in ir::flattens(&found.path) {
+        notes.push(("note", format!("flattened `{}` into `{}`", from, to)));
+    }
+    if found.report.tier > cost::Tier::Widening {
+        let note = format!("transform uses {} conversions", found.report.tier);
+        notes.push(("warning", note));
+    }
+    for step in &found.report.steps {
+        match step.lossiness {
+            report::Lossiness::Lossless => {}
+            report::Lossiness::Lossy => {
+                notes.push(("warning", format!("`{}` is converted lossily", step.source)))
+            }
+            report::Lossiness::Dropping => {
+                notes.push(("warning", format!("`{}` is dropped", step.source)))
+            }
+        }
+    }
+    notes
+}
+
+/// The transform between the schemas `args` name, found as their search
+/// options say. The rest of the options should have been taken out.
+fn search(mut args: Args) -> Result<Found, CliErr> {
+    let mapping_path = args.option("--mapping")?;
+    let synonyms_path = args.option("--synonyms")?;
+    let fill_path = args.option("--fill")?;
+    let key_order = args.option("--key-order")?;
+    let beam = args.option("--beam")?;
+    let strict = args.flag("--strict");
+    let safe = args.flag("--safe");
+    let paths = args.positional(&["SOURCE", "TARGET"])?;
+    let [source, target] = read_schemas([&paths[0], &paths[1]])?;
+    let s1 = source.schema()?;
+    let s2 = target.schema()?;
+
+    let mut config = searcher::SearchConfig {
+        strict,
+        ..Default::default()
+    };
+    if let Some(width) = beam {
+        let width = width
+            .parse()
+            .map_err(|_| CliErr::Usage(format!("beam width `{}` isn't a number", width)))?;
+        config.strategy = searcher::Strategy::Beam(width);
+    }
+    // a corpus says how often each property turns up, so rarely present
+    // ones are the last choice to feed a target property from
+    if source.format == Some(Format::Corpus) {
+        let stats = frontend::infer::from_ndjson(&source.contents)
+            .map_err(|e| CliErr::File(source.name.clone(), e.to_string()))?;
+        config.cost_model = std::sync::Arc::new(cost::Rarity {
+            inner: config.cost_model,
+            presence: stats.presence(),
+            penalty: RARITY_PENALTY,
+        });
+    }
+    if safe {
+        config.cost_model = std::sync::Arc::new(cost::SafetyFirst {
+            inner: config.cost_model,
+        });
+    }
+    if let Some(path) = mapping_path {
+        config.mapping = mapping::Mapping::try_from(&read_json(&path)?)
+            .map_err(|e| CliErr::File(path, e.to_string()))?;
+    }
+    if let Some(path) = fill_path {
+        config
+            .mapping
+            .add_fills(&read_json(&path)?)
+            .map_err(|e| CliErr::File(path, e.to_string()))?;
+    }
+    if let Some(path) = synonyms_path {
+        let synonyms = matcher::Synonyms::try_from(&read_json(&path)?)
+            .map_err(|e| CliErr::File(path, e.to_string()))?;
+        if let Some(key_matcher) = config.key_matcher.as_mut() {
+            key_matcher.synonyms = synonyms;
+        }
+    }
+    match key_order.as_deref() {
+        None | Some("sorted") => {}
+        Some("declared") => {
+            let order = frontend::parse_order(&target.contents)
+                .map_err(|e| CliErr::File(target.name.clone(), e.to_string()))?;
+            config.key_order = searcher::KeyOrder::Declared(std::sync::Arc::new(order));
+        }
+        Some(other) => return Err(unknown("key order", other)),
+    }
+    let key_matcher = config.key_matcher.clone().unwrap_or_default();
+
+    let (path, report) = searcher::SchemaSearcher::with_config(config)
+        .find_path_with_report(&s1, &s2)
+        .map_err(|e| CliErr::Failed(format!("no transform found: {}", e)))?;
+    let path = ir::optimize::optimize(&path, &s1);
+    ir::verify(&path, &s1, &s2)
+        .map_err(|e| CliErr::Failed(format!("invalid transform found: {}", e)))?;
+    Ok(Found {
+        s1,
+        s2,
+        source,
+        path,
+        report,
+        key_matcher,
+    })
+}
+
+/// Print the transform between the schemas `args` name, generated as its
+/// options say.
+fn transform(mut args: Args) -> Result<(), CliErr> {
+    let target = args.option("--target")?;
+    let target = match target.as_deref() {
+        None => "js",
+        Some(
+            target @ ("js" | "ts" | "rust" | "rust-typed" | "jq" | "jmespath" | "jsonata" | "sql"
+            | "swift" | "wasm" | "ir" | "text"),
+        ) => target,
+        Some(other) => return Err(unknown("target", other)),
+    };
+    let js_only = match target {
+        "js" => None,
+        "ts" => JS_ONLY.iter().find(|option| args.has(option)),
+        _ => JS_ONLY
+            .iter()
+            .chain(&["--runtime"])
+            .find(|option| args.has(option)),
+    };
+    if let Some(option) = js_only {
+        let e = format!("`{}` doesn't apply to `--target {}`", option, target);
+        return Err(CliErr::Usage(e));
+    }
+    let runtime = args.option("--runtime")?;
+    let module = args.option("--module")?;
+    let style = args.option("--style")?;
+    let name = args.option("--name")?;
+    let param = args.option("--param")?;
+    let prefix = args.option("--prefix")?;
+    let numbering = args.option("--numbering")?;
+    let tests_path = args.option("--tests")?;
+    let dts_path = args.option("--dts")?;
+    let manifest_path = args.option("--manifest")?;
+    let framework = args.option("--framework")?;
+    let arrays = args.option("--arrays")?;
+    let coercion = args.option("--coercion")?;
+    let stream = args.option("--stream")?;
+    let dialect = args.option("--dialect")?;
+    let tab_width = args.option("--tab-width")?;
+    let returns = args.option("--return")?;
+    let errors = args.option("--errors")?;
+    let validate = args.flag("--validate");
+    let optional_chaining = args.flag("--optional-chaining");
+    let provenance = args.flag("--provenance");
+    let use_strict = args.flag("--use-strict");
+    let jsdoc = args.flag("--jsdoc");
+    let freeze = args.flag("--freeze");
+    let passthrough = args.flag("--passthrough");
+    let helpers = args.flag("--helpers");
+    let bigint = args.flag("--bigint");
+    let use_tabs = args.flag("--use-tabs");
+    let no_semi = args.flag("--no-semi");
+    let single_quote = args.flag("--single-quote");
+
+    let runtime = match runtime.as_deref() {
+        None | Some("browser") => Runtime::Browser,
+        Some("node") => Runtime::Node,
+        Some(other) => return Err(unknown("runtime", other)),
+    };
+    let module = match module.as_deref() {
+        None => ModuleFormat::Expression,
+        Some("default") => ModuleFormat::ExportDefault,
+        Some("named") => ModuleFormat::ExportNamed,
+        Some("commonjs") => ModuleFormat::CommonJs,
+        Some(other) => return Err(unknown("module format", other)),
+    };
+    let style = match style.as_deref() {
+        None => FunctionStyle::Expression,
+        Some("declaration") => FunctionStyle::Declaration,
+        Some("arrow") => FunctionStyle::Arrow,
+        Some(other) => return Err(unknown("function style", other)),
+    };
+    let streaming = match stream.as_deref() {
+        None => Streaming::Off,
+        Some("generator") => Streaming::Generator,
+        Some("async") => Streaming::AsyncGenerator,
+        Some(other) => return Err(unknown("streaming mode", other)),
+    };
+    let formatting = Formatting {
+        indent: match (use_tabs, tab_width) {
+            (true, _) => Indent::Tab,
+            (false, None) => Indent::default(),
+            (false, Some(width)) => match width.parse() {
+                Ok(width) => Indent::Spaces(width),
+                Err(_) => {
+                    let e = format!("tab width `{}` isn't a number", width);
+                    return Err(CliErr::Usage(e));
+                }
+            },
+        },
+        semicolons: !no_semi,
+        quotes: if single_quote {
+            Quotes::Single
+        } else {
+            Quotes::Double
+        },
+    };
+    let numbering = match numbering.as_deref() {
+        None | Some("sequential") => Numbering::Sequential,
+        Some("per-name") => Numbering::PerName,
+        Some(other) => return Err(unknown("variable numbering", other)),
+    };
+    let dialect = match dialect.as_deref() {
+        None | Some("modern") => Dialect::Modern,
+        Some("es5") => Dialect::Es5,
+        Some(other) => return Err(unknown("dialect", other)),
+    };
+    let coercion = match coercion.as_deref() {
+        None | Some("number") => NumberCoercion::Number,
+        Some("parse-float") => NumberCoercion::ParseFloat,
+        Some("strict") => NumberCoercion::Strict,
+        Some(other) => return Err(unknown("number coercion", other)),
+    };
+    let arrays = match arrays.as_deref() {
+        None | Some("loop") => ArrayStyle::Loop,
+        Some("map") => ArrayStyle::Map,
+        Some(other) => return Err(unknown("array style", other)),
+    };
+    let framework = match framework.as_deref() {
+        None | Some("jest") => Framework::Jest,
+        Some("vitest") => Framework::Vitest,
+        Some(other) => return Err(unknown("test framework", other)),
+    };
+    let errors = match errors.as_deref() {
+        None | Some("ignore") => ErrorMode::Ignore,
+        Some("throw") => ErrorMode::Throw,
+        Some("collect") => ErrorMode::Collect,
+        Some("fallback") => ErrorMode::Fallback,
+        Some(other) => return Err(unknown("error mode", other)),
+    };
+    let mut options = CodegenOptions::default();
+    if let Some(name) = name {
+        options.name = name;
+    }
+    if let Some(param) = param {
+        options.input = param;
+    }
+    options.returns = match returns.as_deref() {
+        None | Some("variable") => ReturnStyle::Variable,
+        Some("literal") => ReturnStyle::Literal,
+        Some(other) => return Err(unknown("return style", other)),
+    };
+
+    let found = search(args)?;
+    for (kind, note) in notes(&found) {
+        eprintln!("{}: {}", kind, note);
+    }
+    let Found {
+        s1,
+        s2,
+        source,
+        path,
+        ..
+    } = found;
+    match target {
+        "ir" => {
+            // the program itself, to compile later
+            let program = ir::program::Program::new(path, &s1, &s2);
+            println!("{:#}", serde_json::Value::from(&program))
+        }
+        // the path, to read over or tweak by hand
+        "text" => print!("{}", ir::text::print(&path)),
+        "ts" => print!(
+            "{}",
+            TSCodegen::with_runtime(&s1, &s2, runtime).generate_with(&path, &options)
+        ),
+        "rust" => print!("{}", RustCodegen::new().generate_with(&path, &options)),
+        "rust-typed" => print!(
+            "{}",
+            TypedRustCodegen::new(&s1, &s2).generate_with(&path, &options)
+        ),
+        "jmespath" => match jmespath::expression(&path) {
+            Ok(expression) => println!("{}", expression),
+            Err(e) => return Err(CliErr::Failed(format!("no JMESPath expression: {}", e))),
+        },
+        "jsonata" => match jsonata::expression(&path) {
+            Ok(expression) => println!("{}", expression),
+            Err(e) => return Err(CliErr::Failed(format!("no JSONata expression: {}", e))),
+        },
+        "sql" => match sql::function(&path, &options.name) {
+            Ok(function) => print!("{}", function),
+            Err(e) => return Err(CliErr::Failed(format!("no SQL function: {}", e))),
+        },
+        "swift" => print!(
+            "{}",
+            SwiftCodegen::new(&s1, &s2).generate_with(&path, &options)
+        ),
+        "wasm" => match wasm::module(&path) {
+            Ok(module) => std::io::stdout()
+                .write_all(&module)
+                .map_err(|e| CliErr::File("standard output".to_string(), e.to_string()))?,
+            Err(e) => return Err(CliErr::Failed(format!("no WebAssembly module: {}", e))),
+        },
+        "jq" => print!("{}", JqCodegen::new().generate_with(&path, &options)),
+        _ => {
+            let mut js = JSCodegen::with_runtime(runtime)
+                .module(module)
+                .style(style)
+                .errors(errors)
+                .arrays(arrays)
+                .coercion(coercion)
+                .streaming(streaming)
+                .dialect(dialect)
+                .numbering(numbering)
+                .formatting(formatting)
+                .prefix(prefix.as_deref().unwrap_or_default());
+            if validate {
+                js = js.validate(&s1);
+            }
+            if optional_chaining {
+                js = js.safe(&s1);
+            }
+            if jsdoc {
+                js = js.jsdoc(&s1, &s2);
+            }
+            if helpers {
+                js = js.helpers();
+            }
+            if passthrough {
+                js = js.passthrough(&s2);
+            }
+            if bigint {
+                js = js.bigint(&s1, &s2);
+            }
+            if freeze {
+                js = js.freeze();
+            }
+            if use_strict {
+                js = js.strict();
+            }
+            if provenance {
+                js = js.provenance(&s1, &s2);
+            }
+            print!("{}", js.generate_with(&path, &options));
+            if let Some(dts_path) = &dts_path {
+                write(dts_path, js.declarations(&s1, &s2))?;
+            }
+            if let Some(manifest_path) = &manifest_path {
+                let manifest = js.manifest(&path, &s1);
+                write(manifest_path, format!("{:#}\n", manifest))?;
+            }
+            if let Some(tests_path) = &tests_path {
+                let examples =
+                    frontend::parse_examples(&source.contents, source.format).unwrap_or_default();
+                let import = format!("./{}.js", options.name);
+                let tests =
+                    spec::generate(&s1, &s2, &examples, &options, module, &import, framework);
+                write(tests_path, tests)?;
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,1031 @@
+//! User-supplied property mappings. These pin down correspondences between
+//! source and target properties which are ambiguous or non-obvious, and are
+//! consulted by the searcher before any of its own heuristics.
+//!
+//! A mapping document is a JSON object from source paths to target paths,
+//! where paths are dot-separated property names:
+//!
+//! ```json
+//! {"customer.name": "client.fullName"}
+//! ```
+//!
+//! Pinning `customer.name` to `client.fullName` also pins `customer` to
+//! `client`. Paths pass through arrays transparently, so `orders.id` refers
+//! to the `id` of each item of an `orders` array.
+//!
+//! A target path may be deeper than its source path, in which case the extra
+//! target properties are objects built out of the source object's own
+//! properties: `{"address_city": "address.city"}` nests `address_city` into a
+//! new `address` object. The extra keys sit just before the last one, so
+//! `customer.address_city` to `client.address.city` pins `customer` to
+//! `client` and builds `address` inside it.
+//!
+//! A source path mapped to `null` is waived: strict searches may drop it
+//! without failing. Waiving `customer.legacyId` looks for `legacyId` in the
+//! target property `customer` is pinned to, pinning `customer` to a target
+//! property of the same name if it isn't pinned already.
+//!
+//! Several source properties of the same object can be concatenated into one
+//! target string by joining their paths with `+`. They're separated by a
+//! space, unless the target is given as an object naming the separator:
+//!
+//! ```json
+//! {
+//!     "customer.first_name + customer.last_name": "client.name",
+//!     "street + city": {"to": "address", "separator": ", "}
+//! }
+//! ```
+//!
+//! Arrays are concatenated the same way, into one target array (which
+//! leaves out duplicate items). Items count as duplicates if they're equal,
+//! unless the target object names the path in them to compare by as
+//! `unique`: `{"tags + labels": {"to": "tags", "unique": "id"}}`.
+//!
+//! Joining paths with `??` instead feeds the target from whichever of the
+//! source properties is present (and not null) first, as in
+//! `{"email ?? contact_email": "email"}`. Like concatenated properties, they
+//! have to share an object.
+//!
+//! A numeric target can also be given as an object with a `scale` and an
+//! `offset` to convert the source number with, as `source * scale + offset`,
+//! for when the units differ: `{"price_cents": {"to": "price", "scale":
+//! 0.01}}`. Either may be left out. Enumerated values are translated with
+//! an object of `values` instead, from source values (as strings) to target
+//! values: `{"status": {"to": "state", "values": {"ACTIVE": 1, "INACTIVE":
+//! 0}}}`, or converted to another case (`upper`, `lower`, `camel` or
+//! `snake`) with a `case`: `{"status": {"to": "state", "case": "lower"}}`.
+//! Part of a source string can be pulled out with a regular `pattern`
+//! (in JavaScript syntax) and the `group` of it to take, or the whole match
+//! if left out: `{"email": {"to": "domain", "pattern": "@(.+)$", "group":
+//! 1}}`. Arrays can be cut down to a `slice` of their items, from a start
+//! index up to (but not including) an end one, which may be left out or null
+//! to keep the rest: `{"tags": {"to": "top_tags", "slice": [0, 3]}}`. They
+//! can also be sorted by the value at a dot-separated path in their items,
+//! in `asc`ending (the default) or `desc`ending `order`, before any slicing:
+//! `{"events": {"to": "latest", "sort": "timestamp", "order": "desc",
+//! "slice": [0, 10]}}`. Duplicate items are left out first if `unique` is
+//! `true`, or the path to compare items by.
+//!
+//! Separately, a fill document maps target paths to values to fill them with
+//! when the source has nothing for them: `{"client.status": "active"}`.
+//! Like waivers, fills in nested objects pin the enclosing objects to source
+//! properties of the same name unless they're pinned already.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    sync::Arc,
+};
+
+use serde_json::Value;
+
+use crate::{
+    ir::{Case, SortOrder, SourcePath, ValueTable},
+    schema::{Literal, Real},
+};
+
+/// Error while reading a mapping document.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MappingErr {
+    NotObject,
+    TargetNotString(String),
+    /// A target property was pinned to a source property and also filled.
+    Filled(String),
+    /// Source paths can't be deeper than their target paths.
+    DepthMismatch(String, String),
+    /// A target property was pinned to two different source properties.
+    Conflict(String),
+    /// The sources of a concatenation aren't all properties of the same
+    /// object.
+    Scattered(String),
+    /// A case conversion names no case this knows of.
+    UnknownCase(String),
+    /// A sort names an order other than `asc` or `desc`.
+    UnknownOrder(String),
+}
+
+impl Display for MappingErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotObject => write!(f, "mapping must be an object of source to target paths"),
+            Self::TargetNotString(src) => {
+                write!(
+                    f,
+                    "target of `{}` must be a string, null or a concatenation",
+                    src
+                )
+            }
+            Self::DepthMismatch(src, dst) => {
+                write!(f, "`{}` can't be deeper than `{}`", src, dst)
+            }
+            Self::Conflict(dst) => write!(f, "`{}` is mapped from more than one place", dst),
+            Self::Filled(dst) => write!(f, "`{}` is both mapped and filled", dst),
+            Self::Scattered(src) => {
+                write!(f, "the sources of `{}` must share an object", src)
+            }
+            Self::UnknownCase(case) => write!(f, "unknown case `{}`", case),
+            Self::UnknownOrder(order) => write!(f, "unknown sort order `{}`", order),
+        }
+    }
+}
+
+/// A target string built by joining several source properties.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Concat {
+    pub sources: Vec<Arc<String>>,
+    pub separator: String,
+}
+
+/// A tree of pinned property correspondences, keyed by target property. A
+/// target property without a source is built from the enclosing source
+/// object, out of the properties pinned beneath it.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Mapping {
+    props: BTreeMap<String, (Option<Arc<String>>, Arc<Mapping>)>,
+    /// Source properties at this level which may be dropped.
+    waived: BTreeSet<String>,
+    /// Values for target properties at this level with no source.
+    fills: BTreeMap<String, Literal>,
+    /// Target properties at this level built by concatenation.
+    concats: BTreeMap<String, Concat>,
+    /// Target properties at this level fed from the first present of
+    /// several source properties.
+    coalesces: BTreeMap<String, Vec<Arc<String>>>,
+    /// Factors and offsets to convert the numbers pinned to target
+    /// properties at this level with.
+    scales: BTreeMap<String, (Real, Real)>,
+    /// Translations of the enumerated values pinned to target properties at
+    /// this level.
+    values: BTreeMap<String, ValueTable>,
+    /// Cases to convert the strings pinned to target properties at this
+    /// level to.
+    cases: BTreeMap<String, Case>,
+    /// Patterns and capture groups to extract from the strings pinned to
+    /// target properties at this level.
+    patterns: BTreeMap<String, (String, usize)>,
+    /// Start and end indices of the items to keep of the arrays pinned to
+    /// target properties at this level.
+    slices: BTreeMap<String, (usize, Option<usize>)>,
+    /// Paths in their items to sort the arrays pinned to target properties
+    /// at this level by, and which way.
+    sorts: BTreeMap<String, (SourcePath, SortOrder)>,
+    /// Paths in their items to tell duplicates in the arrays pinned to (or
+    /// concatenated into) target properties at this level apart by, empty
+    /// if duplicates are equal items.
+    uniques: BTreeMap<String, SourcePath>,
+}
+
+impl Mapping {
+    /// Whether this mapping pins nothing (though it may still waive
+    /// properties).
+    pub fn is_empty(&self) -> bool {
+        self.props.is_empty() && self.concats.is_empty() && self.coalesces.is_empty()
+    }
+
+    /// Whether the source property at `src` may be dropped.
+    pub fn is_waived(&self, src: &str) -> bool {
+        match src.split_once('.') {
+            None => self.waived.contains(src),
+            Some((key, rest)) => self.props.values().any(|(pinned, child)| {
+                pinned.as_deref().map(String::as_str) == Some(key) && child.is_waived(rest)
+            }),
+        }
+    }
+
+    /// The value to fill target property `key` with if nothing in the source
+    /// corresponds to it.
+    pub fn fill_for(&self, key: &str) -> Option<&Literal> {
+        self.fills.get(key)
+    }
+
+    /// The factor and offset to convert the number pinned to target property
+    /// `key` with, if it needs converting.
+    pub fn scale_for(&self, key: &str) -> Option<(Real, Real)> {
+        self.scales.get(key).copied()
+    }
+
+    /// Convert the number pinned to the target property at `dst` as
+    /// `value * factor + offset`.
+    pub fn scale(&mut self, dst: &str, factor: Real, offset: Real) {
+        let mut node = self;
+        let mut keys = dst.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                node.scales.insert(key.to_string(), (factor, offset));
+                return;
+            }
+            let (_, child) = node.props.entry(key.to_string()).or_default();
+            node = Arc::make_mut(child);
+        }
+    }
+
+    /// The table to translate the values pinned to target property `key`
+    /// with, if they need translating.
+    pub fn values_for(&self, key: &str) -> Option<&ValueTable> {
+        self.values.get(key)
+    }
+
+    /// Translate the values pinned to the target property at `dst` with
+    /// `table`.
+    pub fn map_values(&mut self, dst: &str, table: ValueTable) {
+        let mut node = self;
+        let mut keys = dst.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                node.values.insert(key.to_string(), table);
+                return;
+            }
+            let (_, child) = node.props.entry(key.to_string()).or_default();
+            node = Arc::make_mut(child);
+        }
+    }
+
+    /// The case to convert the string pinned to target property `key` to, if
+    /// it needs converting.
+    pub fn case_for(&self, key: &str) -> Option<Case> {
+        self.cases.get(key).copied()
+    }
+
+    /// Convert the string pinned to the target property at `dst` to `case`.
+    pub fn change_case(&mut self, dst: &str, case: Case) {
+        let mut node = self;
+        let mut keys = dst.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                node.cases.insert(key.to_string(), case);
+                return;
+            }
+            let (_, child) = node.props.entry(key.to_string()).or_default();
+            node = Arc::make_mut(child);
+        }
+    }
+
+    /// The pattern and capture group to extract from the string pinned to
+    /// target property `key`, if only part of it is wanted.
+    pub fn pattern_for(&self, key: &str) -> Option<(&str, usize)> {
+        self.patterns
+            .get(key)
+            .map(|(pattern, group)| (pattern.as_str(), *group))
+    }
+
+    /// Extract capture group `group` of `pattern` from the string pinned to
+    /// the target property at `dst`.
+    pub fn extract_pattern(&mut self, dst: &str, pattern: &str, group: usize) {
+        let mut node = self;
+        let mut keys = dst.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                node.patterns
+                    .insert(key.to_string(), (pattern.to_string(), group));
+                return;
+            }
+            let (_, child) = node.props.entry(key.to_string()).or_default();
+            node = Arc::make_mut(child);
+        }
+    }
+
+    /// The start and end indices of the items to keep of the array pinned to
+    /// target property `key`, if only some of them are wanted.
+    pub fn slice_for(&self, key: &str) -> Option<(usize, Option<usize>)> {
+        self.slices.get(key).copied()
+    }
+
+    /// Keep the items from `start` up to `end` of the array pinned to the
+    /// target property at `dst`, or all the rest if there's no `end`.
+    pub fn slice(&mut self, dst: &str, start: usize, end: Option<usize>) {
+        let mut node = self;
+        let mut keys = dst.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                node.slices.insert(key.to_string(), (start, end));
+                return;
+            }
+            let (_, child) = node.props.entry(key.to_string()).or_default();
+            node = Arc::make_mut(child);
+        }
+    }
+
+    /// The path in its items to sort the array pinned to target property
+    /// `key` by, and which way, if it's sorted.
+    pub fn sort_for(&self, key: &str) -> Option<(&[Arc<String>], SortOrder)> {
+        self.sorts
+            .get(key)
+            .map(|(path, order)| (path.as_slice(), *order))
+    }
+
+    /// Sort the array pinned to the target property at `dst` by the value at
+    /// dot-separated `path` in its items (the items themselves if it's empty).
+    pub fn sort(&mut self, dst: &str, path: &str, order: SortOrder) {
+        let path = path
+            .split('.')
+            .filter(|k| !k.is_empty())
+            .map(|k| Arc::new(k.to_string()))
+            .collect();
+        let mut node = self;
+        let mut keys = dst.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                node.sorts.insert(key.to_string(), (path, order));
+                return;
+            }
+            let (_, child) = node.props.entry(key.to_string()).or_default();
+            node = Arc::make_mut(child);
+        }
+    }
+
+    /// The path in its items to tell duplicates in the array pinned to
+    /// target property `key` apart by, if they're left out, which is empty
+    /// if they're equal items.
+    pub fn unique_for(&self, key: &str) -> Option<&[Arc<String>]> {
+        self.uniques.get(key).map(Vec::as_slice)
+    }
+
+    /// Leave out the items of the array pinned to the target property at
+    /// `dst` with the same value at dot-separated `path` as an earlier one,
+    /// or which are equal to an earlier one if it's empty.
+    pub fn dedupe(&mut self, dst: &str, path: &str) {
+        let path = path
+            .split('.')
+            .filter(|k| !k.is_empty())
+            .map(|k| Arc::new(k.to_string()))
+            .collect();
+        let mut node = self;
+        let mut keys = dst.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                node.uniques.insert(key.to_string(), path);
+                return;
+            }
+            let (_, child) = node.props.entry(key.to_string()).or_default();
+            node = Arc::make_mut(child);
+        }
+    }
+
+    /// How to build target property `key` by concatenation, if it is.
+    pub fn concat_for(&self, key: &str) -> Option<&Concat> {
+        self.concats.get(key)
+    }
+
+    /// Build the target property at `dst` by joining the source properties
+    /// at `srcs` with `separator`. The sources have to be properties of the
+    /// same object, which is pinned to the object `dst` is in.
+    pub fn concat(&mut self, srcs: &[&str], dst: &str, separator: &str) -> Result<(), MappingErr> {
+        let (node, key, sources) = self.siblings(srcs, " + ", dst)?;
+        node.concats.insert(
+            key,
+            Concat {
+                sources,
+                separator: separator.to_string(),
+            },
+        );
+        Ok(())
+    }
+
+    /// The source properties target property `key` is fed from the first
+    /// present of, if it is.
+    pub fn coalesce_for(&self, key: &str) -> Option<&[Arc<String>]> {
+        self.coalesces.get(key).map(Vec::as_slice)
+    }
+
+    /// Feed the target property at `dst` from the first present of the
+    /// source properties at `srcs`, which have to be properties of the same
+    /// object, like those of [`Mapping::concat`].
+    pub fn coalesce(&mut self, srcs: &[&str], dst: &str) -> Result<(), MappingErr> {
+        let (node, key, sources) = self.siblings(srcs, " ?? ", dst)?;
+        node.coalesces.insert(key, sources);
+        Ok(())
+    }
+
+    /// The level of the mapping for the object `dst` is in, pinned to the
+    /// object the source properties at `srcs` are in, along with the keys of
+    /// `dst` and the sources at that level. Fails if the sources don't share
+    /// an object or `dst` is already built some other way.
+    fn siblings(
+        &mut self,
+        srcs: &[&str],
+        joiner: &str,
+        dst: &str,
+    ) -> Result<(&mut Mapping, String, Vec<Arc<String>>), MappingErr> {
+        let joined = srcs.join(joiner);
+        let split = |path: &'_ str| match path.rsplit_once('.') {
+            Some((parent, key)) => (Some(parent.to_string()), key.to_string()),
+            None => (None, path.to_string()),
+        };
+        let (parent, _) = split(srcs.first().copied().unwrap_or_default());
+        let mut sources = vec![];
+        for src in srcs {
+            let (src_parent, key) = split(src);
+            if src_parent != parent {
+                return Err(MappingErr::Scattered(joined));
+            }
+            sources.push(Arc::new(key));
+        }
+        let (dst_parent, key) = split(dst);
+        let (src_keys, dst_keys) = (
+            parent.iter().flat_map(|p| p.split('.')).collect::<Vec<_>>(),
+            dst_parent
+                .iter()
+                .flat_map(|p| p.split('.'))
+                .collect::<Vec<_>>(),
+        );
+        if src_keys.len() != dst_keys.len() {
+            return Err(MappingErr::DepthMismatch(joined, dst.to_string()));
+        }
+
+        let mut node = self;
+        for (s, d) in src_keys.into_iter().zip(dst_keys) {
+            let (pinned, child) = node
+                .props
+                .entry(d.to_string())
+                .or_insert_with(|| (Some(Arc::new(s.to_string())), Default::default()));
+            if pinned.as_deref().map(String::as_str) != Some(s) {
+                return Err(MappingErr::Conflict(dst.to_string()));
+            }
+            node = Arc::make_mut(child);
+        }
+        if node.props.contains_key(&key)
+            || node.concats.contains_key(&key)
+            || node.coalesces.contains_key(&key)
+        {
+            return Err(MappingErr::Conflict(dst.to_string()));
+        }
+        Ok((node, key, sources))
+    }
+
+    /// Fill the target property at `dst` with `value`.
+    pub fn fill(&mut self, dst: &str, value: Literal) -> Result<(), MappingErr> {
+        let mut node = self;
+        let mut keys = dst.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                break;
+            }
+            let (_, child) = node
+                .props
+                .entry(key.to_string())
+                .or_insert_with(|| (Some(Arc::new(key.to_string())), Default::default()));
+            node = Arc::make_mut(child);
+        }
+        let key = dst.rsplit('.').next().unwrap_or(dst);
+        if node.props.contains_key(key)
+            || node.concats.contains_key(key)
+            || node.coalesces.contains_key(key)
+        {
+            return Err(MappingErr::Filled(dst.to_string()));
+        }
+        node.fills.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    /// Add the fills of a fill document, an object from target paths to
+    /// values.
+    pub fn add_fills(&mut self, json: &Value) -> Result<(), MappingErr> {
+        let obj = json.as_object().ok_or(MappingErr::NotObject)?;
+        for (dst, value) in obj.iter() {
+            self.fill(dst, Literal(value.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Allow the source property at `src` to be dropped.
+    pub fn waive(&mut self, src: &str) {
+        let mut node = self;
+        let mut keys = src.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                node.waived.insert(key.to_string());
+                return;
+            }
+            let pinned = node
+                .props
+                .iter()
+                .find(|(_, (pinned, _))| pinned.as_deref().map(String::as_str) == Some(key))
+                .map(|(dst, _)| dst.clone())
+                .unwrap_or_else(|| key.to_string());
+            let (_, child) = node
+                .props
+                .entry(pinned)
+                .or_insert_with(|| (Some(Arc::new(key.to_string())), Default::default()));
+            node = Arc::make_mut(child);
+        }
+    }
+
+    /// The source property pinned to target property `key` (or `None` if it
+    /// is built from the enclosing source object), along with the mapping for
+    /// the properties beneath it.
+    pub fn get(&self, key: &str) -> Option<&(Option<Arc<String>>, Arc<Mapping>)> {
+        self.props.get(key)
+    }
+
+    /// The source properties read by this level of the mapping, including
+    /// those read by target objects built from the enclosing source object.
+    pub fn sources(&self) -> Vec<Arc<String>> {
+        let mut sources = vec![];
+        for (src, child) in self.props.values() {
+            match src {
+                Some(src) => sources.push(src.clone()),
+                None => sources.extend(child.sources()),
+            }
+        }
+        for concat in self.concats.values() {
+            sources.extend(concat.sources.iter().cloned());
+        }
+        for coalesced in self.coalesces.values() {
+            sources.extend(coalesced.iter().cloned());
+        }
+        sources
+    }
+
+    /// The same correspondences pinned the other way around, for converting
+    /// target data back into source data. Target objects built from the
+    /// enclosing source object have no single property to map back to,
+    /// concatenations can't be split back up, coalesced properties have no
+    /// one source to go back to, extracted patterns and slices are only part
+    /// of their source, and case conversions and value
+    /// translations which send several values to the same one can't be
+    /// reversed, so they're left for the searcher to work out.
+    pub fn inverse(&self) -> Mapping {
+        let mut inverse = Mapping::default();
+        for (dst, (src, child)) in self.props.iter() {
+            if let Some(src) = src {
+                inverse.props.insert(
+                    src.to_string(),
+                    (Some(Arc::new(dst.clone())), Arc::new(child.inverse())),
+                );
+                if let Some((Real(factor), Real(offset))) = self.scale_for(dst) {
+                    let undo = (Real(1.0 / factor), Real(-offset / factor));
+                    inverse.scales.insert(src.to_string(), undo);
+                }
+                if let Some(table) = self.values_for(dst) {
+                    let undo: ValueTable =
+                        table.iter().map(|(k, v)| (v.clone(), k.clone())).collect();
+                    if undo.len() == table.len() {
+                        inverse.values.insert(src.to_string(), undo);
+                    }
+                }
+            }
+        }
+        inverse
+    }
+
+    /// Pin the source property at `src` to the target property at `dst`.
+    pub fn insert(&mut self, src: &str, dst: &str) -> Result<(), MappingErr> {
+        let mut src_keys: Vec<_> = src.split('.').map(Some).collect();
+        let dst_keys: Vec<_> = dst.split('.').collect();
+        if src_keys.len() > dst_keys.len() {
+            return Err(MappingErr::DepthMismatch(src.to_string(), dst.to_string()));
+        }
+        // extra target keys are built just above the last one
+        let last = src_keys.len() - 1;
+        let extra = dst_keys.len() - src_keys.len();
+        src_keys.splice(last..last, std::iter::repeat_n(None, extra));
+
+        let mut node = self;
+        for (s, d) in src_keys.into_iter().zip(dst_keys) {
+            let (pinned, child) = node
+                .props
+                .entry(d.to_string())
+                .or_insert_with(|| (s.map(|s| Arc::new(s.to_string())), Default::default()));
+            if pinned.as_deref().map(String::as_str) != s {
+                return Err(MappingErr::Conflict(dst.to_string()));
+            }
+            node = Arc::make_mut(child);
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&Value> for Mapping {
+    type Error = MappingErr;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let obj = value.as_object().ok_or(MappingErr::NotObject)?;
+        let mut mapping = Mapping::default();
+        let mut waived = vec![];
+        for (src, dst) in obj.iter() {
+            let not_string = || MappingErr::TargetNotString(src.clone());
+            if src.contains('+') {
+                let srcs: Vec<_> = src.split('+').map(str::trim).collect();
+                let (dst, separator, unique) = match dst {
+                    Value::String(dst) => (dst.as_str(), " ", None),
+                    Value::Object(concat) => (
+                        concat
+                            .get("to")
+                            .and_then(Value::as_str)
+                            .ok_or_else(not_string)?,
+                        match concat.get("separator") {
+                            None => " ",
+                            Some(sep) => sep.as_str().ok_or_else(not_string)?,
+                        },
+                        concat.get("unique"),
+                    ),
+                    _ => return Err(not_string()),
+                };
+                mapping.concat(&srcs, dst, separator)?;
+                if let Some(unique) = unique {
+                    mapping.dedupe(dst, unique.as_str().ok_or_else(not_string)?);
+                }
+                continue;
+            }
+            if src.contains("??") {
+                let srcs: Vec<_> = src.split("??").map(str::trim).collect();
+                let dst = dst.as_str().ok_or_else(not_string)?;
+                mapping.coalesce(&srcs, dst)?;
+                continue;
+            }
+            match dst {
+                Value::Null => waived.push(src),
+                Value::String(dst) => mapping.insert(src, dst)?,
+                Value::Object(converted) => {
+                    let dst = converted
+                        .get("to")
+                        .and_then(Value::as_str)
+                        .ok_or_else(not_string)?;
+                    mapping.insert(src, dst)?;
+                    if let Some(values) = converted.get("values") {
+                        let values = values.as_object().ok_or_else(not_string)?;
+                        let table = values
+                            .iter()
+                            .map(|(k, v)| (Literal(Value::from(k.as_str())), Literal(v.clone())))
+                            .collect();
+                        mapping.map_values(dst, table);
+                        continue;
+                    }
+                    if let Some(case) = converted.get("case") {
+                        let case = case.as_str().ok_or_else(not_string)?;
+                        let case = Case::from_name(case)
+                            .ok_or_else(|| MappingErr::UnknownCase(case.to_string()))?;
+                        mapping.change_case(dst, case);
+                        continue;
+                    }
+                    if let Some(pattern) = converted.get("pattern") {
+                        let pattern = pattern.as_str().ok_or_else(not_string)?;
+                        let group = match converted.get("group") {
+                            None => 0,
+                            Some(group) => group.as_u64().ok_or_else(not_string)? as usize,
+                        };
+                        mapping.extract_pattern(dst, pattern, group);
+                        continue;
+                    }
+                    // deduplicating, sorting and slicing go together
+                    let arranged = ["unique", "sort", "slice"]
+                        .iter()
+                        .any(|key| converted.contains_key(*key));
+                    match converted.get("unique") {
+                        None | Some(Value::Bool(false)) => {}
+                        Some(Value::Bool(true)) => mapping.dedupe(dst, ""),
+                        Some(Value::String(path)) => mapping.dedupe(dst, path),
+                        Some(_) => return Err(not_string()),
+                    }
+                    if let Some(by) = converted.get("sort") {
+                        let by = by.as_str().ok_or_else(not_string)?;
+                        let order = match converted.get("order") {
+                            None => SortOrder::Ascending,
+                            Some(order) => match order.as_str().ok_or_else(not_string)? {
+                                "asc" => SortOrder::Ascending,
+                                "desc" => SortOrder::Descending,
+                                other => return Err(MappingErr::UnknownOrder(other.to_string())),
+                            },
+                        };
+                        mapping.sort(dst, by, order);
+                    }
+                    if let Some(slice) = converted.get("slice") {
+                        let index =
+                            |i: &Value| i.as_u64().map(|i| i as usize).ok_or_else(not_string);
+                        let (start, end) = match slice.as_array().map(Vec::as_slice) {
+                            Some([start] | [start, Value::Null]) => (index(start)?, None),
+                            Some([start, end]) => (index(start)?, Some(index(end)?)),
+                            _ => return Err(not_string()),
+                        };
+                        mapping.slice(dst, start, end);
+                    }
+                    if arranged {
+                        continue;
+                    }
+                    let number = |key, default| match converted.get(key) {
+                        None => Ok(Real(default)),
+                        Some(n) => n.as_f64().map(Real).ok_or_else(not_string),
+                    };
+                    let (factor, offset) = (number("scale", 1.0)?, number("offset", 0.0)?);
+                    mapping.scale(dst, factor, offset);
+                }
+                _ => return Err(not_string()),
+            }
+        }
+        // waivers go last, so they find their way through the pins
+        for src in waived {
+            mapping.waive(src);
+        }
+        Ok(mapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_nested_mapping() {
+        let mapping = Mapping::try_from(&json!({
+            "customer.name": "client.fullName",
+            "customer.email": "client.contact"
+        }))
+        .unwrap();
+        let (src, client) = mapping.get("client").unwrap();
+        assert_eq!(src.as_deref().unwrap(), "customer");
+        assert_eq!(
+            client.get("fullName").unwrap().0.as_deref().unwrap(),
+            "name"
+        );
+        assert_eq!(
+            client.get("contact").unwrap().0.as_deref().unwrap(),
+            "email"
+        );
+        assert!(mapping.get("customer").is_none());
+    }
+
+    #[test]
+    fn test_inverse_mapping() {
+        let mapping = Mapping::try_from(&json!({
+            "customer.name": "client.fullName",
+            "town": "address.city"
+        }))
+        .unwrap();
+        assert_eq!(
+            mapping.inverse(),
+            Mapping::try_from(&json!({"client.fullName": "customer.name"})).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_conflicting_mapping() {
+        assert_eq!(
+            Mapping::try_from(&json!({"a.x": "c.x", "b.y": "c.y"})),
+            Err(MappingErr::Conflict("c.y".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_depth_mismatch() {
+        assert!(matches!(
+            Mapping::try_from(&json!({"a.b": "c"})),
+            Err(MappingErr::DepthMismatch(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_waiving() {
+        let mapping = Mapping::try_from(&json!({
+            "customer.name": "client.fullName",
+            "customer.legacyId": null,
+            "order.notes": null,
+            "audit": null
+        }))
+        .unwrap();
+        assert!(mapping.is_waived("audit"));
+        assert!(mapping.is_waived("customer.legacyId"));
+        assert!(mapping.is_waived("order.notes"));
+        assert!(!mapping.is_waived("customer.name"));
+        let (_, client) = mapping.get("client").unwrap();
+        assert!(client.is_waived("legacyId"));
+        assert_eq!(mapping.get("order").unwrap().0.as_deref().unwrap(), "order");
+    }
+
+    #[test]
+    fn test_fills() {
+        let mut mapping = Mapping::try_from(&json!({"customer.name": "client.fullName"})).unwrap();
+        mapping
+            .add_fills(&json!({"version": 2, "client.status": "active"}))
+            .unwrap();
+        assert_eq!(mapping.fill_for("version"), Some(&Literal(json!(2))));
+        let (_, client) = mapping.get("client").unwrap();
+        assert_eq!(client.fill_for("status"), Some(&Literal(json!("active"))));
+        assert_eq!(
+            mapping.add_fills(&json!({"client.fullName": "x"})),
+            Err(MappingErr::Filled("client.fullName".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_concatenation() {
+        let mapping = Mapping::try_from(&json!({
+            "customer.first_name + customer.last_name": "client.name",
+            "customer.street+customer.city": {"to": "client.address", "separator": ", "}
+        }))
+        .unwrap();
+        let (src, client) = mapping.get("client").unwrap();
+        assert_eq!(src.as_deref().unwrap(), "customer");
+        let name = client.concat_for("name").unwrap();
+        assert_eq!(
+            name.sources,
+            vec![
+                Arc::new("first_name".to_string()),
+                Arc::new("last_name".to_string())
+            ]
+        );
+        assert_eq!(name.separator, " ");
+        assert_eq!(client.concat_for("address").unwrap().separator, ", ");
+        assert!(!client.is_empty());
+
+        assert_eq!(
+            Mapping::try_from(&json!({"a.x + b.y": "c.z"})),
+            Err(MappingErr::Scattered("a.x + b.y".to_string()))
+        );
+        assert_eq!(
+            Mapping::try_from(&json!({"x + y": {"separator": ""}})),
+            Err(MappingErr::TargetNotString("x + y".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let mapping = Mapping::try_from(&json!({
+            "customer.email ?? customer.contact_email": "client.email"
+        }))
+        .unwrap();
+        let (src, client) = mapping.get("client").unwrap();
+        assert_eq!(src.as_deref().unwrap(), "customer");
+        assert_eq!(
+            client.coalesce_for("email"),
+            Some(
+                &[
+                    Arc::new("email".to_string()),
+                    Arc::new("contact_email".to_string())
+                ][..]
+            )
+        );
+        assert_eq!(
+            Mapping::try_from(&json!({"a ?? b": "x", "c + d": "x"})),
+            Err(MappingErr::Conflict("x".to_string()))
+        );
+        assert_eq!(
+            Mapping::try_from(&json!({"a.x ?? b.y": "z"})),
+            Err(MappingErr::Scattered("a.x ?? b.y".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_scaling() {
+        let mapping = Mapping::try_from(&json!({
+            "item.price_cents": {"to": "line.price", "scale": 0.01},
+            "temp": {"to": "temperature", "scale": 1.8, "offset": 32}
+        }))
+        .unwrap();
+        let (_, line) = mapping.get("line").unwrap();
+        assert_eq!(line.scale_for("price"), Some((Real(0.01), Real(0.0))));
+        assert_eq!(
+            mapping.scale_for("temperature"),
+            Some((Real(1.8), Real(32.0)))
+        );
+        assert_eq!(
+            Mapping::try_from(&json!({"a": {"to": "b", "scale": "x"}})),
+            Err(MappingErr::TargetNotString("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_value_translation() {
+        let mapping = Mapping::try_from(&json!({
+            "status": {"to": "state", "values": {"ACTIVE": 1, "INACTIVE": 0}},
+            "kind": {"to": "type", "values": {"a": "x", "b": "x"}}
+        }))
+        .unwrap();
+        let table = ValueTable::from([
+            (Literal(json!("ACTIVE")), Literal(json!(1))),
+            (Literal(json!("INACTIVE")), Literal(json!(0))),
+        ]);
+        assert_eq!(mapping.values_for("state"), Some(&table));
+        assert_eq!(mapping.scale_for("state"), None);
+
+        // only translations which can be undone are inverted
+        let inverse = mapping.inverse();
+        assert_eq!(
+            inverse.values_for("status"),
+            Some(&ValueTable::from([
+                (Literal(json!(0)), Literal(json!("INACTIVE"))),
+                (Literal(json!(1)), Literal(json!("ACTIVE"))),
+            ]))
+        );
+        assert_eq!(inverse.values_for("kind"), None);
+    }
+
+    #[test]
+    fn test_pattern_extraction() {
+        let mapping = Mapping::try_from(&json!({
+            "customer.email": {"to": "client.domain", "pattern": "@(.+)$", "group": 1},
+            "description": {"to": "order", "pattern": "#\\d+"}
+        }))
+        .unwrap();
+        let (_, client) = mapping.get("client").unwrap();
+        assert_eq!(client.pattern_for("domain"), Some(("@(.+)$", 1)));
+        assert_eq!(mapping.pattern_for("order"), Some(("#\\d+", 0)));
+        assert_eq!(
+            Mapping::try_from(&json!({"a": {"to": "b", "pattern": "x", "group": -1}})),
+            Err(MappingErr::TargetNotString("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_slicing() {
+        let mapping = Mapping::try_from(&json!({
+            "tags": {"to": "top_tags", "slice": [0, 3]},
+            "history": {"to": "older", "slice": [1, null]},
+            "log": {"to": "rest", "slice": [2]}
+        }))
+        .unwrap();
+        assert_eq!(mapping.slice_for("top_tags"), Some((0, Some(3))));
+        assert_eq!(mapping.slice_for("older"), Some((1, None)));
+        assert_eq!(mapping.slice_for("rest"), Some((2, None)));
+        assert_eq!(mapping.slice_for("tags"), None);
+        assert_eq!(
+            Mapping::try_from(&json!({"a": {"to": "b", "slice": []}})),
+            Err(MappingErr::TargetNotString("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_sorting() {
+        let mapping = Mapping::try_from(&json!({
+            "events": {"to": "latest", "sort": "meta.timestamp", "order": "desc", "slice": [0, 10]},
+            "tags": {"to": "tags", "sort": ""}
+        }))
+        .unwrap();
+        assert_eq!(
+            mapping.sort_for("latest"),
+            Some((
+                &[
+                    Arc::new("meta".to_string()),
+                    Arc::new("timestamp".to_string())
+                ][..],
+                SortOrder::Descending
+            ))
+        );
+        assert_eq!(mapping.slice_for("latest"), Some((0, Some(10))));
+        assert_eq!(
+            mapping.sort_for("tags"),
+            Some((&[][..], SortOrder::Ascending))
+        );
+        assert_eq!(
+            Mapping::try_from(&json!({"a": {"to": "b", "sort": "x", "order": "up"}})),
+            Err(MappingErr::UnknownOrder("up".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_deduplication() {
+        let mapping = Mapping::try_from(&json!({
+            "tags + labels": {"to": "tags", "unique": "id"},
+            "events": {"to": "events", "unique": true, "slice": [0, 5]}
+        }))
+        .unwrap();
+        assert_eq!(
+            mapping.unique_for("tags"),
+            Some(&[Arc::new("id".to_string())][..])
+        );
+        assert_eq!(mapping.unique_for("events"), Some(&[][..]));
+        assert_eq!(mapping.slice_for("events"), Some((0, Some(5))));
+        assert_eq!(
+            Mapping::try_from(&json!({"a": {"to": "b", "unique": 1}})),
+            Err(MappingErr::TargetNotString("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_case_conversion() {
+        let mapping = Mapping::try_from(&json!({
+            "status": {"to": "state", "case": "lower"}
+        }))
+        .unwrap();
+        assert_eq!(mapping.case_for("state"), Some(Case::Lower));
+        assert_eq!(mapping.inverse().case_for("status"), None);
+        assert_eq!(
+            Mapping::try_from(&json!({"a": {"to": "b", "case": "kebab"}})),
+            Err(MappingErr::UnknownCase("kebab".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_nesting_mapping() {
+        let mapping = Mapping::try_from(&json!({
+            "customer.address_city": "client.address.city",
+            "customer.address_zip": "client.address.zip"
+        }))
+        .unwrap();
+        let (_, client) = mapping.get("client").unwrap();
+        let (src, address) = client.get("address").unwrap();
+        assert_eq!(*src, None);
+        assert_eq!(
+            client.sources(),
+            vec![
+                Arc::new("address_city".to_string()),
+                Arc::new("address_zip".to_string())
+            ]
+        );
+        assert_eq!(
+            address.get("zip").unwrap().0.as_deref().unwrap(),
+            "address_zip"
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Matching of property names between source and target objects, for when
+//! the two sides of a migration don't spell their keys the same way.
+
+use std::{collections::BTreeSet, fmt::Display, sync::Arc};
+
+use derive_builder::Builder;
+use serde_json::Value;
+
+/// Extra score for a match between synonyms, on top of what an exact match
+/// would score, so that exact matches win when both are possible.
+const SYNONYM_PENALTY: u64 = 1;
+
+/// Synonym groups shipped by default, for fields which commonly go by
+/// different names in different organizations.
+const BUILTIN_SYNONYMS: &[&[&str]] = &[
+    &["amount", "total", "sum"],
+    &["zip", "zipcode", "postal_code", "postcode"],
+    &["id", "identifier", "key"],
+    &["email", "mail", "email_address"],
+    &["phone", "telephone", "phone_number", "tel"],
+    &["created", "created_at", "creation_date", "created_on"],
+    &[
+        "updated",
+        "updated_at",
+        "modified",
+        "modified_at",
+        "last_modified",
+    ],
+    &["description", "desc", "summary"],
+    &["quantity", "qty", "count"],
+    &["address", "addr"],
+    &["city", "town"],
+    &["country", "nation"],
+    &["price", "cost"],
+];
+
+/// Error while reading a synonym table.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SynonymErr {
+    /// The table wasn't an array of arrays of strings.
+    NotGroups,
+}
+
+impl Display for SynonymErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotGroups => write!(f, "synonyms must be an array of arrays of strings"),
+        }
+    }
+}
+
+/// Groups of words which mean the same thing, like `amount` and `total`.
+/// Words are compared the way [`KeyMatcher`] normalizes keys, so `postal_code`
+/// also covers `postalCode`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Synonyms {
+    groups: Vec<BTreeSet<String>>,
+}
+
+impl Synonyms {
+    /// The synonym table shipped with the transformer.
+    pub fn builtin() -> Self {
+        let mut synonyms = Self::default();
+        for group in BUILTIN_SYNONYMS {
+            synonyms.add_group(group.iter().copied());
+        }
+        synonyms
+    }
+
+    /// Declare all of `words` to be synonyms of each other.
+    pub fn add_group<'a>(&mut self, words: impl IntoIterator<Item = &'a str>) {
+        self.groups.push(words.into_iter().map(normalize).collect());
+    }
+
+    /// Whether `a` and `b` are listed as synonyms.
+    pub fn contains(&self, a: &str, b: &str) -> bool {
+        let (a, b) = (normalize(a), normalize(b));
+        self.groups
+            .iter()
+            .any(|group| group.contains(&a) && group.contains(&b))
+    }
+}
+
+impl TryFrom<&Value> for Synonyms {
+    type Error = SynonymErr;
+
+    /// Read a table of the form `[["amount", "total"], ["zip", "postcode"]]`.
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let mut synonyms = Self::default();
+        for group in value.as_array().ok_or(SynonymErr::NotGroups)? {
+            let words = group.as_array().ok_or(SynonymErr::NotGroups)?;
+            let words: Option<Vec<_>> = words.iter().map(Value::as_str).collect();
+            synonyms.add_group(words.ok_or(SynonymErr::NotGroups)?);
+        }
+        Ok(synonyms)
+    }
+}
+
+/// Lowercase `key` and strip its separators.
+fn normalize(key: &str) -> String {
+    key.chars()
+        .filter(|c| !matches!(c, '_' | '-' | ' '))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Split `key` into lowercase words at separators and camelCase humps.
+pub fn words(key: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut prev_lower = false;
+    for c in key.chars() {
+        let boundary = matches!(c, '_' | '-' | ' ') || (c.is_uppercase() && prev_lower);
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if !matches!(c, '_' | '-' | ' ') {
+            word.extend(c.to_lowercase());
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Whether `plural` is `singular` in the plural (`names` for `name`,
+/// `addresses` for `address`, `categories` for `category`), in whatever
+/// naming convention.
+pub fn is_plural(singular: &str, plural: &str) -> bool {
+    let (singular, plural) = (normalize(singular), normalize(plural));
+    plural == format!("{}s", singular)
+        || plural == format!("{}es", singular)
+        || singular
+            .strip_suffix('y')
+            .is_some_and(|stem| plural == format!("{}ies", stem))
+}
+
+/// Proposes renames between keys which differ only in naming convention
+/// (`user_id` vs. `userId`), by a few characters, or by using synonyms
+/// (`order_total` vs. `orderAmount`).
+#[derive(Builder, Clone, Debug, PartialEq, Eq)]
+#[builder(default)]
+pub struct KeyMatcher {
+    /// Compare keys ignoring case and `_`, `-` and space separators.
+    pub normalize: bool,
+    /// Largest edit distance (after normalization) at which keys still match.
+    pub max_distance: usize,
+    /// Words which are interchangeable in keys, either as the whole key or
+    /// word for word.
+    pub synonyms: Synonyms,
+}
+
+impl Default for KeyMatcher {
+    fn default() -> Self {
+        Self {
+            normalize: true,
+            max_distance: 0,
+            synonyms: Synonyms::builtin(),
+        }
+    }
+}
+
+impl KeyMatcher {
+    fn normalized(&self, key: &str) -> String {
+        if self.normalize {
+            normalize(key)
+        } else {
+            key.to_string()
+        }
+    }
+
+    /// Whether `from` and `to` are synonyms, either outright or word for word.
+    fn synonymous(&self, from: &str, to: &str) -> bool {
+        if self.synonyms.contains(from, to) {
+            return true;
+        }
+        let (from, to) = (words(from), words(to));
+        from.len() == to.len()
+            && from
+                .iter()
+                .zip(&to)
+                .all(|(a, b)| a == b || self.synonyms.contains(a, b))
+    }
+
+    /// Cost of renaming `from` to `to`, or `None` if they're too dissimilar.
+    /// Any rename costs at least 1, plus the edit distance between the keys;
+    /// keys which only match through synonyms cost a little more than that.
+    pub fn score(&self, from: &str, to: &str) -> Option<u64> {
+        let dist = levenshtein(&self.normalized(from), &self.normalized(to));
+        if dist <= self.max_distance {
+            Some(1 + dist as u64)
+        } else if self.synonymous(from, to) {
+            Some(1 + SYNONYM_PENALTY)
+        } else {
+            None
+        }
+    }
+
+    /// How sure we are that `from` and `to` name the same thing, from 0 (not
+    /// at all) to 1 (they only differ in naming convention).
+    pub fn confidence(&self, from: &str, to: &str) -> f64 {
+        self.score(from, to).map_or(0.0, |score| 1.0 / score as f64)
+    }
+
+    /// The best match for `target` among `candidates`, with its score. Ties go
+    /// to the earliest candidate.
+    pub fn best_match<'a>(
+        &self,
+        target: &str,
+        candidates: impl IntoIterator<Item = &'a Arc<String>>,
+    ) -> Option<(&'a Arc<String>, u64)> {
+        self.best_format_match(target, None, candidates.into_iter().map(|c| (c, None)))
+    }
+
+    /// Like [`KeyMatcher::best_match`], but with each key's `format`
+    /// annotation (`date-time`, `email`, ...) if it has one. Ties go to the
+    /// earliest candidate with the same format as `target`, so that out of
+    /// `created_on` and `created_by`, a `createdAt` date-time is matched with
+    /// the date. Formats never make a match on their own.
+    pub fn best_format_match<'a>(
+        &self,
+        target: &str,
+        format: Option<&str>,
+        candidates: impl IntoIterator<Item = (&'a Arc<String>, Option<&'a str>)>,
+    ) -> Option<(&'a Arc<String>, u64)> {
+        self.cheapest_format_match(target, format, candidates, |_, score| score)
+    }
+
+    /// Like [`KeyMatcher::best_format_match`], but ranking each candidate
+    /// by the `cost` of renaming it given its score, rather than by the
+    /// score alone.
+    pub fn cheapest_format_match<'a, C: Ord>(
+        &self,
+        target: &str,
+        format: Option<&str>,
+        candidates: impl IntoIterator<Item = (&'a Arc<String>, Option<&'a str>)>,
+        cost: impl Fn(&str, u64) -> C,
+    ) -> Option<(&'a Arc<String>, u64)> {
+        candidates
+            .into_iter()
+            .filter_map(|(c, f)| {
+                let mismatch = format.is_none() || f != format;
+                self.score(c, target).map(|score| (c, score, mismatch))
+            })
+            .min_by_key(|(c, score, mismatch)| (cost(c, *score), *mismatch))
+            .map(|(c, score, _)| (c, score))
+    }
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diag
+            } else {
+                1 + diag.min(above).min(row[j])
+            };
+            diag = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("same", "same"), 0);
+    }
+
+    #[test]
+    fn test_normalized_match() {
+        let matcher = KeyMatcher::default();
+        assert_eq!(matcher.score("user_id", "userId"), Some(1));
+        assert_eq!(matcher.score("User-Name", "user name"), Some(1));
+        assert_eq!(matcher.score("user_id", "userid2"), None);
+    }
+
+    #[test]
+    fn test_distance_threshold() {
+        let matcher = KeyMatcherBuilder::default()
+            .max_distance(1)
+            .build()
+            .unwrap();
+        assert_eq!(matcher.score("adress", "address"), Some(2));
+        assert_eq!(matcher.score("adrs", "address"), None);
+    }
+
+    #[test]
+    fn test_plurals() {
+        assert!(is_plural("name", "names"));
+        assert!(is_plural("address", "addresses"));
+        assert!(is_plural("category", "categories"));
+        assert!(is_plural("user_id", "userIds"));
+        assert!(!is_plural("name", "name"));
+        assert!(!is_plural("names", "name"));
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(words("orderTotal"), vec!["order", "total"]);
+        assert_eq!(words("postal_code"), vec!["postal", "code"]);
+        assert_eq!(words("HTTPStatus2"), vec!["httpstatus2"]);
+    }
+
+    #[test]
+    fn test_synonym_match() {
+        let matcher = KeyMatcher::default();
+        assert_eq!(matcher.score("zip", "postalCode"), Some(2));
+        assert_eq!(matcher.score("order_total", "orderAmount"), Some(2));
+        assert_eq!(matcher.score("order_total", "order_price"), None);
+        assert_eq!(matcher.confidence("user_id", "userId"), 1.0);
+        assert_eq!(matcher.confidence("zip", "postcode"), 0.5);
+
+        let synonyms = Synonyms::try_from(&serde_json::json!([["sku", "product_code"]])).unwrap();
+        let matcher = KeyMatcherBuilder::default()
+            .synonyms(synonyms)
+            .build()
+            .unwrap();
+        assert_eq!(matcher.score("sku", "productCode"), Some(2));
+        assert_eq!(matcher.score("zip", "postalCode"), None);
+        assert_eq!(
+            Synonyms::try_from(&serde_json::json!([["a", 1]])),
+            Err(SynonymErr::NotGroups)
+        );
+    }
+
+    #[test]
+    fn test_best_match() {
+        let matcher = KeyMatcherBuilder::default()
+            .max_distance(2)
+            .build()
+            .unwrap();
+        let candidates = [
+            Arc::new("fristName".to_string()),
+            Arc::new("first_name".to_string()),
+        ];
+        assert_eq!(
+            matcher.best_match("firstName", &candidates),
+            Some((&candidates[1], 1))
+        );
+    }
+
+    #[test]
+    fn test_format_tie_break() {
+        let matcher = KeyMatcherBuilder::default()
+            .max_distance(2)
+            .build()
+            .unwrap();
+        let (on, by) = (
+            Arc::new("created_on".to_string()),
+            Arc::new("created_by".to_string()),
+        );
+        let candidates = [(&by, None), (&on, Some("date-time"))];
+        assert_eq!(
+            matcher.best_format_match("createdAt", Some("date-time"), candidates),
+            Some((&on, 3))
+        );
+        assert_eq!(
+            matcher.best_format_match("createdAt", None, candidates),
+            Some((&by, 3))
+        );
+        // a closer name still beats a matching format
+        let candidates = [(&by, None), (&on, Some("date-time"))];
+        assert_eq!(
+            matcher.best_format_match("createdBy", Some("date-time"), candidates),
+            Some((&by, 1))
+        );
+    }
+}
@@ -0,0 +1,510 @@
+//! A backtracking matcher for the common part of JavaScript's regular
+//! expression syntax, for running `RegexExtract` instructions without a
+//! regex engine: literals, `.`, character classes and the `\d`, `\w` and
+//! `\s` escapes, anchors and word boundaries, capturing and non-capturing
+//! groups, alternation, and greedy and lazy quantifiers.
+//! Lookaround, backreferences and named groups aren't supported, and
+//! patterns using them fail to compile.
+//!
+//! The interpreter and the Rust backend both use it: the generated code
+//! carries a copy of it inside the [`crate::runtime`] it's bundled with.
+
+/// A part of a pattern.
+#[derive(Clone, Debug)]
+enum Node {
+    /// One character of the given class.
+    Char(Class),
+    Start,
+    End,
+    /// A word boundary, or with `false`, anything but one.
+    Boundary(bool),
+    /// A group, capturing into the given slot if it has one.
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+/// A set of characters.
+#[derive(Clone, Debug)]
+enum Class {
+    Is(char),
+    /// Anything but a line terminator, for `.`.
+    Any,
+    Digit,
+    Word,
+    Space,
+    Range(char, char),
+    Not(Box<Class>),
+    Either(Vec<Class>),
+}
+
+impl Class {
+    fn contains(&self, c: char) -> bool {
+        match self {
+            Class::Is(d) => c == *d,
+            Class::Any => !matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}'),
+            Class::Digit => c.is_ascii_digit(),
+            Class::Word => c.is_ascii_alphanumeric() || c == '_',
+            Class::Space => c.is_whitespace() || c == '\u{feff}',
+            Class::Range(lo, hi) => (*lo..=*hi).contains(&c),
+            Class::Not(class) => !class.contains(c),
+            Class::Either(classes) => classes.iter().any(|class| class.contains(c)),
+        }
+    }
+}
+
+/// A compiled regular expression.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+    /// Number of capturing groups, counting the whole match as group 0.
+    groups: usize,
+    /// Number of registers the program uses.
+    registers: usize,
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    groups: usize,
+}
+
+impl Parser<'_> {
+    fn alternation(&mut self) -> Option<Node> {
+        let mut branches = vec![self.concatenation()?];
+        while self.chars.next_if_eq(&'|').is_some() {
+            branches.push(self.concatenation()?);
+        }
+        Some(match branches.len() {
+            1 => branches.remove(0),
+            _ => Node::Alt(branches),
+        })
+    }
+
+    fn concatenation(&mut self) -> Option<Node> {
+        let mut nodes = vec![];
+        while let Some(&c) = self.chars.peek() {
+            if matches!(c, '|' | ')') {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Some(Node::Concat(nodes))
+    }
+
+    fn quantified(&mut self, node: Node) -> Option<Node> {
+        let (min, max) = match self.chars.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.chars.next();
+                let bounds = self.until('}')?;
+                let (min, max) = match bounds.split_once(',') {
+                    None => (bounds.as_str(), Some(bounds.as_str())),
+                    Some((min, "")) => (min, None),
+                    Some((min, max)) => (min, Some(max)),
+                };
+                let min = min.parse().ok()?;
+                let max = max.map(str::parse).transpose().ok()?;
+                if max.is_some_and(|max| max < min) {
+                    return None;
+                }
+                return Some(self.repeat(node, min, max));
+            }
+            _ => return Some(node),
+        };
+        self.chars.next();
+        Some(self.repeat(node, min, max))
+    }
+
+    fn repeat(&mut self, node: Node, min: usize, max: Option<usize>) -> Node {
+        let greedy = self.chars.next_if_eq(&'?').is_none();
+        Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        }
+    }
+
+    /// The characters up to `end`, which is skipped.
+    fn until(&mut self, end: char) -> Option<String> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next()? {
+                c if c == end => return Some(s),
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Option<Node> {
+        Some(match self.chars.next()? {
+            '^' => Node::Start,
+            '$' => Node::End,
+            '.' => Node::Char(Class::Any),
+            '(' => {
+                let slot = match self.chars.next_if_eq(&'?') {
+                    Some(_) => match self.chars.next()? {
+                        ':' => None,
+                        _ => return None,
+                    },
+                    None => {
+                        self.groups += 1;
+                        Some(self.groups)
+                    }
+                };
+                let inner = self.alternation()?;
+                self.chars.next_if_eq(&')')?;
+                Node::Group(Box::new(inner), slot)
+            }
+            '[' => Node::Char(self.class()?),
+            '\\' => match self.chars.next()? {
+                'b' => Node::Boundary(true),
+                'B' => Node::Boundary(false),
+                c => Node::Char(self.escape(c)?),
+            },
+            '*' | '+' | '?' | '{' | ')' => return None,
+            c => Node::Char(Class::Is(c)),
+        })
+    }
+
+    /// The class written `\c`.
+    fn escape(&mut self, c: char) -> Option<Class> {
+        Some(match c {
+            'd' => Class::Digit,
+            'D' => Class::Not(Box::new(Class::Digit)),
+            'w' => Class::Word,
+            'W' => Class::Not(Box::new(Class::Word)),
+            's' => Class::Space,
+            'S' => Class::Not(Box::new(Class::Space)),
+            'n' => Class::Is('\n'),
+            'r' => Class::Is('\r'),
+            't' => Class::Is('\t'),
+            // backreferences
+            '1'..='9' => return None,
+            c => Class::Is(c),
+        })
+    }
+
+    /// The rest of a bracketed class, after its `[`.
+    fn class(&mut self) -> Option<Class> {
+        let negated = self.chars.next_if_eq(&'^').is_some();
+        let mut classes = vec![];
+        loop {
+            let class = match self.chars.next()? {
+                ']' => break,
+                '\\' => {
+                    let c = self.chars.next()?;
+                    match c {
+                        'b' => Class::Is('\u{8}'),
+                        c => self.escape(c)?,
+                    }
+                }
+                c => Class::Is(c),
+            };
+            let class = match (class, self.chars.peek()) {
+                (Class::Is(lo), Some('-')) => {
+                    self.chars.next();
+                    match self.chars.next()? {
+                        ']' => {
+                            classes.extend([Class::Is(lo), Class::Is('-')]);
+                            break;
+                        }
+                        '\\' => {
+                            let c = self.chars.next()?;
+                            match self.escape(c)? {
+                                Class::Is(hi) => Class::Range(lo, hi),
+                                _ => return None,
+                            }
+                        }
+                        hi => Class::Range(lo, hi),
+                    }
+                }
+                (class, _) => class,
+            };
+            classes.push(class);
+        }
+        let class = Class::Either(classes);
+        Some(match negated {
+            true => Class::Not(Box::new(class)),
+            false => class,
+        })
+    }
+}
+
+/// An instruction of a compiled pattern, which is run against the input
+/// from a position, moving on to the next instruction if it succeeds.
+#[derive(Clone, Debug)]
+enum Inst {
+    /// Match one character of the given class.
+    Char(Class),
+    Start,
+    End,
+    Boundary(bool),
+    /// Carry on at the first instruction, and if that fails, at the second
+    /// from the same position.
+    Split(usize, usize),
+    Jump(usize),
+    /// Record the position in the given register.
+    Save(usize),
+    /// Fail unless the position has moved on since it was recorded in the
+    /// given register, so that a repeat matching nothing can't go on
+    /// forever.
+    Progress(usize),
+    Match,
+}
+
+/// A choice to go back to when matching fails.
+enum Backtrack {
+    /// Carry on at this instruction, from this position.
+    Branch(usize, usize),
+    /// Put this value back in this register.
+    Restore(usize, Option<usize>),
+}
+
+/// Compiles the nodes of a pattern into a program, where the registers
+/// start with a start and an end for each capture group.
+struct Compiler {
+    program: Vec<Inst>,
+    registers: usize,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+
+    /// Point the split at `at`, made as a placeholder, at the instruction
+    /// after it and at `skip`, in the order `greedy` says to try them.
+    fn patch(&mut self, at: usize, skip: usize, greedy: bool) {
+        self.program[at] = match greedy {
+            true => Inst::Split(at + 1, skip),
+            false => Inst::Split(skip, at + 1),
+        };
+    }
+
+    fn compile(&mut self, node: &Node) {
+        match node {
+            Node::Char(class) => {
+                self.push(Inst::Char(class.clone()));
+            }
+            Node::Start => {
+                self.push(Inst::Start);
+            }
+            Node::End => {
+                self.push(Inst::End);
+            }
+            Node::Boundary(at) => {
+                self.push(Inst::Boundary(*at));
+            }
+            Node::Group(inner, slot) => {
+                if let Some(slot) = slot {
+                    self.push(Inst::Save(slot * 2));
+                }
+                self.compile(inner);
+                if let Some(slot) = slot {
+                    self.push(Inst::Save(slot * 2 + 1));
+                }
+            }
+            Node::Concat(nodes) => nodes.iter().for_each(|node| self.compile(node)),
+            Node::Alt(branches) => {
+                let mut jumps = vec![];
+                for (i, branch) in branches.iter().enumerate() {
+                    let split = (i + 1 < branches.len()).then(|| self.push(Inst::Split(0, 0)));
+                    self.compile(branch);
+                    if let Some(split) = split {
+                        jumps.push(self.push(Inst::Jump(0)));
+                        self.program[split] = Inst::Split(split + 1, self.program.len());
+                    }
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node);
+                }
+                // past the minimum, each time round has to match something
+                let progress = self.registers;
+                self.registers += 1;
+                let mut splits = vec![];
+                let times = max.map_or(1, |max| max - min);
+                for _ in 0..times {
+                    splits.push(self.push(Inst::Split(0, 0)));
+                    self.push(Inst::Save(progress));
+                    self.compile(node);
+                    self.push(Inst::Progress(progress));
+                }
+                if max.is_none() {
+                    self.push(Inst::Jump(splits[0]));
+                }
+                let skip = self.program.len();
+                for split in splits {
+                    self.patch(split, skip, *greedy);
+                }
+            }
+        }
+    }
+}
+
+impl Regex {
+    /// `pattern` compiled, if it's one this supports.
+    pub fn new(pattern: &str) -> Option<Regex> {
+        let mut parser = Parser {
+            chars: pattern.chars().peekable(),
+            groups: 0,
+        };
+        let root = parser.alternation()?;
+        if parser.chars.next().is_some() {
+            return None;
+        }
+        let groups = parser.groups + 1;
+        let mut compiler = Compiler {
+            program: vec![],
+            registers: groups * 2,
+        };
+        compiler.compile(&Node::Group(Box::new(root), Some(0)));
+        compiler.push(Inst::Match);
+        Some(Regex {
+            program: compiler.program,
+            groups,
+            registers: compiler.registers,
+        })
+    }
+
+    /// The capture groups of the first match in `s`, like
+    /// `s.match(new RegExp(pattern))`, where group 0 is the whole match and
+    /// groups which didn't take part in it are `None`.
+    pub fn captures(&self, s: &str) -> Option<Vec<Option<String>>> {
+        let chars: Vec<char> = s.chars().collect();
+        let registers = (0..=chars.len()).find_map(|start| self.run(&chars, start))?;
+        let text = |i: usize| match (registers[i * 2], registers[i * 2 + 1]) {
+            (Some(start), Some(end)) => Some(chars[start..end].iter().collect()),
+            _ => None,
+        };
+        Some((0..self.groups).map(text).collect())
+    }
+
+    /// The registers once the program matches `chars` from `start`, if it
+    /// does. Choices are kept on a stack rather than in recursive calls, so
+    /// long inputs don't run out of call stack.
+    fn run(&self, chars: &[char], start: usize) -> Option<Vec<Option<usize>>> {
+        let word = |i: Option<usize>| {
+            i.and_then(|i| chars.get(i))
+                .is_some_and(|c| Class::Word.contains(*c))
+        };
+        let mut registers = vec![None; self.registers];
+        let mut stack = vec![];
+        let (mut pc, mut pos) = (0, start);
+        loop {
+            let matched = match &self.program[pc] {
+                Inst::Char(class) => {
+                    let matched = chars.get(pos).is_some_and(|c| class.contains(*c));
+                    pos += matched as usize;
+                    matched
+                }
+                Inst::Start => pos == 0,
+                Inst::End => pos == chars.len(),
+                Inst::Boundary(at) => (word(pos.checked_sub(1)) != word(Some(pos))) == *at,
+                Inst::Split(first, second) => {
+                    stack.push(Backtrack::Branch(*second, pos));
+                    pc = *first;
+                    continue;
+                }
+                Inst::Jump(to) => {
+                    pc = *to;
+                    continue;
+                }
+                Inst::Save(register) => {
+                    stack.push(Backtrack::Restore(*register, registers[*register]));
+                    registers[*register] = Some(pos);
+                    true
+                }
+                Inst::Progress(register) => registers[*register] != Some(pos),
+                Inst::Match => return Some(registers),
+            };
+            if matched {
+                pc += 1;
+                continue;
+            }
+            loop {
+                match stack.pop()? {
+                    Backtrack::Restore(register, value) => registers[register] = value,
+                    Backtrack::Branch(to, at) => {
+                        (pc, pos) = (to, at);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(pattern: &str, s: &str, group: usize) -> Option<String> {
+        Regex::new(pattern)
+            .expect("supported pattern")
+            .captures(s)?
+            .get(group)
+            .cloned()
+            .flatten()
+    }
+
+    #[test]
+    fn test_matching() {
+        assert_eq!(
+            group("@(.+)$", "me@example.com", 1).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(group("\\d+", "order 1234, 56", 0).as_deref(), Some("1234"));
+        assert_eq!(group("a.*?b", "aXbXb", 0).as_deref(), Some("aXb"));
+        assert_eq!(
+            group("^(\\w+)-(\\d{2,3})", "ab-1234", 2).as_deref(),
+            Some("123")
+        );
+        assert_eq!(group("(?:x|y)+(z)?", "--xyx", 0).as_deref(), Some("xyx"));
+        assert_eq!(group("(?:x|y)+(z)?", "--xyx", 1), None);
+        assert_eq!(group("[^a-c\\s]+", "abc def", 0).as_deref(), Some("def"));
+        assert_eq!(group("\\bcat\\b", "concat cat", 0).as_deref(), Some("cat"));
+        assert_eq!(group("(a*)*b", "aaab", 1).as_deref(), Some("aaa"));
+        assert_eq!(group("^x", "yx", 0), None);
+        assert_eq!(group("a{2,3}?", "aaaa", 0).as_deref(), Some("aa"));
+        assert_eq!(group("(a|ab)(c|bcd)(d*)", "abcd", 3).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_long_input() {
+        // repeats go round without using up the call stack
+        let s = "x".repeat(200_000);
+        assert_eq!(group("^(.*)$", &s, 1).map(|m| m.len()), Some(s.len()));
+        assert_eq!(group("(?:x|y)+?$", &s, 0).map(|m| m.len()), Some(s.len()));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(Regex::new("(?=a)").is_none());
+        assert!(Regex::new("(a)\\1").is_none());
+        assert!(Regex::new("a)").is_none());
+        assert!(Regex::new("*a").is_none());
+        assert!(Regex::new("a{3,1}").is_none());
+    }
+}
@@ -0,0 +1,763 @@
+//! Lossiness analysis of IR paths: which steps of a transform keep all of
+//! their input, which can lose information, and which throw data away.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use serde_json::Value;
+
+use crate::{
+    cost::{is_lossy, tier, Tier},
+    ir::{join_path, DateFormat, IR},
+    schema::{ExtNat, Schema},
+    searcher::{flat, grouped, unzipped, zipped, SearchConfig},
+};
+
+/// How much of its input a step preserves, from best to worst.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Lossiness {
+    /// The input can be recovered from the output.
+    Lossless,
+    /// Different inputs can produce the same output.
+    Lossy,
+    /// Some of the input is discarded entirely.
+    Dropping,
+}
+
+/// A single step of a transform and its classification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// Where in the source data the step reads from, as a dot-separated path
+    /// (with `[]` standing for the items of an array).
+    pub source: String,
+    /// Where in the target data the step writes to.
+    pub target: String,
+    /// The instruction responsible, or `None` for source properties which
+    /// no instruction reads at all.
+    pub op: Option<IR>,
+    /// Index in the path of the instruction the step was found at: the one
+    /// responsible, or the one leaving the unread properties behind.
+    pub index: usize,
+    pub lossiness: Lossiness,
+}
+
+/// Classification of every step of a transform.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathReport {
+    pub steps: Vec<Step>,
+    /// The least safe tier of any conversion in the transform.
+    pub tier: Tier,
+}
+
+impl PathReport {
+    /// The worst lossiness of any step.
+    pub fn lossiness(&self) -> Lossiness {
+        self.steps
+            .iter()
+            .map(|step| step.lossiness)
+            .max()
+            .unwrap_or(Lossiness::Lossless)
+    }
+
+    /// Source paths of all the data the transform discards.
+    pub fn dropped(&self) -> impl Iterator<Item = &str> {
+        self.steps
+            .iter()
+            .filter(|step| step.lossiness == Lossiness::Dropping)
+            .map(|step| step.source.as_str())
+    }
+}
+
+struct Frame {
+    /// Schema of the input at this point, if known.
+    schema: Option<Arc<Schema>>,
+    source: String,
+    target: String,
+    /// Properties of the input read by an object being built here.
+    read: Vec<Arc<String>>,
+}
+
+/// Classify each step of `ir`, which transforms data matching `source`.
+pub fn analyze(ir: &[IR], source: &Schema) -> PathReport {
+    classify(ir, source).0
+}
+
+/// The source and target paths each instruction of `ir` works at, as
+/// dot-separated keys with `[]` standing for the items of an array.
+pub fn places(ir: &[IR], source: &Schema) -> Vec<(String, String)> {
+    classify(ir, source).1
+}
+
+/// Like [`analyze`], along with the source and target paths each
+/// instruction of `ir` works at: those of the scope an instruction opens or
+/// closes, or of the input it leaves behind.
+fn classify(ir: &[IR], source: &Schema) -> (PathReport, Vec<(String, String)>) {
+    let mut places = vec![];
+    let mut steps = vec![];
+    let mut worst = Tier::Identity;
+    let mut frames = vec![Frame {
+        schema: Some(Arc::new(source.clone())),
+        source: String::new(),
+        target: String::new(),
+        read: vec![],
+    }];
+
+    for (index, op) in ir.iter().enumerate() {
+        let top = frames.last_mut().expect("unbalanced IR");
+        // annotations don't change the shape of the input
+        if let Some(Schema::Annotated(inner, _)) = top.schema.as_deref() {
+            top.schema = Some(inner.clone());
+        }
+        let before = (top.source.clone(), top.target.clone());
+        let step = |lossiness, source: &str, target: &str| Step {
+            source: source.to_string(),
+            target: target.to_string(),
+            op: Some(op.clone()),
+            index,
+            lossiness,
+        };
+
+        match op {
+            IR::Scale { .. } | IR::Base64(_) => {
+                worst = worst.max(Tier::Widening);
+                steps.push(step(Lossiness::Lossless, &top.source, &top.target));
+            }
+            IR::DateConvert { to_format, .. } => {
+                // anything finer than a second is lost to whole seconds
+                let lossiness = if *to_format == DateFormat::EpochSeconds {
+                    worst = worst.max(Tier::Narrowing);
+                    Lossiness::Lossy
+                } else {
+                    worst = worst.max(Tier::Widening);
+                    Lossiness::Lossless
+                };
+                steps.push(step(lossiness, &top.source, &top.target));
+            }
+            IR::RegexExtract(..) => {
+                // the rest of the string is thrown away
+                worst = worst.max(Tier::Narrowing);
+                steps.push(step(Lossiness::Lossy, &top.source, &top.target));
+            }
+            IR::CaseTransform(_) => {
+                // strings differing only in case end up the same
+                worst = worst.max(Tier::Narrowing);
+                steps.push(step(Lossiness::Lossy, &top.source, &top.target));
+            }
+            IR::MapValues(table) => {
+                // translating several values to the same one is narrowing
+                let mut targets: Vec<_> = table.values().collect();
+                targets.sort();
+                targets.dedup();
+                let lossiness = if targets.len() < table.len() {
+                    worst = worst.max(Tier::Narrowing);
+                    Lossiness::Lossy
+                } else {
+                    worst = worst.max(Tier::Widening);
+                    Lossiness::Lossless
+                };
+                steps.push(step(lossiness, &top.source, &top.target));
+            }
+            IR::Copy | IR::Wrap | IR::Inv => {
+                steps.push(step(Lossiness::Lossless, &top.source, &top.target));
+            }
+            IR::G2G(from, to) => {
+                worst = worst.max(tier(from, to));
+                let lossiness = if is_lossy(from, to) {
+                    Lossiness::Lossy
+                } else {
+                    Lossiness::Lossless
+                };
+                steps.push(step(lossiness, &top.source, &top.target));
+            }
+            IR::Join(_) | IR::First => {
+                worst = worst.max(Tier::Narrowing);
+                steps.push(step(Lossiness::Lossy, &top.source, &top.target));
+                if *op == IR::First {
+                    top.source = join_path(&top.source, "[0]");
+                    top.schema = match top.schema.as_deref() {
+                        Some(Schema::Arr(item)) => Some(item.clone()),
+                        _ => None,
+                    };
+                }
+            }
+            IR::Flatten(depth) => {
+                // which nested array each item came from is lost
+                worst = worst.max(Tier::Narrowing);
+                steps.push(step(Lossiness::Lossy, &top.source, &top.target));
+                top.schema = top
+                    .schema
+                    .as_deref()
+                    .and_then(|schema| flat(schema, *depth))
+                    .map(Arc::new);
+            }
+            IR::Slice(start, end) => {
+                // the items cut off either end are dropped
+                worst = worst.max(Tier::Narrowing);
+                if *start > 0 {
+                    let head = join_path(&top.source, &format!("[:{}]", start));
+                    steps.push(step(Lossiness::Dropping, &head, &top.target));
+                }
+                if let Some(end) = end {
+                    let tail = join_path(&top.source, &format!("[{}:]", end));
+                    steps.push(step(Lossiness::Dropping, &tail, &top.target));
+                }
+            }
+            IR::Sort(..) => {
+                // the items' original order is lost
+                worst = worst.max(Tier::Narrowing);
+                steps.push(step(Lossiness::Lossy, &top.source, &top.target));
+            }
+            IR::Dedupe(_) => {
+                // only the first of the items counted as the same is kept
+                worst = worst.max(Tier::Narrowing);
+                steps.push(step(Lossiness::Lossy, &top.source, &top.target));
+            }
+            IR::Gather(paths) => {
+                for path in paths {
+                    let source = path
+                        .iter()
+                        .fold(top.source.clone(), |s, k| join_path(&s, k));
+                    steps.push(step(Lossiness::Lossless, &source, &top.target));
+                }
+                top.schema = None;
+            }
+            IR::GroupBy(key) => {
+                // grouping keeps only the last of the items sharing a key
+                let lossiness = match top.schema.as_deref() {
+                    Some(Schema::Arr(_)) => Lossiness::Lossy,
+                    _ => Lossiness::Lossless,
+                };
+                if lossiness == Lossiness::Lossy {
+                    worst = worst.max(Tier::Narrowing);
+                }
+                steps.push(step(lossiness, &top.source, &top.target));
+                top.schema = top
+                    .schema
+                    .as_deref()
+                    .and_then(|schema| grouped(schema, key))
+                    .map(Arc::new);
+            }
+            IR::Zip(pairs) => {
+                // arrays left out of the zip are dropped
+                if let Some(Schema::Obj { props, .. }) = top.schema.as_deref() {
+                    for key in props.keys().filter(|k| !pairs.iter().any(|(a, _)| a == *k)) {
+                        let source = join_path(&top.source, key);
+                        steps.push(step(Lossiness::Dropping, &source, &top.target));
+                    }
+                }
+                top.schema = top
+                    .schema
+                    .as_deref()
+                    .and_then(|schema| zipped(schema, pairs))
+                    .map(Arc::new);
+            }
+            IR::Unzip(pairs) => {
+                // as are the items' properties left out of the unzip
+                if let Some(Schema::Arr(item)) = top.schema.as_deref() {
+                    if let Schema::Obj { props, .. } = item.bare() {
+                        for key in props.keys().filter(|k| !pairs.iter().any(|(_, p)| p == *k)) {
+                            let source = join_path(&join_path(&top.source, "[]"), key);
+                            steps.push(step(Lossiness::Dropping, &source, &top.target));
+                        }
+                    }
+                }
+                top.schema = top
+                    .schema
+                    .as_deref()
+                    .and_then(|schema| unzipped(schema, pairs))
+                    .map(Arc::new);
+            }
+            IR::PushObj => top.read.clear(),
+            // the earlier parts' reads count towards the merged object
+            IR::Merge => {}
+            IR::PopObj => {
+                if let Some(Schema::Obj { props, .. }) = top.schema.as_deref() {
+                    for key in props.keys().filter(|k| !top.read.contains(k)) {
+                        steps.push(Step {
+                            source: join_path(&top.source, key),
+                            target: top.target.clone(),
+                            op: None,
+                            index,
+                            lossiness: Lossiness::Dropping,
+                        });
+                    }
+                }
+            }
+            IR::PushKey(from) | IR::Rename(from, _) => {
+                let to = match op {
+                    IR::Rename(_, to) => to,
+                    _ => from,
+                };
+                if let IR::Rename(..) = op {
+                    steps.push(step(
+                        Lossiness::Lossless,
+                        &join_path(&top.source, from),
+                        &join_path(&top.target, to),
+                    ));
+                }
+                top.read.push(from.clone());
+                let frame = Frame {
+                    schema: match top.schema.as_deref() {
+                        Some(Schema::Obj { props, .. }) => props.get(from).cloned(),
+                        _ => None,
+                    },
+                    source: join_path(&top.source, from),
+                    target: join_path(&top.target, to),
+                    read: vec![],
+                };
+                frames.push(frame);
+            }
+            IR::Nest(key) => {
+                let frame = Frame {
+                    schema: top.schema.clone(),
+                    source: top.source.clone(),
+                    target: join_path(&top.target, key),
+                    read: vec![],
+                };
+                frames.push(frame);
+            }
+            IR::PushArr => {
+                let frame = Frame {
+                    schema: match top.schema.as_deref() {
+                        Some(Schema::Arr(item)) => Some(item.clone()),
+                        _ => None,
+                    },
+                    source: join_path(&top.source, "[]"),
+                    target: join_path(&top.target, "[]"),
+                    read: vec![],
+                };
+                frames.push(frame);
+            }
+            IR::Case(branch) => {
+                let frame = Frame {
+                    schema: Some(branch.clone()),
+                    source: top.source.clone(),
+                    target: top.target.clone(),
+                    read: vec![],
+                };
+                frames.push(frame);
+            }
+            IR::PushRest(_) => {
+                let frame = Frame {
+                    schema: match top.schema.as_deref() {
+                        Some(Schema::Obj { additional, .. }) => additional.clone(),
+                        _ => None,
+                    },
+                    source: join_path(&top.source, "*"),
+                    target: join_path(&top.target, "*"),
+                    read: vec![],
+                };
+                frames.push(frame);
+            }
+            IR::Rec(_) => {
+                let frame = Frame {
+                    schema: top
+                        .schema
+                        .as_deref()
+                        .map(|schema| Arc::new(schema.unfold())),
+                    source: top.source.clone(),
+                    target: top.target.clone(),
+                    read: vec![],
+                };
+                frames.push(frame);
+            }
+            IR::PopKey | IR::PopArr | IR::PopCase | IR::PopRest | IR::PopRec => {
+                frames.pop();
+            }
+            // what's read after it comes from the output, not the source
+            IR::Pipe => top.schema = None,
+            IR::Coalesce(paths) => {
+                // all but the first present value are ignored
+                worst = worst.max(Tier::Narrowing);
+                for path in paths {
+                    let source = path
+                        .iter()
+                        .fold(top.source.clone(), |s, k| join_path(&s, k));
+                    steps.push(step(Lossiness::Lossy, &source, &top.target));
+                }
+                top.schema = None;
+            }
+            IR::Concat(paths, _) => {
+                // joined strings can't always be told apart again
+                worst = worst.max(Tier::Narrowing);
+                for path in paths {
+                    let source = path
+                        .iter()
+                        .fold(top.source.clone(), |s, k| join_path(&s, k));
+                    steps.push(step(Lossiness::Lossy, &source, &top.target));
+                }
+            }
+            // filling in a value reads nothing, so loses nothing either
+            IR::Const(_) => worst = Tier::Fabricated,
+            // a part converted like the whole is reported with the whole
+            IR::PushOpt | IR::PopOpt | IR::PushUnion | IR::PopUnion | IR::Call(_) => {}
+            IR::Extr(key) => {
+                // everything but the extracted property is discarded
+                steps.push(step(Lossiness::Lossless, &top.source, &top.target));
+                if let Some(Schema::Obj { props, .. }) = top.schema.as_deref() {
+                    for sibling in props.keys().filter(|k| *k != key) {
+                        steps.push(Step {
+                            source: join_path(&top.source, sibling),
+                            target: top.target.clone(),
+                            op: None,
+                            index,
+                            lossiness: Lossiness::Dropping,
+                        });
+                    }
+                }
+                top.schema = match top.schema.as_deref() {
+                    Some(Schema::Obj { props, .. }) => props.get(key).cloned(),
+                    _ => None,
+                };
+                top.source = join_path(&top.source, key);
+            }
+        }
+        let top = frames.last().expect("unbalanced IR");
+        places.push(if op.is_closer() {
+            before
+        } else {
+            (top.source.clone(), top.target.clone())
+        });
+    }
+
+    // an object can be read in several pieces (when flattening it, or
+    // nesting parts of it), so anything another step went on to read wasn't
+    // dropped after all
+    let read: Vec<String> = steps
+        .iter()
+        .filter(|step| step.lossiness != Lossiness::Dropping)
+        .map(|step| step.source.clone())
+        .collect();
+    let mut dropped = HashSet::new();
+    steps.retain(|step| {
+        step.lossiness != Lossiness::Dropping
+            || (!read.iter().any(|source| {
+                source == &step.source || source.starts_with(&format!("{}.", step.source))
+            }) && dropped.insert(step.source.clone()))
+    });
+
+    (PathReport { steps, tier: worst }, places)
+}
+
+/// What one instruction of a transform does, for explaining the transform
+/// and tuning cost models against it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    pub op: IR,
+    /// The cost model's estimate of the instruction, including dropping the
+    /// properties it leaves unread. Scaffolding like scopes and copies costs
+    /// nothing.
+    pub cost: ExtNat,
+    /// The worst lossiness of the instruction's steps.
+    pub lossiness: Lossiness,
+    /// JSON Pointer to where in the source data the instruction works, with
+    /// `*` standing for every item of an array (and every other property of
+    /// an object).
+    pub source: String,
+    /// JSON Pointer to where in the target data the instruction works.
+    pub target: String,
+}
+
+impl Annotation {
+    /// The name of the instruction, like `Rename`.
+    pub fn kind(&self) -> String {
+        match Value::from(&self.op) {
+            Value::Object(map) => map.keys().next().cloned().unwrap_or_default(),
+            name => name.as_str().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// Totals over some annotated instructions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub count: usize,
+    pub cost: ExtNat,
+    /// The worst lossiness of any of the instructions.
+    pub lossiness: Lossiness,
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            cost: ExtNat::Nat(0),
+            lossiness: Lossiness::Lossless,
+        }
+    }
+}
+
+impl Summary {
+    fn add(&mut self, annotation: &Annotation) {
+        self.count += 1;
+        self.cost += annotation.cost;
+        self.lossiness = self.lossiness.max(annotation.lossiness);
+    }
+}
+
+/// Every instruction of a transform, annotated, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Annotations(pub Vec<Annotation>);
+
+impl Annotations {
+    /// Totals over the whole transform.
+    pub fn total(&self) -> Summary {
+        let mut total = Summary::default();
+        for annotation in &self.0 {
+            total.add(annotation);
+        }
+        total
+    }
+
+    /// Totals over the instructions grouped by `key`, such as
+    /// [`Annotation::kind`] or the target pointer.
+    pub fn group_by<K: Ord>(&self, key: impl Fn(&Annotation) -> K) -> BTreeMap<K, Summary> {
+        let mut groups: BTreeMap<K, Summary> = BTreeMap::new();
+        for annotation in &self.0 {
+            groups.entry(key(annotation)).or_default().add(annotation);
+        }
+        groups
+    }
+
+    /// The instructions which can lose information.
+    pub fn lossy(&self) -> impl Iterator<Item = &Annotation> {
+        self.0
+            .iter()
+            .filter(|annotation| annotation.lossiness > Lossiness::Lossless)
+    }
+}
+
+/// A dot-separated path as a JSON Pointer.
+fn pointer(path: &str) -> String {
+    if path.is_empty() {
+        return String::new();
+    }
+    path.split('.')
+        .map(|key| {
+            let key = match key {
+                "[]" => "*",
+                key => key
+                    .strip_prefix('[')
+                    .and_then(|key| key.strip_suffix(']'))
+                    .filter(|index| index.parse::<usize>().is_ok())
+                    .unwrap_or(key),
+            };
+            format!("/{}", key.replace('~', "~0").replace('/', "~1"))
+        })
+        .collect()
+}
+
+/// The last key of a dot-separated path.
+fn last(path: &str) -> &str {
+    path.rsplit('.').next().unwrap_or_default()
+}
+
+/// What the cost model `config` searches with charges for `op`, which
+/// writes to the target property `key`.
+fn cost(op: &IR, key: &str, config: &SearchConfig) -> ExtNat {
+    let costs = config.cost_model.as_ref();
+    match op {
+        IR::G2G(from, to) => costs.ground_to_ground(from, to),
+        IR::Rename(from, to) => {
+            let score = config
+                .key_matcher
+                .as_ref()
+                .and_then(|matcher| matcher.score(from, to))
+                .unwrap_or(1);
+            costs.rename(from, to, score)
+        }
+        IR::Nest(key) => costs.nest(key),
+        IR::Extr(key) => costs.extract(key),
+        IR::Coalesce(_) => costs.coalesce(key),
+        IR::Concat(_, _) => costs.concat(key),
+        IR::Const(_) => costs.fill(key),
+        IR::Scale { .. } => costs.scale(key),
+        IR::Base64(_) => costs.transcode(key),
+        IR::RegexExtract(_, _) => costs.extract_pattern(key),
+        IR::CaseTransform(_) => costs.change_case(key),
+        IR::DateConvert { .. } => costs.convert_date(key),
+        IR::MapValues(_) => costs.map_values(key),
+        IR::Slice(_, _) => costs.slice(key),
+        IR::Sort(_, _) => costs.sort(key),
+        IR::Dedupe(_) => costs.dedupe(key),
+        IR::Gather(_) => costs.gather(key),
+        IR::Wrap => costs.wrap(),
+        IR::First => costs.first(),
+        IR::Flatten(_) => costs.flatten(),
+        IR::Join(_) => costs.join(),
+        IR::Inv => costs.invert(),
+        IR::GroupBy(_) => costs.group_by(),
+        IR::Zip(_) | IR::Unzip(_) => costs.zip(),
+        IR::PushUnion => costs.dispatch(),
+        _ => ExtNat::Nat(0),
+    }
+}
+
+/// Annotate each instruction of `ir`, which transforms data matching
+/// `source`, with its cost under `config`'s cost model, how lossy it is and
+/// where it works.
+pub fn annotate(ir: &[IR], source: &Schema, config: &SearchConfig) -> Annotations {
+    let (report, places) = classify(ir, source);
+    let mut annotations: Vec<_> = ir
+        .iter()
+        .zip(places)
+        .map(|(op, (source, target))| Annotation {
+            op: op.clone(),
+            cost: cost(op, last(&target), config),
+            lossiness: Lossiness::Lossless,
+            source: pointer(&source),
+            target: pointer(&target),
+        })
+        .collect();
+    for step in &report.steps {
+        let annotation = &mut annotations[step.index];
+        annotation.lossiness = annotation.lossiness.max(step.lossiness);
+        if step.op.is_none() {
+            annotation.cost += config.cost_model.drop_field(last(&step.source));
+        }
+    }
+    Annotations(annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{schema, searcher::SchemaSearcher};
+
+    #[test]
+    fn test_lossless_report() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "n": {"type": "number"},
+                "tags": {"type": "array", "items": {"type": "boolean"}}
+            }
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "n": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            }
+        });
+        let ir = SchemaSearcher::new().find_path(&s1, &s2).unwrap();
+        let report = analyze(&ir, &s1);
+        assert_eq!(report.lossiness(), Lossiness::Lossless);
+        assert_eq!(
+            report
+                .steps
+                .iter()
+                .map(|s| (s.source.as_str(), s.target.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("n", "n"), ("tags.[]", "tags.[]")]
+        );
+    }
+
+    #[test]
+    fn test_dropped_fields() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "keep": {"type": "string"},
+                "secret": {"type": "string"},
+                "nested": {
+                    "type": "object",
+                    "properties": {
+                        "a": {"type": "number"},
+                        "b": {"type": "number"}
+                    }
+                }
+            },
+            "required": ["keep", "secret", "nested"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "keep": {"type": "string"},
+                "nested": {"type": "number"}
+            }
+        });
+        let (ir, report) = SchemaSearcher::new()
+            .find_path_with_report(&s1, &s2)
+            .unwrap();
+        assert_eq!(ir.len(), 9);
+        assert_eq!(report.lossiness(), Lossiness::Dropping);
+        assert_eq!(
+            report.dropped().collect::<Vec<_>>(),
+            vec!["nested.b", "secret"]
+        );
+    }
+
+    #[test]
+    fn test_sliced_items() {
+        let s1 = schema!({"type": "array", "items": {"type": "string"}});
+        let report = analyze(&[IR::Slice(1, Some(4)), IR::Copy], &s1);
+        assert_eq!(report.lossiness(), Lossiness::Dropping);
+        assert_eq!(report.dropped().collect::<Vec<_>>(), vec!["[:1]", "[4:]"]);
+        let report = analyze(&[IR::Slice(0, None), IR::Copy], &s1);
+        assert_eq!(report.lossiness(), Lossiness::Lossless);
+    }
+
+    #[test]
+    fn test_lossy_conversion() {
+        let s1 = schema!({"type": "array", "items": {"type": "string"}});
+        let s2 = schema!({"type": "number"});
+        let (_, report) = SchemaSearcher::new()
+            .find_path_with_report(&s1, &s2)
+            .unwrap();
+        assert_eq!(report.lossiness(), Lossiness::Lossy);
+        assert_eq!(report.tier, Tier::Narrowing);
+        assert_eq!(report.steps.len(), 2);
+        assert_eq!(report.steps[1].source, "[0]");
+    }
+
+    #[test]
+    fn test_annotations() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "user_id": {"type": "number"},
+                "secret": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "boolean"}}
+            },
+            "required": ["user_id", "secret", "tags"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "userId": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["userId", "tags"]
+        });
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        let annotations = annotate(&path.ir, &s1, &SearchConfig::default());
+        assert_eq!(annotations.0.len(), path.ir.len());
+        // the same costs the searcher found the path with
+        assert_eq!(annotations.total().cost, path.cost);
+        assert_eq!(annotations.total().lossiness, Lossiness::Dropping);
+
+        let rename = annotations
+            .0
+            .iter()
+            .find(|a| matches!(a.op, IR::Rename(..)))
+            .unwrap();
+        assert_eq!(
+            (rename.source.as_str(), rename.target.as_str()),
+            ("/user_id", "/userId")
+        );
+        let by_kind = annotations.group_by(Annotation::kind);
+        assert_eq!(by_kind["G2G"].count, 2);
+        assert_eq!(by_kind["G2G"].cost, ExtNat::Nat(2));
+        let by_target = annotations.group_by(|a| a.target.clone());
+        assert_eq!(by_target["/tags/*"].count, 3);
+        // the object leaving `secret` behind
+        assert_eq!(
+            annotations.lossy().map(|a| &a.op).collect::<Vec<_>>(),
+            vec![&IR::PopObj]
+        );
+        assert_eq!(pointer("a/b.[].[0].~"), "/a~1b/*/0/~0");
+    }
+}
@@ -0,0 +1,705 @@
+//! The runtime generated Rust transforms run on: JavaScript's conversions
+//! between values, and the operations instructions make on arrays and
+//! objects, so that they run the way the generated JavaScript runs them.
+//! `None` stands for `undefined`.
+//!
+//! The interpreter runs instructions on it too, and generated code carries
+//! a copy of it (along with [`crate::regex`]), so it depends on nothing but
+//! `std` and `serde_json`.
+
+use std::cmp::Ordering;
+
+use serde_json::{Map, Value};
+
+use crate::regex;
+
+/// `String(value)`.
+pub fn to_string(value: Option<&Value>) -> String {
+    match value {
+        None => "undefined".to_string(),
+        Some(Value::Null) => "null".to_string(),
+        Some(Value::Bool(b)) => b.to_string(),
+        Some(Value::Number(n)) => number_to_string(n.as_f64().unwrap_or(f64::NAN)),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::Null => String::new(),
+                item => to_string(Some(item)),
+            })
+            .collect::<Vec<_>>()
+            .join(","),
+        Some(Value::Object(_)) => "[object Object]".to_string(),
+    }
+}
+
+/// `String(n)` for a number.
+pub fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 {
+        "0".to_string()
+    } else if n.abs() >= 1e21 || n.abs() < 1e-6 {
+        // exponents are signed, and written without leading zeros
+        let exp = format!("{:e}", n);
+        match exp.split_once('e') {
+            Some((mantissa, exponent)) if !exponent.starts_with('-') => {
+                format!("{}e+{}", mantissa, exponent)
+            }
+            _ => exp,
+        }
+    } else {
+        n.to_string()
+    }
+}
+
+/// `Number(value)`.
+pub fn to_number(value: Option<&Value>) -> f64 {
+    match value {
+        None => f64::NAN,
+        Some(Value::Null) => 0.0,
+        Some(Value::Bool(b)) => f64::from(u8::from(*b)),
+        Some(Value::Number(n)) => n.as_f64().unwrap_or(f64::NAN),
+        Some(Value::String(s)) => string_to_number(s),
+        // through their string form, like `[5]` to `"5"` to 5
+        Some(value) => string_to_number(&to_string(Some(value))),
+    }
+}
+
+/// `Number(s)` for a string.
+pub fn string_to_number(s: &str) -> f64 {
+    let s = s.trim();
+    let radix = |prefix: &str, radix| {
+        let digits = s.strip_prefix(prefix)?;
+        u64::from_str_radix(digits, radix).ok().map(|n| n as f64)
+    };
+    if s.is_empty() {
+        return 0.0;
+    }
+    if let Some(n) = radix("0x", 16)
+        .or_else(|| radix("0X", 16))
+        .or_else(|| radix("0o", 8))
+        .or_else(|| radix("0O", 8))
+        .or_else(|| radix("0b", 2))
+        .or_else(|| radix("0B", 2))
+    {
+        return n;
+    }
+    match s.trim_start_matches(['+', '-']) {
+        "Infinity" if s.starts_with('-') => f64::NEG_INFINITY,
+        "Infinity" => f64::INFINITY,
+        // Rust reads `inf` and `nan`, which JavaScript doesn't
+        _ if !s
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-')) =>
+        {
+            f64::NAN
+        }
+        _ => s.parse().unwrap_or(f64::NAN),
+    }
+}
+
+/// `Boolean(value)`.
+pub fn truthy(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(b)) => *b,
+        Some(Value::Number(n)) => n.as_f64().is_some_and(|n| n != 0.0 && !n.is_nan()),
+        Some(Value::String(s)) => !s.is_empty(),
+        Some(Value::Array(_) | Value::Object(_)) => true,
+    }
+}
+
+/// A number as JSON, written as an integer if it's whole. `NaN` and the
+/// infinities become `null`, as `JSON.stringify` writes them.
+pub fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < 2f64.powi(53) {
+        Value::from(n as i64)
+    } else {
+        serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number)
+    }
+}
+
+/// `JSON.stringify(value)`, which writes whole numbers as integers.
+pub fn stringify(value: &Value) -> String {
+    fn whole(value: &Value) -> Value {
+        match value {
+            Value::Number(n) => n.as_f64().map_or(value.clone(), number),
+            Value::Array(items) => items.iter().map(whole).collect(),
+            Value::Object(props) => props.iter().map(|(k, v)| (k.clone(), whole(v))).collect(),
+            _ => value.clone(),
+        }
+    }
+    whole(value).to_string()
+}
+
+/// `a < b`.
+pub fn less(a: Option<&Value>, b: Option<&Value>) -> bool {
+    let primitive = |value: Option<&Value>| match value {
+        Some(value @ (Value::Array(_) | Value::Object(_))) => {
+            Some(Value::String(to_string(Some(value))))
+        }
+        value => value.cloned(),
+    };
+    match (primitive(a), primitive(b)) {
+        (Some(Value::String(a)), Some(Value::String(b))) => {
+            a.encode_utf16().cmp(b.encode_utf16()) == Ordering::Less
+        }
+        (a, b) => to_number(a.as_ref()) < to_number(b.as_ref()),
+    }
+}
+
+/// Whether `a` and `b` are the same key of a `Set` or `Map`. Objects and
+/// arrays are only ever the same as themselves, so never the same as a
+/// copy of either.
+pub fn same(a: Option<&Value>, b: Option<&Value>) -> bool {
+    match (a, b) {
+        (Some(Value::Number(a)), Some(Value::Number(b))) => a.as_f64() == b.as_f64(),
+        (Some(Value::Array(_) | Value::Object(_)), _) => false,
+        (a, b) => a == b,
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The UTF-8 bytes of `s`, base64 encoded.
+pub fn base64_encode(s: &str) -> String {
+    let mut out = String::new();
+    for chunk in s.as_bytes().chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, b)| bits | u32::from(*b) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(char::from(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize])),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+/// The UTF-8 string base64 encoded in `s`, with any invalid sequences
+/// replaced. Whitespace and padding are ignored, and any other character
+/// outside the base64 alphabet fails.
+pub fn base64_decode(s: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let (mut bits, mut len) = (0u32, 0);
+    for c in s.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let digit = BASE64.iter().position(|d| char::from(*d) == c)?;
+        bits = bits << 6 | digit as u32;
+        len += 6;
+        if len >= 8 {
+            len -= 8;
+            bytes.push((bits >> len) as u8);
+            bits &= (1 << len) - 1;
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The furthest from the epoch a JavaScript date can be, in milliseconds.
+const MAX_TIME: f64 = 8.64e15;
+
+/// Days since the epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date in the proleptic Gregorian calendar `days` since the epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Milliseconds since the epoch of an ISO 8601 date (`2024-01-31`) or
+/// date-time (`2024-01-31T12:00:00.000Z`). Date-times without an offset
+/// are taken to be UTC, where JavaScript would take them to be local time.
+fn parse_iso(s: &str) -> Option<f64> {
+    let number = |s: &str| match s.chars().all(|c| c.is_ascii_digit()) {
+        true => s.parse::<i64>().ok(),
+        false => None,
+    };
+    let (date, time) = match s.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let mut parts = date.split('-');
+    let year = parts.next().filter(|y| y.len() == 4).and_then(number)?;
+    let month = parts.next().map_or(Some(1), number)?;
+    let day = parts.next().map_or(Some(1), number)?;
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut millis = days_from_civil(year, month, day) * 86_400_000;
+    if let Some(time) = time {
+        let (time, offset) = match time.find(['Z', '+', '-']) {
+            Some(i) => time.split_at(i),
+            None => (time, ""),
+        };
+        let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+        let mut parts = time.split(':');
+        let hours = parts.next().and_then(number)?;
+        let minutes = parts.next().and_then(number)?;
+        let seconds = parts.next().map_or(Some(0), number)?;
+        if parts.next().is_some() || hours > 24 || minutes > 59 || seconds > 59 {
+            return None;
+        }
+        // only whole milliseconds count
+        number(fraction)?;
+        let fraction = number(&format!("{:0<3}", &fraction[..fraction.len().min(3)]))?;
+        millis += ((hours * 60 + minutes) * 60 + seconds) * 1000 + fraction;
+        let offset = match offset {
+            "" | "Z" => 0,
+            offset => {
+                let sign = if offset.starts_with('-') { -1 } else { 1 };
+                let digits = offset[1..].replace(':', "");
+                if digits.len() != 4 {
+                    return None;
+                }
+                let (hours, minutes) = (number(&digits[..2])?, number(&digits[2..])?);
+                sign * (hours * 60 + minutes) * 60_000
+            }
+        };
+        millis -= offset;
+    }
+    Some(millis as f64)
+}
+
+/// `new Date(millis).getTime()`: `millis` truncated to a whole number, or
+/// `NaN` if it's out of range.
+pub fn time(millis: f64) -> f64 {
+    match millis.abs() <= MAX_TIME {
+        true => millis.trunc(),
+        false => f64::NAN,
+    }
+}
+
+/// `new Date(value).getTime()`, for an ISO 8601 string or milliseconds
+/// since the epoch.
+pub fn date(value: Option<&Value>) -> f64 {
+    match value {
+        Some(Value::String(s)) => time(parse_iso(s).unwrap_or(f64::NAN)),
+        value => time(to_number(value)),
+    }
+}
+
+/// `new Date(time).toISOString()`, or nothing for an invalid date, where
+/// JavaScript would throw.
+pub fn to_iso(time: f64) -> Option<String> {
+    if time.is_nan() {
+        return None;
+    }
+    let time = time as i64;
+    let (days, millis) = (time.div_euclid(86_400_000), time.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    let year = match year {
+        0..=9999 => format!("{:04}", year),
+        year if year < 0 => format!("-{:06}", -year),
+        year => format!("+{:06}", year),
+    };
+    Some(format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    ))
+}
+
+/// The words of `s`, split at separators and where a lowercase letter or
+/// digit runs into an uppercase letter.
+fn words(s: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut prev_lower = false;
+    for c in s.chars() {
+        let separator = matches!(c, '_' | '-' | ' ');
+        if (separator || c.is_ascii_uppercase() && prev_lower) && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if !separator {
+            word.extend(c.to_lowercase());
+        }
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// `s` in camelCase.
+pub fn camel_case(s: &str) -> String {
+    let mut out = String::new();
+    for (i, word) in words(s).iter().enumerate() {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) if i > 0 => out.extend(first.to_uppercase().chain(chars)),
+            _ => out.push_str(word),
+        }
+    }
+    out
+}
+
+/// `s` in snake_case.
+pub fn snake_case(s: &str) -> String {
+    words(s).join("_")
+}
+
+/// Property `key` of `value`.
+pub fn get<'a>(value: Option<&'a Value>, key: &str) -> Option<&'a Value> {
+    match value? {
+        Value::Object(map) => map.get(key),
+        _ => None,
+    }
+}
+
+/// The value at `path` into `value`.
+pub fn get_path<'a>(value: Option<&'a Value>, path: &[&str]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(value?, |value, key| get(Some(value), key))
+}
+
+/// The items of `value`, if it's an array.
+pub fn items(value: Option<&Value>) -> &[Value] {
+    match value {
+        Some(Value::Array(items)) => items,
+        _ => &[],
+    }
+}
+
+/// Property `key` of the output written so far.
+pub fn field(output: &Option<Value>, key: &str) -> Option<Value> {
+    get(output.as_ref(), key).cloned()
+}
+
+/// Write property `key` of the output, leaving it out if it's `undefined`.
+/// Nothing is written unless the output is an object.
+pub fn put(output: &mut Option<Value>, key: &str, value: Option<Value>) {
+    if let Some(Value::Object(map)) = output {
+        match value {
+            Some(value) => map.insert(key.to_string(), value),
+            None => map.remove(key),
+        };
+    }
+}
+
+/// `value`'s own properties, spread into a new object.
+pub fn spread(value: Option<&Value>) -> Map<String, Value> {
+    match value {
+        Some(Value::Object(map)) => map.clone(),
+        Some(Value::Array(items)) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (i.to_string(), item.clone()))
+            .collect(),
+        Some(Value::String(s)) => s
+            .chars()
+            .enumerate()
+            .map(|(i, c)| (i.to_string(), Value::from(c.to_string())))
+            .collect(),
+        _ => Map::new(),
+    }
+}
+
+/// The value at the first of `paths` into `value` which is neither `null`
+/// nor `undefined`, or else the value at the last.
+pub fn coalesce<'a>(value: Option<&'a Value>, paths: &[&[&str]]) -> Option<&'a Value> {
+    let mut found = None;
+    for path in paths {
+        found = get_path(value, path);
+        if found.is_some_and(|found| !found.is_null()) {
+            break;
+        }
+    }
+    found
+}
+
+/// `value.flat(depth)`.
+pub fn flatten(value: Option<&Value>, depth: usize) -> Option<Value> {
+    fn flatten_into(items: &[Value], depth: usize, flat: &mut Vec<Value>) {
+        for item in items {
+            match item {
+                Value::Array(nested) if depth > 0 => flatten_into(nested, depth - 1, flat),
+                item => flat.push(item.clone()),
+            }
+        }
+    }
+    let mut flat = vec![];
+    flatten_into(items(value), depth, &mut flat);
+    value
+        .filter(|value| value.is_array())
+        .map(|_| Value::from(flat))
+}
+
+/// `value.slice(start, end)`.
+pub fn slice(value: Option<&Value>, start: usize, end: Option<usize>) -> Option<Value> {
+    let items = items(value);
+    let end = end.unwrap_or(items.len()).min(items.len());
+    let start = start.min(end);
+    value
+        .filter(|value| value.is_array())
+        .map(|_| Value::from(&items[start..end]))
+}
+
+/// `items`, stably sorted by `<` on the value at `path` in each.
+fn merge_sort(items: &[Value], path: &[&str], descending: bool) -> Vec<Value> {
+    if items.len() < 2 {
+        return items.to_vec();
+    }
+    let (left, right) = items.split_at(items.len() / 2);
+    let (left, right) = (
+        merge_sort(left, path, descending),
+        merge_sort(right, path, descending),
+    );
+    let key = |item: &Value| get_path(Some(item), path).cloned();
+    // whether the item on the right goes before the one on the left
+    let before = |right: &Value, left: &Value| match descending {
+        false => less(key(right).as_ref(), key(left).as_ref()),
+        true => less(key(left).as_ref(), key(right).as_ref()),
+    };
+    let mut sorted = Vec::with_capacity(items.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        let next = match before(r, l) {
+            true => right.next(),
+            false => left.next(),
+        };
+        sorted.extend(next);
+    }
+    sorted.extend(left.chain(right));
+    sorted
+}
+
+/// A copy of `value` sorted by the value at `path` in each item.
+pub fn sort(value: Option<&Value>, path: &[&str], descending: bool) -> Option<Value> {
+    value
+        .filter(|value| value.is_array())
+        .map(|_| Value::from(merge_sort(items(value), path, descending)))
+}
+
+/// The items of `value` without repeats, telling items apart by the value
+/// at `path`, or by their JSON if there's no path.
+pub fn dedupe(value: Option<&Value>, path: Option<&[&str]>) -> Option<Value> {
+    let mut seen: Vec<Option<Value>> = vec![];
+    let mut unique = vec![];
+    for item in items(value) {
+        let key = match path {
+            Some(path) => get_path(Some(item), path).cloned(),
+            None => Some(Value::from(stringify(item))),
+        };
+        if !seen.iter().any(|seen| same(seen.as_ref(), key.as_ref())) {
+            seen.push(key);
+            unique.push(item.clone());
+        }
+    }
+    value
+        .filter(|value| value.is_array())
+        .map(|_| Value::from(unique))
+}
+
+/// The items of the arrays at `paths` into `value`, one after another.
+pub fn gather(value: Option<&Value>, paths: &[&[&str]]) -> Option<Value> {
+    Some(Value::from(
+        paths
+            .iter()
+            .flat_map(|path| items(get_path(value, path)).to_vec())
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Objects made of the items at each index of the arrays at the `from`
+/// properties of `value`, written to the paired `to` properties.
+pub fn zip(value: Option<&Value>, pairs: &[(&str, &str)]) -> Option<Value> {
+    let arrays: Vec<_> = pairs
+        .iter()
+        .map(|(from, to)| (items(get(value, from)), to))
+        .collect();
+    let length = arrays
+        .iter()
+        .map(|(array, _)| array.len())
+        .max()
+        .unwrap_or(0);
+    let zipped = (0..length).map(|i| {
+        let fields = arrays.iter().filter_map(|(array, to)| {
+            let item = array.get(i)?;
+            Some((to.to_string(), item.clone()))
+        });
+        Value::Object(fields.collect())
+    });
+    Some(Value::from(zipped.collect::<Vec<_>>()))
+}
+
+/// An object of arrays, each `to` property holding the `from` property of
+/// every item of `value`.
+pub fn unzip(value: Option<&Value>, pairs: &[(&str, &str)]) -> Option<Value> {
+    let items = items(value);
+    let fields = pairs.iter().map(|(to, from)| {
+        let column = items
+            .iter()
+            .map(|item| get(Some(item), from).cloned().unwrap_or(Value::Null));
+        (to.to_string(), Value::from(column.collect::<Vec<_>>()))
+    });
+    Some(Value::Object(fields.collect()))
+}
+
+/// An object's `{key, value}` entries, or the object made of such entries.
+pub fn invert(value: Option<&Value>) -> Option<Value> {
+    match value? {
+        Value::Array(entries) => {
+            let mut map = Map::new();
+            for entry in entries {
+                let key = to_string(get(Some(entry), "key"));
+                match get(Some(entry), "value") {
+                    Some(value) => map.insert(key, value.clone()),
+                    None => map.remove(&key),
+                };
+            }
+            Some(Value::Object(map))
+        }
+        Value::Object(map) => Some(Value::from(
+            map.iter()
+                .map(|(key, value)| {
+                    let mut entry = Map::new();
+                    entry.insert("key".to_string(), Value::from(key.as_str()));
+                    entry.insert("value".to_string(), value.clone());
+                    Value::Object(entry)
+                })
+                .collect::<Vec<_>>(),
+        )),
+        _ => None,
+    }
+}
+
+/// The items of `value` keyed by their property `key`, or the items of such
+/// an object with their keys written back to `key`.
+pub fn group_by(value: Option<&Value>, key: &str) -> Option<Value> {
+    match value? {
+        Value::Array(items) => {
+            let mut map = Map::new();
+            for item in items {
+                map.insert(to_string(get(Some(item), key)), item.clone());
+            }
+            Some(Value::Object(map))
+        }
+        Value::Object(map) => Some(Value::from(
+            map.iter()
+                .map(|(group, item)| {
+                    let mut item = spread(Some(item));
+                    item.insert(key.to_string(), Value::from(group.as_str()));
+                    Value::Object(item)
+                })
+                .collect::<Vec<_>>(),
+        )),
+        _ => None,
+    }
+}
+
+/// What `table` maps `value` to.
+pub fn map_values(value: Option<&Value>, table: &[(Value, Value)]) -> Option<Value> {
+    table
+        .iter()
+        .find(|(from, _)| same(Some(from), value))
+        .map(|(_, to)| to.clone())
+}
+
+/// The values at `paths` into `value` which are neither `null` nor
+/// `undefined`, joined by `sep`.
+pub fn concat(value: Option<&Value>, paths: &[&[&str]], sep: &str) -> Value {
+    let values: Vec<_> = paths
+        .iter()
+        .filter_map(|path| get_path(value, path))
+        .filter(|value| !value.is_null())
+        .map(|value| to_string(Some(value)))
+        .collect();
+    Value::from(values.join(sep))
+}
+
+/// `value.join(sep)`, if `value` is an array.
+pub fn join(value: Option<&Value>, sep: &str) -> Option<Value> {
+    let items = value?.as_array()?;
+    let items: Vec<_> = items.iter().map(|item| to_string(Some(item))).collect();
+    Some(Value::from(items.join(sep)))
+}
+
+/// Capture `group` of the first match of `pattern` in `String(value)`.
+pub fn extract(value: Option<&Value>, pattern: &str, group: usize) -> Option<Value> {
+    regex::Regex::new(pattern)?
+        .captures(&to_string(value))?
+        .get(group)
+        .cloned()
+        .flatten()
+        .map(Value::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(to_string(Some(&json!(3.0))), "3");
+        assert_eq!(to_string(Some(&json!(0.5))), "0.5");
+        assert_eq!(to_string(Some(&json!(1e21))), "1e+21");
+        assert_eq!(to_string(Some(&json!([1, null, "a"]))), "1,,a");
+        assert_eq!(to_number(Some(&json!(" 12 "))), 12.0);
+        assert_eq!(to_number(Some(&json!(""))), 0.0);
+        assert_eq!(to_number(Some(&json!("0x10"))), 16.0);
+        assert_eq!(to_number(Some(&json!([7]))), 7.0);
+        assert!(to_number(Some(&json!("inf"))).is_nan());
+        assert!(to_number(None).is_nan());
+        assert_eq!(number(2.0), json!(2));
+        assert_eq!(number(f64::NAN), Value::Null);
+        assert!(less(Some(&json!("B")), Some(&json!("a"))));
+        assert!(less(Some(&json!(9)), Some(&json!("10"))));
+        assert!(!truthy(Some(&json!(""))));
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64_encode("hello"), "aGVsbG8=");
+        assert_eq!(base64_encode("hi!"), "aGkh");
+        assert_eq!(base64_decode("aGVsbG8="), Some("hello".to_string()));
+        assert_eq!(base64_decode("aGk h"), Some("hi!".to_string()));
+        assert_eq!(base64_decode("a*"), None);
+    }
+
+    #[test]
+    fn test_dates() {
+        let iso = |s: &str| date(Some(&json!(s)));
+        assert_eq!(iso("1970-01-02"), 86_400_000.0);
+        assert_eq!(iso("2024-02-29T12:30:15.250Z"), 1_709_209_815_250.0);
+        assert_eq!(iso("2024-02-29T14:30:15.250+02:00"), 1_709_209_815_250.0);
+        assert!(iso("yesterday").is_nan());
+        assert_eq!(
+            to_iso(1_709_209_815_250.0).as_deref(),
+            Some("2024-02-29T12:30:15.250Z")
+        );
+        assert_eq!(to_iso(-1.0).as_deref(), Some("1969-12-31T23:59:59.999Z"));
+        assert_eq!(date(Some(&json!(1500.5))), 1500.0);
+        assert_eq!(to_iso(f64::NAN), None);
+    }
+}
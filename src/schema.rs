@@ -1,33 +1,31 @@
+//! Our representation of JSON Schemas: the subset of the language the
+//! searcher works with, parsed from (and written back out as) JSON.
+
 use std::{
     cmp::Ordering,
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    hash::{Hash, Hasher},
     ops::{Add, AddAssign},
     sync::Arc,
 };
 
-use serde_json::Value;
+use serde_json::{json, Map, Value};
+
+use crate::frontend::json5::KeyOrders;
+
+pub mod check;
 
 /// Extended natural numbers (naturals plus infinity). Used for edit distances;
 /// Inf represents a path that doesn't exist. (i.e. all distances of sound
-/// transform paths are of finite length.)
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord)]
+/// transform paths are of finite length.) Ordered totally, with Inf above
+/// every Nat and equal to itself, so that costs can key sorts and heaps.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ExtNat {
     Nat(u64),
     Inf,
 }
 
-impl PartialOrd for ExtNat {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        use ExtNat::*;
-        match (self, other) {
-            (Inf, Inf) => None,
-            (Nat(_), Inf) => Some(Ordering::Less),
-            (Inf, Nat(_)) => Some(Ordering::Greater),
-            (Nat(x), Nat(y)) => <u64 as PartialOrd>::partial_cmp(x, y),
-        }
-    }
-}
-
 impl Add for ExtNat {
     type Output = Self;
 
@@ -60,6 +58,50 @@ pub enum SchemaErr {
     InvalidSchema,
     ArrNeedsItems,
     ObjNeedsProperties,
+    InvalidRequired,
+    /// A `oneOf` or `anyOf` which isn't a non-empty array of schemas.
+    InvalidUnion,
+    /// A `$ref` which isn't to a subschema of the same document.
+    UnresolvedRef(String),
+}
+
+impl Display for SchemaErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSchema => write!(f, "not a schema with a known `type`"),
+            Self::ArrNeedsItems => write!(f, "array schema without `items`"),
+            Self::ObjNeedsProperties => write!(f, "object schema without `properties`"),
+            Self::InvalidRequired => write!(f, "`required` isn't an array of property names"),
+            Self::InvalidUnion => {
+                write!(f, "`oneOf` or `anyOf` isn't a non-empty array of schemas")
+            }
+            Self::UnresolvedRef(reference) => write!(f, "can't resolve `$ref` {:?}", reference),
+        }
+    }
+}
+
+/// A JSON value appearing in a schema or a transform, such as a property's
+/// `default`. Hashed and ordered by its serialized form, which [`Value`]
+/// itself doesn't support.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Literal(pub Value);
+
+impl Hash for Literal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_string().hash(state)
+    }
+}
+
+impl PartialOrd for Literal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Literal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.to_string().cmp(&other.0.to_string())
+    }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -70,28 +112,207 @@ pub enum Ground {
     Null,
 }
 
+/// A number appearing in a transform, such as a unit conversion factor.
+/// Compared and hashed by its bits, which `f64` itself doesn't support.
+#[derive(Copy, Clone, Debug)]
+pub struct Real(pub f64);
+
+impl PartialEq for Real {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Real {}
+
+impl Hash for Real {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
 /// Top-level schema representation. Num, Bool, String, and Null represent
 /// schemas which match against those types of data. Arr and Obj are recursive
 /// schemas; Arr's subschema matches against the items in the list, and Obj is a
-/// map between the property names and their respective schemas. True and False
-/// are trivial schemas which always or never validate, respectively.
+/// map between the property names and their respective schemas, along with
+/// which of those properties are required to be present, the schema of any
+/// other properties when `additionalProperties` declares them, and whether it
+/// rules them out (with `false`, rather than being left out). Annotated is a
+/// schema along with the [`Annotations`] it was written with, which say more
+/// about its values than their shape does. Union matches data which matches
+/// any of its branches (`oneOf` and `anyOf` are treated alike).
+/// Rec is a schema which refers back to itself (through a `$ref` to a schema
+/// it's part of), where each Var of the same name inside it stands for the
+/// whole Rec again. True and False are trivial schemas which always or never
+/// validate, respectively.
+// schemas are shared behind `Arc`s, so the size of annotations isn't copied
+// around
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Schema {
     Ground(Ground),
     Arr(Arc<Schema>),
-    Obj(BTreeMap<Arc<String>, Arc<Schema>>),
+    Obj {
+        props: BTreeMap<Arc<String>, Arc<Schema>>,
+        required: BTreeSet<Arc<String>>,
+        additional: Option<Arc<Schema>>,
+        closed: bool,
+    },
+    Annotated(Arc<Schema>, Annotations),
+    Union(Vec<Arc<Schema>>),
+    Rec(Arc<String>, Arc<Schema>),
+    Var(Arc<String>),
     True,
     False,
 }
 
+/// What a schema says about its values beyond their shape, which decides how
+/// they're converted: the value to fill them in with when there's nothing
+/// else (its `const`, or failing that its `default`), their `format` (`int64`
+/// for integers bounded past what a double holds exactly) and `unit`, the
+/// values their `enum` restricts them to, their `contentEncoding`, and for
+/// arrays, their `maxItems` and whether they have `uniqueItems`.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Annotations {
+    pub default: Option<Literal>,
+    pub format: Option<Arc<String>>,
+    pub unit: Option<Arc<String>>,
+    pub values: Option<Vec<Literal>>,
+    pub encoding: Option<Arc<String>>,
+    pub max_items: Option<usize>,
+    pub unique_items: bool,
+}
+
+/// The annotations of a schema written without any.
+static UNANNOTATED: Annotations = Annotations {
+    default: None,
+    format: None,
+    unit: None,
+    values: None,
+    encoding: None,
+    max_items: None,
+    unique_items: false,
+};
+
+impl Annotations {
+    /// Read the annotations of `schema` (as raw JSON).
+    fn parse(schema: &Value) -> Self {
+        let string = |name| match schema.get(name) {
+            Some(Value::String(s)) => Some(Arc::new(s.clone())),
+            _ => None,
+        };
+        Annotations {
+            default: schema
+                .get("const")
+                .or_else(|| schema.get("default"))
+                .map(|default| Literal(default.clone())),
+            format: string("format")
+                .or_else(|| wide(schema).then(|| Arc::new("int64".to_string()))),
+            unit: string("unit"),
+            values: match schema.get("enum") {
+                Some(Value::Array(values)) => Some(values.iter().cloned().map(Literal).collect()),
+                _ => None,
+            },
+            encoding: string("contentEncoding"),
+            max_items: schema
+                .get("maxItems")
+                .and_then(Value::as_u64)
+                .map(|max| max as usize),
+            unique_items: schema.get("uniqueItems") == Some(&Value::Bool(true)),
+        }
+    }
+
+    /// Whether there are no annotations at all.
+    pub fn is_empty(&self) -> bool {
+        *self == UNANNOTATED
+    }
+
+    /// The annotations these and `other` agree on.
+    pub fn common(&self, other: &Annotations) -> Self {
+        fn same<T: Clone + PartialEq>(a: &Option<T>, b: &Option<T>) -> Option<T> {
+            if a == b {
+                a.clone()
+            } else {
+                None
+            }
+        }
+        Annotations {
+            default: same(&self.default, &other.default),
+            format: same(&self.format, &other.format),
+            unit: same(&self.unit, &other.unit),
+            values: same(&self.values, &other.values),
+            encoding: same(&self.encoding, &other.encoding),
+            max_items: same(&self.max_items, &other.max_items),
+            unique_items: self.unique_items && other.unique_items,
+        }
+    }
+
+    /// These annotations, with any `over` has taken their place.
+    fn overlaid(self, over: Annotations) -> Self {
+        Annotations {
+            default: over.default.or(self.default),
+            format: over.format.or(self.format),
+            unit: over.unit.or(self.unit),
+            values: over.values.or(self.values),
+            encoding: over.encoding.or(self.encoding),
+            max_items: over.max_items.or(self.max_items),
+            unique_items: over.unique_items || self.unique_items,
+        }
+    }
+
+    /// Write these annotations into `schema`, the JSON Schema of what
+    /// they're on.
+    fn write(&self, schema: &mut Map<String, Value>) {
+        let mut annotate = |name: &str, annotation: Option<Value>| {
+            if let Some(annotation) = annotation {
+                schema.insert(name.to_string(), annotation);
+            }
+        };
+        annotate("default", self.default.as_ref().map(|d| d.0.clone()));
+        annotate("format", self.format.as_ref().map(|f| json!(f.as_str())));
+        annotate("unit", self.unit.as_ref().map(|u| json!(u.as_str())));
+        annotate(
+            "enum",
+            self.values
+                .as_ref()
+                .map(|values| values.iter().map(|v| v.0.clone()).collect()),
+        );
+        annotate(
+            "contentEncoding",
+            self.encoding.as_ref().map(|e| json!(e.as_str())),
+        );
+        annotate("maxItems", self.max_items.map(|max| json!(max)));
+        annotate(
+            "uniqueItems",
+            self.unique_items.then_some(Value::Bool(true)),
+        );
+    }
+}
+
+/// The largest integer a double holds exactly, and every integer below.
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+/// Whether `schema` (as raw JSON) is of integers, with a `minimum` or a
+/// `maximum` past what a double holds exactly, which are taken to be 64-bit
+/// integers when they have no `format` saying otherwise.
+fn wide(schema: &Value) -> bool {
+    schema.get("type") == Some(&json!("integer"))
+        && ["minimum", "maximum"].iter().any(|bound| {
+            schema
+                .get(bound)
+                .and_then(Value::as_f64)
+                .is_some_and(|bound| bound.abs() > MAX_SAFE_INTEGER)
+        })
+}
+
 /// Create a [`Schema`] from raw JSON.
 #[macro_export]
 macro_rules! schema {
     ($($v:tt)?) => {
         {
             $(
-            let json_schema = serde_json::json!($v);
-            super::Schema::try_from(&json_schema).unwrap()
+            let json_schema = $crate::serde_json::json!($v);
+            $crate::schema::Schema::try_from(&json_schema).unwrap()
             )?
         }
     };
@@ -111,68 +332,588 @@ impl TryFrom<&Value> for Schema {
     type Error = SchemaErr;
 
     fn try_from(value: &Value) -> Result<Schema, Self::Error> {
+        Self::resolving(value, value)
+    }
+}
+
+impl Schema {
+    /// Parse `value`, part of the schema document `root`, which its local
+    /// `$ref`s (like `#/$defs/address`) point into.
+    pub fn resolving(value: &Value, root: &Value) -> Result<Schema, SchemaErr> {
+        let mut refs = Refs {
+            root,
+            seen: vec![],
+            names: HashMap::new(),
+        };
+        // the root can be referred back to from inside itself as `#`
+        match std::ptr::eq(value, root) {
+            true => refs.follow("#".to_string(), value),
+            false => refs.parse(value),
+        }
+    }
+
+    /// This schema with `annotations`, on top of any it has already. A schema
+    /// is only ever annotated once, and only with some annotations.
+    pub fn annotated(self, annotations: Annotations) -> Schema {
+        match self {
+            _ if annotations.is_empty() => self,
+            Schema::Annotated(inner, existing) => {
+                Schema::Annotated(inner, existing.overlaid(annotations))
+            }
+            _ => Schema::Annotated(Arc::new(self), annotations),
+        }
+    }
+
+    /// This schema without its annotations.
+    pub fn bare(&self) -> &Schema {
+        match self {
+            Schema::Annotated(inner, _) => inner,
+            _ => self,
+        }
+    }
+
+    /// This schema's annotations, which are empty if it has none.
+    pub fn annotations(&self) -> &Annotations {
+        match self {
+            Schema::Annotated(_, annotations) => annotations,
+            _ => &UNANNOTATED,
+        }
+    }
+
+    /// This schema with the annotations anywhere in it left out, for what
+    /// only cares about the shape of data.
+    pub fn stripped(&self) -> Schema {
+        let strip = |schema: &Arc<Schema>| Arc::new(schema.stripped());
+        match self {
+            Schema::Annotated(inner, _) => inner.stripped(),
+            Schema::Arr(item) => Schema::Arr(strip(item)),
+            Schema::Obj {
+                props,
+                required,
+                additional,
+                closed,
+            } => Schema::Obj {
+                props: props.iter().map(|(k, v)| (k.clone(), strip(v))).collect(),
+                required: required.clone(),
+                additional: additional.as_ref().map(strip),
+                closed: *closed,
+            },
+            Schema::Union(branches) => Schema::Union(branches.iter().map(strip).collect()),
+            Schema::Rec(name, body) => Schema::Rec(name.clone(), strip(body)),
+            _ => self.clone(),
+        }
+    }
+
+    /// The schema a [`Schema::Rec`] stands for, unrolled once: its body with
+    /// each [`Schema::Var`] referring to it replaced by the whole `Rec`
+    /// again. Any other schema is its own unfolding.
+    pub fn unfold(&self) -> Schema {
+        match self {
+            Schema::Rec(name, body) => body.substitute(name, self),
+            _ => self.clone(),
+        }
+    }
+
+    /// This schema with each free [`Schema::Var`] named `name` replaced by
+    /// `rec`.
+    fn substitute(&self, name: &str, rec: &Schema) -> Schema {
+        let sub = |schema: &Arc<Schema>| Arc::new(schema.substitute(name, rec));
+        match self {
+            Schema::Var(var) if var.as_str() == name => rec.clone(),
+            // an inner schema of the same name shadows the outer one
+            Schema::Rec(inner, _) if inner.as_str() == name => self.clone(),
+            Schema::Rec(inner, body) => Schema::Rec(inner.clone(), sub(body)),
+            Schema::Arr(item) => Schema::Arr(sub(item)),
+            Schema::Annotated(inner, annotations) => {
+                Schema::Annotated(sub(inner), annotations.clone())
+            }
+            Schema::Union(branches) => Schema::Union(branches.iter().map(sub).collect()),
+            Schema::Obj { .. } => {
+                let mut substituted = self.clone();
+                if let Schema::Obj {
+                    props, additional, ..
+                } = &mut substituted
+                {
+                    props.values_mut().for_each(|prop| *prop = sub(prop));
+                    *additional = additional.as_ref().map(sub);
+                }
+                substituted
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+/// The subschema a local `$ref` in `schema` points to in `root`, if it has
+/// one: either a JSON pointer (like `#/$defs/node`) or the name of an
+/// `$anchor` (like `#node`).
+fn target<'a>(root: &'a Value, schema: &Value) -> Option<&'a Value> {
+    let reference = schema.get("$ref")?.as_str()?.strip_prefix('#')?;
+    if reference.is_empty() || reference.starts_with('/') {
+        root.pointer(reference)
+    } else {
+        anchored(root, reference)
+    }
+}
+
+/// The subschema of `json` with `$anchor` `name`, if there is one.
+fn anchored<'a>(json: &'a Value, name: &str) -> Option<&'a Value> {
+    match json {
+        Value::Object(obj) if obj.get("$anchor").and_then(Value::as_str) == Some(name) => {
+            Some(json)
+        }
+        Value::Object(obj) => obj.values().find_map(|value| anchored(value, name)),
+        Value::Array(values) => values.iter().find_map(|value| anchored(value, name)),
+        _ => None,
+    }
+}
+
+/// Parses the schemas of a document, inlining what `$ref`s point to. A
+/// schema which refers back to itself has no finite tree to inline, so the
+/// references being followed are kept, and a reference back to one of them
+/// becomes a [`Schema::Var`] of the [`Schema::Rec`] it's followed into.
+struct Refs<'a> {
+    root: &'a Value,
+    /// The references being followed, and whether each has been referred
+    /// back to.
+    seen: Vec<(String, bool)>,
+    /// The name of each reference which has been referred back to.
+    names: HashMap<String, Arc<String>>,
+}
+
+impl<'a> Refs<'a> {
+    fn parse(&mut self, value: &'a Value) -> Result<Schema, SchemaErr> {
         use SchemaErr::*;
 
         match value {
             Value::Bool(b) => Ok(Schema::from(*b)),
             Value::Object(obj) => {
-                let ty = obj.get("type").ok_or(InvalidSchema)?;
-                if let Value::String(tyname) = ty {
-                    return match tyname.as_str() {
-                        "number" => Ok(Self::num()),
-                        "string" => Ok(Self::string()),
-                        "boolean" => Ok(Self::bool()),
-                        "null" => Ok(Self::null()),
-                        "array" => {
-                            return if let Some(item_type) = obj.get("items") {
-                                let item_type = Self::try_from(item_type)?;
-                                Ok(Schema::Arr(Arc::new(item_type)))
-                            } else {
-                                Err(ArrNeedsItems)
-                            }
+                if let Some(reference) = obj.get("$ref") {
+                    let reference = reference.as_str().ok_or(InvalidSchema)?;
+                    if let Some(name) = self.recur(reference) {
+                        return Ok(Schema::Var(name));
+                    }
+                    let target = target(self.root, value)
+                        .ok_or_else(|| UnresolvedRef(reference.to_string()))?;
+                    // annotations may be on a reference as well as on what
+                    // it refers to
+                    let schema = self.follow(reference.to_string(), target)?;
+                    return Ok(schema.annotated(Annotations::parse(value)));
+                }
+                // a schema with an anchor may be referred to by it from
+                // inside itself
+                if let Some(Value::String(anchor)) = obj.get("$anchor") {
+                    let reference = format!("#{anchor}");
+                    if !self.seen.iter().any(|(seen, _)| *seen == reference) {
+                        return self.follow(reference, value);
+                    }
+                }
+                Ok(self.shape(obj)?.annotated(Annotations::parse(value)))
+            }
+            _ => Err(InvalidSchema),
+        }
+    }
+
+    /// Parse the shape of the schema `obj`, leaving its annotations to
+    /// [`Refs::parse`].
+    fn shape(&mut self, obj: &'a Map<String, Value>) -> Result<Schema, SchemaErr> {
+        use SchemaErr::*;
+
+        // a constant needs no type, since it can only be one value
+        if let (Some(value), None) = (obj.get("const"), obj.get("type")) {
+            return Ok(crate::frontend::infer::from_instance(value));
+        }
+        // and likewise an enumeration, which can only be one of its
+        // values
+        if let (Some(Value::Array(values)), None) = (obj.get("enum"), obj.get("type")) {
+            return Ok(values
+                .iter()
+                .map(crate::frontend::infer::from_instance)
+                .fold(Schema::False, |s1, s2| {
+                    crate::frontend::infer::unify(&s1, &s2)
+                }));
+        }
+
+        if let Some(branches) = obj.get("oneOf").or_else(|| obj.get("anyOf")) {
+            let branches = branches.as_array().ok_or(InvalidUnion)?;
+            let mut union = vec![];
+            for branch in branches {
+                union.push(Arc::new(self.parse(branch)?));
+            }
+            return match union.len() {
+                0 => Err(InvalidUnion),
+                1 => Ok(union[0].as_ref().clone()),
+                _ => Ok(Schema::Union(union)),
+            };
+        }
+
+        let ty = obj.get("type").ok_or(InvalidSchema)?;
+        if let Value::String(tyname) = ty {
+            return match tyname.as_str() {
+                // integers are numbers which happen to be whole
+                "number" | "integer" => Ok(Schema::num()),
+                "string" => Ok(Schema::string()),
+                "boolean" => Ok(Schema::bool()),
+                "null" => Ok(Schema::null()),
+                "array" => {
+                    return if let Some(item_type) = obj.get("items") {
+                        let item_type = self.parse(item_type)?;
+                        Ok(Schema::Arr(Arc::new(item_type)))
+                    } else {
+                        Err(ArrNeedsItems)
+                    }
+                }
+                "object" => {
+                    let props = obj.get("properties");
+                    let mut subschemas = BTreeMap::new();
+                    if let Some(Value::Object(props)) = props {
+                        for (prop, subschema) in props.iter() {
+                            let prop = Arc::new(prop.clone());
+                            subschemas.insert(prop, Arc::new(self.parse(subschema)?));
                         }
-                        "object" => {
-                            let props = obj.get("properties");
-                            let mut subschemas = BTreeMap::new();
-                            if let Some(Value::Object(props)) = props {
-                                for (prop, subschema) in props.iter() {
-                                    subschemas.insert(
-                                        Arc::new(prop.clone()),
-                                        Arc::new(Self::try_from(subschema)?),
-                                    );
-                                }
-                                Ok(Schema::Obj(subschemas))
-                            } else {
-                                Err(ObjNeedsProperties)
-                            }
+                    } else {
+                        return Err(ObjNeedsProperties);
+                    }
+
+                    let mut required = BTreeSet::new();
+                    if let Some(names) = obj.get("required") {
+                        let names = names.as_array().ok_or(InvalidRequired)?;
+                        for name in names {
+                            let name = Arc::new(name.as_str().ok_or(InvalidRequired)?.to_string());
+                            // required properties without a schema
+                            // may be anything
+                            subschemas
+                                .entry(name.clone())
+                                .or_insert_with(|| Arc::new(Schema::True));
+                            required.insert(name);
                         }
-                        _ => Err(InvalidSchema),
+                    }
+
+                    // left out, additional properties are allowed
+                    // but unknown, so there's nothing to convert
+                    // them with
+                    let additional = match obj.get("additionalProperties") {
+                        None | Some(Value::Bool(false)) => None,
+                        Some(additional) => Some(Arc::new(self.parse(additional)?)),
                     };
+                    let closed = obj.get("additionalProperties") == Some(&Value::Bool(false));
+
+                    Ok(Schema::Obj {
+                        props: subschemas,
+                        required,
+                        additional,
+                        closed,
+                    })
                 }
-                Err(InvalidSchema)
+                _ => Err(InvalidSchema),
+            };
+        }
+        Err(InvalidSchema)
+    }
+
+    /// Parse `target`, which `reference` points to, as a [`Schema::Rec`] if
+    /// it refers back to itself.
+    fn follow(&mut self, reference: String, target: &'a Value) -> Result<Schema, SchemaErr> {
+        self.seen.push((reference, false));
+        let schema = self.parse(target);
+        let (reference, recursive) = self.seen.pop().expect("pushed above");
+        match (schema?, recursive) {
+            (schema, true) => Ok(Schema::Rec(
+                self.names[&reference].clone(),
+                Arc::new(schema),
+            )),
+            (schema, false) => Ok(schema),
+        }
+    }
+
+    /// The name of `reference` if it's being followed already, so that
+    /// it's referred back to. References are named after the last part of
+    /// their pointer (or their anchor), which is made unique.
+    fn recur(&mut self, reference: &str) -> Option<Arc<String>> {
+        let (_, recursive) = self.seen.iter_mut().find(|(seen, _)| seen == reference)?;
+        *recursive = true;
+        if let Some(name) = self.names.get(reference) {
+            return Some(name.clone());
+        }
+        let base = match reference.rsplit(['#', '/']).next() {
+            Some("") | None => "root",
+            Some(last) => last,
+        };
+        let mut name = base.to_string();
+        for i in 2.. {
+            if !self.names.values().any(|taken| **taken == name) {
+                break;
             }
-            _ => Err(InvalidSchema),
+            name = format!("{base}{i}");
+        }
+        let name = Arc::new(name);
+        self.names.insert(reference.to_string(), name.clone());
+        Some(name)
+    }
+}
+
+impl From<&Schema> for Value {
+    /// Write `schema` out as JSON Schema, which parses back into the same
+    /// [`Schema`].
+    fn from(schema: &Schema) -> Self {
+        match schema {
+            Schema::Ground(Ground::Num) => json!({"type": "number"}),
+            Schema::Ground(Ground::Bool) => json!({"type": "boolean"}),
+            Schema::Ground(Ground::String) => json!({"type": "string"}),
+            Schema::Ground(Ground::Null) => json!({"type": "null"}),
+            Schema::Arr(item) => json!({"type": "array", "items": Value::from(item.as_ref())}),
+            Schema::Obj {
+                props,
+                required,
+                additional,
+                closed,
+            } => {
+                let mut properties = Map::new();
+                for (key, value) in props {
+                    properties.insert(key.to_string(), Value::from(value.as_ref()));
+                }
+                let mut obj = Map::new();
+                obj.insert("type".to_string(), json!("object"));
+                obj.insert("properties".to_string(), Value::Object(properties));
+                if !required.is_empty() {
+                    let required = required.iter().map(|k| json!(k.as_str())).collect();
+                    obj.insert("required".to_string(), Value::Array(required));
+                }
+                if let Some(additional) = additional {
+                    obj.insert(
+                        "additionalProperties".to_string(),
+                        Value::from(additional.as_ref()),
+                    );
+                } else if *closed {
+                    obj.insert("additionalProperties".to_string(), Value::Bool(false));
+                }
+                Value::Object(obj)
+            }
+            Schema::Annotated(inner, annotations) => {
+                let mut value = Value::from(inner.as_ref());
+                if let Value::Object(obj) = &mut value {
+                    annotations.write(obj);
+                }
+                value
+            }
+            Schema::Union(branches) => {
+                let branches: Vec<_> = branches.iter().map(|b| Value::from(b.as_ref())).collect();
+                json!({ "anyOf": branches })
+            }
+            // with an anchor, for the schema's references to itself to name
+            Schema::Rec(name, body) => {
+                let mut value = Value::from(body.as_ref());
+                if let Value::Object(obj) = &mut value {
+                    obj.insert("$anchor".to_string(), json!(name.as_str()));
+                }
+                value
+            }
+            Schema::Var(name) => json!({ "$ref": format!("#{name}") }),
+            Schema::True => Value::Bool(true),
+            Schema::False => Value::Bool(false),
+        }
+    }
+}
+
+/// FNV-1a, which unlike the standard library's hasher is guaranteed to hash
+/// the same way in every build.
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+impl Schema {
+    /// A hash of this schema which stays the same from one run (and build)
+    /// to the next, for telling whether a stored transform was found for
+    /// it. It hashes the schema written out as compact JSON Schema, whose
+    /// keys are always in the same order, rather than the derived [`Hash`],
+    /// which may change along with the layout of [`Schema`] itself.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv(0xcbf29ce484222325);
+        hasher.write(Value::from(self).to_string().as_bytes());
+        hasher.finish()
+    }
+}
+
+/// The order properties were declared in, for each object schema in a
+/// document. [`Schema`] keeps properties sorted, so the order has to be
+/// recorded while parsing, and is looked up by the object schema itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PropOrder {
+    objects: HashMap<Schema, Vec<Arc<String>>>,
+}
+
+impl PropOrder {
+    /// Collect the declared property order of every object schema in `json`,
+    /// given the key order of each of its objects (see
+    /// [`crate::frontend::json5::from_str_ordered`]). Object schemas which
+    /// are identical but for their order take the first order found.
+    pub fn from_json(json: &Value, orders: &KeyOrders) -> Self {
+        let mut order = Self::default();
+        order.collect(json, json, &mut vec![], orders);
+        order
+    }
+
+    fn collect(&mut self, root: &Value, json: &Value, path: &mut Vec<String>, orders: &KeyOrders) {
+        let Value::Object(obj) = json else {
+            return;
+        };
+
+        if obj.get("properties").is_some_and(Value::is_object) {
+            path.push("properties".to_string());
+            let keys = orders.get(path);
+            if let (Some(keys), Ok(schema @ Schema::Obj { .. })) =
+                (keys, Schema::resolving(json, root))
+            {
+                let keys = keys.iter().map(|k| Arc::new(k.clone())).collect();
+                self.objects.entry(schema).or_insert(keys);
+            }
+            path.pop();
+        }
+
+        let mut descend = |order: &mut Self, keys: &[&str], json: &Value| {
+            path.extend(keys.iter().map(|k| k.to_string()));
+            order.collect(root, json, path, orders);
+            path.truncate(path.len() - keys.len());
+        };
+        if let Some(Value::Object(props)) = obj.get("properties") {
+            for (k, v) in props.iter() {
+                descend(self, &["properties", k], v);
+            }
+        }
+        if let Some(items) = obj.get("items") {
+            descend(self, &["items"], items);
+        }
+        for union in ["oneOf", "anyOf"] {
+            if let Some(Value::Array(branches)) = obj.get(union) {
+                for (i, branch) in branches.iter().enumerate() {
+                    descend(self, &[union, &i.to_string()], branch);
+                }
+            }
+        }
+        for defs in ["$defs", "definitions"] {
+            if let Some(Value::Object(defs_obj)) = obj.get(defs) {
+                for (k, v) in defs_obj.iter() {
+                    descend(self, &[defs, k], v);
+                }
+            }
+        }
+    }
+
+    /// The declared order of the properties of object schema `schema`.
+    pub fn get(&self, schema: &Schema) -> Option<&[Arc<String>]> {
+        self.objects.get(schema).map(Vec::as_slice)
+    }
+}
+
+/// A small change to a schema, for updating a transform without searching
+/// for it from scratch (see [`crate::searcher::IncrementalSearch`]). The
+/// subschema at `path` is replaced with `schema`, or removed from its object
+/// if `schema` is `None`. Paths are property names, with `[]` standing for
+/// the items of an array; a property which doesn't exist yet is added as an
+/// optional one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaEdit {
+    pub path: Vec<String>,
+    pub schema: Option<Schema>,
+}
+
+/// Error while applying a [`SchemaEdit`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum EditErr {
+    /// The path doesn't lead to a property or array items which can be
+    /// changed.
+    BadPath(String),
+}
+
+impl Display for EditErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadPath(path) => write!(f, "can't edit the schema at `{}`", path),
         }
     }
 }
 
 impl Schema {
-    fn num() -> Self {
+    pub fn num() -> Self {
         Self::Ground(Ground::Num)
     }
 
-    fn bool() -> Self {
+    pub fn bool() -> Self {
         Self::Ground(Ground::Bool)
     }
 
-    fn string() -> Self {
+    pub fn string() -> Self {
         Self::Ground(Ground::String)
     }
 
-    fn null() -> Self {
+    pub fn null() -> Self {
         Self::Ground(Ground::Null)
     }
 
+    /// An object schema whose properties are all required.
+    pub fn obj(props: BTreeMap<Arc<String>, Arc<Schema>>) -> Self {
+        let required = props.keys().cloned().collect();
+        Self::Obj {
+            props,
+            required,
+            additional: None,
+            closed: false,
+        }
+    }
+
+    /// This schema with `edit` applied.
+    pub fn edited(&self, edit: &SchemaEdit) -> Result<Schema, EditErr> {
+        let edited = match edit.path.is_empty() {
+            true => edit.schema.clone(),
+            false => self.edit_at(&edit.path, edit.schema.as_ref()),
+        };
+        edited.ok_or_else(|| EditErr::BadPath(edit.path.join(".")))
+    }
+
+    fn edit_at(&self, path: &[String], schema: Option<&Schema>) -> Option<Schema> {
+        let (key, rest) = path.split_first()?;
+        let mut edited = self.clone();
+        match &mut edited {
+            Schema::Arr(item) if key == "[]" => {
+                *item = Arc::new(match rest.is_empty() {
+                    true => schema?.clone(),
+                    false => item.edit_at(rest, schema)?,
+                });
+            }
+            Schema::Annotated(inner, _) => *inner = Arc::new(inner.edit_at(path, schema)?),
+            Schema::Obj {
+                props, required, ..
+            } => {
+                let key = Arc::new(key.clone());
+                match (rest.is_empty(), schema) {
+                    (false, _) => {
+                        let prop = props.get(&key)?.edit_at(rest, schema)?;
+                        props.insert(key, Arc::new(prop));
+                    }
+                    (true, Some(schema)) => {
+                        props.insert(key, Arc::new(schema.clone()));
+                    }
+                    (true, None) => {
+                        props.remove(&key)?;
+                        required.remove(&key);
+                    }
+                }
+            }
+            _ => return None,
+        }
+        Some(edited)
+    }
+
     pub fn edit_distance(&self, other: &Self) -> ExtNat {
         use ExtNat::*;
         use Schema::*;
@@ -181,12 +922,19 @@ impl Schema {
             return Nat(0);
         }
 
-        match (self, other) {
+        match (self.bare(), other.bare()) {
             // convert an array
             (Arr(s1), Arr(s2)) => s1.edit_distance(s2),
             // convert an object property-wise
-            (Obj(o1), Obj(o2)) => {
-                for k in o2.keys() {
+            (
+                Obj { props: o1, .. },
+                Obj {
+                    props: o2,
+                    required,
+                    ..
+                },
+            ) => {
+                for k in required.iter() {
                     if !o1.contains_key(k) {
                         return Inf;
                     }
@@ -199,15 +947,15 @@ impl Schema {
                         Some(v2) => dist += v1.edit_distance(v2),
                     }
                 }
-                return dist;
+                dist
             }
             // extract single property from object
-            (Obj(o1), v2) => {
-                return if o1.values().any(|v1| v1.as_ref() == v2) {
+            (Obj { props: o1, .. }, v2) => {
+                if o1.values().any(|v1| v1.as_ref() == v2) {
                     Nat(1)
                 } else {
                     Inf
-                };
+                }
             }
             (_, _) => Nat(1),
         }
@@ -216,10 +964,24 @@ impl Schema {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use super::ExtNat::*;
     use super::Schema;
-    use super::Schema::*;
-    use crate::schema;
+
+    #[test]
+    fn test_ext_nat_order() {
+        use std::cmp::Ordering;
+
+        assert!(Nat(1) < Nat(2));
+        assert!(Nat(u64::MAX) < Inf);
+        assert_eq!(Inf.cmp(&Inf), Ordering::Equal);
+        assert_eq!(Inf.partial_cmp(&Inf), Some(Ordering::Equal));
+        let mut costs = vec![Inf, Nat(3), Inf, Nat(0)];
+        costs.sort();
+        assert_eq!(costs, [Nat(0), Nat(3), Inf, Inf]);
+        assert_eq!(Nat(1) + Inf, Inf);
+    }
 
     #[test]
     fn test_same_base_type_edit_dist() {
@@ -279,10 +1041,272 @@ mod tests {
         assert_eq!(v1.edit_distance(&v2), Nat(2))
     }
 
-    // change path to wherever your project is located
+    #[test]
+    fn test_parse_union() {
+        let union = schema!({
+            "oneOf": [
+                {"type": "string"},
+                {"type": "array", "items": {"type": "string"}}
+            ]
+        });
+        assert_eq!(
+            union,
+            Schema::Union(vec![
+                Arc::new(Schema::string()),
+                Arc::new(Schema::Arr(Arc::new(Schema::string())))
+            ])
+        );
+        assert_eq!(schema!({"anyOf": [{"type": "null"}]}), Schema::null());
+        assert!(matches!(
+            Schema::try_from(&serde_json::json!({"anyOf": []})),
+            Err(super::SchemaErr::InvalidUnion)
+        ));
+    }
+
+    #[test]
+    fn test_parse_refs() {
+        let schema = schema!({
+            "type": "object",
+            "properties": {
+                "billing": {"$ref": "#/$defs/address"},
+                "shipping": {"$ref": "#/$defs/address"},
+                "placed": {"$ref": "#/definitions/date"}
+            },
+            "$defs": {
+                "address": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                }
+            },
+            "definitions": {"date": {"type": "string", "format": "date"}}
+        });
+        let address = schema!({
+            "type": "object",
+            "properties": {"city": {"type": "string"}},
+            "required": ["city"]
+        });
+        let Schema::Obj { props, .. } = &schema else {
+            panic!("expected an object, got {:?}", schema);
+        };
+        assert_eq!(props[&Arc::new("billing".to_string())].as_ref(), &address);
+        assert_eq!(props[&Arc::new("shipping".to_string())].as_ref(), &address);
+        let placed = &props[&Arc::new("placed".to_string())];
+        assert_eq!(placed.annotations().format.as_deref().unwrap(), "date");
+
+        assert!(matches!(
+            Schema::try_from(&serde_json::json!({"$ref": "other.json#/$defs/a"})),
+            Err(super::SchemaErr::UnresolvedRef(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_recursive_refs() {
+        let tree = schema!({
+            "type": "object",
+            "properties": {"children": {"type": "array", "items": {"$ref": "#"}}}
+        });
+        let Schema::Rec(name, body) = &tree else {
+            panic!("expected a recursive schema, got {:?}", tree);
+        };
+        assert_eq!(name.as_str(), "root");
+        let children = |schema: &Schema| match schema {
+            Schema::Obj { props, .. } => props[&Arc::new("children".to_string())].clone(),
+            _ => panic!("expected an object, got {:?}", schema),
+        };
+        assert_eq!(
+            children(body).as_ref(),
+            &Schema::Arr(Arc::new(Schema::Var(name.clone())))
+        );
+        // unrolling puts the whole tree back where it was referred to
+        assert_eq!(
+            children(&tree.unfold()).as_ref(),
+            &Schema::Arr(Arc::new(tree.clone()))
+        );
+        assert_eq!(
+            Schema::try_from(&serde_json::Value::from(&tree)).unwrap(),
+            tree
+        );
+
+        // references to a definition, and to an anchor, recur from there
+        let list = schema!({
+            "type": "object",
+            "properties": {"head": {"$ref": "#/$defs/node"}},
+            "$defs": {
+                "node": {
+                    "$anchor": "item",
+                    "type": "object",
+                    "properties": {"value": {"type": "number"}, "next": {"$ref": "#item"}}
+                }
+            }
+        });
+        let Schema::Obj { props, .. } = &list else {
+            panic!("expected an object, got {:?}", list);
+        };
+        let head = &props[&Arc::new("head".to_string())];
+        assert!(matches!(head.as_ref(), Schema::Rec(name, _) if name.as_str() == "item"));
+        assert_eq!(
+            Schema::try_from(&serde_json::Value::from(&list)).unwrap(),
+            list
+        );
+    }
+
+    #[test]
+    fn test_write_json_schema() {
+        let schema = schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "string", "format": "uuid"},
+                "price": {"type": "number", "unit": "cents", "default": 0},
+                "status": {"type": "string", "enum": ["on", "off"]},
+                "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 3, "uniqueItems": true},
+                "avatar": {"type": "string", "contentEncoding": "base64"},
+                "value": {"anyOf": [{"type": "number"}, {"type": "null"}]},
+                "extra": true
+            },
+            "required": ["id", "anything"],
+            "additionalProperties": {"type": "boolean"}
+        });
+        let json = serde_json::Value::from(&schema);
+        assert_eq!(Schema::try_from(&json).unwrap(), schema);
+        assert_eq!(json["properties"]["tags"]["maxItems"], 3);
+        assert_eq!(
+            Schema::try_from(&json).unwrap().fingerprint(),
+            schema.fingerprint()
+        );
+        assert_ne!(Schema::num().fingerprint(), Schema::string().fingerprint());
+    }
+
+    #[test]
+    fn test_parse_annotations() {
+        let schema = schema!({
+            "type": "array",
+            "items": {"type": "string", "contentEncoding": "base64"},
+            "maxItems": 3
+        });
+        let Schema::Annotated(arr, annotations) = &schema else {
+            panic!("expected an annotated array, got {:?}", schema);
+        };
+        assert_eq!(annotations.max_items, Some(3));
+        let Schema::Arr(item) = arr.as_ref() else {
+            panic!("expected an array, got {:?}", arr);
+        };
+        assert_eq!(item.bare(), &Schema::string());
+        assert_eq!(item.annotations().encoding.as_deref().unwrap(), "base64");
+        let json = serde_json::Value::from(&schema);
+        assert_eq!(json["items"]["contentEncoding"], "base64");
+        assert_eq!(Schema::try_from(&json).unwrap(), schema);
+
+        // a reference's annotations go on top of what it refers to's
+        let schema = schema!({
+            "$ref": "#/$defs/when",
+            "unit": "ms",
+            "$defs": {"when": {"type": "number", "format": "timestamp", "unit": "s"}}
+        });
+        assert_eq!(schema.bare(), &Schema::num());
+        assert_eq!(schema.annotations().format.as_deref().unwrap(), "timestamp");
+        assert_eq!(schema.annotations().unit.as_deref().unwrap(), "ms");
+    }
+
+    #[test]
+    fn test_fingerprint_is_pinned() {
+        // FNV-1a of `{"type":"number"}`, and of the object written out with
+        // its keys sorted; stored programs rely on these never changing
+        assert_eq!(Schema::num().fingerprint(), 0xcc25cd5f4632c21e);
+        let object = schema!({
+            "type": "object",
+            "properties": {"id": {"type": "string"}},
+            "required": ["id"]
+        });
+        assert_eq!(object.fingerprint(), 0xe845fd9bba681f97);
+    }
+
+    #[test]
+    fn test_parse_enum() {
+        let schema = schema!({
+            "type": "object",
+            "properties": {
+                "status": {"enum": ["on", "off"]},
+                "code": {"enum": [1, "x"]}
+            }
+        });
+        let Schema::Obj { props, .. } = &schema else {
+            panic!("expected object");
+        };
+        let status = &props[&Arc::new("status".to_string())];
+        assert_eq!(*status.bare(), Schema::string());
+        assert!(matches!(
+            props[&Arc::new("code".to_string())].bare(),
+            Schema::Union(_)
+        ));
+        assert_eq!(
+            status.annotations().values.clone().unwrap(),
+            vec![
+                super::Literal(serde_json::json!("on")),
+                super::Literal(serde_json::json!("off"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_int64() {
+        let schema = schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "integer", "format": "int64"},
+                "big": {"type": "integer", "maximum": 18446744073709551615u64},
+                "small": {"type": "integer", "maximum": 100}
+            }
+        });
+        let Schema::Obj { props, .. } = &schema else {
+            panic!("expected object");
+        };
+        assert_eq!(*props[&Arc::new("small".to_string())], Schema::num());
+        let format = |key: &str| {
+            props[&Arc::new(key.to_string())]
+                .annotations()
+                .format
+                .clone()
+        };
+        let int64 = Some(Arc::new("int64".to_string()));
+        assert_eq!(format("id"), int64);
+        assert_eq!(format("big"), int64);
+        assert_eq!(format("small"), None);
+    }
+
+    #[test]
+    fn test_declared_order() {
+        let text = r#"{
+            "type": "object",
+            "properties": {
+                "zip": {"type": "string"},
+                "inner": {
+                    "type": "object",
+                    "properties": {"b": {"type": "number"}, "a": {"type": "number"}}
+                },
+                "city": {"type": "string"}
+            }
+        }"#;
+        let (json, orders) = crate::frontend::json5::from_str_ordered(text).unwrap();
+        let order = super::PropOrder::from_json(&json, &orders);
+        let schema = Schema::try_from(&json).unwrap();
+        let keys = |keys: &[&str]| keys.iter().map(|k| Arc::new(k.to_string())).collect();
+        assert_eq!(
+            order.get(&schema).map(<[_]>::to_vec),
+            Some(keys(&["zip", "inner", "city"]))
+        );
+        let Schema::Obj { props, .. } = &schema else {
+            unreachable!()
+        };
+        assert_eq!(
+            order.get(&props[&"inner".to_string()]).map(<[_]>::to_vec),
+            Some(keys(&["b", "a"]))
+        );
+    }
+
     #[test]
     fn test_open_file() {
-        let path = "/Users/dkillough/Desktop/gradschool/jsonschema-transformer/schemas/simple.json";
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/schemas/simple.json");
         let file = std::fs::read_to_string(path).unwrap();
         let json_schema: serde_json::Value = serde_json::from_str(&file).unwrap();
         let testjson = schema!(
@@ -0,0 +1,163 @@
+//! Checking data has the shape of a schema, the way the validation the
+//! JavaScript backend generates checks it: types and required properties,
+//! all the way down, with the branches of unions told apart the way union
+//! dispatches tell them apart, without checking them further. Objects
+//! which rule out undeclared properties are also checked for them.
+
+use std::fmt::Display;
+
+use serde_json::Value;
+
+use crate::{
+    codegen::js::identifier,
+    schema::{Ground, Schema},
+    searcher::describe,
+};
+
+/// A value which doesn't have the shape of its schema: where it's found,
+/// and what's wrong with it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub path: String,
+    pub problem: String,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.problem)
+    }
+}
+
+/// Check `value`, found at `path`, has the shape of `schema`, saying
+/// where it first doesn't if it hasn't.
+pub fn check(schema: &Schema, value: &Value, path: &str) -> Result<(), Mismatch> {
+    let mismatch = |path: &str, problem: String| {
+        Err(Mismatch {
+            path: path.to_string(),
+            problem,
+        })
+    };
+    if let Schema::Rec(..) = schema {
+        return check(&schema.unfold(), value, path);
+    }
+    // objects' required properties are checked one by one, below
+    let shaped = match schema {
+        Schema::Obj { .. } => value.is_object(),
+        _ => shaped(schema, value),
+    };
+    if !shaped {
+        return mismatch(path, format!("expected {}", describe(schema)));
+    }
+    match (schema, value) {
+        (Schema::Arr(item), Value::Array(items)) => {
+            for (i, value) in items.iter().enumerate() {
+                check(item, value, &format!("{}[{}]", path, i))?;
+            }
+        }
+        (
+            Schema::Obj {
+                props,
+                required,
+                additional,
+                closed,
+                ..
+            },
+            Value::Object(obj),
+        ) => {
+            let undeclared = obj.keys().find(|key| !props.contains_key(*key));
+            if let (Some(key), None, true) = (undeclared, additional, closed) {
+                return mismatch(&child(path, key), "not allowed".to_string());
+            }
+            for (key, prop) in props {
+                let path = child(path, key);
+                match obj.get(key.as_str()) {
+                    Some(value) => check(prop, value, &path)?,
+                    None if required.contains(key) => {
+                        return mismatch(&path, "required but missing".to_string())
+                    }
+                    None => {}
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `path` extended with property `key`, the way JavaScript reads it.
+fn child(path: &str, key: &str) -> String {
+    match identifier(key) {
+        true => format!("{}.{}", path, key),
+        false => format!("{}[{}]", path, Value::from(key)),
+    }
+}
+
+/// Whether `value` has the shape of `schema` at its top, as union
+/// dispatches tell: objects by their required properties.
+fn shaped(schema: &Schema, value: &Value) -> bool {
+    match (schema, value) {
+        (Schema::Ground(Ground::Num), Value::Number(_))
+        | (Schema::Ground(Ground::Bool), Value::Bool(_))
+        | (Schema::Ground(Ground::String), Value::String(_))
+        | (Schema::Ground(Ground::Null), Value::Null)
+        | (Schema::Arr(_), Value::Array(_))
+        | (Schema::True | Schema::Var(_), _) => true,
+        (Schema::Rec(_, body) | Schema::Annotated(body, _), value) => shaped(body, value),
+        (Schema::Obj { required, .. }, Value::Object(obj)) => {
+            required.iter().all(|key| obj.contains_key(key.as_str()))
+        }
+        (Schema::Union(branches), value) => branches.iter().any(|branch| shaped(branch, value)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let schema = crate::schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "first name": {"type": "string"}
+            },
+            "required": ["id"]
+        });
+        assert_eq!(
+            check(&schema, &json!({"id": 1, "tags": []}), "output"),
+            Ok(())
+        );
+        let mismatch = |path: &str, problem: &str| {
+            Err(Mismatch {
+                path: path.to_string(),
+                problem: problem.to_string(),
+            })
+        };
+        assert_eq!(
+            check(&schema, &json!({"tags": []}), "output"),
+            mismatch("output.id", "required but missing")
+        );
+        assert_eq!(
+            check(&schema, &json!({"id": 1, "tags": ["a", 2]}), "output"),
+            mismatch("output.tags[1]", "expected string")
+        );
+        assert_eq!(
+            check(&schema, &json!({"id": 1, "first name": null}), "output"),
+            mismatch("output[\"first name\"]", "expected string")
+        );
+        let closed = crate::schema!({
+            "type": "object",
+            "properties": {"id": {"type": "number"}},
+            "additionalProperties": false
+        });
+        assert_eq!(
+            check(&closed, &json!({"id": 1, "secret": "leak"}), "output"),
+            mismatch("output.secret", "not allowed")
+        );
+    }
+}
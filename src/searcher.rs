@@ -0,0 +1,5210 @@
+//! Search for transforms: the cheapest path of IR instructions converting
+//! data of a source schema into data of a target schema, under a
+//! [`SearchConfig`].
+
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use derive_builder::Builder;
+
+use crate::{
+    cost::{CostModel, DefaultCostModel, PreferInvertible},
+    frontend::infer::unify,
+    ir::{join_path, Case, Coding, ValueTable, IR},
+    mapping::{Concat, Mapping},
+    matcher::{is_plural, KeyMatcher},
+    report::{analyze, Lossiness, PathReport, Step},
+    schema::{Annotations, EditErr, ExtNat, Ground, Literal, PropOrder, Real, Schema, SchemaEdit},
+    units,
+};
+
+/// How (and whether) an array may be collapsed into a single ground value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArrToGround {
+    /// Arrays never convert to ground values.
+    Never,
+    /// Take the first item of the array and convert it.
+    First,
+    /// Join the items into a string with the given separator when the target
+    /// is a string, and otherwise take the first item.
+    Join(String),
+}
+
+/// The order in which the properties of converted objects are built, and so
+/// the order they appear in in the output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum KeyOrder {
+    /// Alphabetical order.
+    #[default]
+    Sorted,
+    /// The order the target schema declares its properties in. Properties
+    /// of objects with no declared order come last, alphabetically.
+    Declared(Arc<PropOrder>),
+}
+
+impl KeyOrder {
+    /// The declared order of the properties of object schema `schema`, if
+    /// there is one.
+    fn of(&self, schema: &Schema) -> Option<&[Arc<String>]> {
+        match self {
+            Self::Sorted => None,
+            Self::Declared(order) => order.get(schema),
+        }
+    }
+}
+
+/// How thoroughly the searcher explores the ways of converting each pair of
+/// schemas.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Explore alternatives until the cheapest path is certain.
+    #[default]
+    Exact,
+    /// Only ever explore the given number of alternatives for each pair of
+    /// schemas, those which look cheapest before any of their subgoals are
+    /// solved. Time and memory grow with the width rather than with the
+    /// number of rules which apply (such as extracting each property of a
+    /// very wide object), but the path found may not be the cheapest, or a
+    /// path may be missed altogether.
+    Beam(usize),
+}
+
+/// Options controlling which conversions the searcher may use.
+#[derive(Builder, Clone, Debug)]
+#[builder(default)]
+pub struct SearchConfig {
+    /// Strategy for `(Arr, Ground)` conversions.
+    pub arr_to_ground: ArrToGround,
+    /// Matcher proposing renames for target properties with no source
+    /// property of the same name. `None` disables renaming.
+    pub key_matcher: Option<KeyMatcher>,
+    /// User-supplied property correspondences, which take precedence over
+    /// both same-named properties and the key matcher.
+    pub mapping: Mapping,
+    /// How much each kind of conversion costs.
+    pub cost_model: Arc<dyn CostModel>,
+    /// How much work a single search may do before giving up.
+    pub limits: SearchLimits,
+    /// The order converted objects' properties are built in.
+    pub key_order: KeyOrder,
+    /// Fail rather than drop any source property the mapping doesn't
+    /// explicitly waive (see [`Mapping::waive`]).
+    pub strict: bool,
+    /// Whether to search exhaustively or within a beam.
+    pub strategy: Strategy,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            arr_to_ground: ArrToGround::First,
+            key_matcher: Some(KeyMatcher::default()),
+            mapping: Mapping::default(),
+            cost_model: Arc::new(DefaultCostModel),
+            limits: SearchLimits::default(),
+            key_order: KeyOrder::default(),
+            strict: false,
+            strategy: Strategy::default(),
+        }
+    }
+}
+
+/// Bounds on the work done by a single search, so that deeply nested or
+/// adversarial schema pairs fail quickly instead of running indefinitely.
+/// Every limit is unbounded by default.
+#[derive(Builder, Clone, Debug, Default, PartialEq, Eq)]
+#[builder(default)]
+pub struct SearchLimits {
+    /// How many schemas deep the search may descend.
+    pub max_depth: Option<usize>,
+    /// How many alternatives the search may explore in total.
+    pub max_states: Option<usize>,
+    /// How long the search may run for.
+    pub timeout: Option<Duration>,
+}
+
+/// Which of the [`SearchLimits`] a search ran into.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Depth,
+    States,
+    Time,
+}
+
+/// How far a search got before running out of budget.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Budget {
+    pub limit: Limit,
+    /// Alternatives explored before giving up.
+    pub explored: usize,
+    /// Deepest nesting of schemas reached.
+    pub depth: usize,
+}
+
+/// Why a rule couldn't convert one schema into another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// One of the rule's subgoals has no path.
+    Subgoal(Arc<NoPath>),
+    /// A required target property has no source property to come from.
+    Unmatched(Arc<String>),
+    /// A required target property comes from a source property which may
+    /// be missing, with no fill or default to fall back on.
+    Unguaranteed(Arc<String>),
+    /// The mapping pinned a source property which doesn't exist.
+    MissingSource(Arc<String>),
+    /// The cost model forbids the rule.
+    Forbidden,
+    /// A strict search would have to drop these source properties, which
+    /// the mapping doesn't waive.
+    Dropped(Vec<Arc<String>>),
+    /// Beam search gave up on the rule before trying it.
+    Pruned,
+    /// A property the mapping concatenates either isn't a ground value, or
+    /// (as the target) isn't a string.
+    Unconcatenable(Arc<String>),
+    /// The mapping scales a target property which isn't pinned to a number,
+    /// or isn't one itself.
+    Unscalable(Arc<String>),
+    /// The mapping translates the values of a target property (or converts
+    /// their case), but not every value its source property can have.
+    Unmappable(Arc<String>),
+    /// The mapping extracts a pattern into a target property, but either
+    /// it or its source isn't a string.
+    Unextractable(Arc<String>),
+    /// The mapping slices a target property which either isn't an array, or
+    /// isn't pinned to one.
+    Unsliceable(Arc<String>),
+    /// The mapping sorts a target property which either isn't an array, or
+    /// isn't pinned to one whose items have the value to sort by.
+    Unsortable(Arc<String>),
+}
+
+/// A rule tried while searching, and why it didn't work out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attempt {
+    /// Name of the rule, like `object` or `extract "id"`.
+    pub rule: String,
+    /// Where the failure lies in the target, relative to the rule's own
+    /// location: a property name, `[]` for array items, or empty.
+    pub step: String,
+    pub failure: Failure,
+}
+
+/// Why no path exists between two schemas: the schemas involved, and every
+/// rule which was attempted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NoPath {
+    pub source: Arc<Schema>,
+    pub target: Arc<Schema>,
+    pub attempts: Vec<Attempt>,
+}
+
+impl NoPath {
+    /// The deepest point the search got stuck at, following the first failed
+    /// subgoal of each level down, along with the (dot-separated) path to it
+    /// in the target. This is usually where a schema fix or mapping hint is
+    /// needed.
+    pub fn blame(&self) -> (String, &NoPath) {
+        let mut path = String::new();
+        let mut stuck = self;
+        while let Some((step, sub)) = stuck.attempts.iter().find_map(|a| match &a.failure {
+            Failure::Subgoal(sub) => Some((&a.step, sub)),
+            _ => None,
+        }) {
+            if !step.is_empty() {
+                path = join_path(&path, step);
+            }
+            stuck = sub;
+        }
+        (path, stuck)
+    }
+}
+
+impl Display for NoPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (path, stuck) = self.blame();
+        let at = if path.is_empty() { "<root>" } else { &path };
+        write!(
+            f,
+            "no conversion from {} to {} at `{}`",
+            describe(&stuck.source),
+            describe(&stuck.target),
+            at
+        )?;
+        if stuck.attempts.is_empty() {
+            return write!(f, "; no rule applies");
+        }
+        for attempt in &stuck.attempts {
+            write!(f, "\n  tried {}: ", attempt.rule)?;
+            match &attempt.failure {
+                Failure::Subgoal(_) => write!(f, "`{}` has no path", attempt.step)?,
+                Failure::Unmatched(key) => write!(f, "required property `{}` has no source", key)?,
+                Failure::Unguaranteed(key) => {
+                    write!(f, "required property `{}` comes from an optional one", key)?
+                }
+                Failure::MissingSource(key) => {
+                    write!(f, "mapped source property `{}` doesn't exist", key)?
+                }
+                Failure::Forbidden => write!(f, "forbidden by the cost model")?,
+                Failure::Dropped(keys) => {
+                    let keys: Vec<_> = keys.iter().map(|k| format!("`{}`", k)).collect();
+                    write!(f, "would drop {}", keys.join(", "))?
+                }
+                Failure::Pruned => write!(f, "pruned from the search beam")?,
+                Failure::Unconcatenable(key) => {
+                    write!(f, "`{}` can't be part of a concatenation", key)?
+                }
+                Failure::Unscalable(key) => write!(f, "`{}` isn't a number to scale", key)?,
+                Failure::Unmappable(key) => {
+                    write!(f, "`{}` has values the mapping doesn't translate", key)?
+                }
+                Failure::Unextractable(key) => {
+                    write!(f, "`{}` isn't a string to extract a pattern into", key)?
+                }
+                Failure::Unsliceable(key) => write!(f, "`{}` isn't an array to slice", key)?,
+                Failure::Unsortable(key) => {
+                    write!(f, "`{}` isn't an array with the values to sort by", key)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A short description of `schema` for error messages.
+pub(crate) fn describe(schema: &Schema) -> String {
+    match schema {
+        Schema::Ground(Ground::Num) => "number".to_string(),
+        Schema::Ground(Ground::Bool) => "boolean".to_string(),
+        Schema::Ground(Ground::String) => "string".to_string(),
+        Schema::Ground(Ground::Null) => "null".to_string(),
+        Schema::Arr(item) => format!("array of {}", describe(item)),
+        Schema::Obj { props, .. } => {
+            let keys: Vec<_> = props.keys().map(|k| k.as_str()).collect();
+            format!("object {{{}}}", keys.join(", "))
+        }
+        Schema::Union(branches) => {
+            let branches: Vec<_> = branches.iter().map(|b| describe(b)).collect();
+            format!("one of ({})", branches.join(" | "))
+        }
+        Schema::Rec(_, body) | Schema::Annotated(body, _) => describe(body),
+        Schema::Var(name) => name.to_string(),
+        Schema::True => "anything".to_string(),
+        Schema::False => "nothing".to_string(),
+    }
+}
+
+/// Error while searching for a transform between two schemas.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SearchErr {
+    /// No sequence of conversions turns the source schema into the target.
+    NoPath(NoPath),
+    /// The search gave up after hitting one of its [`SearchLimits`]. A path
+    /// may still exist.
+    BudgetExceeded(Budget),
+}
+
+impl Display for SearchErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoPath(no_path) => no_path.fmt(f),
+            Self::BudgetExceeded(budget) => write!(
+                f,
+                "search ran out of {} after exploring {} alternatives, {} levels deep",
+                match budget.limit {
+                    Limit::Depth => "depth",
+                    Limit::States => "states",
+                    Limit::Time => "time",
+                },
+                budget.explored,
+                budget.depth
+            ),
+        }
+    }
+}
+
+/// A solved conversion between two schemas: the IR implementing it and the
+/// total cost of the conversions it uses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Path {
+    pub cost: ExtNat,
+    pub ir: Vec<IR>,
+}
+
+impl Path {
+    /// Bind this path to `label`, so that calls to it inside convert their
+    /// part of the input the same way as the whole.
+    fn recursive(mut self, label: &Arc<String>) -> Path {
+        self.ir.insert(0, IR::Rec(label.clone()));
+        self.ir.push(IR::PopRec);
+        self
+    }
+}
+
+/// How much more a step which can't be undone costs when searching for a
+/// round trip.
+const ONE_WAY_PENALTY: u64 = 2;
+
+/// Transforms between two schemas in both directions, along with how lossy
+/// each one is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoundTrip {
+    pub forward: Vec<IR>,
+    pub backward: Vec<IR>,
+    pub forward_report: PathReport,
+    pub backward_report: PathReport,
+}
+
+impl RoundTrip {
+    /// Steps of the forward path, then of the backward path, which lose or
+    /// drop information, so that data doesn't survive a round trip through
+    /// them intact.
+    pub fn one_way(&self) -> impl Iterator<Item = &Step> {
+        self.forward_report
+            .steps
+            .iter()
+            .chain(&self.backward_report.steps)
+            .filter(|step| step.lossiness != Lossiness::Lossless)
+    }
+
+    /// Whether data survives a round trip in either direction.
+    pub fn is_lossless(&self) -> bool {
+        self.one_way().next().is_none()
+    }
+}
+
+/// A pair of (source, target) schemas to find a conversion between, along
+/// with the user-supplied mapping for the properties beneath them.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct Rel {
+    lhs: Arc<Schema>,
+    rhs: Arc<Schema>,
+    mapping: Arc<Mapping>,
+}
+
+impl Rel {
+    fn new(lhs: Arc<Schema>, rhs: Arc<Schema>) -> Self {
+        Self::with_mapping(lhs, rhs, Default::default())
+    }
+
+    fn with_mapping(lhs: Arc<Schema>, rhs: Arc<Schema>, mapping: Arc<Mapping>) -> Self {
+        Self { lhs, rhs, mapping }
+    }
+}
+
+/// Stands for the properties of an object which its schema doesn't name, in
+/// paths and error messages.
+const REST: &str = "*";
+
+/// Keys leading down into a nested object, each with whether it may be
+/// absent.
+type KeyPath = Vec<(Arc<String>, bool)>;
+
+/// Where the value of a target property comes from.
+#[derive(Clone, Debug)]
+enum PropSource {
+    /// Source property `from`, read further down `path` when flattening a
+    /// nested object. If `from` may be absent, its conversion is guarded.
+    /// Arrays may be sorted or sliced by the instructions in `arrange`
+    /// first.
+    Key {
+        from: Arc<String>,
+        optional: bool,
+        path: KeyPath,
+        arrange: Vec<IR>,
+    },
+    /// A new object built out of the given properties of the source object
+    /// itself.
+    Nest(Vec<Arc<String>>),
+    /// A fixed value, for target properties with nothing in the source.
+    Const(Literal),
+    /// Every property of the source object except the given named ones,
+    /// each converted the same way.
+    Rest(Vec<Arc<String>>),
+    /// The given properties of the source object joined into a string.
+    Concat(Concat),
+    /// The first present of the given properties of the source object. If
+    /// they may all be absent, the conversion is guarded.
+    Coalesce {
+        sources: Vec<Arc<String>>,
+        optional: bool,
+    },
+    /// The items of the given array properties of the source object
+    /// concatenated into one array, arranged by the instructions in
+    /// `arrange`.
+    Gathered {
+        sources: Vec<Arc<String>>,
+        arrange: Vec<IR>,
+    },
+    /// Source property `from` converted by a single instruction, like
+    /// scaling a number to another unit or translating an enumerated value.
+    Converted {
+        from: Arc<String>,
+        optional: bool,
+        op: IR,
+    },
+}
+
+/// A target property matched up with where its value comes from.
+#[derive(Clone, Debug)]
+struct PropMatch {
+    to: Arc<String>,
+    source: PropSource,
+}
+
+impl PropMatch {
+    fn key(from: Arc<String>, to: Arc<String>, optional: bool) -> Self {
+        Self::flattened(from, to, optional, vec![])
+    }
+
+    fn flattened(from: Arc<String>, to: Arc<String>, optional: bool, path: KeyPath) -> Self {
+        Self {
+            to,
+            source: PropSource::Key {
+                from,
+                optional,
+                path,
+                arrange: vec![],
+            },
+        }
+    }
+
+    /// Whether the value this reads may be missing from the source.
+    fn may_be_missing(&self) -> bool {
+        match &self.source {
+            PropSource::Key { optional, path, .. } => {
+                *optional || path.iter().any(|(_, optional)| *optional)
+            }
+            PropSource::Coalesce { optional, .. } | PropSource::Converted { optional, .. } => {
+                *optional
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether this reads all of source property `k1`.
+    fn reads_whole(&self, k1: &Arc<String>) -> bool {
+        match &self.source {
+            PropSource::Key { from, path, .. } => from == k1 && path.is_empty(),
+            PropSource::Nest(keys) => keys.contains(k1),
+            PropSource::Concat(concat) => concat.sources.contains(k1),
+            PropSource::Coalesce { sources, .. } | PropSource::Gathered { sources, .. } => {
+                sources.contains(k1)
+            }
+            PropSource::Converted { from, .. } => from == k1,
+            PropSource::Const(_) | PropSource::Rest(_) => false,
+        }
+    }
+
+    /// The path this reads beneath source property `k1`, if it reads any of
+    /// it at all.
+    fn reads_under(&self, k1: &Arc<String>) -> Option<&[(Arc<String>, bool)]> {
+        match &self.source {
+            PropSource::Key { from, path, .. } if from == k1 => Some(path),
+            PropSource::Nest(keys) if keys.contains(k1) => Some(&[]),
+            PropSource::Concat(concat) if concat.sources.contains(k1) => Some(&[]),
+            PropSource::Coalesce { sources, .. } | PropSource::Gathered { sources, .. }
+                if sources.contains(k1) =>
+            {
+                Some(&[])
+            }
+            PropSource::Converted { from, .. } if from == k1 => Some(&[]),
+            _ => None,
+        }
+    }
+}
+
+/// Every property nested (at least one level) beneath the object-valued
+/// properties of `props`, named by joining its key path with `_`, along with
+/// the top-level property it's under and the path down to it.
+fn flattened(
+    props: &BTreeMap<Arc<String>, Arc<Schema>>,
+) -> Vec<(Arc<String>, Arc<String>, KeyPath)> {
+    fn descend(
+        name: &str,
+        schema: &Schema,
+        path: &mut KeyPath,
+        out: &mut Vec<(Arc<String>, KeyPath)>,
+    ) {
+        if let Schema::Obj {
+            props, required, ..
+        } = schema.bare()
+        {
+            for (key, value) in props {
+                let name = format!("{}_{}", name, key);
+                path.push((key.clone(), !required.contains(key)));
+                out.push((Arc::new(name.clone()), path.clone()));
+                descend(&name, value, path, out);
+                path.pop();
+            }
+        }
+    }
+
+    let mut flat = vec![];
+    for (key, value) in props {
+        let mut nested = vec![];
+        descend(key, value, &mut vec![], &mut nested);
+        flat.extend(
+            nested
+                .into_iter()
+                .map(|(name, path)| (name, key.clone(), path)),
+        );
+    }
+    flat
+}
+
+/// One way of converting a source schema into a target schema. Rules which
+/// descend into the schemas produce subgoals which must be solved before the
+/// rule's IR can be emitted.
+#[derive(Clone, Debug)]
+enum Rule {
+    Copy,
+    G2G(Ground, Ground),
+    /// Convert an array item-wise.
+    Arr,
+    /// Build an object, converting each matched pair of properties.
+    Obj(Vec<PropMatch>),
+    /// Pull a single property out of an object and convert it.
+    Extr(Arc<String>),
+    /// Convert a value and make it the only item of an array.
+    Wrap,
+    /// Convert the first item of an array.
+    First,
+    /// Group an array of objects into an object keyed by the given property,
+    /// or back, then convert the result.
+    GroupBy(Arc<String>),
+    /// Zip an object of parallel arrays into an array of objects, pairing
+    /// up array properties with item properties, then convert the result.
+    Zip(Vec<(Arc<String>, Arc<String>)>),
+    /// Unzip an array of objects into an object of parallel arrays, then
+    /// convert the result.
+    Unzip(Vec<(Arc<String>, Arc<String>)>),
+    /// Flatten nested arrays the given number of levels, then convert the
+    /// flattened array.
+    Flatten(usize),
+    /// Join the items of an array of grounds into a string.
+    Join(String),
+    /// Invert an object into an array of entries (or vice versa) and convert
+    /// the result.
+    Inv,
+    /// Convert into one branch of a target union.
+    Variant,
+    /// Check which branch of a source union the input matches at runtime,
+    /// and convert each branch separately.
+    Dispatch(Vec<Arc<Schema>>),
+    /// Convert as the annotations on either side call for, with the given
+    /// instructions, then convert the schemas they annotate.
+    Annotated(Vec<IR>),
+}
+
+impl Rule {
+    /// Name of the rule, for diagnostics.
+    fn name(&self) -> String {
+        match self {
+            Rule::Copy => "copy".to_string(),
+            Rule::G2G(from, to) => format!("convert {:?} to {:?}", from, to),
+            Rule::Arr => "array".to_string(),
+            Rule::Obj(_) => "object".to_string(),
+            Rule::Extr(key) => format!("extract {:?}", key.as_str()),
+            Rule::Wrap => "wrap".to_string(),
+            Rule::First => "first".to_string(),
+            Rule::Flatten(depth) => format!("flatten {}", depth),
+            Rule::Join(sep) => format!("join {:?}", sep),
+            Rule::Inv => "invert".to_string(),
+            Rule::GroupBy(key) => format!("group by {:?}", key.as_str()),
+            Rule::Zip(_) => "zip".to_string(),
+            Rule::Unzip(_) => "unzip".to_string(),
+            Rule::Variant => "variant".to_string(),
+            Rule::Dispatch(_) => "dispatch".to_string(),
+            Rule::Annotated(_) => "annotations".to_string(),
+        }
+    }
+
+    /// Where subgoal `i` of this rule lies in the target, relative to the
+    /// rule itself.
+    fn step(&self, i: usize) -> String {
+        match self {
+            Rule::Obj(props) => props
+                .iter()
+                .filter(|prop| {
+                    !matches!(
+                        prop.source,
+                        PropSource::Const(_) | PropSource::Concat(_) | PropSource::Converted { .. }
+                    )
+                })
+                .nth(i)
+                .map_or_else(String::new, |prop| prop.to.to_string()),
+            Rule::Arr => "[]".to_string(),
+            Rule::Wrap => "[0]".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Assemble the IR for this rule from the paths of its solved subgoals.
+    fn emit(self, subpaths: Vec<Vec<IR>>) -> Vec<IR> {
+        match self {
+            Rule::Copy => vec![IR::Copy],
+            Rule::G2G(from, to) => vec![IR::G2G(from, to)],
+            Rule::Arr => {
+                let mut ir = vec![IR::PushArr];
+                ir.extend(subpaths.into_iter().flatten());
+                ir.push(IR::PopArr);
+                ir
+            }
+            Rule::Obj(props) => {
+                let mut ir = vec![IR::PushObj];
+                let mut subpaths = subpaths.into_iter();
+                // properties already filled in, which a missing value mustn't
+                // overwrite
+                let mut filled = BTreeSet::new();
+                for prop in props {
+                    let (from, optional, path, arrange) = match prop.source {
+                        PropSource::Key {
+                            from,
+                            optional,
+                            path,
+                            arrange,
+                        } => (from, optional, path, arrange),
+                        PropSource::Nest(_) => {
+                            ir.push(IR::Nest(prop.to));
+                            ir.extend(subpaths.next().unwrap_or_default());
+                            ir.push(IR::PopKey);
+                            continue;
+                        }
+                        PropSource::Const(value) => {
+                            filled.insert(prop.to.clone());
+                            ir.extend([IR::Nest(prop.to), IR::Const(value), IR::PopKey]);
+                            continue;
+                        }
+                        PropSource::Coalesce { sources, optional } => {
+                            let sub = subpaths.next().unwrap_or_default();
+                            let paths = sources.into_iter().map(|k| vec![k]).collect();
+                            let guard = sub != [IR::Copy] || filled.contains(&prop.to);
+                            ir.extend([IR::Nest(prop.to), IR::Coalesce(paths)]);
+                            if optional && guard {
+                                ir.push(IR::PushOpt);
+                                ir.extend(sub);
+                                ir.push(IR::PopOpt);
+                            } else {
+                                ir.extend(sub);
+                            }
+                            ir.push(IR::PopKey);
+                            continue;
+                        }
+                        PropSource::Gathered { sources, arrange } => {
+                            let paths = sources.into_iter().map(|k| vec![k]).collect();
+                            ir.extend([IR::Nest(prop.to), IR::Gather(paths)]);
+                            ir.extend(arrange);
+                            ir.extend(subpaths.next().unwrap_or_default());
+                            ir.push(IR::PopKey);
+                            continue;
+                        }
+                        PropSource::Concat(concat) => {
+                            let paths = concat.sources.into_iter().map(|k| vec![k]).collect();
+                            ir.extend([
+                                IR::Nest(prop.to),
+                                IR::Concat(paths, concat.separator),
+                                IR::PopKey,
+                            ]);
+                            continue;
+                        }
+                        PropSource::Converted { from, optional, op } => {
+                            ir.push(if from == prop.to {
+                                IR::PushKey(prop.to)
+                            } else {
+                                IR::Rename(from, prop.to)
+                            });
+                            if optional {
+                                ir.extend([IR::PushOpt, op, IR::PopOpt]);
+                            } else {
+                                ir.push(op);
+                            }
+                            ir.push(IR::PopKey);
+                            continue;
+                        }
+                        PropSource::Rest(named) => {
+                            ir.push(IR::PushRest(named));
+                            ir.extend(subpaths.next().unwrap_or_default());
+                            ir.push(IR::PopRest);
+                            continue;
+                        }
+                    };
+                    let sub = subpaths.next().unwrap_or_default();
+                    let guard =
+                        sub != [IR::Copy] || !arrange.is_empty() || filled.contains(&prop.to);
+                    ir.push(if from == prop.to {
+                        IR::PushKey(prop.to)
+                    } else {
+                        IR::Rename(from, prop.to)
+                    });
+                    // anything optional has to be there before reading
+                    // further into it
+                    let mut guards = 0;
+                    let mut optional = optional;
+                    for (key, key_optional) in path {
+                        if optional {
+                            ir.push(IR::PushOpt);
+                            guards += 1;
+                        }
+                        ir.push(IR::Extr(key));
+                        optional = key_optional;
+                    }
+                    // copying a missing property leaves it missing, so only
+                    // conversions which actually touch the value, or which
+                    // would overwrite a fallback, need a guard
+                    if optional && guard {
+                        ir.push(IR::PushOpt);
+                        guards += 1;
+                    }
+                    ir.extend(arrange);
+                    ir.extend(sub);
+                    ir.extend(std::iter::repeat_n(IR::PopOpt, guards));
+                    ir.push(IR::PopKey);
+                }
+                ir.push(IR::PopObj);
+                ir
+            }
+            Rule::Extr(key) => {
+                let mut ir = vec![IR::Extr(key)];
+                ir.extend(subpaths.into_iter().flatten());
+                ir
+            }
+            Rule::Wrap => {
+                let mut ir = vec![IR::Wrap];
+                ir.extend(subpaths.into_iter().flatten());
+                ir
+            }
+            Rule::First => {
+                let mut ir = vec![IR::First];
+                ir.extend(subpaths.into_iter().flatten());
+                ir
+            }
+            Rule::Flatten(depth) => {
+                let mut ir = vec![IR::Flatten(depth)];
+                ir.extend(subpaths.into_iter().flatten());
+                ir
+            }
+            Rule::Join(sep) => vec![IR::Join(sep)],
+            Rule::Annotated(mut ir) => {
+                ir.extend(subpaths.into_iter().flatten());
+                ir
+            }
+            Rule::Inv => {
+                let mut ir = vec![IR::Inv];
+                ir.extend(subpaths.into_iter().flatten());
+                ir
+            }
+            Rule::GroupBy(key) => {
+                let mut ir = vec![IR::GroupBy(key)];
+                ir.extend(subpaths.into_iter().flatten());
+                ir
+            }
+            Rule::Zip(pairs) => {
+                let mut ir = vec![IR::Zip(pairs)];
+                ir.extend(subpaths.into_iter().flatten());
+                ir
+            }
+            Rule::Unzip(pairs) => {
+                let mut ir = vec![IR::Unzip(pairs)];
+                ir.extend(subpaths.into_iter().flatten());
+                ir
+            }
+            Rule::Variant => subpaths.into_iter().flatten().collect(),
+            Rule::Dispatch(branches) => {
+                let mut ir = vec![IR::PushUnion];
+                for (branch, sub) in branches.into_iter().zip(subpaths) {
+                    ir.push(IR::Case(branch));
+                    ir.extend(sub);
+                    ir.push(IR::PopCase);
+                }
+                ir.push(IR::PopUnion);
+                ir
+            }
+        }
+    }
+}
+
+/// A partially explored alternative: the cost of the rule plus the subgoals
+/// solved so far, and the subgoals which remain.
+struct Alternative {
+    cost: ExtNat,
+    rule: Rule,
+    goals: std::vec::IntoIter<Rel>,
+    solved: Vec<Vec<IR>>,
+}
+
+impl Alternative {
+    fn new(cost: ExtNat, rule: Rule, goals: Vec<Rel>) -> Self {
+        Self {
+            cost,
+            rule,
+            goals: goals.into_iter(),
+            solved: vec![],
+        }
+    }
+}
+
+/// The (at most) `k` cheapest ways of picking one path from each of `subs`,
+/// as the total cost and the index picked from each list. Each list must be
+/// sorted by cost. Combinations are enumerated lazily from the cheapest one,
+/// so this doesn't need to look at the whole cartesian product.
+fn cheapest_combinations(subs: &[Vec<Path>], k: usize) -> Vec<(ExtNat, Vec<usize>)> {
+    let cost = |choice: &Vec<usize>| {
+        choice
+            .iter()
+            .zip(subs)
+            .fold(ExtNat::Nat(0), |acc, (i, sub)| acc + sub[*i].cost)
+    };
+
+    let mut combos = vec![];
+    if subs.iter().any(|sub| sub.is_empty()) {
+        return combos;
+    }
+
+    let start = vec![0; subs.len()];
+    let mut seen = HashSet::from([start.clone()]);
+    let mut queue = BinaryHeap::from([Reverse((cost(&start), start))]);
+    while let Some(Reverse((c, choice))) = queue.pop() {
+        if combos.len() == k {
+            break;
+        }
+        for i in 0..choice.len() {
+            if choice[i] + 1 < subs[i].len() {
+                let mut next = choice.clone();
+                next[i] += 1;
+                if seen.insert(next.clone()) {
+                    queue.push(Reverse((cost(&next), next)));
+                }
+            }
+        }
+        combos.push((c, choice));
+    }
+    combos
+}
+
+/// Schema of array `arr` after flattening it `depth` levels, if it's nested
+/// that deep.
+pub fn flat(arr: &Schema, depth: usize) -> Option<Schema> {
+    let Schema::Arr(mut item) = arr.bare().clone() else {
+        return None;
+    };
+    for _ in 0..depth {
+        match item.bare() {
+            Schema::Arr(inner) => item = inner.clone(),
+            _ => return None,
+        }
+    }
+    Some(Schema::Arr(item))
+}
+
+/// Array schema `schema` without the `maxItems` and `uniqueItems` its
+/// items have already been arranged for.
+fn arranged(schema: &Arc<Schema>) -> Arc<Schema> {
+    let Schema::Annotated(inner, annotations) = schema.as_ref() else {
+        return schema.clone();
+    };
+    let annotations = Annotations {
+        max_items: None,
+        unique_items: false,
+        ..annotations.clone()
+    };
+    Arc::new(inner.as_ref().clone().annotated(annotations))
+}
+
+/// `schema` without its annotations.
+fn unannotated(schema: &Arc<Schema>) -> Arc<Schema> {
+    match schema.as_ref() {
+        Schema::Annotated(inner, _) => inner.clone(),
+        _ => schema.clone(),
+    }
+}
+
+/// Whether every item of array `arr` has a ground value at `path` to sort
+/// them by (or is one, if the path is empty).
+fn sortable(arr: &Schema, path: &[Arc<String>]) -> bool {
+    let Schema::Arr(item) = arr.bare() else {
+        return false;
+    };
+    let value = path
+        .iter()
+        .try_fold(item.as_ref(), |schema, key| match schema.bare() {
+            Schema::Obj {
+                props, required, ..
+            } if required.contains(key) => props.get(key).map(Arc::as_ref),
+            _ => None,
+        });
+    matches!(value.map(Schema::bare), Some(Schema::Ground(_)))
+}
+
+/// The table translating the values of source enumeration `from` into those
+/// of target enumeration `to`, if they differ: values which both have stay
+/// the same, the rest go to the target value the key matcher (if any) finds
+/// the best match, and failing that, the enumerations are paired up in
+/// order if they have as many values. `None` if any value is left without a
+/// translation.
+fn value_table(
+    from: &[Literal],
+    to: &[Literal],
+    matcher: Option<&KeyMatcher>,
+) -> Option<ValueTable> {
+    if from.iter().all(|v1| to.contains(v1)) {
+        return None;
+    }
+    let names: Vec<_> = to
+        .iter()
+        .filter_map(|v2| Some(Arc::new(v2.0.as_str()?.to_string())))
+        .collect();
+    let matched: Option<ValueTable> = from
+        .iter()
+        .map(|v1| {
+            if to.contains(v1) {
+                return Some((v1.clone(), v1.clone()));
+            }
+            let (name, _) = matcher?.best_match(v1.0.as_str()?, &names)?;
+            Some((v1.clone(), Literal(name.as_str().into())))
+        })
+        .collect();
+    matched.or_else(|| {
+        (from.len() == to.len()).then(|| from.iter().cloned().zip(to.iter().cloned()).collect())
+    })
+}
+
+/// The case converting every value of source enumeration `from` into one of
+/// target enumeration `to`, if they differ.
+fn value_case(from: &[Literal], to: &[Literal]) -> Option<Case> {
+    if from.iter().all(|v1| to.contains(v1)) {
+        return None;
+    }
+    Case::ALL.into_iter().find(|case| {
+        from.iter().all(|v1| {
+            v1.0.as_str()
+                .is_some_and(|v1| to.contains(&Literal(case.apply(v1).into())))
+        })
+    })
+}
+
+/// Schema of `schema` after grouping it by property `key` (see
+/// [`IR::GroupBy`]), if it's an array of objects which all have `key`, or an
+/// object of objects.
+pub fn grouped(schema: &Schema, key: &Arc<String>) -> Option<Schema> {
+    match schema.bare() {
+        Schema::Arr(item) => match item.bare() {
+            Schema::Obj { required, .. } if required.contains(key) => Some(Schema::Obj {
+                props: BTreeMap::new(),
+                required: BTreeSet::new(),
+                additional: Some(item.clone()),
+                closed: false,
+            }),
+            _ => None,
+        },
+        Schema::Obj {
+            props,
+            additional: Some(value),
+            ..
+        } if props.is_empty() => {
+            let mut value = value.bare().clone();
+            let Schema::Obj {
+                props, required, ..
+            } = &mut value
+            else {
+                return None;
+            };
+            // keys are always strings
+            props.insert(key.clone(), Arc::new(Schema::string()));
+            required.insert(key.clone());
+            Some(Schema::Arr(Arc::new(value)))
+        }
+        _ => None,
+    }
+}
+
+/// The properties an array of `item`s could be grouped by to become an
+/// object of `value`s: required strings and numbers, starting with those
+/// `value` doesn't have (which likely moved into the keys).
+fn group_keys(item: &Schema, value: &Schema) -> Vec<Arc<String>> {
+    let Schema::Obj {
+        props, required, ..
+    } = item.bare()
+    else {
+        return vec![];
+    };
+    let mut keys: Vec<_> = required
+        .iter()
+        .filter(|k| {
+            matches!(
+                props[*k].bare(),
+                Schema::Ground(Ground::String | Ground::Num)
+            )
+        })
+        .cloned()
+        .collect();
+    let kept = |k: &Arc<String>| matches!(value.bare(), Schema::Obj { props, .. } if props.contains_key(k));
+    keys.sort_by_key(kept);
+    keys
+}
+
+/// Pairs of an array property of `arrays` and a property of `item` to hold
+/// its items, for converting between parallel arrays and an array of
+/// `item`s (see [`IR::Zip`]): each property of `item` goes with the array
+/// of the same name, or of its name in the plural, or failing that the key
+/// matcher's best match. Each array goes with one property at most.
+fn zip_pairs(
+    arrays: &BTreeMap<Arc<String>, Arc<Schema>>,
+    item: &BTreeMap<Arc<String>, Arc<Schema>>,
+    matcher: Option<&KeyMatcher>,
+) -> Vec<(Arc<String>, Arc<String>)> {
+    let mut free: Vec<_> = arrays
+        .iter()
+        .filter(|(_, v)| matches!(v.bare(), Schema::Arr(_)))
+        .map(|(k, _)| k.clone())
+        .collect();
+    let mut pairs = vec![];
+    for k in item.keys() {
+        let named = free
+            .iter()
+            .position(|a| a == k || is_plural(k, a))
+            .or_else(|| {
+                let (best, _) = matcher?.best_match(k, &free)?;
+                free.iter().position(|a| a == best)
+            });
+        if let Some(i) = named {
+            pairs.push((free.remove(i), k.clone()));
+        }
+    }
+    pairs
+}
+
+/// Schema of `schema` after zipping the given pairs of its array properties
+/// and item properties (see [`IR::Zip`]), if it's an object with those
+/// arrays. Item properties from required arrays are required, since the
+/// arrays are meant to be parallel.
+pub fn zipped(schema: &Schema, pairs: &[(Arc<String>, Arc<String>)]) -> Option<Schema> {
+    let Schema::Obj {
+        props, required, ..
+    } = schema.bare()
+    else {
+        return None;
+    };
+    let items = pairs
+        .iter()
+        .map(
+            |(array, key)| match props.get(array).map(|array| array.bare()) {
+                Some(Schema::Arr(item)) => Some((key.clone(), item.clone())),
+                _ => None,
+            },
+        )
+        .collect::<Option<_>>()?;
+    let mut item = Schema::obj(items);
+    if let Schema::Obj { required: r, .. } = &mut item {
+        r.retain(|key| {
+            pairs
+                .iter()
+                .any(|(array, k)| k == key && required.contains(array))
+        });
+    }
+    Some(Schema::Arr(Arc::new(item)))
+}
+
+/// Schema of `schema` after unzipping it into the given pairs of array
+/// properties and item properties (see [`IR::Unzip`]), if it's an array of
+/// objects with those properties.
+pub fn unzipped(schema: &Schema, pairs: &[(Arc<String>, Arc<String>)]) -> Option<Schema> {
+    let Schema::Arr(item) = schema.bare() else {
+        return None;
+    };
+    let Schema::Obj { props, .. } = item.bare() else {
+        return None;
+    };
+    let arrays = pairs
+        .iter()
+        .map(|(array, key)| {
+            Some((
+                array.clone(),
+                Arc::new(Schema::Arr(props.get(key)?.clone())),
+            ))
+        })
+        .collect::<Option<_>>()?;
+    Some(Schema::obj(arrays))
+}
+
+/// Schema of an entry of an inverted object: `{"key": string, "value": value}`.
+fn entry_schema(value: Arc<Schema>) -> Schema {
+    Schema::obj(BTreeMap::from([
+        (Arc::new("key".to_string()), Arc::new(Schema::string())),
+        (Arc::new("value".to_string()), value),
+    ]))
+}
+
+/// Fewest independent subgoals worth spreading over several threads.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_GOALS: usize = 8;
+
+/// Searches for the cheapest transform between two schemas.
+#[derive(Default)]
+pub struct SchemaSearcher {
+    config: SearchConfig,
+    /// Memoized solutions (or the lack of one) for every pair of schemas
+    /// solved so far. Keyed by value, so a subschema repeated throughout a
+    /// large object tree is only ever solved once.
+    schema_rels: RefCell<HashMap<Rel, Result<Path, SearchErr>>>,
+    /// Memoized top-K solutions, along with the K they were computed for.
+    ranked_rels: RefCell<HashMap<Rel, (usize, Vec<Path>)>>,
+    /// Alternatives explored by the current search, shared with the
+    /// threads it spreads subgoals over so they count towards the same
+    /// budget.
+    explored: Arc<AtomicUsize>,
+    /// Current and deepest nesting of schemas in the current search.
+    depth: Cell<usize>,
+    deepest: Cell<usize>,
+    /// When the current search runs out of time.
+    deadline: Cell<Option<Instant>>,
+    /// Memoized lower bounds on the cost of each pair of schemas.
+    estimates: RefCell<HashMap<Rel, ExtNat>>,
+    /// Conversions between recursive schemas being searched for, innermost
+    /// last, each with the label its IR is bound to and whether anything
+    /// inside it has called it yet.
+    recursing: RefCell<Vec<(Rel, Arc<String>, bool)>>,
+}
+
+impl SchemaSearcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(config: SearchConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Find the cheapest IR path transforming data matching `lhs` into data
+    /// matching `rhs`.
+    pub fn find_path(&self, lhs: &Schema, rhs: &Schema) -> Result<Vec<IR>, SearchErr> {
+        self.solve(lhs, rhs).map(|path| path.ir)
+    }
+
+    /// Like [`SchemaSearcher::find_path`], but also classify how lossy each
+    /// step of the path is.
+    pub fn find_path_with_report(
+        &self,
+        lhs: &Schema,
+        rhs: &Schema,
+    ) -> Result<(Vec<IR>, PathReport), SearchErr> {
+        let ir = self.find_path(lhs, rhs)?;
+        let report = analyze(&ir, lhs);
+        Ok((ir, report))
+    }
+
+    /// Find transforms both from `lhs` to `rhs` and back again, preferring
+    /// steps which the other direction can undo. Steps which can't be undone
+    /// are flagged in the result.
+    pub fn find_bidirectional(&self, lhs: &Schema, rhs: &Schema) -> Result<RoundTrip, SearchErr> {
+        let invertible = |mapping: Mapping| SearchConfig {
+            mapping,
+            cost_model: Arc::new(PreferInvertible {
+                inner: self.config.cost_model.clone(),
+                penalty: ONE_WAY_PENALTY,
+            }),
+            ..self.config.clone()
+        };
+        let forward = SchemaSearcher::with_config(invertible(self.config.mapping.clone()));
+        let backward = SchemaSearcher::with_config(invertible(self.config.mapping.inverse()));
+        let (forward, forward_report) = forward.find_path_with_report(lhs, rhs)?;
+        let (backward, backward_report) = backward.find_path_with_report(rhs, lhs)?;
+        Ok(RoundTrip {
+            forward,
+            backward,
+            forward_report,
+            backward_report,
+        })
+    }
+
+    /// Find up to `k` distinct IR paths transforming data matching `lhs` into
+    /// data matching `rhs`, cheapest first. Useful when several mappings are
+    /// plausible and a person should pick between them. Fails only if the
+    /// search runs out of budget; with no paths at all, none are returned.
+    pub fn find_paths(&self, lhs: &Schema, rhs: &Schema, k: usize) -> Result<Vec<Path>, SearchErr> {
+        self.start();
+        self.solve_ranked(
+            Rel::with_mapping(
+                Arc::new(lhs.clone()),
+                Arc::new(rhs.clone()),
+                Arc::new(self.config.mapping.clone()),
+            ),
+            k,
+        )
+    }
+
+    fn solve_ranked(&self, rel: Rel, k: usize) -> Result<Vec<Path>, SearchErr> {
+        if let Some((solved_k, paths)) = self.ranked_rels.borrow().get(&rel) {
+            if *solved_k >= k {
+                return Ok(paths.iter().take(k).cloned().collect());
+            }
+        }
+        if let Some(call) = self.call(&rel) {
+            return Ok(vec![call]);
+        }
+
+        let depth = self.depth.get() + 1;
+        if self.config.limits.max_depth.is_some_and(|max| depth > max) {
+            return Err(self.exceeded(Limit::Depth));
+        }
+        self.depth.set(depth);
+        self.deepest.set(self.deepest.get().max(depth));
+        let ranked = self.unrolling(
+            &rel,
+            |rel| self.rank(rel, k),
+            |paths, label| {
+                paths
+                    .into_iter()
+                    .map(|path| path.recursive(label))
+                    .collect()
+            },
+        );
+        self.depth.set(depth - 1);
+        let paths = ranked?;
+
+        if !paths.iter().any(|path| self.open(&path.ir)) {
+            self.ranked_rels
+                .borrow_mut()
+                .insert(rel, (k, paths.clone()));
+        }
+        Ok(paths)
+    }
+
+    /// The `k` cheapest paths for `rel`, found by combining the cheapest
+    /// paths for the subgoals of every alternative.
+    fn rank(&self, rel: &Rel, k: usize) -> Result<Vec<Path>, SearchErr> {
+        let (mut alts, mut attempts) = self.alternatives(rel);
+        self.prune(&mut alts, &mut attempts);
+        let mut paths = vec![];
+        for alt in alts {
+            self.explore()?;
+            #[cfg(feature = "parallel")]
+            self.in_parallel(alt.goals.as_slice(), |worker, goal| {
+                let _ = worker.solve_ranked(goal.clone(), k);
+            });
+            let subs = alt
+                .goals
+                .map(|goal| self.solve_ranked(goal, k))
+                .collect::<Result<Vec<_>, _>>()?;
+            for (cost, choice) in cheapest_combinations(&subs, k) {
+                let subpaths = choice
+                    .iter()
+                    .zip(&subs)
+                    .map(|(i, sub)| sub[*i].ir.clone())
+                    .collect();
+                paths.push(Path {
+                    cost: alt.cost + cost,
+                    ir: alt.rule.clone().emit(subpaths),
+                });
+            }
+        }
+
+        // different rules can produce the same IR; keep the cheapest of each
+        paths.sort_by_key(|path| path.cost);
+        let mut seen = HashSet::new();
+        paths.retain(|path| seen.insert(path.ir.clone()));
+        paths.truncate(k);
+        Ok(paths)
+    }
+
+    /// Solve `lhs -> rhs` with an A* search over the applicable rules. Every
+    /// rule starts out costing its own step cost, and each time an
+    /// alternative is taken off the queue its next subgoal is solved and its
+    /// cost grows accordingly. Alternatives are ordered by their cost plus a
+    /// lower bound on the cost of their unsolved subgoals (see
+    /// `SchemaSearcher::estimate`), which never decreases, so the first
+    /// alternative popped with no subgoals left is the cheapest, and
+    /// expensive alternatives never have their subgoals explored at all.
+    pub fn solve(&self, lhs: &Schema, rhs: &Schema) -> Result<Path, SearchErr> {
+        self.start();
+        self.solve_rel(Rel::with_mapping(
+            Arc::new(lhs.clone()),
+            Arc::new(rhs.clone()),
+            Arc::new(self.config.mapping.clone()),
+        ))
+    }
+
+    fn solve_rel(&self, rel: Rel) -> Result<Path, SearchErr> {
+        if let Some(solved) = self.schema_rels.borrow().get(&rel) {
+            return solved.clone();
+        }
+        if let Some(call) = self.call(&rel) {
+            return Ok(call);
+        }
+
+        let depth = self.depth.get() + 1;
+        if self.config.limits.max_depth.is_some_and(|max| depth > max) {
+            return Err(self.exceeded(Limit::Depth));
+        }
+        self.depth.set(depth);
+        self.deepest.set(self.deepest.get().max(depth));
+        let solved = self.unrolling(&rel, |rel| self.search(rel), Path::recursive);
+        self.depth.set(depth - 1);
+
+        // running out of budget says nothing about whether a path exists,
+        // and a path calling an enclosing conversion only works inside it
+        let open = solved.as_ref().is_ok_and(|path| self.open(&path.ir));
+        if !matches!(solved, Err(SearchErr::BudgetExceeded(_))) && !open {
+            self.schema_rels.borrow_mut().insert(rel, solved.clone());
+        }
+        solved
+    }
+
+    /// A call back into the conversion for `rel`, if it's already being
+    /// searched for further out: the same pair of recursive schemas turned
+    /// up again inside themselves.
+    fn call(&self, rel: &Rel) -> Option<Path> {
+        let mut recursing = self.recursing.borrow_mut();
+        let (_, label, called) = recursing.iter_mut().rev().find(|(open, ..)| open == rel)?;
+        *called = true;
+        Some(Path {
+            cost: ExtNat::Nat(0),
+            ir: vec![IR::Call(label.clone())],
+        })
+    }
+
+    /// Solve `rel` with `solve`, unfolding either side first if it's
+    /// recursive so that its contents are searched for like any other
+    /// schema's. If they refer back to the whole, so that `rel` comes up
+    /// again inside itself, it's solved with a call back into the whole
+    /// conversion, and `wrap` binds the result to the label it calls.
+    fn unrolling<T>(
+        &self,
+        rel: &Rel,
+        solve: impl FnOnce(&Rel) -> Result<T, SearchErr>,
+        wrap: impl FnOnce(T, &Arc<String>) -> T,
+    ) -> Result<T, SearchErr> {
+        let name = |schema: &Schema| match schema {
+            Schema::Rec(name, _) => Some(name.clone()),
+            _ => None,
+        };
+        let Some(name) = name(&rel.lhs).or_else(|| name(&rel.rhs)) else {
+            return solve(rel);
+        };
+        if rel.lhs == rel.rhs {
+            return solve(rel);
+        }
+
+        // labels only have to differ from the ones they're nested in
+        let taken = |label: &str| {
+            self.recursing
+                .borrow()
+                .iter()
+                .any(|(_, open, _)| open.as_str() == label)
+        };
+        let label = (1..)
+            .map(|n| match n {
+                1 => name.to_string(),
+                n => format!("{name}{n}"),
+            })
+            .find(|label| !taken(label))
+            .expect("some label is free");
+        self.recursing
+            .borrow_mut()
+            .push((rel.clone(), Arc::new(label), false));
+        let unfolded = Rel::with_mapping(
+            Arc::new(rel.lhs.unfold()),
+            Arc::new(rel.rhs.unfold()),
+            rel.mapping.clone(),
+        );
+        let solved = solve(&unfolded);
+        let (_, label, called) = self.recursing.borrow_mut().pop().expect("pushed above");
+        match called {
+            true => solved.map(|solved| wrap(solved, &label)),
+            false => solved,
+        }
+    }
+
+    /// Whether `ir` calls a conversion it isn't itself inside, which only
+    /// happens partway through searching for that conversion.
+    fn open(&self, ir: &[IR]) -> bool {
+        if self.recursing.borrow().is_empty() {
+            return false;
+        }
+        let mut bound = vec![];
+        ir.iter().any(|op| match op {
+            IR::Rec(label) => {
+                bound.push(label);
+                false
+            }
+            IR::PopRec => {
+                bound.pop();
+                false
+            }
+            IR::Call(label) => !bound.contains(&label),
+            _ => false,
+        })
+    }
+
+    /// Solve many independent subgoals (such as the properties of a wide
+    /// object) up front on several threads, so that the search itself finds
+    /// them already memoized. Each thread solves its share with a searcher of
+    /// its own, whose memoized solutions are merged back in afterwards. The
+    /// threads carry on from the current depth, and count what they explore
+    /// towards the search's own budget, so they keep within its limits.
+    #[cfg(feature = "parallel")]
+    fn in_parallel(&self, goals: &[Rel], solve: impl Fn(&SchemaSearcher, &Rel) + Sync) {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        if goals.len() < PARALLEL_MIN_GOALS || threads < 2 {
+            return;
+        }
+
+        let config = &self.config;
+        let deadline = self.deadline.get();
+        let depth = self.depth.get();
+        let recursing = &*self.recursing.borrow();
+        let solve = &solve;
+        let workers: Vec<SchemaSearcher> = std::thread::scope(|scope| {
+            let handles: Vec<_> = goals
+                .chunks(goals.len().div_ceil(threads))
+                .map(|chunk| {
+                    let explored = self.explored.clone();
+                    scope.spawn(move || {
+                        let worker = SchemaSearcher {
+                            explored,
+                            ..SchemaSearcher::with_config(config.clone())
+                        };
+                        worker.deadline.set(deadline);
+                        worker.depth.set(depth);
+                        worker.recursing.replace(recursing.clone());
+                        for goal in chunk {
+                            solve(&worker, goal);
+                        }
+                        worker
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("search thread panicked"))
+                .collect()
+        });
+
+        for worker in workers {
+            self.deepest
+                .set(self.deepest.get().max(worker.deepest.get()));
+            self.schema_rels
+                .borrow_mut()
+                .extend(worker.schema_rels.into_inner());
+            let mut ranked = self.ranked_rels.borrow_mut();
+            for (rel, (k, paths)) in worker.ranked_rels.into_inner() {
+                if ranked.get(&rel).is_none_or(|(solved_k, _)| *solved_k < k) {
+                    ranked.insert(rel, (k, paths));
+                }
+            }
+        }
+    }
+
+    /// Reset the budget for a new search.
+    fn start(&self) {
+        self.explored.store(0, Ordering::Relaxed);
+        self.depth.set(0);
+        self.deepest.set(0);
+        let timeout = self.config.limits.timeout;
+        self.deadline
+            .set(timeout.map(|timeout| Instant::now() + timeout));
+    }
+
+    fn exceeded(&self, limit: Limit) -> SearchErr {
+        SearchErr::BudgetExceeded(Budget {
+            limit,
+            explored: self.explored(),
+            depth: self.deepest.get(),
+        })
+    }
+
+    /// Alternatives explored by the current search so far.
+    fn explored(&self) -> usize {
+        self.explored.load(Ordering::Relaxed)
+    }
+
+    /// Count one more explored alternative, failing if that's over budget.
+    fn explore(&self) -> Result<(), SearchErr> {
+        let limits = &self.config.limits;
+        let explored = self.explored.fetch_add(1, Ordering::Relaxed) + 1;
+        if limits.max_states.is_some_and(|max| explored > max) {
+            return Err(self.exceeded(Limit::States));
+        }
+        if self
+            .deadline
+            .get()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(self.exceeded(Limit::Time));
+        }
+        Ok(())
+    }
+
+    fn search(&self, rel: &Rel) -> Result<Path, SearchErr> {
+        let (mut alts, mut attempts) = self.alternatives(rel);
+        self.prune(&mut alts, &mut attempts);
+        let mut queue: BinaryHeap<_> = alts
+            .iter()
+            .enumerate()
+            .map(|(i, alt)| Reverse((self.priority(alt), i)))
+            .collect();
+
+        while let Some(Reverse((_, i))) = queue.pop() {
+            self.explore()?;
+            let alt = &mut alts[i];
+            // only once nothing else is left to take over from it is an
+            // alternative sure to be seen through, so that solving its
+            // subgoals ahead of time doesn't undo the ordering
+            #[cfg(feature = "parallel")]
+            if alt.solved.is_empty() && queue.is_empty() {
+                self.in_parallel(alt.goals.as_slice(), |worker, goal| {
+                    let _ = worker.solve_rel(goal.clone());
+                });
+            }
+            match alt.goals.next() {
+                None => {
+                    let solved = std::mem::take(&mut alt.solved);
+                    return Ok(Path {
+                        cost: alt.cost,
+                        ir: alt.rule.clone().emit(solved),
+                    });
+                }
+                Some(goal) => match self.solve_rel(goal) {
+                    Ok(sub) => {
+                        alt.cost += sub.cost;
+                        alt.solved.push(sub.ir);
+                        queue.push(Reverse((self.priority(alt), i)));
+                    }
+                    // an unsolvable subgoal rules out the whole alternative
+                    Err(SearchErr::NoPath(no_path)) => attempts.push(Attempt {
+                        rule: alt.rule.name(),
+                        step: alt.rule.step(alt.solved.len()),
+                        failure: Failure::Subgoal(Arc::new(no_path)),
+                    }),
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+
+        Err(SearchErr::NoPath(NoPath {
+            source: rel.lhs.clone(),
+            target: rel.rhs.clone(),
+            attempts,
+        }))
+    }
+
+    /// The cost of `alt` so far plus a lower bound on the cost of its
+    /// unsolved subgoals.
+    fn priority(&self, alt: &Alternative) -> ExtNat {
+        alt.goals
+            .as_slice()
+            .iter()
+            .fold(alt.cost, |acc, goal| acc + self.estimate(goal))
+    }
+
+    /// Under [`Strategy::Beam`], keep only the alternatives which look
+    /// cheapest, recording the rest as pruned.
+    fn prune(&self, alts: &mut Vec<Alternative>, attempts: &mut Vec<Attempt>) {
+        let Strategy::Beam(width) = self.config.strategy else {
+            return;
+        };
+        if alts.len() <= width {
+            return;
+        }
+        alts.sort_by_cached_key(|alt| self.priority(alt));
+        for alt in alts.drain(width..) {
+            attempts.push(Attempt {
+                rule: alt.rule.name(),
+                step: String::new(),
+                failure: Failure::Pruned,
+            });
+        }
+    }
+
+    /// A lower bound on the cost of converting `lhs` into `rhs`, computed
+    /// like an edit distance between the schemas but only counting steps
+    /// which every path has to take under the cost model: converting
+    /// mismatched grounds, wrapping grounds into arrays, and converting the
+    /// properties both objects have in common (which are always paired up
+    /// unless a mapping says otherwise). Where a shortcut such as extracting
+    /// a property or taking the first item might be cheaper, the estimate
+    /// takes the cheapest of them, and anything less certain counts for
+    /// nothing, so the estimate never overshoots.
+    fn estimate(&self, rel: &Rel) -> ExtNat {
+        use Schema::*;
+
+        if let Some(estimate) = self.estimates.borrow().get(rel) {
+            return *estimate;
+        }
+
+        let Rel { lhs, rhs, mapping } = rel;
+        let costs = &self.config.cost_model;
+        let estimate = match (lhs.as_ref(), rhs.as_ref()) {
+            _ if lhs == rhs || **rhs == True => ExtNat::Nat(0),
+            (Ground(g1), Ground(g2)) => costs.ground_to_ground(g1, g2),
+            (Ground(_), Arr(item)) => {
+                costs.wrap() + self.estimate(&Rel::new(lhs.clone(), item.clone()))
+            }
+            (Arr(s1), Arr(s2)) => {
+                let items = Rel::with_mapping(s1.clone(), s2.clone(), mapping.clone());
+                let first = Rel::with_mapping(s1.clone(), rhs.clone(), mapping.clone());
+                let wrap = Rel::with_mapping(lhs.clone(), s2.clone(), mapping.clone());
+                let flattened = (1..).map_while(|depth| flat(lhs, depth));
+                flattened.fold(
+                    self.estimate(&items)
+                        .min(costs.first() + self.estimate(&first))
+                        .min(costs.wrap() + self.estimate(&wrap)),
+                    |best, flat| {
+                        let rel = Rel::with_mapping(Arc::new(flat), rhs.clone(), mapping.clone());
+                        best.min(costs.flatten() + self.estimate(&rel))
+                    },
+                )
+            }
+            (Obj { props: o1, .. }, Obj { props: o2, .. }) if mapping.is_empty() => {
+                let common = o2
+                    .iter()
+                    .filter_map(|(k, v2)| Some(Rel::new(o1.get(k)?.clone(), v2.clone())))
+                    .fold(ExtNat::Nat(0), |acc, sub| acc + self.estimate(&sub));
+                o1.iter().fold(common, |best, (k, v1)| {
+                    let extr = Rel::new(v1.clone(), rhs.clone());
+                    best.min(costs.extract(k) + self.estimate(&extr))
+                })
+            }
+            _ => ExtNat::Nat(0),
+        };
+        self.estimates.borrow_mut().insert(rel.clone(), estimate);
+        estimate
+    }
+
+    /// The single instruction converting `v1` data into `v2` data, and its
+    /// cost, if their annotations or the mapping's entry for the property
+    /// `prop` they're at call for one: dates written down differently are
+    /// converted, as are numbers in different units (or which the mapping
+    /// scales); strings base64 encoded on only one side are encoded or
+    /// decoded; parts of strings the mapping extracts are matched out of
+    /// them; strings the mapping converts to another case are converted, as
+    /// are enumerations whose values only differ by their case; and
+    /// enumerations with different values, or which the mapping translates,
+    /// are looked up.
+    fn conversion(
+        &self,
+        v1: &Schema,
+        v2: &Schema,
+        prop: Option<(&Arc<String>, &Mapping)>,
+    ) -> Option<(ExtNat, IR)> {
+        let costs = &self.config.cost_model;
+        let matcher = self.config.key_matcher.as_ref();
+        let (key, mapping) = match prop {
+            Some((key, mapping)) => (key.as_str(), Some(mapping)),
+            None => ("", None),
+        };
+        let (a1, a2) = (v1.annotations(), v2.annotations());
+        let (v1, v2) = (v1.bare(), v2.bare());
+        let string = |s: &Schema| matches!(s, Schema::Ground(Ground::String) | Schema::True);
+
+        let dated = || {
+            let (Schema::Ground(g1), Schema::Ground(g2)) = (v1, v2) else {
+                return None;
+            };
+            let from_format = units::date_format(
+                g1,
+                a1.format.as_deref().map(String::as_str),
+                a1.unit.as_deref().map(String::as_str),
+            )?;
+            let to_format = units::date_format(
+                g2,
+                a2.format.as_deref().map(String::as_str),
+                a2.unit.as_deref().map(String::as_str),
+            )?;
+            (from_format != to_format).then_some(IR::DateConvert {
+                from_format,
+                to_format,
+            })
+        };
+        let scaled = || {
+            let (Schema::Ground(Ground::Num), Schema::Ground(Ground::Num)) = (v1, v2) else {
+                return None;
+            };
+            let (factor, offset) = match mapping.and_then(|m| m.scale_for(key)) {
+                Some(scale) => scale,
+                // the same unit needs no converting
+                None => units::conversion(a1.unit.as_deref()?, a2.unit.as_deref()?)
+                    .filter(|scale| *scale != (Real(1.0), Real(0.0)))?,
+            };
+            Some(IR::Scale { factor, offset })
+        };
+        let encoded = || {
+            if !string(v1) || !string(v2) {
+                return None;
+            }
+            let base64 = |a: &Annotations| {
+                a.encoding
+                    .as_ref()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("base64"))
+            };
+            match (base64(a1), base64(a2)) {
+                (false, true) => Some(IR::Base64(Coding::Encode)),
+                (true, false) => Some(IR::Base64(Coding::Decode)),
+                _ => None,
+            }
+        };
+        let extracted = || {
+            let (pattern, group) = mapping?.pattern_for(key)?;
+            (string(v1) && string(v2)).then(|| IR::RegexExtract(pattern.to_string(), group))
+        };
+        let cased = || {
+            let Schema::Ground(Ground::String) = v1 else {
+                return None;
+            };
+            let case = match (mapping.and_then(|m| m.case_for(key)), &a1.values) {
+                (Some(case), _) => case,
+                (None, Some(values)) => value_case(values, a2.values.as_ref()?)?,
+                (None, None) => return None,
+            };
+            Some(IR::CaseTransform(case))
+        };
+        let mapped = || {
+            let table = match (mapping.and_then(|m| m.values_for(key)), &a1.values) {
+                (Some(table), None) => table.clone(),
+                // every source value needs a translation, which the mapping
+                // gives as strings
+                (Some(table), Some(values)) => values
+                    .iter()
+                    .map(|v1| {
+                        let v2 = table
+                            .get(v1)
+                            .or_else(|| table.get(&Literal(v1.0.to_string().into())))?;
+                        Some((v1.clone(), v2.clone()))
+                    })
+                    .collect::<Option<_>>()?,
+                (None, Some(values)) => value_table(values, a2.values.as_ref()?, matcher)?,
+                (None, None) => return None,
+            };
+            Some(IR::MapValues(table))
+        };
+        dated()
+            .map(|op| (costs.convert_date(key), op))
+            .or_else(|| scaled().map(|op| (costs.scale(key), op)))
+            .or_else(|| encoded().map(|op| (costs.transcode(key), op)))
+            .or_else(|| extracted().map(|op| (costs.extract_pattern(key), op)))
+            .or_else(|| cased().map(|op| (costs.change_case(key), op)))
+            .or_else(|| mapped().map(|op| (costs.map_values(key), op)))
+    }
+
+    /// Convert between schemas either of which is annotated: with the
+    /// instruction their annotations call for, if any, or otherwise by
+    /// converting the schemas they annotate, leaving out duplicates first
+    /// if the target wants unique items and cutting down arrays with more
+    /// items than it allows.
+    fn annotated_alternative(&self, rel: &Rel) -> Alternative {
+        let Rel { lhs, rhs, mapping } = rel;
+        let costs = &self.config.cost_model;
+        if let Some((cost, op)) = self.conversion(lhs, rhs, None) {
+            return Alternative::new(cost, Rule::Annotated(vec![op]), vec![]);
+        }
+        let (a1, a2) = (lhs.annotations(), rhs.annotations());
+        let mut cost = ExtNat::Nat(0);
+        let mut arrange = vec![];
+        if let (Schema::Arr(_), Schema::Arr(_)) = (lhs.bare(), rhs.bare()) {
+            if a2.unique_items && !a1.unique_items {
+                cost += costs.dedupe("");
+                arrange.push(IR::Dedupe(None));
+            }
+            if let Some(max) = a2
+                .max_items
+                .filter(|max| a1.max_items.is_none_or(|m1| m1 > *max))
+            {
+                cost += costs.slice("");
+                arrange.push(IR::Slice(0, Some(max)));
+            }
+        }
+        let bare = Rel::with_mapping(unannotated(lhs), unannotated(rhs), mapping.clone());
+        Alternative::new(cost, Rule::Annotated(arrange), vec![bare])
+    }
+
+    /// Convert an object property-wise. Every required target property needs
+    /// a source property to come from: the one pinned by the mapping, the one
+    /// of the same name, the best match proposed by the key matcher, a
+    /// property nested inside another object whose key path matches (so
+    /// `address.city` can become `address_city`), or for target objects,
+    /// source properties prefixed with its name (so `address_city` can
+    /// become `address.city`). Target properties with no such source are
+    /// filled with the value the mapping gives them, or required ones with
+    /// the target schema's `const` or `default`; otherwise required ones fail
+    /// and optional ones are left out. Unused source properties are dropped,
+    /// and properties are built in the configured [`KeyOrder`].
+    fn obj_alternative(
+        &self,
+        lhs: &Schema,
+        rhs: &Schema,
+        mapping: &Mapping,
+    ) -> Result<Alternative, Failure> {
+        let (
+            Schema::Obj {
+                props: o1,
+                required: r1,
+                additional: a1,
+                ..
+            },
+            Schema::Obj {
+                props: o2,
+                required: r2,
+                additional: a2,
+                ..
+            },
+        ) = (lhs, rhs)
+        else {
+            unreachable!("object rule converts objects")
+        };
+        let costs = &self.config.cost_model;
+        let matcher = self.config.key_matcher.as_ref();
+        let mut cost = ExtNat::Nat(0);
+        let mut props: Vec<PropMatch> = vec![];
+        let mut goals = vec![];
+        // filled, concatenated, scaled and translated properties have no
+        // goal, so they're kept apart until the end
+        let mut fills = vec![];
+        let optional = |k1: &Arc<String>| !r1.contains(k1);
+        // properties converted with a single operation have no goal
+        let converted = |k1: &Arc<String>, k2: &Arc<String>| {
+            let (cost, op) = self.conversion(&o1[k1], &o2[k2], Some((k2, mapping)))?;
+            let source = PropSource::Converted {
+                from: k1.clone(),
+                optional: optional(k1),
+                op,
+            };
+            Some((
+                cost,
+                PropMatch {
+                    to: k2.clone(),
+                    source,
+                },
+            ))
+        };
+        // the part of the source object made of `keys`, to build a nested
+        // target object from
+        let project = |keys: &[Arc<String>]| Schema::Obj {
+            props: keys.iter().map(|k| (k.clone(), o1[k].clone())).collect(),
+            required: keys.iter().filter(|k| r1.contains(*k)).cloned().collect(),
+            additional: None,
+            closed: false,
+        };
+
+        // pinned properties go first, so that they get first pick of the
+        // source properties
+        for k2 in o2.keys() {
+            match mapping.get(k2) {
+                Some((Some(k1), sub)) => {
+                    let v1 = o1
+                        .get(k1)
+                        .ok_or_else(|| Failure::MissingSource(k1.clone()))?;
+                    if let Some((c, prop)) = converted(k1, k2) {
+                        cost += c;
+                        fills.push(prop);
+                    } else if mapping.scale_for(k2).is_some() {
+                        return Err(Failure::Unscalable(k2.clone()));
+                    } else if mapping.values_for(k2).is_some() || mapping.case_for(k2).is_some() {
+                        return Err(Failure::Unmappable(k2.clone()));
+                    } else if mapping.pattern_for(k2).is_some() {
+                        return Err(Failure::Unextractable(k2.clone()));
+                    } else if mapping.slice_for(k2).is_some()
+                        && !matches!((v1.bare(), o2[k2].bare()), (Schema::Arr(_), Schema::Arr(_)))
+                    {
+                        return Err(Failure::Unsliceable(k2.clone()));
+                    } else if mapping.sort_for(k2).is_some_and(|(path, _)| {
+                        !sortable(v1, path) || !matches!(o2[k2].bare(), Schema::Arr(_))
+                    }) {
+                        return Err(Failure::Unsortable(k2.clone()));
+                    } else {
+                        props.push(PropMatch::key(k1.clone(), k2.clone(), optional(k1)));
+                        goals.push(Rel::with_mapping(v1.clone(), o2[k2].clone(), sub.clone()));
+                    }
+                }
+                Some((None, sub)) => {
+                    let keys = sub.sources();
+                    if let Some(k1) = keys.iter().find(|k1| !o1.contains_key(*k1)) {
+                        return Err(Failure::MissingSource(k1.clone()));
+                    }
+                    cost += costs.nest(k2);
+                    goals.push(Rel::with_mapping(
+                        Arc::new(project(&keys)),
+                        o2[k2].clone(),
+                        sub.clone(),
+                    ));
+                    props.push(PropMatch {
+                        to: k2.clone(),
+                        source: PropSource::Nest(keys),
+                    });
+                }
+                None => {}
+            }
+            if let Some(sources) = mapping.coalesce_for(k2) {
+                let mut schema = Schema::False;
+                for k1 in sources {
+                    let v1 = o1
+                        .get(k1)
+                        .ok_or_else(|| Failure::MissingSource(k1.clone()))?;
+                    schema = unify(&schema, v1);
+                }
+                cost += costs.coalesce(k2);
+                goals.push(Rel::new(Arc::new(schema), o2[k2].clone()));
+                props.push(PropMatch {
+                    to: k2.clone(),
+                    source: PropSource::Coalesce {
+                        sources: sources.to_vec(),
+                        optional: sources.iter().all(optional),
+                    },
+                });
+            }
+            if let Some(concat) = mapping
+                .concat_for(k2)
+                .filter(|_| matches!(o2[k2].bare(), Schema::Arr(_)))
+            {
+                // arrays are concatenated into one
+                let mut schema = Schema::False;
+                for k1 in &concat.sources {
+                    match o1.get(k1) {
+                        None => return Err(Failure::MissingSource(k1.clone())),
+                        Some(v1) if matches!(v1.bare(), Schema::Arr(_)) => {
+                            schema = unify(&schema, v1)
+                        }
+                        Some(_) => return Err(Failure::Unconcatenable(k1.clone())),
+                    }
+                }
+                cost += costs.gather(k2);
+                goals.push(Rel::new(Arc::new(schema), o2[k2].clone()));
+                props.push(PropMatch {
+                    to: k2.clone(),
+                    source: PropSource::Gathered {
+                        sources: concat.sources.clone(),
+                        arrange: vec![],
+                    },
+                });
+            } else if let Some(concat) = mapping.concat_for(k2) {
+                if !matches!(o2[k2].bare(), Schema::Ground(Ground::String) | Schema::True) {
+                    return Err(Failure::Unconcatenable(k2.clone()));
+                }
+                for k1 in &concat.sources {
+                    match o1.get(k1).map(|v1| v1.bare()) {
+                        None => return Err(Failure::MissingSource(k1.clone())),
+                        Some(Schema::Ground(_)) => {}
+                        Some(_) => return Err(Failure::Unconcatenable(k1.clone())),
+                    }
+                }
+                cost += costs.concat(k2);
+                fills.push(PropMatch {
+                    to: k2.clone(),
+                    source: PropSource::Concat(concat.clone()),
+                });
+            }
+        }
+
+        let flat = flattened(o1);
+        for k2 in o2.keys() {
+            let used =
+                |k1: &Arc<String>| props.iter().chain(&fills).any(|prop| prop.reads_whole(k1));
+            if mapping.get(k2).is_some()
+                || mapping.concat_for(k2).is_some()
+                || mapping.coalesce_for(k2).is_some()
+            {
+                continue;
+            }
+
+            if o1.contains_key(k2) && !used(k2) {
+                if let Some((c, prop)) = converted(k2, k2) {
+                    cost += c;
+                    fills.push(prop);
+                } else {
+                    props.push(PropMatch::key(k2.clone(), k2.clone(), optional(k2)));
+                    goals.push(Rel::new(o1[k2].clone(), o2[k2].clone()));
+                }
+                continue;
+            }
+
+            // only source properties which wouldn't otherwise be used can
+            // be renamed
+            let candidates = o1
+                .keys()
+                .filter(|k1| !o2.contains_key(*k1) && !used(k1))
+                .map(|k1| {
+                    (
+                        k1,
+                        o1[k1].annotations().format.as_deref().map(String::as_str),
+                    )
+                });
+            let format = o2[k2].annotations().format.as_deref().map(String::as_str);
+            let rename = |k1: &str, score| costs.rename(k1, k2, score);
+            if let Some((k1, score)) =
+                matcher.and_then(|m| m.cheapest_format_match(k2, format, candidates, rename))
+            {
+                cost += costs.rename(k1, k2, score);
+                if let Some((c, prop)) = converted(k1, k2) {
+                    cost += c;
+                    fills.push(prop);
+                } else {
+                    props.push(PropMatch::key(k1.clone(), k2.clone(), optional(k1)));
+                    goals.push(Rel::new(o1[k1].clone(), o2[k2].clone()));
+                }
+                continue;
+            }
+
+            let nested = flat.iter().filter(|(_, k1, path)| {
+                !o2.contains_key(k1)
+                    && !props.iter().any(|prop| {
+                        prop.reads_under(k1)
+                            .is_some_and(|p| p.is_empty() || p == path)
+                    })
+            });
+            let flattened = match matcher {
+                Some(m) => m
+                    .best_match(k2, nested.clone().map(|(name, _, _)| name))
+                    .and_then(|(name, score)| {
+                        let (_, k1, path) = nested.clone().find(|(n, _, _)| n == name)?;
+                        Some((k1, path, score))
+                    }),
+                None => nested
+                    .clone()
+                    .find(|(name, _, _)| name == k2)
+                    .map(|(_, k1, path)| (k1, path, 1)),
+            };
+            if let Some((k1, path, score)) = flattened {
+                cost += costs.rename(k1, k2, score);
+                let mut v1 = o1[k1].clone();
+                for (key, _) in path {
+                    cost += costs.extract(key);
+                    v1 = match v1.bare() {
+                        Schema::Obj { props, .. } => props[key].clone(),
+                        _ => unreachable!("flattened paths go through objects"),
+                    };
+                }
+                props.push(PropMatch::flattened(
+                    k1.clone(),
+                    k2.clone(),
+                    optional(k1),
+                    path.clone(),
+                ));
+                goals.push(Rel::new(v1, o2[k2].clone()));
+                continue;
+            }
+
+            if let Schema::Obj { props: inner, .. } = o2[k2].bare() {
+                let prefix = format!("{}_", k2);
+                let prefixed: Vec<_> = o1
+                    .keys()
+                    .filter(|k1| k1.starts_with(&prefix) && !o2.contains_key(*k1) && !used(k1))
+                    .collect();
+                let mut sub = Mapping::default();
+                let mut keys = vec![];
+                for k in inner.keys() {
+                    let rest = |k1: &&Arc<String>| k1[prefix.len()..].to_string();
+                    let found = prefixed.iter().find(|k1| match matcher {
+                        Some(m) => m.score(&rest(k1), k).is_some(),
+                        None => rest(k1) == **k,
+                    });
+                    if let Some(k1) = found {
+                        if sub.insert(k1, k).is_ok() {
+                            keys.push((*k1).clone());
+                        }
+                    }
+                }
+                if !keys.is_empty() {
+                    cost += costs.nest(k2);
+                    goals.push(Rel::with_mapping(
+                        Arc::new(project(&keys)),
+                        o2[k2].clone(),
+                        Arc::new(sub),
+                    ));
+                    props.push(PropMatch {
+                        to: k2.clone(),
+                        source: PropSource::Nest(keys),
+                    });
+                    continue;
+                }
+            }
+
+            let default = o2[k2].annotations().default.as_ref();
+            let default = default.filter(|_| r2.contains(k2));
+            if let Some(value) = mapping.fill_for(k2).or(default) {
+                cost += costs.fill(k2);
+                fills.push(PropMatch {
+                    to: k2.clone(),
+                    source: PropSource::Const(value.clone()),
+                });
+                continue;
+            }
+
+            if r2.contains(k2) {
+                return Err(Failure::Unmatched(k2.clone()));
+            }
+            cost += costs.skip_field(k2);
+        }
+
+        // arrays the mapping dedupes (or which the target wants unique), or
+        // which are concatenated, leave out duplicates; those it sorts are
+        // sorted; and those it slices, or with more items than the target
+        // allows, are cut down, which leaves nothing for their goals to
+        // arrange
+        for (prop, goal) in props.iter_mut().zip(goals.iter_mut()) {
+            let to = &prop.to;
+            let (from, arrange) = match &mut prop.source {
+                PropSource::Key {
+                    from,
+                    path,
+                    arrange,
+                    ..
+                } if path.is_empty()
+                    && matches!(
+                        (o1[from].bare(), o2[to].bare()),
+                        (Schema::Arr(_), Schema::Arr(_))
+                    ) =>
+                {
+                    (Some(&*from), arrange)
+                }
+                PropSource::Gathered { arrange, .. } => (None, arrange),
+                _ => continue,
+            };
+            let (a1, a2) = (from.map(|k1| o1[k1].annotations()), o2[to].annotations());
+            let unique = match mapping.unique_for(to) {
+                Some(path) => Some((!path.is_empty()).then(|| path.to_vec())),
+                None => (a1.is_none_or(|a1| a2.unique_items && !a1.unique_items)).then_some(None),
+            };
+            if let Some(path) = unique {
+                cost += costs.dedupe(to);
+                arrange.push(IR::Dedupe(path));
+            }
+            if let Some((by, order)) = mapping.sort_for(to) {
+                cost += costs.sort(to);
+                arrange.push(IR::Sort(by.to_vec(), order));
+            }
+            let slice = mapping.slice_for(to).or_else(|| {
+                let max = a2.max_items?;
+                a1.and_then(|a1| a1.max_items)
+                    .is_none_or(|m1| m1 > max)
+                    .then_some((0, Some(max)))
+            });
+            if let Some((start, end)) = slice {
+                cost += costs.slice(to);
+                arrange.push(IR::Slice(start, end));
+            }
+            goal.rhs = arranged(&goal.rhs);
+        }
+
+        let mut dropped = vec![];
+        for (k1, v1) in o1.iter() {
+            let uses: Vec<_> = props
+                .iter()
+                .chain(&fills)
+                .filter_map(|prop| prop.reads_under(k1))
+                .collect();
+            if uses.is_empty() {
+                cost += costs.drop_field(k1);
+                dropped.push(k1.clone());
+            } else if let (Schema::Obj { props: nested, .. }, false) =
+                (v1.as_ref(), uses.iter().any(|path| path.is_empty()))
+            {
+                // only flattened in part, so whatever wasn't is dropped
+                for key in nested.keys() {
+                    if !uses.iter().any(|path| &path[0].0 == key) {
+                        cost += costs.drop_field(key);
+                        dropped.push(Arc::new(join_path(k1, key)));
+                    }
+                }
+            }
+        }
+
+        // properties neither side names are converted in a loop, as long as
+        // both sides allow them and their schemas are compatible
+        let rest = match (a1, a2) {
+            (Some(a1), Some(a2)) => Some(Rel::new(a1.clone(), a2.clone())),
+            _ => None,
+        }
+        .filter(|rest| self.solve_rel(rest.clone()).is_ok());
+        if let Some(rest) = rest {
+            let named: BTreeSet<_> = o1.keys().chain(o2.keys()).cloned().collect();
+            props.push(PropMatch {
+                to: Arc::new(REST.to_string()),
+                source: PropSource::Rest(named.into_iter().collect()),
+            });
+            goals.push(rest);
+        } else if a1.is_some() {
+            cost += costs.drop_field(REST);
+            dropped.push(Arc::new(REST.to_string()));
+        }
+        self.check_dropped(dropped, mapping)?;
+
+        // required properties coming from ones which may be missing fall
+        // back on a fill or default, written first
+        let mut fallbacks = vec![];
+        for prop in props.iter().chain(&fills) {
+            if !prop.may_be_missing() || !r2.contains(&prop.to) {
+                continue;
+            }
+            let default = o2[&prop.to].annotations().default.as_ref();
+            let Some(value) = mapping.fill_for(&prop.to).or(default) else {
+                return Err(Failure::Unguaranteed(prop.to.clone()));
+            };
+            cost += costs.fill(&prop.to);
+            fallbacks.push(PropMatch {
+                to: prop.to.clone(),
+                source: PropSource::Const(value.clone()),
+            });
+        }
+        fills.extend(fallbacks);
+
+        // emit properties in target order, regardless of how they were matched
+        let mut props: Vec<_> = props
+            .into_iter()
+            .zip(goals.into_iter().map(Some))
+            .chain(fills.into_iter().map(|fill| (fill, None)))
+            .collect();
+        let order = self.config.key_order.of(rhs);
+        let position = |k: &Arc<String>| {
+            order
+                .and_then(|order| order.iter().position(|o| o == k))
+                .unwrap_or(usize::MAX)
+        };
+        // the rest go last, so that they can't overwrite named properties,
+        // and fallbacks go before what they fall back from
+        let rank = |prop: &PropMatch| {
+            let rest = matches!(prop.source, PropSource::Rest(_));
+            let fallback = matches!(prop.source, PropSource::Const(_));
+            (rest, position(&prop.to), prop.to.clone(), !fallback)
+        };
+        props.sort_by_key(|(prop, _)| rank(prop));
+        let (props, goals): (_, Vec<_>) = props.into_iter().unzip();
+        let goals = goals.into_iter().flatten().collect();
+        Ok(Alternative::new(cost, Rule::Obj(props), goals))
+    }
+
+    /// In a strict search, fail if any of the `dropped` source properties
+    /// isn't waived by `mapping`.
+    fn check_dropped(
+        &self,
+        mut dropped: Vec<Arc<String>>,
+        mapping: &Mapping,
+    ) -> Result<(), Failure> {
+        dropped.retain(|k| !mapping.is_waived(k));
+        if self.config.strict && !dropped.is_empty() {
+            return Err(Failure::Dropped(dropped));
+        }
+        Ok(())
+    }
+
+    /// All rules which could convert `lhs` into `rhs`, with their step costs,
+    /// along with the rules which were ruled out straight away.
+    fn alternatives(&self, rel: &Rel) -> (Vec<Alternative>, Vec<Attempt>) {
+        use Schema::*;
+
+        let Rel { lhs, rhs, mapping } = rel;
+        let costs = &self.config.cost_model;
+        let mut alts = vec![];
+        let mut rejected = vec![];
+        // copying would ignore anything pinned beneath here
+        if (lhs == rhs && mapping.is_empty()) || **rhs == True {
+            alts.push(Alternative::new(ExtNat::Nat(0), Rule::Copy, vec![]));
+        }
+        // everything else converts the schemas annotations are on
+        if let (Annotated(..), _) | (_, Annotated(..)) = (lhs.as_ref(), rhs.as_ref()) {
+            if alts.is_empty() {
+                alts.push(self.annotated_alternative(rel));
+            }
+            return (alts, rejected);
+        }
+
+        if let Union(branches) = rhs.as_ref() {
+            for branch in branches {
+                alts.push(Alternative::new(
+                    ExtNat::Nat(0),
+                    Rule::Variant,
+                    vec![Rel::with_mapping(
+                        lhs.clone(),
+                        branch.clone(),
+                        mapping.clone(),
+                    )],
+                ));
+            }
+        }
+        if let Union(branches) = lhs.as_ref() {
+            // every branch has to convert, since any of them might show up
+            let goals = branches
+                .iter()
+                .map(|branch| Rel::with_mapping(branch.clone(), rhs.clone(), mapping.clone()))
+                .collect();
+            alts.push(Alternative::new(
+                costs.dispatch(),
+                Rule::Dispatch(branches.clone()),
+                goals,
+            ));
+        }
+
+        match (lhs.as_ref(), rhs.as_ref()) {
+            (Ground(g1), Ground(g2)) if g1 != g2 => {
+                alts.push(Alternative::new(
+                    costs.ground_to_ground(g1, g2),
+                    Rule::G2G(g1.clone(), g2.clone()),
+                    vec![],
+                ));
+            }
+            (Arr(s1), Arr(s2)) => {
+                // flattening goes first, so that it wins ties with taking the
+                // first item of each nested array
+                let flattened = (1..).map_while(|depth| Some((depth, flat(lhs, depth)?)));
+                for (depth, flat) in flattened {
+                    alts.push(Alternative::new(
+                        costs.flatten(),
+                        Rule::Flatten(depth),
+                        vec![Rel::with_mapping(
+                            Arc::new(flat),
+                            rhs.clone(),
+                            mapping.clone(),
+                        )],
+                    ));
+                }
+                alts.push(Alternative::new(
+                    ExtNat::Nat(0),
+                    Rule::Arr,
+                    vec![Rel::with_mapping(s1.clone(), s2.clone(), mapping.clone())],
+                ));
+            }
+            (Obj { .. }, Obj { .. }) => match self.obj_alternative(lhs, rhs, mapping) {
+                Ok(alt) => alts.push(alt),
+                Err(failure) => rejected.push(Attempt {
+                    rule: Rule::Obj(vec![]).name(),
+                    step: String::new(),
+                    failure,
+                }),
+            },
+            (Obj { props: o1, .. }, Ground(_)) => {
+                // nested objects are searched for a ground in turn, paying for
+                // each level extracted
+                for (k, v1) in o1.iter() {
+                    if let Ground(_) | Obj { .. } = v1.bare() {
+                        let others = o1.keys().filter(|k1| *k1 != k).cloned().collect();
+                        if let Err(failure) = self.check_dropped(others, mapping) {
+                            rejected.push(Attempt {
+                                rule: Rule::Extr(k.clone()).name(),
+                                step: String::new(),
+                                failure,
+                            });
+                            continue;
+                        }
+                        alts.push(Alternative::new(
+                            costs.extract(k),
+                            Rule::Extr(k.clone()),
+                            vec![Rel::new(v1.clone(), rhs.clone())],
+                        ));
+                    }
+                }
+            }
+            (
+                Obj {
+                    props: o1,
+                    additional: a1,
+                    ..
+                },
+                Arr(_),
+            ) => {
+                // every entry is converted by the same code, so this only
+                // works when all the properties share a schema
+                if let (Some(value), Arr(item)) = (a1, rhs.as_ref()) {
+                    // the items' keys come back out of the object's keys
+                    let keys = match (value.bare(), item.bare()) {
+                        (Obj { props: v1, .. }, Obj { props: v2, .. }) if o1.is_empty() => v2
+                            .iter()
+                            .filter(|(k, v)| !v1.contains_key(*k) && matches!(v.bare(), Ground(_)))
+                            .map(|(k, _)| k.clone())
+                            .collect(),
+                        _ => vec![],
+                    };
+                    for key in keys {
+                        if let Some(ungrouped) = grouped(lhs, &key) {
+                            alts.push(Alternative::new(
+                                costs.group_by(),
+                                Rule::GroupBy(key),
+                                vec![Rel::new(Arc::new(ungrouped), rhs.clone())],
+                            ));
+                        }
+                    }
+                }
+                // parallel arrays are zipped into objects
+                if let Arr(item) = rhs.as_ref() {
+                    if let Obj { props: i2, .. } = item.bare() {
+                        let pairs = zip_pairs(o1, i2, self.config.key_matcher.as_ref());
+                        if let (false, Some(zipped)) = (pairs.is_empty(), zipped(lhs, &pairs)) {
+                            alts.push(Alternative::new(
+                                costs.zip(),
+                                Rule::Zip(pairs),
+                                vec![Rel::new(Arc::new(zipped), rhs.clone())],
+                            ));
+                        }
+                    }
+                }
+                // as do the additional properties, whatever they're called
+                let mut values = o1.values().chain(a1);
+                if let Some(first) = values.next() {
+                    if values.all(|v| v == first) {
+                        let entries = Arr(Arc::new(entry_schema(first.clone())));
+                        alts.push(Alternative::new(
+                            costs.invert(),
+                            Rule::Inv,
+                            vec![Rel::new(Arc::new(entries), rhs.clone())],
+                        ));
+                    }
+                }
+            }
+            (
+                Arr(item),
+                Obj {
+                    props: o2,
+                    additional: a2,
+                    ..
+                },
+            ) => {
+                // inverting goes first, so that it wins ties with grouping
+                // the entries by their keys
+                if let Obj { props: entry, .. } = item.bare() {
+                    let key = entry.get(&"key".to_string());
+                    let value = entry.get(&"value".to_string());
+                    if let (Some(key), Some(value), 2) = (key, value, entry.len()) {
+                        if *key.bare() == Schema::string() {
+                            // any key may be missing from the entries, and
+                            // the rest become additional properties
+                            let inverted = Obj {
+                                props: o2.keys().map(|k| (k.clone(), value.clone())).collect(),
+                                required: BTreeSet::new(),
+                                additional: a2.as_ref().map(|_| value.clone()),
+                                closed: false,
+                            };
+                            alts.push(Alternative::new(
+                                costs.invert(),
+                                Rule::Inv,
+                                vec![Rel::new(Arc::new(inverted), rhs.clone())],
+                            ));
+                        }
+                    }
+                }
+                if let Some(value) = a2 {
+                    for key in group_keys(item, value) {
+                        if let Some(grouped) = grouped(lhs, &key) {
+                            alts.push(Alternative::new(
+                                costs.group_by(),
+                                Rule::GroupBy(key),
+                                vec![Rel::new(Arc::new(grouped), rhs.clone())],
+                            ));
+                        }
+                    }
+                }
+                // and objects unzipped into parallel arrays
+                if let Obj { props: i1, .. } = item.bare() {
+                    let pairs = zip_pairs(o2, i1, self.config.key_matcher.as_ref());
+                    if let (false, Some(unzipped)) = (pairs.is_empty(), unzipped(lhs, &pairs)) {
+                        alts.push(Alternative::new(
+                            costs.zip(),
+                            Rule::Unzip(pairs),
+                            vec![Rel::new(Arc::new(unzipped), rhs.clone())],
+                        ));
+                    }
+                }
+            }
+            (Ground(_), Arr(item)) => {
+                alts.push(Alternative::new(
+                    costs.wrap(),
+                    Rule::Wrap,
+                    vec![Rel::new(lhs.clone(), item.clone())],
+                ));
+            }
+            (Arr(item), Ground(g)) => match (&self.config.arr_to_ground, item.bare()) {
+                (ArrToGround::Never, _) => {}
+                (ArrToGround::Join(sep), Ground(item_g)) if *g == self::Ground::String => {
+                    // items which aren't strings already need converting too
+                    let mut cost = costs.join();
+                    if item_g != g {
+                        cost += costs.ground_to_ground(item_g, g);
+                    }
+                    alts.push(Alternative::new(cost, Rule::Join(sep.clone()), vec![]));
+                }
+                _ => {
+                    alts.push(Alternative::new(
+                        costs.first(),
+                        Rule::First,
+                        vec![Rel::new(item.clone(), rhs.clone())],
+                    ));
+                }
+            },
+            _ => {}
+        }
+
+        // forbidden conversions would never finish, so don't bother
+        alts.retain(|alt| {
+            if alt.cost == ExtNat::Inf {
+                rejected.push(Attempt {
+                    rule: alt.rule.name(),
+                    step: String::new(),
+                    failure: Failure::Forbidden,
+                });
+            }
+            alt.cost != ExtNat::Inf
+        });
+        (alts, rejected)
+    }
+}
+
+/// A transform kept up to date as its schemas are edited, for tools which
+/// recompute it on every change. Solutions are memoized by the schemas
+/// involved, so after a small edit only the subschemas enclosing it are
+/// searched again, and everything else is found already solved.
+pub struct IncrementalSearch {
+    searcher: SchemaSearcher,
+    lhs: Schema,
+    rhs: Schema,
+    path: Result<Path, SearchErr>,
+}
+
+impl IncrementalSearch {
+    /// Search for a transform from `lhs` to `rhs` with `searcher`, which
+    /// keeps its memoized solutions for the searches after each edit.
+    pub fn new(searcher: SchemaSearcher, lhs: Schema, rhs: Schema) -> Self {
+        let path = searcher.solve(&lhs, &rhs);
+        Self {
+            searcher,
+            lhs,
+            rhs,
+            path,
+        }
+    }
+
+    /// The transform for the schemas as they are now.
+    pub fn path(&self) -> Result<&Path, &SearchErr> {
+        self.path.as_ref()
+    }
+
+    pub fn source(&self) -> &Schema {
+        &self.lhs
+    }
+
+    pub fn target(&self) -> &Schema {
+        &self.rhs
+    }
+
+    /// Apply `edit` to the source schema and update the transform.
+    pub fn edit_source(&mut self, edit: &SchemaEdit) -> Result<(), EditErr> {
+        self.lhs = self.lhs.edited(edit)?;
+        self.path = self.searcher.solve(&self.lhs, &self.rhs);
+        Ok(())
+    }
+
+    /// Apply `edit` to the target schema and update the transform.
+    pub fn edit_target(&mut self, edit: &SchemaEdit) -> Result<(), EditErr> {
+        self.rhs = self.rhs.edited(edit)?;
+        self.path = self.searcher.solve(&self.lhs, &self.rhs);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ir::{DateFormat, SortOrder},
+        schema,
+        test_util::key,
+    };
+
+    #[test]
+    fn test_copy_identical() {
+        let s = schema!({"type": "number"});
+        let path = SchemaSearcher::new().solve(&s, &s).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(0));
+        assert_eq!(path.ir, vec![IR::Copy]);
+    }
+
+    #[test]
+    fn test_ground_conversion() {
+        let s1 = schema!({"type": "number"});
+        let s2 = schema!({"type": "string"});
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![IR::G2G(Ground::Num, Ground::String)])
+        );
+    }
+
+    #[test]
+    fn test_converting_array() {
+        let s1 = schema!({"type": "array", "items": {"type": "boolean"}});
+        let s2 = schema!({"type": "array", "items": {"type": "number"}});
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushArr,
+                IR::G2G(Ground::Bool, Ground::Num),
+                IR::PopArr
+            ])
+        );
+    }
+
+    #[test]
+    fn test_deleting_key() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "foo": {"type": "number"},
+                "bar": {"type": "string"}
+            }
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "foo": {"type": "number"}
+            }
+        });
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(1));
+        assert_eq!(
+            path.ir,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("foo")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strict_refuses_to_drop() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "foo": {"type": "number"},
+                "bar": {"type": "number"},
+                "baz": {"type": "number"}
+            },
+            "required": ["foo", "bar", "baz"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {"foo": {"type": "number"}},
+            "required": ["foo"]
+        });
+        let strict = |mapping: serde_json::Value| {
+            SearchConfigBuilder::default()
+                .strict(true)
+                .mapping(Mapping::try_from(&mapping).unwrap())
+                .build()
+                .unwrap()
+        };
+
+        let err = SchemaSearcher::with_config(strict(serde_json::json!({})))
+            .find_path(&s1, &s2)
+            .unwrap_err();
+        let SearchErr::NoPath(no_path) = &err else {
+            panic!("expected no path, got {:?}", err);
+        };
+        assert_eq!(
+            no_path.attempts[0].failure,
+            Failure::Dropped(vec![key("bar"), key("baz")])
+        );
+        assert!(err.to_string().contains("would drop `bar`, `baz`"));
+
+        // extracting drops the other properties just the same
+        let s3 = schema!({"type": "number"});
+        assert!(
+            SchemaSearcher::with_config(strict(serde_json::json!({"bar": null})))
+                .find_path(&s1, &s3)
+                .is_err()
+        );
+
+        let waived = strict(serde_json::json!({"bar": null, "baz": null}));
+        assert_eq!(
+            SchemaSearcher::with_config(waived).find_path(&s1, &s2),
+            SchemaSearcher::new().find_path(&s1, &s2)
+        );
+    }
+
+    #[test]
+    fn test_extracting_key() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "foo": {"type": "number"}
+            }
+        });
+        let s2 = schema!({"type": "number"});
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![IR::Extr(key("foo")), IR::Copy])
+        );
+    }
+
+    #[test]
+    fn test_prefers_cheapest_extraction() {
+        // both properties can become a string, but only `b` needs no conversion
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "number"},
+                "b": {"type": "string"}
+            }
+        });
+        let s2 = schema!({"type": "string"});
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(1));
+        assert_eq!(path.ir, vec![IR::Extr(key("b")), IR::Copy]);
+    }
+
+    #[test]
+    fn test_extracting_nested_key() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "meta": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type": "string"},
+                        "tags": {"type": "array", "items": {"type": "string"}}
+                    }
+                },
+                "count": {"type": "number"}
+            }
+        });
+        let s2 = schema!({"type": "string"});
+        let paths = SchemaSearcher::new().find_paths(&s1, &s2, 2).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                Path {
+                    cost: ExtNat::Nat(2),
+                    ir: vec![IR::Extr(key("count")), IR::G2G(Ground::Num, Ground::String)]
+                },
+                Path {
+                    cost: ExtNat::Nat(2),
+                    ir: vec![IR::Extr(key("meta")), IR::Extr(key("id")), IR::Copy]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prefers_copy() {
+        let s = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "array", "items": {"type": "number"}}
+            }
+        });
+        assert_eq!(SchemaSearcher::new().find_path(&s, &s), Ok(vec![IR::Copy]));
+    }
+
+    #[test]
+    fn test_wrapping_ground() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "tag": {"type": "number"}
+            },
+            "required": ["tag"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "tag": {"type": "array", "items": {"type": "string"}}
+            }
+        });
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(2));
+        assert_eq!(
+            path.ir,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("tag")),
+                IR::Wrap,
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PopObj
+            ]
+        );
+    }
+
+    #[test]
+    fn test_first_item() {
+        let s1 = schema!({"type": "array", "items": {"type": "number"}});
+        let s2 = schema!({"type": "string"});
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![IR::First, IR::G2G(Ground::Num, Ground::String)])
+        );
+    }
+
+    #[test]
+    fn test_joining_items() {
+        let config = SearchConfigBuilder::default()
+            .arr_to_ground(ArrToGround::Join(", ".to_string()))
+            .build()
+            .unwrap();
+        let searcher = SchemaSearcher::with_config(config);
+        let s1 = schema!({"type": "array", "items": {"type": "string"}});
+        assert_eq!(
+            searcher.find_path(&s1, &schema!({"type": "string"})),
+            Ok(vec![IR::Join(", ".to_string())])
+        );
+        // joining only makes sense for strings; other targets take the first item
+        assert_eq!(
+            searcher.find_path(&s1, &schema!({"type": "number"})),
+            Ok(vec![IR::First, IR::G2G(Ground::String, Ground::Num)])
+        );
+    }
+
+    #[test]
+    fn test_never_collapsing_arrays() {
+        let config = SearchConfigBuilder::default()
+            .arr_to_ground(ArrToGround::Never)
+            .build()
+            .unwrap();
+        let s1 = schema!({"type": "array", "items": {"type": "string"}});
+        let s2 = schema!({"type": "string"});
+        assert!(matches!(
+            SchemaSearcher::with_config(config).find_path(&s1, &s2),
+            Err(SearchErr::NoPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_flattening_nested_arrays() {
+        let s1 = schema!({
+            "type": "array",
+            "items": {"type": "array", "items": {"type": "number"}}
+        });
+        let s2 = schema!({"type": "array", "items": {"type": "string"}});
+        let flattened = Ok(vec![
+            IR::Flatten(1),
+            IR::PushArr,
+            IR::G2G(Ground::Num, Ground::String),
+            IR::PopArr,
+        ]);
+        let config = SearchConfigBuilder::default()
+            .arr_to_ground(ArrToGround::Never)
+            .build()
+            .unwrap();
+        assert_eq!(
+            SchemaSearcher::with_config(config).find_path(&s1, &s2),
+            flattened
+        );
+        // taking the first item of each nested array costs just as much
+        assert_eq!(SchemaSearcher::new().find_path(&s1, &s2), flattened);
+
+        let s3 = schema!({
+            "type": "array",
+            "items": {
+                "type": "array",
+                "items": {"type": "array", "items": {"type": "string"}}
+            }
+        });
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s3, &s2),
+            Ok(vec![IR::Flatten(2), IR::Copy])
+        );
+    }
+
+    #[test]
+    fn test_inverting_object() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "number"},
+                "b": {"type": "number"}
+            },
+            "required": ["a", "b"]
+        });
+        let s2 = schema!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "key": {"type": "string"},
+                    "value": {"type": "string"}
+                },
+                "required": ["key", "value"]
+            }
+        });
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![
+                IR::Inv,
+                IR::PushArr,
+                IR::PushObj,
+                IR::PushKey(key("key")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PushKey(key("value")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PopObj,
+                IR::PopArr
+            ])
+        );
+        // and back again, where any key may be missing from the entries, so
+        // the required properties need defaults to fall back on
+        assert!(matches!(
+            SchemaSearcher::new().find_path(&s2, &s1),
+            Err(SearchErr::NoPath(_))
+        ));
+        let s3 = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "number", "default": 0},
+                "b": {"type": "number", "default": 0}
+            },
+            "required": ["a", "b"]
+        });
+        let fallback = |k: &str| {
+            vec![
+                IR::Nest(key(k)),
+                IR::Const(Literal(serde_json::json!(0))),
+                IR::PopKey,
+                IR::PushKey(key(k)),
+                IR::PushOpt,
+                IR::G2G(Ground::String, Ground::Num),
+                IR::PopOpt,
+                IR::PopKey,
+            ]
+        };
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s2, &s3),
+            Ok([
+                vec![IR::Inv, IR::PushObj],
+                fallback("a"),
+                fallback("b"),
+                vec![IR::PopObj]
+            ]
+            .concat())
+        );
+    }
+
+    #[test]
+    fn test_inverting_maps() {
+        let map = schema!({
+            "type": "object",
+            "properties": {},
+            "additionalProperties": {"type": "number"}
+        });
+        let entries = schema!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "key": {"type": "string"},
+                    "value": {"type": "string"}
+                },
+                "required": ["key", "value"]
+            }
+        });
+        assert_eq!(
+            SchemaSearcher::new().find_path(&map, &entries),
+            Ok(vec![
+                IR::Inv,
+                IR::PushArr,
+                IR::PushObj,
+                IR::PushKey(key("key")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PushKey(key("value")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PopObj,
+                IR::PopArr
+            ])
+        );
+        // and back again, where every entry becomes an additional property,
+        // rather than the entries being grouped by their keys
+        assert_eq!(
+            SchemaSearcher::new().find_path(&entries, &map),
+            Ok(vec![
+                IR::Inv,
+                IR::PushObj,
+                IR::PushRest(vec![]),
+                IR::G2G(Ground::String, Ground::Num),
+                IR::PopRest,
+                IR::PopObj
+            ])
+        );
+    }
+
+    #[test]
+    fn test_grouping_records() {
+        let list = schema!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "id": {"type": "string"},
+                    "name": {"type": "string"},
+                    "age": {"type": "number"}
+                },
+                "required": ["id", "name", "age"]
+            }
+        });
+        let map = schema!({
+            "type": "object",
+            "properties": {},
+            "additionalProperties": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "age": {"type": "number"}
+                },
+                "required": ["name", "age"]
+            }
+        });
+        // `id` is the only property the values lack, so it's what the
+        // records are keyed by
+        assert_eq!(
+            SchemaSearcher::new().find_path(&list, &map),
+            Ok(vec![
+                IR::GroupBy(key("id")),
+                IR::PushObj,
+                IR::PushRest(vec![]),
+                IR::PushObj,
+                IR::PushKey(key("age")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PushKey(key("name")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj,
+                IR::PopRest,
+                IR::PopObj
+            ])
+        );
+        assert_eq!(
+            SchemaSearcher::new().find_path(&map, &list),
+            Ok(vec![IR::GroupBy(key("id")), IR::Copy])
+        );
+    }
+
+    #[test]
+    fn test_zipping_parallel_arrays() {
+        let columns = schema!({
+            "type": "object",
+            "properties": {
+                "names": {"type": "array", "items": {"type": "string"}},
+                "ages": {"type": "array", "items": {"type": "number"}}
+            },
+            "required": ["names", "ages"]
+        });
+        let rows = schema!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "age": {"type": "number"}
+                },
+                "required": ["name", "age"]
+            }
+        });
+        let pairs = vec![(key("ages"), key("age")), (key("names"), key("name"))];
+        let searcher = SchemaSearcher::new();
+        assert_eq!(
+            searcher.find_path(&columns, &rows),
+            Ok(vec![IR::Zip(pairs.clone()), IR::Copy])
+        );
+        assert_eq!(
+            searcher.find_path(&rows, &columns),
+            Ok(vec![IR::Unzip(pairs), IR::Copy])
+        );
+    }
+
+    #[test]
+    fn test_inverting_needs_uniform_values() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "number"},
+                "b": {"type": "array", "items": {"type": "number"}}
+            }
+        });
+        let s2 = schema!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "key": {"type": "string"},
+                    "value": {"type": "number"}
+                }
+            }
+        });
+        assert!(matches!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Err(SearchErr::NoPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_renaming_key() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "user_id": {"type": "number"},
+                "name": {"type": "string"}
+            },
+            "required": ["user_id", "name"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "userId": {"type": "string"},
+                "name": {"type": "string"}
+            },
+            "required": ["userId", "name"]
+        });
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(2));
+        assert_eq!(
+            path.ir,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("name")),
+                IR::Copy,
+                IR::PopKey,
+                IR::Rename(key("user_id"), key("userId")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PopObj
+            ]
+        );
+        assert_eq!(
+            crate::ir::renames(&path.ir),
+            vec![("user_id".to_string(), "userId".to_string())]
+        );
+
+        let config = SearchConfigBuilder::default()
+            .key_matcher(None)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            SchemaSearcher::with_config(config).find_path(&s1, &s2),
+            Err(SearchErr::NoPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_declared_key_order() {
+        let text = r#"{
+            "type": "object",
+            "properties": {
+                "zip": {"type": "string"},
+                "city": {"type": "string"}
+            },
+            "required": ["zip", "city"]
+        }"#;
+        let (json, orders) = crate::frontend::json5::from_str_ordered(text).unwrap();
+        let s2 = Schema::try_from(&json).unwrap();
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "city": {"type": "string"},
+                "zip": {"type": "number"}
+            },
+            "required": ["city", "zip"]
+        });
+        let sorted = SchemaSearcher::new().find_path(&s1, &s2).unwrap();
+        assert_eq!(sorted[1], IR::PushKey(key("city")));
+
+        let config = SearchConfigBuilder::default()
+            .key_order(KeyOrder::Declared(Arc::new(PropOrder::from_json(
+                &json, &orders,
+            ))))
+            .build()
+            .unwrap();
+        let declared = SchemaSearcher::with_config(config)
+            .find_path(&s1, &s2)
+            .unwrap();
+        assert_eq!(
+            declared,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("zip")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PushKey(key("city")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mapping_overrides() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "customer": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "nickname": {"type": "string"}
+                    },
+                    "required": ["name", "nickname"]
+                }
+            },
+            "required": ["customer"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "client": {
+                    "type": "object",
+                    "properties": {
+                        "fullName": {"type": "string"},
+                        "nickname": {"type": "string"}
+                    },
+                    "required": ["fullName", "nickname"]
+                }
+            },
+            "required": ["client"]
+        });
+        assert!(matches!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Err(SearchErr::NoPath(_))
+        ));
+
+        let mapping =
+            Mapping::try_from(&serde_json::json!({"customer.name": "client.fullName"})).unwrap();
+        let config = SearchConfigBuilder::default()
+            .mapping(mapping)
+            .build()
+            .unwrap();
+        assert_eq!(
+            SchemaSearcher::with_config(config).find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::Rename(key("customer"), key("client")),
+                IR::PushObj,
+                IR::Rename(key("name"), key("fullName")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PushKey(key("nickname")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+    }
+
+    #[test]
+    fn test_concatenating_keys() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "first_name": {"type": "string"},
+                "last_name": {"type": "string"},
+                "age": {"type": "number"}
+            },
+            "required": ["first_name", "last_name", "age"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": "number"}
+            },
+            "required": ["name", "age"]
+        });
+        let mapping =
+            Mapping::try_from(&serde_json::json!({"first_name + last_name": "name"})).unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            strict: true,
+            ..Default::default()
+        });
+        let (ir, report) = searcher.find_path_with_report(&s1, &s2).unwrap();
+        assert_eq!(
+            ir,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("age")),
+                IR::Copy,
+                IR::PopKey,
+                IR::Nest(key("name")),
+                IR::Concat(
+                    vec![vec![key("first_name")], vec![key("last_name")]],
+                    " ".to_string()
+                ),
+                IR::PopKey,
+                IR::PopObj
+            ]
+        );
+        assert_eq!(report.lossiness(), Lossiness::Lossy);
+
+        let mapping = Mapping::try_from(&serde_json::json!({"first_name + age": "age"})).unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert!(matches!(
+            searcher.solve(&s1, &s2),
+            Err(SearchErr::NoPath(NoPath { attempts, .. }))
+                if attempts[0].failure == Failure::Unconcatenable(key("age"))
+        ));
+    }
+
+    #[test]
+    fn test_coalescing_keys() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "email": {"type": "string"},
+                "contact_email": {"type": "string"},
+                "phone": {"type": "number"},
+                "mobile": {"type": "number"}
+            }
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "email": {"type": "string"},
+                "phone": {"type": "string"}
+            }
+        });
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "email ?? contact_email": "email",
+            "phone ?? mobile": "phone"
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        let paths = |keys: &[&str]| IR::Coalesce(keys.iter().map(|k| vec![key(k)]).collect());
+        // copying a missing value leaves it missing, so only the conversion
+        // is guarded
+        assert_eq!(
+            searcher.find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::Nest(key("email")),
+                paths(&["email", "contact_email"]),
+                IR::Copy,
+                IR::PopKey,
+                IR::Nest(key("phone")),
+                paths(&["phone", "mobile"]),
+                IR::PushOpt,
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopOpt,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+    }
+
+    #[test]
+    fn test_converting_units() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "price": {"type": "number", "unit": "cents"},
+                "elapsed": {"type": "number", "unit": "ms"},
+                "temp": {"type": "number"}
+            },
+            "required": ["price", "temp"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "price": {"type": "number", "unit": "dollars"},
+                "elapsed": {"type": "number", "unit": "s"},
+                "fahrenheit": {"type": "number"}
+            },
+            "required": ["price", "fahrenheit"]
+        });
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "temp": {"to": "fahrenheit", "scale": 1.8, "offset": 32}
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        let scale = |factor, offset| IR::Scale {
+            factor: Real(factor),
+            offset: Real(offset),
+        };
+        assert_eq!(
+            searcher.find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::PushKey(key("elapsed")),
+                IR::PushOpt,
+                scale(0.001, 0.0),
+                IR::PopOpt,
+                IR::PopKey,
+                IR::Rename(key("temp"), key("fahrenheit")),
+                scale(1.8, 32.0),
+                IR::PopKey,
+                IR::PushKey(key("price")),
+                scale(0.01, 0.0),
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+
+        let mapping =
+            Mapping::try_from(&serde_json::json!({"temp": {"to": "elapsed", "scale": 2}})).unwrap();
+        let s1 = schema!({"type": "object", "properties": {"temp": {"type": "string"}}});
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert!(matches!(
+            searcher.solve(&s1, &s2),
+            Err(SearchErr::NoPath(NoPath { attempts, .. }))
+                if attempts[0].failure == Failure::Unscalable(key("elapsed"))
+        ));
+    }
+
+    #[test]
+    fn test_base64_coding() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "avatar": {"type": "string", "contentEncoding": "base64"},
+                "note": {"type": "string"}
+            },
+            "required": ["avatar", "note"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "avatar": {"type": "string"},
+                "note": {"type": "string", "contentEncoding": "base64"}
+            },
+            "required": ["avatar", "note"]
+        });
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::PushKey(key("avatar")),
+                IR::Base64(Coding::Decode),
+                IR::PopKey,
+                IR::PushKey(key("note")),
+                IR::Base64(Coding::Encode),
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s1),
+            Ok(vec![IR::Copy])
+        );
+    }
+
+    #[test]
+    fn test_annotated_items_and_roots() {
+        // annotations count wherever they're written, not only on properties
+        let s1 = schema!({
+            "type": "object",
+            "properties": {"files": {"type": "array", "items": {"type": "string"}}},
+            "required": ["files"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "files": {
+                    "type": "array",
+                    "items": {"type": "string", "contentEncoding": "base64"}
+                }
+            },
+            "required": ["files"]
+        });
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::PushKey(key("files")),
+                IR::PushArr,
+                IR::Base64(Coding::Encode),
+                IR::PopArr,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+
+        let s1 = schema!({"type": "number", "unit": "ms"});
+        let s2 = schema!({"type": "number", "unit": "s"});
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![IR::Scale {
+                factor: Real(0.001),
+                offset: Real(0.0)
+            }])
+        );
+
+        let s1 = schema!({
+            "type": "array",
+            "items": {"type": "array", "items": {"type": "number"}}
+        });
+        let s2 = schema!({
+            "type": "array",
+            "items": {"type": "array", "items": {"type": "number"}, "maxItems": 2},
+            "uniqueItems": true
+        });
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![
+                IR::Dedupe(None),
+                IR::PushArr,
+                IR::Slice(0, Some(2)),
+                IR::Copy,
+                IR::PopArr
+            ])
+        );
+    }
+
+    #[test]
+    fn test_slicing_arrays() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "log": {"type": "array", "items": {"type": "number"}},
+                "total": {"type": "number"}
+            },
+            "required": ["tags", "total"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 3},
+                "log": {"type": "array", "items": {"type": "number"}},
+                "total": {"type": "number"}
+            },
+            "required": ["tags", "total"]
+        });
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "log": {"to": "log", "slice": [1]}
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert_eq!(
+            searcher.find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::PushKey(key("log")),
+                IR::PushOpt,
+                IR::Slice(1, None),
+                IR::Copy,
+                IR::PopOpt,
+                IR::PopKey,
+                IR::PushKey(key("tags")),
+                IR::Slice(0, Some(3)),
+                IR::Copy,
+                IR::PopKey,
+                IR::PushKey(key("total")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+
+        // arrays already short enough are left alone
+        let s3 = schema!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 2}
+            },
+            "required": ["tags"]
+        });
+        let s4 = schema!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 3}
+            },
+            "required": ["tags"]
+        });
+        assert_eq!(
+            SchemaSearcher::default().find_path(&s3, &s4),
+            Ok(vec![
+                IR::PushObj,
+                IR::PushKey(key("tags")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "total": {"to": "total", "slice": [0, 1]}
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert!(matches!(
+            searcher.solve(&s1, &s2),
+            Err(SearchErr::NoPath(NoPath { attempts, .. }))
+                if attempts[0].failure == Failure::Unsliceable(key("total"))
+        ));
+    }
+
+    #[test]
+    fn test_sorting_arrays() {
+        let event = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "timestamp": {"type": "number"},
+                "name": {"type": "string"}
+            },
+            "required": ["timestamp"]
+        });
+        let s1 = schema!({
+            "type": "object",
+            "properties": {"events": {"type": "array", "items": event}},
+            "required": ["events"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {"latest": {"type": "array", "items": event}},
+            "required": ["latest"]
+        });
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "events": {"to": "latest", "sort": "timestamp", "order": "desc", "slice": [0, 2]}
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert_eq!(
+            searcher.find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::Rename(key("events"), key("latest")),
+                IR::Sort(vec![key("timestamp")], SortOrder::Descending),
+                IR::Slice(0, Some(2)),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+
+        // sorting by something items may not have fails
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "events": {"to": "latest", "sort": "name"}
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert!(matches!(
+            searcher.solve(&s1, &s2),
+            Err(SearchErr::NoPath(NoPath { attempts, .. }))
+                if attempts[0].failure == Failure::Unsortable(key("latest"))
+        ));
+    }
+
+    #[test]
+    fn test_deduplicating_arrays() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "labels": {"type": "array", "items": {"type": "string"}},
+                "ids": {"type": "array", "items": {"type": "number"}}
+            },
+            "required": ["tags", "ids"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "ids": {"type": "array", "items": {"type": "number"}, "uniqueItems": true}
+            },
+            "required": ["tags", "ids"]
+        });
+        let mapping = Mapping::try_from(&serde_json::json!({"tags + labels": "tags"})).unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert_eq!(
+            searcher.find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::PushKey(key("ids")),
+                IR::Dedupe(None),
+                IR::Copy,
+                IR::PopKey,
+                IR::Nest(key("tags")),
+                IR::Gather(vec![vec![key("tags")], vec![key("labels")]]),
+                IR::Dedupe(None),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "ids": {"to": "ids", "unique": "value"}
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        let ir = searcher.find_path(&s1, &s2).unwrap();
+        assert_eq!(ir[2], IR::Dedupe(Some(vec![key("value")])));
+    }
+
+    #[test]
+    fn test_extracting_patterns() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "email": {"type": "string"},
+                "description": {"type": "string"},
+                "total": {"type": "number"}
+            },
+            "required": ["email", "description", "total"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "domain": {"type": "string"},
+                "order": {"type": "string"},
+                "total": {"type": "number"}
+            },
+            "required": ["domain", "order", "total"]
+        });
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "email": {"to": "domain", "pattern": "@(.+)$", "group": 1},
+            "description": {"to": "order", "pattern": "#\\d+"}
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert_eq!(
+            searcher.find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::Rename(key("email"), key("domain")),
+                IR::RegexExtract("@(.+)$".to_string(), 1),
+                IR::PopKey,
+                IR::Rename(key("description"), key("order")),
+                IR::RegexExtract("#\\d+".to_string(), 0),
+                IR::PopKey,
+                IR::PushKey(key("total")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "total": {"to": "order", "pattern": "\\d+"}
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert!(matches!(
+            searcher.solve(&s1, &s2),
+            Err(SearchErr::NoPath(NoPath { attempts, .. }))
+                if attempts[0].failure == Failure::Unextractable(key("order"))
+        ));
+    }
+
+    #[test]
+    fn test_converting_dates() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "created": {"type": "string", "format": "date-time"},
+                "updated": {"type": "number", "format": "unix-time", "unit": "ms"}
+            },
+            "required": ["created"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "created": {"type": "number", "format": "unix-time"},
+                "updated": {"type": "string", "format": "date-time"}
+            },
+            "required": ["created"]
+        });
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::PushKey(key("created")),
+                IR::DateConvert {
+                    from_format: DateFormat::Iso,
+                    to_format: DateFormat::EpochSeconds
+                },
+                IR::PopKey,
+                IR::PushKey(key("updated")),
+                IR::PushOpt,
+                IR::DateConvert {
+                    from_format: DateFormat::EpochMillis,
+                    to_format: DateFormat::Iso
+                },
+                IR::PopOpt,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+    }
+
+    #[test]
+    fn test_translating_enums() {
+        use serde_json::json;
+
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "status": {"type": "string", "enum": ["ACTIVE", "INACTIVE"]},
+                "level": {"enum": ["low", "high"]},
+                "kind": {"type": "string", "enum": ["a", "b"]},
+                "priority": {"type": "string", "enum": ["urgent", "normal"]}
+            },
+            "required": ["status", "level", "kind", "priority"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "status": {"type": "number", "enum": [1, 0]},
+                "level": {"type": "string", "enum": ["LOW", "MEDIUM", "HIGH"]},
+                "kind": {"type": "string", "enum": ["a", "b", "c"]},
+                "priority": {"type": "string", "enum": ["Normal", "Urgent", "Low"]}
+            },
+            "required": ["status", "level", "kind", "priority"]
+        });
+        let table = |pairs: &[(serde_json::Value, serde_json::Value)]| {
+            IR::MapValues(
+                pairs
+                    .iter()
+                    .map(|(from, to)| (Literal(from.clone()), Literal(to.clone())))
+                    .collect(),
+            )
+        };
+        // `kind` needs no translating, `level` only needs uppercasing,
+        // `priority` is matched up by name, and `status` in order
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::PushKey(key("kind")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PushKey(key("level")),
+                IR::CaseTransform(Case::Upper),
+                IR::PopKey,
+                IR::PushKey(key("priority")),
+                table(&[
+                    (json!("normal"), json!("Normal")),
+                    (json!("urgent"), json!("Urgent"))
+                ]),
+                IR::PopKey,
+                IR::PushKey(key("status")),
+                table(&[(json!("ACTIVE"), json!(1)), (json!("INACTIVE"), json!(0))]),
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+
+        let mapping = Mapping::try_from(&json!({"kind": {"to": "kind", "case": "upper"}})).unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert_eq!(
+            searcher.find_path(&s1, &s2).unwrap()[1..4],
+            [
+                IR::PushKey(key("kind")),
+                IR::CaseTransform(Case::Upper),
+                IR::PopKey
+            ]
+        );
+
+        let mapping = Mapping::try_from(&json!({
+            "status": {"to": "status", "values": {"ACTIVE": 0}}
+        }))
+        .unwrap();
+        let searcher = SchemaSearcher::with_config(SearchConfig {
+            mapping,
+            ..Default::default()
+        });
+        assert!(matches!(
+            searcher.solve(&s1, &s2),
+            Err(SearchErr::NoPath(NoPath { attempts, .. }))
+                if attempts[0].failure == Failure::Unmappable(key("status"))
+        ));
+    }
+
+    #[test]
+    fn test_mapping_beats_same_name() {
+        // `a` would normally come from `a`, but the mapping says otherwise
+        let s = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "number"},
+                "b": {"type": "number"}
+            }
+        });
+        let t = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "number"}
+            }
+        });
+        let mapping = Mapping::try_from(&serde_json::json!({"b": "a"})).unwrap();
+        let config = SearchConfigBuilder::default()
+            .mapping(mapping)
+            .build()
+            .unwrap();
+        assert_eq!(
+            SchemaSearcher::with_config(config).find_path(&s, &t),
+            Ok(vec![
+                IR::PushObj,
+                IR::Rename(key("b"), key("a")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+    }
+
+    #[test]
+    fn test_top_k_paths() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "first": {"type": "string"},
+                "last": {"type": "string"},
+                "age": {"type": "number"}
+            }
+        });
+        let s2 = schema!({"type": "string"});
+        let searcher = SchemaSearcher::new();
+        let paths = searcher.find_paths(&s1, &s2, 5).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                Path {
+                    cost: ExtNat::Nat(1),
+                    ir: vec![IR::Extr(key("first")), IR::Copy]
+                },
+                Path {
+                    cost: ExtNat::Nat(1),
+                    ir: vec![IR::Extr(key("last")), IR::Copy]
+                },
+                Path {
+                    cost: ExtNat::Nat(2),
+                    ir: vec![IR::Extr(key("age")), IR::G2G(Ground::Num, Ground::String)]
+                },
+            ]
+        );
+        assert_eq!(searcher.find_paths(&s1, &s2, 1).unwrap(), paths[..1]);
+        assert_eq!(searcher.find_paths(&s1, &s2, 0).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_top_k_combines_subpaths() {
+        let inner = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "s": {"type": "string"},
+                "n": {"type": "number"}
+            }
+        });
+        let s1 = schema!({
+            "type": "object",
+            "properties": {"x": inner, "y": inner},
+            "required": ["x", "y"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "x": {"type": "string"},
+                "y": {"type": "string"}
+            },
+            "required": ["x", "y"]
+        });
+        let paths = SchemaSearcher::new().find_paths(&s1, &s2, 10).unwrap();
+        let costs: Vec<_> = paths.iter().map(|p| p.cost).collect();
+        assert_eq!(
+            costs,
+            vec![
+                ExtNat::Nat(2),
+                ExtNat::Nat(3),
+                ExtNat::Nat(3),
+                ExtNat::Nat(4)
+            ]
+        );
+        assert_eq!(
+            paths[3].ir,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("x")),
+                IR::Extr(key("n")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PushKey(key("y")),
+                IR::Extr(key("n")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PopObj
+            ]
+        );
+    }
+
+    #[test]
+    fn test_top_k_matches_find_path() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "user_id": {"type": "number"},
+                "tags": {"type": "array", "items": {"type": "number"}}
+            }
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "userId": {"type": "string"},
+                "tags": {"type": "string"}
+            }
+        });
+        let searcher = SchemaSearcher::new();
+        let best = searcher.solve(&s1, &s2).unwrap();
+        assert_eq!(searcher.find_paths(&s1, &s2, 1).unwrap(), vec![best]);
+    }
+
+    #[test]
+    fn test_custom_cost_model() {
+        #[derive(Debug)]
+        struct NoStrings;
+        impl CostModel for NoStrings {
+            fn ground_to_ground(&self, _: &Ground, to: &Ground) -> ExtNat {
+                match to {
+                    Ground::String => ExtNat::Inf,
+                    _ => ExtNat::Nat(1),
+                }
+            }
+        }
+
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "n": {"type": "number"},
+                "b": {"type": "boolean"}
+            }
+        });
+        let s2 = schema!({"type": "string"});
+        assert_eq!(
+            SchemaSearcher::new().find_paths(&s1, &s2, 5).unwrap().len(),
+            2
+        );
+        let config = SearchConfigBuilder::default()
+            .cost_model(Arc::new(NoStrings) as Arc<dyn CostModel>)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            SchemaSearcher::with_config(config).find_path(&s1, &s2),
+            Err(SearchErr::NoPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_rare_properties() {
+        let corpus = r#"
+            {"e_mail": "a@example.com", "mail": "a@example.com"}
+            {"mail": "b@example.com"}
+            {"mail": "c@example.com"}
+            {"mail": "d@example.com"}
+        "#;
+        let stats = crate::frontend::infer::from_ndjson(corpus).unwrap();
+        let target = schema!({
+            "type": "object",
+            "properties": {"email": {"type": "string"}}
+        });
+        let renamed = |searcher: SchemaSearcher| match &searcher
+            .find_path(&stats.schema(), &target)
+            .unwrap()[1]
+        {
+            IR::Rename(from, _) => from.clone(),
+            op => panic!("expected a rename, got {:?}", op),
+        };
+        // the closer name wins, unless it's rarely there
+        assert_eq!(renamed(SchemaSearcher::new()), key("e_mail"));
+        let config = SearchConfigBuilder::default()
+            .cost_model(Arc::new(crate::cost::Rarity {
+                inner: Arc::new(crate::cost::DefaultCostModel),
+                presence: stats.presence(),
+                penalty: 4,
+            }) as Arc<dyn CostModel>)
+            .build()
+            .unwrap();
+        assert_eq!(renamed(SchemaSearcher::with_config(config)), key("mail"));
+    }
+
+    #[test]
+    fn test_loss_averse_prefers_lossless() {
+        // both need a conversion, but string -> number can lose information
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "string"},
+                "b": {"type": "boolean"}
+            }
+        });
+        let s2 = schema!({"type": "number"});
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![
+                IR::Extr(key("a")),
+                IR::G2G(Ground::String, Ground::Num)
+            ])
+        );
+        let config = SearchConfigBuilder::default()
+            .cost_model(Arc::new(crate::cost::LossAverse { penalty: 5 }) as Arc<dyn CostModel>)
+            .build()
+            .unwrap();
+        assert_eq!(
+            SchemaSearcher::with_config(config).find_path(&s1, &s2),
+            Ok(vec![IR::Extr(key("b")), IR::G2G(Ground::Bool, Ground::Num)])
+        );
+    }
+
+    #[test]
+    fn test_safety_first() {
+        // the narrowing conversion is cheaper, but a widening one exists
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "label": {"type": "string"},
+                "meta": {
+                    "type": "object",
+                    "properties": {"flag": {"type": "boolean"}}
+                }
+            }
+        });
+        let s2 = schema!({"type": "number"});
+        let config = SearchConfigBuilder::default()
+            .cost_model(Arc::new(crate::cost::SafetyFirst {
+                inner: Arc::new(DefaultCostModel),
+            }) as Arc<dyn CostModel>)
+            .build()
+            .unwrap();
+        let searcher = SchemaSearcher::with_config(config);
+        let (ir, report) = searcher.find_path_with_report(&s1, &s2).unwrap();
+        assert_eq!(
+            ir,
+            vec![
+                IR::Extr(key("meta")),
+                IR::Extr(key("flag")),
+                IR::G2G(Ground::Bool, Ground::Num)
+            ]
+        );
+        assert_eq!(report.tier, crate::cost::Tier::Widening);
+        let (_, report) = SchemaSearcher::new()
+            .find_path_with_report(&s1, &s2)
+            .unwrap();
+        assert_eq!(report.tier, crate::cost::Tier::Narrowing);
+
+        // narrowing is still used when there's nothing safer
+        let s1 = schema!({"type": "string"});
+        let (_, report) = searcher.find_path_with_report(&s1, &s2).unwrap();
+        assert_eq!(report.tier, crate::cost::Tier::Narrowing);
+    }
+
+    #[test]
+    fn test_memoizes_repeated_subschemas() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "array", "items": {"type": "number"}},
+                "b": {"type": "array", "items": {"type": "number"}},
+                "c": {"type": "array", "items": {"type": "number"}}
+            }
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "array", "items": {"type": "string"}},
+                "b": {"type": "array", "items": {"type": "string"}},
+                "c": {"type": "array", "items": {"type": "string"}}
+            }
+        });
+        let searcher = SchemaSearcher::new();
+        let path = searcher.solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(3));
+        // the whole pair, the array pair, and the item pair
+        assert_eq!(searcher.schema_rels.borrow().len(), 3);
+        assert_eq!(searcher.solve(&s1, &s2), Ok(path));
+        assert_eq!(searcher.schema_rels.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_recursive_schemas() {
+        let tree = |id: &str| {
+            schema!({
+                "type": "object",
+                "properties": {
+                    "id": {"type": id},
+                    "children": {"type": "array", "items": {"$ref": "#"}}
+                },
+                "required": ["id", "children"]
+            })
+        };
+        let root = Arc::new("root".to_string());
+        let searcher = SchemaSearcher::new();
+        let path = searcher.solve(&tree("number"), &tree("string")).unwrap();
+        assert_eq!(
+            path.ir,
+            vec![
+                IR::Rec(root.clone()),
+                IR::PushObj,
+                IR::PushKey(key("children")),
+                IR::PushArr,
+                IR::Call(root),
+                IR::PopArr,
+                IR::PopKey,
+                IR::PushKey(key("id")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PopObj,
+                IR::PopRec,
+            ]
+        );
+        // the parts calling back into the whole only hold inside it, so
+        // they aren't memoized
+        let open = |path: &Path| {
+            path.ir.iter().any(|op| matches!(op, IR::Call(_))) && !matches!(path.ir[0], IR::Rec(_))
+        };
+        assert!(!searcher
+            .schema_rels
+            .borrow()
+            .values()
+            .any(|solved| solved.as_ref().is_ok_and(open)));
+        assert_eq!(
+            searcher
+                .find_paths(&tree("number"), &tree("string"), 1)
+                .unwrap(),
+            [path]
+        );
+    }
+
+    #[test]
+    fn test_no_path() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "foo": {"type": "number"}
+            }
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "bar": {"type": "number"}
+            },
+            "required": ["bar"]
+        });
+        let Err(SearchErr::NoPath(no_path)) = SchemaSearcher::new().find_path(&s1, &s2) else {
+            panic!("expected no path");
+        };
+        assert_eq!(
+            no_path.attempts,
+            vec![Attempt {
+                rule: "object".to_string(),
+                step: String::new(),
+                failure: Failure::Unmatched(key("bar")),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_no_path_blame() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "items": {"type": "array", "items": {"type": "number"}}
+            },
+            "required": ["items"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "items": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {"id": {"type": "number"}},
+                        "required": ["id"]
+                    }
+                }
+            },
+            "required": ["items"]
+        });
+        let Err(SearchErr::NoPath(no_path)) = SchemaSearcher::new().find_path(&s1, &s2) else {
+            panic!("expected no path");
+        };
+        let (path, stuck) = no_path.blame();
+        assert_eq!(path, "items.[]");
+        assert_eq!(*stuck.source, Schema::num());
+        assert!(stuck.attempts.is_empty());
+        assert_eq!(
+            no_path.to_string(),
+            "no conversion from number to object {id} at `items.[]`; no rule applies"
+        );
+    }
+
+    #[test]
+    fn test_filling_defaults() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {"name": {"type": "string"}},
+            "required": ["name"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "version": {"const": 2},
+                "status": {"type": "string", "default": "active"},
+                "note": {"type": "string", "default": "none"}
+            },
+            "required": ["name", "version", "status"]
+        });
+        let fill = |k: &str, v: serde_json::Value| {
+            vec![IR::Nest(key(k)), IR::Const(Literal(v)), IR::PopKey]
+        };
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        // the optional `note` is left for consumers to default
+        assert_eq!(path.cost, ExtNat::Nat(3));
+        let mut expected = vec![IR::PushObj, IR::PushKey(key("name")), IR::Copy, IR::PopKey];
+        expected.extend(fill("status", serde_json::json!("active")));
+        expected.extend(fill("version", serde_json::json!(2)));
+        expected.push(IR::PopObj);
+        assert_eq!(path.ir, expected);
+
+        // a fill from the user applies to optional properties too, and takes
+        // precedence over the schema
+        let mut mapping = Mapping::default();
+        mapping
+            .add_fills(&serde_json::json!({"note": "migrated", "status": "new"}))
+            .unwrap();
+        let config = SearchConfigBuilder::default()
+            .mapping(mapping)
+            .build()
+            .unwrap();
+        let path = SchemaSearcher::with_config(config).solve(&s1, &s2).unwrap();
+        assert!(path
+            .ir
+            .windows(3)
+            .any(|w| w == fill("note", serde_json::json!("migrated"))));
+        assert!(path
+            .ir
+            .windows(3)
+            .any(|w| w == fill("status", serde_json::json!("new"))));
+    }
+
+    #[test]
+    fn test_additional_properties() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {"id": {"type": "number"}},
+            "required": ["id"],
+            "additionalProperties": {"type": "number"}
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {"id": {"type": "string"}},
+            "required": ["id"],
+            "additionalProperties": {"type": "string"}
+        });
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(
+            path.ir,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("id")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::PushRest(vec![key("id")]),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopRest,
+                IR::PopObj
+            ]
+        );
+
+        // incompatible additional properties are dropped instead
+        let s3 = schema!({
+            "type": "object",
+            "properties": {"id": {"type": "string"}},
+            "required": ["id"],
+            "additionalProperties": {
+                "type": "object",
+                "properties": {"x": {"type": "number"}},
+                "required": ["x"]
+            }
+        });
+        let ir = SchemaSearcher::new().find_path(&s1, &s3).unwrap();
+        assert!(!ir.contains(&IR::PopRest));
+        let config = SearchConfigBuilder::default().strict(true).build().unwrap();
+        assert!(SchemaSearcher::with_config(config)
+            .find_path(&s1, &s3)
+            .is_err());
+    }
+
+    #[test]
+    fn test_skipping_optional_target() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"}
+            },
+            "required": ["id"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "nickname": {"type": "string"}
+            },
+            "required": ["id"]
+        });
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(1));
+        assert_eq!(
+            path.ir,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("id")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ]
+        );
+    }
+
+    #[test]
+    fn test_guarding_optional_source() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "age": {"type": "number"},
+                "name": {"type": "string"}
+            },
+            "required": ["name"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "age": {"type": "string"},
+                "name": {"type": "string"}
+            }
+        });
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::PushKey(key("age")),
+                IR::PushOpt,
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopOpt,
+                IR::PopKey,
+                IR::PushKey(key("name")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ])
+        );
+    }
+
+    /// A complete binary tree of objects `depth` levels deep, with leaves of
+    /// type `leaf`.
+    fn nested(depth: usize, leaf: &str) -> Schema {
+        let tree = (0..depth).fold(serde_json::json!({"type": leaf}), |inner, _| {
+            serde_json::json!({
+                "type": "object",
+                "properties": {"a": inner, "b": inner},
+                "required": ["a", "b"]
+            })
+        });
+        Schema::try_from(&tree).unwrap()
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let s1 = nested(4, "number");
+        let s2 = nested(4, "string");
+        let limits = SearchLimitsBuilder::default()
+            .max_depth(Some(3))
+            .build()
+            .unwrap();
+        let config = SearchConfigBuilder::default()
+            .limits(limits)
+            .build()
+            .unwrap();
+        let searcher = SchemaSearcher::with_config(config);
+        match searcher.find_path(&s1, &s2) {
+            Err(SearchErr::BudgetExceeded(budget)) => {
+                assert_eq!(budget.limit, Limit::Depth);
+                assert_eq!(budget.depth, 3);
+            }
+            other => panic!("expected budget to run out, got {:?}", other),
+        }
+        // partial results aren't remembered as failures
+        assert!(searcher
+            .schema_rels
+            .borrow()
+            .values()
+            .all(|solved| !matches!(solved, Err(SearchErr::BudgetExceeded(_)))));
+        assert!(SchemaSearcher::new().find_path(&s1, &s2).is_ok());
+    }
+
+    #[test]
+    fn test_state_and_time_limits() {
+        let s1 = nested(3, "number");
+        let s2 = nested(3, "string");
+        let limits = SearchLimitsBuilder::default()
+            .max_states(Some(2))
+            .build()
+            .unwrap();
+        let config = SearchConfigBuilder::default()
+            .limits(limits)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            SchemaSearcher::with_config(config).find_path(&s1, &s2),
+            Err(SearchErr::BudgetExceeded(Budget {
+                limit: Limit::States,
+                explored: 3,
+                ..
+            }))
+        ));
+
+        let limits = SearchLimitsBuilder::default()
+            .timeout(Some(Duration::ZERO))
+            .build()
+            .unwrap();
+        let config = SearchConfigBuilder::default()
+            .limits(limits)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            SchemaSearcher::with_config(config).find_path(&s1, &s2),
+            Err(SearchErr::BudgetExceeded(Budget {
+                limit: Limit::Time,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_ranked_limits() {
+        let s1 = nested(4, "number");
+        let s2 = nested(4, "string");
+        let searcher = |limits| {
+            SchemaSearcher::with_config(SearchConfig {
+                limits,
+                ..SearchConfig::default()
+            })
+        };
+        let depth = SearchLimitsBuilder::default()
+            .max_depth(Some(3))
+            .build()
+            .unwrap();
+        let shallow = searcher(depth);
+        assert!(matches!(
+            shallow.find_paths(&s1, &s2, 3),
+            Err(SearchErr::BudgetExceeded(Budget {
+                limit: Limit::Depth,
+                depth: 3,
+                ..
+            }))
+        ));
+        // a search within the limits still succeeds afterwards
+        let (t1, t2) = (nested(2, "number"), nested(2, "string"));
+        assert_eq!(shallow.find_paths(&t1, &t2, 3).unwrap().len(), 1);
+
+        let states = SearchLimitsBuilder::default()
+            .max_states(Some(2))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            searcher(states).find_paths(&s1, &s2, 3),
+            Err(SearchErr::BudgetExceeded(Budget {
+                limit: Limit::States,
+                ..
+            }))
+        ));
+        let time = SearchLimitsBuilder::default()
+            .timeout(Some(Duration::ZERO))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            searcher(time).find_paths(&s1, &s2, 3),
+            Err(SearchErr::BudgetExceeded(Budget {
+                limit: Limit::Time,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_cheapest_target_variant() {
+        let s1 = schema!({"type": "number"});
+        let s2 = schema!({
+            "oneOf": [
+                {"type": "array", "items": {"type": "string"}},
+                {"type": "string"}
+            ]
+        });
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(1));
+        assert_eq!(path.ir, vec![IR::G2G(Ground::Num, Ground::String)]);
+    }
+
+    #[test]
+    fn test_dispatching_source_union() {
+        let s1 = schema!({
+            "anyOf": [
+                {"type": "string"},
+                {"type": "array", "items": {"type": "string"}}
+            ]
+        });
+        let s2 = schema!({"type": "array", "items": {"type": "string"}});
+        let Schema::Union(branches) = &s1 else {
+            unreachable!()
+        };
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(2));
+        assert_eq!(
+            path.ir,
+            vec![
+                IR::PushUnion,
+                IR::Case(branches[0].clone()),
+                IR::Wrap,
+                IR::Copy,
+                IR::PopCase,
+                IR::Case(branches[1].clone()),
+                IR::Copy,
+                IR::PopCase,
+                IR::PopUnion
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fabricating_discriminator() {
+        // the target tags each kind of shape, where the source tells them apart
+        // by their properties alone
+        let s1 = schema!({
+            "oneOf": [
+                {"type": "object", "properties": {"radius": {"type": "number"}}, "required": ["radius"]},
+                {"type": "object", "properties": {"side": {"type": "number"}}, "required": ["side"]}
+            ]
+        });
+        let s2 = schema!({
+            "oneOf": [
+                {
+                    "type": "object",
+                    "properties": {"kind": {"const": "circle"}, "radius": {"type": "number"}},
+                    "required": ["kind", "radius"]
+                },
+                {
+                    "type": "object",
+                    "properties": {"kind": {"const": "square"}, "side": {"type": "number"}},
+                    "required": ["kind", "side"]
+                }
+            ]
+        });
+        let Schema::Union(branches) = &s1 else {
+            unreachable!()
+        };
+        let tag = |kind: &str| {
+            [
+                IR::Nest(key("kind")),
+                IR::Const(Literal(serde_json::json!(kind))),
+                IR::PopKey,
+            ]
+        };
+        let mut expected = vec![IR::PushUnion, IR::Case(branches[0].clone()), IR::PushObj];
+        expected.extend(tag("circle"));
+        expected.extend([
+            IR::PushKey(key("radius")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopCase,
+            IR::Case(branches[1].clone()),
+            IR::PushObj,
+        ]);
+        expected.extend(tag("square"));
+        expected.extend([
+            IR::PushKey(key("side")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopCase,
+            IR::PopUnion,
+        ]);
+        assert_eq!(SchemaSearcher::new().find_path(&s1, &s2), Ok(expected));
+    }
+
+    #[test]
+    fn test_heterogeneous_array() {
+        // items inferred from mixed data are dispatched on inside the loop
+        let s1 = crate::frontend::infer::from_instance(&serde_json::json!([1, "two", 3]));
+        let s2 = schema!({"type": "array", "items": {"type": "string"}});
+        let Schema::Arr(item) = &s1 else {
+            unreachable!()
+        };
+        let Schema::Union(branches) = item.as_ref() else {
+            unreachable!()
+        };
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2).unwrap(),
+            vec![
+                IR::PushArr,
+                IR::PushUnion,
+                IR::Case(branches[0].clone()),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopCase,
+                IR::Case(branches[1].clone()),
+                IR::Copy,
+                IR::PopCase,
+                IR::PopUnion,
+                IR::PopArr
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flattening_nested_object() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "address": {
+                    "type": "object",
+                    "properties": {
+                        "city": {"type": "string"},
+                        "zip": {"type": "number"}
+                    },
+                    "required": ["city", "zip"]
+                }
+            },
+            "required": ["address"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "address_city": {"type": "string"},
+                "addressZip": {"type": "string"}
+            },
+            "required": ["address_city", "addressZip"]
+        });
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(5));
+        assert_eq!(
+            path.ir,
+            vec![
+                IR::PushObj,
+                IR::Rename(key("address"), key("addressZip")),
+                IR::Extr(key("zip")),
+                IR::G2G(Ground::Num, Ground::String),
+                IR::PopKey,
+                IR::Rename(key("address"), key("address_city")),
+                IR::Extr(key("city")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj
+            ]
+        );
+        let report = analyze(&path.ir, &s1);
+        assert_eq!(report.dropped().count(), 0);
+    }
+
+    #[test]
+    fn test_nesting_prefixed_keys() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "address_city": {"type": "string"},
+                "address_zip": {"type": "string"}
+            },
+            "required": ["id", "address_city", "address_zip"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "address": {
+                    "type": "object",
+                    "properties": {
+                        "city": {"type": "string"},
+                        "zip": {"type": "string"}
+                    },
+                    "required": ["city", "zip"]
+                }
+            },
+            "required": ["id", "address"]
+        });
+        let expected = vec![
+            IR::PushObj,
+            IR::Nest(key("address")),
+            IR::PushObj,
+            IR::Rename(key("address_city"), key("city")),
+            IR::Copy,
+            IR::PopKey,
+            IR::Rename(key("address_zip"), key("zip")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+            IR::PopKey,
+            IR::PushKey(key("id")),
+            IR::Copy,
+            IR::PopKey,
+            IR::PopObj,
+        ];
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(1));
+        assert_eq!(path.ir, expected);
+        assert_eq!(analyze(&path.ir, &s1).dropped().count(), 0);
+
+        // an explicit mapping can nest keys which don't follow the convention
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "number"},
+                "town": {"type": "string"},
+                "postcode": {"type": "string"}
+            },
+            "required": ["id", "town", "postcode"]
+        });
+        let mapping = Mapping::try_from(&serde_json::json!({
+            "town": "address.city",
+            "postcode": "address.zip"
+        }))
+        .unwrap();
+        let config = SearchConfigBuilder::default()
+            .mapping(mapping)
+            .build()
+            .unwrap();
+        assert_eq!(
+            SchemaSearcher::with_config(config).find_path(&s1, &s2),
+            Ok(vec![
+                IR::PushObj,
+                IR::Nest(key("address")),
+                IR::PushObj,
+                IR::Rename(key("town"), key("city")),
+                IR::Copy,
+                IR::PopKey,
+                IR::Rename(key("postcode"), key("zip")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj,
+                IR::PopKey,
+                IR::PushKey(key("id")),
+                IR::Copy,
+                IR::PopKey,
+                IR::PopObj,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_matching_synonyms() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "order_total": {"type": "number"},
+                "zip": {"type": "string"}
+            },
+            "required": ["order_total", "zip"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "orderAmount": {"type": "number"},
+                "postalCode": {"type": "string"}
+            },
+            "required": ["orderAmount", "postalCode"]
+        });
+        let path = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(4));
+        assert_eq!(
+            crate::ir::renames(&path.ir),
+            vec![
+                ("order_total".to_string(), "orderAmount".to_string()),
+                ("zip".to_string(), "postalCode".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_tie_break() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "created_on": {"type": "string"},
+                "creation_date": {"type": "string", "format": "date-time"}
+            },
+            "required": ["created_on", "creation_date"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "created_at": {"type": "string", "format": "date-time"}
+            },
+            "required": ["created_at"]
+        });
+        let ir = SchemaSearcher::new().find_path(&s1, &s2).unwrap();
+        assert_eq!(
+            crate::ir::renames(&ir),
+            vec![("creation_date".to_string(), "created_at".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_bidirectional() {
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "user_id": {"type": "number"},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["user_id", "tags"]
+        });
+        let s2 = schema!({
+            "type": "object",
+            "properties": {
+                "userId": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["userId", "tags"]
+        });
+        let round_trip = SchemaSearcher::new().find_bidirectional(&s1, &s2).unwrap();
+        assert_eq!(
+            round_trip.backward,
+            vec![
+                IR::PushObj,
+                IR::PushKey(key("tags")),
+                IR::Copy,
+                IR::PopKey,
+                IR::Rename(key("userId"), key("user_id")),
+                IR::G2G(Ground::String, Ground::Num),
+                IR::PopKey,
+                IR::PopObj
+            ]
+        );
+        // numbers survive being written as strings, but not the reverse
+        assert!(round_trip.forward_report.lossiness() == Lossiness::Lossless);
+        assert_eq!(
+            round_trip
+                .one_way()
+                .map(|step| step.source.as_str())
+                .collect::<Vec<_>>(),
+            vec!["userId"]
+        );
+        assert!(!round_trip.is_lossless());
+    }
+
+    #[test]
+    fn test_bidirectional_prefers_invertible() {
+        // both branches cost the same, but only strings convert back
+        let s1 = schema!({"type": "number"});
+        let s2 = schema!({
+            "oneOf": [{"type": "boolean"}, {"type": "string"}]
+        });
+        assert_eq!(
+            SchemaSearcher::new().find_path(&s1, &s2),
+            Ok(vec![IR::G2G(Ground::Num, Ground::Bool)])
+        );
+        let round_trip = SchemaSearcher::new().find_bidirectional(&s1, &s2).unwrap();
+        assert_eq!(
+            round_trip.forward,
+            vec![IR::G2G(Ground::Num, Ground::String)]
+        );
+        assert_eq!(round_trip.forward_report.lossiness(), Lossiness::Lossless);
+    }
+
+    #[test]
+    fn test_estimate_prunes_wide_objects() {
+        // only the last property converts for free, and the estimate finds it
+        // without trying all the others first
+        let mut props = serde_json::Map::new();
+        for i in 0..20 {
+            props.insert(format!("n{:02}", i), serde_json::json!({"type": "number"}));
+        }
+        props.insert("s".to_string(), serde_json::json!({"type": "string"}));
+        let s1 = Schema::try_from(&serde_json::json!({
+            "type": "object",
+            "properties": props
+        }))
+        .unwrap();
+        let s2 = schema!({"type": "string"});
+        let searcher = SchemaSearcher::new();
+        let path = searcher.solve(&s1, &s2).unwrap();
+        assert_eq!(path.ir, vec![IR::Extr(key("s")), IR::Copy]);
+        assert_eq!(searcher.explored(), 3);
+
+        // the estimate never overshoots the real cost
+        let s1 = Arc::new(nested(3, "number"));
+        let s2 = Arc::new(nested(3, "string"));
+        let searcher = SchemaSearcher::new();
+        let estimate = searcher.estimate(&Rel::new(s1.clone(), s2.clone()));
+        assert!(estimate > ExtNat::Nat(0));
+        assert!(estimate <= searcher.solve(&s1, &s2).unwrap().cost);
+    }
+
+    #[test]
+    fn test_beam_search() {
+        // extracting `a` looks cheapest, but it takes a few more steps to get
+        // a string out of it than out of `b`
+        let s1 = schema!({
+            "type": "object",
+            "properties": {
+                "a": {
+                    "type": "object",
+                    "properties": {
+                        "x": {"type": "object", "properties": {"y": {"type": "number"}}}
+                    }
+                },
+                "b": {"type": "number"}
+            }
+        });
+        let s2 = schema!({"type": "string"});
+        let exact = SchemaSearcher::new().solve(&s1, &s2).unwrap();
+        assert_eq!(exact.cost, ExtNat::Nat(2));
+
+        let beam = |width| {
+            SchemaSearcher::with_config(SearchConfig {
+                strategy: Strategy::Beam(width),
+                ..Default::default()
+            })
+        };
+        let narrow = beam(1).solve(&s1, &s2).unwrap();
+        assert_eq!(narrow.cost, ExtNat::Nat(4));
+        assert_eq!(narrow.ir[0], IR::Extr(key("a")));
+        assert_eq!(beam(2).solve(&s1, &s2).unwrap(), exact);
+    }
+
+    #[test]
+    fn test_incremental_search() {
+        let wide = |ty: &str| {
+            // every property is different, so none are solved only once
+            let props: serde_json::Map<_, _> = (0..20)
+                .map(|i| {
+                    let inner = serde_json::json!({
+                        "type": "object",
+                        "properties": {format!("v{:02}", i): {"type": ty}}
+                    });
+                    (format!("p{:02}", i), inner)
+                })
+                .collect();
+            Schema::try_from(&serde_json::json!({"type": "object", "properties": props})).unwrap()
+        };
+        let mut search =
+            IncrementalSearch::new(SchemaSearcher::new(), wide("number"), wide("string"));
+        let fresh = search.searcher.explored();
+        assert_eq!(search.path().unwrap().cost, ExtNat::Nat(20));
+
+        let edit = SchemaEdit {
+            path: vec!["p07".to_string(), "v07".to_string()],
+            schema: Some(Schema::num()),
+        };
+        search.edit_target(&edit).unwrap();
+        assert_eq!(search.path().unwrap().cost, ExtNat::Nat(19));
+        // only the edited property and the object around it are searched
+        assert!(search.searcher.explored() < fresh / 2);
+
+        let edit = SchemaEdit {
+            path: vec!["p07".to_string(), "[]".to_string()],
+            schema: None,
+        };
+        assert_eq!(
+            search.edit_source(&edit),
+            Err(EditErr::BadPath("p07.[]".to_string()))
+        );
+        let edit = SchemaEdit {
+            path: vec!["p07".to_string()],
+            schema: None,
+        };
+        search.edit_source(&edit).unwrap();
+        // the target property is optional, so it's left out instead
+        assert_eq!(search.path().unwrap().cost, ExtNat::Nat(20));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_wide_object() {
+        let wide = |ty: &str| {
+            let props: serde_json::Map<_, _> = (0..40)
+                .map(|i| (format!("p{:02}", i), serde_json::json!({"type": ty})))
+                .collect();
+            Schema::try_from(&serde_json::json!({"type": "object", "properties": props})).unwrap()
+        };
+        let (s1, s2) = (wide("number"), wide("string"));
+        let searcher = SchemaSearcher::new();
+        let path = searcher.solve(&s1, &s2).unwrap();
+        assert_eq!(path.cost, ExtNat::Nat(40));
+        assert_eq!(path.ir, SchemaSearcher::new().find_path(&s1, &s2).unwrap());
+        let paths = searcher.find_paths(&s1, &s2, 2).unwrap();
+        assert_eq!(paths[0].ir, path.ir);
+
+        // the threads keep within the search's limits
+        let limited = |limits| {
+            SchemaSearcher::with_config(SearchConfig {
+                limits,
+                ..Default::default()
+            })
+        };
+        let depth = SearchLimits {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            limited(depth).solve(&s1, &s2),
+            Err(SearchErr::BudgetExceeded(Budget {
+                limit: Limit::Depth,
+                ..
+            }))
+        ));
+        let states = SearchLimits {
+            max_states: Some(10),
+            ..Default::default()
+        };
+        let searcher = limited(states);
+        assert!(matches!(
+            searcher.solve(&s1, &s2),
+            Err(SearchErr::BudgetExceeded(Budget {
+                limit: Limit::States,
+                ..
+            }))
+        ));
+        assert!(searcher.explored() <= 10 + std::thread::available_parallelism().unwrap().get());
+    }
+}
@@ -0,0 +1,8 @@
+//! Helpers shared by the tests of several modules.
+
+use std::sync::Arc;
+
+/// The property name `k`, as IR and schemas hold it.
+pub fn key(k: &str) -> Arc<String> {
+    Arc::new(k.to_string())
+}
@@ -0,0 +1,113 @@
+//! Conversions between units of measurement, for numeric properties whose
+//! schemas carry a `unit` annotation, and between the ways of writing down
+//! dates.
+
+use crate::{
+    ir::DateFormat,
+    schema::{Ground, Real},
+};
+
+/// Known units: their name, what they measure, and how to get from a value
+/// in the unit to one in the base unit of what they measure (multiplying by
+/// the factor, then adding the offset).
+const UNITS: &[(&str, &str, f64, f64)] = &[
+    ("ms", "time", 0.001, 0.0),
+    ("s", "time", 1.0, 0.0),
+    ("min", "time", 60.0, 0.0),
+    ("h", "time", 3600.0, 0.0),
+    ("mm", "length", 0.001, 0.0),
+    ("cm", "length", 0.01, 0.0),
+    ("m", "length", 1.0, 0.0),
+    ("km", "length", 1000.0, 0.0),
+    ("g", "mass", 0.001, 0.0),
+    ("kg", "mass", 1.0, 0.0),
+    ("cents", "money", 0.01, 0.0),
+    ("dollars", "money", 1.0, 0.0),
+    ("celsius", "temperature", 1.0, 0.0),
+    ("fahrenheit", "temperature", 5.0 / 9.0, -160.0 / 9.0),
+    ("kelvin", "temperature", 1.0, -273.15),
+];
+
+/// The factor and offset converting a value in unit `from` into unit `to`,
+/// as `value * factor + offset`, or `None` if either unit is unknown or they
+/// measure different things.
+pub fn conversion(from: &str, to: &str) -> Option<(Real, Real)> {
+    let unit = |name: &str| {
+        UNITS
+            .iter()
+            .find(|(unit, ..)| unit.eq_ignore_ascii_case(name))
+    };
+    let (_, from_kind, f1, o1) = unit(from)?;
+    let (_, to_kind, f2, o2) = unit(to)?;
+    if from_kind != to_kind {
+        return None;
+    }
+    // round away the noise of going through the base unit
+    let round = |x: f64| (x * 1e12).round() / 1e12;
+    Some((Real(round(f1 / f2)), Real(round((o1 - o2) / f2))))
+}
+
+/// How a property of type `ground` with the given `format` and `unit`
+/// annotations writes down dates, if it does: `date-time` strings are ISO
+/// 8601, and `unix-time` (or `timestamp`) numbers count seconds since the
+/// epoch, or milliseconds if their unit is `ms`.
+pub fn date_format(
+    ground: &Ground,
+    format: Option<&str>,
+    unit: Option<&str>,
+) -> Option<DateFormat> {
+    match (ground, format?, unit) {
+        (Ground::String, "date-time", _) => Some(DateFormat::Iso),
+        (Ground::Num, "unix-time" | "timestamp", None | Some("s")) => {
+            Some(DateFormat::EpochSeconds)
+        }
+        (Ground::Num, "unix-time" | "timestamp", Some("ms")) => Some(DateFormat::EpochMillis),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(
+            conversion("cents", "dollars"),
+            Some((Real(0.01), Real(0.0)))
+        );
+        assert_eq!(conversion("ms", "s"), Some((Real(0.001), Real(0.0))));
+        assert_eq!(
+            conversion("Celsius", "fahrenheit"),
+            Some((Real(1.8), Real(32.0)))
+        );
+        assert_eq!(
+            conversion("kelvin", "celsius"),
+            Some((Real(1.0), Real(-273.15)))
+        );
+        assert_eq!(conversion("kg", "s"), None);
+        assert_eq!(conversion("furlongs", "m"), None);
+    }
+
+    #[test]
+    fn test_date_formats() {
+        assert_eq!(
+            date_format(&Ground::String, Some("date-time"), None),
+            Some(DateFormat::Iso)
+        );
+        assert_eq!(
+            date_format(&Ground::Num, Some("unix-time"), None),
+            Some(DateFormat::EpochSeconds)
+        );
+        assert_eq!(
+            date_format(&Ground::Num, Some("timestamp"), Some("ms")),
+            Some(DateFormat::EpochMillis)
+        );
+        assert_eq!(
+            date_format(&Ground::Num, Some("unix-time"), Some("h")),
+            None
+        );
+        assert_eq!(date_format(&Ground::String, Some("email"), None), None);
+        assert_eq!(date_format(&Ground::Num, None, Some("ms")), None);
+    }
+}